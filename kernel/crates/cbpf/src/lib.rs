@@ -0,0 +1,488 @@
+//! 经典BPF（cBPF）程序的检查与解释执行
+//!
+//! 程序只能以4字节为单位读取输入数据，读取时使用本机字节序（与seccomp的约定一致）。
+//!
+//! 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/net/core/filter.c
+
+#![no_std]
+#![allow(clippy::needless_return)]
+
+#[cfg(test)]
+#[macro_use]
+extern crate std;
+
+use system_error::SystemError;
+
+/// 一条BPF指令
+///
+/// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/include/uapi/linux/filter.h#24
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SockFilter {
+    pub code: u16,
+    /// 条件成立时跳过的指令数
+    pub jt: u8,
+    /// 条件不成立时跳过的指令数
+    pub jf: u8,
+    pub k: u32,
+}
+
+/// 用户传入的BPF程序
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct SockFprog {
+    pub len: u16,
+    pub filter: *const SockFilter,
+}
+
+/// 单个程序的最大指令数
+pub const BPF_MAXINSNS: usize = 4096;
+/// 暂存区的大小（以32位字为单位）
+const BPF_MEMWORDS: u32 = 16;
+
+// 指令类别
+pub const BPF_LD: u16 = 0x00;
+pub const BPF_LDX: u16 = 0x01;
+pub const BPF_ST: u16 = 0x02;
+pub const BPF_STX: u16 = 0x03;
+pub const BPF_ALU: u16 = 0x04;
+pub const BPF_JMP: u16 = 0x05;
+pub const BPF_RET: u16 = 0x06;
+pub const BPF_MISC: u16 = 0x07;
+
+// ld/ldx的操作数大小
+pub const BPF_W: u16 = 0x00;
+pub const BPF_H: u16 = 0x08;
+pub const BPF_B: u16 = 0x10;
+
+// ld/ldx的寻址模式
+pub const BPF_IMM: u16 = 0x00;
+pub const BPF_ABS: u16 = 0x20;
+pub const BPF_IND: u16 = 0x40;
+pub const BPF_MEM: u16 = 0x60;
+pub const BPF_LEN: u16 = 0x80;
+pub const BPF_MSH: u16 = 0xa0;
+
+// alu操作
+pub const BPF_ADD: u16 = 0x00;
+pub const BPF_SUB: u16 = 0x10;
+pub const BPF_MUL: u16 = 0x20;
+pub const BPF_DIV: u16 = 0x30;
+pub const BPF_OR: u16 = 0x40;
+pub const BPF_AND: u16 = 0x50;
+pub const BPF_LSH: u16 = 0x60;
+pub const BPF_RSH: u16 = 0x70;
+pub const BPF_NEG: u16 = 0x80;
+pub const BPF_MOD: u16 = 0x90;
+pub const BPF_XOR: u16 = 0xa0;
+
+// jmp操作
+pub const BPF_JA: u16 = 0x00;
+pub const BPF_JEQ: u16 = 0x10;
+pub const BPF_JGT: u16 = 0x20;
+pub const BPF_JGE: u16 = 0x30;
+pub const BPF_JSET: u16 = 0x40;
+
+// 操作数来源
+pub const BPF_K: u16 = 0x00;
+pub const BPF_X: u16 = 0x08;
+
+// ret的返回值来源
+pub const BPF_A: u16 = 0x10;
+
+// misc操作
+pub const BPF_TAX: u16 = 0x00;
+pub const BPF_TXA: u16 = 0x80;
+
+pub const fn bpf_class(code: u16) -> u16 {
+    code & 0x07
+}
+
+pub const fn bpf_size(code: u16) -> u16 {
+    code & 0x18
+}
+
+pub const fn bpf_mode(code: u16) -> u16 {
+    code & 0xe0
+}
+
+pub const fn bpf_op(code: u16) -> u16 {
+    code & 0xf0
+}
+
+pub const fn bpf_src(code: u16) -> u16 {
+    code & 0x08
+}
+
+pub const fn bpf_rval(code: u16) -> u16 {
+    code & 0x18
+}
+
+pub const fn bpf_miscop(code: u16) -> u16 {
+    code & 0xf8
+}
+
+/// 指令的操作码是否合法
+///
+/// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/net/core/filter.c#968
+fn bpf_code_allowed(code: u16) -> bool {
+    match bpf_class(code) {
+        BPF_LD => {
+            let size_ok = matches!(bpf_size(code), BPF_W | BPF_H | BPF_B);
+            match bpf_mode(code) {
+                BPF_ABS | BPF_IND => size_ok,
+                BPF_IMM | BPF_MEM | BPF_LEN => bpf_size(code) == BPF_W,
+                _ => false,
+            }
+        }
+        BPF_LDX => match bpf_mode(code) {
+            BPF_IMM | BPF_MEM | BPF_LEN => bpf_size(code) == BPF_W,
+            BPF_MSH => bpf_size(code) == BPF_B,
+            _ => false,
+        },
+        BPF_ST | BPF_STX => code & !0x07 == 0,
+        BPF_ALU => match bpf_op(code) {
+            BPF_NEG => bpf_src(code) == BPF_K && code & 0x07 == BPF_ALU && code & !0xff == 0,
+            BPF_ADD | BPF_SUB | BPF_MUL | BPF_DIV | BPF_OR | BPF_AND | BPF_LSH | BPF_RSH
+            | BPF_MOD | BPF_XOR => code & !0xff == 0,
+            _ => false,
+        },
+        BPF_JMP => match bpf_op(code) {
+            BPF_JA => bpf_src(code) == BPF_K && code & !0xf7 == 0,
+            BPF_JEQ | BPF_JGT | BPF_JGE | BPF_JSET => code & !0xff == 0,
+            _ => false,
+        },
+        BPF_RET => matches!(bpf_rval(code), BPF_K | BPF_A) && code & !0x1f == 0,
+        BPF_MISC => matches!(code & !0x07, BPF_TAX | BPF_TXA),
+        _ => false,
+    }
+}
+
+/// 检查程序是否合法
+///
+/// - 程序的长度必须在1到BPF_MAXINSNS之间
+/// - 不能除以常数0，常数移位不能超过31位
+/// - 暂存区的下标必须小于BPF_MEMWORDS
+/// - 跳转目标必须在程序内，并且最后一条指令必须是ret
+///
+/// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/net/core/filter.c#1035
+pub fn bpf_check_classic(prog: &[SockFilter]) -> Result<(), SystemError> {
+    let flen = prog.len();
+    if flen == 0 || flen > BPF_MAXINSNS {
+        return Err(SystemError::EINVAL);
+    }
+
+    for (pc, insn) in prog.iter().enumerate() {
+        let code = insn.code;
+        if !bpf_code_allowed(code) {
+            return Err(SystemError::EINVAL);
+        }
+
+        let ok = match bpf_class(code) {
+            BPF_ALU if bpf_src(code) == BPF_K => match bpf_op(code) {
+                BPF_DIV | BPF_MOD => insn.k != 0,
+                BPF_LSH | BPF_RSH => insn.k < 32,
+                _ => true,
+            },
+            BPF_LD | BPF_LDX if bpf_mode(code) == BPF_MEM => insn.k < BPF_MEMWORDS,
+            BPF_ST | BPF_STX => insn.k < BPF_MEMWORDS,
+            BPF_JMP if bpf_op(code) == BPF_JA => (insn.k as usize) < flen - pc - 1,
+            BPF_JMP => pc + insn.jt as usize + 1 < flen && pc + insn.jf as usize + 1 < flen,
+            _ => true,
+        };
+        if !ok {
+            return Err(SystemError::EINVAL);
+        }
+    }
+
+    if bpf_class(prog[flen - 1].code) != BPF_RET {
+        return Err(SystemError::EINVAL);
+    }
+    return Ok(());
+}
+
+/// 解释执行一个已经通过检查的程序，返回ret指令给出的值
+///
+/// 程序无法完成的读取（越界或者不是4字节对齐的读取）、除以0等操作会使程序返回0
+///
+/// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/net/core/filter.c#1220
+pub fn bpf_run(prog: &[SockFilter], data: &[u8]) -> u32 {
+    let load_word = |off: u32| -> Option<u32> {
+        let off = off as usize;
+        let bytes = data.get(off..off.checked_add(4)?)?;
+        if off % 4 != 0 {
+            return None;
+        }
+        Some(u32::from_ne_bytes(bytes.try_into().ok()?))
+    };
+
+    let mut a: u32 = 0;
+    let mut x: u32 = 0;
+    let mut mem = [0u32; BPF_MEMWORDS as usize];
+    let mut pc = 0;
+
+    while let Some(insn) = prog.get(pc) {
+        pc += 1;
+        let code = insn.code;
+        let k = insn.k;
+        match bpf_class(code) {
+            BPF_LD => {
+                a = match (bpf_mode(code), bpf_size(code)) {
+                    (BPF_IMM, _) => k,
+                    (BPF_MEM, _) => mem[k as usize],
+                    (BPF_LEN, _) => data.len() as u32,
+                    (BPF_ABS, BPF_W) => match load_word(k) {
+                        Some(v) => v,
+                        None => return 0,
+                    },
+                    _ => return 0,
+                }
+            }
+            BPF_LDX => {
+                x = match bpf_mode(code) {
+                    BPF_IMM => k,
+                    BPF_MEM => mem[k as usize],
+                    BPF_LEN => data.len() as u32,
+                    _ => return 0,
+                }
+            }
+            BPF_ST => mem[k as usize] = a,
+            BPF_STX => mem[k as usize] = x,
+            BPF_ALU => {
+                let src = if bpf_src(code) == BPF_X { x } else { k };
+                a = match bpf_op(code) {
+                    BPF_ADD => a.wrapping_add(src),
+                    BPF_SUB => a.wrapping_sub(src),
+                    BPF_MUL => a.wrapping_mul(src),
+                    BPF_DIV => match a.checked_div(src) {
+                        Some(v) => v,
+                        None => return 0,
+                    },
+                    BPF_MOD => match a.checked_rem(src) {
+                        Some(v) => v,
+                        None => return 0,
+                    },
+                    BPF_OR => a | src,
+                    BPF_AND => a & src,
+                    BPF_XOR => a ^ src,
+                    BPF_LSH => a.checked_shl(src).unwrap_or(0),
+                    BPF_RSH => a.checked_shr(src).unwrap_or(0),
+                    BPF_NEG => a.wrapping_neg(),
+                    _ => return 0,
+                }
+            }
+            BPF_JMP => {
+                let src = if bpf_src(code) == BPF_X { x } else { k };
+                let taken = match bpf_op(code) {
+                    BPF_JA => {
+                        pc += k as usize;
+                        continue;
+                    }
+                    BPF_JEQ => a == src,
+                    BPF_JGT => a > src,
+                    BPF_JGE => a >= src,
+                    BPF_JSET => a & src != 0,
+                    _ => return 0,
+                };
+                pc += if taken { insn.jt } else { insn.jf } as usize;
+            }
+            BPF_RET => {
+                return if bpf_rval(code) == BPF_A { a } else { k };
+            }
+            BPF_MISC => {
+                if bpf_miscop(code) == BPF_TAX {
+                    x = a;
+                } else {
+                    a = x;
+                }
+            }
+            _ => return 0,
+        }
+    }
+
+    return 0;
+}
+
+#[cfg(test)]
+mod tests {
+    use std::vec::Vec;
+
+    use super::*;
+
+    const fn stmt(code: u16, k: u32) -> SockFilter {
+        SockFilter {
+            code,
+            jt: 0,
+            jf: 0,
+            k,
+        }
+    }
+
+    const fn jump(code: u16, k: u32, jt: u8, jf: u8) -> SockFilter {
+        SockFilter { code, jt, jf, k }
+    }
+
+    fn words(data: &[u32]) -> Vec<u8> {
+        data.iter().flat_map(|w| w.to_ne_bytes()).collect()
+    }
+
+    #[test]
+    fn test_check_length() {
+        assert_eq!(bpf_check_classic(&[]), Err(SystemError::EINVAL));
+        let prog = vec![stmt(BPF_RET | BPF_K, 0); BPF_MAXINSNS + 1];
+        assert_eq!(bpf_check_classic(&prog), Err(SystemError::EINVAL));
+        assert_eq!(bpf_check_classic(&prog[..BPF_MAXINSNS]), Ok(()));
+    }
+
+    #[test]
+    fn test_check_last_insn_is_ret() {
+        let prog = [stmt(BPF_LD | BPF_IMM, 1)];
+        assert_eq!(bpf_check_classic(&prog), Err(SystemError::EINVAL));
+    }
+
+    #[test]
+    fn test_check_invalid_code() {
+        // ld的绝对寻址只能以字节、半字或字为单位
+        let prog = [stmt(BPF_LD | 0x18 | BPF_ABS, 0), stmt(BPF_RET | BPF_K, 0)];
+        assert_eq!(bpf_check_classic(&prog), Err(SystemError::EINVAL));
+        // ldx不支持绝对寻址
+        let prog = [stmt(BPF_LDX | BPF_W | BPF_ABS, 0), stmt(BPF_RET | BPF_K, 0)];
+        assert_eq!(bpf_check_classic(&prog), Err(SystemError::EINVAL));
+    }
+
+    #[test]
+    fn test_check_constant_operands() {
+        let div0 = [stmt(BPF_ALU | BPF_DIV | BPF_K, 0), stmt(BPF_RET | BPF_A, 0)];
+        assert_eq!(bpf_check_classic(&div0), Err(SystemError::EINVAL));
+        let mod0 = [stmt(BPF_ALU | BPF_MOD | BPF_K, 0), stmt(BPF_RET | BPF_A, 0)];
+        assert_eq!(bpf_check_classic(&mod0), Err(SystemError::EINVAL));
+        let shift = [
+            stmt(BPF_ALU | BPF_LSH | BPF_K, 32),
+            stmt(BPF_RET | BPF_A, 0),
+        ];
+        assert_eq!(bpf_check_classic(&shift), Err(SystemError::EINVAL));
+        let mem = [stmt(BPF_ST, BPF_MEMWORDS), stmt(BPF_RET | BPF_A, 0)];
+        assert_eq!(bpf_check_classic(&mem), Err(SystemError::EINVAL));
+        let ok = [
+            stmt(BPF_ALU | BPF_LSH | BPF_K, 31),
+            stmt(BPF_ST, BPF_MEMWORDS - 1),
+            stmt(BPF_RET | BPF_A, 0),
+        ];
+        assert_eq!(bpf_check_classic(&ok), Ok(()));
+    }
+
+    #[test]
+    fn test_check_jump_target() {
+        // 跳转目标不能越过程序末尾
+        let prog = [stmt(BPF_JMP | BPF_JA, 1), stmt(BPF_RET | BPF_K, 0)];
+        assert_eq!(bpf_check_classic(&prog), Err(SystemError::EINVAL));
+        let prog = [
+            jump(BPF_JMP | BPF_JEQ | BPF_K, 0, 0, 1),
+            stmt(BPF_RET | BPF_K, 0),
+        ];
+        assert_eq!(bpf_check_classic(&prog), Err(SystemError::EINVAL));
+        let prog = [
+            jump(BPF_JMP | BPF_JEQ | BPF_K, 0, 0, 1),
+            stmt(BPF_RET | BPF_K, 0),
+            stmt(BPF_RET | BPF_K, 1),
+        ];
+        assert_eq!(bpf_check_classic(&prog), Ok(()));
+    }
+
+    #[test]
+    fn test_run_load_and_compare() {
+        // 第一个字等于42时返回1，否则返回2
+        let prog = [
+            stmt(BPF_LD | BPF_W | BPF_ABS, 0),
+            jump(BPF_JMP | BPF_JEQ | BPF_K, 42, 0, 1),
+            stmt(BPF_RET | BPF_K, 1),
+            stmt(BPF_RET | BPF_K, 2),
+        ];
+        assert_eq!(bpf_check_classic(&prog), Ok(()));
+        assert_eq!(bpf_run(&prog, &words(&[42, 0])), 1);
+        assert_eq!(bpf_run(&prog, &words(&[7, 42])), 2);
+    }
+
+    #[test]
+    fn test_run_bad_load_returns_zero() {
+        let prog = [stmt(BPF_LD | BPF_W | BPF_ABS, 8), stmt(BPF_RET | BPF_K, 1)];
+        // 越界读取
+        assert_eq!(bpf_run(&prog, &words(&[1, 2])), 0);
+        assert_eq!(bpf_run(&prog, &words(&[1, 2, 3])), 1);
+        // 非对齐读取
+        let prog = [stmt(BPF_LD | BPF_W | BPF_ABS, 2), stmt(BPF_RET | BPF_K, 1)];
+        assert_eq!(bpf_run(&prog, &words(&[1, 2])), 0);
+    }
+
+    #[test]
+    fn test_run_alu() {
+        let prog = [
+            stmt(BPF_LD | BPF_IMM, 6),
+            stmt(BPF_ALU | BPF_MUL | BPF_K, 7),
+            stmt(BPF_ALU | BPF_SUB | BPF_K, 2),
+            stmt(BPF_LDX | BPF_IMM, 3),
+            stmt(BPF_ALU | BPF_MOD | BPF_X, 0),
+            stmt(BPF_ALU | BPF_LSH | BPF_K, 4),
+            stmt(BPF_ALU | BPF_OR | BPF_K, 1),
+            stmt(BPF_RET | BPF_A, 0),
+        ];
+        assert_eq!(bpf_check_classic(&prog), Ok(()));
+        assert_eq!(bpf_run(&prog, &[]), ((40 % 3) << 4) | 1);
+
+        let neg = [
+            stmt(BPF_LD | BPF_IMM, 1),
+            stmt(BPF_ALU | BPF_NEG, 0),
+            stmt(BPF_RET | BPF_A, 0),
+        ];
+        assert_eq!(bpf_run(&neg, &[]), u32::MAX);
+    }
+
+    #[test]
+    fn test_run_div_by_zero_register() {
+        let prog = [
+            stmt(BPF_LD | BPF_IMM, 1),
+            stmt(BPF_LDX | BPF_IMM, 0),
+            stmt(BPF_ALU | BPF_DIV | BPF_X, 0),
+            stmt(BPF_RET | BPF_K, 1),
+        ];
+        assert_eq!(bpf_check_classic(&prog), Ok(()));
+        assert_eq!(bpf_run(&prog, &[]), 0);
+    }
+
+    #[test]
+    fn test_run_scratch_memory_and_misc() {
+        let prog = [
+            stmt(BPF_LD | BPF_IMM, 5),
+            stmt(BPF_ST, 3),
+            stmt(BPF_LD | BPF_IMM, 0),
+            stmt(BPF_LDX | BPF_MEM, 3),
+            stmt(BPF_MISC | BPF_TXA, 0),
+            stmt(BPF_ALU | BPF_ADD | BPF_K, 1),
+            stmt(BPF_MISC | BPF_TAX, 0),
+            stmt(BPF_STX, 4),
+            stmt(BPF_LD | BPF_MEM, 4),
+            stmt(BPF_RET | BPF_A, 0),
+        ];
+        assert_eq!(bpf_check_classic(&prog), Ok(()));
+        assert_eq!(bpf_run(&prog, &[]), 6);
+    }
+
+    #[test]
+    fn test_run_jumps() {
+        let prog = [
+            stmt(BPF_LD | BPF_LEN, 0),
+            jump(BPF_JMP | BPF_JGT | BPF_K, 4, 0, 1),
+            stmt(BPF_JMP | BPF_JA, 1),
+            stmt(BPF_RET | BPF_K, 1),
+            stmt(BPF_LD | BPF_IMM, 0b1010),
+            jump(BPF_JMP | BPF_JSET | BPF_K, 0b0100, 0, 1),
+            stmt(BPF_RET | BPF_K, 2),
+            jump(BPF_JMP | BPF_JGE | BPF_K, 0b1010, 0, 1),
+            stmt(BPF_RET | BPF_K, 3),
+            stmt(BPF_RET | BPF_K, 4),
+        ];
+        assert_eq!(bpf_check_classic(&prog), Ok(()));
+        assert_eq!(bpf_run(&prog, &words(&[0])), 1);
+        assert_eq!(bpf_run(&prog, &words(&[0, 0])), 3);
+    }
+}
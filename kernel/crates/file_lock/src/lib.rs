@@ -0,0 +1,249 @@
+//! 文件记录锁的范围计算
+//!
+//! 与内核其他部分无关的纯逻辑：把`struct flock`描述的范围换算为字节区间、判断两个锁是否冲突，
+//! 以及加锁与解锁时对同一个持有者的锁进行拆分与合并。
+//!
+//! 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/fs/locks.c
+
+#![no_std]
+#![allow(clippy::needless_return)]
+
+extern crate alloc;
+
+#[cfg(test)]
+#[macro_use]
+extern crate std;
+
+use alloc::vec::Vec;
+use core::cmp::Ordering;
+use system_error::SystemError;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileLockType {
+    Read,
+    Write,
+}
+
+/// 一个锁住了[start, end]范围的锁，`O`是锁的持有者
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FileLock<O> {
+    pub owner: O,
+    pub lock_type: FileLockType,
+    /// 锁住的第一个字节
+    pub start: u64,
+    /// 锁住的最后一个字节，`u64::MAX`表示一直锁到文件末尾（包括以后追加的内容）
+    pub end: u64,
+}
+
+impl<O: Copy + Eq> FileLock<O> {
+    pub fn conflicts_with(&self, other: &FileLock<O>) -> bool {
+        self.owner != other.owner
+            && self.start <= other.end
+            && other.start <= self.end
+            && (self.lock_type == FileLockType::Write || other.lock_type == FileLockType::Write)
+    }
+}
+
+/// 把`struct flock`中从`base`开始、偏移为`l_start`、长度为`l_len`的范围转换为[start, end]
+///
+/// `base`由l_whence决定：文件开头为0，当前位置为文件偏移量，文件末尾为文件大小
+///
+/// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/fs/locks.c#466
+pub fn flock_range(base: i64, l_start: i64, l_len: i64) -> Result<(u64, u64), SystemError> {
+    let mut start = base.checked_add(l_start).ok_or(SystemError::EOVERFLOW)?;
+    if start < 0 {
+        return Err(SystemError::EINVAL);
+    }
+
+    let end = match l_len.cmp(&0) {
+        Ordering::Greater => start.checked_add(l_len - 1).ok_or(SystemError::EOVERFLOW)? as u64,
+        Ordering::Less => {
+            // 长度为负数时，锁住[start + len, start - 1]
+            let end = start - 1;
+            start = start.checked_add(l_len).ok_or(SystemError::EINVAL)?;
+            if start < 0 {
+                return Err(SystemError::EINVAL);
+            }
+            end as u64
+        }
+        // 长度为0时一直锁到文件末尾
+        Ordering::Equal => u64::MAX,
+    };
+    return Ok((start as u64, end));
+}
+
+/// 把`owner`在[start, end]范围内的锁替换为`lock_type`类型的锁，`lock_type`为None时表示解锁
+///
+/// 范围之外的部分保持不变，相邻的同类型锁会被合并
+pub fn posix_update<O: Copy + Eq>(
+    locks: &mut Vec<FileLock<O>>,
+    owner: O,
+    lock_type: Option<FileLockType>,
+    mut start: u64,
+    mut end: u64,
+) {
+    let mut updated = Vec::with_capacity(locks.len() + 1);
+    for lock in locks.drain(..) {
+        if lock.owner != owner || lock.end < start || lock.start > end {
+            updated.push(lock);
+            continue;
+        }
+        if lock.start < start {
+            updated.push(FileLock {
+                end: start - 1,
+                ..lock
+            });
+        }
+        if lock.end > end {
+            updated.push(FileLock {
+                start: end + 1,
+                ..lock
+            });
+        }
+    }
+
+    if let Some(lock_type) = lock_type {
+        updated.retain(|lock| {
+            if lock.owner != owner || lock.lock_type != lock_type {
+                return true;
+            }
+            if lock.end.checked_add(1) == Some(start) {
+                start = lock.start;
+                return false;
+            }
+            if end.checked_add(1) == Some(lock.start) {
+                end = lock.end;
+                return false;
+            }
+            true
+        });
+        updated.push(FileLock {
+            owner,
+            lock_type,
+            start,
+            end,
+        });
+    }
+    *locks = updated;
+}
+
+#[cfg(test)]
+mod tests {
+    use std::vec::Vec;
+
+    use super::*;
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    enum Owner {
+        Posix(usize),
+        Ofd(usize),
+    }
+
+    const A: Owner = Owner::Posix(1);
+    const B: Owner = Owner::Posix(2);
+
+    fn lock(owner: Owner, lock_type: FileLockType, start: u64, end: u64) -> FileLock<Owner> {
+        FileLock {
+            owner,
+            lock_type,
+            start,
+            end,
+        }
+    }
+
+    /// 按范围排序后的(持有者, 类型, start, end)
+    fn ranges(locks: &[FileLock<Owner>]) -> Vec<(Owner, FileLockType, u64, u64)> {
+        let mut v: Vec<_> = locks
+            .iter()
+            .map(|l| (l.owner, l.lock_type, l.start, l.end))
+            .collect();
+        v.sort_by_key(|&(_, _, start, end)| (start, end));
+        v
+    }
+
+    #[test]
+    fn test_flock_range() {
+        assert_eq!(flock_range(0, 10, 5), Ok((10, 14)));
+        // 长度为0表示一直锁到文件末尾
+        assert_eq!(flock_range(100, -10, 0), Ok((90, u64::MAX)));
+        // 长度为负数时锁住起点之前的字节
+        assert_eq!(flock_range(0, 10, -4), Ok((6, 9)));
+        assert_eq!(flock_range(0, 10, -11), Err(SystemError::EINVAL));
+        assert_eq!(flock_range(5, -6, 1), Err(SystemError::EINVAL));
+        assert_eq!(flock_range(i64::MAX, 1, 1), Err(SystemError::EOVERFLOW));
+        assert_eq!(flock_range(0, i64::MAX, 2), Err(SystemError::EOVERFLOW));
+        assert_eq!(
+            flock_range(0, i64::MAX, 1),
+            Ok((i64::MAX as u64, i64::MAX as u64))
+        );
+    }
+
+    #[test]
+    fn test_conflicts_with() {
+        use FileLockType::{Read, Write};
+        assert!(!lock(A, Read, 0, 9).conflicts_with(&lock(B, Read, 0, 9)));
+        assert!(lock(A, Read, 0, 9).conflicts_with(&lock(B, Write, 9, 20)));
+        assert!(lock(A, Write, 0, 9).conflicts_with(&lock(B, Read, 5, 5)));
+        // 相邻但不重叠
+        assert!(!lock(A, Write, 0, 9).conflicts_with(&lock(B, Write, 10, 20)));
+        // 同一个持有者的锁不会冲突
+        assert!(!lock(A, Write, 0, 9).conflicts_with(&lock(A, Write, 0, 9)));
+        // OFD锁与POSIX记录锁会冲突
+        assert!(lock(Owner::Ofd(1), Write, 0, u64::MAX).conflicts_with(&lock(A, Read, 100, 100)));
+    }
+
+    #[test]
+    fn test_posix_update_split() {
+        use FileLockType::{Read, Write};
+        let mut locks = Vec::new();
+        posix_update(&mut locks, A, Some(Write), 0, 99);
+        // 解锁中间的一段
+        posix_update(&mut locks, A, None, 10, 19);
+        assert_eq!(ranges(&locks), [(A, Write, 0, 9), (A, Write, 20, 99)]);
+        // 在写锁中间加读锁
+        posix_update(&mut locks, A, Some(Read), 40, 59);
+        assert_eq!(
+            ranges(&locks),
+            [
+                (A, Write, 0, 9),
+                (A, Write, 20, 39),
+                (A, Read, 40, 59),
+                (A, Write, 60, 99)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_posix_update_merge() {
+        use FileLockType::{Read, Write};
+        let mut locks = Vec::new();
+        posix_update(&mut locks, A, Some(Write), 0, 9);
+        posix_update(&mut locks, A, Some(Write), 20, 29);
+        // 填上中间的空隙后，三段合并为一段
+        posix_update(&mut locks, A, Some(Write), 10, 19);
+        assert_eq!(ranges(&locks), [(A, Write, 0, 29)]);
+
+        // 类型不同的相邻锁不合并
+        posix_update(&mut locks, A, Some(Read), 30, 39);
+        assert_eq!(ranges(&locks), [(A, Write, 0, 29), (A, Read, 30, 39)]);
+
+        // 覆盖整个范围时替换原有的锁
+        posix_update(&mut locks, A, Some(Read), 0, u64::MAX);
+        assert_eq!(ranges(&locks), [(A, Read, 0, u64::MAX)]);
+        posix_update(&mut locks, A, None, 0, u64::MAX);
+        assert!(locks.is_empty());
+    }
+
+    #[test]
+    fn test_posix_update_other_owner() {
+        use FileLockType::Read;
+        let mut locks = Vec::new();
+        posix_update(&mut locks, A, Some(Read), 0, 9);
+        posix_update(&mut locks, B, Some(Read), 0, 9);
+        posix_update(&mut locks, B, Some(Read), 10, 19);
+        // 不同持有者的锁既不合并也不相互影响
+        assert_eq!(ranges(&locks), [(A, Read, 0, 9), (B, Read, 0, 19)]);
+        posix_update(&mut locks, A, None, 0, u64::MAX);
+        assert_eq!(ranges(&locks), [(B, Read, 0, 19)]);
+    }
+}
@@ -0,0 +1,506 @@
+//! FUSE协议的消息格式
+//!
+//! 与linux的FUSE 7.31二进制兼容，因此可以直接使用libfuse、fuser等用户态库。
+//! 所有的结构体都以本机字节序传输。
+//!
+//! 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/include/uapi/linux/fuse.h
+
+#![no_std]
+#![allow(clippy::needless_return)]
+
+extern crate alloc;
+
+#[cfg(test)]
+#[macro_use]
+extern crate std;
+
+use core::mem::size_of;
+
+use alloc::{string::String, vec::Vec};
+use system_error::SystemError;
+
+/// 协议的主版本号
+pub const FUSE_KERNEL_VERSION: u32 = 7;
+/// 协议的次版本号
+pub const FUSE_KERNEL_MINOR_VERSION: u32 = 31;
+
+/// 根目录的节点号
+pub const FUSE_ROOT_ID: u64 = 1;
+
+/// 守护进程读取请求时，缓冲区的最小长度
+pub const FUSE_MIN_READ_BUFFER: usize = 8192;
+
+/// FUSE请求的操作码
+///
+/// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/include/uapi/linux/fuse.h#536
+#[allow(dead_code)]
+pub mod opcode {
+    pub const FUSE_LOOKUP: u32 = 1;
+    pub const FUSE_FORGET: u32 = 2;
+    pub const FUSE_GETATTR: u32 = 3;
+    pub const FUSE_SETATTR: u32 = 4;
+    pub const FUSE_READLINK: u32 = 5;
+    pub const FUSE_UNLINK: u32 = 10;
+    pub const FUSE_OPEN: u32 = 14;
+    pub const FUSE_READ: u32 = 15;
+    pub const FUSE_WRITE: u32 = 16;
+    pub const FUSE_RELEASE: u32 = 18;
+    pub const FUSE_INIT: u32 = 26;
+    pub const FUSE_OPENDIR: u32 = 27;
+    pub const FUSE_READDIR: u32 = 28;
+    pub const FUSE_RELEASEDIR: u32 = 29;
+    pub const FUSE_CREATE: u32 = 35;
+}
+
+/// 可以与字节序列直接相互转换的协议结构体
+///
+/// # Safety
+///
+/// 实现者必须是`#[repr(C)]`的、没有填充字节的、任意位模式都合法的类型
+pub unsafe trait FuseAbi: Copy + Default {
+    fn as_bytes(&self) -> &[u8] {
+        unsafe { core::slice::from_raw_parts(self as *const Self as *const u8, size_of::<Self>()) }
+    }
+
+    /// 从`buf`的开头解析结构体，长度不足时返回EIO
+    fn read_from(buf: &[u8]) -> Result<Self, SystemError> {
+        if buf.len() < size_of::<Self>() {
+            return Err(SystemError::EIO);
+        }
+        Ok(unsafe { core::ptr::read_unaligned(buf.as_ptr() as *const Self) })
+    }
+
+    /// 与`read_from`相同，但是允许`buf`比结构体短，缺少的字段为0
+    ///
+    /// 用于解析旧版本的守护进程发来的较短的回复
+    fn read_from_prefix(buf: &[u8]) -> Self {
+        let mut value = Self::default();
+        let len = buf.len().min(size_of::<Self>());
+        unsafe {
+            core::ptr::copy_nonoverlapping(buf.as_ptr(), &mut value as *mut Self as *mut u8, len)
+        };
+        value
+    }
+}
+
+/// 请求头
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FuseInHeader {
+    pub len: u32,
+    pub opcode: u32,
+    pub unique: u64,
+    pub nodeid: u64,
+    pub uid: u32,
+    pub gid: u32,
+    pub pid: u32,
+    pub padding: u32,
+}
+
+/// 回复头
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FuseOutHeader {
+    pub len: u32,
+    /// 0或负的错误码
+    pub error: i32,
+    pub unique: u64,
+}
+
+/// 文件属性
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FuseAttr {
+    pub ino: u64,
+    pub size: u64,
+    pub blocks: u64,
+    pub atime: u64,
+    pub mtime: u64,
+    pub ctime: u64,
+    pub atimensec: u32,
+    pub mtimensec: u32,
+    pub ctimensec: u32,
+    pub mode: u32,
+    pub nlink: u32,
+    pub uid: u32,
+    pub gid: u32,
+    pub rdev: u32,
+    pub blksize: u32,
+    pub flags: u32,
+}
+
+/// LOOKUP、CREATE的回复
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FuseEntryOut {
+    /// 为0时表示文件不存在
+    pub nodeid: u64,
+    pub generation: u64,
+    pub entry_valid: u64,
+    pub attr_valid: u64,
+    pub entry_valid_nsec: u32,
+    pub attr_valid_nsec: u32,
+    pub attr: FuseAttr,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FuseForgetIn {
+    pub nlookup: u64,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FuseGetattrIn {
+    pub getattr_flags: u32,
+    pub dummy: u32,
+    pub fh: u64,
+}
+
+/// GETATTR、SETATTR的回复
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FuseAttrOut {
+    pub attr_valid: u64,
+    pub attr_valid_nsec: u32,
+    pub dummy: u32,
+    pub attr: FuseAttr,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FuseSetattrIn {
+    pub valid: u32,
+    pub padding: u32,
+    pub fh: u64,
+    pub size: u64,
+    pub lock_owner: u64,
+    pub atime: u64,
+    pub mtime: u64,
+    pub ctime: u64,
+    pub atimensec: u32,
+    pub mtimensec: u32,
+    pub ctimensec: u32,
+    pub mode: u32,
+    pub unused4: u32,
+    pub uid: u32,
+    pub gid: u32,
+    pub unused5: u32,
+}
+
+/// OPEN、OPENDIR的参数
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FuseOpenIn {
+    pub flags: u32,
+    pub open_flags: u32,
+}
+
+/// OPEN、OPENDIR、CREATE的回复
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FuseOpenOut {
+    pub fh: u64,
+    pub open_flags: u32,
+    pub padding: u32,
+}
+
+/// CREATE的参数，后面跟着以'\0'结尾的文件名
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FuseCreateIn {
+    pub flags: u32,
+    pub mode: u32,
+    pub umask: u32,
+    pub open_flags: u32,
+}
+
+/// RELEASE、RELEASEDIR的参数
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FuseReleaseIn {
+    pub fh: u64,
+    pub flags: u32,
+    pub release_flags: u32,
+    pub lock_owner: u64,
+}
+
+/// READ、READDIR的参数
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FuseReadIn {
+    pub fh: u64,
+    pub offset: u64,
+    pub size: u32,
+    pub read_flags: u32,
+    pub lock_owner: u64,
+    pub flags: u32,
+    pub padding: u32,
+}
+
+/// WRITE的参数，后面跟着要写入的数据
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FuseWriteIn {
+    pub fh: u64,
+    pub offset: u64,
+    pub size: u32,
+    pub write_flags: u32,
+    pub lock_owner: u64,
+    pub flags: u32,
+    pub padding: u32,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FuseWriteOut {
+    pub size: u32,
+    pub padding: u32,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FuseInitIn {
+    pub major: u32,
+    pub minor: u32,
+    pub max_readahead: u32,
+    pub flags: u32,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FuseInitOut {
+    pub major: u32,
+    pub minor: u32,
+    pub max_readahead: u32,
+    pub flags: u32,
+    pub max_background: u16,
+    pub congestion_threshold: u16,
+    pub max_write: u32,
+    pub time_gran: u32,
+    pub max_pages: u16,
+    pub map_alignment: u16,
+    pub flags2: u32,
+    pub unused: [u32; 7],
+}
+
+/// READDIR回复中的目录项头部，后面跟着文件名，整个目录项按8字节对齐
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FuseDirentHeader {
+    pub ino: u64,
+    /// 下一个目录项的偏移量
+    pub off: u64,
+    pub namelen: u32,
+    pub ty: u32,
+}
+
+unsafe impl FuseAbi for FuseInHeader {}
+unsafe impl FuseAbi for FuseOutHeader {}
+unsafe impl FuseAbi for FuseAttr {}
+unsafe impl FuseAbi for FuseEntryOut {}
+unsafe impl FuseAbi for FuseForgetIn {}
+unsafe impl FuseAbi for FuseGetattrIn {}
+unsafe impl FuseAbi for FuseAttrOut {}
+unsafe impl FuseAbi for FuseSetattrIn {}
+unsafe impl FuseAbi for FuseOpenIn {}
+unsafe impl FuseAbi for FuseOpenOut {}
+unsafe impl FuseAbi for FuseCreateIn {}
+unsafe impl FuseAbi for FuseReleaseIn {}
+unsafe impl FuseAbi for FuseReadIn {}
+unsafe impl FuseAbi for FuseWriteIn {}
+unsafe impl FuseAbi for FuseWriteOut {}
+unsafe impl FuseAbi for FuseInitIn {}
+unsafe impl FuseAbi for FuseInitOut {}
+unsafe impl FuseAbi for FuseDirentHeader {}
+
+/// READDIR回复中的一个目录项
+#[derive(Debug, Clone)]
+pub struct FuseDirent {
+    pub ino: u64,
+    pub off: u64,
+    pub name: String,
+}
+
+/// 解析READDIR的回复
+pub fn parse_dirents(mut buf: &[u8]) -> Result<Vec<FuseDirent>, SystemError> {
+    const HDR_LEN: usize = size_of::<FuseDirentHeader>();
+
+    let mut entries = Vec::new();
+    while !buf.is_empty() {
+        let hdr = FuseDirentHeader::read_from(buf)?;
+        let namelen = hdr.namelen as usize;
+        if namelen == 0 || buf.len() < HDR_LEN + namelen {
+            return Err(SystemError::EIO);
+        }
+        let name = core::str::from_utf8(&buf[HDR_LEN..HDR_LEN + namelen])
+            .map_err(|_| SystemError::EILSEQ)?;
+        entries.push(FuseDirent {
+            ino: hdr.ino,
+            off: hdr.off,
+            name: String::from(name),
+        });
+
+        let reclen = (HDR_LEN + namelen + 7) & !7;
+        buf = &buf[reclen.min(buf.len())..];
+    }
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::vec::Vec;
+
+    /// 构造一个READDIR回复中的目录项，按8字节对齐
+    fn dirent(ino: u64, off: u64, name: &[u8]) -> Vec<u8> {
+        let hdr = FuseDirentHeader {
+            ino,
+            off,
+            namelen: name.len() as u32,
+            ty: 0,
+        };
+        let mut buf = Vec::from(hdr.as_bytes());
+        buf.extend_from_slice(name);
+        buf.resize((buf.len() + 7) & !7, 0);
+        buf
+    }
+
+    #[test]
+    fn test_struct_sizes() {
+        // 与linux的fuse.h中的结构体大小一致
+        assert_eq!(size_of::<FuseInHeader>(), 40);
+        assert_eq!(size_of::<FuseOutHeader>(), 16);
+        assert_eq!(size_of::<FuseAttr>(), 88);
+        assert_eq!(size_of::<FuseEntryOut>(), 128);
+        assert_eq!(size_of::<FuseForgetIn>(), 8);
+        assert_eq!(size_of::<FuseGetattrIn>(), 16);
+        assert_eq!(size_of::<FuseAttrOut>(), 104);
+        assert_eq!(size_of::<FuseSetattrIn>(), 88);
+        assert_eq!(size_of::<FuseOpenIn>(), 8);
+        assert_eq!(size_of::<FuseOpenOut>(), 16);
+        assert_eq!(size_of::<FuseCreateIn>(), 16);
+        assert_eq!(size_of::<FuseReleaseIn>(), 24);
+        assert_eq!(size_of::<FuseReadIn>(), 40);
+        assert_eq!(size_of::<FuseWriteIn>(), 40);
+        assert_eq!(size_of::<FuseWriteOut>(), 8);
+        assert_eq!(size_of::<FuseInitIn>(), 16);
+        assert_eq!(size_of::<FuseInitOut>(), 64);
+        assert_eq!(size_of::<FuseDirentHeader>(), 24);
+    }
+
+    #[test]
+    fn test_encode_decode() {
+        let hdr = FuseInHeader {
+            len: 48,
+            opcode: opcode::FUSE_LOOKUP,
+            unique: 0x1122334455667788,
+            nodeid: FUSE_ROOT_ID,
+            uid: 1000,
+            gid: 100,
+            pid: 42,
+            padding: 0,
+        };
+        let bytes = hdr.as_bytes();
+        assert_eq!(bytes.len(), size_of::<FuseInHeader>());
+        assert_eq!(&bytes[0..4], &48u32.to_ne_bytes());
+        assert_eq!(&bytes[4..8], &opcode::FUSE_LOOKUP.to_ne_bytes());
+        assert_eq!(&bytes[8..16], &0x1122334455667788u64.to_ne_bytes());
+
+        let decoded = FuseInHeader::read_from(bytes).unwrap();
+        assert_eq!(decoded.unique, hdr.unique);
+        assert_eq!(decoded.nodeid, hdr.nodeid);
+        assert_eq!((decoded.uid, decoded.gid, decoded.pid), (1000, 100, 42));
+
+        // 缓冲区可以比结构体长，也可以不对齐
+        let mut buf = vec![0u8; 1];
+        buf.extend_from_slice(
+            FuseOutHeader {
+                len: 16,
+                error: -2,
+                unique: 7,
+            }
+            .as_bytes(),
+        );
+        buf.extend_from_slice(&[0xff; 8]);
+        let out = FuseOutHeader::read_from(&buf[1..]).unwrap();
+        assert_eq!((out.len, out.error, out.unique), (16, -2, 7));
+
+        assert_eq!(
+            FuseOutHeader::read_from(&buf[1..16]).unwrap_err(),
+            SystemError::EIO
+        );
+    }
+
+    #[test]
+    fn test_read_from_prefix() {
+        let out = FuseInitOut {
+            major: FUSE_KERNEL_VERSION,
+            minor: 22,
+            max_readahead: 4096,
+            flags: 1,
+            max_background: 12,
+            congestion_threshold: 9,
+            max_write: 65536,
+            time_gran: 1,
+            ..Default::default()
+        };
+        // 旧版本的守护进程只回复前24个字节，其余字段为0
+        let init = FuseInitOut::read_from_prefix(&out.as_bytes()[..24]);
+        assert_eq!((init.major, init.minor), (FUSE_KERNEL_VERSION, 22));
+        assert_eq!(init.max_write, 65536);
+        assert_eq!(init.time_gran, 0);
+        assert_eq!(init.max_pages, 0);
+
+        // 多余的字节被忽略
+        let mut long = Vec::from(out.as_bytes());
+        long.extend_from_slice(&[0xff; 16]);
+        let init = FuseInitOut::read_from_prefix(&long);
+        assert_eq!(init.time_gran, 1);
+        assert_eq!(init.unused, [0; 7]);
+    }
+
+    #[test]
+    fn test_parse_dirents() {
+        assert!(parse_dirents(&[]).unwrap().is_empty());
+
+        let mut buf = dirent(1, 1, b".");
+        buf.extend(dirent(2, 2, b"hello"));
+        // 名字长度正好是8的倍数时没有填充
+        buf.extend(dirent(3, 3, b"12345678"));
+        let entries = parse_dirents(&buf).unwrap();
+        assert_eq!(entries.len(), 3);
+        assert_eq!((entries[1].ino, entries[1].off), (2, 2));
+        assert_eq!(entries[1].name, "hello");
+        assert_eq!(entries[2].name, "12345678");
+
+        // 最后一个目录项可以省略填充
+        let mut buf = dirent(4, 4, b"abc");
+        buf.truncate(size_of::<FuseDirentHeader>() + 3);
+        assert_eq!(parse_dirents(&buf).unwrap()[0].name, "abc");
+    }
+
+    #[test]
+    fn test_parse_dirents_invalid() {
+        // 文件名为空
+        assert_eq!(
+            parse_dirents(&dirent(1, 1, b"")).unwrap_err(),
+            SystemError::EIO
+        );
+        // 文件名被截断
+        let buf = dirent(1, 1, b"hello");
+        assert_eq!(
+            parse_dirents(&buf[..size_of::<FuseDirentHeader>() + 2]).unwrap_err(),
+            SystemError::EIO
+        );
+        // 头部被截断
+        let mut buf = dirent(1, 1, b"a");
+        buf.extend_from_slice(&[0; 8]);
+        assert_eq!(parse_dirents(&buf).unwrap_err(), SystemError::EIO);
+        // 文件名不是合法的UTF-8
+        assert_eq!(
+            parse_dirents(&dirent(1, 1, &[0xff, 0xfe])).unwrap_err(),
+            SystemError::EILSEQ
+        );
+    }
+}
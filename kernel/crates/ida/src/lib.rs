@@ -102,6 +102,20 @@ impl IdAllocator {
         return None;
     }
 
+    /// 分配指定的id
+    ///
+    /// ## 返回
+    ///
+    /// 如果id在分配器的范围内且尚未被使用，返回Some(id)，否则返回None
+    pub fn alloc_specific(&mut self, id: usize) -> Option<usize> {
+        if id < self.min_id || id >= self.max_id || self.exists(id) {
+            return None;
+        }
+        self.xarray.store(id as u64, EmptyIdaItem);
+        self.used += 1;
+        return Some(id);
+    }
+
     /// 检查id是否存在
     ///
     /// ## 参数
@@ -207,4 +221,24 @@ mod tests {
 
         assert_eq!(ida.used(), 0);
     }
+
+    #[test]
+    fn test_alloc_specific() {
+        let mut ida = IdAllocator::new(1, 10).unwrap();
+        assert_eq!(ida.alloc_specific(0), None);
+        assert_eq!(ida.alloc_specific(10), None);
+        assert_eq!(ida.alloc_specific(5), Some(5));
+        assert_eq!(ida.alloc_specific(5), None);
+        assert_eq!(ida.used(), 1);
+
+        for i in 1..10 {
+            if i != 5 {
+                assert_eq!(ida.alloc(), Some(i));
+            }
+        }
+        assert_eq!(ida.alloc(), None);
+
+        ida.free(5);
+        assert_eq!(ida.alloc_specific(5), Some(5));
+    }
 }
@@ -0,0 +1,65 @@
+//! 文件系统挂载选项的解析
+//!
+//! mount系统调用传入的选项是以逗号分隔的`key`或`key=value`，
+//! 这里只负责把它们解析为各个文件系统的选项结构体，不涉及内核的其他部分。
+
+#![no_std]
+#![allow(clippy::needless_return)]
+
+extern crate alloc;
+
+#[cfg(test)]
+#[macro_use]
+extern crate std;
+
+use system_error::SystemError;
+
+pub mod overlayfs;
+pub mod tmpfs;
+
+/// 解析带有k/m/g后缀的数字
+///
+/// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/lib/cmdline.c#153
+pub fn memparse(value: &str) -> Result<usize, SystemError> {
+    let (digits, shift) = match value.as_bytes().last() {
+        Some(b'k' | b'K') => (&value[..value.len() - 1], 10),
+        Some(b'm' | b'M') => (&value[..value.len() - 1], 20),
+        Some(b'g' | b'G') => (&value[..value.len() - 1], 30),
+        _ => (value, 0),
+    };
+    let num = digits.parse::<usize>().map_err(|_| SystemError::EINVAL)?;
+    return num.checked_mul(1 << shift).ok_or(SystemError::EINVAL);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::string::ToString;
+
+    use super::*;
+
+    /// 把以逗号分隔的选项拆分为(key, value)
+    pub(crate) fn split(options: &str) -> impl Iterator<Item = (&str, Option<&str>)> {
+        options
+            .split(',')
+            .filter(|opt| !opt.is_empty())
+            .map(|opt| match opt.split_once('=') {
+                Some((key, value)) => (key, Some(value)),
+                None => (opt, None),
+            })
+    }
+
+    #[test]
+    fn test_memparse() {
+        assert_eq!(memparse("123"), Ok(123));
+        assert_eq!(memparse("4k"), Ok(4 << 10));
+        assert_eq!(memparse("2M"), Ok(2 << 20));
+        assert_eq!(memparse("1g"), Ok(1 << 30));
+        assert_eq!(memparse(""), Err(SystemError::EINVAL));
+        assert_eq!(memparse("k"), Err(SystemError::EINVAL));
+        assert_eq!(memparse("-1"), Err(SystemError::EINVAL));
+        assert_eq!(memparse("1t"), Err(SystemError::EINVAL));
+        // 乘上后缀之后溢出
+        let overflow = (usize::MAX >> 20).to_string() + "g";
+        assert_eq!(memparse(&overflow), Err(SystemError::EINVAL));
+    }
+}
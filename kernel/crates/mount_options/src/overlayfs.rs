@@ -0,0 +1,79 @@
+//! overlayfs的挂载选项
+//!
+//! 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/fs/overlayfs/params.c
+
+use alloc::vec::Vec;
+use system_error::SystemError;
+
+/// overlayfs的挂载选项
+#[derive(Debug, PartialEq, Eq)]
+pub struct OvlOptions<'a> {
+    /// 下层目录，从上到下排列
+    pub lowerdirs: Vec<&'a str>,
+    pub upperdir: Option<&'a str>,
+    pub workdir: Option<&'a str>,
+}
+
+impl<'a> OvlOptions<'a> {
+    /// 解析`lowerdir=`、`upperdir=`与`workdir=`选项，至少需要一个下层目录
+    pub fn parse(
+        options: impl Iterator<Item = (&'a str, Option<&'a str>)>,
+    ) -> Result<Self, SystemError> {
+        let mut lowerdir = None;
+        let mut upperdir = None;
+        let mut workdir = None;
+        for (key, value) in options {
+            let value = value.ok_or(SystemError::EINVAL)?;
+            match key {
+                "lowerdir" => lowerdir = Some(value),
+                "upperdir" => upperdir = Some(value),
+                "workdir" => workdir = Some(value),
+                _ => return Err(SystemError::EINVAL),
+            }
+        }
+
+        let lowerdirs = lowerdir
+            .ok_or(SystemError::EINVAL)?
+            .split(':')
+            .filter(|path| !path.is_empty())
+            .collect::<Vec<_>>();
+        if lowerdirs.is_empty() {
+            return Err(SystemError::EINVAL);
+        }
+        return Ok(Self {
+            lowerdirs,
+            upperdir,
+            workdir,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::split;
+
+    fn parse(options: &str) -> Result<OvlOptions, SystemError> {
+        OvlOptions::parse(split(options))
+    }
+
+    #[test]
+    fn test_parse_options() {
+        let options = parse("lowerdir=/a:/b,upperdir=/u,workdir=/w").unwrap();
+        assert_eq!(options.lowerdirs, vec!["/a", "/b"]);
+        assert_eq!(options.upperdir, Some("/u"));
+        assert_eq!(options.workdir, Some("/w"));
+
+        // 空的路径被忽略
+        let options = parse("lowerdir=:/a::").unwrap();
+        assert_eq!(options.lowerdirs, vec!["/a"]);
+        assert_eq!(options.upperdir, None);
+        assert_eq!(options.workdir, None);
+
+        assert_eq!(parse(""), Err(SystemError::EINVAL));
+        assert_eq!(parse("upperdir=/u"), Err(SystemError::EINVAL));
+        assert_eq!(parse("lowerdir=:"), Err(SystemError::EINVAL));
+        assert_eq!(parse("lowerdir"), Err(SystemError::EINVAL));
+        assert_eq!(parse("lowerdir=/a,index=on"), Err(SystemError::EINVAL));
+    }
+}
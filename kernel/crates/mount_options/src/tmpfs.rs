@@ -0,0 +1,133 @@
+//! tmpfs的挂载选项
+//!
+//! 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/mm/shmem.c#3744
+
+use system_error::SystemError;
+
+use crate::memparse;
+
+/// 根目录的默认权限
+pub const TMPFS_DEFAULT_MODE: u32 = 0o1777;
+
+/// 权限位（包括set-user-ID、set-group-ID与sticky位）
+const S_IALLUGO: u32 = 0o7777;
+
+/// tmpfs的挂载选项
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TmpfsOptions {
+    /// 最多能使用的页面数，为None时不限制
+    pub max_blocks: Option<usize>,
+    /// 最多能创建的inode数，为None时不限制
+    pub max_inodes: Option<usize>,
+    /// 根目录的权限
+    pub mode: u32,
+}
+
+impl TmpfsOptions {
+    /// 解析`size=`、`nr_inodes=`与`mode=`选项
+    ///
+    /// ## 参数
+    ///
+    /// - `options`: 挂载选项的(key, value)
+    /// - `total_pages`: 物理内存的总页数，用于计算默认上限以及`size=`的百分比
+    /// - `page_size`: 页面大小
+    pub fn parse<'a>(
+        options: impl Iterator<Item = (&'a str, Option<&'a str>)>,
+        total_pages: usize,
+        page_size: usize,
+    ) -> Result<Self, SystemError> {
+        // 与Linux一致，默认使用一半的物理内存
+        let mut result = Self {
+            max_blocks: Some(total_pages / 2),
+            max_inodes: Some(total_pages / 2),
+            mode: TMPFS_DEFAULT_MODE,
+        };
+
+        for (key, value) in options {
+            let value = value.ok_or(SystemError::EINVAL)?;
+            match key {
+                "size" => {
+                    let bytes = if let Some(percent) = value.strip_suffix('%') {
+                        let percent = percent.parse::<usize>().map_err(|_| SystemError::EINVAL)?;
+                        total_pages
+                            .checked_mul(percent)
+                            .ok_or(SystemError::EINVAL)?
+                            / 100
+                            * page_size
+                    } else {
+                        memparse(value)?
+                    };
+                    let pages = bytes.div_ceil(page_size);
+                    result.max_blocks = if pages == 0 { None } else { Some(pages) };
+                }
+                "nr_inodes" => {
+                    let inodes = memparse(value)?;
+                    result.max_inodes = if inodes == 0 { None } else { Some(inodes) };
+                }
+                "mode" => {
+                    let mode = u32::from_str_radix(value, 8).map_err(|_| SystemError::EINVAL)?;
+                    result.mode = mode & S_IALLUGO;
+                }
+                _ => return Err(SystemError::EINVAL),
+            }
+        }
+
+        return Ok(result);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::split;
+
+    const TOTAL_PAGES: usize = 1000;
+    const PAGE_SIZE: usize = 4096;
+
+    fn parse(options: &str) -> Result<TmpfsOptions, SystemError> {
+        TmpfsOptions::parse(split(options), TOTAL_PAGES, PAGE_SIZE)
+    }
+
+    #[test]
+    fn test_parse_defaults() {
+        let options = parse("").unwrap();
+        assert_eq!(options.max_blocks, Some(TOTAL_PAGES / 2));
+        assert_eq!(options.max_inodes, Some(TOTAL_PAGES / 2));
+        assert_eq!(options.mode, TMPFS_DEFAULT_MODE);
+    }
+
+    #[test]
+    fn test_parse_size() {
+        // 不足一页的部分按一页计算
+        let options = parse("size=1").unwrap();
+        assert_eq!(options.max_blocks, Some(1));
+        let options = parse(&format!("size={}", PAGE_SIZE * 3 + 1)).unwrap();
+        assert_eq!(options.max_blocks, Some(4));
+        let options = parse("size=1m").unwrap();
+        assert_eq!(options.max_blocks, Some((1 << 20) / PAGE_SIZE));
+        let options = parse("size=10%").unwrap();
+        assert_eq!(options.max_blocks, Some(TOTAL_PAGES / 10));
+        // 为0时不限制
+        let options = parse("size=0,nr_inodes=0").unwrap();
+        assert_eq!(options.max_blocks, None);
+        assert_eq!(options.max_inodes, None);
+        assert_eq!(parse("size=x%"), Err(SystemError::EINVAL));
+        assert_eq!(
+            parse(&format!("size={}%", usize::MAX)),
+            Err(SystemError::EINVAL)
+        );
+    }
+
+    #[test]
+    fn test_parse_other_options() {
+        let options = parse("nr_inodes=2k,mode=755").unwrap();
+        assert_eq!(options.max_inodes, Some(2048));
+        assert_eq!(options.mode, 0o755);
+        // 只保留权限位
+        let options = parse("mode=177777").unwrap();
+        assert_eq!(options.mode, 0o7777);
+        assert_eq!(parse("mode=8"), Err(SystemError::EINVAL));
+        assert_eq!(parse("size"), Err(SystemError::EINVAL));
+        assert_eq!(parse("uid=0"), Err(SystemError::EINVAL));
+    }
+}
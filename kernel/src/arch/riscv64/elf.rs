@@ -1,4 +1,8 @@
-use crate::{arch::MMArch, libs::elf::ElfArch, mm::MemoryManagementArch};
+use crate::{
+    arch::{interrupt::TrapFrame, MMArch},
+    libs::elf::ElfArch,
+    mm::MemoryManagementArch,
+};
 
 #[derive(Debug, Clone, Copy, Hash)]
 pub struct RiscV64ElfArch;
@@ -7,4 +11,18 @@ impl ElfArch for RiscV64ElfArch {
     const ELF_ET_DYN_BASE: usize = MMArch::USER_END_VADDR.data() / 3 * 2;
 
     const ELF_PAGE_SIZE: usize = MMArch::PAGE_SIZE;
+
+    const ELF_MACHINE: u16 = elf::abi::EM_RISCV;
+
+    type Gregset = [usize; 32];
+
+    /// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/arch/riscv/include/uapi/asm/ptrace.h#19
+    fn core_regs(frame: &TrapFrame) -> Self::Gregset {
+        [
+            frame.epc, frame.ra, frame.sp, frame.gp, frame.tp, frame.t0, frame.t1, frame.t2,
+            frame.s0, frame.s1, frame.a0, frame.a1, frame.a2, frame.a3, frame.a4, frame.a5,
+            frame.a6, frame.a7, frame.s2, frame.s3, frame.s4, frame.s5, frame.s6, frame.s7,
+            frame.s8, frame.s9, frame.s10, frame.s11, frame.t3, frame.t4, frame.t5, frame.t6,
+        ]
+    }
 }
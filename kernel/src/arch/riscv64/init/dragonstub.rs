@@ -27,6 +27,11 @@ impl BootCallbacks for DragonStubCallBack {
         Ok(())
     }
 
+    fn init_initramfs(&self) -> Result<(), SystemError> {
+        // parsed in `early_init_scan_chosen()`
+        Ok(())
+    }
+
     fn early_init_framebuffer_info(
         &self,
         _scinfo: &mut BootTimeScreenInfo,
@@ -1,9 +1,9 @@
 use log::error;
 
 use crate::{
-    arch::{sched::sched, CurrentIrqArch},
+    arch::{interrupt::TrapFrame, sched::sched, CurrentIrqArch},
     exception::InterruptArch,
-    process::ProcessManager,
+    process::{coredump::do_coredump, exit::StopEvent, ProcessManager},
 };
 
 /// 信号最大值
@@ -125,22 +125,24 @@ impl Signal {
     }
 
     /// 调用信号的默认处理函数
-    pub fn handle_default(&self) {
+    ///
+    /// `frame`是进程陷入内核时保存的栈帧，生成core dump时会用到
+    pub fn handle_default(&self, frame: &TrapFrame) {
         match self {
             Signal::INVALID => {
                 error!("attempting to handler an Invalid");
             }
             Signal::SIGHUP => sig_terminate(self.clone()),
             Signal::SIGINT => sig_terminate(self.clone()),
-            Signal::SIGQUIT => sig_terminate_dump(self.clone()),
-            Signal::SIGILL => sig_terminate_dump(self.clone()),
-            Signal::SIGTRAP => sig_terminate_dump(self.clone()),
-            Signal::SIGABRT_OR_IOT => sig_terminate_dump(self.clone()),
-            Signal::SIGBUS => sig_terminate_dump(self.clone()),
-            Signal::SIGFPE => sig_terminate_dump(self.clone()),
+            Signal::SIGQUIT => sig_terminate_dump(self.clone(), frame),
+            Signal::SIGILL => sig_terminate_dump(self.clone(), frame),
+            Signal::SIGTRAP => sig_terminate_dump(self.clone(), frame),
+            Signal::SIGABRT_OR_IOT => sig_terminate_dump(self.clone(), frame),
+            Signal::SIGBUS => sig_terminate_dump(self.clone(), frame),
+            Signal::SIGFPE => sig_terminate_dump(self.clone(), frame),
             Signal::SIGKILL => sig_terminate(self.clone()),
             Signal::SIGUSR1 => sig_terminate(self.clone()),
-            Signal::SIGSEGV => sig_terminate_dump(self.clone()),
+            Signal::SIGSEGV => sig_terminate_dump(self.clone(), frame),
             Signal::SIGUSR2 => sig_terminate(self.clone()),
             Signal::SIGPIPE => sig_terminate(self.clone()),
             Signal::SIGALRM => sig_terminate(self.clone()),
@@ -153,8 +155,8 @@ impl Signal {
             Signal::SIGTTIN => sig_stop(self.clone()),
             Signal::SIGTTOU => sig_stop(self.clone()),
             Signal::SIGURG => sig_ignore(self.clone()),
-            Signal::SIGXCPU => sig_terminate_dump(self.clone()),
-            Signal::SIGXFSZ => sig_terminate_dump(self.clone()),
+            Signal::SIGXCPU => sig_terminate_dump(self.clone(), frame),
+            Signal::SIGXFSZ => sig_terminate_dump(self.clone(), frame),
             Signal::SIGVTALRM => sig_terminate(self.clone()),
             Signal::SIGPROF => sig_terminate(self.clone()),
             Signal::SIGWINCH => sig_ignore(self.clone()),
@@ -176,6 +178,8 @@ pub enum SigCode {
     User = 0,
     /// sent by kernel from somewhere
     Kernel = 0x80,
+    /// seccomp过滤器返回SECCOMP_RET_TRAP时发送的SIGSYS
+    SysSeccomp = 1,
     /// 通过sigqueue发送
     Queue = -1,
     /// 定时器过期时发送
@@ -195,6 +199,7 @@ impl SigCode {
         match x {
             0 => Self::User,
             0x80 => Self::Kernel,
+            1 => Self::SysSeccomp,
             -1 => Self::Queue,
             -2 => Self::Timer,
             -3 => Self::Mesgq,
@@ -304,13 +309,18 @@ fn sig_terminate(sig: Signal) {
 }
 
 /// 信号默认处理函数——终止进程并生成 core dump
-fn sig_terminate_dump(sig: Signal) {
-    ProcessManager::exit(sig as usize);
-    // TODO 生成 coredump 文件
+fn sig_terminate_dump(sig: Signal, frame: &TrapFrame) {
+    let mut exit_code = sig as usize;
+    // 生成了core文件时，设置wait状态中的WCOREDUMP位
+    if do_coredump(sig, frame) {
+        exit_code |= 0x80;
+    }
+    ProcessManager::exit(exit_code);
 }
 
 /// 信号默认处理函数——暂停进程
 fn sig_stop(sig: Signal) {
+    ProcessManager::current_pcb().notify_parent_cldstop(StopEvent::Stopped(sig));
     let guard = unsafe { CurrentIrqArch::save_and_disable_irq() };
     ProcessManager::mark_stop().unwrap_or_else(|e| {
         error!(
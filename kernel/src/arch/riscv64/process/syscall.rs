@@ -7,7 +7,7 @@ use crate::{
     exception::InterruptArch,
     mm::ucontext::AddressSpace,
     process::{
-        exec::{load_binary_file, ExecParam, ExecParamFlags},
+        exec::{exec_transform_cred, load_binary_file, ExecParam, ExecParamFlags},
         ProcessManager,
     },
     syscall::Syscall,
@@ -64,6 +64,7 @@ impl Syscall {
 
         // 加载可执行文件
         let load_result = load_binary_file(&mut param)?;
+        exec_transform_cred(&param)?;
         // debug!("load binary file done");
         // debug!("argv: {:?}, envp: {:?}", argv, envp);
         param.init_info_mut().args = argv;
@@ -11,3 +11,13 @@ pub fn rand() -> usize {
     let x: usize = unsafe { core::mem::transmute(buf) };
     return x;
 }
+
+/// riscv64暂不支持硬件随机数发生器（Zkr扩展）
+pub fn arch_get_random_long() -> Option<u64> {
+    None
+}
+
+/// riscv64暂不支持硬件熵源（Zkr扩展）
+pub fn arch_get_random_seed_long() -> Option<u64> {
+    None
+}
@@ -2,7 +2,11 @@
 pub mod nr;
 use system_error::SystemError;
 
-use crate::{exception::InterruptArch, process::ProcessManager, syscall::Syscall};
+use crate::{
+    exception::InterruptArch,
+    process::{seccomp::secure_computing, ProcessManager},
+    syscall::Syscall,
+};
 
 use super::{interrupt::TrapFrame, CurrentIrqArch};
 
@@ -35,6 +39,10 @@ pub(super) fn syscall_handler(syscall_num: usize, frame: &mut TrapFrame) -> () {
     }
 
     let args = [frame.a0, frame.a1, frame.a2, frame.a3, frame.a4, frame.a5];
+    // seccomp可能会阻止系统调用的执行
+    if let Some(ret) = secure_computing(syscall_num, &args, frame.epc) {
+        syscall_return!(ret, frame, false);
+    }
     syscall_return!(
         Syscall::handle(syscall_num, &args, frame).unwrap_or_else(|e| e.to_posix_errno() as usize),
         frame,
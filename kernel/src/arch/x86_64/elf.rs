@@ -1,4 +1,9 @@
-use crate::{arch::MMArch, libs::elf::ElfArch, mm::MemoryManagementArch};
+use crate::{
+    arch::{interrupt::TrapFrame, MMArch},
+    libs::elf::ElfArch,
+    mm::MemoryManagementArch,
+    process::ProcessManager,
+};
 
 #[derive(Debug, Clone, Copy, Hash)]
 pub struct X86_64ElfArch;
@@ -7,4 +12,45 @@ impl ElfArch for X86_64ElfArch {
     const ELF_ET_DYN_BASE: usize = MMArch::USER_END_VADDR.data() / 3 * 2;
 
     const ELF_PAGE_SIZE: usize = MMArch::PAGE_SIZE;
+
+    const ELF_MACHINE: u16 = elf::abi::EM_X86_64;
+
+    type Gregset = [u64; 27];
+
+    /// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/arch/x86/include/asm/user_64.h#69
+    fn core_regs(frame: &TrapFrame) -> Self::Gregset {
+        // 用户态的fs base在进入内核时不会被切换，可以直接读取
+        let fs_base = unsafe { x86::msr::rdmsr(x86::msr::IA32_FS_BASE) };
+        let gs_base = ProcessManager::current_pcb().arch_info_irqsave().gsbase() as u64;
+        [
+            frame.r15,
+            frame.r14,
+            frame.r13,
+            frame.r12,
+            frame.rbp,
+            frame.rbx,
+            frame.r11,
+            frame.r10,
+            frame.r9,
+            frame.r8,
+            frame.rax,
+            frame.rcx,
+            frame.rdx,
+            frame.rsi,
+            frame.rdi,
+            // orig_ax：导致core dump的信号不是在系统调用中产生的
+            u64::MAX,
+            frame.rip,
+            frame.cs,
+            frame.rflags,
+            frame.rsp,
+            frame.ss,
+            fs_base,
+            gs_base,
+            frame.ds,
+            frame.es,
+            0,
+            0,
+        ]
+    }
 }
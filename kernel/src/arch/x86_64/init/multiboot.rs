@@ -73,6 +73,17 @@ impl BootCallbacks for Mb1Callback {
         Ok(())
     }
 
+    fn init_initramfs(&self) -> Result<(), SystemError> {
+        let info = MB1_INFO.get();
+        // 第一个模块被视为initramfs
+        if let Some(module) = unsafe { info.modules(&Mb1Ops) }.and_then(|mut it| it.next()) {
+            let start = PhysAddr::new(module.start() as usize);
+            let size = module.end() as usize - module.start() as usize;
+            boot_params().write_irqsave().initrd = Some((start, size));
+        }
+        Ok(())
+    }
+
     fn early_init_framebuffer_info(
         &self,
         scinfo: &mut BootTimeScreenInfo,
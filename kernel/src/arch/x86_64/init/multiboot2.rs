@@ -96,6 +96,16 @@ impl BootCallbacks for Mb2Callback {
         Ok(())
     }
 
+    fn init_initramfs(&self) -> Result<(), SystemError> {
+        // 第一个模块被视为initramfs
+        if let Some(module) = MB2_INFO.get().module_tags().next() {
+            let start = PhysAddr::new(module.start_address() as usize);
+            let size = module.module_size() as usize;
+            boot_params().write_irqsave().initrd = Some((start, size));
+        }
+        Ok(())
+    }
+
     fn early_init_framebuffer_info(
         &self,
         scinfo: &mut BootTimeScreenInfo,
@@ -12,11 +12,11 @@ use crate::{
     },
     exception::InterruptArch,
     ipc::{
-        signal::set_current_sig_blocked,
+        signal::{restore_saved_sigmask, set_current_sig_blocked},
         signal_types::{SaHandlerType, SigInfo, Sigaction, SigactionType, SignalArch},
     },
     mm::MemoryManagementArch,
-    process::ProcessManager,
+    process::{coredump::do_coredump, exit::StopEvent, ProcessManager},
     sched::{schedule, SchedMode},
     syscall::{user_access::UserBufferWriter, Syscall},
 };
@@ -142,22 +142,24 @@ impl Signal {
     }
 
     /// 调用信号的默认处理函数
-    pub fn handle_default(&self) {
+    ///
+    /// `frame`是进程陷入内核时保存的栈帧，生成core dump时会用到
+    pub fn handle_default(&self, frame: &TrapFrame) {
         match self {
             Signal::INVALID => {
                 error!("attempting to handler an Invalid");
             }
             Signal::SIGHUP => sig_terminate(*self),
             Signal::SIGINT => sig_terminate(*self),
-            Signal::SIGQUIT => sig_terminate_dump(*self),
-            Signal::SIGILL => sig_terminate_dump(*self),
-            Signal::SIGTRAP => sig_terminate_dump(*self),
-            Signal::SIGABRT_OR_IOT => sig_terminate_dump(*self),
-            Signal::SIGBUS => sig_terminate_dump(*self),
-            Signal::SIGFPE => sig_terminate_dump(*self),
+            Signal::SIGQUIT => sig_terminate_dump(*self, frame),
+            Signal::SIGILL => sig_terminate_dump(*self, frame),
+            Signal::SIGTRAP => sig_terminate_dump(*self, frame),
+            Signal::SIGABRT_OR_IOT => sig_terminate_dump(*self, frame),
+            Signal::SIGBUS => sig_terminate_dump(*self, frame),
+            Signal::SIGFPE => sig_terminate_dump(*self, frame),
             Signal::SIGKILL => sig_terminate(*self),
             Signal::SIGUSR1 => sig_terminate(*self),
-            Signal::SIGSEGV => sig_terminate_dump(*self),
+            Signal::SIGSEGV => sig_terminate_dump(*self, frame),
             Signal::SIGUSR2 => sig_terminate(*self),
             Signal::SIGPIPE => sig_terminate(*self),
             Signal::SIGALRM => sig_terminate(*self),
@@ -170,8 +172,8 @@ impl Signal {
             Signal::SIGTTIN => sig_stop(*self),
             Signal::SIGTTOU => sig_stop(*self),
             Signal::SIGURG => sig_ignore(*self),
-            Signal::SIGXCPU => sig_terminate_dump(*self),
-            Signal::SIGXFSZ => sig_terminate_dump(*self),
+            Signal::SIGXCPU => sig_terminate_dump(*self, frame),
+            Signal::SIGXFSZ => sig_terminate_dump(*self, frame),
             Signal::SIGVTALRM => sig_terminate(*self),
             Signal::SIGPROF => sig_terminate(*self),
             Signal::SIGWINCH => sig_ignore(*self),
@@ -193,6 +195,8 @@ pub enum SigCode {
     User = 0,
     /// sent by kernel from somewhere
     Kernel = 0x80,
+    /// seccomp过滤器返回SECCOMP_RET_TRAP时发送的SIGSYS
+    SysSeccomp = 1,
     /// 通过sigqueue发送
     Queue = -1,
     /// 定时器过期时发送
@@ -212,6 +216,7 @@ impl SigCode {
         match x {
             0 => Self::User,
             0x80 => Self::Kernel,
+            1 => Self::SysSeccomp,
             -1 => Self::Queue,
             -2 => Self::Timer,
             -3 => Self::Mesgq,
@@ -427,6 +432,12 @@ impl SignalArch for X86_64SignalArch {
 
         // 检查sigpending是否为0
         if siginfo_read_guard.sig_pending().signal().bits() == 0 || !frame.is_from_user() {
+            // 系统调用临时替换了屏蔽信号，但没有需要处理的信号时，直接恢复原来的屏蔽信号
+            let restore = frame.is_from_user() && siginfo_read_guard.saved_sigmask().is_some();
+            drop(siginfo_read_guard);
+            if restore {
+                restore_saved_sigmask();
+            }
             // 若没有正在等待处理的信号，或者将要返回到的是内核态，则返回
             return;
         }
@@ -454,6 +465,12 @@ impl SignalArch for X86_64SignalArch {
             (sig_number, info) = siginfo_mut_guard.dequeue_signal(&sig_block);
             // 如果信号非法，则直接返回
             if sig_number == Signal::INVALID {
+                let saved_sigmask = siginfo_mut_guard.take_saved_sigmask();
+                drop(siginfo_mut_guard);
+                drop(sig_guard);
+                if let Some(mut saved_sigmask) = saved_sigmask {
+                    set_current_sig_blocked(&mut saved_sigmask);
+                }
                 return;
             }
 
@@ -479,15 +496,27 @@ impl SignalArch for X86_64SignalArch {
             // 如果当前动作是忽略这个信号，就继续循环。
         }
 
-        let oldset = *siginfo_mut_guard.sig_block();
+        // 如果系统调用临时替换了屏蔽信号，那么信号处理程序返回时应当恢复为原来的屏蔽信号
+        let saved_sigmask = siginfo_mut_guard.take_saved_sigmask();
+        let oldset = saved_sigmask.unwrap_or(*siginfo_mut_guard.sig_block());
         //避免死锁
         drop(siginfo_mut_guard);
         drop(sig_guard);
 
+        let customized = matches!(
+            sigaction.action(),
+            SigactionType::SaHandler(SaHandlerType::Customized(_))
+        );
         // 做完上面的检查后，开中断
         CurrentIrqArch::interrupt_enable();
         let res: Result<i32, SystemError> =
             handle_signal(sig_number, &mut sigaction, &info.unwrap(), &oldset, frame);
+        // 没有进入用户态的信号处理程序时，不会经过sigreturn，需要在这里恢复屏蔽信号
+        if let Some(mut saved_sigmask) = saved_sigmask {
+            if !customized || res.is_err() {
+                set_current_sig_blocked(&mut saved_sigmask);
+            }
+        }
         if res.is_err() {
             error!(
                 "Error occurred when handling signal: {}, pid={:?}, errcode={:?}",
@@ -559,7 +588,7 @@ fn setup_frame(
     match sigaction.action() {
         SigactionType::SaHandler(handler_type) => match handler_type {
             SaHandlerType::Default => {
-                sig.handle_default();
+                sig.handle_default(trap_frame);
                 return Ok(0);
             }
             SaHandlerType::Customized(handler) => {
@@ -567,11 +596,11 @@ fn setup_frame(
                 if handler >= MMArch::USER_END_VADDR {
                     // 如果当前是SIGSEGV,则采用默认函数处理
                     if sig == Signal::SIGSEGV {
-                        sig.handle_default();
+                        sig.handle_default(trap_frame);
                         return Ok(0);
                     } else {
                         error!("attempting  to execute a signal handler from kernel");
-                        sig.handle_default();
+                        sig.handle_default(trap_frame);
                         return Err(SystemError::EINVAL);
                     }
                 } else {
@@ -699,13 +728,18 @@ fn sig_terminate(sig: Signal) {
 }
 
 /// 信号默认处理函数——终止进程并生成 core dump
-fn sig_terminate_dump(sig: Signal) {
-    ProcessManager::exit(sig as usize);
-    // TODO 生成 coredump 文件
+fn sig_terminate_dump(sig: Signal, frame: &TrapFrame) {
+    let mut exit_code = sig as usize;
+    // 生成了core文件时，设置wait状态中的WCOREDUMP位
+    if do_coredump(sig, frame) {
+        exit_code |= 0x80;
+    }
+    ProcessManager::exit(exit_code);
 }
 
 /// 信号默认处理函数——暂停进程
 fn sig_stop(sig: Signal) {
+    ProcessManager::current_pcb().notify_parent_cldstop(StopEvent::Stopped(sig));
     let guard = unsafe { CurrentIrqArch::save_and_disable_irq() };
     ProcessManager::mark_stop().unwrap_or_else(|e| {
         error!(
@@ -143,6 +143,22 @@ impl X86_64MMArch {
         panic!()
     }
 
+    /// 向当前进程发送SIGSEGV
+    ///
+    /// 信号发送失败时直接终止当前进程，以免其返回用户态后反复触发同一个缺页异常。
+    /// 由于终止进程时不会返回，调用者需要先释放地址空间的锁与引用
+    fn force_sigsegv() {
+        let pid = ProcessManager::current_pid();
+        let mut info = SigInfo::new(Signal::SIGSEGV, 0, SigCode::User, SigType::Kill(pid));
+        if let Err(e) = Signal::SIGSEGV.send_signal_info(Some(&mut info), pid) {
+            error!(
+                "failed to send SIGSEGV to process {:?}: {:?}, killing it",
+                pid, e
+            );
+            ProcessManager::exit(Signal::SIGSEGV as usize);
+        }
+    }
+
     /// 内核态缺页异常处理
     /// ## 参数
     ///
@@ -240,12 +256,9 @@ impl X86_64MMArch {
                         error_code,
                         address.data(),
                     );
-                    let pid = ProcessManager::current_pid();
-                    let mut info =
-                        SigInfo::new(Signal::SIGSEGV, 0, SigCode::User, SigType::Kill(pid));
-                    Signal::SIGSEGV
-                        .send_signal_info(Some(&mut info), pid)
-                        .expect("failed to send SIGSEGV to process");
+                    drop(space_guard);
+                    drop(current_address_space);
+                    Self::force_sigsegv();
                     return;
                 }
             };
@@ -256,27 +269,30 @@ impl X86_64MMArch {
 
             if !region.contains(address) {
                 if vm_flags.contains(VmFlags::VM_GROWSDOWN) {
-                    space_guard
-                        .extend_stack(region.start() - address)
-                        .unwrap_or_else(|_| {
-                            panic!(
-                                "user stack extend failed, error_code: {:#b}, address: {:#x}",
-                                error_code,
-                                address.data(),
-                            )
-                        });
+                    if let Err(e) = space_guard.extend_stack(region.start() - address) {
+                        // 超出RLIMIT_STACK或内存不足时，向进程发送SIGSEGV
+                        log::error!(
+                            "user stack extend failed: {:?}, error_code: {:#b}, address: {:#x}",
+                            e,
+                            error_code,
+                            address.data(),
+                        );
+                        drop(space_guard);
+                        drop(vma);
+                        drop(current_address_space);
+                        Self::force_sigsegv();
+                        return;
+                    }
                 } else {
                     log::error!(
                         "No mapped vma, error_code: {:#b}, address: {:#x}",
                         error_code,
                         address.data(),
                     );
-                    let pid = ProcessManager::current_pid();
-                    let mut info =
-                        SigInfo::new(Signal::SIGSEGV, 0, SigCode::User, SigType::Kill(pid));
-                    Signal::SIGSEGV
-                        .send_signal_info(Some(&mut info), pid)
-                        .expect("failed to send SIGSEGV to process");
+                    drop(space_guard);
+                    drop(vma);
+                    drop(current_address_space);
+                    Self::force_sigsegv();
                     return;
                 }
             }
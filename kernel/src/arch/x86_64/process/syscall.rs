@@ -10,7 +10,7 @@ use crate::{
     exception::InterruptArch,
     mm::ucontext::AddressSpace,
     process::{
-        exec::{load_binary_file, ExecParam, ExecParamFlags},
+        exec::{exec_transform_cred, load_binary_file, ExecParam, ExecParamFlags},
         ProcessControlBlock, ProcessManager,
     },
     syscall::{user_access::UserBufferWriter, Syscall},
@@ -67,6 +67,7 @@ impl Syscall {
 
         // 加载可执行文件
         let load_result = load_binary_file(&mut param)?;
+        exec_transform_cred(&param)?;
         // debug!("load binary file done");
         // debug!("argv: {:?}, envp: {:?}", argv, envp);
         param.init_info_mut().args = argv;
@@ -1,5 +1,86 @@
-use core::arch::x86_64::_rdtsc;
+use core::{
+    arch::x86_64::{_rdrand64_step, _rdseed64_step, _rdtsc},
+    sync::atomic::{AtomicU8, Ordering},
+};
 
 pub fn rand() -> usize {
     return unsafe { (_rdtsc() * _rdtsc() + 998244353_u64 * _rdtsc()) as usize };
 }
+
+/// rdrand/rdseed失败时的重试次数
+///
+/// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/arch/x86/include/asm/archrandom.h#19
+const RDRAND_RETRY_LOOPS: usize = 10;
+
+const RAND_FEATURE_PROBED: u8 = 1 << 0;
+const RAND_FEATURE_RDRAND: u8 = 1 << 1;
+const RAND_FEATURE_RDSEED: u8 = 1 << 2;
+
+/// CPU对rdrand、rdseed指令的支持情况（首次使用时通过cpuid探测）
+static RAND_FEATURES: AtomicU8 = AtomicU8::new(0);
+
+fn rand_features() -> u8 {
+    let features = RAND_FEATURES.load(Ordering::Relaxed);
+    if features & RAND_FEATURE_PROBED != 0 {
+        return features;
+    }
+
+    let cpuid = x86::cpuid::CpuId::new();
+    let mut features = RAND_FEATURE_PROBED;
+    if cpuid
+        .get_feature_info()
+        .map(|f| f.has_rdrand())
+        .unwrap_or(false)
+    {
+        features |= RAND_FEATURE_RDRAND;
+    }
+    if cpuid
+        .get_extended_feature_info()
+        .map(|f| f.has_rdseed())
+        .unwrap_or(false)
+    {
+        features |= RAND_FEATURE_RDSEED;
+    }
+    RAND_FEATURES.store(features, Ordering::Relaxed);
+    features
+}
+
+#[target_feature(enable = "rdrand")]
+unsafe fn rdrand64() -> Option<u64> {
+    let mut v = 0;
+    for _ in 0..RDRAND_RETRY_LOOPS {
+        if _rdrand64_step(&mut v) == 1 {
+            return Some(v);
+        }
+    }
+    None
+}
+
+#[target_feature(enable = "rdseed")]
+unsafe fn rdseed64() -> Option<u64> {
+    let mut v = 0;
+    if _rdseed64_step(&mut v) == 1 {
+        return Some(v);
+    }
+    None
+}
+
+/// 从CPU的硬件随机数发生器（rdrand）获取一个随机数
+///
+/// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/arch/x86/include/asm/archrandom.h#54
+pub fn arch_get_random_long() -> Option<u64> {
+    if rand_features() & RAND_FEATURE_RDRAND == 0 {
+        return None;
+    }
+    unsafe { rdrand64() }
+}
+
+/// 从CPU的硬件熵源（rdseed）获取一个随机数
+///
+/// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/arch/x86/include/asm/archrandom.h#62
+pub fn arch_get_random_seed_long() -> Option<u64> {
+    if rand_features() & RAND_FEATURE_RDSEED == 0 {
+        return None;
+    }
+    unsafe { rdseed64() }
+}
@@ -8,7 +8,7 @@ use crate::{
     ipc::signal_types::SignalArch,
     libs::align::SafeForZero,
     mm::VirtAddr,
-    process::ProcessManager,
+    process::{seccomp::secure_computing, ProcessManager},
     syscall::{Syscall, SYS_SCHED},
 };
 use log::debug;
@@ -98,6 +98,11 @@ pub extern "sysv64" fn syscall_handler(frame: &mut TrapFrame) {
         debug!("syscall: pid: {:?}, num={:?}\n", pid, syscall_num);
     }
 
+    // seccomp可能会阻止系统调用的执行
+    if let Some(ret) = secure_computing(syscall_num, &args, frame.rip as usize) {
+        syscall_return!(ret, frame, show);
+    }
+
     // Arch specific syscall
     match syscall_num {
         SYS_RT_SIGRETURN => {
@@ -0,0 +1,455 @@
+//! cgroup2文件系统：基于kernfs，以目录的形式展现cgroup的层级结构
+//!
+//! 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/kernel/cgroup/cgroup.c#5092
+
+use core::{cmp::min, fmt::Write};
+
+use alloc::{
+    string::{String, ToString},
+    sync::{Arc, Weak},
+};
+use linkme::distributed_slice;
+use log::info;
+use system_error::SystemError;
+use unified_init::macros::unified_init;
+
+use crate::{
+    arch::MMArch,
+    filesystem::{
+        kernfs::{
+            callback::{KernCallbackData, KernFSCallback, KernInodePrivateData},
+            KernFS, KernFSInode, KernFSSyscallOps,
+        },
+        sysfs::sysfs_instance,
+        vfs::{
+            core::do_mount_mkdir, mount::MountFSInode, syscall::ModeType, FileSystem,
+            FileSystemMaker, FileSystemMakerData, PollStatus, FSMAKER,
+        },
+    },
+    init::initcall::INITCALL_FS,
+    libs::casting::DowncastArc,
+    mm::MemoryManagementArch,
+    process::{cred::CAPFlags, Pid, ProcessManager},
+};
+
+use super::{memory::MEMORY_MAX_UNLIMITED, pids::PIDS_MAX_UNLIMITED, Cgroup, CGROUP_ROOT};
+
+lazy_static! {
+    /// cgroup2文件系统的实例。与Linux一样，多次挂载cgroup2得到的是同一个层级结构
+    static ref CGROUP_FS: Arc<KernFS> = {
+        let fs = KernFS::new_with_syscall_ops(&CgroupSyscallOps);
+        let root: Arc<KernFSInode> = fs.root_inode().downcast_arc().unwrap();
+        *root.private_data_mut() = Some(KernInodePrivateData::Cgroup(
+            CgroupKernPrivateData::Dir(Arc::downgrade(&CGROUP_ROOT)),
+        ));
+        populate_dir(&CGROUP_ROOT, &root).expect("Failed to create cgroup root files");
+        fs
+    };
+}
+
+/// cgroup目录下的接口文件
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CgroupFileType {
+    Procs,
+    Controllers,
+    SubtreeControl,
+    PidsMax,
+    PidsCurrent,
+    PidsEvents,
+    MemoryMax,
+    MemoryCurrent,
+    MemoryEvents,
+}
+
+impl CgroupFileType {
+    const ALL: [CgroupFileType; 9] = [
+        CgroupFileType::Procs,
+        CgroupFileType::Controllers,
+        CgroupFileType::SubtreeControl,
+        CgroupFileType::PidsMax,
+        CgroupFileType::PidsCurrent,
+        CgroupFileType::PidsEvents,
+        CgroupFileType::MemoryMax,
+        CgroupFileType::MemoryCurrent,
+        CgroupFileType::MemoryEvents,
+    ];
+
+    /// 启用的控制器，在cgroup.controllers与cgroup.subtree_control中展示
+    const CONTROLLERS: [&'static str; 2] = ["memory", "pids"];
+
+    fn name(&self) -> &'static str {
+        match self {
+            CgroupFileType::Procs => "cgroup.procs",
+            CgroupFileType::Controllers => "cgroup.controllers",
+            CgroupFileType::SubtreeControl => "cgroup.subtree_control",
+            CgroupFileType::PidsMax => "pids.max",
+            CgroupFileType::PidsCurrent => "pids.current",
+            CgroupFileType::PidsEvents => "pids.events",
+            CgroupFileType::MemoryMax => "memory.max",
+            CgroupFileType::MemoryCurrent => "memory.current",
+            CgroupFileType::MemoryEvents => "memory.events",
+        }
+    }
+
+    fn mode(&self) -> ModeType {
+        match self {
+            CgroupFileType::Procs
+            | CgroupFileType::SubtreeControl
+            | CgroupFileType::PidsMax
+            | CgroupFileType::MemoryMax => ModeType::from_bits_truncate(0o644),
+            _ => ModeType::from_bits_truncate(0o444),
+        }
+    }
+
+    /// 控制器的接口文件只存在于非根cgroup中
+    fn visible_in(&self, cgroup: &Cgroup) -> bool {
+        match self {
+            CgroupFileType::Procs
+            | CgroupFileType::Controllers
+            | CgroupFileType::SubtreeControl => true,
+            _ => !cgroup.is_root(),
+        }
+    }
+}
+
+/// cgroupfs在kernfs的inode中的私有信息
+#[derive(Debug)]
+pub enum CgroupKernPrivateData {
+    Dir(Weak<Cgroup>),
+    File(Weak<Cgroup>, CgroupFileType),
+}
+
+impl CgroupKernPrivateData {
+    fn cgroup(&self) -> Result<Arc<Cgroup>, SystemError> {
+        let cgroup = match self {
+            CgroupKernPrivateData::Dir(cgroup) => cgroup,
+            CgroupKernPrivateData::File(cgroup, _) => cgroup,
+        };
+        // cgroup已经被删除
+        return cgroup.upgrade().ok_or(SystemError::ENODEV);
+    }
+
+    pub fn callback_read(&self, buf: &mut [u8], offset: usize) -> Result<usize, SystemError> {
+        let CgroupKernPrivateData::File(_, file_type) = self else {
+            return Err(SystemError::ENOSYS);
+        };
+        let content = cgroup_file_show(&self.cgroup()?, *file_type)?;
+        let content = content.as_bytes();
+        if offset >= content.len() {
+            return Ok(0);
+        }
+        let len = min(buf.len(), content.len() - offset);
+        buf[..len].copy_from_slice(&content[offset..offset + len]);
+        return Ok(len);
+    }
+
+    pub fn callback_write(&self, buf: &[u8], _offset: usize) -> Result<usize, SystemError> {
+        let CgroupKernPrivateData::File(_, file_type) = self else {
+            return Err(SystemError::ENOSYS);
+        };
+        let input = core::str::from_utf8(buf)
+            .map_err(|_| SystemError::EINVAL)?
+            .trim();
+        cgroup_file_store(&self.cgroup()?, *file_type, input)?;
+        return Ok(buf.len());
+    }
+}
+
+fn cgroup_file_show(
+    cgroup: &Arc<Cgroup>,
+    file_type: CgroupFileType,
+) -> Result<String, SystemError> {
+    let mut s = String::new();
+    match file_type {
+        CgroupFileType::Procs => {
+            // 只展示线程组leader，pid为读取者所在pid namespace中的pid
+            let ns = ProcessManager::current_pcb().active_pid_ns();
+            for task in ProcessManager::cgroup_tasks(cgroup) {
+                if task.pid() != task.tgid() {
+                    continue;
+                }
+                if let Some(nr) = task.pid_nr_ns(&ns) {
+                    writeln!(s, "{}", nr.data()).ok();
+                }
+            }
+        }
+        CgroupFileType::Controllers | CgroupFileType::SubtreeControl => {
+            writeln!(s, "{}", CgroupFileType::CONTROLLERS.join(" ")).ok();
+        }
+        CgroupFileType::PidsMax => {
+            writeln!(
+                s,
+                "{}",
+                limit_to_string(cgroup.pids().max(), PIDS_MAX_UNLIMITED)
+            )
+            .ok();
+        }
+        CgroupFileType::PidsCurrent => {
+            writeln!(s, "{}", cgroup.pids().current()).ok();
+        }
+        CgroupFileType::PidsEvents => {
+            writeln!(s, "max {}", cgroup.pids().events_max()).ok();
+        }
+        CgroupFileType::MemoryMax => {
+            let max = cgroup.memory().max_pages();
+            let max = if max == MEMORY_MAX_UNLIMITED {
+                MEMORY_MAX_UNLIMITED
+            } else {
+                max * MMArch::PAGE_SIZE
+            };
+            writeln!(s, "{}", limit_to_string(max, MEMORY_MAX_UNLIMITED)).ok();
+        }
+        CgroupFileType::MemoryCurrent => {
+            writeln!(s, "{}", cgroup.memory().current_bytes()).ok();
+        }
+        CgroupFileType::MemoryEvents => {
+            let (max, oom, oom_kill) = cgroup.memory().events();
+            write!(
+                s,
+                "low 0\nhigh 0\nmax {}\noom {}\noom_kill {}\n",
+                max, oom, oom_kill
+            )
+            .ok();
+        }
+    }
+    return Ok(s);
+}
+
+fn cgroup_file_store(
+    cgroup: &Arc<Cgroup>,
+    file_type: CgroupFileType,
+    input: &str,
+) -> Result<(), SystemError> {
+    match file_type {
+        CgroupFileType::Procs => {
+            let nr = input.parse::<usize>().map_err(|_| SystemError::EINVAL)?;
+            let current = ProcessManager::current_pcb();
+            let pcb = if nr == 0 {
+                current.clone()
+            } else {
+                ProcessManager::find_vpid(Pid::new(nr)).ok_or(SystemError::ESRCH)?
+            };
+
+            let cred = current.cred();
+            if !cred.has_capability(CAPFlags::CAP_SYS_ADMIN) && cred.euid != pcb.cred().uid {
+                return Err(SystemError::EACCES);
+            }
+            cgroup.attach(&pcb)
+        }
+        CgroupFileType::SubtreeControl => {
+            // 所有控制器总是启用的，这里只检查控制器名称是否合法
+            for token in input.split_whitespace() {
+                let name = token
+                    .strip_prefix('+')
+                    .or_else(|| token.strip_prefix('-'))
+                    .ok_or(SystemError::EINVAL)?;
+                if !CgroupFileType::CONTROLLERS.contains(&name) {
+                    return Err(SystemError::ENOENT);
+                }
+            }
+            Ok(())
+        }
+        CgroupFileType::PidsMax => {
+            let max = parse_limit(input, false)?;
+            cgroup.pids().set_max(max);
+            Ok(())
+        }
+        CgroupFileType::MemoryMax => {
+            let max = parse_limit(input, true)?;
+            cgroup.memory().set_max_bytes(max);
+            Ok(())
+        }
+        _ => Err(SystemError::EACCES),
+    }
+}
+
+fn limit_to_string(value: usize, unlimited: usize) -> String {
+    if value == unlimited {
+        "max".to_string()
+    } else {
+        value.to_string()
+    }
+}
+
+/// 解析写入`*.max`文件的上限，"max"表示不限制（返回usize::MAX）。
+/// `with_suffix`为true时，允许使用K、M、G、T作为单位后缀
+///
+/// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/lib/cmdline.c#152
+fn parse_limit(input: &str, with_suffix: bool) -> Result<usize, SystemError> {
+    if input == "max" {
+        return Ok(usize::MAX);
+    }
+
+    let (digits, shift) = match input.chars().last() {
+        Some(c) if with_suffix && c.is_ascii_alphabetic() => {
+            let shift = match c.to_ascii_uppercase() {
+                'K' => 10,
+                'M' => 20,
+                'G' => 30,
+                'T' => 40,
+                _ => return Err(SystemError::EINVAL),
+            };
+            (&input[..input.len() - 1], shift)
+        }
+        _ => (input, 0),
+    };
+    let value = digits.parse::<usize>().map_err(|_| SystemError::EINVAL)?;
+    // checked_shl只检查移位数，不检查移出的高位
+    return value.checked_mul(1 << shift).ok_or(SystemError::EINVAL);
+}
+
+/// 获取cgroupfs中的目录的文件描述符所对应的cgroup
+///
+/// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/kernel/cgroup/cgroup.c#6853
+pub fn cgroup_from_fd(fd: i32) -> Result<Arc<Cgroup>, SystemError> {
+    let file = ProcessManager::current_pcb()
+        .fd_table()
+        .read()
+        .get_file_by_fd(fd)
+        .ok_or(SystemError::EBADF)?;
+    let mut inode = file.inode();
+    // MountFSInode的as_any_ref()返回的是内层inode，因此需要通过Arc来判断类型
+    if let Some(mount_inode) = inode.clone().downcast_arc::<MountFSInode>() {
+        inode = mount_inode.inner_inode();
+    }
+    let inode: Arc<KernFSInode> = inode.downcast_arc().ok_or(SystemError::EBADF)?;
+    let cgroup = match inode.private_data_mut().as_ref() {
+        Some(KernInodePrivateData::Cgroup(CgroupKernPrivateData::Dir(cgroup))) => {
+            cgroup.upgrade().ok_or(SystemError::ENODEV)?
+        }
+        _ => return Err(SystemError::EBADF),
+    };
+    if cgroup.is_dead() {
+        return Err(SystemError::ENODEV);
+    }
+    return Ok(cgroup);
+}
+
+/// 在cgroup对应的目录下创建接口文件
+fn populate_dir(cgroup: &Arc<Cgroup>, dir: &Arc<KernFSInode>) -> Result<(), SystemError> {
+    for file_type in CgroupFileType::ALL {
+        if !file_type.visible_in(cgroup) {
+            continue;
+        }
+        dir.add_file(
+            file_type.name().to_string(),
+            file_type.mode(),
+            None,
+            Some(KernInodePrivateData::Cgroup(CgroupKernPrivateData::File(
+                Arc::downgrade(cgroup),
+                file_type,
+            ))),
+            Some(&CgroupFileCallback),
+        )?;
+    }
+    return Ok(());
+}
+
+#[derive(Debug)]
+struct CgroupFileCallback;
+
+impl KernFSCallback for CgroupFileCallback {
+    fn open(&self, _data: KernCallbackData) -> Result<(), SystemError> {
+        return Ok(());
+    }
+
+    fn read(
+        &self,
+        data: KernCallbackData,
+        buf: &mut [u8],
+        offset: usize,
+    ) -> Result<usize, SystemError> {
+        return data.callback_read(buf, offset);
+    }
+
+    fn write(
+        &self,
+        data: KernCallbackData,
+        buf: &[u8],
+        offset: usize,
+    ) -> Result<usize, SystemError> {
+        return data.callback_write(buf, offset);
+    }
+
+    fn poll(&self, _data: KernCallbackData) -> Result<PollStatus, SystemError> {
+        return Ok(PollStatus::READ | PollStatus::WRITE);
+    }
+}
+
+/// 用户态在cgroupfs中mkdir/rmdir时，创建、删除对应的cgroup
+#[derive(Debug)]
+struct CgroupSyscallOps;
+
+impl KernFSSyscallOps for CgroupSyscallOps {
+    fn mkdir(
+        &self,
+        parent: &Arc<KernFSInode>,
+        name: &str,
+        mode: ModeType,
+    ) -> Result<Arc<KernFSInode>, SystemError> {
+        let parent_cgroup = match parent.private_data_mut().as_ref() {
+            Some(KernInodePrivateData::Cgroup(private_data)) => private_data.cgroup()?,
+            _ => return Err(SystemError::EPERM),
+        };
+        let cgroup = parent_cgroup.create_child(name)?;
+
+        let dir = parent
+            .add_dir(
+                name.to_string(),
+                mode & ModeType::S_IRWXUGO,
+                Some(KernInodePrivateData::Cgroup(CgroupKernPrivateData::Dir(
+                    Arc::downgrade(&cgroup),
+                ))),
+                None,
+            )
+            .and_then(|dir| populate_dir(&cgroup, &dir).map(|_| dir));
+        if dir.is_err() {
+            cgroup.destroy().ok();
+        }
+        return dir;
+    }
+
+    fn rmdir(&self, inode: &Arc<KernFSInode>) -> Result<(), SystemError> {
+        let cgroup = match inode.private_data_mut().as_ref() {
+            Some(KernInodePrivateData::Cgroup(private_data)) => private_data.cgroup()?,
+            _ => return Err(SystemError::EPERM),
+        };
+        cgroup.destroy()?;
+        inode.remove_inode_include_self();
+        return Ok(());
+    }
+}
+
+fn make_cgroup2(_data: &FileSystemMakerData) -> Result<Arc<dyn FileSystem + 'static>, SystemError> {
+    return Ok(CGROUP_FS.clone());
+}
+
+#[distributed_slice(FSMAKER)]
+static CGROUP2MAKER: FileSystemMaker = FileSystemMaker::new(
+    "cgroup2",
+    &(make_cgroup2
+        as fn(&FileSystemMakerData) -> Result<Arc<dyn FileSystem + 'static>, SystemError>),
+);
+
+/// 创建/sys/fs/cgroup目录，并将cgroup2挂载到这里
+#[unified_init(INITCALL_FS)]
+#[inline(never)]
+pub fn cgroup_init() -> Result<(), SystemError> {
+    // sysfs不允许从用户态创建目录，因此先在kernfs中创建挂载点
+    let sys_fs = sysfs_instance().root_inode().add_dir(
+        "fs".to_string(),
+        ModeType::from_bits_truncate(0o755),
+        None,
+        None,
+    )?;
+    sys_fs.add_dir(
+        "cgroup".to_string(),
+        ModeType::from_bits_truncate(0o755),
+        None,
+        None,
+    )?;
+
+    do_mount_mkdir(CGROUP_FS.clone(), "/sys/fs/cgroup")?;
+    info!("cgroup2 mounted.");
+    return Ok(());
+}
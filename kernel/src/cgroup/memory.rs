@@ -0,0 +1,132 @@
+//! memory控制器：限制cgroup（包括其子孙cgroup）中的进程在缺页时分配的物理页数量
+//!
+//! 目前对用户态缺页时新分配的匿名页与写时复制页、文件缺页时新建的PageCache页，以及tmpfs的文件页进行计费。
+//! 计费的对象是分配页面的进程当时所在的cgroup，页面在被释放或被逐出PageCache时从同一个cgroup中撤销计费。
+//!
+//! 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/mm/memcontrol.c
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use alloc::sync::Arc;
+use log::warn;
+use system_error::SystemError;
+
+use crate::{
+    arch::{
+        ipc::signal::{SigCode, Signal},
+        MMArch,
+    },
+    ipc::signal_types::{SigInfo, SigType},
+    mm::MemoryManagementArch,
+    process::ProcessManager,
+};
+
+use super::Cgroup;
+
+/// memory.max为"max"时对应的页数
+pub const MEMORY_MAX_UNLIMITED: usize = usize::MAX;
+
+#[derive(Debug)]
+pub struct MemCgroup {
+    /// 当前cgroup及其子孙cgroup已计费的页数
+    usage: AtomicUsize,
+    /// 页数上限
+    limit: AtomicUsize,
+    /// 计费时超出上限的次数
+    events_max: AtomicUsize,
+    /// 因超出上限而触发OOM的次数
+    events_oom: AtomicUsize,
+    /// 因OOM而被杀死的进程数
+    events_oom_kill: AtomicUsize,
+}
+
+impl MemCgroup {
+    pub(super) fn new() -> Self {
+        Self {
+            usage: AtomicUsize::new(0),
+            limit: AtomicUsize::new(MEMORY_MAX_UNLIMITED),
+            events_max: AtomicUsize::new(0),
+            events_oom: AtomicUsize::new(0),
+            events_oom_kill: AtomicUsize::new(0),
+        }
+    }
+
+    /// 已计费的内存大小（字节）
+    pub fn current_bytes(&self) -> usize {
+        self.usage.load(Ordering::SeqCst) * MMArch::PAGE_SIZE
+    }
+
+    /// 内存上限（页数）
+    pub fn max_pages(&self) -> usize {
+        self.limit.load(Ordering::SeqCst)
+    }
+
+    /// 设置内存上限（字节），会向下对齐到页大小
+    pub fn set_max_bytes(&self, bytes: usize) {
+        let pages = if bytes == MEMORY_MAX_UNLIMITED {
+            MEMORY_MAX_UNLIMITED
+        } else {
+            bytes / MMArch::PAGE_SIZE
+        };
+        self.limit.store(pages, Ordering::SeqCst);
+    }
+
+    /// memory.events的内容
+    pub fn events(&self) -> (usize, usize, usize) {
+        (
+            self.events_max.load(Ordering::SeqCst),
+            self.events_oom.load(Ordering::SeqCst),
+            self.events_oom_kill.load(Ordering::SeqCst),
+        )
+    }
+
+    /// cgroup内存不足时的处理：杀死触发计费的当前进程
+    ///
+    /// 与Linux选择cgroup中占用内存最多的进程不同，这里总是杀死当前进程，
+    /// 以保证超出限制的cgroup不会影响到其它cgroup中的进程。
+    ///
+    /// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/mm/memcontrol.c#1749
+    fn out_of_memory(&self) {
+        self.events_oom.fetch_add(1, Ordering::SeqCst);
+
+        let pid = ProcessManager::current_pid();
+        warn!("memory cgroup out of memory: killing process {:?}", pid);
+        let sig = Signal::SIGKILL;
+        let mut info = SigInfo::new(sig, 0, SigCode::Kernel, SigType::Kill(pid));
+        if sig.send_signal_info(Some(&mut info), pid).is_ok() {
+            self.events_oom_kill.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+}
+
+impl Cgroup {
+    /// 向当前cgroup及其祖先计费`nr_pages`个页
+    ///
+    /// 任意一级超出上限时撤销计费，并在该级cgroup中触发OOM，返回ENOMEM。
+    /// 如果当前进程已经收到SIGKILL，则允许超出上限，使得它能够尽快退出。
+    ///
+    /// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/mm/memcontrol.c#2644
+    pub fn try_charge_memory(self: &Arc<Self>, nr_pages: usize) -> Result<(), SystemError> {
+        let force = Signal::fatal_signal_pending(&ProcessManager::current_pcb());
+        for cg in self.ancestors() {
+            let new = cg.memory.usage.fetch_add(nr_pages, Ordering::SeqCst) + nr_pages;
+            if new > cg.memory.max_pages() && !force {
+                cg.memory.usage.fetch_sub(nr_pages, Ordering::SeqCst);
+                for charged in self.ancestors().take_while(|c| !Arc::ptr_eq(c, &cg)) {
+                    charged.memory.usage.fetch_sub(nr_pages, Ordering::SeqCst);
+                }
+                cg.memory.events_max.fetch_add(1, Ordering::SeqCst);
+                cg.memory.out_of_memory();
+                return Err(SystemError::ENOMEM);
+            }
+        }
+        return Ok(());
+    }
+
+    /// 从当前cgroup及其祖先中撤销`nr_pages`个页的计费
+    pub fn uncharge_memory(self: &Arc<Self>, nr_pages: usize) {
+        for cg in self.ancestors() {
+            cg.memory.usage.fetch_sub(nr_pages, Ordering::SeqCst);
+        }
+    }
+}
@@ -0,0 +1,166 @@
+//! cgroup v2：以统一的层级结构组织进程，并通过控制器限制每个cgroup所能使用的资源
+//!
+//! 目前支持pids与memory两种控制器，它们在除根cgroup以外的所有cgroup中总是启用的。
+//!
+//! 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/Documentation/admin-guide/cgroup-v2.rst
+
+use alloc::{
+    collections::BTreeMap,
+    string::{String, ToString},
+    sync::Arc,
+    vec::Vec,
+};
+use system_error::SystemError;
+
+use crate::{
+    libs::spinlock::SpinLock,
+    process::{ProcessControlBlock, ProcessManager},
+};
+
+use self::{memory::MemCgroup, pids::PidsCgroup};
+
+pub mod cgroupfs;
+pub mod memory;
+pub mod pids;
+
+lazy_static! {
+    /// 根cgroup，所有进程在创建之初都位于这里（除非其父进程已被迁移到了其它cgroup）
+    pub static ref CGROUP_ROOT: Arc<Cgroup> = Cgroup::new(None, String::new());
+}
+
+/// 控制组
+///
+/// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/include/linux/cgroup-defs.h#380
+#[derive(Debug)]
+pub struct Cgroup {
+    name: String,
+    /// 父cgroup，根cgroup为None
+    parent: Option<Arc<Cgroup>>,
+    inner: SpinLock<InnerCgroup>,
+    pids: PidsCgroup,
+    memory: MemCgroup,
+}
+
+#[derive(Debug)]
+struct InnerCgroup {
+    children: BTreeMap<String, Arc<Cgroup>>,
+    /// 是否已经被删除
+    dead: bool,
+}
+
+impl Cgroup {
+    fn new(parent: Option<Arc<Cgroup>>, name: String) -> Arc<Self> {
+        Arc::new(Self {
+            name,
+            parent,
+            inner: SpinLock::new(InnerCgroup {
+                children: BTreeMap::new(),
+                dead: false,
+            }),
+            pids: PidsCgroup::new(),
+            memory: MemCgroup::new(),
+        })
+    }
+
+    pub fn is_root(&self) -> bool {
+        self.parent.is_none()
+    }
+
+    pub fn is_dead(&self) -> bool {
+        self.inner.lock_irqsave().dead
+    }
+
+    pub fn pids(&self) -> &PidsCgroup {
+        &self.pids
+    }
+
+    pub fn memory(&self) -> &MemCgroup {
+        &self.memory
+    }
+
+    /// 从当前cgroup开始，依次返回它自身及其所有祖先
+    pub fn ancestors(self: &Arc<Self>) -> impl Iterator<Item = Arc<Cgroup>> {
+        core::iter::successors(Some(self.clone()), |cg| cg.parent.clone())
+    }
+
+    /// cgroup在层级中的路径，例如`/ci/job1`，根cgroup为`/`
+    pub fn path(self: &Arc<Self>) -> String {
+        let mut names: Vec<String> = self
+            .ancestors()
+            .filter(|cg| !cg.is_root())
+            .map(|cg| cg.name.clone())
+            .collect();
+        if names.is_empty() {
+            return "/".to_string();
+        }
+        names.reverse();
+        let mut path = String::new();
+        for name in names {
+            path.push('/');
+            path.push_str(&name);
+        }
+        return path;
+    }
+
+    /// 创建名为`name`的子cgroup
+    ///
+    /// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/kernel/cgroup/cgroup.c#5695
+    pub fn create_child(self: &Arc<Self>, name: &str) -> Result<Arc<Cgroup>, SystemError> {
+        if name.contains('/') || name == "." || name == ".." {
+            return Err(SystemError::EINVAL);
+        }
+
+        let mut inner = self.inner.lock_irqsave();
+        if inner.dead {
+            return Err(SystemError::ENOENT);
+        }
+        if inner.children.contains_key(name) {
+            return Err(SystemError::EEXIST);
+        }
+        let child = Cgroup::new(Some(self.clone()), name.to_string());
+        inner.children.insert(name.to_string(), child.clone());
+        return Ok(child);
+    }
+
+    /// 删除当前cgroup。只有没有子cgroup、也没有进程的cgroup才能被删除
+    ///
+    /// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/kernel/cgroup/cgroup.c#5823
+    pub fn destroy(self: &Arc<Self>) -> Result<(), SystemError> {
+        let parent = self.parent.as_ref().ok_or(SystemError::EBUSY)?;
+        if !ProcessManager::cgroup_tasks(self).is_empty() {
+            return Err(SystemError::EBUSY);
+        }
+
+        let mut inner = self.inner.lock_irqsave();
+        if !inner.children.is_empty() {
+            return Err(SystemError::EBUSY);
+        }
+        inner.dead = true;
+        drop(inner);
+
+        parent.inner.lock_irqsave().children.remove(&self.name);
+        return Ok(());
+    }
+
+    /// 将`pcb`所在的整个线程组迁移到当前cgroup中
+    ///
+    /// pids控制器在迁移时不检查上限，已经计费的内存也不会随进程迁移。
+    ///
+    /// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/kernel/cgroup/cgroup.c#2895
+    pub fn attach(self: &Arc<Self>, pcb: &Arc<ProcessControlBlock>) -> Result<(), SystemError> {
+        if self.is_dead() {
+            return Err(SystemError::ENODEV);
+        }
+
+        for task in ProcessManager::thread_group_tasks(pcb.tgid()) {
+            let old = task.cgroup();
+            if Arc::ptr_eq(&old, self) {
+                continue;
+            }
+            old.uncharge_pids(1);
+            self.charge_pids(1);
+            task.set_cgroup(self.clone());
+        }
+        return Ok(());
+    }
+}
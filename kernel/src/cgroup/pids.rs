@@ -0,0 +1,84 @@
+//! pids控制器：限制cgroup（包括其子孙cgroup）中的进程（线程）数量
+//!
+//! 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/kernel/cgroup/pids.c
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use alloc::sync::Arc;
+use system_error::SystemError;
+
+use super::Cgroup;
+
+/// pids.max为"max"时对应的值
+pub const PIDS_MAX_UNLIMITED: usize = usize::MAX;
+
+#[derive(Debug)]
+pub struct PidsCgroup {
+    /// 当前cgroup及其子孙cgroup中的进程（线程）数量
+    counter: AtomicUsize,
+    /// 进程数量上限
+    limit: AtomicUsize,
+    /// 因为超出上限而创建进程失败的次数
+    events_max: AtomicUsize,
+}
+
+impl PidsCgroup {
+    pub(super) fn new() -> Self {
+        Self {
+            counter: AtomicUsize::new(0),
+            limit: AtomicUsize::new(PIDS_MAX_UNLIMITED),
+            events_max: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn current(&self) -> usize {
+        self.counter.load(Ordering::SeqCst)
+    }
+
+    pub fn max(&self) -> usize {
+        self.limit.load(Ordering::SeqCst)
+    }
+
+    pub fn set_max(&self, max: usize) {
+        self.limit.store(max, Ordering::SeqCst);
+    }
+
+    pub fn events_max(&self) -> usize {
+        self.events_max.load(Ordering::SeqCst)
+    }
+}
+
+impl Cgroup {
+    /// 向当前cgroup及其祖先计入`num`个进程，不检查上限（用于进程迁移）
+    pub fn charge_pids(self: &Arc<Self>, num: usize) {
+        for cg in self.ancestors() {
+            cg.pids.counter.fetch_add(num, Ordering::SeqCst);
+        }
+    }
+
+    /// 向当前cgroup及其祖先计入`num`个进程，任意一级超出上限时撤销计数并返回EAGAIN
+    ///
+    /// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/kernel/cgroup/pids.c#148
+    pub fn try_charge_pids(self: &Arc<Self>, num: usize) -> Result<(), SystemError> {
+        for cg in self.ancestors() {
+            let new = cg.pids.counter.fetch_add(num, Ordering::SeqCst) + num;
+            if new > cg.pids.max() {
+                cg.pids.counter.fetch_sub(num, Ordering::SeqCst);
+                for charged in self.ancestors().take_while(|c| !Arc::ptr_eq(c, &cg)) {
+                    charged.pids.counter.fetch_sub(num, Ordering::SeqCst);
+                }
+                // 在触发限制的cgroup中记录事件
+                cg.pids.events_max.fetch_add(1, Ordering::SeqCst);
+                return Err(SystemError::EAGAIN_OR_EWOULDBLOCK);
+            }
+        }
+        return Ok(());
+    }
+
+    /// 从当前cgroup及其祖先中移除`num`个进程的计数
+    pub fn uncharge_pids(self: &Arc<Self>, num: usize) {
+        for cg in self.ancestors() {
+            cg.pids.counter.fetch_sub(num, Ordering::SeqCst);
+        }
+    }
+}
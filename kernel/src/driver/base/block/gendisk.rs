@@ -170,8 +170,12 @@ impl GenDiskMap {
         self.max_idx.fetch_add(1, Ordering::SeqCst)
     }
 
+    /// 检查range是否与已有的分区重叠（代表整个磁盘的gendisk不参与检查）
     pub fn intersects(&self, range: &GeneralBlockRange) -> bool {
-        for (_, v) in self.iter() {
+        for (idx, v) in self.iter() {
+            if *idx == GenDisk::ENTIRE_DISK_IDX {
+                continue;
+            }
             if range.intersects_with(&v.range).is_some() {
                 return true;
             }
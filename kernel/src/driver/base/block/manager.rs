@@ -71,6 +71,37 @@ impl BlockDevManager {
         Ok(())
     }
 
+    /// 注册磁盘设备，并把整个磁盘注册为一个gendisk
+    ///
+    /// 与[`register`](Self::register)不同，整个磁盘总是可以通过设备名（例如`/dev/loop0`）访问。
+    /// `partscan`为true时，还会检测分区表，把分区注册为`/dev/loop0p1`这样的gendisk
+    pub fn register_disk(
+        &self,
+        dev: Arc<dyn BlockDevice>,
+        partscan: bool,
+    ) -> Result<(), SystemError> {
+        let dev_name = dev.dev_name();
+        if self.inner().disks.contains_key(dev_name) {
+            return Err(SystemError::EEXIST);
+        }
+
+        // 检测分区表需要读取磁盘，不能持有锁
+        let gendisk = GenDisk::new(Arc::downgrade(&dev), dev.disk_range(), None);
+        self.register_gendisk(&dev, gendisk)?;
+        if partscan {
+            // 没有分区表并不是错误
+            self.check_mbr(&dev).ok();
+        }
+
+        let mut inner = self.inner();
+        if inner.disks.contains_key(dev_name) {
+            dev.blkdev_meta().inner().gendisks = GenDiskMap::new();
+            return Err(SystemError::EEXIST);
+        }
+        inner.disks.insert(dev_name.clone(), dev.clone());
+        Ok(())
+    }
+
     /// 检测分区表，并创建gendisk
     fn check_partitions(&self, dev: &Arc<dyn BlockDevice>) -> Result<(), SystemError> {
         if self.check_mbr(dev).is_ok() {
@@ -133,12 +164,24 @@ impl BlockDevManager {
         Ok(())
     }
 
-    /// 卸载磁盘设备
-    pub fn unregister(&self, dev: &Arc<dyn BlockDevice>) {
+    /// 卸载磁盘设备，并删除磁盘上所有的gendisk
+    ///
+    /// 如果还有gendisk正在被使用（例如上面挂载了文件系统），则返回EBUSY
+    pub fn unregister(&self, dev: &Arc<dyn BlockDevice>) -> Result<(), SystemError> {
         let mut inner = self.inner();
+        let mut meta_inner = dev.blkdev_meta().inner();
+        if meta_inner
+            .gendisks
+            .values()
+            .any(|gendisk| Arc::strong_count(gendisk) > 1)
+        {
+            return Err(SystemError::EBUSY);
+        }
+
         inner.disks.remove(dev.dev_name());
+        meta_inner.gendisks = GenDiskMap::new();
         // todo: 这里应该callback一下磁盘设备，但是现在还没实现热插拔，所以暂时没做这里
-        todo!("BlockDevManager: unregister disk")
+        Ok(())
     }
 
     /// 通过路径查找gendisk
@@ -147,8 +190,25 @@ impl BlockDevManager {
     ///
     /// - `path`: 分区路径 `/dev/sda1` 或者 `sda1`，或者是`/dev/sda`
     pub fn lookup_gendisk_by_path(&self, path: &str) -> Option<Arc<GenDisk>> {
-        let (devname, partno) = self.path2devname(path)?;
         let inner = self.inner();
+        // 路径与磁盘名完全相同（例如loop0）时，优先返回整个磁盘
+        let name = path.strip_prefix("/dev/").unwrap_or(path);
+        if let Some(dev) = inner
+            .disks
+            .values()
+            .find(|dev| dev.dev_name().as_str() == name)
+        {
+            if let Some(gendisk) = dev
+                .blkdev_meta()
+                .inner()
+                .gendisks
+                .get(&GenDisk::ENTIRE_DISK_IDX)
+            {
+                return Some(gendisk.clone());
+            }
+        }
+
+        let (devname, partno) = self.path2devname(path)?;
         for dev in inner.disks.values() {
             if dev.dev_name().as_str() == devname {
                 return dev.blkdev_meta().inner().gendisks.get(&partno).cloned();
@@ -167,6 +227,9 @@ impl BlockDevManager {
             for idx in meta.gendisks.keys() {
                 if idx == &GenDisk::ENTIRE_DISK_IDX {
                     disks.push(format!("/dev/{}", dev.dev_name()));
+                } else if dev.dev_name().ends_with(|c: char| c.is_ascii_digit()) {
+                    // 设备名以数字结尾时，分区名需要用'p'隔开，例如nvme0n1p1
+                    disks.push(format!("/dev/{}p{}", dev.dev_name(), idx));
                 } else {
                     disks.push(format!("/dev/{}{}", dev.dev_name(), idx));
                 }
@@ -184,6 +247,7 @@ impl BlockDevManager {
         if path.starts_with("/dev/") {
             path = path.strip_prefix("/dev/")?;
         }
+        let full_path = path;
 
         let mut partno = GenDisk::ENTIRE_DISK_IDX;
         // 截取末尾数字
@@ -199,7 +263,16 @@ impl BlockDevManager {
             partno = path[last_digit..].parse().ok()?;
         }
 
-        let path = &path[..last_digit];
+        let mut path = &path[..last_digit];
+        if let Some(prefix) = path.strip_suffix('p') {
+            if prefix.ends_with(|c: char| c.is_ascii_digit()) {
+                // nvme0n1p1
+                path = prefix;
+            }
+        } else if path.contains(|c: char| c.is_ascii_digit()) {
+            // 设备名本身以数字结尾（例如nvme0n1），末尾的数字不是分区号
+            return Some((full_path, GenDisk::ENTIRE_DISK_IDX));
+        }
 
         Some((path, partno))
     }
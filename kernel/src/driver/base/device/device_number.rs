@@ -9,10 +9,17 @@ impl Major {
     /// 未命名的主设备
     pub const UNNAMED_MAJOR: Self = Self::new(0);
 
+    /// 内存设备（/dev/null, /dev/random...）
+    pub const MEM_MAJOR: Self = Self::new(1);
+
     pub const IDE0_MAJOR: Self = Self::new(3);
     pub const TTY_MAJOR: Self = Self::new(4);
     pub const TTYAUX_MAJOR: Self = Self::new(5);
     pub const HD_MAJOR: Self = Self::IDE0_MAJOR;
+    /// loop块设备
+    pub const LOOP_MAJOR: Self = Self::new(7);
+    /// 杂项设备（/dev/loop-control...）
+    pub const MISC_MAJOR: Self = Self::new(10);
 
     pub const INPUT_MAJOR: Self = Self::new(13);
     /// /dev/fb* framebuffers
@@ -24,6 +31,9 @@ impl Major {
     pub const UNIX98_PTY_SLAVE_MAJOR: Self =
         Self::new(Self::UNIX98_PTY_MASTER_MAJOR.0 + Self::UNIX98_PTY_MAJOR_COUNT.0);
 
+    /// hvc (虚拟化控制台)
+    pub const HVC_MAJOR: Self = Self::new(229);
+
     pub const fn new(x: u32) -> Self {
         Major(x)
     }
@@ -0,0 +1,1045 @@
+//! loop块设备
+//!
+//! 把一个普通文件（或另一个块设备）作为块设备使用，从而可以在不重启的情况下挂载文件系统镜像。
+//!
+//! 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/drivers/block/loop.c
+
+use core::any::Any;
+
+use alloc::{
+    collections::BTreeMap,
+    format,
+    string::{String, ToString},
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+use log::{info, warn};
+use system_error::SystemError;
+use unified_init::macros::unified_init;
+
+use crate::{
+    driver::base::{
+        block::{
+            block_device::{BlockDevName, BlockDevice, BlockId, GeneralBlockRange, LBA_SIZE},
+            disk_info::Partition,
+            manager::{block_dev_manager, BlockDevMeta},
+        },
+        class::Class,
+        device::{
+            bus::Bus,
+            device_number::{DeviceNumber, Major},
+            driver::Driver,
+            Device, DeviceCommonData, DeviceType, IdTable,
+        },
+        kobject::{KObjType, KObject, KObjectCommonData, KObjectState, LockedKObjectState},
+        kset::KSet,
+    },
+    filesystem::{
+        devfs::{devfs_register, devfs_unregister, DevFS, DeviceINode},
+        kernfs::KernFSInode,
+        mbr::MbrDiskPartionTable,
+        vfs::{
+            core::generate_inode_id,
+            file::{File, FileMode},
+            syscall::ModeType,
+            utils::inode_identity,
+            FilePrivateData, FileSystem, FileType, IndexNode, Metadata,
+        },
+    },
+    init::initcall::INITCALL_DEVICE,
+    libs::{
+        mutex::Mutex,
+        rwlock::{RwLockReadGuard, RwLockWriteGuard},
+        spinlock::{SpinLock, SpinLockGuard},
+    },
+    process::ProcessManager,
+    syscall::user_access::{UserBufferReader, UserBufferWriter},
+    time::PosixTimeSpec,
+};
+
+/// 系统启动时默认创建的loop设备数量
+const LOOP_DEFAULT_NR: u32 = 8;
+/// /dev/loop-control 的次设备号
+const LOOP_CTRL_MINOR: u32 = 237;
+/// loop_info64中名字字段的长度
+const LO_NAME_SIZE: usize = 64;
+const LO_KEY_SIZE: usize = 32;
+
+/// /dev/loopN 支持的ioctl命令
+const LOOP_SET_FD: u32 = 0x4C00;
+const LOOP_CLR_FD: u32 = 0x4C01;
+const LOOP_SET_STATUS64: u32 = 0x4C04;
+const LOOP_GET_STATUS64: u32 = 0x4C05;
+const LOOP_CONFIGURE: u32 = 0x4C0A;
+
+/// /dev/loop-control 支持的ioctl命令
+const LOOP_CTL_ADD: u32 = 0x4C80;
+const LOOP_CTL_REMOVE: u32 = 0x4C81;
+const LOOP_CTL_GET_FREE: u32 = 0x4C82;
+
+bitflags! {
+    /// loop设备的标志位
+    ///
+    /// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/include/uapi/linux/loop.h#21
+    pub struct LoopFlags: u32 {
+        /// 设备只读
+        const READ_ONLY = 1;
+        /// 最后一个使用者关闭后自动解除绑定
+        const AUTOCLEAR = 4;
+        /// 绑定时扫描分区表
+        const PARTSCAN = 8;
+        /// 绕过后端文件的页缓存
+        const DIRECT_IO = 16;
+    }
+}
+
+impl LoopFlags {
+    /// LOOP_SET_STATUS64能够设置的标志位
+    const SETTABLE: Self = Self::from_bits_truncate(Self::AUTOCLEAR.bits() | Self::PARTSCAN.bits());
+    /// LOOP_SET_STATUS64能够清除的标志位
+    const CLEARABLE: Self = Self::AUTOCLEAR;
+    /// LOOP_CONFIGURE能够设置的标志位
+    const CONFIGURE: Self = Self::from_bits_truncate(
+        Self::READ_ONLY.bits()
+            | Self::AUTOCLEAR.bits()
+            | Self::PARTSCAN.bits()
+            | Self::DIRECT_IO.bits(),
+    );
+}
+
+/// 用户态的struct loop_info64
+///
+/// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/include/uapi/linux/loop.h#56
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct LoopInfo64 {
+    pub lo_device: u64,
+    pub lo_inode: u64,
+    pub lo_rdevice: u64,
+    pub lo_offset: u64,
+    pub lo_sizelimit: u64,
+    pub lo_number: u32,
+    pub lo_encrypt_type: u32,
+    pub lo_encrypt_key_size: u32,
+    pub lo_flags: u32,
+    pub lo_file_name: [u8; LO_NAME_SIZE],
+    pub lo_crypt_name: [u8; LO_NAME_SIZE],
+    pub lo_encrypt_key: [u8; LO_KEY_SIZE],
+    pub lo_init: [u64; 2],
+}
+
+impl Default for LoopInfo64 {
+    fn default() -> Self {
+        Self {
+            lo_device: 0,
+            lo_inode: 0,
+            lo_rdevice: 0,
+            lo_offset: 0,
+            lo_sizelimit: 0,
+            lo_number: 0,
+            lo_encrypt_type: 0,
+            lo_encrypt_key_size: 0,
+            lo_flags: 0,
+            lo_file_name: [0; LO_NAME_SIZE],
+            lo_crypt_name: [0; LO_NAME_SIZE],
+            lo_encrypt_key: [0; LO_KEY_SIZE],
+            lo_init: [0; 2],
+        }
+    }
+}
+
+/// 用户态的struct loop_config，LOOP_CONFIGURE的参数
+///
+/// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/include/uapi/linux/loop.h#69
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct LoopConfig {
+    pub fd: u32,
+    pub block_size: u32,
+    pub info: LoopInfo64,
+    pub reserved: [u64; 8],
+}
+
+/// loop设备绑定的后端文件
+#[derive(Debug)]
+struct LoopBacking {
+    file: Arc<File>,
+    /// 数据在后端文件中的起始偏移
+    offset: usize,
+    /// 设备的最大字节数，0表示使用整个文件
+    sizelimit: usize,
+    flags: LoopFlags,
+    file_name: [u8; LO_NAME_SIZE],
+    /// 设备的容量（以LBA_SIZE为单位）
+    nr_sectors: usize,
+}
+
+impl LoopBacking {
+    /// 根据后端文件的大小计算设备容量
+    fn capacity(&self) -> Result<usize, SystemError> {
+        let file_size = self.file.metadata()?.size as usize;
+        let mut size = file_size.saturating_sub(self.offset);
+        if self.sizelimit != 0 {
+            size = size.min(self.sizelimit);
+        }
+        Ok(size / LBA_SIZE)
+    }
+}
+
+/// loop块设备，对上层表现为以512字节为块的块设备
+#[derive(Debug)]
+#[cast_to([sync] Device)]
+pub struct LoopDevice {
+    blkdev_meta: BlockDevMeta,
+    number: u32,
+    inner: SpinLock<InnerLoopDevice>,
+    /// 串行化绑定、解绑以及修改状态的操作
+    ctl_lock: Mutex<()>,
+    locked_kobj_state: LockedKObjectState,
+    self_ref: Weak<Self>,
+}
+
+#[derive(Debug)]
+struct InnerLoopDevice {
+    backing: Option<LoopBacking>,
+    device_common: DeviceCommonData,
+    kobject_common: KObjectCommonData,
+}
+
+impl LoopDevice {
+    fn new(number: u32) -> Arc<Self> {
+        let devname = BlockDevName::new(format!("loop{}", number), number as usize);
+        Arc::new_cyclic(|self_ref| Self {
+            blkdev_meta: BlockDevMeta::new(devname),
+            number,
+            inner: SpinLock::new(InnerLoopDevice {
+                backing: None,
+                device_common: DeviceCommonData::default(),
+                kobject_common: KObjectCommonData::default(),
+            }),
+            ctl_lock: Mutex::new(()),
+            locked_kobj_state: LockedKObjectState::default(),
+            self_ref: self_ref.clone(),
+        })
+    }
+
+    fn inner(&self) -> SpinLockGuard<InnerLoopDevice> {
+        self.inner.lock()
+    }
+
+    fn as_block_device(&self) -> Arc<dyn BlockDevice> {
+        self.self_ref.upgrade().unwrap()
+    }
+
+    /// 设备是否已经绑定了后端文件
+    pub fn is_bound(&self) -> bool {
+        self.inner().backing.is_some()
+    }
+
+    /// 设备的字节数，未绑定时为0
+    pub fn size(&self) -> usize {
+        self.inner()
+            .backing
+            .as_ref()
+            .map(|b| b.nr_sectors * LBA_SIZE)
+            .unwrap_or(0)
+    }
+
+    /// 获取进行I/O所需的信息，避免在持有自旋锁的情况下读写后端文件
+    ///
+    /// ## 返回值
+    ///
+    /// (后端文件, 起始偏移, 设备容量, 是否只读)
+    fn io_target(&self) -> Result<(Arc<File>, usize, usize, bool), SystemError> {
+        let inner = self.inner();
+        let backing = inner.backing.as_ref().ok_or(SystemError::ENXIO)?;
+        Ok((
+            backing.file.clone(),
+            backing.offset,
+            backing.nr_sectors,
+            backing.flags.contains(LoopFlags::READ_ONLY),
+        ))
+    }
+
+    /// 把文件绑定到loop设备上，并注册到块设备管理器
+    ///
+    /// ## 参数
+    ///
+    /// - `file`: 后端文件
+    /// - `info`: 设备的初始状态，LOOP_SET_FD时为默认值
+    /// - `allowed`: info中允许设置的标志位
+    fn attach(
+        &self,
+        file: Arc<File>,
+        info: &LoopInfo64,
+        allowed: LoopFlags,
+    ) -> Result<(), SystemError> {
+        let _guard = self.ctl_lock.lock();
+        if self.is_bound() {
+            return Err(SystemError::EBUSY);
+        }
+
+        let file_type = file.file_type();
+        if file_type != FileType::File && file_type != FileType::BlockDevice {
+            return Err(SystemError::EINVAL);
+        }
+        file.readable().map_err(|_| SystemError::EBADF)?;
+
+        let mut flags = LoopFlags::from_bits_truncate(info.lo_flags) & allowed;
+        // 没有实现直接I/O，总是经过后端文件的页缓存
+        flags.remove(LoopFlags::DIRECT_IO);
+        if file.mode().accmode() == FileMode::O_RDONLY.bits() {
+            flags.insert(LoopFlags::READ_ONLY);
+        }
+
+        let mut backing = LoopBacking {
+            file,
+            offset: info.lo_offset as usize,
+            sizelimit: info.lo_sizelimit as usize,
+            flags,
+            file_name: info.lo_file_name,
+            nr_sectors: 0,
+        };
+        backing.nr_sectors = backing.capacity()?;
+        if backing.nr_sectors == 0 {
+            return Err(SystemError::EINVAL);
+        }
+
+        self.inner().backing = Some(backing);
+        let partscan = flags.contains(LoopFlags::PARTSCAN);
+        block_dev_manager()
+            .register_disk(self.as_block_device(), partscan)
+            .inspect_err(|_| {
+                self.inner().backing = None;
+            })
+    }
+
+    /// 解除loop设备与后端文件的绑定
+    ///
+    /// 设备上的文件系统还处于挂载状态时返回EBUSY
+    fn detach(&self) -> Result<(), SystemError> {
+        let _guard = self.ctl_lock.lock();
+        if !self.is_bound() {
+            return Err(SystemError::ENXIO);
+        }
+
+        block_dev_manager().unregister(&self.as_block_device())?;
+        self.inner().backing = None;
+        Ok(())
+    }
+
+    /// LOOP_SET_STATUS64
+    fn set_status(&self, info: &LoopInfo64) -> Result<(), SystemError> {
+        let _guard = self.ctl_lock.lock();
+        let mut inner = self.inner();
+        let backing = inner.backing.as_mut().ok_or(SystemError::ENXIO)?;
+
+        let new_flags = LoopFlags::from_bits_truncate(info.lo_flags);
+        let mut flags = backing.flags;
+        flags.remove(LoopFlags::CLEARABLE & !new_flags);
+        flags.insert(LoopFlags::SETTABLE & new_flags);
+
+        let offset = info.lo_offset as usize;
+        let sizelimit = info.lo_sizelimit as usize;
+        // 容量变化或者新开启分区扫描时，需要重新注册gendisk
+        let reregister = offset != backing.offset
+            || sizelimit != backing.sizelimit
+            || (flags.contains(LoopFlags::PARTSCAN)
+                && !backing.flags.contains(LoopFlags::PARTSCAN));
+
+        if !reregister {
+            backing.file_name = info.lo_file_name;
+            backing.flags = flags;
+            return Ok(());
+        }
+        let file = backing.file.clone();
+        drop(inner);
+
+        let mut new_backing = LoopBacking {
+            file,
+            offset,
+            sizelimit,
+            flags,
+            file_name: info.lo_file_name,
+            nr_sectors: 0,
+        };
+        new_backing.nr_sectors = new_backing.capacity()?;
+        if new_backing.nr_sectors == 0 {
+            return Err(SystemError::EINVAL);
+        }
+
+        let dev = self.as_block_device();
+        block_dev_manager().unregister(&dev)?;
+        let old_backing = self.inner().backing.replace(new_backing);
+        let Err(e) =
+            block_dev_manager().register_disk(dev.clone(), flags.contains(LoopFlags::PARTSCAN))
+        else {
+            return Ok(());
+        };
+
+        // 恢复原来的状态。仍然失败时设备已经无法使用，只能解除绑定
+        warn!(
+            "{}: failed to re-register with the new status: {:?}, rolling back",
+            self.dev_name(),
+            e
+        );
+        let old_partscan = old_backing
+            .as_ref()
+            .is_some_and(|backing| backing.flags.contains(LoopFlags::PARTSCAN));
+        self.inner().backing = old_backing;
+        if let Err(e) = block_dev_manager().register_disk(dev, old_partscan) {
+            warn!(
+                "{}: failed to restore the old status: {:?}, detaching",
+                self.dev_name(),
+                e
+            );
+            self.inner().backing = None;
+        }
+        Err(e)
+    }
+
+    /// LOOP_GET_STATUS64
+    fn status(&self) -> Result<LoopInfo64, SystemError> {
+        let (file, _, _, _) = self.io_target()?;
+        let metadata = file.metadata()?;
+
+        let inner = self.inner();
+        let backing = inner.backing.as_ref().ok_or(SystemError::ENXIO)?;
+        Ok(LoopInfo64 {
+            lo_device: metadata.dev_id as u64,
+            lo_inode: metadata.inode_id.into() as u64,
+            lo_rdevice: metadata.raw_dev.data() as u64,
+            lo_offset: backing.offset as u64,
+            lo_sizelimit: backing.sizelimit as u64,
+            lo_number: self.number,
+            lo_flags: backing.flags.bits(),
+            lo_file_name: backing.file_name,
+            ..Default::default()
+        })
+    }
+}
+
+impl BlockDevice for LoopDevice {
+    fn dev_name(&self) -> &BlockDevName {
+        &self.blkdev_meta.devname
+    }
+
+    fn blkdev_meta(&self) -> &BlockDevMeta {
+        &self.blkdev_meta
+    }
+
+    /// 只有绑定了后端文件的设备才会注册到块设备管理器，因此容量不会为0
+    fn disk_range(&self) -> GeneralBlockRange {
+        let nr_sectors = self
+            .inner()
+            .backing
+            .as_ref()
+            .map(|b| b.nr_sectors)
+            .unwrap_or(0);
+        GeneralBlockRange::new(0, nr_sectors).unwrap()
+    }
+
+    fn read_at_sync(
+        &self,
+        lba_id_start: BlockId,
+        count: usize,
+        buf: &mut [u8],
+    ) -> Result<usize, SystemError> {
+        let (file, offset, nr_sectors, _) = self.io_target()?;
+        if lba_id_start + count > nr_sectors {
+            return Err(SystemError::EIO);
+        }
+
+        let len = count * LBA_SIZE;
+        let buf = &mut buf[..len];
+        let read = file.pread(offset + lba_id_start * LBA_SIZE, len, buf)?;
+        // 后端文件被截断时，超出文件末尾的部分读出为0
+        buf[read..].fill(0);
+        Ok(len)
+    }
+
+    fn write_at_sync(
+        &self,
+        lba_id_start: BlockId,
+        count: usize,
+        buf: &[u8],
+    ) -> Result<usize, SystemError> {
+        let (file, offset, nr_sectors, read_only) = self.io_target()?;
+        if read_only {
+            return Err(SystemError::EROFS);
+        }
+        if lba_id_start + count > nr_sectors {
+            return Err(SystemError::EIO);
+        }
+
+        let len = count * LBA_SIZE;
+        let written = file.pwrite(offset + lba_id_start * LBA_SIZE, len, &buf[..len])?;
+        if written != len {
+            return Err(SystemError::EIO);
+        }
+        Ok(len)
+    }
+
+    /// 后端文件自己有页缓存，不经过全局的BlockCache
+    fn read_at(
+        &self,
+        lba_id_start: BlockId,
+        count: usize,
+        buf: &mut [u8],
+    ) -> Result<usize, SystemError> {
+        self.read_at_sync(lba_id_start, count, buf)
+    }
+
+    fn write_at(
+        &self,
+        lba_id_start: BlockId,
+        count: usize,
+        buf: &[u8],
+    ) -> Result<usize, SystemError> {
+        self.write_at_sync(lba_id_start, count, buf)
+    }
+
+    fn sync(&self) -> Result<(), SystemError> {
+        let (file, _, _, _) = self.io_target()?;
+        file.inode().sync()
+    }
+
+    fn blk_size_log2(&self) -> u8 {
+        9
+    }
+
+    fn as_any_ref(&self) -> &dyn Any {
+        self
+    }
+
+    fn device(&self) -> Arc<dyn Device> {
+        self.self_ref.upgrade().unwrap()
+    }
+
+    fn block_size(&self) -> usize {
+        LBA_SIZE
+    }
+
+    fn partitions(&self) -> Vec<Arc<Partition>> {
+        let device = self.as_block_device();
+        MbrDiskPartionTable::from_disk(device.clone())
+            .map(|mbr_table| mbr_table.partitions(Arc::downgrade(&device)))
+            .unwrap_or_default()
+    }
+}
+
+impl Device for LoopDevice {
+    fn dev_type(&self) -> DeviceType {
+        DeviceType::Block
+    }
+
+    fn id_table(&self) -> IdTable {
+        IdTable::new(
+            self.dev_name().to_string(),
+            Some(DeviceNumber::new(Major::LOOP_MAJOR, self.number)),
+        )
+    }
+
+    fn bus(&self) -> Option<Weak<dyn Bus>> {
+        self.inner().device_common.bus.clone()
+    }
+
+    fn set_bus(&self, bus: Option<Weak<dyn Bus>>) {
+        self.inner().device_common.bus = bus;
+    }
+
+    fn class(&self) -> Option<Arc<dyn Class>> {
+        let mut guard = self.inner();
+        let r = guard.device_common.class.clone()?.upgrade();
+        if r.is_none() {
+            guard.device_common.class = None;
+        }
+
+        return r;
+    }
+
+    fn set_class(&self, class: Option<Weak<dyn Class>>) {
+        self.inner().device_common.class = class;
+    }
+
+    fn driver(&self) -> Option<Arc<dyn Driver>> {
+        let r = self.inner().device_common.driver.clone()?.upgrade();
+        if r.is_none() {
+            self.inner().device_common.driver = None;
+        }
+
+        return r;
+    }
+
+    fn set_driver(&self, driver: Option<Weak<dyn Driver>>) {
+        self.inner().device_common.driver = driver;
+    }
+
+    fn is_dead(&self) -> bool {
+        false
+    }
+
+    fn can_match(&self) -> bool {
+        self.inner().device_common.can_match
+    }
+
+    fn set_can_match(&self, can_match: bool) {
+        self.inner().device_common.can_match = can_match;
+    }
+
+    fn state_synced(&self) -> bool {
+        true
+    }
+
+    fn dev_parent(&self) -> Option<Weak<dyn Device>> {
+        self.inner().device_common.get_parent_weak_or_clear()
+    }
+
+    fn set_dev_parent(&self, parent: Option<Weak<dyn Device>>) {
+        self.inner().device_common.parent = parent;
+    }
+}
+
+impl KObject for LoopDevice {
+    fn as_any_ref(&self) -> &dyn Any {
+        self
+    }
+
+    fn set_inode(&self, inode: Option<Arc<KernFSInode>>) {
+        self.inner().kobject_common.kern_inode = inode;
+    }
+
+    fn inode(&self) -> Option<Arc<KernFSInode>> {
+        self.inner().kobject_common.kern_inode.clone()
+    }
+
+    fn parent(&self) -> Option<Weak<dyn KObject>> {
+        self.inner().kobject_common.parent.clone()
+    }
+
+    fn set_parent(&self, parent: Option<Weak<dyn KObject>>) {
+        self.inner().kobject_common.parent = parent;
+    }
+
+    fn kset(&self) -> Option<Arc<KSet>> {
+        self.inner().kobject_common.kset.clone()
+    }
+
+    fn set_kset(&self, kset: Option<Arc<KSet>>) {
+        self.inner().kobject_common.kset = kset;
+    }
+
+    fn kobj_type(&self) -> Option<&'static dyn KObjType> {
+        self.inner().kobject_common.kobj_type
+    }
+
+    fn name(&self) -> String {
+        self.dev_name().to_string()
+    }
+
+    fn set_name(&self, _name: String) {
+        // do nothing
+    }
+
+    fn kobj_state(&self) -> RwLockReadGuard<KObjectState> {
+        self.locked_kobj_state.read()
+    }
+
+    fn kobj_state_mut(&self) -> RwLockWriteGuard<KObjectState> {
+        self.locked_kobj_state.write()
+    }
+
+    fn set_kobj_state(&self, state: KObjectState) {
+        *self.locked_kobj_state.write() = state;
+    }
+
+    fn set_kobj_type(&self, ktype: Option<&'static dyn KObjType>) {
+        self.inner().kobject_common.kobj_type = ktype;
+    }
+}
+
+/// /dev/loopN 设备节点，负责处理LOOP_*系列ioctl
+#[derive(Debug)]
+pub struct LoopDevInode {
+    loop_dev: Arc<LoopDevice>,
+    inner: SpinLock<InnerLoopDevInode>,
+    self_ref: Weak<Self>,
+}
+
+#[derive(Debug)]
+struct InnerLoopDevInode {
+    /// 指向inode所在的文件系统对象的指针
+    fs: Weak<DevFS>,
+    /// INode 元数据
+    metadata: Metadata,
+}
+
+impl LoopDevInode {
+    fn new(number: u32) -> Arc<Self> {
+        let metadata = Metadata {
+            dev_id: 1,
+            inode_id: generate_inode_id(),
+            size: 0,
+            blk_size: LBA_SIZE,
+            blocks: 0,
+            atime: PosixTimeSpec::default(),
+            mtime: PosixTimeSpec::default(),
+            ctime: PosixTimeSpec::default(),
+            file_type: FileType::BlockDevice,
+            mode: ModeType::from_bits_truncate(0o660),
+            nlinks: 1,
+            uid: 0,
+            gid: 0,
+            raw_dev: DeviceNumber::new(Major::LOOP_MAJOR, number),
+        };
+
+        Arc::new_cyclic(|self_ref| Self {
+            loop_dev: LoopDevice::new(number),
+            inner: SpinLock::new(InnerLoopDevInode {
+                fs: Weak::default(),
+                metadata,
+            }),
+            self_ref: self_ref.clone(),
+        })
+    }
+
+    pub fn loop_device(&self) -> &Arc<LoopDevice> {
+        &self.loop_dev
+    }
+
+    /// 从当前进程的文件描述符表中取出后端文件
+    fn backing_file(&self, fd: i32) -> Result<Arc<File>, SystemError> {
+        let file = ProcessManager::current_pcb()
+            .fd_table()
+            .read()
+            .get_file_by_fd(fd)
+            .ok_or(SystemError::EBADF)?;
+
+        // 不允许把loop设备绑定到自己身上
+        let self_inode = self.self_ref.upgrade().unwrap() as Arc<dyn IndexNode>;
+        if inode_identity(&file.inode()) == inode_identity(&self_inode) {
+            return Err(SystemError::EINVAL);
+        }
+        Ok(file)
+    }
+
+    fn ioctl_set_fd(&self, fd: usize) -> Result<usize, SystemError> {
+        let file = self.backing_file(fd as i32)?;
+        self.loop_dev
+            .attach(file, &LoopInfo64::default(), LoopFlags::empty())?;
+        Ok(0)
+    }
+
+    fn ioctl_configure(&self, arg: usize) -> Result<usize, SystemError> {
+        let reader = UserBufferReader::new(
+            arg as *const LoopConfig,
+            core::mem::size_of::<LoopConfig>(),
+            true,
+        )?;
+        let config = *reader.read_one_from_user::<LoopConfig>(0)?;
+        // 只支持512字节的逻辑块
+        if config.block_size != 0 && config.block_size as usize != LBA_SIZE {
+            return Err(SystemError::EINVAL);
+        }
+
+        let file = self.backing_file(config.fd as i32)?;
+        self.loop_dev
+            .attach(file, &config.info, LoopFlags::CONFIGURE)?;
+        Ok(0)
+    }
+
+    fn ioctl_set_status(&self, arg: usize) -> Result<usize, SystemError> {
+        let reader = UserBufferReader::new(
+            arg as *const LoopInfo64,
+            core::mem::size_of::<LoopInfo64>(),
+            true,
+        )?;
+        let info = *reader.read_one_from_user::<LoopInfo64>(0)?;
+        self.loop_dev.set_status(&info)?;
+        Ok(0)
+    }
+
+    fn ioctl_get_status(&self, arg: usize) -> Result<usize, SystemError> {
+        let info = self.loop_dev.status()?;
+        let mut writer = UserBufferWriter::new(
+            arg as *mut LoopInfo64,
+            core::mem::size_of::<LoopInfo64>(),
+            true,
+        )?;
+        writer.copy_one_to_user(&info, 0)?;
+        Ok(0)
+    }
+}
+
+impl DeviceINode for LoopDevInode {
+    fn set_fs(&self, fs: Weak<DevFS>) {
+        self.inner.lock().fs = fs;
+    }
+}
+
+impl IndexNode for LoopDevInode {
+    fn as_any_ref(&self) -> &dyn Any {
+        self
+    }
+
+    fn open(
+        &self,
+        _data: SpinLockGuard<FilePrivateData>,
+        _mode: &FileMode,
+    ) -> Result<(), SystemError> {
+        Ok(())
+    }
+
+    fn close(&self, _data: SpinLockGuard<FilePrivateData>) -> Result<(), SystemError> {
+        Ok(())
+    }
+
+    fn metadata(&self) -> Result<Metadata, SystemError> {
+        let mut metadata = self.inner.lock().metadata.clone();
+        let size = self.loop_dev.size();
+        metadata.size = size as i64;
+        metadata.blocks = size / LBA_SIZE;
+        Ok(metadata)
+    }
+
+    fn set_metadata(&self, metadata: &Metadata) -> Result<(), SystemError> {
+        let mut inner = self.inner.lock();
+        inner.metadata.atime = metadata.atime;
+        inner.metadata.mtime = metadata.mtime;
+        inner.metadata.ctime = metadata.ctime;
+        inner.metadata.mode = metadata.mode;
+        inner.metadata.uid = metadata.uid;
+        inner.metadata.gid = metadata.gid;
+        Ok(())
+    }
+
+    fn fs(&self) -> Arc<dyn FileSystem> {
+        self.inner.lock().fs.upgrade().unwrap()
+    }
+
+    fn list(&self) -> Result<Vec<String>, SystemError> {
+        Err(SystemError::ENOTDIR)
+    }
+
+    fn read_at(
+        &self,
+        offset: usize,
+        len: usize,
+        buf: &mut [u8],
+        _data: SpinLockGuard<FilePrivateData>,
+    ) -> Result<usize, SystemError> {
+        let size = self.loop_dev.size();
+        if offset >= size {
+            return Ok(0);
+        }
+
+        let len = len.min(buf.len()).min(size - offset);
+        self.loop_dev.read_at_bytes(offset, len, buf)
+    }
+
+    fn write_at(
+        &self,
+        offset: usize,
+        len: usize,
+        buf: &[u8],
+        _data: SpinLockGuard<FilePrivateData>,
+    ) -> Result<usize, SystemError> {
+        if !self.loop_dev.is_bound() {
+            return Err(SystemError::ENXIO);
+        }
+        let size = self.loop_dev.size();
+        if offset >= size {
+            return Err(SystemError::ENOSPC);
+        }
+
+        let len = len.min(buf.len()).min(size - offset);
+        self.loop_dev.write_at_bytes(offset, len, buf)
+    }
+
+    fn ioctl(
+        &self,
+        cmd: u32,
+        data: usize,
+        _private_data: &FilePrivateData,
+    ) -> Result<usize, SystemError> {
+        match cmd {
+            LOOP_SET_FD => self.ioctl_set_fd(data),
+            LOOP_CONFIGURE => self.ioctl_configure(data),
+            LOOP_CLR_FD => self.loop_dev.detach().map(|_| 0),
+            LOOP_SET_STATUS64 => self.ioctl_set_status(data),
+            LOOP_GET_STATUS64 => self.ioctl_get_status(data),
+            _ => Err(SystemError::ENOIOCTLCMD),
+        }
+    }
+}
+
+/// 所有的loop设备，以设备号为索引
+static LOOP_DEVICES: SpinLock<BTreeMap<u32, Arc<LoopDevInode>>> = SpinLock::new(BTreeMap::new());
+
+/// 创建编号为`number`的loop设备，并注册到devfs
+fn loop_add(number: u32) -> Result<Arc<LoopDevInode>, SystemError> {
+    if number > DeviceNumber::MINOR_MASK {
+        return Err(SystemError::EINVAL);
+    }
+
+    let mut devices = LOOP_DEVICES.lock();
+    if devices.contains_key(&number) {
+        return Err(SystemError::EEXIST);
+    }
+
+    let inode = LoopDevInode::new(number);
+    devfs_register(&format!("loop{}", number), inode.clone())?;
+    devices.insert(number, inode.clone());
+    Ok(inode)
+}
+
+/// 删除编号为`number`的loop设备，设备仍然绑定着文件时返回EBUSY
+fn loop_remove(number: u32) -> Result<(), SystemError> {
+    let mut devices = LOOP_DEVICES.lock();
+    let inode = devices.get(&number).ok_or(SystemError::ENODEV)?;
+    if inode.loop_device().is_bound() {
+        return Err(SystemError::EBUSY);
+    }
+
+    devfs_unregister(&format!("loop{}", number), inode.clone())?;
+    devices.remove(&number);
+    Ok(())
+}
+
+/// 返回第一个未绑定的loop设备的编号，如果没有，则创建一个新的loop设备
+fn loop_get_free() -> Result<u32, SystemError> {
+    let next = {
+        let devices = LOOP_DEVICES.lock();
+        if let Some((number, _)) = devices
+            .iter()
+            .find(|(_, inode)| !inode.loop_device().is_bound())
+        {
+            return Ok(*number);
+        }
+        devices.keys().next_back().map(|n| n + 1).unwrap_or(0)
+    };
+
+    loop_add(next).map(|_| next)
+}
+
+/// /dev/loop-control，用于动态地添加、删除以及查找空闲的loop设备
+///
+/// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/drivers/block/loop.c#2142
+#[derive(Debug)]
+pub struct LoopControlInode {
+    inner: SpinLock<InnerLoopDevInode>,
+}
+
+impl LoopControlInode {
+    fn new() -> Arc<Self> {
+        let metadata = Metadata {
+            dev_id: 1,
+            inode_id: generate_inode_id(),
+            size: 0,
+            blk_size: 0,
+            blocks: 0,
+            atime: PosixTimeSpec::default(),
+            mtime: PosixTimeSpec::default(),
+            ctime: PosixTimeSpec::default(),
+            file_type: FileType::CharDevice,
+            mode: ModeType::from_bits_truncate(0o660),
+            nlinks: 1,
+            uid: 0,
+            gid: 0,
+            raw_dev: DeviceNumber::new(Major::MISC_MAJOR, LOOP_CTRL_MINOR),
+        };
+
+        Arc::new(Self {
+            inner: SpinLock::new(InnerLoopDevInode {
+                fs: Weak::default(),
+                metadata,
+            }),
+        })
+    }
+}
+
+impl DeviceINode for LoopControlInode {
+    fn set_fs(&self, fs: Weak<DevFS>) {
+        self.inner.lock().fs = fs;
+    }
+}
+
+impl IndexNode for LoopControlInode {
+    fn as_any_ref(&self) -> &dyn Any {
+        self
+    }
+
+    fn open(
+        &self,
+        _data: SpinLockGuard<FilePrivateData>,
+        _mode: &FileMode,
+    ) -> Result<(), SystemError> {
+        Ok(())
+    }
+
+    fn close(&self, _data: SpinLockGuard<FilePrivateData>) -> Result<(), SystemError> {
+        Ok(())
+    }
+
+    fn metadata(&self) -> Result<Metadata, SystemError> {
+        Ok(self.inner.lock().metadata.clone())
+    }
+
+    fn fs(&self) -> Arc<dyn FileSystem> {
+        self.inner.lock().fs.upgrade().unwrap()
+    }
+
+    fn list(&self) -> Result<Vec<String>, SystemError> {
+        Err(SystemError::ENOTDIR)
+    }
+
+    fn read_at(
+        &self,
+        _offset: usize,
+        _len: usize,
+        _buf: &mut [u8],
+        _data: SpinLockGuard<FilePrivateData>,
+    ) -> Result<usize, SystemError> {
+        Err(SystemError::EINVAL)
+    }
+
+    fn write_at(
+        &self,
+        _offset: usize,
+        _len: usize,
+        _buf: &[u8],
+        _data: SpinLockGuard<FilePrivateData>,
+    ) -> Result<usize, SystemError> {
+        Err(SystemError::EINVAL)
+    }
+
+    fn ioctl(
+        &self,
+        cmd: u32,
+        data: usize,
+        _private_data: &FilePrivateData,
+    ) -> Result<usize, SystemError> {
+        match cmd {
+            LOOP_CTL_ADD => {
+                let number = u32::try_from(data).map_err(|_| SystemError::EINVAL)?;
+                loop_add(number).map(|_| number as usize)
+            }
+            LOOP_CTL_REMOVE => {
+                let number = u32::try_from(data).map_err(|_| SystemError::EINVAL)?;
+                loop_remove(number).map(|_| number as usize)
+            }
+            LOOP_CTL_GET_FREE => loop_get_free().map(|n| n as usize),
+            _ => Err(SystemError::ENOIOCTLCMD),
+        }
+    }
+}
+
+#[unified_init(INITCALL_DEVICE)]
+fn loop_init() -> Result<(), SystemError> {
+    for number in 0..LOOP_DEFAULT_NR {
+        loop_add(number)?;
+    }
+    devfs_register("loop-control", LoopControlInode::new())?;
+    info!("loop: module loaded");
+    Ok(())
+}
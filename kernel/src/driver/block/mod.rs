@@ -1,2 +1,4 @@
 pub mod cache;
+pub mod loop_device;
+pub mod nvme;
 pub mod virtio_blk;
@@ -0,0 +1,712 @@
+//! NVMe块设备驱动
+//!
+//! 每个控制器使用一对管理队列和一对I/O队列，完成中断通过MSI-X送达。
+//! 控制器上的每个namespace都会被注册为一个块设备（nvme{控制器编号}n{nsid}）。
+//!
+//! 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/drivers/nvme/host/pci.c
+
+use core::{
+    ptr::NonNull,
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+};
+
+use alloc::{
+    boxed::Box,
+    format,
+    string::{String, ToString},
+    sync::Arc,
+    vec::Vec,
+};
+use log::{error, info, warn};
+use system_error::SystemError;
+
+use crate::{
+    driver::{
+        base::{
+            block::{block_device::BlockDevice, manager::block_dev_manager},
+            device::DeviceId,
+        },
+        net::dma::dma_alloc,
+        pci::{
+            pci::{
+                get_pci_device_structure_mut, PciDeviceStructure, PciDeviceStructureGeneralDevice,
+                PCI_DEVICE_LINKEDLIST,
+            },
+            pci_irq::{IrqCommonMsg, IrqSpecificMsg, PciInterrupt, PciIrqMsg, IRQ},
+        },
+    },
+    exception::{
+        irqdata::IrqHandlerData,
+        irqdesc::{IrqHandler, IrqReturn},
+        IrqNumber,
+    },
+    libs::{mutex::Mutex, spinlock::SpinLock},
+    time::{sleep::nanosleep, Duration, Instant, PosixTimeSpec},
+};
+
+use self::{namespace::NvmeNamespace, queue::NvmeQueue};
+
+mod namespace;
+mod queue;
+
+const NVME_CLASS: u8 = 0x1;
+const NVME_SUBCLASS: u8 = 0x8;
+const NVME_PROG_IF: u8 = 0x2;
+
+/// 目前缺少对PCI设备中断号的统一管理，所以这里需要指定一个中断号。不能与其他中断重复
+const NVME_IRQ_VECTOR: IrqNumber = IrqNumber::new(58);
+
+/// 驱动使用的内存页大小（CC.MPS = 0）
+const NVME_PAGE_SIZE: usize = 4096;
+/// 管理队列和I/O队列的长度
+const NVME_QUEUE_DEPTH: u16 = 64;
+/// 单条读写命令最多传输的字节数（也是中转缓冲区的大小）
+const NVME_MAX_TRANSFER: usize = 128 * 1024;
+
+// 控制器寄存器的偏移
+const NVME_REG_CAP: usize = 0x00;
+const NVME_REG_VS: usize = 0x08;
+const NVME_REG_CC: usize = 0x14;
+const NVME_REG_CSTS: usize = 0x1c;
+const NVME_REG_AQA: usize = 0x24;
+const NVME_REG_ASQ: usize = 0x28;
+const NVME_REG_ACQ: usize = 0x30;
+const NVME_REG_DOORBELL: usize = 0x1000;
+
+const NVME_CC_ENABLE: u32 = 1 << 0;
+/// I/O提交队列项的大小为2^6字节
+const NVME_CC_IOSQES: u32 = 6 << 16;
+/// I/O完成队列项的大小为2^4字节
+const NVME_CC_IOCQES: u32 = 4 << 20;
+const NVME_CSTS_RDY: u32 = 1 << 0;
+const NVME_CSTS_CFS: u32 = 1 << 1;
+
+// 管理命令
+const NVME_ADMIN_CREATE_SQ: u8 = 0x01;
+const NVME_ADMIN_CREATE_CQ: u8 = 0x05;
+const NVME_ADMIN_IDENTIFY: u8 = 0x06;
+const NVME_ADMIN_SET_FEATURES: u8 = 0x09;
+
+// I/O命令
+const NVME_CMD_FLUSH: u8 = 0x00;
+const NVME_CMD_WRITE: u8 = 0x01;
+const NVME_CMD_READ: u8 = 0x02;
+
+const NVME_ID_CNS_NS: u32 = 0x00;
+const NVME_ID_CNS_CTRL: u32 = 0x01;
+const NVME_ID_CNS_NS_ACTIVE_LIST: u32 = 0x02;
+
+const NVME_FEAT_NUM_QUEUES: u32 = 0x07;
+
+const NVME_QUEUE_PHYS_CONTIG: u32 = 1 << 0;
+const NVME_CQ_IRQ_ENABLED: u32 = 1 << 1;
+
+/// 提交队列项
+///
+/// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/include/linux/nvme.h#917
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+#[allow(dead_code)]
+struct NvmeCommand {
+    opcode: u8,
+    flags: u8,
+    cid: u16,
+    nsid: u32,
+    cdw2: u32,
+    cdw3: u32,
+    metadata: u64,
+    prp1: u64,
+    prp2: u64,
+    cdw10: u32,
+    cdw11: u32,
+    cdw12: u32,
+    cdw13: u32,
+    cdw14: u32,
+    cdw15: u32,
+}
+
+/// 完成队列项
+///
+/// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/include/linux/nvme.h#1758
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+#[allow(dead_code)]
+struct NvmeCompletion {
+    result: u32,
+    rsvd: u32,
+    sq_head: u16,
+    sq_id: u16,
+    cid: u16,
+    status: u16,
+}
+
+impl NvmeCompletion {
+    fn phase(&self) -> u16 {
+        self.status & 1
+    }
+
+    fn status_code(&self) -> u16 {
+        self.status >> 1
+    }
+}
+
+/// 用于数据传输的物理连续缓冲区
+///
+/// 超过两页的传输需要PRP列表，这里预先为整个缓冲区建好列表
+#[derive(Debug)]
+struct NvmeDmaBuffer {
+    paddr: usize,
+    vaddr: NonNull<u8>,
+    len: usize,
+    prp_list_paddr: usize,
+}
+
+unsafe impl Send for NvmeDmaBuffer {}
+unsafe impl Sync for NvmeDmaBuffer {}
+
+impl NvmeDmaBuffer {
+    fn new(len: usize) -> Self {
+        let pages = len / NVME_PAGE_SIZE;
+        let (paddr, vaddr) = dma_alloc(pages);
+        let (prp_list_paddr, prp_list) = dma_alloc(1);
+        let prp_list = prp_list.cast::<u64>();
+        for i in 1..pages {
+            unsafe {
+                prp_list
+                    .as_ptr()
+                    .add(i - 1)
+                    .write_volatile((paddr + i * NVME_PAGE_SIZE) as u64)
+            };
+        }
+
+        Self {
+            paddr,
+            vaddr,
+            len,
+            prp_list_paddr,
+        }
+    }
+
+    fn as_slice(&self) -> &[u8] {
+        unsafe { core::slice::from_raw_parts(self.vaddr.as_ptr(), self.len) }
+    }
+
+    fn as_mut_slice(&mut self) -> &mut [u8] {
+        unsafe { core::slice::from_raw_parts_mut(self.vaddr.as_ptr(), self.len) }
+    }
+
+    /// 设置命令中描述缓冲区前`len`字节的PRP
+    fn set_prp(&self, cmd: &mut NvmeCommand, len: usize) {
+        cmd.prp1 = self.paddr as u64;
+        cmd.prp2 = if len <= NVME_PAGE_SIZE {
+            0
+        } else if len <= 2 * NVME_PAGE_SIZE {
+            (self.paddr + NVME_PAGE_SIZE) as u64
+        } else {
+            self.prp_list_paddr as u64
+        };
+    }
+}
+
+static NVME_CONTROLLERS: SpinLock<Vec<Arc<NvmeController>>> = SpinLock::new(Vec::new());
+
+/// NVMe控制器
+#[derive(Debug)]
+pub struct NvmeController {
+    /// 控制器编号，用于生成设备名
+    index: usize,
+    dev_id: Arc<DeviceId>,
+    /// BAR0的虚拟地址
+    bar: usize,
+    /// 控制器就绪与命令完成的超时时间，由CAP.TO得到
+    timeout: Duration,
+    admin_queue: NvmeQueue,
+    io_queue: NvmeQueue,
+    /// 串行化I/O命令，同时保护中转缓冲区
+    io_buf: Mutex<NvmeDmaBuffer>,
+    /// 是否成功分配了MSI-X中断
+    irq_installed: bool,
+    /// 是否等待中断来获知命令完成
+    use_irq: AtomicBool,
+    /// 命令超时后复位控制器失败时置位，之后的命令直接失败
+    dead: AtomicBool,
+}
+
+impl NvmeController {
+    fn new(
+        device: &mut PciDeviceStructureGeneralDevice,
+        index: usize,
+        dev_id: Arc<DeviceId>,
+    ) -> Result<Self, SystemError> {
+        device
+            .bar_ioremap()
+            .ok_or(SystemError::ENODEV)?
+            .map_err(|_| SystemError::ENOMEM)?;
+        device.enable_master();
+        let bar = device
+            .bar()
+            .ok_or(SystemError::ENODEV)?
+            .get_bar(0)
+            .map_err(|_| SystemError::ENODEV)?
+            .virtual_address()
+            .ok_or(SystemError::ENODEV)?
+            .data();
+
+        let irq_installed = Self::setup_irq(device, dev_id.clone());
+
+        let cap = Self::read_reg64(bar, NVME_REG_CAP);
+        let vs = Self::read_reg32(bar, NVME_REG_VS);
+        let mqes = (cap & 0xffff) as u16 + 1;
+        let doorbell_stride = 4usize << ((cap >> 32) & 0xf);
+        // CAP.TO以500ms为单位。命令的超时时间也使用它
+        let timeout = Duration::from_millis(((cap >> 24) & 0xff).max(1) * 500);
+        let depth = NVME_QUEUE_DEPTH.min(mqes);
+        let doorbell_base = bar + NVME_REG_DOORBELL;
+
+        // 复位控制器
+        Self::disable(bar, timeout)?;
+        let admin_queue = NvmeQueue::new(0, depth, doorbell_base, doorbell_stride);
+        Self::enable(bar, &admin_queue, timeout)?;
+
+        let mut io_buf = NvmeDmaBuffer::new(NVME_MAX_TRANSFER);
+
+        // 识别控制器
+        let mut cmd = NvmeCommand {
+            opcode: NVME_ADMIN_IDENTIFY,
+            cdw10: NVME_ID_CNS_CTRL,
+            ..Default::default()
+        };
+        io_buf.set_prp(&mut cmd, NVME_PAGE_SIZE);
+        Self::poll_admin_cmd(bar, &admin_queue, cmd, timeout)?;
+        let id_ctrl = &io_buf.as_slice()[..NVME_PAGE_SIZE];
+        let model = String::from_utf8_lossy(&id_ctrl[24..64]).trim().to_string();
+        let mdts = id_ctrl[77];
+        if mdts != 0 {
+            let mpsmin = NVME_PAGE_SIZE << ((cap >> 48) & 0xf);
+            let max_transfer = mpsmin << mdts;
+            if max_transfer < NVME_MAX_TRANSFER {
+                io_buf.len = max_transfer;
+            }
+        }
+
+        let io_queue = NvmeQueue::new(1, depth, doorbell_base, doorbell_stride);
+        Self::create_io_queues(bar, &admin_queue, &io_queue, timeout)?;
+
+        info!(
+            "nvme{}: {} (NVMe {}.{}), max transfer {} bytes",
+            index,
+            model,
+            vs >> 16,
+            (vs >> 8) & 0xff,
+            io_buf.len
+        );
+
+        Ok(Self {
+            index,
+            dev_id,
+            bar,
+            timeout,
+            admin_queue,
+            io_queue,
+            io_buf: Mutex::new(io_buf),
+            irq_installed,
+            use_irq: AtomicBool::new(false),
+            dead: AtomicBool::new(false),
+        })
+    }
+
+    /// 关闭控制器（CC.EN = 0），并等待其停止工作
+    fn disable(bar: usize, timeout: Duration) -> Result<(), SystemError> {
+        let cc = Self::read_reg32(bar, NVME_REG_CC);
+        if cc & NVME_CC_ENABLE != 0 {
+            Self::write_reg32(bar, NVME_REG_CC, cc & !NVME_CC_ENABLE);
+        }
+        Self::wait_ready(bar, false, timeout)
+    }
+
+    /// 设置管理队列并启用控制器
+    fn enable(bar: usize, admin_queue: &NvmeQueue, timeout: Duration) -> Result<(), SystemError> {
+        let depth = admin_queue.depth() as u32;
+        let aqa = (depth - 1) | ((depth - 1) << 16);
+        Self::write_reg32(bar, NVME_REG_AQA, aqa);
+        Self::write_reg64(bar, NVME_REG_ASQ, admin_queue.sq_paddr() as u64);
+        Self::write_reg64(bar, NVME_REG_ACQ, admin_queue.cq_paddr() as u64);
+        Self::write_reg32(
+            bar,
+            NVME_REG_CC,
+            NVME_CC_ENABLE | NVME_CC_IOSQES | NVME_CC_IOCQES,
+        );
+        Self::wait_ready(bar, true, timeout)
+    }
+
+    /// 在控制器上创建I/O队列
+    fn create_io_queues(
+        bar: usize,
+        admin_queue: &NvmeQueue,
+        io_queue: &NvmeQueue,
+        timeout: Duration,
+    ) -> Result<(), SystemError> {
+        // 只需要一对I/O队列
+        Self::poll_admin_cmd(
+            bar,
+            admin_queue,
+            NvmeCommand {
+                opcode: NVME_ADMIN_SET_FEATURES,
+                cdw10: NVME_FEAT_NUM_QUEUES,
+                cdw11: 0,
+                ..Default::default()
+            },
+            timeout,
+        )?;
+
+        let queue_size = (io_queue.depth() as u32 - 1) << 16;
+        Self::poll_admin_cmd(
+            bar,
+            admin_queue,
+            NvmeCommand {
+                opcode: NVME_ADMIN_CREATE_CQ,
+                prp1: io_queue.cq_paddr() as u64,
+                cdw10: queue_size | io_queue.qid() as u32,
+                // 使用0号中断向量
+                cdw11: NVME_QUEUE_PHYS_CONTIG | NVME_CQ_IRQ_ENABLED,
+                ..Default::default()
+            },
+            timeout,
+        )?;
+        Self::poll_admin_cmd(
+            bar,
+            admin_queue,
+            NvmeCommand {
+                opcode: NVME_ADMIN_CREATE_SQ,
+                prp1: io_queue.sq_paddr() as u64,
+                cdw10: queue_size | io_queue.qid() as u32,
+                cdw11: NVME_QUEUE_PHYS_CONTIG | ((io_queue.qid() as u32) << 16),
+                ..Default::default()
+            },
+            timeout,
+        )?;
+        Ok(())
+    }
+
+    /// 轮询执行一条管理命令，用于初始化和复位控制器。命令超时时关闭控制器
+    fn poll_admin_cmd(
+        bar: usize,
+        admin_queue: &NvmeQueue,
+        cmd: NvmeCommand,
+        timeout: Duration,
+    ) -> Result<NvmeCompletion, SystemError> {
+        match admin_queue.submit(cmd, false, timeout) {
+            Err(SystemError::ETIMEDOUT) => {
+                error!("nvme: admin command {:#x} timed out", cmd.opcode);
+                let _ = Self::disable(bar, timeout);
+                Err(SystemError::EIO)
+            }
+            r => r,
+        }
+    }
+
+    /// 为控制器分配MSI-X中断（所有的完成队列都使用0号向量）
+    ///
+    /// 分配失败时，驱动会退化为轮询完成队列
+    fn setup_irq(device: &mut PciDeviceStructureGeneralDevice, dev_id: Arc<DeviceId>) -> bool {
+        let irq_vector = device.irq_vector_mut().unwrap();
+        irq_vector.push(NVME_IRQ_VECTOR);
+        if device.irq_init(IRQ::PCI_IRQ_MSIX).is_none() {
+            warn!("nvme: MSI-X is not supported, fall back to polling");
+            return false;
+        }
+
+        let msg = PciIrqMsg {
+            irq_common_message: IrqCommonMsg::init_from(
+                0,
+                "NVMe_IRQ".to_string(),
+                &NvmeIrqHandler,
+                dev_id,
+            ),
+            irq_specific_message: IrqSpecificMsg::msi_default(),
+        };
+        if let Err(e) = device
+            .irq_install(msg)
+            .and_then(|_| device.irq_enable(true))
+        {
+            warn!(
+                "nvme: failed to install MSI-X irq: {:?}, fall back to polling",
+                e
+            );
+            return false;
+        }
+        true
+    }
+
+    fn wait_ready(bar: usize, ready: bool, timeout: Duration) -> Result<(), SystemError> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            let csts = Self::read_reg32(bar, NVME_REG_CSTS);
+            if csts & NVME_CSTS_CFS != 0 {
+                return Err(SystemError::EIO);
+            }
+            if (csts & NVME_CSTS_RDY != 0) == ready {
+                return Ok(());
+            }
+            if Instant::now() >= deadline {
+                return Err(SystemError::ETIMEDOUT);
+            }
+            let _ = nanosleep(PosixTimeSpec::new(0, 1_000_000));
+        }
+    }
+
+    fn read_reg32(bar: usize, offset: usize) -> u32 {
+        unsafe { ((bar + offset) as *const u32).read_volatile() }
+    }
+
+    fn write_reg32(bar: usize, offset: usize, value: u32) {
+        unsafe { ((bar + offset) as *mut u32).write_volatile(value) }
+    }
+
+    fn read_reg64(bar: usize, offset: usize) -> u64 {
+        let low = Self::read_reg32(bar, offset) as u64;
+        let high = Self::read_reg32(bar, offset + 4) as u64;
+        low | (high << 32)
+    }
+
+    fn write_reg64(bar: usize, offset: usize, value: u64) {
+        Self::write_reg32(bar, offset, value as u32);
+        Self::write_reg32(bar, offset + 4, (value >> 32) as u32);
+    }
+
+    fn use_irq(&self) -> bool {
+        self.use_irq.load(Ordering::SeqCst)
+    }
+
+    /// 在`queue`上执行一条命令
+    ///
+    /// 调用者需要持有`io_buf`的锁。命令超时时复位控制器并返回EIO，复位失败时控制器不再可用
+    ///
+    /// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/drivers/nvme/host/pci.c#1289
+    fn submit(&self, queue: &NvmeQueue, cmd: NvmeCommand) -> Result<NvmeCompletion, SystemError> {
+        if self.dead.load(Ordering::SeqCst) {
+            return Err(SystemError::EIO);
+        }
+
+        match queue.submit(cmd, self.use_irq(), self.timeout) {
+            Err(SystemError::ETIMEDOUT) => {}
+            r => return r,
+        }
+
+        warn!(
+            "nvme{}: command {:#x} on queue {} timed out, resetting controller",
+            self.index,
+            cmd.opcode,
+            queue.qid()
+        );
+        if let Err(e) = self.reset() {
+            error!(
+                "nvme{}: failed to reset controller: {:?}, disabling it",
+                self.index, e
+            );
+            self.dead.store(true, Ordering::SeqCst);
+            let _ = Self::disable(self.bar, self.timeout);
+        }
+        Err(SystemError::EIO)
+    }
+
+    /// 复位控制器，并重建管理队列和I/O队列
+    ///
+    /// 复位会丢弃控制器上所有未完成的命令，因此调用者需要保证没有其它命令在执行
+    fn reset(&self) -> Result<(), SystemError> {
+        Self::disable(self.bar, self.timeout)?;
+        self.admin_queue.reset();
+        self.io_queue.reset();
+        Self::enable(self.bar, &self.admin_queue, self.timeout)?;
+        Self::create_io_queues(self.bar, &self.admin_queue, &self.io_queue, self.timeout)
+    }
+
+    /// 执行一条管理命令，数据（如果有）放在I/O中转缓冲区中
+    fn admin_cmd(
+        &self,
+        io_buf: &NvmeDmaBuffer,
+        mut cmd: NvmeCommand,
+        len: usize,
+    ) -> Result<NvmeCompletion, SystemError> {
+        if len > 0 {
+            io_buf.set_prp(&mut cmd, len);
+        }
+        self.submit(&self.admin_queue, cmd)
+    }
+
+    /// 在I/O队列上读写连续的若干个逻辑块，数据放在中转缓冲区的开头
+    fn io_cmd(
+        &self,
+        io_buf: &NvmeDmaBuffer,
+        opcode: u8,
+        nsid: u32,
+        slba: u64,
+        nlb: u32,
+        lba_shift: u8,
+    ) -> Result<(), SystemError> {
+        let mut cmd = NvmeCommand {
+            opcode,
+            nsid,
+            cdw10: slba as u32,
+            cdw11: (slba >> 32) as u32,
+            cdw12: nlb - 1,
+            ..Default::default()
+        };
+        io_buf.set_prp(&mut cmd, (nlb as usize) << lba_shift);
+        self.submit(&self.io_queue, cmd)?;
+        Ok(())
+    }
+
+    fn flush(&self, nsid: u32) -> Result<(), SystemError> {
+        let _guard = self.io_buf.lock();
+        self.submit(
+            &self.io_queue,
+            NvmeCommand {
+                opcode: NVME_CMD_FLUSH,
+                nsid,
+                ..Default::default()
+            },
+        )?;
+        Ok(())
+    }
+
+    /// 发现控制器上所有活动的namespace
+    fn scan_namespaces(self: &Arc<Self>) -> Result<Vec<Arc<NvmeNamespace>>, SystemError> {
+        let io_buf = self.io_buf.lock();
+        self.admin_cmd(
+            &io_buf,
+            NvmeCommand {
+                opcode: NVME_ADMIN_IDENTIFY,
+                cdw10: NVME_ID_CNS_NS_ACTIVE_LIST,
+                ..Default::default()
+            },
+            NVME_PAGE_SIZE,
+        )?;
+        let nsids: Vec<u32> = io_buf.as_slice()[..NVME_PAGE_SIZE]
+            .chunks_exact(4)
+            .map(|id| u32::from_le_bytes(id.try_into().unwrap()))
+            .take_while(|&id| id != 0)
+            .collect();
+
+        let mut namespaces = Vec::new();
+        for nsid in nsids {
+            self.admin_cmd(
+                &io_buf,
+                NvmeCommand {
+                    opcode: NVME_ADMIN_IDENTIFY,
+                    nsid,
+                    cdw10: NVME_ID_CNS_NS,
+                    ..Default::default()
+                },
+                NVME_PAGE_SIZE,
+            )?;
+            let id_ns = &io_buf.as_slice()[..NVME_PAGE_SIZE];
+            let nsze = u64::from_le_bytes(id_ns[0..8].try_into().unwrap());
+            let flbas = (id_ns[26] & 0xf) as usize;
+            let lba_shift = id_ns[128 + flbas * 4 + 2];
+            if nsze == 0 {
+                continue;
+            }
+            if !(9..=12).contains(&lba_shift) {
+                warn!(
+                    "nvme{}n{}: unsupported lba size 2^{}",
+                    self.index, nsid, lba_shift
+                );
+                continue;
+            }
+
+            info!(
+                "nvme{}n{}: {} blocks of {} bytes",
+                self.index,
+                nsid,
+                nsze,
+                1 << lba_shift
+            );
+            namespaces.push(NvmeNamespace::new(self.clone(), nsid, nsze, lba_shift));
+        }
+        Ok(namespaces)
+    }
+
+    fn handle_irq(&self) -> bool {
+        let admin = self.admin_queue.handle_irq();
+        let io = self.io_queue.handle_irq();
+        admin || io
+    }
+}
+
+#[derive(Debug)]
+struct NvmeIrqHandler;
+
+impl IrqHandler for NvmeIrqHandler {
+    fn handle(
+        &self,
+        _irq: IrqNumber,
+        _static_data: Option<&dyn IrqHandlerData>,
+        dev_id: Option<Arc<dyn IrqHandlerData>>,
+    ) -> Result<IrqReturn, SystemError> {
+        let dev_id = dev_id
+            .ok_or(SystemError::EINVAL)?
+            .arc_any()
+            .downcast::<DeviceId>()
+            .map_err(|_| SystemError::EINVAL)?;
+
+        let ctrl = NVME_CONTROLLERS
+            .lock_irqsave()
+            .iter()
+            .find(|ctrl| ctrl.dev_id == dev_id)
+            .cloned();
+        match ctrl {
+            Some(ctrl) if ctrl.handle_irq() => Ok(IrqReturn::Handled),
+            _ => Ok(IrqReturn::NotHandled),
+        }
+    }
+}
+
+/// 初始化所有的NVMe控制器，并注册其中的namespace
+pub fn nvme_init() -> Result<(), SystemError> {
+    static NVME_INDEX: AtomicUsize = AtomicUsize::new(0);
+
+    let mut controllers = Vec::new();
+    {
+        let mut list = PCI_DEVICE_LINKEDLIST.write();
+        let devices: Vec<&mut Box<dyn PciDeviceStructure>> =
+            get_pci_device_structure_mut(&mut list, NVME_CLASS, NVME_SUBCLASS);
+        for device in devices {
+            if device.common_header().prog_if != NVME_PROG_IF {
+                continue;
+            }
+            let Some(standard_device) = device.as_standard_device_mut() else {
+                continue;
+            };
+
+            let index = NVME_INDEX.fetch_add(1, Ordering::SeqCst);
+            let dev_id = DeviceId::new(None, Some(format!("nvme{}", index))).unwrap();
+            match NvmeController::new(standard_device, index, dev_id) {
+                Ok(ctrl) => controllers.push(Arc::new(ctrl)),
+                Err(e) => error!("nvme{}: failed to initialize controller: {:?}", index, e),
+            }
+        }
+    }
+
+    for ctrl in controllers {
+        NVME_CONTROLLERS.lock_irqsave().push(ctrl.clone());
+        // 控制器已经可以被中断处理函数找到，之后的命令都等待中断完成
+        ctrl.use_irq.store(ctrl.irq_installed, Ordering::SeqCst);
+
+        let namespaces = match ctrl.scan_namespaces() {
+            Ok(namespaces) => namespaces,
+            Err(e) => {
+                error!("nvme{}: failed to scan namespaces: {:?}", ctrl.index, e);
+                continue;
+            }
+        };
+        for ns in namespaces {
+            block_dev_manager()
+                .register(ns.clone() as Arc<dyn BlockDevice>)
+                .inspect_err(|e| error!("{}: failed to register: {:?}", ns.dev_name(), e))
+                .ok();
+        }
+    }
+
+    Ok(())
+}
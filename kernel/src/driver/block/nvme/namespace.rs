@@ -0,0 +1,317 @@
+use core::any::Any;
+
+use alloc::{
+    format,
+    string::{String, ToString},
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+use system_error::SystemError;
+
+use crate::{
+    driver::base::{
+        block::{
+            block_device::{BlockDevName, BlockDevice, BlockId, GeneralBlockRange, LBA_SIZE},
+            disk_info::Partition,
+            manager::BlockDevMeta,
+        },
+        class::Class,
+        device::{bus::Bus, driver::Driver, Device, DeviceCommonData, DeviceType, IdTable},
+        kobject::{KObjType, KObject, KObjectCommonData, KObjectState, LockedKObjectState},
+        kset::KSet,
+    },
+    filesystem::{kernfs::KernFSInode, mbr::MbrDiskPartionTable},
+    libs::{
+        rwlock::{RwLockReadGuard, RwLockWriteGuard},
+        spinlock::{SpinLock, SpinLockGuard},
+    },
+};
+
+use super::{NvmeController, NVME_CMD_READ, NVME_CMD_WRITE};
+
+/// NVMe namespace，对上层表现为以512字节为块的块设备
+#[derive(Debug)]
+#[cast_to([sync] Device)]
+pub struct NvmeNamespace {
+    blkdev_meta: BlockDevMeta,
+    ctrl: Arc<NvmeController>,
+    nsid: u32,
+    /// namespace的大小（以逻辑块为单位）
+    nsze: u64,
+    /// 逻辑块大小的log2
+    lba_shift: u8,
+    inner: SpinLock<InnerNvmeNamespace>,
+    locked_kobj_state: LockedKObjectState,
+    self_ref: Weak<Self>,
+}
+
+#[derive(Debug)]
+struct InnerNvmeNamespace {
+    device_common: DeviceCommonData,
+    kobject_common: KObjectCommonData,
+}
+
+impl NvmeNamespace {
+    pub(super) fn new(ctrl: Arc<NvmeController>, nsid: u32, nsze: u64, lba_shift: u8) -> Arc<Self> {
+        let devname = BlockDevName::new(format!("nvme{}n{}", ctrl.index, nsid), nsid as usize);
+        Arc::new_cyclic(|self_ref| Self {
+            blkdev_meta: BlockDevMeta::new(devname),
+            ctrl,
+            nsid,
+            nsze,
+            lba_shift,
+            inner: SpinLock::new(InnerNvmeNamespace {
+                device_common: DeviceCommonData::default(),
+                kobject_common: KObjectCommonData::default(),
+            }),
+            locked_kobj_state: LockedKObjectState::default(),
+            self_ref: self_ref.clone(),
+        })
+    }
+
+    fn inner(&self) -> SpinLockGuard<InnerNvmeNamespace> {
+        self.inner.lock()
+    }
+
+    /// 计算一次传输覆盖的逻辑块
+    ///
+    /// ## 返回值
+    ///
+    /// (起始逻辑块, 中转缓冲区中需要传输的字节数, 数据在缓冲区中的偏移, 本次处理的数据长度)
+    fn chunk(&self, pos: usize, remain: usize, max: usize) -> (u64, usize, usize, usize) {
+        let lba_size = 1usize << self.lba_shift;
+        let slba = (pos >> self.lba_shift) as u64;
+        let skip = pos & (lba_size - 1);
+        let bytes = (skip + remain).next_multiple_of(lba_size).min(max);
+        let len = (bytes - skip).min(remain);
+        (slba, bytes, skip, len)
+    }
+
+    fn read_bytes(&self, offset: usize, buf: &mut [u8]) -> Result<(), SystemError> {
+        let io_buf = self.ctrl.io_buf.lock();
+        let mut done = 0;
+        while done < buf.len() {
+            let (slba, bytes, skip, len) = self.chunk(offset + done, buf.len() - done, io_buf.len);
+            let nlb = (bytes >> self.lba_shift) as u32;
+            self.ctrl
+                .io_cmd(&io_buf, NVME_CMD_READ, self.nsid, slba, nlb, self.lba_shift)?;
+            buf[done..done + len].copy_from_slice(&io_buf.as_slice()[skip..skip + len]);
+            done += len;
+        }
+        Ok(())
+    }
+
+    fn write_bytes(&self, offset: usize, buf: &[u8]) -> Result<(), SystemError> {
+        let mut io_buf = self.ctrl.io_buf.lock();
+        let mut done = 0;
+        while done < buf.len() {
+            let (slba, bytes, skip, len) = self.chunk(offset + done, buf.len() - done, io_buf.len);
+            let nlb = (bytes >> self.lba_shift) as u32;
+            // 没有覆盖完整的逻辑块时，先读出原有的数据
+            if skip != 0 || len != bytes {
+                self.ctrl
+                    .io_cmd(&io_buf, NVME_CMD_READ, self.nsid, slba, nlb, self.lba_shift)?;
+            }
+            io_buf.as_mut_slice()[skip..skip + len].copy_from_slice(&buf[done..done + len]);
+            self.ctrl.io_cmd(
+                &io_buf,
+                NVME_CMD_WRITE,
+                self.nsid,
+                slba,
+                nlb,
+                self.lba_shift,
+            )?;
+            done += len;
+        }
+        Ok(())
+    }
+}
+
+impl BlockDevice for NvmeNamespace {
+    fn dev_name(&self) -> &BlockDevName {
+        &self.blkdev_meta.devname
+    }
+
+    fn blkdev_meta(&self) -> &BlockDevMeta {
+        &self.blkdev_meta
+    }
+
+    fn disk_range(&self) -> GeneralBlockRange {
+        let blocks = ((self.nsze as usize) << self.lba_shift) / LBA_SIZE;
+        GeneralBlockRange::new(0, blocks).unwrap()
+    }
+
+    fn read_at_sync(
+        &self,
+        lba_id_start: BlockId,
+        count: usize,
+        buf: &mut [u8],
+    ) -> Result<usize, SystemError> {
+        self.read_bytes(lba_id_start * LBA_SIZE, &mut buf[..count * LBA_SIZE])?;
+        Ok(count)
+    }
+
+    fn write_at_sync(
+        &self,
+        lba_id_start: BlockId,
+        count: usize,
+        buf: &[u8],
+    ) -> Result<usize, SystemError> {
+        self.write_bytes(lba_id_start * LBA_SIZE, &buf[..count * LBA_SIZE])?;
+        Ok(count)
+    }
+
+    fn sync(&self) -> Result<(), SystemError> {
+        self.ctrl.flush(self.nsid)
+    }
+
+    fn blk_size_log2(&self) -> u8 {
+        9
+    }
+
+    fn as_any_ref(&self) -> &dyn Any {
+        self
+    }
+
+    fn device(&self) -> Arc<dyn Device> {
+        self.self_ref.upgrade().unwrap()
+    }
+
+    fn block_size(&self) -> usize {
+        LBA_SIZE
+    }
+
+    fn partitions(&self) -> Vec<Arc<Partition>> {
+        let device = self.self_ref.upgrade().unwrap() as Arc<dyn BlockDevice>;
+        MbrDiskPartionTable::from_disk(device.clone())
+            .map(|mbr_table| mbr_table.partitions(Arc::downgrade(&device)))
+            .unwrap_or_default()
+    }
+}
+
+impl Device for NvmeNamespace {
+    fn dev_type(&self) -> DeviceType {
+        DeviceType::Block
+    }
+
+    fn id_table(&self) -> IdTable {
+        IdTable::new(self.dev_name().to_string(), None)
+    }
+
+    fn bus(&self) -> Option<Weak<dyn Bus>> {
+        self.inner().device_common.bus.clone()
+    }
+
+    fn set_bus(&self, bus: Option<Weak<dyn Bus>>) {
+        self.inner().device_common.bus = bus;
+    }
+
+    fn class(&self) -> Option<Arc<dyn Class>> {
+        let mut guard = self.inner();
+        let r = guard.device_common.class.clone()?.upgrade();
+        if r.is_none() {
+            guard.device_common.class = None;
+        }
+
+        return r;
+    }
+
+    fn set_class(&self, class: Option<Weak<dyn Class>>) {
+        self.inner().device_common.class = class;
+    }
+
+    fn driver(&self) -> Option<Arc<dyn Driver>> {
+        let r = self.inner().device_common.driver.clone()?.upgrade();
+        if r.is_none() {
+            self.inner().device_common.driver = None;
+        }
+
+        return r;
+    }
+
+    fn set_driver(&self, driver: Option<Weak<dyn Driver>>) {
+        self.inner().device_common.driver = driver;
+    }
+
+    fn is_dead(&self) -> bool {
+        false
+    }
+
+    fn can_match(&self) -> bool {
+        self.inner().device_common.can_match
+    }
+
+    fn set_can_match(&self, can_match: bool) {
+        self.inner().device_common.can_match = can_match;
+    }
+
+    fn state_synced(&self) -> bool {
+        true
+    }
+
+    fn dev_parent(&self) -> Option<Weak<dyn Device>> {
+        self.inner().device_common.get_parent_weak_or_clear()
+    }
+
+    fn set_dev_parent(&self, parent: Option<Weak<dyn Device>>) {
+        self.inner().device_common.parent = parent;
+    }
+}
+
+impl KObject for NvmeNamespace {
+    fn as_any_ref(&self) -> &dyn Any {
+        self
+    }
+
+    fn set_inode(&self, inode: Option<Arc<KernFSInode>>) {
+        self.inner().kobject_common.kern_inode = inode;
+    }
+
+    fn inode(&self) -> Option<Arc<KernFSInode>> {
+        self.inner().kobject_common.kern_inode.clone()
+    }
+
+    fn parent(&self) -> Option<Weak<dyn KObject>> {
+        self.inner().kobject_common.parent.clone()
+    }
+
+    fn set_parent(&self, parent: Option<Weak<dyn KObject>>) {
+        self.inner().kobject_common.parent = parent;
+    }
+
+    fn kset(&self) -> Option<Arc<KSet>> {
+        self.inner().kobject_common.kset.clone()
+    }
+
+    fn set_kset(&self, kset: Option<Arc<KSet>>) {
+        self.inner().kobject_common.kset = kset;
+    }
+
+    fn kobj_type(&self) -> Option<&'static dyn KObjType> {
+        self.inner().kobject_common.kobj_type
+    }
+
+    fn name(&self) -> String {
+        self.dev_name().to_string()
+    }
+
+    fn set_name(&self, _name: String) {
+        // do nothing
+    }
+
+    fn kobj_state(&self) -> RwLockReadGuard<KObjectState> {
+        self.locked_kobj_state.read()
+    }
+
+    fn kobj_state_mut(&self) -> RwLockWriteGuard<KObjectState> {
+        self.locked_kobj_state.write()
+    }
+
+    fn set_kobj_state(&self, state: KObjectState) {
+        *self.locked_kobj_state.write() = state;
+    }
+
+    fn set_kobj_type(&self, ktype: Option<&'static dyn KObjType>) {
+        self.inner().kobject_common.kobj_type = ktype;
+    }
+}
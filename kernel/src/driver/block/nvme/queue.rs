@@ -0,0 +1,242 @@
+use core::{
+    mem::size_of,
+    ptr::NonNull,
+    sync::atomic::{fence, Ordering},
+};
+
+use system_error::SystemError;
+
+use crate::{
+    driver::net::dma::dma_alloc,
+    libs::spinlock::SpinLock,
+    sched::completion::Completion,
+    time::{Duration, Instant},
+};
+
+use super::{NvmeCommand, NvmeCompletion, NVME_PAGE_SIZE};
+
+/// 一对提交队列和完成队列
+///
+/// 每个队列同一时刻只有一条命令在执行，调用者需要自行串行化`submit`。
+///
+/// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/drivers/nvme/host/pci.c#190
+#[derive(Debug)]
+pub(super) struct NvmeQueue {
+    qid: u16,
+    depth: u16,
+    sq: NonNull<NvmeCommand>,
+    sq_paddr: usize,
+    cq: NonNull<NvmeCompletion>,
+    cq_paddr: usize,
+    sq_doorbell: NonNull<u32>,
+    cq_doorbell: NonNull<u32>,
+    inner: SpinLock<InnerNvmeQueue>,
+    /// 完成队列有新的完成项时，由中断处理函数唤醒等待者
+    completion: Completion,
+}
+
+#[derive(Debug)]
+struct InnerNvmeQueue {
+    sq_tail: u16,
+    cq_head: u16,
+    /// 完成队列当前的相位，新的完成项的相位位与之相等
+    cq_phase: u16,
+    next_cid: u16,
+    /// 正在等待的命令
+    pending_cid: Option<u16>,
+    result: Option<NvmeCompletion>,
+}
+
+unsafe impl Send for NvmeQueue {}
+unsafe impl Sync for NvmeQueue {}
+
+impl NvmeQueue {
+    /// 分配队列所需的内存
+    ///
+    /// ## 参数
+    ///
+    /// - `qid`: 队列编号，0为管理队列
+    /// - `depth`: 队列长度
+    /// - `doorbell_base`: 门铃寄存器的起始地址（BAR0 + 0x1000）
+    /// - `doorbell_stride`: 相邻门铃寄存器的间隔（字节）
+    pub fn new(qid: u16, depth: u16, doorbell_base: usize, doorbell_stride: usize) -> Self {
+        let sq_bytes = depth as usize * size_of::<NvmeCommand>();
+        let cq_bytes = depth as usize * size_of::<NvmeCompletion>();
+        let (sq_paddr, sq) = dma_alloc(sq_bytes.div_ceil(NVME_PAGE_SIZE));
+        let (cq_paddr, cq) = dma_alloc(cq_bytes.div_ceil(NVME_PAGE_SIZE));
+
+        let sq_doorbell = doorbell_base + (2 * qid as usize) * doorbell_stride;
+        let cq_doorbell = doorbell_base + (2 * qid as usize + 1) * doorbell_stride;
+
+        Self {
+            qid,
+            depth,
+            sq: sq.cast(),
+            sq_paddr,
+            cq: cq.cast(),
+            cq_paddr,
+            sq_doorbell: NonNull::new(sq_doorbell as *mut u32).unwrap(),
+            cq_doorbell: NonNull::new(cq_doorbell as *mut u32).unwrap(),
+            inner: SpinLock::new(InnerNvmeQueue {
+                sq_tail: 0,
+                cq_head: 0,
+                cq_phase: 1,
+                next_cid: 0,
+                pending_cid: None,
+                result: None,
+            }),
+            completion: Completion::new(),
+        }
+    }
+
+    pub fn qid(&self) -> u16 {
+        self.qid
+    }
+
+    pub fn depth(&self) -> u16 {
+        self.depth
+    }
+
+    pub fn sq_paddr(&self) -> usize {
+        self.sq_paddr
+    }
+
+    pub fn cq_paddr(&self) -> usize {
+        self.cq_paddr
+    }
+
+    /// 提交一条命令并等待其完成
+    ///
+    /// ## 参数
+    ///
+    /// - `cmd`: 要提交的命令，其中的cid会被覆盖
+    /// - `use_irq`: 是否等待中断唤醒。为false时忙等完成队列
+    /// - `timeout`: 超时时间
+    ///
+    /// ## 返回值
+    ///
+    /// - `Err(SystemError::ETIMEDOUT)`: 命令超时未完成。控制器可能仍在执行它，调用者需要中止命令或复位控制器
+    /// - `Err(SystemError::EIO)`: 命令执行失败
+    pub fn submit(
+        &self,
+        mut cmd: NvmeCommand,
+        use_irq: bool,
+        timeout: Duration,
+    ) -> Result<NvmeCompletion, SystemError> {
+        let deadline = Instant::now() + timeout;
+        let mut inner = self.inner.lock_irqsave();
+        let cid = inner.next_cid;
+        inner.next_cid = inner.next_cid.wrapping_add(1);
+        inner.pending_cid = Some(cid);
+        inner.result = None;
+
+        cmd.cid = cid;
+        unsafe {
+            self.sq
+                .as_ptr()
+                .add(inner.sq_tail as usize)
+                .write_volatile(cmd)
+        };
+        inner.sq_tail = (inner.sq_tail + 1) % self.depth;
+        fence(Ordering::SeqCst);
+        unsafe {
+            self.sq_doorbell
+                .as_ptr()
+                .write_volatile(inner.sq_tail as u32)
+        };
+
+        let completion = loop {
+            self.reap(&mut inner);
+            if let Some(completion) = inner.result.take() {
+                break completion;
+            }
+            if Instant::now() >= deadline {
+                inner.pending_cid = None;
+                return Err(SystemError::ETIMEDOUT);
+            }
+
+            drop(inner);
+            if use_irq {
+                // 为了应对中断丢失，每个时钟周期也醒来检查一次
+                self.completion.wait_for_completion_timeout(1)?;
+            } else {
+                core::hint::spin_loop();
+            }
+            inner = self.inner.lock_irqsave();
+        };
+        inner.pending_cid = None;
+        drop(inner);
+
+        if completion.status_code() != 0 {
+            log::warn!(
+                "nvme: command {:#x} on queue {} failed with status {:#x}",
+                cmd.opcode,
+                self.qid,
+                completion.status_code()
+            );
+            return Err(SystemError::EIO);
+        }
+        Ok(completion)
+    }
+
+    /// 处理完成队列（在中断上下文中调用）
+    ///
+    /// ## 返回值
+    ///
+    /// 是否有新的完成项
+    pub fn handle_irq(&self) -> bool {
+        let mut inner = self.inner.lock_irqsave();
+        let handled = self.reap(&mut inner);
+        drop(inner);
+        if handled {
+            self.completion.complete();
+        }
+        handled
+    }
+
+    /// 控制器被复位后，把队列恢复到初始状态
+    ///
+    /// 调用者需要保证此时没有正在执行的命令
+    pub fn reset(&self) {
+        let mut inner = self.inner.lock_irqsave();
+        unsafe { core::ptr::write_bytes(self.cq.as_ptr(), 0, self.depth as usize) };
+        inner.sq_tail = 0;
+        inner.cq_head = 0;
+        inner.cq_phase = 1;
+        inner.pending_cid = None;
+        inner.result = None;
+    }
+
+    /// 取出完成队列中所有新的完成项，并更新门铃
+    fn reap(&self, inner: &mut InnerNvmeQueue) -> bool {
+        let mut found = false;
+        loop {
+            fence(Ordering::SeqCst);
+            let completion =
+                unsafe { self.cq.as_ptr().add(inner.cq_head as usize).read_volatile() };
+            if completion.phase() != inner.cq_phase {
+                break;
+            }
+
+            found = true;
+            if inner.pending_cid == Some(completion.cid) {
+                inner.result = Some(completion);
+            }
+
+            inner.cq_head += 1;
+            if inner.cq_head == self.depth {
+                inner.cq_head = 0;
+                inner.cq_phase ^= 1;
+            }
+        }
+
+        if found {
+            unsafe {
+                self.cq_doorbell
+                    .as_ptr()
+                    .write_volatile(inner.cq_head as u32)
+            };
+        }
+        found
+    }
+}
@@ -0,0 +1,3 @@
+pub mod random;
+pub mod virtio_console;
+pub mod virtio_rng;
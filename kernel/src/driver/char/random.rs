@@ -0,0 +1,402 @@
+//! 内核随机数子系统
+//!
+//! 熵源（CPU硬件随机数、中断时间、virtio-rng等硬件随机数发生器）先被混合进以BLAKE2s为核心的输入池，
+//! 输入池积累到足够的熵之后，再为基于ChaCha20的CRNG提供种子。CRNG采用“快速密钥擦除”的方式生成随机数，
+//! 并且会定期从输入池重新播种。
+//!
+//! 与linux 5.18之后的实现一致，`/dev/random`与`/dev/urandom`共用同一个CRNG，
+//! 区别只在于前者（以及不带`GRND_INSECURE`的getrandom）会阻塞到CRNG完成初始化。
+//!
+//! 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/drivers/char/random.c
+
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use alloc::{boxed::Box, sync::Arc};
+use log::info;
+use system_error::SystemError;
+use unified_init::macros::unified_init;
+
+use crate::{
+    arch::{
+        rand::{arch_get_random_long, arch_get_random_seed_long},
+        CurrentTimeArch,
+    },
+    init::initcall::INITCALL_CORE,
+    libs::{
+        crypto::{
+            blake2s::{blake2s, Blake2sState, BLAKE2S_HASH_SIZE},
+            chacha::{chacha20_block, chacha_init, CHACHA_BLOCK_SIZE, CHACHA_KEY_SIZE},
+        },
+        spinlock::SpinLock,
+        wait_queue::WaitQueue,
+    },
+    process::ProcessManager,
+    sched::{schedule, SchedMode},
+    time::{
+        clocksource::HZ,
+        timer::{clock, Jiffies, Timer, TimerFunction},
+        TimeArch,
+    },
+};
+
+/// 输入池能够记录的最大熵（比特）
+const POOL_BITS: u32 = (BLAKE2S_HASH_SIZE * 8) as u32;
+/// CRNG初始化所需的熵（比特）
+const POOL_READY_BITS: u32 = POOL_BITS;
+/// CRNG重新播种的间隔（jiffies）
+const CRNG_RESEED_INTERVAL: u64 = 60 * HZ;
+/// 每收集多少次中断，就把快速池混合进输入池并记1比特熵
+const FAST_POOL_MIX_COUNT: u32 = 64;
+/// 判断周期计数器是否在前进时，最多连续读取的次数
+const CYCLE_COUNTER_PROBES: usize = 1024;
+
+/// 输入池
+struct InputPool {
+    hash: Blake2sState,
+    /// 已经记入的熵（比特），最多为`POOL_BITS`
+    init_bits: u32,
+}
+
+/// 基础CRNG
+struct BaseCrng {
+    key: [u8; CHACHA_KEY_SIZE],
+    /// 上一次播种的时间（jiffies）
+    birth: u64,
+}
+
+/// 收集中断时间的快速池
+///
+/// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/drivers/char/random.c#1000
+struct FastPool {
+    pool: [u32; 4],
+    count: u32,
+}
+
+static INPUT_POOL: SpinLock<InputPool> = SpinLock::new(InputPool {
+    hash: Blake2sState::new(BLAKE2S_HASH_SIZE),
+    init_bits: 0,
+});
+
+static BASE_CRNG: SpinLock<BaseCrng> = SpinLock::new(BaseCrng {
+    key: [0; CHACHA_KEY_SIZE],
+    birth: 0,
+});
+
+static FAST_POOL: SpinLock<FastPool> = SpinLock::new(FastPool {
+    pool: [0; 4],
+    count: 0,
+});
+
+/// CRNG是否已经获得了足够的熵
+static CRNG_READY: AtomicBool = AtomicBool::new(false);
+/// 等待CRNG初始化完成的进程
+static CRNG_INIT_WAIT: WaitQueue = WaitQueue::default();
+
+/// CRNG是否已经完成初始化
+#[inline]
+pub fn crng_ready() -> bool {
+    CRNG_READY.load(Ordering::Acquire)
+}
+
+/// 把数据混合进输入池
+fn mix_pool_bytes(buf: &[u8]) {
+    INPUT_POOL.lock_irqsave().hash.update(buf);
+}
+
+/// 从输入池中提取一个种子，并为输入池换上新的密钥
+///
+/// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/drivers/char/random.c#651
+fn extract_entropy(out: &mut [u8; BLAKE2S_HASH_SIZE]) {
+    // block = 硬件随机数 || 计数器
+    let mut block = [0u8; BLAKE2S_HASH_SIZE + 8];
+    for chunk in block[..BLAKE2S_HASH_SIZE].chunks_exact_mut(8) {
+        let v = arch_get_random_seed_long()
+            .or_else(arch_get_random_long)
+            .unwrap_or(CurrentTimeArch::get_cycles() as u64);
+        chunk.copy_from_slice(&v.to_le_bytes());
+    }
+
+    let mut seed = [0u8; BLAKE2S_HASH_SIZE];
+    let mut next_key = [0u8; BLAKE2S_HASH_SIZE];
+    {
+        let mut pool = INPUT_POOL.lock_irqsave();
+        let hash = core::mem::replace(&mut pool.hash, Blake2sState::new(BLAKE2S_HASH_SIZE));
+        hash.finalize(&mut seed);
+
+        // next_key = HASHPRF(seed, block || 0)
+        blake2s(&mut next_key, &block, &seed);
+        pool.hash = Blake2sState::new_keyed(BLAKE2S_HASH_SIZE, &next_key);
+    }
+
+    // out = HASHPRF(seed, block || 1)
+    block[BLAKE2S_HASH_SIZE..].copy_from_slice(&1u64.to_le_bytes());
+    blake2s(out, &block, &seed);
+
+    seed.fill(0);
+    next_key.fill(0);
+}
+
+/// 从输入池为CRNG重新播种
+///
+/// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/drivers/char/random.c#254
+fn crng_reseed() {
+    let mut key = [0u8; CHACHA_KEY_SIZE];
+    extract_entropy(&mut key);
+
+    let mut crng = BASE_CRNG.lock_irqsave();
+    // 保留旧密钥的影响，避免一次不充分的播种降低CRNG的强度
+    let mut input = [0u8; CHACHA_KEY_SIZE * 2];
+    input[..CHACHA_KEY_SIZE].copy_from_slice(&crng.key);
+    input[CHACHA_KEY_SIZE..].copy_from_slice(&key);
+    blake2s(&mut crng.key, &input, &[]);
+    crng.birth = clock();
+    drop(crng);
+
+    input.fill(0);
+    key.fill(0);
+}
+
+/// 为输入池记入熵，熵足够时完成CRNG的初始化
+///
+/// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/drivers/char/random.c#681
+fn credit_init_bits(bits: u32) {
+    if bits == 0 || crng_ready() {
+        return;
+    }
+
+    let ready = {
+        let mut pool = INPUT_POOL.lock_irqsave();
+        let orig = pool.init_bits;
+        pool.init_bits = core::cmp::min(POOL_BITS, orig.saturating_add(bits));
+        orig < POOL_READY_BITS && pool.init_bits >= POOL_READY_BITS
+    };
+
+    if ready {
+        crng_reseed();
+        CRNG_READY.store(true, Ordering::Release);
+        CRNG_INIT_WAIT.wakeup_all(None);
+        info!("random: crng init done");
+    }
+}
+
+/// 生成随机字节（不会阻塞）
+///
+/// 在CRNG完成初始化之前，得到的随机数可能不够安全。
+/// 需要密码学安全的随机数时，应当先调用`wait_for_random_bytes`。
+///
+/// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/drivers/char/random.c#394
+pub fn get_random_bytes(buf: &mut [u8]) {
+    if buf.is_empty() {
+        return;
+    }
+
+    if crng_ready() {
+        let birth = BASE_CRNG.lock_irqsave().birth;
+        if clock().wrapping_sub(birth) > CRNG_RESEED_INTERVAL {
+            crng_reseed();
+        }
+    }
+
+    // 快速密钥擦除：用当前密钥生成的第一个分组替换密钥，第二半作为本次输出使用的密钥
+    let mut block = [0u8; CHACHA_BLOCK_SIZE];
+    let mut chacha_key = [0u8; CHACHA_KEY_SIZE];
+    {
+        let mut crng = BASE_CRNG.lock_irqsave();
+        let mut state = chacha_init(&crng.key, 0, 0);
+        chacha20_block(&mut state, &mut block);
+        crng.key.copy_from_slice(&block[..CHACHA_KEY_SIZE]);
+        chacha_key.copy_from_slice(&block[CHACHA_KEY_SIZE..]);
+    }
+
+    let mut state = chacha_init(&chacha_key, 0, 0);
+    for chunk in buf.chunks_mut(CHACHA_BLOCK_SIZE) {
+        chacha20_block(&mut state, &mut block);
+        chunk.copy_from_slice(&block[..chunk.len()]);
+    }
+
+    block.fill(0);
+    chacha_key.fill(0);
+    state.fill(0);
+}
+
+/// 每个jiffy触发一次的定时器，记录触发时的周期计数器并记入1比特熵
+#[derive(Debug)]
+struct EntropyTimerFunc;
+
+impl TimerFunction for EntropyTimerFunc {
+    fn run(&mut self) -> Result<(), SystemError> {
+        let cycles = CurrentTimeArch::get_cycles() as u64;
+        mix_pool_bytes(&cycles.to_le_bytes());
+        credit_init_bits(1);
+        Ok(())
+    }
+}
+
+/// 利用周期计数器与时钟中断之间的抖动产生熵
+///
+/// 没有硬件随机数，中断又很少的时候（例如riscv64上没有virtio-rng的虚拟机），
+/// 仅靠中断时间需要很长时间才能完成CRNG的初始化。这里不断调度并把周期计数器混合进输入池，
+/// 同时由定时器在每个jiffy记入1比特熵，两者之间的相对时序受缓存、调度与中断的影响而难以预测。
+///
+/// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/drivers/char/random.c#1283
+fn try_to_generate_entropy() {
+    // 周期计数器不前进（或者没有可用的周期计数器）时，无法产生熵。
+    // 一些架构（例如riscv64的time CSR）的计数器频率较低，因此多读几次再下结论
+    let mut entropy = CurrentTimeArch::get_cycles() as u64;
+    if (0..CYCLE_COUNTER_PROBES).all(|_| CurrentTimeArch::get_cycles() as u64 == entropy) {
+        return;
+    }
+
+    let pcb = ProcessManager::current_pcb();
+    let mut timer: Option<Arc<Timer>> = None;
+    while !crng_ready() && !pcb.has_pending_signal() {
+        if timer.as_ref().map_or(true, |timer| timer.timeout()) {
+            let new_timer = Timer::new(Box::new(EntropyTimerFunc), Jiffies::new(1).timer_jiffies());
+            new_timer.activate();
+            timer = Some(new_timer);
+        }
+        mix_pool_bytes(&entropy.to_le_bytes());
+        schedule(SchedMode::SM_NONE);
+        entropy = CurrentTimeArch::get_cycles() as u64;
+    }
+
+    if let Some(timer) = timer {
+        if !timer.timeout() {
+            timer.cancel();
+        }
+    }
+}
+
+/// 等待CRNG完成初始化
+///
+/// 等待期间通过`try_to_generate_entropy`主动产生熵，因此即使没有硬件随机数发生器，也只需要等待几秒。
+///
+/// ## 返回值
+///
+/// - `Ok(())`: CRNG已经完成初始化
+/// - `Err(SystemError::ERESTARTSYS)`: 等待过程被信号打断
+pub fn wait_for_random_bytes() -> Result<(), SystemError> {
+    if crng_ready() {
+        return Ok(());
+    }
+
+    try_to_generate_entropy();
+    wq_wait_event_interruptible!(CRNG_INIT_WAIT, crng_ready(), {})
+        .map_err(|_| SystemError::ERESTARTSYS)
+}
+
+/// 混合设备相关的数据（例如MAC地址、序列号），不记入熵
+///
+/// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/drivers/char/random.c#833
+pub fn add_device_randomness(buf: &[u8]) {
+    let cycles = CurrentTimeArch::get_cycles() as u64;
+    let mut pool = INPUT_POOL.lock_irqsave();
+    pool.hash.update(&cycles.to_le_bytes());
+    pool.hash.update(buf);
+}
+
+/// 混合来自硬件随机数发生器的数据，并记入其声称的熵
+///
+/// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/drivers/char/random.c#887
+pub fn add_hwgenerator_randomness(buf: &[u8], entropy_bits: u32) {
+    mix_pool_bytes(buf);
+    credit_init_bits(entropy_bits);
+}
+
+/// 混合用户写入`/dev/random`、`/dev/urandom`的数据，不记入熵
+pub fn write_pool_user(buf: &[u8]) {
+    mix_pool_bytes(buf);
+}
+
+#[inline(always)]
+fn hsiphash_permute(s: &mut [u32; 4]) {
+    s[0] = s[0].wrapping_add(s[1]);
+    s[1] = s[1].rotate_left(5);
+    s[1] ^= s[0];
+    s[0] = s[0].rotate_left(16);
+    s[2] = s[2].wrapping_add(s[3]);
+    s[3] = s[3].rotate_left(8);
+    s[3] ^= s[2];
+    s[0] = s[0].wrapping_add(s[3]);
+    s[3] = s[3].rotate_left(7);
+    s[3] ^= s[0];
+    s[2] = s[2].wrapping_add(s[1]);
+    s[1] = s[1].rotate_left(13);
+    s[1] ^= s[2];
+    s[2] = s[2].rotate_left(16);
+}
+
+/// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/drivers/char/random.c#1017
+fn fast_mix(s: &mut [u32; 4], v1: u32, v2: u32) {
+    s[3] ^= v1;
+    hsiphash_permute(s);
+    s[0] ^= v1;
+    s[3] ^= v2;
+    hsiphash_permute(s);
+    s[0] ^= v2;
+}
+
+/// 收集中断到来的时间作为熵源，在中断上下文中调用
+///
+/// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/drivers/char/random.c#1066
+pub fn add_interrupt_randomness(irq: u32) {
+    // 其他CPU正在使用快速池时，直接放弃这一次的采样
+    let Ok(mut fast_pool) = FAST_POOL.try_lock_irqsave() else {
+        return;
+    };
+
+    let cycles = CurrentTimeArch::get_cycles() as u64;
+    fast_mix(
+        &mut fast_pool.pool,
+        cycles as u32 ^ irq,
+        (cycles >> 32) as u32 ^ clock() as u32,
+    );
+    fast_pool.count += 1;
+    if fast_pool.count < FAST_POOL_MIX_COUNT {
+        return;
+    }
+
+    let Ok(mut input_pool) = INPUT_POOL.try_lock_irqsave() else {
+        return;
+    };
+    let mut bytes = [0u8; 16];
+    for (chunk, v) in bytes.chunks_exact_mut(4).zip(fast_pool.pool.iter()) {
+        chunk.copy_from_slice(&v.to_le_bytes());
+    }
+    input_pool.hash.update(&bytes);
+    fast_pool.count = 0;
+    drop(input_pool);
+    drop(fast_pool);
+
+    // 保守地认为64次中断只提供1比特的熵
+    credit_init_bits(1);
+}
+
+/// 使用CPU的硬件随机数、时间戳初始化随机数子系统
+///
+/// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/drivers/char/random.c#806
+#[unified_init(INITCALL_CORE)]
+fn random_init() -> Result<(), SystemError> {
+    let mut arch_bits = 0;
+    let mut buf = [0u8; BLAKE2S_HASH_SIZE];
+    for chunk in buf.chunks_exact_mut(8) {
+        let v = match arch_get_random_seed_long().or_else(arch_get_random_long) {
+            Some(v) => {
+                arch_bits += 64;
+                v
+            }
+            None => CurrentTimeArch::get_cycles() as u64,
+        };
+        chunk.copy_from_slice(&v.to_le_bytes());
+    }
+
+    add_device_randomness(&buf);
+    add_device_randomness(&clock().to_le_bytes());
+    crng_reseed();
+    buf.fill(0);
+
+    // 与linux默认的CONFIG_RANDOM_TRUST_CPU一致，信任CPU提供的硬件随机数
+    credit_init_bits(arch_bits);
+
+    return Ok(());
+}
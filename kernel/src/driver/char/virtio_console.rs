@@ -0,0 +1,1148 @@
+//! virtio-console驱动
+//!
+//! 每个virtio console端口都会被绑定到一个hvc终端（`/dev/hvc0`, `/dev/hvc1`...），
+//! 其中hvc0同时会被注册为内核消息控制台，用于输出内核日志。
+//!
+//! 设备支持`VIRTIO_CONSOLE_F_MULTIPORT`特性时，一个virtio-serial控制器可以带有多个端口，
+//! 设备通过控制队列通知端口的添加和删除。只有控制台端口（virtconsole）会被绑定到hvc终端，
+//! 普通端口（virtserialport）目前没有对应的`/dev/vport*`设备节点，发往它们的数据会被丢弃。
+//!
+//! 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/drivers/char/virtio_console.c
+
+use core::{any::Any, fmt::Debug, ptr::addr_of};
+
+use alloc::{
+    boxed::Box,
+    collections::{BTreeMap, LinkedList},
+    string::{String, ToString},
+    sync::{Arc, Weak},
+    vec,
+    vec::Vec,
+};
+use log::{error, warn};
+use system_error::SystemError;
+use unified_init::macros::unified_init;
+use virtio_drivers::transport::Transport;
+
+use crate::{
+    driver::{
+        base::{
+            class::Class,
+            device::{
+                bus::Bus,
+                device_number::{DeviceNumber, Major},
+                device_register,
+                driver::{Driver, DriverCommonData},
+                Device, DeviceCommonData, DeviceId, DeviceType, IdTable,
+            },
+            kobject::{KObjType, KObject, KObjectCommonData, KObjectState, LockedKObjectState},
+            kset::KSet,
+        },
+        tty::{
+            console::{register_console, unregister_console, Console},
+            termios::{WindowSize, TTY_STD_TERMIOS},
+            tty_core::{TtyCore, TtyCoreData},
+            tty_device::{TtyDevice, TtyType},
+            tty_driver::{TtyDriver, TtyDriverManager, TtyDriverType, TtyOperation},
+        },
+        virtio::{
+            queue::{virtio_begin_init, SplitVirtQueue, VIRTQUEUE_REQUEST_TIMEOUT},
+            sysfs::{virtio_bus, virtio_device_manager, virtio_driver_manager},
+            transport::VirtIOTransport,
+            VirtIODevice, VirtIODeviceIndex, VirtIODriver, VirtIODriverCommonData, VirtioDeviceId,
+            VIRTIO_VENDOR_ID,
+        },
+    },
+    exception::{irqdesc::IrqReturn, IrqNumber},
+    filesystem::{
+        devfs::{devfs_register, devfs_unregister},
+        kernfs::KernFSInode,
+    },
+    init::initcall::{INITCALL_DEVICE, INITCALL_POSTCORE},
+    libs::{
+        lazy_init::Lazy,
+        rwlock::{RwLockReadGuard, RwLockWriteGuard},
+        spinlock::{SpinLock, SpinLockGuard},
+    },
+    process::kthread::{KernelThreadClosure, KernelThreadMechanism},
+    sched::completion::Completion,
+    time::Instant,
+};
+
+const VIRTIO_CONSOLE_BASENAME: &str = "virtio_console";
+
+/// 设备支持多个端口，端口的添加和删除通过控制队列通知驱动
+///
+/// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/include/uapi/linux/virtio_console.h
+const VIRTIO_CONSOLE_F_MULTIPORT: u64 = 1 << 1;
+
+/// 控制消息的事件类型
+///
+/// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/include/uapi/linux/virtio_console.h
+const VIRTIO_CONSOLE_DEVICE_READY: u16 = 0;
+const VIRTIO_CONSOLE_DEVICE_ADD: u16 = 1;
+const VIRTIO_CONSOLE_DEVICE_REMOVE: u16 = 2;
+const VIRTIO_CONSOLE_PORT_READY: u16 = 3;
+const VIRTIO_CONSOLE_CONSOLE_PORT: u16 = 4;
+const VIRTIO_CONSOLE_PORT_OPEN: u16 = 6;
+
+/// 控制队列的编号。port0使用0、1号队列，其余端口的队列排在控制队列之后
+const VIRTIO_CONSOLE_CONTROL_RX_QUEUE: u16 = 2;
+const VIRTIO_CONSOLE_CONTROL_TX_QUEUE: u16 = 3;
+
+const VIRTIO_CONSOLE_QUEUE_SIZE: u16 = 8;
+
+/// 每个接收、发送缓冲区的大小
+const VIRTIO_CONSOLE_BUF_SIZE: usize = 256;
+
+/// hvc终端的最大数量
+///
+/// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/drivers/tty/hvc/hvc_console.c#52
+const HVC_MAX_LINES: usize = 8;
+
+/// 每个设备最多使用的端口数量
+const VIRTIO_CONSOLE_MAX_PORTS: u32 = HVC_MAX_LINES as u32;
+
+static VIRTIO_CONSOLE_DRIVER: Lazy<Arc<VirtIOConsoleDriver>> = Lazy::new();
+static HVC_TTY_DRIVER: Lazy<Arc<TtyDriver>> = Lazy::new();
+
+/// 中断处理函数通过它唤醒khvcd线程，处理接收到的数据和控制消息
+///
+/// 接收的数据需要经过线路规程处理，端口的添加还需要注册设备，这些都可能睡眠，不能在中断上下文中完成
+static HVC_WORK: Completion = Completion::new();
+
+/// 每个hvc终端绑定的virtio console端口
+static HVC_LINES: SpinLock<[Option<Arc<HvcLine>>; HVC_MAX_LINES]> =
+    SpinLock::new([const { None }; HVC_MAX_LINES]);
+
+/// 每个hvc终端的tty设备
+///
+/// 设备模型目前不支持删除设备，因此tty设备在终端第一次被绑定时创建，之后一直保留，
+/// 端口被删除时只删除设备节点
+static HVC_TTY_DEVICES: SpinLock<[Option<Arc<TtyDevice>>; HVC_MAX_LINES]> =
+    SpinLock::new([const { None }; HVC_MAX_LINES]);
+
+#[inline(always)]
+fn virtio_console_driver() -> Arc<VirtIOConsoleDriver> {
+    VIRTIO_CONSOLE_DRIVER.get().clone()
+}
+
+#[inline(always)]
+fn hvc_tty_driver() -> Arc<TtyDriver> {
+    HVC_TTY_DRIVER.get().clone()
+}
+
+/// 获取hvc终端绑定的端口
+fn hvc_line(index: usize) -> Option<Arc<HvcLine>> {
+    HVC_LINES.lock_irqsave().get(index)?.clone()
+}
+
+pub fn virtio_console(
+    transport: VirtIOTransport,
+    dev_id: Arc<DeviceId>,
+    dev_parent: Option<Arc<dyn Device>>,
+) {
+    let device = VirtIOConsoleDevice::new(transport, dev_id);
+    if let Some(device) = device {
+        if let Some(dev_parent) = dev_parent {
+            device.set_dev_parent(Some(Arc::downgrade(&dev_parent)));
+        }
+        virtio_device_manager()
+            .device_add(device.clone() as Arc<dyn VirtIODevice>)
+            .expect("Add virtio console failed");
+        // 中断在device_add的最后才被注册，在此之前设备发来的控制消息和数据不会产生中断，
+        // 因此这里主动让khvcd处理一次
+        HVC_WORK.complete();
+    }
+}
+
+/// 控制消息
+///
+/// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/include/uapi/linux/virtio_console.h
+#[derive(Debug, Clone, Copy)]
+struct VirtioConsoleControl {
+    /// 端口号
+    id: u32,
+    event: u16,
+    value: u16,
+}
+
+impl VirtioConsoleControl {
+    const SIZE: usize = 8;
+
+    fn to_bytes(self) -> [u8; Self::SIZE] {
+        let mut buf = [0u8; Self::SIZE];
+        buf[0..4].copy_from_slice(&self.id.to_le_bytes());
+        buf[4..6].copy_from_slice(&self.event.to_le_bytes());
+        buf[6..8].copy_from_slice(&self.value.to_le_bytes());
+        buf
+    }
+
+    fn from_bytes(buf: &[u8]) -> Option<Self> {
+        if buf.len() < Self::SIZE {
+            return None;
+        }
+        Some(Self {
+            id: u32::from_le_bytes(buf[0..4].try_into().unwrap()),
+            event: u16::from_le_bytes(buf[4..6].try_into().unwrap()),
+            value: u16::from_le_bytes(buf[6..8].try_into().unwrap()),
+        })
+    }
+}
+
+/// 设备的配置空间
+#[allow(dead_code)]
+#[repr(C)]
+struct VirtioConsoleConfig {
+    cols: u16,
+    rows: u16,
+    max_nr_ports: u32,
+    emerg_wr: u32,
+}
+
+/// 端口的接收队列与发送队列的编号
+fn port_queue_index(id: u32) -> (u16, u16) {
+    if id == 0 {
+        (0, 1)
+    } else {
+        let rx = (id * 2 + 2) as u16;
+        (rx, rx + 1)
+    }
+}
+
+/// 接收队列，队列中总是放满了空闲的缓冲区
+struct VirtIOConsoleRecvQueue {
+    queue: SplitVirtQueue,
+    /// 已经放入队列的缓冲区（token -> 缓冲区）
+    bufs: BTreeMap<u16, Box<[u8]>>,
+}
+
+impl VirtIOConsoleRecvQueue {
+    fn new(transport: &mut VirtIOTransport, index: u16) -> Result<Self, SystemError> {
+        let mut queue = Self {
+            queue: SplitVirtQueue::new(transport, index, VIRTIO_CONSOLE_QUEUE_SIZE)?,
+            bufs: BTreeMap::new(),
+        };
+        queue.fill()?;
+        Ok(queue)
+    }
+
+    /// 把队列填满空闲的缓冲区
+    fn fill(&mut self) -> Result<(), SystemError> {
+        while self.bufs.len() < self.queue.size() as usize {
+            let mut buf = vec![0u8; VIRTIO_CONSOLE_BUF_SIZE].into_boxed_slice();
+            let token = unsafe { self.queue.add(&[], &mut [&mut buf[..]])? };
+            self.bufs.insert(token, buf);
+        }
+        Ok(())
+    }
+
+    /// 取出一个设备写入了数据的缓冲区，把数据复制到`out`中，然后把缓冲区放回队列
+    ///
+    /// ## 返回值
+    ///
+    /// 复制的字节数，队列中没有数据时返回`None`
+    fn pop(&mut self, transport: &mut VirtIOTransport, out: &mut [u8]) -> Option<usize> {
+        loop {
+            let (token, len) = self.queue.pop_used()?;
+            let Some(mut buf) = self.bufs.remove(&token) else {
+                continue;
+            };
+            let len = core::cmp::min(len as usize, out.len());
+            out[..len].copy_from_slice(&buf[..len]);
+
+            if let Ok(token) = unsafe { self.queue.add(&[], &mut [&mut buf[..]]) } {
+                self.bufs.insert(token, buf);
+                self.queue.notify(transport);
+            }
+            return Some(len);
+        }
+    }
+}
+
+/// 发送队列
+struct VirtIOConsoleSendQueue {
+    queue: SplitVirtQueue,
+    /// 发送缓冲区只有一个，因此队列中同时只会有一个请求
+    buf: Box<[u8]>,
+}
+
+impl VirtIOConsoleSendQueue {
+    fn new(transport: &mut VirtIOTransport, index: u16) -> Result<Self, SystemError> {
+        Ok(Self {
+            queue: SplitVirtQueue::new(transport, index, VIRTIO_CONSOLE_QUEUE_SIZE)?,
+            buf: vec![0u8; VIRTIO_CONSOLE_BUF_SIZE].into_boxed_slice(),
+        })
+    }
+
+    /// 发送数据，并等待设备取走
+    ///
+    /// 内核消息可能在不能睡眠的上下文中输出，因此与Linux一样忙等待设备处理完发送队列。
+    /// 设备超时未处理时返回错误，缓冲区之后可能会被覆盖，但这只影响输出的内容
+    ///
+    /// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/drivers/char/virtio_console.c#__send_to_port
+    ///
+    /// ## 返回值
+    ///
+    /// 发送的字节数
+    fn send(&mut self, transport: &mut VirtIOTransport, data: &[u8]) -> Result<usize, SystemError> {
+        let len = core::cmp::min(data.len(), self.buf.len());
+        self.buf[..len].copy_from_slice(&data[..len]);
+        let token = unsafe { self.queue.add(&[&self.buf[..len]], &mut [])? };
+        self.queue.notify(transport);
+
+        let deadline = Instant::now() + VIRTQUEUE_REQUEST_TIMEOUT;
+        loop {
+            if let Some((t, _)) = self.queue.pop_used() {
+                if t == token {
+                    return Ok(len);
+                }
+                continue;
+            }
+            if Instant::now() >= deadline {
+                return Err(SystemError::ETIMEDOUT);
+            }
+            core::hint::spin_loop();
+        }
+    }
+}
+
+/// virtio console端口
+struct VirtIOConsolePort {
+    rx: VirtIOConsoleRecvQueue,
+    tx: VirtIOConsoleSendQueue,
+    /// 设备是否已经添加了该端口
+    present: bool,
+    /// 绑定的hvc终端
+    hvc_index: Option<usize>,
+}
+
+/// virtio console device
+#[derive(Debug)]
+#[cast_to([sync] VirtIODevice)]
+#[cast_to([sync] Device)]
+pub struct VirtIOConsoleDevice {
+    dev_id: Arc<DeviceId>,
+    inner: SpinLock<InnerVirtIOConsoleDevice>,
+    locked_kobj_state: LockedKObjectState,
+}
+
+unsafe impl Send for VirtIOConsoleDevice {}
+unsafe impl Sync for VirtIOConsoleDevice {}
+
+impl VirtIOConsoleDevice {
+    pub fn new(mut transport: VirtIOTransport, dev_id: Arc<DeviceId>) -> Option<Arc<Self>> {
+        let irq = transport.irq().map(|irq| IrqNumber::new(irq.data()));
+        let r = virtio_begin_init(&mut transport, VIRTIO_CONSOLE_F_MULTIPORT)
+            .and_then(|features| Self::init_queues(&mut transport, features));
+        let (control, ports) = match r {
+            Ok(r) => r,
+            Err(e) => {
+                error!("VirtIOConsoleDevice '{dev_id:?}' create failed: {:?}", e);
+                return None;
+            }
+        };
+        transport.finish_init();
+
+        let dev = Arc::new(Self {
+            dev_id,
+            locked_kobj_state: LockedKObjectState::default(),
+            inner: SpinLock::new(InnerVirtIOConsoleDevice {
+                transport,
+                control,
+                ports,
+                name: None,
+                virtio_index: None,
+                device_common: DeviceCommonData::default(),
+                kobject_common: KObjectCommonData::default(),
+                irq,
+            }),
+        });
+
+        Some(dev)
+    }
+
+    /// 创建控制队列与各个端口的队列
+    ///
+    /// 与Linux一样，所有端口的队列都在初始化时创建，端口被添加、删除时只修改端口的状态
+    #[allow(clippy::type_complexity)]
+    fn init_queues(
+        transport: &mut VirtIOTransport,
+        features: u64,
+    ) -> Result<
+        (
+            Option<(VirtIOConsoleRecvQueue, VirtIOConsoleSendQueue)>,
+            Vec<VirtIOConsolePort>,
+        ),
+        SystemError,
+    > {
+        let (control, nr_ports) = if features & VIRTIO_CONSOLE_F_MULTIPORT != 0 {
+            let config = transport
+                .config_space::<VirtioConsoleConfig>()
+                .map_err(|_| SystemError::EINVAL)?;
+            let max_nr_ports = unsafe { addr_of!((*config.as_ptr()).max_nr_ports).read_volatile() };
+            let control = (
+                VirtIOConsoleRecvQueue::new(transport, VIRTIO_CONSOLE_CONTROL_RX_QUEUE)?,
+                VirtIOConsoleSendQueue::new(transport, VIRTIO_CONSOLE_CONTROL_TX_QUEUE)?,
+            );
+            (
+                Some(control),
+                core::cmp::min(max_nr_ports, VIRTIO_CONSOLE_MAX_PORTS),
+            )
+        } else {
+            (None, 1)
+        };
+
+        // 没有多端口特性时，port0总是存在
+        let present = control.is_none();
+        let ports = (0..nr_ports)
+            .map(|id| {
+                let (rx, tx) = port_queue_index(id);
+                Ok(VirtIOConsolePort {
+                    rx: VirtIOConsoleRecvQueue::new(transport, rx)?,
+                    tx: VirtIOConsoleSendQueue::new(transport, tx)?,
+                    present,
+                    hvc_index: None,
+                })
+            })
+            .collect::<Result<Vec<_>, SystemError>>()?;
+
+        Ok((control, ports))
+    }
+
+    fn inner(&self) -> SpinLockGuard<InnerVirtIOConsoleDevice> {
+        self.inner.lock_irqsave()
+    }
+
+    /// 是否协商了多端口特性
+    fn multiport(&self) -> bool {
+        self.inner().control.is_some()
+    }
+
+    /// 把数据写入端口的发送队列
+    fn send_bytes(
+        inner: &mut InnerVirtIOConsoleDevice,
+        port: u32,
+        buf: &[u8],
+    ) -> Result<usize, SystemError> {
+        let port = inner
+            .ports
+            .get_mut(port as usize)
+            .filter(|port| port.present)
+            .ok_or(SystemError::ENODEV)?;
+
+        let mut sent = 0;
+        while sent < buf.len() {
+            match port.tx.send(&mut inner.transport, &buf[sent..]) {
+                Ok(n) => sent += n,
+                Err(e) if sent == 0 => {
+                    error!("VirtIOConsoleDevice send failed: {:?}", e);
+                    return Err(SystemError::EIO);
+                }
+                Err(_) => break,
+            }
+        }
+        Ok(sent)
+    }
+
+    fn write_bytes(&self, port: u32, buf: &[u8]) -> Result<usize, SystemError> {
+        Self::send_bytes(&mut self.inner(), port, buf)
+    }
+
+    /// 通过控制队列向设备发送控制消息
+    fn send_control(&self, id: u32, event: u16, value: u16) {
+        let msg = VirtioConsoleControl { id, event, value }.to_bytes();
+        let mut inner = self.inner();
+        let inner = &mut *inner;
+        if let Some((_, tx)) = inner.control.as_mut() {
+            if let Err(e) = tx.send(&mut inner.transport, &msg) {
+                warn!(
+                    "virtio-console: failed to send control event {event} for port {id}: {:?}",
+                    e
+                );
+            }
+        }
+    }
+
+    /// 从各个端口的接收队列中取出数据，并交给对应的hvc终端
+    ///
+    /// 数据在释放设备锁之后才交给终端，因为线路规程可能需要回显
+    fn poll_input(&self) {
+        let nr_ports = self.inner().ports.len();
+        let mut buf = [0u8; VIRTIO_CONSOLE_BUF_SIZE];
+        for id in 0..nr_ports {
+            loop {
+                let (len, index) = {
+                    let mut inner = self.inner();
+                    let inner = &mut *inner;
+                    let port = &mut inner.ports[id];
+                    let Some(len) = port.rx.pop(&mut inner.transport, &mut buf) else {
+                        break;
+                    };
+                    (len, port.hvc_index)
+                };
+
+                // 端口没有绑定终端、或者终端还没有被打开时，直接丢弃输入
+                let tty = index.and_then(|index| hvc_tty_driver().ttys().get(&index).cloned());
+                if let Some(port) = tty.and_then(|tty| tty.core().port()) {
+                    port.receive_buf(&buf[..len], &[], len).ok();
+                }
+            }
+        }
+    }
+
+    /// 处理设备通过控制队列发来的消息
+    fn poll_control(self: &Arc<Self>) {
+        let mut buf = [0u8; VIRTIO_CONSOLE_BUF_SIZE];
+        loop {
+            let len = {
+                let mut inner = self.inner();
+                let inner = &mut *inner;
+                let Some((rx, _)) = inner.control.as_mut() else {
+                    return;
+                };
+                let Some(len) = rx.pop(&mut inner.transport, &mut buf) else {
+                    return;
+                };
+                len
+            };
+
+            match VirtioConsoleControl::from_bytes(&buf[..len]) {
+                Some(msg) => self.handle_control(msg),
+                None => warn!("virtio-console: dropped a malformed control message"),
+            }
+        }
+    }
+
+    /// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/drivers/char/virtio_console.c#handle_control_message
+    fn handle_control(self: &Arc<Self>, msg: VirtioConsoleControl) {
+        let id = msg.id;
+        match msg.event {
+            VIRTIO_CONSOLE_DEVICE_ADD => {
+                let added = match self.inner().ports.get_mut(id as usize) {
+                    Some(port) => {
+                        port.present = true;
+                        true
+                    }
+                    None => false,
+                };
+                if !added {
+                    warn!("virtio-console: port {id} exceeds the number of supported ports");
+                    return;
+                }
+                self.send_control(id, VIRTIO_CONSOLE_PORT_READY, 1);
+            }
+            VIRTIO_CONSOLE_DEVICE_REMOVE => {
+                let index = self.inner().ports.get_mut(id as usize).and_then(|port| {
+                    port.present = false;
+                    port.hvc_index.take()
+                });
+                if let Some(index) = index {
+                    hvc_unbind_line(index);
+                }
+            }
+            VIRTIO_CONSOLE_CONSOLE_PORT if msg.value != 0 => {
+                let unbound = self
+                    .inner()
+                    .ports
+                    .get(id as usize)
+                    .is_some_and(|port| port.present && port.hvc_index.is_none());
+                if !unbound {
+                    return;
+                }
+                match hvc_bind_line(self, id) {
+                    Ok(_) => self.send_control(id, VIRTIO_CONSOLE_PORT_OPEN, 1),
+                    Err(e) => warn!(
+                        "virtio-console: failed to bind hvc line for port {id}: {:?}",
+                        e
+                    ),
+                }
+            }
+            // 窗口大小、端口名称以及宿主机一端的连接状态目前都不需要处理
+            _ => {}
+        }
+    }
+}
+
+struct InnerVirtIOConsoleDevice {
+    transport: VirtIOTransport,
+    /// 控制队列，只有协商了多端口特性时才存在
+    control: Option<(VirtIOConsoleRecvQueue, VirtIOConsoleSendQueue)>,
+    /// 端口，下标为端口号
+    ports: Vec<VirtIOConsolePort>,
+    name: Option<String>,
+    virtio_index: Option<VirtIODeviceIndex>,
+    device_common: DeviceCommonData,
+    kobject_common: KObjectCommonData,
+    irq: Option<IrqNumber>,
+}
+
+impl Debug for InnerVirtIOConsoleDevice {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("InnerVirtIOConsoleDevice").finish()
+    }
+}
+
+impl VirtIODevice for VirtIOConsoleDevice {
+    fn irq(&self) -> Option<IrqNumber> {
+        self.inner().irq
+    }
+
+    fn handle_irq(&self, _irq: IrqNumber) -> Result<IrqReturn, SystemError> {
+        if !self.inner().transport.ack_interrupt() {
+            return Ok(IrqReturn::NotHandled);
+        }
+
+        HVC_WORK.complete();
+        Ok(IrqReturn::Handled)
+    }
+
+    fn dev_id(&self) -> &Arc<DeviceId> {
+        &self.dev_id
+    }
+
+    fn set_device_name(&self, name: String) {
+        self.inner().name = Some(name);
+    }
+
+    fn device_name(&self) -> String {
+        self.inner()
+            .name
+            .clone()
+            .unwrap_or_else(|| VIRTIO_CONSOLE_BASENAME.to_string())
+    }
+
+    fn set_virtio_device_index(&self, index: VirtIODeviceIndex) {
+        self.inner().virtio_index = Some(index);
+    }
+
+    fn virtio_device_index(&self) -> Option<VirtIODeviceIndex> {
+        self.inner().virtio_index
+    }
+
+    fn device_type_id(&self) -> u32 {
+        virtio_drivers::transport::DeviceType::Console as u32
+    }
+
+    fn vendor(&self) -> u32 {
+        VIRTIO_VENDOR_ID.into()
+    }
+}
+
+impl Device for VirtIOConsoleDevice {
+    fn dev_type(&self) -> DeviceType {
+        DeviceType::Char
+    }
+
+    fn id_table(&self) -> IdTable {
+        IdTable::new(VIRTIO_CONSOLE_BASENAME.to_string(), None)
+    }
+
+    fn bus(&self) -> Option<Weak<dyn Bus>> {
+        self.inner().device_common.bus.clone()
+    }
+
+    fn set_bus(&self, bus: Option<Weak<dyn Bus>>) {
+        self.inner().device_common.bus = bus;
+    }
+
+    fn class(&self) -> Option<Arc<dyn Class>> {
+        let mut guard = self.inner();
+        let r = guard.device_common.class.clone()?.upgrade();
+        if r.is_none() {
+            guard.device_common.class = None;
+        }
+
+        return r;
+    }
+
+    fn set_class(&self, class: Option<Weak<dyn Class>>) {
+        self.inner().device_common.class = class;
+    }
+
+    fn driver(&self) -> Option<Arc<dyn Driver>> {
+        let r = self.inner().device_common.driver.clone()?.upgrade();
+        if r.is_none() {
+            self.inner().device_common.driver = None;
+        }
+
+        return r;
+    }
+
+    fn set_driver(&self, driver: Option<Weak<dyn Driver>>) {
+        self.inner().device_common.driver = driver;
+    }
+
+    fn is_dead(&self) -> bool {
+        false
+    }
+
+    fn can_match(&self) -> bool {
+        self.inner().device_common.can_match
+    }
+
+    fn set_can_match(&self, can_match: bool) {
+        self.inner().device_common.can_match = can_match;
+    }
+
+    fn state_synced(&self) -> bool {
+        true
+    }
+
+    fn dev_parent(&self) -> Option<Weak<dyn Device>> {
+        self.inner().device_common.get_parent_weak_or_clear()
+    }
+
+    fn set_dev_parent(&self, parent: Option<Weak<dyn Device>>) {
+        self.inner().device_common.parent = parent;
+    }
+}
+
+impl KObject for VirtIOConsoleDevice {
+    fn as_any_ref(&self) -> &dyn Any {
+        self
+    }
+
+    fn set_inode(&self, inode: Option<Arc<KernFSInode>>) {
+        self.inner().kobject_common.kern_inode = inode;
+    }
+
+    fn inode(&self) -> Option<Arc<KernFSInode>> {
+        self.inner().kobject_common.kern_inode.clone()
+    }
+
+    fn parent(&self) -> Option<Weak<dyn KObject>> {
+        self.inner().kobject_common.parent.clone()
+    }
+
+    fn set_parent(&self, parent: Option<Weak<dyn KObject>>) {
+        self.inner().kobject_common.parent = parent;
+    }
+
+    fn kset(&self) -> Option<Arc<KSet>> {
+        self.inner().kobject_common.kset.clone()
+    }
+
+    fn set_kset(&self, kset: Option<Arc<KSet>>) {
+        self.inner().kobject_common.kset = kset;
+    }
+
+    fn kobj_type(&self) -> Option<&'static dyn KObjType> {
+        self.inner().kobject_common.kobj_type
+    }
+
+    fn name(&self) -> String {
+        self.device_name()
+    }
+
+    fn set_name(&self, _name: String) {
+        // do nothing
+    }
+
+    fn kobj_state(&self) -> RwLockReadGuard<KObjectState> {
+        self.locked_kobj_state.read()
+    }
+
+    fn kobj_state_mut(&self) -> RwLockWriteGuard<KObjectState> {
+        self.locked_kobj_state.write()
+    }
+
+    fn set_kobj_state(&self, state: KObjectState) {
+        *self.locked_kobj_state.write() = state;
+    }
+
+    fn set_kobj_type(&self, ktype: Option<&'static dyn KObjType>) {
+        self.inner().kobject_common.kobj_type = ktype;
+    }
+}
+
+/// hvc终端与virtio console端口的绑定
+#[derive(Debug)]
+struct HvcLine {
+    index: usize,
+    dev: Weak<VirtIOConsoleDevice>,
+    port: u32,
+}
+
+impl HvcLine {
+    fn write_bytes(&self, buf: &[u8]) -> Result<usize, SystemError> {
+        let dev = self.dev.upgrade().ok_or(SystemError::ENODEV)?;
+        dev.write_bytes(self.port, buf)
+    }
+}
+
+impl Console for HvcLine {
+    fn name(&self) -> String {
+        format!("hvc{}", self.index)
+    }
+
+    fn write(&self, buf: &[u8]) {
+        let Some(dev) = self.dev.upgrade() else {
+            return;
+        };
+        // 避免在持有设备锁的时候打印日志而导致死锁
+        let Ok(mut inner) = dev.inner.try_lock_irqsave() else {
+            return;
+        };
+
+        for line in buf.split_inclusive(|c| *c == b'\n') {
+            if let Some(content) = line.strip_suffix(b"\n") {
+                VirtIOConsoleDevice::send_bytes(&mut inner, self.port, content).ok();
+                VirtIOConsoleDevice::send_bytes(&mut inner, self.port, b"\r\n").ok();
+            } else {
+                VirtIOConsoleDevice::send_bytes(&mut inner, self.port, line).ok();
+            }
+        }
+    }
+}
+
+/// hvc终端的tty驱动方法
+#[derive(Debug)]
+struct HvcTtyDriverInner;
+
+impl TtyOperation for HvcTtyDriverInner {
+    fn open(&self, tty: &TtyCoreData) -> Result<(), SystemError> {
+        hvc_line(tty.index()).ok_or(SystemError::ENODEV)?;
+        Ok(())
+    }
+
+    fn write(&self, tty: &TtyCoreData, buf: &[u8], nr: usize) -> Result<usize, SystemError> {
+        let line = hvc_line(tty.index()).ok_or(SystemError::ENODEV)?;
+        line.write_bytes(&buf[..nr])
+    }
+
+    fn flush_chars(&self, _tty: &TtyCoreData) {
+        // virtio console每次写入都会通知设备，不需要额外刷新
+    }
+
+    fn put_char(&self, tty: &TtyCoreData, ch: u8) -> Result<(), SystemError> {
+        self.write(tty, &[ch], 1).map(|_| ())
+    }
+
+    fn ioctl(&self, _tty: Arc<TtyCore>, _cmd: u32, _arg: usize) -> Result<(), SystemError> {
+        Err(SystemError::ENOIOCTLCMD)
+    }
+
+    fn close(&self, _tty: Arc<TtyCore>) -> Result<(), SystemError> {
+        Ok(())
+    }
+
+    fn resize(&self, _tty: Arc<TtyCore>, _winsize: WindowSize) -> Result<(), SystemError> {
+        // 交给tty层记录窗口大小
+        Err(SystemError::ENOSYS)
+    }
+}
+
+/// 为virtio console端口分配hvc终端，并创建对应的设备节点
+fn hvc_bind_line(dev: &Arc<VirtIOConsoleDevice>, port: u32) -> Result<usize, SystemError> {
+    let line = {
+        let mut lines = HVC_LINES.lock_irqsave();
+        let index = lines
+            .iter()
+            .position(|line| line.is_none())
+            .ok_or(SystemError::ENOSPC)?;
+        let line = Arc::new(HvcLine {
+            index,
+            dev: Arc::downgrade(dev),
+            port,
+        });
+        lines[index] = Some(line.clone());
+        line
+    };
+
+    if let Err(e) = hvc_register_line(&line) {
+        HVC_LINES.lock_irqsave()[line.index] = None;
+        return Err(e);
+    }
+    if let Some(port) = dev.inner().ports.get_mut(port as usize) {
+        port.hvc_index = Some(line.index);
+    }
+
+    Ok(line.index)
+}
+
+fn hvc_register_line(line: &Arc<HvcLine>) -> Result<(), SystemError> {
+    let index = line.index;
+    let name = line.name();
+    let (tty_dev, created) = {
+        let mut tty_devices = HVC_TTY_DEVICES.lock_irqsave();
+        match tty_devices[index].clone() {
+            Some(tty_dev) => (tty_dev, false),
+            None => {
+                let tty_dev = TtyDevice::new(
+                    name.clone(),
+                    IdTable::new(
+                        name.clone(),
+                        Some(DeviceNumber::new(Major::HVC_MAJOR, index as u32)),
+                    ),
+                    TtyType::Tty,
+                );
+                tty_devices[index] = Some(tty_dev.clone());
+                (tty_dev, true)
+            }
+        }
+    };
+    if created {
+        device_register(tty_dev.clone())?;
+    }
+    devfs_register(&name, tty_dev.clone())?;
+
+    // hvc0作为内核消息控制台
+    if index == 0 {
+        if let Err(e) = register_console(line.clone() as Arc<dyn Console>) {
+            devfs_unregister(&name, tty_dev).ok();
+            return Err(e);
+        }
+    }
+
+    Ok(())
+}
+
+/// 解除hvc终端与端口的绑定，并删除终端的设备节点
+///
+/// 已经打开了该终端的进程之后读写都会失败
+fn hvc_unbind_line(index: usize) {
+    let Some(line) = HVC_LINES.lock_irqsave()[index].take() else {
+        return;
+    };
+    let name = line.name();
+    if index == 0 {
+        unregister_console(&name);
+    }
+    if let Some(tty_dev) = HVC_TTY_DEVICES.lock_irqsave()[index].clone() {
+        devfs_unregister(&name, tty_dev).ok();
+    }
+}
+
+fn hvc_work_thread() -> i32 {
+    loop {
+        HVC_WORK.wait_for_completion().ok();
+        for dev in virtio_console_driver().devices() {
+            if let Ok(dev) = dev.arc_any().downcast::<VirtIOConsoleDevice>() {
+                dev.poll_control();
+                dev.poll_input();
+            }
+        }
+    }
+}
+
+#[unified_init(INITCALL_POSTCORE)]
+fn virtio_console_driver_init() -> Result<(), SystemError> {
+    let driver = VirtIOConsoleDriver::new();
+    virtio_driver_manager()
+        .register(driver.clone() as Arc<dyn VirtIODriver>)
+        .expect("Add virtio console driver failed");
+    VIRTIO_CONSOLE_DRIVER.init(driver);
+
+    return Ok(());
+}
+
+#[unified_init(INITCALL_DEVICE)]
+fn hvc_tty_driver_init() -> Result<(), SystemError> {
+    let hvc_driver = TtyDriver::new(
+        HVC_MAX_LINES as u32,
+        "hvc",
+        0,
+        Major::HVC_MAJOR,
+        0,
+        TtyDriverType::System,
+        *TTY_STD_TERMIOS,
+        Arc::new(HvcTtyDriverInner),
+    );
+    HVC_TTY_DRIVER.init(TtyDriverManager::tty_register_driver(hvc_driver)?);
+
+    // 在此之前到达的中断已经记录在HVC_WORK中，线程启动后会立即处理
+    let closure = KernelThreadClosure::StaticEmptyClosure((&(hvc_work_thread as fn() -> i32), ()));
+    KernelThreadMechanism::create_and_run(closure, "khvcd".to_string())
+        .ok_or(SystemError::ENOMEM)?;
+
+    return Ok(());
+}
+
+#[derive(Debug)]
+#[cast_to([sync] VirtIODriver)]
+#[cast_to([sync] Driver)]
+struct VirtIOConsoleDriver {
+    inner: SpinLock<InnerVirtIOConsoleDriver>,
+    kobj_state: LockedKObjectState,
+}
+
+impl VirtIOConsoleDriver {
+    pub fn new() -> Arc<Self> {
+        let inner = InnerVirtIOConsoleDriver {
+            virtio_driver_common: VirtIODriverCommonData::default(),
+            driver_common: DriverCommonData::default(),
+            kobj_common: KObjectCommonData::default(),
+        };
+
+        let id_table = VirtioDeviceId::new(
+            virtio_drivers::transport::DeviceType::Console as u32,
+            VIRTIO_VENDOR_ID.into(),
+        );
+        let result = VirtIOConsoleDriver {
+            inner: SpinLock::new(inner),
+            kobj_state: LockedKObjectState::default(),
+        };
+        result.add_virtio_id(id_table);
+
+        return Arc::new(result);
+    }
+
+    fn inner(&self) -> SpinLockGuard<InnerVirtIOConsoleDriver> {
+        return self.inner.lock();
+    }
+}
+
+#[derive(Debug)]
+struct InnerVirtIOConsoleDriver {
+    virtio_driver_common: VirtIODriverCommonData,
+    driver_common: DriverCommonData,
+    kobj_common: KObjectCommonData,
+}
+
+impl VirtIODriver for VirtIOConsoleDriver {
+    fn probe(&self, device: &Arc<dyn VirtIODevice>) -> Result<(), SystemError> {
+        let dev = device
+            .clone()
+            .arc_any()
+            .downcast::<VirtIOConsoleDevice>()
+            .map_err(|_| {
+                error!(
+                "VirtIOConsoleDriver::probe() failed: device is not a VirtIO console device. Device: '{:?}'",
+                device.name()
+            );
+                SystemError::EINVAL
+            })?;
+
+        if dev.multiport() {
+            // 设备收到DEVICE_READY之后，会通过控制队列逐个添加端口
+            dev.send_control(0, VIRTIO_CONSOLE_DEVICE_READY, 1);
+            return Ok(());
+        }
+
+        if let Err(e) = hvc_bind_line(&dev, 0) {
+            warn!(
+                "VirtIOConsoleDriver::probe() failed to bind hvc line for device '{:?}': {:?}",
+                device.name(),
+                e
+            );
+            return Err(e);
+        }
+        return Ok(());
+    }
+
+    fn virtio_id_table(&self) -> LinkedList<VirtioDeviceId> {
+        self.inner().virtio_driver_common.id_table.clone()
+    }
+
+    fn add_virtio_id(&self, id: VirtioDeviceId) {
+        self.inner().virtio_driver_common.id_table.push_back(id);
+    }
+}
+
+impl Driver for VirtIOConsoleDriver {
+    fn id_table(&self) -> Option<IdTable> {
+        Some(IdTable::new(VIRTIO_CONSOLE_BASENAME.to_string(), None))
+    }
+
+    fn add_device(&self, device: Arc<dyn Device>) {
+        let iface = device.arc_any().downcast::<VirtIOConsoleDevice>().expect(
+            "VirtIOConsoleDriver::add_device() failed: device is not a VirtIOConsoleDevice",
+        );
+
+        self.inner()
+            .driver_common
+            .devices
+            .push(iface as Arc<dyn Device>);
+    }
+
+    fn delete_device(&self, device: &Arc<dyn Device>) {
+        let _iface = device
+            .clone()
+            .arc_any()
+            .downcast::<VirtIOConsoleDevice>()
+            .expect(
+                "VirtIOConsoleDriver::delete_device() failed: device is not a VirtIOConsoleDevice",
+            );
+
+        let mut guard = self.inner();
+        let index = guard
+            .driver_common
+            .devices
+            .iter()
+            .position(|dev| Arc::ptr_eq(device, dev))
+            .expect("VirtIOConsoleDriver::delete_device() failed: device not found");
+
+        guard.driver_common.devices.remove(index);
+    }
+
+    fn devices(&self) -> Vec<Arc<dyn Device>> {
+        self.inner().driver_common.devices.clone()
+    }
+
+    fn bus(&self) -> Option<Weak<dyn Bus>> {
+        Some(Arc::downgrade(&virtio_bus()) as Weak<dyn Bus>)
+    }
+
+    fn set_bus(&self, _bus: Option<Weak<dyn Bus>>) {
+        // do nothing
+    }
+}
+
+impl KObject for VirtIOConsoleDriver {
+    fn as_any_ref(&self) -> &dyn Any {
+        self
+    }
+
+    fn set_inode(&self, inode: Option<Arc<KernFSInode>>) {
+        self.inner().kobj_common.kern_inode = inode;
+    }
+
+    fn inode(&self) -> Option<Arc<KernFSInode>> {
+        self.inner().kobj_common.kern_inode.clone()
+    }
+
+    fn parent(&self) -> Option<Weak<dyn KObject>> {
+        self.inner().kobj_common.parent.clone()
+    }
+
+    fn set_parent(&self, parent: Option<Weak<dyn KObject>>) {
+        self.inner().kobj_common.parent = parent;
+    }
+
+    fn kset(&self) -> Option<Arc<KSet>> {
+        self.inner().kobj_common.kset.clone()
+    }
+
+    fn set_kset(&self, kset: Option<Arc<KSet>>) {
+        self.inner().kobj_common.kset = kset;
+    }
+
+    fn kobj_type(&self) -> Option<&'static dyn KObjType> {
+        self.inner().kobj_common.kobj_type
+    }
+
+    fn set_kobj_type(&self, ktype: Option<&'static dyn KObjType>) {
+        self.inner().kobj_common.kobj_type = ktype;
+    }
+
+    fn name(&self) -> String {
+        VIRTIO_CONSOLE_BASENAME.to_string()
+    }
+
+    fn set_name(&self, _name: String) {
+        // do nothing
+    }
+
+    fn kobj_state(&self) -> RwLockReadGuard<KObjectState> {
+        self.kobj_state.read()
+    }
+
+    fn kobj_state_mut(&self) -> RwLockWriteGuard<KObjectState> {
+        self.kobj_state.write()
+    }
+
+    fn set_kobj_state(&self, state: KObjectState) {
+        *self.kobj_state.write() = state;
+    }
+}
@@ -0,0 +1,626 @@
+//! virtio-rng驱动
+//!
+//! virtio entropy设备作为硬件随机数发生器，由“hwrng”内核线程定期读取，
+//! 并混合进随机数子系统的输入池。
+//!
+//! 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/drivers/char/hw_random/virtio-rng.c
+
+use core::{
+    any::Any,
+    fmt::Debug,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use alloc::{
+    collections::LinkedList,
+    string::{String, ToString},
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+use log::error;
+use system_error::SystemError;
+use unified_init::macros::unified_init;
+use virtio_drivers::transport::Transport;
+
+use crate::{
+    driver::{
+        base::{
+            class::Class,
+            device::{
+                bus::Bus,
+                driver::{Driver, DriverCommonData},
+                Device, DeviceCommonData, DeviceId, DeviceType, IdTable,
+            },
+            kobject::{KObjType, KObject, KObjectCommonData, KObjectState, LockedKObjectState},
+            kset::KSet,
+        },
+        char::random::{add_hwgenerator_randomness, crng_ready},
+        virtio::{
+            queue::{
+                virtio_begin_init, virtio_reset, virtqueue_wait_used, SplitVirtQueue,
+                VIRTQUEUE_REQUEST_TIMEOUT,
+            },
+            sysfs::{virtio_bus, virtio_device_manager, virtio_driver_manager},
+            transport::VirtIOTransport,
+            VirtIODevice, VirtIODeviceIndex, VirtIODriver, VirtIODriverCommonData, VirtioDeviceId,
+            VIRTIO_VENDOR_ID,
+        },
+    },
+    exception::{irqdesc::IrqReturn, IrqNumber},
+    filesystem::kernfs::KernFSInode,
+    init::initcall::INITCALL_POSTCORE,
+    libs::{
+        lazy_init::Lazy,
+        mutex::Mutex,
+        rwlock::{RwLockReadGuard, RwLockWriteGuard},
+        spinlock::{SpinLock, SpinLockGuard},
+    },
+    process::kthread::{KernelThreadClosure, KernelThreadMechanism},
+    sched::completion::Completion,
+    time::{sleep::nanosleep, PosixTimeSpec},
+};
+
+const VIRTIO_RNG_BASENAME: &str = "virtio_rng";
+
+/// virtio entropy设备只有一个请求队列
+const VIRTIO_RNG_QUEUE: u16 = 0;
+const VIRTIO_RNG_QUEUE_SIZE: u16 = 8;
+
+/// 每次从设备读取的字节数
+///
+/// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/drivers/char/hw_random/core.c#28
+const RNG_BUFFER_SIZE: usize = 32;
+
+/// CRNG初始化之前，两次读取设备之间的间隔（秒）
+const HWRNG_FILL_INTERVAL_EARLY_SEC: i64 = 1;
+/// CRNG初始化之后，两次读取设备之间的间隔（秒），与CRNG的重新播种间隔一致
+const HWRNG_FILL_INTERVAL_SEC: i64 = 60;
+
+static VIRTIO_RNG_DRIVER: Lazy<Arc<VirtIORngDriver>> = Lazy::new();
+static HWRNG_THREAD_STARTED: SpinLock<bool> = SpinLock::new(false);
+
+#[inline(always)]
+fn virtio_rng_driver() -> Arc<VirtIORngDriver> {
+    VIRTIO_RNG_DRIVER.get().clone()
+}
+
+pub fn virtio_rng(
+    transport: VirtIOTransport,
+    dev_id: Arc<DeviceId>,
+    dev_parent: Option<Arc<dyn Device>>,
+) {
+    let device = VirtIORngDevice::new(transport, dev_id);
+    if let Some(device) = device {
+        if let Some(dev_parent) = dev_parent {
+            device.set_dev_parent(Some(Arc::downgrade(&dev_parent)));
+        }
+        virtio_device_manager()
+            .device_add(device.clone() as Arc<dyn VirtIODevice>)
+            .expect("Add virtio rng failed");
+    }
+}
+
+/// virtio entropy device
+#[derive(Debug)]
+#[cast_to([sync] VirtIODevice)]
+#[cast_to([sync] Device)]
+pub struct VirtIORngDevice {
+    dev_id: Arc<DeviceId>,
+    /// 串行化对设备的请求，等待设备时不持有`inner`
+    request_lock: Mutex<()>,
+    /// 设备处理完请求后，由中断处理函数唤醒等待者
+    completion: Completion,
+    /// 设备超时未响应而被复位后置位，之后的请求直接失败
+    broken: AtomicBool,
+    inner: SpinLock<InnerVirtIORngDevice>,
+    locked_kobj_state: LockedKObjectState,
+}
+
+unsafe impl Send for VirtIORngDevice {}
+unsafe impl Sync for VirtIORngDevice {}
+
+impl VirtIORngDevice {
+    pub fn new(mut transport: VirtIOTransport, dev_id: Arc<DeviceId>) -> Option<Arc<Self>> {
+        let irq = transport.irq().map(|irq| IrqNumber::new(irq.data()));
+        // virtio entropy设备没有任何特性位
+        let queue = virtio_begin_init(&mut transport, 0).and_then(|_| {
+            SplitVirtQueue::new(&mut transport, VIRTIO_RNG_QUEUE, VIRTIO_RNG_QUEUE_SIZE)
+        });
+        let queue = match queue {
+            Ok(queue) => queue,
+            Err(e) => {
+                error!("VirtIORngDevice '{dev_id:?}' create failed: {:?}", e);
+                return None;
+            }
+        };
+        transport.finish_init();
+
+        let dev = Arc::new(Self {
+            dev_id,
+            request_lock: Mutex::new(()),
+            completion: Completion::new(),
+            broken: AtomicBool::new(false),
+            locked_kobj_state: LockedKObjectState::default(),
+            inner: SpinLock::new(InnerVirtIORngDevice {
+                device_inner: transport,
+                queue,
+                name: None,
+                virtio_index: None,
+                device_common: DeviceCommonData::default(),
+                kobject_common: KObjectCommonData::default(),
+                irq,
+            }),
+        });
+
+        Some(dev)
+    }
+
+    fn inner(&self) -> SpinLockGuard<InnerVirtIORngDevice> {
+        self.inner.lock_irqsave()
+    }
+
+    /// 从设备读取随机数
+    ///
+    /// 等待设备时会睡眠，不能在中断上下文或持有自旋锁时调用
+    ///
+    /// ## 返回值
+    ///
+    /// - `Ok(usize)`: 设备实际写入的字节数
+    /// - `Err(SystemError::EIO)`: 设备超时未响应，已被复位
+    fn read(&self, buf: &mut [u8]) -> Result<usize, SystemError> {
+        let _guard = self.request_lock.lock();
+        if self.broken.load(Ordering::Acquire) {
+            return Err(SystemError::EIO);
+        }
+
+        let token = {
+            let mut inner = self.inner();
+            let inner = &mut *inner;
+            // buf在请求完成或设备被复位之前不会被释放
+            let token = unsafe { inner.queue.add(&[], &mut [buf])? };
+            inner.queue.notify(&mut inner.device_inner);
+            token
+        };
+
+        let completion = self.inner().irq.map(|_| &self.completion);
+        let r = virtqueue_wait_used(completion, VIRTQUEUE_REQUEST_TIMEOUT, || {
+            let mut inner = self.inner();
+            while let Some((t, len)) = inner.queue.pop_used() {
+                if t == token {
+                    return Some(len);
+                }
+            }
+            None
+        });
+        match r {
+            Ok(len) => Ok(len as usize),
+            Err(e) => {
+                error!(
+                    "VirtIORngDevice '{:?}' request failed: {:?}, resetting device",
+                    self.dev_id, e
+                );
+                self.broken.store(true, Ordering::Release);
+                virtio_reset(&mut self.inner().device_inner);
+                Err(SystemError::EIO)
+            }
+        }
+    }
+}
+
+struct InnerVirtIORngDevice {
+    device_inner: VirtIOTransport,
+    queue: SplitVirtQueue,
+    name: Option<String>,
+    virtio_index: Option<VirtIODeviceIndex>,
+    device_common: DeviceCommonData,
+    kobject_common: KObjectCommonData,
+    irq: Option<IrqNumber>,
+}
+
+impl Debug for InnerVirtIORngDevice {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("InnerVirtIORngDevice").finish()
+    }
+}
+
+impl VirtIODevice for VirtIORngDevice {
+    fn irq(&self) -> Option<IrqNumber> {
+        self.inner().irq
+    }
+
+    fn handle_irq(&self, _irq: IrqNumber) -> Result<IrqReturn, SystemError> {
+        if self.inner().device_inner.ack_interrupt() {
+            self.completion.complete();
+            Ok(IrqReturn::Handled)
+        } else {
+            Ok(IrqReturn::NotHandled)
+        }
+    }
+
+    fn dev_id(&self) -> &Arc<DeviceId> {
+        &self.dev_id
+    }
+
+    fn set_device_name(&self, name: String) {
+        self.inner().name = Some(name);
+    }
+
+    fn device_name(&self) -> String {
+        self.inner()
+            .name
+            .clone()
+            .unwrap_or_else(|| VIRTIO_RNG_BASENAME.to_string())
+    }
+
+    fn set_virtio_device_index(&self, index: VirtIODeviceIndex) {
+        self.inner().virtio_index = Some(index);
+    }
+
+    fn virtio_device_index(&self) -> Option<VirtIODeviceIndex> {
+        self.inner().virtio_index
+    }
+
+    fn device_type_id(&self) -> u32 {
+        virtio_drivers::transport::DeviceType::EntropySource as u32
+    }
+
+    fn vendor(&self) -> u32 {
+        VIRTIO_VENDOR_ID.into()
+    }
+}
+
+impl Device for VirtIORngDevice {
+    fn dev_type(&self) -> DeviceType {
+        DeviceType::Char
+    }
+
+    fn id_table(&self) -> IdTable {
+        IdTable::new(VIRTIO_RNG_BASENAME.to_string(), None)
+    }
+
+    fn bus(&self) -> Option<Weak<dyn Bus>> {
+        self.inner().device_common.bus.clone()
+    }
+
+    fn set_bus(&self, bus: Option<Weak<dyn Bus>>) {
+        self.inner().device_common.bus = bus;
+    }
+
+    fn class(&self) -> Option<Arc<dyn Class>> {
+        let mut guard = self.inner();
+        let r = guard.device_common.class.clone()?.upgrade();
+        if r.is_none() {
+            guard.device_common.class = None;
+        }
+
+        return r;
+    }
+
+    fn set_class(&self, class: Option<Weak<dyn Class>>) {
+        self.inner().device_common.class = class;
+    }
+
+    fn driver(&self) -> Option<Arc<dyn Driver>> {
+        let r = self.inner().device_common.driver.clone()?.upgrade();
+        if r.is_none() {
+            self.inner().device_common.driver = None;
+        }
+
+        return r;
+    }
+
+    fn set_driver(&self, driver: Option<Weak<dyn Driver>>) {
+        self.inner().device_common.driver = driver;
+    }
+
+    fn is_dead(&self) -> bool {
+        false
+    }
+
+    fn can_match(&self) -> bool {
+        self.inner().device_common.can_match
+    }
+
+    fn set_can_match(&self, can_match: bool) {
+        self.inner().device_common.can_match = can_match;
+    }
+
+    fn state_synced(&self) -> bool {
+        true
+    }
+
+    fn dev_parent(&self) -> Option<Weak<dyn Device>> {
+        self.inner().device_common.get_parent_weak_or_clear()
+    }
+
+    fn set_dev_parent(&self, parent: Option<Weak<dyn Device>>) {
+        self.inner().device_common.parent = parent;
+    }
+}
+
+impl KObject for VirtIORngDevice {
+    fn as_any_ref(&self) -> &dyn Any {
+        self
+    }
+
+    fn set_inode(&self, inode: Option<Arc<KernFSInode>>) {
+        self.inner().kobject_common.kern_inode = inode;
+    }
+
+    fn inode(&self) -> Option<Arc<KernFSInode>> {
+        self.inner().kobject_common.kern_inode.clone()
+    }
+
+    fn parent(&self) -> Option<Weak<dyn KObject>> {
+        self.inner().kobject_common.parent.clone()
+    }
+
+    fn set_parent(&self, parent: Option<Weak<dyn KObject>>) {
+        self.inner().kobject_common.parent = parent;
+    }
+
+    fn kset(&self) -> Option<Arc<KSet>> {
+        self.inner().kobject_common.kset.clone()
+    }
+
+    fn set_kset(&self, kset: Option<Arc<KSet>>) {
+        self.inner().kobject_common.kset = kset;
+    }
+
+    fn kobj_type(&self) -> Option<&'static dyn KObjType> {
+        self.inner().kobject_common.kobj_type
+    }
+
+    fn name(&self) -> String {
+        self.device_name()
+    }
+
+    fn set_name(&self, _name: String) {
+        // do nothing
+    }
+
+    fn kobj_state(&self) -> RwLockReadGuard<KObjectState> {
+        self.locked_kobj_state.read()
+    }
+
+    fn kobj_state_mut(&self) -> RwLockWriteGuard<KObjectState> {
+        self.locked_kobj_state.write()
+    }
+
+    fn set_kobj_state(&self, state: KObjectState) {
+        *self.locked_kobj_state.write() = state;
+    }
+
+    fn set_kobj_type(&self, ktype: Option<&'static dyn KObjType>) {
+        self.inner().kobject_common.kobj_type = ktype;
+    }
+}
+
+/// hwrng内核线程：定期从硬件随机数发生器读取数据，混合进输入池
+///
+/// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/drivers/char/hw_random/core.c#488
+fn hwrng_fillfn() -> i32 {
+    let mut buf = [0u8; RNG_BUFFER_SIZE];
+    loop {
+        for dev in virtio_rng_driver().devices() {
+            let Ok(dev) = dev.arc_any().downcast::<VirtIORngDevice>() else {
+                continue;
+            };
+            match dev.read(&mut buf) {
+                Ok(len) if len > 0 => {
+                    let len = core::cmp::min(len, buf.len());
+                    add_hwgenerator_randomness(&buf[..len], (len * 8) as u32);
+                }
+                Ok(_) => {}
+                Err(e) => error!("hwrng: failed to read from '{}': {:?}", dev.name(), e),
+            }
+        }
+        buf.fill(0);
+
+        let interval = if crng_ready() {
+            HWRNG_FILL_INTERVAL_SEC
+        } else {
+            HWRNG_FILL_INTERVAL_EARLY_SEC
+        };
+        let _ = nanosleep(PosixTimeSpec::new(interval, 0));
+    }
+}
+
+/// 第一个硬件随机数发生器就绪时，启动hwrng内核线程
+fn hwrng_start() -> Result<(), SystemError> {
+    let mut started = HWRNG_THREAD_STARTED.lock();
+    if *started {
+        return Ok(());
+    }
+
+    let closure = KernelThreadClosure::StaticEmptyClosure((&(hwrng_fillfn as fn() -> i32), ()));
+    KernelThreadMechanism::create_and_run(closure, "hwrng".to_string())
+        .ok_or(SystemError::ENOMEM)?;
+    *started = true;
+    Ok(())
+}
+
+#[unified_init(INITCALL_POSTCORE)]
+fn virtio_rng_driver_init() -> Result<(), SystemError> {
+    let driver = VirtIORngDriver::new();
+    virtio_driver_manager()
+        .register(driver.clone() as Arc<dyn VirtIODriver>)
+        .expect("Add virtio rng driver failed");
+    VIRTIO_RNG_DRIVER.init(driver);
+
+    return Ok(());
+}
+
+#[derive(Debug)]
+#[cast_to([sync] VirtIODriver)]
+#[cast_to([sync] Driver)]
+struct VirtIORngDriver {
+    inner: SpinLock<InnerVirtIORngDriver>,
+    kobj_state: LockedKObjectState,
+}
+
+impl VirtIORngDriver {
+    pub fn new() -> Arc<Self> {
+        let inner = InnerVirtIORngDriver {
+            virtio_driver_common: VirtIODriverCommonData::default(),
+            driver_common: DriverCommonData::default(),
+            kobj_common: KObjectCommonData::default(),
+        };
+
+        let id_table = VirtioDeviceId::new(
+            virtio_drivers::transport::DeviceType::EntropySource as u32,
+            VIRTIO_VENDOR_ID.into(),
+        );
+        let result = VirtIORngDriver {
+            inner: SpinLock::new(inner),
+            kobj_state: LockedKObjectState::default(),
+        };
+        result.add_virtio_id(id_table);
+
+        return Arc::new(result);
+    }
+
+    fn inner(&self) -> SpinLockGuard<InnerVirtIORngDriver> {
+        return self.inner.lock();
+    }
+}
+
+#[derive(Debug)]
+struct InnerVirtIORngDriver {
+    virtio_driver_common: VirtIODriverCommonData,
+    driver_common: DriverCommonData,
+    kobj_common: KObjectCommonData,
+}
+
+impl VirtIODriver for VirtIORngDriver {
+    fn probe(&self, device: &Arc<dyn VirtIODevice>) -> Result<(), SystemError> {
+        let _dev = device
+            .clone()
+            .arc_any()
+            .downcast::<VirtIORngDevice>()
+            .map_err(|_| {
+                error!(
+                "VirtIORngDriver::probe() failed: device is not a VirtIO entropy device. Device: '{:?}'",
+                device.name()
+            );
+                SystemError::EINVAL
+            })?;
+
+        hwrng_start()?;
+        return Ok(());
+    }
+
+    fn virtio_id_table(&self) -> LinkedList<VirtioDeviceId> {
+        self.inner().virtio_driver_common.id_table.clone()
+    }
+
+    fn add_virtio_id(&self, id: VirtioDeviceId) {
+        self.inner().virtio_driver_common.id_table.push_back(id);
+    }
+}
+
+impl Driver for VirtIORngDriver {
+    fn id_table(&self) -> Option<IdTable> {
+        Some(IdTable::new(VIRTIO_RNG_BASENAME.to_string(), None))
+    }
+
+    fn add_device(&self, device: Arc<dyn Device>) {
+        let iface = device
+            .arc_any()
+            .downcast::<VirtIORngDevice>()
+            .expect("VirtIORngDriver::add_device() failed: device is not a VirtIORngDevice");
+
+        self.inner()
+            .driver_common
+            .devices
+            .push(iface as Arc<dyn Device>);
+    }
+
+    fn delete_device(&self, device: &Arc<dyn Device>) {
+        let _iface = device
+            .clone()
+            .arc_any()
+            .downcast::<VirtIORngDevice>()
+            .expect("VirtIORngDriver::delete_device() failed: device is not a VirtIORngDevice");
+
+        let mut guard = self.inner();
+        let index = guard
+            .driver_common
+            .devices
+            .iter()
+            .position(|dev| Arc::ptr_eq(device, dev))
+            .expect("VirtIORngDriver::delete_device() failed: device not found");
+
+        guard.driver_common.devices.remove(index);
+    }
+
+    fn devices(&self) -> Vec<Arc<dyn Device>> {
+        self.inner().driver_common.devices.clone()
+    }
+
+    fn bus(&self) -> Option<Weak<dyn Bus>> {
+        Some(Arc::downgrade(&virtio_bus()) as Weak<dyn Bus>)
+    }
+
+    fn set_bus(&self, _bus: Option<Weak<dyn Bus>>) {
+        // do nothing
+    }
+}
+
+impl KObject for VirtIORngDriver {
+    fn as_any_ref(&self) -> &dyn Any {
+        self
+    }
+
+    fn set_inode(&self, inode: Option<Arc<KernFSInode>>) {
+        self.inner().kobj_common.kern_inode = inode;
+    }
+
+    fn inode(&self) -> Option<Arc<KernFSInode>> {
+        self.inner().kobj_common.kern_inode.clone()
+    }
+
+    fn parent(&self) -> Option<Weak<dyn KObject>> {
+        self.inner().kobj_common.parent.clone()
+    }
+
+    fn set_parent(&self, parent: Option<Weak<dyn KObject>>) {
+        self.inner().kobj_common.parent = parent;
+    }
+
+    fn kset(&self) -> Option<Arc<KSet>> {
+        self.inner().kobj_common.kset.clone()
+    }
+
+    fn set_kset(&self, kset: Option<Arc<KSet>>) {
+        self.inner().kobj_common.kset = kset;
+    }
+
+    fn kobj_type(&self) -> Option<&'static dyn KObjType> {
+        self.inner().kobj_common.kobj_type
+    }
+
+    fn set_kobj_type(&self, ktype: Option<&'static dyn KObjType>) {
+        self.inner().kobj_common.kobj_type = ktype;
+    }
+
+    fn name(&self) -> String {
+        VIRTIO_RNG_BASENAME.to_string()
+    }
+
+    fn set_name(&self, _name: String) {
+        // do nothing
+    }
+
+    fn kobj_state(&self) -> RwLockReadGuard<KObjectState> {
+        self.kobj_state.read()
+    }
+
+    fn kobj_state_mut(&self) -> RwLockWriteGuard<KObjectState> {
+        self.kobj_state.write()
+    }
+
+    fn set_kobj_state(&self, state: KObjectState) {
+        *self.kobj_state.write() = state;
+    }
+}
@@ -14,7 +14,8 @@ use crate::{
         rwlock::{RwLockReadGuard, RwLockWriteGuard},
         spinlock::{SpinLock, SpinLockGuard},
     },
-    net::{generate_iface_id, NET_DEVICES},
+    net::generate_iface_id,
+    process::namespace::net::INIT_NET_NS,
     time::Instant,
 };
 use alloc::{
@@ -441,10 +442,8 @@ pub fn e1000e_driver_init(device: E1000EDevice) {
     // 标识网络设备已经启动
     iface.set_net_state(NetDeivceState::__LINK_STATE_START);
 
-    // 将网卡的接口信息注册到全局的网卡接口信息表中
-    NET_DEVICES
-        .write_irqsave()
-        .insert(iface.nic_id(), iface.clone());
+    // 将网卡的接口信息注册到初始network namespace的网卡列表中
+    INIT_NET_NS.register_device(iface.clone());
     info!("e1000e driver init successfully!\tMAC: [{}]", mac);
 
     register_netdevice(iface.clone()).expect("register lo device failed");
@@ -11,7 +11,8 @@ use crate::filesystem::kernfs::KernFSInode;
 use crate::init::initcall::INITCALL_DEVICE;
 use crate::libs::rwlock::{RwLockReadGuard, RwLockWriteGuard};
 use crate::libs::spinlock::{SpinLock, SpinLockGuard};
-use crate::net::{generate_iface_id, NET_DEVICES};
+use crate::net::generate_iface_id;
+use crate::process::namespace::net::INIT_NET_NS;
 use crate::time::Instant;
 use alloc::collections::VecDeque;
 use alloc::fmt::Debug;
@@ -539,16 +540,14 @@ pub fn loopback_probe() {
     loopback_driver_init();
 }
 /// ## lo网卡设备初始化函数
-/// 创建驱动和iface，初始化一个lo网卡，添加到全局NET_DEVICES中
+/// 创建驱动和iface，初始化一个lo网卡，添加到初始的network namespace中
 pub fn loopback_driver_init() {
     let driver = LoopbackDriver::new();
     let iface = LoopbackInterface::new(driver);
     // 标识网络设备已经启动
     iface.set_net_state(NetDeivceState::__LINK_STATE_START);
 
-    NET_DEVICES
-        .write_irqsave()
-        .insert(iface.iface_id, iface.clone());
+    INIT_NET_NS.register_device(iface.clone());
 
     register_netdevice(iface.clone()).expect("register lo device failed");
 }
@@ -10,12 +10,14 @@ use crate::libs::spinlock::SpinLock;
 use system_error::SystemError;
 
 pub mod class;
-mod dma;
+pub mod dma;
 pub mod e1000e;
 pub mod irq_handle;
 pub mod loopback;
 pub mod sysfs;
+pub mod veth;
 pub mod virtio_net;
+pub mod virtio_vsock;
 
 bitflags! {
     pub struct NetDeivceState: u16 {
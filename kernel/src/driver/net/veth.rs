@@ -0,0 +1,473 @@
+use crate::arch::rand::rand;
+use crate::driver::base::class::Class;
+use crate::driver::base::device::bus::Bus;
+use crate::driver::base::device::driver::Driver;
+use crate::driver::base::device::{Device, DeviceCommonData, DeviceType, IdTable};
+use crate::driver::base::kobject::{
+    KObjType, KObject, KObjectCommonData, KObjectState, LockedKObjectState,
+};
+use crate::driver::base::kset::KSet;
+use crate::filesystem::kernfs::KernFSInode;
+use crate::libs::rwlock::{RwLockReadGuard, RwLockWriteGuard};
+use crate::libs::spinlock::{SpinLock, SpinLockGuard};
+use crate::net::generate_iface_id;
+use crate::process::namespace::net::NetNamespace;
+use crate::time::Instant;
+use alloc::collections::VecDeque;
+use alloc::fmt::Debug;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::sync::{Arc, Weak};
+use alloc::vec::Vec;
+use core::cell::UnsafeCell;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use smoltcp::phy;
+use smoltcp::wire::{EthernetAddress, HardwareAddress};
+use system_error::SystemError;
+
+use super::{NetDeivceState, NetDevice, NetDeviceCommonData, Operstate};
+
+const DEVICE_NAME: &str = "veth";
+
+/// veth的最大传输单元
+const VETH_MTU: usize = 1500;
+
+/// 所有veth接收队列中尚未被协议栈处理的数据包的数量
+static VETH_PENDING_FRAMES: AtomicUsize = AtomicUsize::new(0);
+
+/// 是否有veth网卡收到了尚未被协议栈处理的数据包
+///
+/// 网络轮询时据此判断是否需要再次轮询对端所在的network namespace
+pub fn veth_has_pending_frames() -> bool {
+    VETH_PENDING_FRAMES.load(Ordering::SeqCst) != 0
+}
+
+/// ## veth网卡的接收队列
+/// 对端发送的数据包会被直接放入本端的接收队列
+#[derive(Debug)]
+struct VethQueue {
+    queue: SpinLock<VecDeque<Vec<u8>>>,
+}
+
+impl VethQueue {
+    fn new() -> Arc<Self> {
+        Arc::new(Self {
+            queue: SpinLock::new(VecDeque::new()),
+        })
+    }
+
+    fn push(&self, buffer: Vec<u8>) {
+        self.queue.lock_irqsave().push_back(buffer);
+        VETH_PENDING_FRAMES.fetch_add(1, Ordering::SeqCst);
+    }
+
+    fn pop(&self) -> Option<Vec<u8>> {
+        let buffer = self.queue.lock_irqsave().pop_front();
+        if buffer.is_some() {
+            VETH_PENDING_FRAMES.fetch_sub(1, Ordering::SeqCst);
+        }
+        buffer
+    }
+}
+
+impl Drop for VethQueue {
+    fn drop(&mut self) {
+        VETH_PENDING_FRAMES.fetch_sub(self.queue.lock_irqsave().len(), Ordering::SeqCst);
+    }
+}
+
+/// ## veth接收令牌
+pub struct VethRxToken {
+    buffer: Vec<u8>,
+}
+
+impl phy::RxToken for VethRxToken {
+    fn consume<R, F>(mut self, f: F) -> R
+    where
+        F: FnOnce(&mut [u8]) -> R,
+    {
+        f(self.buffer.as_mut_slice())
+    }
+}
+
+/// ## veth发送令牌
+/// 发送的数据包会被放入对端的接收队列。若对端已被销毁，则丢弃数据包
+pub struct VethTxToken {
+    peer_rx: Weak<VethQueue>,
+}
+
+impl phy::TxToken for VethTxToken {
+    fn consume<R, F>(self, len: usize, f: F) -> R
+    where
+        F: FnOnce(&mut [u8]) -> R,
+    {
+        let mut buffer = vec![0; len];
+        let result = f(buffer.as_mut_slice());
+        if let Some(peer_rx) = self.peer_rx.upgrade() {
+            peer_rx.push(buffer);
+        }
+        result
+    }
+}
+
+/// ## veth驱动
+/// 持有本端的接收队列，以及对端接收队列的弱引用
+#[derive(Debug, Clone)]
+pub struct VethDriver {
+    rx: Arc<VethQueue>,
+    peer_rx: Weak<VethQueue>,
+}
+
+impl phy::Device for VethDriver {
+    type RxToken<'a> = VethRxToken where Self: 'a;
+    type TxToken<'a> = VethTxToken where Self: 'a;
+
+    fn capabilities(&self) -> phy::DeviceCapabilities {
+        let mut result = phy::DeviceCapabilities::default();
+        result.max_transmission_unit = VETH_MTU;
+        result.max_burst_size = Some(1);
+        result.medium = smoltcp::phy::Medium::Ethernet;
+        return result;
+    }
+
+    fn receive(
+        &mut self,
+        _timestamp: smoltcp::time::Instant,
+    ) -> Option<(Self::RxToken<'_>, Self::TxToken<'_>)> {
+        let buffer = self.rx.pop()?;
+        let rx = VethRxToken { buffer };
+        let tx = VethTxToken {
+            peer_rx: self.peer_rx.clone(),
+        };
+        return Some((rx, tx));
+    }
+
+    fn transmit(&mut self, _timestamp: smoltcp::time::Instant) -> Option<Self::TxToken<'_>> {
+        Some(VethTxToken {
+            peer_rx: self.peer_rx.clone(),
+        })
+    }
+}
+
+/// ## driver的包裹器
+/// 为实现获得不可变引用的Interface的内部可变性，故为Driver提供UnsafeCell包裹器
+///
+/// 参考loopback.rs
+struct VethDriverWrapper(UnsafeCell<VethDriver>);
+unsafe impl Send for VethDriverWrapper {}
+unsafe impl Sync for VethDriverWrapper {}
+
+impl VethDriverWrapper {
+    #[allow(clippy::mut_from_ref)]
+    fn force_get_mut(&self) -> &mut VethDriver {
+        unsafe { &mut *self.0.get() }
+    }
+}
+
+/// ## veth网卡
+/// veth总是成对创建，从一端发送的数据包会从另一端被接收，
+/// 两端可以位于不同的network namespace中，从而把两个namespace连接起来
+///
+/// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/drivers/net/veth.c
+#[cast_to([sync] NetDevice)]
+#[cast_to([sync] Device)]
+pub struct VethInterface {
+    driver: VethDriverWrapper,
+    iface_id: usize,
+    iface: SpinLock<smoltcp::iface::Interface>,
+    name: String,
+    mac: EthernetAddress,
+    inner: SpinLock<InnerVethInterface>,
+    locked_kobj_state: LockedKObjectState,
+}
+
+#[derive(Debug)]
+pub struct InnerVethInterface {
+    netdevice_common: NetDeviceCommonData,
+    device_common: DeviceCommonData,
+    kobj_common: KObjectCommonData,
+}
+
+impl VethInterface {
+    fn new(name: String, mut driver: VethDriver) -> Arc<Self> {
+        let iface_id = generate_iface_id();
+        // 随机生成一个本地管理的单播mac地址
+        let mut mac = [0u8; 6];
+        mac[0] = 0x02;
+        for byte in mac[1..].iter_mut() {
+            *byte = rand() as u8;
+        }
+        let mac = EthernetAddress(mac);
+
+        let mut iface_config = smoltcp::iface::Config::new(HardwareAddress::Ethernet(mac));
+        iface_config.random_seed = rand() as u64;
+        let iface =
+            smoltcp::iface::Interface::new(iface_config, &mut driver, Instant::now().into());
+
+        let iface = Arc::new(VethInterface {
+            driver: VethDriverWrapper(UnsafeCell::new(driver)),
+            iface_id,
+            iface: SpinLock::new(iface),
+            name,
+            mac,
+            inner: SpinLock::new(InnerVethInterface {
+                netdevice_common: NetDeviceCommonData::default(),
+                device_common: DeviceCommonData::default(),
+                kobj_common: KObjectCommonData::default(),
+            }),
+            locked_kobj_state: LockedKObjectState::default(),
+        });
+        // 标识网络设备已经启动
+        iface.set_net_state(NetDeivceState::__LINK_STATE_START);
+        iface.set_operstate(Operstate::IF_OPER_UP);
+        iface
+    }
+
+    fn inner(&self) -> SpinLockGuard<InnerVethInterface> {
+        return self.inner.lock();
+    }
+}
+
+/// 创建一对veth网卡，并分别加入`netns0`与`netns1`
+///
+/// 网卡的名称为`veth<N>`。由于sysfs尚未区分network namespace，veth网卡不注册到sysfs中。
+/// 用户态通过socket上的`SIOCVETHCREATE` ioctl调用它，见[`crate::net::dev_ioctl`]。
+///
+/// ## 返回值
+/// 分别位于`netns0`与`netns1`中的两端网卡
+pub fn veth_pair_create(
+    netns0: &Arc<NetNamespace>,
+    netns1: &Arc<NetNamespace>,
+) -> (Arc<VethInterface>, Arc<VethInterface>) {
+    static VETH_ID: AtomicUsize = AtomicUsize::new(0);
+
+    let rx0 = VethQueue::new();
+    let rx1 = VethQueue::new();
+    let driver0 = VethDriver {
+        peer_rx: Arc::downgrade(&rx1),
+        rx: rx0,
+    };
+    let driver1 = VethDriver {
+        peer_rx: Arc::downgrade(&driver0.rx),
+        rx: rx1,
+    };
+
+    let id0 = VETH_ID.fetch_add(2, Ordering::SeqCst);
+    let veth0 = VethInterface::new(format!("veth{}", id0), driver0);
+    let veth1 = VethInterface::new(format!("veth{}", id0 + 1), driver1);
+
+    netns0.register_device(veth0.clone());
+    netns1.register_device(veth1.clone());
+
+    (veth0, veth1)
+}
+
+impl Debug for VethInterface {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("VethInterface")
+            .field("iface_id", &self.iface_id)
+            .field("iface", &"smtoltcp::iface::Interface")
+            .field("name", &self.name)
+            .field("mac", &self.mac)
+            .finish()
+    }
+}
+
+impl KObject for VethInterface {
+    fn as_any_ref(&self) -> &dyn core::any::Any {
+        self
+    }
+
+    fn set_inode(&self, inode: Option<Arc<KernFSInode>>) {
+        self.inner().kobj_common.kern_inode = inode;
+    }
+
+    fn inode(&self) -> Option<Arc<KernFSInode>> {
+        self.inner().kobj_common.kern_inode.clone()
+    }
+
+    fn parent(&self) -> Option<Weak<dyn KObject>> {
+        self.inner().kobj_common.parent.clone()
+    }
+
+    fn set_parent(&self, parent: Option<Weak<dyn KObject>>) {
+        self.inner().kobj_common.parent = parent;
+    }
+
+    fn kset(&self) -> Option<Arc<KSet>> {
+        self.inner().kobj_common.kset.clone()
+    }
+
+    fn set_kset(&self, kset: Option<Arc<KSet>>) {
+        self.inner().kobj_common.kset = kset;
+    }
+
+    fn kobj_type(&self) -> Option<&'static dyn KObjType> {
+        self.inner().kobj_common.kobj_type
+    }
+
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn set_name(&self, _name: String) {
+        // do nothing
+    }
+
+    fn kobj_state(&self) -> RwLockReadGuard<KObjectState> {
+        self.locked_kobj_state.read()
+    }
+
+    fn kobj_state_mut(&self) -> RwLockWriteGuard<KObjectState> {
+        self.locked_kobj_state.write()
+    }
+
+    fn set_kobj_state(&self, state: KObjectState) {
+        *self.locked_kobj_state.write() = state;
+    }
+
+    fn set_kobj_type(&self, ktype: Option<&'static dyn KObjType>) {
+        self.inner().kobj_common.kobj_type = ktype;
+    }
+}
+
+impl Device for VethInterface {
+    fn dev_type(&self) -> DeviceType {
+        DeviceType::Net
+    }
+
+    fn id_table(&self) -> IdTable {
+        IdTable::new(DEVICE_NAME.to_string(), None)
+    }
+
+    fn bus(&self) -> Option<Weak<dyn Bus>> {
+        self.inner().device_common.bus.clone()
+    }
+
+    fn set_bus(&self, bus: Option<Weak<dyn Bus>>) {
+        self.inner().device_common.bus = bus;
+    }
+
+    fn class(&self) -> Option<Arc<dyn Class>> {
+        let mut guard = self.inner();
+        let r = guard.device_common.class.clone()?.upgrade();
+        if r.is_none() {
+            guard.device_common.class = None;
+        }
+
+        return r;
+    }
+
+    fn set_class(&self, class: Option<Weak<dyn Class>>) {
+        self.inner().device_common.class = class;
+    }
+
+    fn driver(&self) -> Option<Arc<dyn Driver>> {
+        let r = self.inner().device_common.driver.clone()?.upgrade();
+        if r.is_none() {
+            self.inner().device_common.driver = None;
+        }
+
+        return r;
+    }
+
+    fn set_driver(&self, driver: Option<Weak<dyn Driver>>) {
+        self.inner().device_common.driver = driver;
+    }
+
+    fn is_dead(&self) -> bool {
+        false
+    }
+
+    fn can_match(&self) -> bool {
+        self.inner().device_common.can_match
+    }
+
+    fn set_can_match(&self, can_match: bool) {
+        self.inner().device_common.can_match = can_match;
+    }
+
+    fn state_synced(&self) -> bool {
+        true
+    }
+
+    fn dev_parent(&self) -> Option<Weak<dyn Device>> {
+        self.inner().device_common.get_parent_weak_or_clear()
+    }
+
+    fn set_dev_parent(&self, parent: Option<Weak<dyn Device>>) {
+        self.inner().device_common.parent = parent;
+    }
+}
+
+impl NetDevice for VethInterface {
+    fn mac(&self) -> EthernetAddress {
+        self.mac
+    }
+
+    #[inline]
+    fn nic_id(&self) -> usize {
+        self.iface_id
+    }
+
+    #[inline]
+    fn iface_name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn update_ip_addrs(&self, ip_addrs: &[smoltcp::wire::IpCidr]) -> Result<(), SystemError> {
+        if ip_addrs.len() != 1 {
+            return Err(SystemError::EINVAL);
+        }
+
+        self.iface.lock().update_ip_addrs(|addrs| {
+            let dest = addrs.iter_mut().next();
+
+            if let Some(dest) = dest {
+                *dest = ip_addrs[0];
+            } else {
+                addrs.push(ip_addrs[0]).expect("Push ipCidr failed: full");
+            }
+        });
+        return Ok(());
+    }
+
+    fn poll(&self, sockets: &mut smoltcp::iface::SocketSet) -> Result<(), SystemError> {
+        let timestamp: smoltcp::time::Instant = Instant::now().into();
+        let mut guard = self.iface.lock();
+        let poll_res = guard.poll(timestamp, self.driver.force_get_mut(), sockets);
+        if poll_res {
+            return Ok(());
+        }
+        return Err(SystemError::EAGAIN_OR_EWOULDBLOCK);
+    }
+
+    #[inline(always)]
+    fn inner_iface(&self) -> &SpinLock<smoltcp::iface::Interface> {
+        return &self.iface;
+    }
+
+    fn addr_assign_type(&self) -> u8 {
+        return self.inner().netdevice_common.addr_assign_type;
+    }
+
+    fn net_device_type(&self) -> u16 {
+        return self.inner().netdevice_common.net_device_type;
+    }
+
+    fn net_state(&self) -> NetDeivceState {
+        return self.inner().netdevice_common.state;
+    }
+
+    fn set_net_state(&self, state: NetDeivceState) {
+        self.inner().netdevice_common.state |= state;
+    }
+
+    fn operstate(&self) -> Operstate {
+        return self.inner().netdevice_common.operstate;
+    }
+
+    fn set_operstate(&self, state: Operstate) {
+        self.inner().netdevice_common.operstate = state;
+    }
+}
@@ -46,7 +46,8 @@ use crate::{
         rwlock::{RwLockReadGuard, RwLockWriteGuard},
         spinlock::{SpinLock, SpinLockGuard},
     },
-    net::{generate_iface_id, net_core::poll_ifaces_try_lock_onetime, NET_DEVICES},
+    net::{generate_iface_id, net_core::poll_ifaces_try_lock_onetime},
+    process::namespace::net::INIT_NET_NS,
     time::Instant,
 };
 use system_error::SystemError;
@@ -429,8 +430,8 @@ impl VirtioInterface {
 
 impl Drop for VirtioInterface {
     fn drop(&mut self) {
-        // 从全局的网卡接口信息表中删除这个网卡的接口信息
-        NET_DEVICES.write_irqsave().remove(&self.iface_id);
+        // 从初始network namespace的网卡列表中删除这个网卡的接口信息
+        INIT_NET_NS.unregister_device(self.iface_id);
     }
 }
 
@@ -840,10 +841,8 @@ impl VirtIODriver for VirtIONetDriver {
         // 在sysfs中注册iface
         register_netdevice(iface.clone() as Arc<dyn NetDevice>)?;
 
-        // 将网卡的接口信息注册到全局的网卡接口信息表中
-        NET_DEVICES
-            .write_irqsave()
-            .insert(iface.nic_id(), iface.clone());
+        // 将网卡的接口信息注册到初始network namespace的网卡列表中
+        INIT_NET_NS.register_device(iface.clone());
 
         return Ok(());
     }
@@ -0,0 +1,744 @@
+//! virtio-vsock驱动
+//!
+//! 设备有三个队列：接收队列、发送队列和事件队列。收到的数据包交给
+//! `net::socket::vsock`处理，连接管理和流控都在那里完成。
+//!
+//! 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/net/vmw_vsock/virtio_transport.c
+
+use core::{any::Any, fmt::Debug};
+
+use alloc::{
+    boxed::Box,
+    collections::{BTreeMap, LinkedList},
+    string::{String, ToString},
+    sync::{Arc, Weak},
+    vec,
+    vec::Vec,
+};
+use log::{error, info, warn};
+use system_error::SystemError;
+use unified_init::macros::unified_init;
+use virtio_drivers::transport::Transport;
+
+use crate::{
+    driver::{
+        base::{
+            class::Class,
+            device::{
+                bus::Bus,
+                driver::{Driver, DriverCommonData},
+                Device, DeviceCommonData, DeviceId, DeviceType, IdTable,
+            },
+            kobject::{KObjType, KObject, KObjectCommonData, KObjectState, LockedKObjectState},
+            kset::KSet,
+        },
+        virtio::{
+            queue::{virtio_begin_init, SplitVirtQueue},
+            sysfs::{virtio_bus, virtio_device_manager, virtio_driver_manager},
+            transport::VirtIOTransport,
+            VirtIODevice, VirtIODeviceIndex, VirtIODriver, VirtIODriverCommonData, VirtioDeviceId,
+            VIRTIO_VENDOR_ID,
+        },
+    },
+    exception::{irqdesc::IrqReturn, IrqNumber},
+    filesystem::kernfs::KernFSInode,
+    init::initcall::{INITCALL_DEVICE, INITCALL_POSTCORE},
+    libs::{
+        lazy_init::Lazy,
+        rwlock::{RwLockReadGuard, RwLockWriteGuard},
+        spinlock::{SpinLock, SpinLockGuard},
+    },
+    net::socket::vsock::{
+        vsock_recv_pkt, vsock_register_transport, vsock_transport_reset, VsockHeader,
+        VsockTransport,
+    },
+    process::{
+        kthread::{KernelThreadClosure, KernelThreadMechanism},
+        ProcessControlBlock, ProcessManager,
+    },
+    time::{sleep::nanosleep, PosixTimeSpec},
+};
+
+const VIRTIO_VSOCK_BASENAME: &str = "virtio_vsock";
+
+const VIRTIO_VSOCK_RX_QUEUE: u16 = 0;
+const VIRTIO_VSOCK_TX_QUEUE: u16 = 1;
+const VIRTIO_VSOCK_EVENT_QUEUE: u16 = 2;
+
+const VIRTIO_VSOCK_RX_QUEUE_SIZE: u16 = 64;
+const VIRTIO_VSOCK_TX_QUEUE_SIZE: u16 = 16;
+const VIRTIO_VSOCK_EVENT_QUEUE_SIZE: u16 = 8;
+
+/// 每个接收缓冲区的大小（包括数据包头部）
+///
+/// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/include/linux/virtio_vsock.h#11
+const VIRTIO_VSOCK_RX_BUF_SIZE: usize = 4096;
+/// 发送缓冲区的大小（包括数据包头部）
+const VIRTIO_VSOCK_TX_BUF_SIZE: usize = 64 * 1024;
+
+/// 事件：传输层被复位，所有连接失效，需要重新读取cid
+const VIRTIO_VSOCK_EVENT_TRANSPORT_RESET: u32 = 0;
+/// `struct virtio_vsock_event`的大小
+const VIRTIO_VSOCK_EVENT_SIZE: usize = 4;
+
+/// 没有中断可用时，轮询接收队列的间隔（纳秒）
+const VIRTIO_VSOCK_POLL_INTERVAL_NS: i64 = 10_000_000;
+
+static VIRTIO_VSOCK_DRIVER: Lazy<Arc<VirtIOVsockDriver>> = Lazy::new();
+static VIRTIO_VSOCK_POLL_THREAD: Lazy<Arc<ProcessControlBlock>> = Lazy::new();
+
+#[inline(always)]
+fn virtio_vsock_driver() -> Arc<VirtIOVsockDriver> {
+    VIRTIO_VSOCK_DRIVER.get().clone()
+}
+
+pub fn virtio_vsock(
+    transport: VirtIOTransport,
+    dev_id: Arc<DeviceId>,
+    dev_parent: Option<Arc<dyn Device>>,
+) {
+    let device = VirtIOVsockDevice::new(transport, dev_id);
+    if let Some(device) = device {
+        if let Some(dev_parent) = dev_parent {
+            device.set_dev_parent(Some(Arc::downgrade(&dev_parent)));
+        }
+        virtio_device_manager()
+            .device_add(device.clone() as Arc<dyn VirtIODevice>)
+            .expect("Add virtio vsock failed");
+
+        // 目前只使用第一个vsock设备
+        if vsock_register_transport(device.clone() as Arc<dyn VsockTransport>).is_err() {
+            warn!("virtio-vsock: only the first device is used");
+        }
+    }
+}
+
+/// virtio-vsock的配置空间
+///
+/// 规范中guest_cid是一个u64，这里拆成两半以满足配置空间的对齐要求
+#[repr(C)]
+struct VirtioVsockConfig {
+    guest_cid_low: u32,
+    guest_cid_high: u32,
+}
+
+/// virtio vsock device
+#[derive(Debug)]
+#[cast_to([sync] VirtIODevice)]
+#[cast_to([sync] Device)]
+pub struct VirtIOVsockDevice {
+    dev_id: Arc<DeviceId>,
+    /// 发送缓冲区，同时用于串行化发送
+    tx_buf: SpinLock<Vec<u8>>,
+    inner: SpinLock<InnerVirtIOVsockDevice>,
+    locked_kobj_state: LockedKObjectState,
+}
+
+unsafe impl Send for VirtIOVsockDevice {}
+unsafe impl Sync for VirtIOVsockDevice {}
+
+impl VirtIOVsockDevice {
+    pub fn new(mut transport: VirtIOTransport, dev_id: Arc<DeviceId>) -> Option<Arc<Self>> {
+        let irq = transport.irq().map(|irq| IrqNumber::new(irq.data()));
+        let r = virtio_begin_init(&mut transport, 0).and_then(|_| {
+            let rx = SplitVirtQueue::new(
+                &mut transport,
+                VIRTIO_VSOCK_RX_QUEUE,
+                VIRTIO_VSOCK_RX_QUEUE_SIZE,
+            )?;
+            let tx = SplitVirtQueue::new(
+                &mut transport,
+                VIRTIO_VSOCK_TX_QUEUE,
+                VIRTIO_VSOCK_TX_QUEUE_SIZE,
+            )?;
+            let event = SplitVirtQueue::new(
+                &mut transport,
+                VIRTIO_VSOCK_EVENT_QUEUE,
+                VIRTIO_VSOCK_EVENT_QUEUE_SIZE,
+            )?;
+            Ok((rx, tx, event))
+        });
+        let (rx, tx, event) = match r {
+            Ok(r) => r,
+            Err(e) => {
+                error!("VirtIOVsockDevice '{dev_id:?}' create failed: {:?}", e);
+                return None;
+            }
+        };
+
+        let mut inner = InnerVirtIOVsockDevice {
+            guest_cid: Self::read_guest_cid(&transport),
+            transport,
+            rx,
+            tx,
+            event,
+            rx_bufs: BTreeMap::new(),
+            event_bufs: BTreeMap::new(),
+            name: None,
+            virtio_index: None,
+            device_common: DeviceCommonData::default(),
+            kobject_common: KObjectCommonData::default(),
+            irq,
+        };
+        if let Err(e) = inner.fill_rx().and_then(|_| inner.fill_event()) {
+            error!("VirtIOVsockDevice '{dev_id:?}' fill queues failed: {:?}", e);
+            return None;
+        }
+        inner.transport.finish_init();
+        inner.rx.notify(&mut inner.transport);
+        inner.event.notify(&mut inner.transport);
+        info!("virtio-vsock: guest cid {}", inner.guest_cid);
+
+        let dev = Arc::new(Self {
+            dev_id,
+            tx_buf: SpinLock::new(vec![0; VIRTIO_VSOCK_TX_BUF_SIZE]),
+            locked_kobj_state: LockedKObjectState::default(),
+            inner: SpinLock::new(inner),
+        });
+
+        Some(dev)
+    }
+
+    fn read_guest_cid(transport: &VirtIOTransport) -> u32 {
+        let Ok(config) = transport.config_space::<VirtioVsockConfig>() else {
+            return 0;
+        };
+        // 有效的cid只有32位，高32位保留
+        unsafe { core::ptr::addr_of!((*config.as_ptr()).guest_cid_low).read_volatile() }
+    }
+
+    fn inner(&self) -> SpinLockGuard<InnerVirtIOVsockDevice> {
+        self.inner.lock_irqsave()
+    }
+
+    /// 处理接收队列和事件队列
+    ///
+    /// 数据包在释放设备锁之后才交给协议栈，因为协议栈可能需要回复数据包
+    ///
+    /// ## 返回值
+    ///
+    /// 是否收到了数据包或事件
+    fn poll(&self) -> bool {
+        let mut pkts = Vec::new();
+        let mut reset = false;
+        {
+            let mut inner = self.inner();
+            let inner = &mut *inner;
+
+            while let Some((token, len)) = inner.rx.pop_used() {
+                let Some(buf) = inner.rx_bufs.remove(&token) else {
+                    continue;
+                };
+                let len = core::cmp::min(len as usize, buf.len());
+                match VsockHeader::from_bytes(&buf[..len]) {
+                    Some(hdr) if VsockHeader::SIZE + hdr.len as usize <= len => {
+                        pkts.push((hdr, buf));
+                    }
+                    _ => warn!("virtio-vsock: dropped a malformed packet"),
+                }
+            }
+
+            while let Some((token, len)) = inner.event.pop_used() {
+                let Some(buf) = inner.event_bufs.remove(&token) else {
+                    continue;
+                };
+                if len as usize >= VIRTIO_VSOCK_EVENT_SIZE {
+                    let id = u32::from_le_bytes(buf[..4].try_into().unwrap());
+                    if id == VIRTIO_VSOCK_EVENT_TRANSPORT_RESET {
+                        reset = true;
+                    }
+                }
+            }
+
+            if reset {
+                inner.guest_cid = Self::read_guest_cid(&inner.transport);
+            }
+            inner.fill_rx().ok();
+            inner.fill_event().ok();
+            inner.rx.notify(&mut inner.transport);
+            inner.event.notify(&mut inner.transport);
+        }
+
+        if reset {
+            vsock_transport_reset();
+        }
+
+        let received = reset || !pkts.is_empty();
+        for (hdr, buf) in pkts {
+            let data = &buf[VsockHeader::SIZE..VsockHeader::SIZE + hdr.len as usize];
+            vsock_recv_pkt(&hdr, data);
+        }
+        received
+    }
+}
+
+impl VsockTransport for VirtIOVsockDevice {
+    fn guest_cid(&self) -> u32 {
+        self.inner().guest_cid
+    }
+
+    fn send_pkt(&self, hdr: &VsockHeader, data: &[u8]) -> Result<(), SystemError> {
+        let len = VsockHeader::SIZE + data.len();
+        if len > VIRTIO_VSOCK_TX_BUF_SIZE {
+            return Err(SystemError::EMSGSIZE);
+        }
+
+        let mut tx_buf = self.tx_buf.lock_irqsave();
+        tx_buf[..VsockHeader::SIZE].copy_from_slice(&hdr.to_bytes());
+        tx_buf[VsockHeader::SIZE..len].copy_from_slice(data);
+
+        let token = {
+            let mut inner = self.inner();
+            let inner = &mut *inner;
+            let token = unsafe { inner.tx.add(&[&tx_buf[..len]], &mut [])? };
+            inner.tx.notify(&mut inner.transport);
+            token
+        };
+
+        // 发送缓冲区只有一个，因此发送队列中只会有这一个请求
+        loop {
+            if let Some((t, _)) = self.inner().tx.pop_used() {
+                if t == token {
+                    return Ok(());
+                }
+                continue;
+            }
+            core::hint::spin_loop();
+        }
+    }
+
+    fn max_pkt_size(&self) -> usize {
+        VIRTIO_VSOCK_TX_BUF_SIZE - VsockHeader::SIZE
+    }
+}
+
+struct InnerVirtIOVsockDevice {
+    transport: VirtIOTransport,
+    rx: SplitVirtQueue,
+    tx: SplitVirtQueue,
+    event: SplitVirtQueue,
+    /// 已经放入接收队列的缓冲区（token -> 缓冲区）
+    rx_bufs: BTreeMap<u16, Box<[u8]>>,
+    /// 已经放入事件队列的缓冲区（token -> 缓冲区）
+    event_bufs: BTreeMap<u16, Box<[u8]>>,
+    guest_cid: u32,
+    name: Option<String>,
+    virtio_index: Option<VirtIODeviceIndex>,
+    device_common: DeviceCommonData,
+    kobject_common: KObjectCommonData,
+    irq: Option<IrqNumber>,
+}
+
+impl InnerVirtIOVsockDevice {
+    /// 把接收队列填满空闲的缓冲区
+    fn fill_rx(&mut self) -> Result<(), SystemError> {
+        while self.rx_bufs.len() < self.rx.size() as usize {
+            let mut buf = vec![0u8; VIRTIO_VSOCK_RX_BUF_SIZE].into_boxed_slice();
+            let token = unsafe { self.rx.add(&[], &mut [&mut buf[..]])? };
+            self.rx_bufs.insert(token, buf);
+        }
+        Ok(())
+    }
+
+    /// 把事件队列填满空闲的缓冲区
+    fn fill_event(&mut self) -> Result<(), SystemError> {
+        while self.event_bufs.len() < self.event.size() as usize {
+            let mut buf = vec![0u8; VIRTIO_VSOCK_EVENT_SIZE].into_boxed_slice();
+            let token = unsafe { self.event.add(&[], &mut [&mut buf[..]])? };
+            self.event_bufs.insert(token, buf);
+        }
+        Ok(())
+    }
+}
+
+impl Debug for InnerVirtIOVsockDevice {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("InnerVirtIOVsockDevice").finish()
+    }
+}
+
+impl VirtIODevice for VirtIOVsockDevice {
+    fn irq(&self) -> Option<IrqNumber> {
+        self.inner().irq
+    }
+
+    fn handle_irq(&self, _irq: IrqNumber) -> Result<IrqReturn, SystemError> {
+        if !self.inner().transport.ack_interrupt() {
+            return Ok(IrqReturn::NotHandled);
+        }
+
+        // 处理数据包时可能需要回复，因此交给轮询线程完成
+        if let Some(pcb) = VIRTIO_VSOCK_POLL_THREAD.try_get() {
+            ProcessManager::wakeup(pcb).ok();
+        }
+        Ok(IrqReturn::Handled)
+    }
+
+    fn dev_id(&self) -> &Arc<DeviceId> {
+        &self.dev_id
+    }
+
+    fn set_device_name(&self, name: String) {
+        self.inner().name = Some(name);
+    }
+
+    fn device_name(&self) -> String {
+        self.inner()
+            .name
+            .clone()
+            .unwrap_or_else(|| VIRTIO_VSOCK_BASENAME.to_string())
+    }
+
+    fn set_virtio_device_index(&self, index: VirtIODeviceIndex) {
+        self.inner().virtio_index = Some(index);
+    }
+
+    fn virtio_device_index(&self) -> Option<VirtIODeviceIndex> {
+        self.inner().virtio_index
+    }
+
+    fn device_type_id(&self) -> u32 {
+        virtio_drivers::transport::DeviceType::Socket as u32
+    }
+
+    fn vendor(&self) -> u32 {
+        VIRTIO_VENDOR_ID.into()
+    }
+}
+
+impl Device for VirtIOVsockDevice {
+    fn dev_type(&self) -> DeviceType {
+        DeviceType::Char
+    }
+
+    fn id_table(&self) -> IdTable {
+        IdTable::new(VIRTIO_VSOCK_BASENAME.to_string(), None)
+    }
+
+    fn bus(&self) -> Option<Weak<dyn Bus>> {
+        self.inner().device_common.bus.clone()
+    }
+
+    fn set_bus(&self, bus: Option<Weak<dyn Bus>>) {
+        self.inner().device_common.bus = bus;
+    }
+
+    fn class(&self) -> Option<Arc<dyn Class>> {
+        let mut guard = self.inner();
+        let r = guard.device_common.class.clone()?.upgrade();
+        if r.is_none() {
+            guard.device_common.class = None;
+        }
+
+        return r;
+    }
+
+    fn set_class(&self, class: Option<Weak<dyn Class>>) {
+        self.inner().device_common.class = class;
+    }
+
+    fn driver(&self) -> Option<Arc<dyn Driver>> {
+        let r = self.inner().device_common.driver.clone()?.upgrade();
+        if r.is_none() {
+            self.inner().device_common.driver = None;
+        }
+
+        return r;
+    }
+
+    fn set_driver(&self, driver: Option<Weak<dyn Driver>>) {
+        self.inner().device_common.driver = driver;
+    }
+
+    fn is_dead(&self) -> bool {
+        false
+    }
+
+    fn can_match(&self) -> bool {
+        self.inner().device_common.can_match
+    }
+
+    fn set_can_match(&self, can_match: bool) {
+        self.inner().device_common.can_match = can_match;
+    }
+
+    fn state_synced(&self) -> bool {
+        true
+    }
+
+    fn dev_parent(&self) -> Option<Weak<dyn Device>> {
+        self.inner().device_common.get_parent_weak_or_clear()
+    }
+
+    fn set_dev_parent(&self, parent: Option<Weak<dyn Device>>) {
+        self.inner().device_common.parent = parent;
+    }
+}
+
+impl KObject for VirtIOVsockDevice {
+    fn as_any_ref(&self) -> &dyn Any {
+        self
+    }
+
+    fn set_inode(&self, inode: Option<Arc<KernFSInode>>) {
+        self.inner().kobject_common.kern_inode = inode;
+    }
+
+    fn inode(&self) -> Option<Arc<KernFSInode>> {
+        self.inner().kobject_common.kern_inode.clone()
+    }
+
+    fn parent(&self) -> Option<Weak<dyn KObject>> {
+        self.inner().kobject_common.parent.clone()
+    }
+
+    fn set_parent(&self, parent: Option<Weak<dyn KObject>>) {
+        self.inner().kobject_common.parent = parent;
+    }
+
+    fn kset(&self) -> Option<Arc<KSet>> {
+        self.inner().kobject_common.kset.clone()
+    }
+
+    fn set_kset(&self, kset: Option<Arc<KSet>>) {
+        self.inner().kobject_common.kset = kset;
+    }
+
+    fn kobj_type(&self) -> Option<&'static dyn KObjType> {
+        self.inner().kobject_common.kobj_type
+    }
+
+    fn name(&self) -> String {
+        self.device_name()
+    }
+
+    fn set_name(&self, _name: String) {
+        // do nothing
+    }
+
+    fn kobj_state(&self) -> RwLockReadGuard<KObjectState> {
+        self.locked_kobj_state.read()
+    }
+
+    fn kobj_state_mut(&self) -> RwLockWriteGuard<KObjectState> {
+        self.locked_kobj_state.write()
+    }
+
+    fn set_kobj_state(&self, state: KObjectState) {
+        *self.locked_kobj_state.write() = state;
+    }
+
+    fn set_kobj_type(&self, ktype: Option<&'static dyn KObjType>) {
+        self.inner().kobject_common.kobj_type = ktype;
+    }
+}
+
+fn virtio_vsock_poll_thread() -> i32 {
+    loop {
+        let mut received = false;
+        for dev in virtio_vsock_driver().devices() {
+            if let Ok(dev) = dev.arc_any().downcast::<VirtIOVsockDevice>() {
+                received |= dev.poll();
+            }
+        }
+
+        if !received {
+            let _ = nanosleep(PosixTimeSpec::new(0, VIRTIO_VSOCK_POLL_INTERVAL_NS));
+        }
+    }
+}
+
+#[unified_init(INITCALL_POSTCORE)]
+fn virtio_vsock_driver_init() -> Result<(), SystemError> {
+    let driver = VirtIOVsockDriver::new();
+    virtio_driver_manager()
+        .register(driver.clone() as Arc<dyn VirtIODriver>)
+        .expect("Add virtio vsock driver failed");
+    VIRTIO_VSOCK_DRIVER.init(driver);
+
+    return Ok(());
+}
+
+#[unified_init(INITCALL_DEVICE)]
+fn virtio_vsock_poll_thread_init() -> Result<(), SystemError> {
+    let closure =
+        KernelThreadClosure::StaticEmptyClosure((&(virtio_vsock_poll_thread as fn() -> i32), ()));
+    let pcb = KernelThreadMechanism::create_and_run(closure, "kvsockd".to_string())
+        .ok_or(SystemError::ENOMEM)?;
+    VIRTIO_VSOCK_POLL_THREAD.init(pcb);
+
+    return Ok(());
+}
+
+#[derive(Debug)]
+#[cast_to([sync] VirtIODriver)]
+#[cast_to([sync] Driver)]
+struct VirtIOVsockDriver {
+    inner: SpinLock<InnerVirtIOVsockDriver>,
+    kobj_state: LockedKObjectState,
+}
+
+impl VirtIOVsockDriver {
+    pub fn new() -> Arc<Self> {
+        let inner = InnerVirtIOVsockDriver {
+            virtio_driver_common: VirtIODriverCommonData::default(),
+            driver_common: DriverCommonData::default(),
+            kobj_common: KObjectCommonData::default(),
+        };
+
+        let id_table = VirtioDeviceId::new(
+            virtio_drivers::transport::DeviceType::Socket as u32,
+            VIRTIO_VENDOR_ID.into(),
+        );
+        let result = VirtIOVsockDriver {
+            inner: SpinLock::new(inner),
+            kobj_state: LockedKObjectState::default(),
+        };
+        result.add_virtio_id(id_table);
+
+        return Arc::new(result);
+    }
+
+    fn inner(&self) -> SpinLockGuard<InnerVirtIOVsockDriver> {
+        return self.inner.lock();
+    }
+}
+
+#[derive(Debug)]
+struct InnerVirtIOVsockDriver {
+    virtio_driver_common: VirtIODriverCommonData,
+    driver_common: DriverCommonData,
+    kobj_common: KObjectCommonData,
+}
+
+impl VirtIODriver for VirtIOVsockDriver {
+    fn probe(&self, device: &Arc<dyn VirtIODevice>) -> Result<(), SystemError> {
+        let _dev = device
+            .clone()
+            .arc_any()
+            .downcast::<VirtIOVsockDevice>()
+            .map_err(|_| {
+                error!(
+                "VirtIOVsockDriver::probe() failed: device is not a VirtIO vsock device. Device: '{:?}'",
+                device.name()
+            );
+                SystemError::EINVAL
+            })?;
+
+        return Ok(());
+    }
+
+    fn virtio_id_table(&self) -> LinkedList<VirtioDeviceId> {
+        self.inner().virtio_driver_common.id_table.clone()
+    }
+
+    fn add_virtio_id(&self, id: VirtioDeviceId) {
+        self.inner().virtio_driver_common.id_table.push_back(id);
+    }
+}
+
+impl Driver for VirtIOVsockDriver {
+    fn id_table(&self) -> Option<IdTable> {
+        Some(IdTable::new(VIRTIO_VSOCK_BASENAME.to_string(), None))
+    }
+
+    fn add_device(&self, device: Arc<dyn Device>) {
+        let iface = device
+            .arc_any()
+            .downcast::<VirtIOVsockDevice>()
+            .expect("VirtIOVsockDriver::add_device() failed: device is not a VirtIOVsockDevice");
+
+        self.inner()
+            .driver_common
+            .devices
+            .push(iface as Arc<dyn Device>);
+    }
+
+    fn delete_device(&self, device: &Arc<dyn Device>) {
+        let _iface = device
+            .clone()
+            .arc_any()
+            .downcast::<VirtIOVsockDevice>()
+            .expect("VirtIOVsockDriver::delete_device() failed: device is not a VirtIOVsockDevice");
+
+        let mut guard = self.inner();
+        let index = guard
+            .driver_common
+            .devices
+            .iter()
+            .position(|dev| Arc::ptr_eq(device, dev))
+            .expect("VirtIOVsockDriver::delete_device() failed: device not found");
+
+        guard.driver_common.devices.remove(index);
+    }
+
+    fn devices(&self) -> Vec<Arc<dyn Device>> {
+        self.inner().driver_common.devices.clone()
+    }
+
+    fn bus(&self) -> Option<Weak<dyn Bus>> {
+        Some(Arc::downgrade(&virtio_bus()) as Weak<dyn Bus>)
+    }
+
+    fn set_bus(&self, _bus: Option<Weak<dyn Bus>>) {
+        // do nothing
+    }
+}
+
+impl KObject for VirtIOVsockDriver {
+    fn as_any_ref(&self) -> &dyn Any {
+        self
+    }
+
+    fn set_inode(&self, inode: Option<Arc<KernFSInode>>) {
+        self.inner().kobj_common.kern_inode = inode;
+    }
+
+    fn inode(&self) -> Option<Arc<KernFSInode>> {
+        self.inner().kobj_common.kern_inode.clone()
+    }
+
+    fn parent(&self) -> Option<Weak<dyn KObject>> {
+        self.inner().kobj_common.parent.clone()
+    }
+
+    fn set_parent(&self, parent: Option<Weak<dyn KObject>>) {
+        self.inner().kobj_common.parent = parent;
+    }
+
+    fn kset(&self) -> Option<Arc<KSet>> {
+        self.inner().kobj_common.kset.clone()
+    }
+
+    fn set_kset(&self, kset: Option<Arc<KSet>>) {
+        self.inner().kobj_common.kset = kset;
+    }
+
+    fn kobj_type(&self) -> Option<&'static dyn KObjType> {
+        self.inner().kobj_common.kobj_type
+    }
+
+    fn set_kobj_type(&self, ktype: Option<&'static dyn KObjType>) {
+        self.inner().kobj_common.kobj_type = ktype;
+    }
+
+    fn name(&self) -> String {
+        VIRTIO_VSOCK_BASENAME.to_string()
+    }
+
+    fn set_name(&self, _name: String) {
+        // do nothing
+    }
+
+    fn kobj_state(&self) -> RwLockReadGuard<KObjectState> {
+        self.kobj_state.read()
+    }
+
+    fn kobj_state_mut(&self) -> RwLockWriteGuard<KObjectState> {
+        self.kobj_state.write()
+    }
+
+    fn set_kobj_state(&self, state: KObjectState) {
+        *self.kobj_state.write() = state;
+    }
+}
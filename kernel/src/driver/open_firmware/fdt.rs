@@ -141,6 +141,8 @@ impl OpenFirmwareFdtDriver {
                     .write()
                     .boot_cmdline_append(bootargs.as_bytes());
             }
+
+            self.early_init_dt_check_for_initrd(&node);
         }
 
         // TODO: 拼接内核自定义的command line参数
@@ -149,6 +151,34 @@ impl OpenFirmwareFdtDriver {
         return Ok(());
     }
 
+    /// 从`/chosen`节点中获取initramfs的位置
+    ///
+    /// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/drivers/of/fdt.c#930
+    fn early_init_dt_check_for_initrd(&self, node: &FdtNode) {
+        let read_prop = |name: &str| -> Option<u64> {
+            let prop = node.property(name)?;
+            let cells = prop.value.len() / 4;
+            if cells != 1 && cells != 2 {
+                return None;
+            }
+            Some(read_cell(prop.value, 0, cells).0)
+        };
+
+        let (Some(start), Some(end)) = (
+            read_prop("linux,initrd-start"),
+            read_prop("linux,initrd-end"),
+        ) else {
+            return;
+        };
+        if end <= start {
+            return;
+        }
+
+        debug!("Found initrd: {:#x}-{:#x}", start, end);
+        boot_params().write().initrd =
+            Some((PhysAddr::new(start as usize), (end - start) as usize));
+    }
+
     /// 扫描 `/memory` 节点
     ///
     /// ## 参数
@@ -320,6 +350,12 @@ impl OpenFirmwareFdtDriver {
 
         self.fdt_scan_reserved_mem(&fdt)
             .expect("Failed to scan reserved memory");
+
+        // initramfs在被解压到根文件系统之前，不能被内存分配器使用
+        if let Some((base, size)) = boot_params().read().initrd {
+            debug!("Reserve initrd: {:?}-{:?}", base, base + size);
+            mem_block_manager().reserve_block(base, size).unwrap();
+        }
     }
 
     /// 保留fdt自身的内存空间
@@ -1,5 +1,10 @@
+use core::fmt::Debug;
+
+use alloc::{string::String, sync::Arc, vec::Vec};
 use system_error::SystemError;
 
+use crate::libs::rwlock::RwLock;
+
 use super::virtual_terminal::virtual_console::{
     CursorOperation, ScrollDir, VirtualConsoleData, VirtualConsoleIntensity,
 };
@@ -125,3 +130,48 @@ pub trait ConsoleSwitch: Sync + Send {
         nr: usize,
     ) -> bool;
 }
+
+/// 内核消息输出控制台
+///
+/// 与`ConsoleSwitch`不同，这里的控制台不负责虚拟终端的绘制，只负责把内核日志输出到对应的设备上（例如hvc）。
+///
+/// 参考 https://code.dragonos.org.cn/xref/linux-6.1.9/include/linux/console.h#142
+pub trait Console: Sync + Send + Debug {
+    /// 控制台的名称，例如`hvc0`
+    fn name(&self) -> String;
+
+    /// ## 向控制台输出内核消息
+    ///
+    /// 该函数可能在持有其他锁、甚至在中断上下文中被调用，因此实现者不应当在这里睡眠。
+    fn write(&self, buf: &[u8]);
+}
+
+/// 已注册的内核消息控制台
+static CONSOLES: RwLock<Vec<Arc<dyn Console>>> = RwLock::new(Vec::new());
+
+/// ## 注册内核消息控制台
+///
+/// 注册之后，printk输出的内容都会同步写入到该控制台
+pub fn register_console(console: Arc<dyn Console>) -> Result<(), SystemError> {
+    let mut consoles = CONSOLES.write_irqsave();
+    if consoles.iter().any(|c| c.name() == console.name()) {
+        return Err(SystemError::EEXIST);
+    }
+    consoles.push(console);
+    Ok(())
+}
+
+/// ## 取消注册内核消息控制台
+pub fn unregister_console(name: &str) {
+    CONSOLES.write_irqsave().retain(|c| c.name() != name);
+}
+
+/// ## 将内核消息写入所有已注册的控制台
+pub fn console_write(buf: &[u8]) {
+    // 如果控制台列表正在被修改，则丢弃本次输出，避免在printk中死锁
+    if let Some(consoles) = CONSOLES.try_read_irqsave() {
+        for console in consoles.iter() {
+            console.write(buf);
+        }
+    }
+}
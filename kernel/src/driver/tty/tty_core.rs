@@ -7,6 +7,7 @@ use alloc::{
     collections::LinkedList,
     string::String,
     sync::{Arc, Weak},
+    vec::Vec,
 };
 use system_error::SystemError;
 
@@ -18,7 +19,7 @@ use crate::{
         wait_queue::EventWaitQueue,
     },
     mm::VirtAddr,
-    net::event_poll::{EPollEventType, EPollItem},
+    net::event_poll::{EPollEventType, EPollItem, EventPoll},
     process::Pid,
     syscall::user_access::{UserBufferReader, UserBufferWriter},
 };
@@ -454,6 +455,21 @@ impl TtyCoreData {
     pub fn add_epitem(&self, epitem: Arc<EPollItem>) {
         self.epitems.lock().push_back(epitem)
     }
+
+    pub fn remove_epitem(&self, epoll: &Weak<SpinLock<EventPoll>>) -> Result<(), SystemError> {
+        let is_remove = !self
+            .epitems
+            .lock()
+            .extract_if(|x| x.epoll().ptr_eq(epoll))
+            .collect::<Vec<_>>()
+            .is_empty();
+
+        if is_remove {
+            return Ok(());
+        }
+
+        Err(SystemError::ENOENT)
+    }
 }
 
 impl TtyOperation for TtyCore {
@@ -469,7 +485,10 @@ impl TtyOperation for TtyCore {
 
     #[inline]
     fn write(&self, tty: &TtyCoreData, buf: &[u8], nr: usize) -> Result<usize, SystemError> {
-        send_to_default_serial8250_port(buf);
+        // hvc等系统控制台有自己的输出通道，不需要再镜像到串口
+        if self.core().driver().tty_driver_type() != TtyDriverType::System {
+            send_to_default_serial8250_port(buf);
+        }
         return self.core().tty_driver.driver_funcs().write(tty, buf, nr);
     }
 
@@ -7,6 +7,7 @@ use super::base::device::{driver::Driver, Device, DeviceId};
 
 pub(super) mod irq;
 pub mod mmio;
+pub mod queue;
 pub mod sysfs;
 pub mod transport;
 pub mod transport_mmio;
@@ -0,0 +1,360 @@
+//! 简单的split virtqueue实现
+//!
+//! virtio-drivers没有公开其内部的virtqueue，也没有实现entropy、9p等设备。
+//! 这里提供一个只依赖`Transport`的最小实现，供这些设备的驱动使用。
+//!
+//! 队列按照legacy接口要求的布局分配（描述符表、available ring依次排列，
+//! used ring对齐到下一个页边界），因此同时适用于legacy和modern的transport。
+//!
+//! 参考 https://docs.oasis-open.org/virtio/virtio/v1.1/csprd01/virtio-v1.1-csprd01.html#x1-240006
+
+use core::{
+    mem::size_of,
+    ptr::{addr_of_mut, NonNull},
+    sync::atomic::{fence, Ordering},
+};
+
+use system_error::SystemError;
+use virtio_drivers::{
+    transport::{DeviceStatus, Transport},
+    BufferDirection, Hal, PAGE_SIZE,
+};
+
+use crate::{
+    arch::MMArch,
+    libs::align::page_align_up,
+    mm::{MemoryManagementArch, VirtAddr},
+    sched::completion::Completion,
+    time::{jiffies::NSEC_PER_JIFFY, timer::schedule_timeout, Duration, Instant},
+};
+
+use super::virtio_impl::HalImpl;
+
+/// 所有设备都需要协商的特性：VIRTIO_F_VERSION_1
+pub const VIRTIO_F_VERSION_1: u64 = 1 << 32;
+
+const VIRTQ_DESC_F_NEXT: u16 = 1;
+const VIRTQ_DESC_F_WRITE: u16 = 2;
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+struct VirtqDesc {
+    addr: u64,
+    len: u32,
+    flags: u16,
+    next: u16,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+struct VirtqUsedElem {
+    id: u32,
+    len: u32,
+}
+
+/// 按照virtio规范完成设备初始化的前半部分（复位、特性协商）
+///
+/// 队列设置完毕后，需要调用`Transport::finish_init`来设置DRIVER_OK。
+///
+/// ## 返回值
+///
+/// 协商后的特性
+pub fn virtio_begin_init<T: Transport>(
+    transport: &mut T,
+    supported_features: u64,
+) -> Result<u64, SystemError> {
+    transport.set_status(DeviceStatus::empty());
+    transport.set_status(DeviceStatus::ACKNOWLEDGE | DeviceStatus::DRIVER);
+
+    let device_features = transport.read_device_features();
+    let features = device_features & (supported_features | VIRTIO_F_VERSION_1);
+    transport.write_driver_features(features);
+
+    transport
+        .set_status(DeviceStatus::ACKNOWLEDGE | DeviceStatus::DRIVER | DeviceStatus::FEATURES_OK);
+    if !transport.get_status().contains(DeviceStatus::FEATURES_OK) {
+        transport.set_status(DeviceStatus::FAILED);
+        return Err(SystemError::ENODEV);
+    }
+
+    transport.set_guest_page_size(PAGE_SIZE as u32);
+    Ok(features)
+}
+
+/// 同步请求的默认超时时间
+pub const VIRTQUEUE_REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// 复位设备
+///
+/// 请求超时之后调用，复位之后设备不会再访问队列中的缓冲区，设备也不再可用
+pub fn virtio_reset<T: Transport>(transport: &mut T) {
+    transport.set_status(DeviceStatus::empty());
+}
+
+/// # 等待设备处理完一个请求
+///
+/// 设备有中断时，驱动在中断处理函数中调用`completion.complete()`唤醒等待者，等待者只在被唤醒或超时的时候检查请求。
+/// 设备没有可用的中断时（`completion`为None），只能每个时钟周期轮询一次。调用者不能持有自旋锁
+///
+/// ## 参数
+///
+/// - `completion`: 设备的完成通知，设备没有中断时为None
+/// - `timeout`: 超时时间
+/// - `poll`: 检查请求是否已经完成，完成时返回设备写入的字节数
+///
+/// ## 返回值
+///
+/// - `Ok(u32)`: 设备写入的字节数
+/// - `Err(SystemError::ETIMEDOUT)`: 超时。设备可能仍在使用请求的缓冲区，调用者需要用[`virtio_reset`]复位设备
+pub fn virtqueue_wait_used(
+    completion: Option<&Completion>,
+    timeout: Duration,
+    mut poll: impl FnMut() -> Option<u32>,
+) -> Result<u32, SystemError> {
+    let deadline = Instant::now() + timeout;
+    loop {
+        if let Some(len) = poll() {
+            return Ok(len);
+        }
+        let now = Instant::now();
+        if now >= deadline {
+            return Err(SystemError::ETIMEDOUT);
+        }
+        match completion {
+            // 之前超时的请求迟到的中断也会唤醒等待者，因此被唤醒后仍然要检查请求是否完成
+            Some(completion) => {
+                let jiffies = (deadline - now).total_micros() * 1000 / NSEC_PER_JIFFY as u64;
+                completion.wait_for_completion_timeout(jiffies.max(1) as i64)?;
+            }
+            None => {
+                schedule_timeout(1)?;
+            }
+        }
+    }
+}
+
+/// split virtqueue
+///
+/// 队列被drop时释放其占用的DMA内存，在此之前设备必须已经被复位，不再访问队列。
+/// virtio-drivers的transport在drop时会复位设备，因此持有队列的结构体需要把transport声明在队列之前。
+#[derive(Debug)]
+pub struct SplitVirtQueue {
+    /// 队列编号
+    index: u16,
+    /// 队列长度
+    size: u16,
+    /// 队列所在DMA内存的物理地址
+    paddr: usize,
+    /// 队列所占的页数
+    pages: usize,
+    desc: NonNull<VirtqDesc>,
+    /// available ring: flags, idx, ring[size], used_event
+    avail: NonNull<u16>,
+    /// used ring: flags, idx, ring[size], avail_event
+    used: NonNull<u8>,
+    /// 空闲描述符链表的表头
+    free_head: u16,
+    num_free: u16,
+    avail_idx: u16,
+    last_used_idx: u16,
+}
+
+unsafe impl Send for SplitVirtQueue {}
+
+impl SplitVirtQueue {
+    /// 创建并向设备注册一个virtqueue
+    ///
+    /// ## 参数
+    ///
+    /// - `transport`: 设备的transport
+    /// - `index`: 队列编号
+    /// - `max_size`: 驱动希望使用的最大队列长度（必须是2的幂）
+    pub fn new<T: Transport>(
+        transport: &mut T,
+        index: u16,
+        max_size: u16,
+    ) -> Result<Self, SystemError> {
+        if transport.queue_used(index) {
+            return Err(SystemError::EBUSY);
+        }
+
+        let dev_max = transport.max_queue_size(index);
+        if dev_max == 0 {
+            return Err(SystemError::ENODEV);
+        }
+        let size = core::cmp::min(dev_max, max_size as u32) as u16;
+        if !size.is_power_of_two() {
+            return Err(SystemError::EINVAL);
+        }
+
+        let desc_size = size_of::<VirtqDesc>() * size as usize;
+        let avail_size = size_of::<u16>() * (3 + size as usize);
+        let used_size = size_of::<u16>() * 3 + size_of::<VirtqUsedElem>() * size as usize;
+        let used_offset = page_align_up(desc_size + avail_size);
+        let total = used_offset + page_align_up(used_size);
+
+        let pages = total / PAGE_SIZE;
+        let (paddr, vaddr) = HalImpl::dma_alloc(pages, BufferDirection::Both);
+
+        let desc = vaddr.cast::<VirtqDesc>();
+        let avail = unsafe { NonNull::new_unchecked(vaddr.as_ptr().add(desc_size)).cast::<u16>() };
+        let used = unsafe { NonNull::new_unchecked(vaddr.as_ptr().add(used_offset)) };
+
+        // 把所有描述符串成空闲链表
+        for i in 0..size {
+            unsafe {
+                addr_of_mut!((*desc.as_ptr().add(i as usize)).next).write_volatile(i + 1);
+            }
+        }
+
+        transport.queue_set(
+            index,
+            size as u32,
+            paddr,
+            paddr + desc_size,
+            paddr + used_offset,
+        );
+
+        Ok(Self {
+            index,
+            size,
+            paddr,
+            pages,
+            desc,
+            avail,
+            used,
+            free_head: 0,
+            num_free: size,
+            avail_idx: 0,
+            last_used_idx: 0,
+        })
+    }
+
+    /// 把一组缓冲区作为一个请求放入队列
+    ///
+    /// `inputs`为设备只读的缓冲区，`outputs`为设备可写的缓冲区。
+    /// 在请求被设备处理完（通过`pop_used`取回）之前，调用者必须保证缓冲区有效。
+    ///
+    /// ## 返回值
+    ///
+    /// 请求的token（描述符链表头的下标）
+    pub unsafe fn add(
+        &mut self,
+        inputs: &[&[u8]],
+        outputs: &mut [&mut [u8]],
+    ) -> Result<u16, SystemError> {
+        let count = inputs.len() + outputs.len();
+        if count == 0 {
+            return Err(SystemError::EINVAL);
+        }
+        if count > self.num_free as usize {
+            return Err(SystemError::ENOSPC);
+        }
+
+        let head = self.free_head;
+        let mut last = head;
+        let mut cur = head;
+        let buffers = inputs.iter().map(|buf| (buf.as_ptr(), buf.len(), 0)).chain(
+            outputs
+                .iter_mut()
+                .map(|buf| (buf.as_mut_ptr() as *const u8, buf.len(), VIRTQ_DESC_F_WRITE)),
+        );
+        for (ptr, len, flags) in buffers {
+            let desc = self.desc.as_ptr().add(cur as usize);
+            let paddr = MMArch::virt_2_phys(VirtAddr::new(ptr as usize))
+                .ok_or(SystemError::EFAULT)?
+                .data();
+            addr_of_mut!((*desc).addr).write_volatile(paddr as u64);
+            addr_of_mut!((*desc).len).write_volatile(len as u32);
+            addr_of_mut!((*desc).flags).write_volatile(flags | VIRTQ_DESC_F_NEXT);
+            last = cur;
+            cur = addr_of_mut!((*desc).next).read_volatile();
+        }
+
+        // 结束描述符链
+        let last_desc = self.desc.as_ptr().add(last as usize);
+        let flags = addr_of_mut!((*last_desc).flags).read_volatile();
+        addr_of_mut!((*last_desc).flags).write_volatile(flags & !VIRTQ_DESC_F_NEXT);
+        self.free_head = cur;
+        self.num_free -= count as u16;
+
+        // 放入available ring
+        let slot = self.avail_idx & (self.size - 1);
+        self.avail
+            .as_ptr()
+            .add(2 + slot as usize)
+            .write_volatile(head);
+        fence(Ordering::SeqCst);
+        self.avail_idx = self.avail_idx.wrapping_add(1);
+        self.avail.as_ptr().add(1).write_volatile(self.avail_idx);
+        fence(Ordering::SeqCst);
+
+        Ok(head)
+    }
+
+    /// 队列长度
+    pub fn size(&self) -> u16 {
+        self.size
+    }
+
+    /// 通知设备有新的请求
+    pub fn notify<T: Transport>(&self, transport: &mut T) {
+        transport.notify(self.index);
+    }
+
+    fn used_idx(&self) -> u16 {
+        unsafe { self.used.as_ptr().add(2).cast::<u16>().read_volatile() }
+    }
+
+    /// 设备是否已经处理完了一些请求
+    pub fn can_pop(&self) -> bool {
+        fence(Ordering::SeqCst);
+        self.last_used_idx != self.used_idx()
+    }
+
+    /// 取回一个已经被设备处理完的请求
+    ///
+    /// ## 返回值
+    ///
+    /// (请求的token, 设备写入的字节数)
+    pub fn pop_used(&mut self) -> Option<(u16, u32)> {
+        if !self.can_pop() {
+            return None;
+        }
+
+        let slot = self.last_used_idx & (self.size - 1);
+        let elem = unsafe {
+            self.used
+                .as_ptr()
+                .add(4 + slot as usize * size_of::<VirtqUsedElem>())
+                .cast::<VirtqUsedElem>()
+                .read_volatile()
+        };
+        self.last_used_idx = self.last_used_idx.wrapping_add(1);
+
+        // 把描述符链归还到空闲链表
+        let head = elem.id as u16;
+        let mut cur = head;
+        loop {
+            let desc = unsafe { self.desc.as_ptr().add(cur as usize) };
+            self.num_free += 1;
+            let flags = unsafe { addr_of_mut!((*desc).flags).read_volatile() };
+            if flags & VIRTQ_DESC_F_NEXT == 0 {
+                unsafe { addr_of_mut!((*desc).next).write_volatile(self.free_head) };
+                break;
+            }
+            cur = unsafe { addr_of_mut!((*desc).next).read_volatile() };
+        }
+        self.free_head = head;
+
+        Some((head, elem.len))
+    }
+}
+
+impl Drop for SplitVirtQueue {
+    fn drop(&mut self) {
+        unsafe {
+            HalImpl::dma_dealloc(self.paddr, self.desc.cast::<u8>(), self.pages);
+        }
+    }
+}
@@ -4,13 +4,17 @@ use super::virtio_impl::HalImpl;
 use crate::driver::base::device::bus::Bus;
 use crate::driver::base::device::{Device, DeviceId};
 use crate::driver::block::virtio_blk::virtio_blk;
+use crate::driver::char::virtio_console::virtio_console;
+use crate::driver::char::virtio_rng::virtio_rng;
 use crate::driver::net::virtio_net::virtio_net;
+use crate::driver::net::virtio_vsock::virtio_vsock;
 use crate::driver::pci::pci::{
     get_pci_device_structures_mut_by_vendor_id, PciDeviceStructure,
     PciDeviceStructureGeneralDevice, PCI_DEVICE_LINKEDLIST,
 };
 use crate::driver::pci::subsys::pci_bus;
 use crate::driver::virtio::transport::VirtIOTransport;
+use crate::filesystem::v9fs::trans_virtio::virtio_9p;
 use crate::libs::rwlock::RwLockWriteGuard;
 
 use alloc::string::String;
@@ -63,6 +67,9 @@ pub(super) fn virtio_device_init(
 ) {
     match transport.device_type() {
         DeviceType::Block => virtio_blk(transport, dev_id, dev_parent),
+        DeviceType::Console => virtio_console(transport, dev_id, dev_parent),
+        DeviceType::EntropySource => virtio_rng(transport, dev_id, dev_parent),
+        DeviceType::_9P => virtio_9p(transport, dev_id, dev_parent),
         DeviceType::GPU => {
             warn!("Not support virtio_gpu device for now");
         }
@@ -70,6 +77,7 @@ pub(super) fn virtio_device_init(
             warn!("Not support virtio_input device for now");
         }
         DeviceType::Network => virtio_net(transport, dev_id, dev_parent),
+        DeviceType::Socket => virtio_vsock(transport, dev_id, dev_parent),
         t => {
             warn!("Unrecognized virtio device: {:?}", t);
         }
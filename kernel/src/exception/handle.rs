@@ -6,6 +6,7 @@ use system_error::SystemError;
 
 use crate::{
     arch::{interrupt::TrapFrame, CurrentIrqArch},
+    driver::char::random::add_interrupt_randomness,
     exception::{irqchip::IrqChipFlags, irqdesc::InnerIrqDesc},
     libs::{once::Once, spinlock::SpinLockGuard},
     process::{ProcessFlags, ProcessManager},
@@ -288,6 +289,9 @@ fn do_handle_irq_event(desc: &Arc<IrqDesc>) -> Result<(), SystemError> {
         };
     }
 
+    // 中断到来的时间可以作为随机数子系统的熵源
+    add_interrupt_randomness(irq.data());
+
     return r.map(|_| ());
 }
 
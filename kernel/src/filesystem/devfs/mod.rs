@@ -1,5 +1,6 @@
 /// 导出devfs的模块
 pub mod null_dev;
+pub mod random_dev;
 pub mod zero_dev;
 
 use super::vfs::{
@@ -104,6 +105,7 @@ impl DevFS {
     /// @brief 注册系统内部自带的设备
     fn register_bultinin_device(&self) {
         use null_dev::LockedNullInode;
+        use random_dev::LockedRandomInode;
         use zero_dev::LockedZeroInode;
         let dev_root: Arc<LockedDevFSInode> = self.root_inode.clone();
         dev_root
@@ -112,6 +114,12 @@ impl DevFS {
         dev_root
             .add_dev("zero", LockedZeroInode::new())
             .expect("DevFS: Failed to register /dev/zero");
+        dev_root
+            .add_dev("random", LockedRandomInode::new(true))
+            .expect("DevFS: Failed to register /dev/random");
+        dev_root
+            .add_dev("urandom", LockedRandomInode::new(false))
+            .expect("DevFS: Failed to register /dev/urandom");
     }
 
     /// @brief 在devfs内注册设备
@@ -148,8 +156,8 @@ impl DevFS {
                 if name.starts_with("tty") && name.len() > 3 {
                     dev_root_inode.add_dev(name, device.clone())?;
                 }
-                // ptmx设备
-                if name == "ptmx" {
+                // ptmx、loop-control以及fuse设备
+                if name == "ptmx" || name == "loop-control" || name == "fuse" {
                     dev_root_inode.add_dev(name, device.clone())?;
                 }
                 device.set_fs(dev_char_inode.0.lock().fs.clone());
@@ -170,6 +178,10 @@ impl DevFS {
                     .unwrap();
 
                 dev_block_inode.add_dev(name, device.clone())?;
+                // loop设备同时挂载在 /dev 下
+                if name.starts_with("loop") {
+                    dev_root_inode.add_dev(name, device.clone())?;
+                }
                 device.set_fs(dev_block_inode.0.lock().fs.clone());
             }
             FileType::KvmDevice => {
@@ -224,6 +236,9 @@ impl DevFS {
                     .unwrap();
 
                 dev_block_inode.remove(name)?;
+                if name.starts_with("loop") {
+                    dev_root_inode.remove(name)?;
+                }
             }
             _ => {
                 return Err(SystemError::ENOSYS);
@@ -602,7 +617,6 @@ pub fn devfs_register<T: DeviceINode>(name: &str, device: Arc<T>) -> Result<(),
 }
 
 /// @brief devfs的设备卸载函数
-#[allow(dead_code)]
 pub fn devfs_unregister<T: DeviceINode>(name: &str, device: Arc<T>) -> Result<(), SystemError> {
     return devfs_exact_ref!().unregister_device(name, device);
 }
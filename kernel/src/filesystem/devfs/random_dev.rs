@@ -0,0 +1,191 @@
+use crate::driver::base::device::device_number::{DeviceNumber, Major};
+use crate::driver::char::random::{
+    crng_ready, get_random_bytes, wait_for_random_bytes, write_pool_user,
+};
+use crate::filesystem::vfs::file::FileMode;
+use crate::filesystem::vfs::syscall::ModeType;
+use crate::filesystem::vfs::{
+    core::generate_inode_id, FilePrivateData, FileSystem, FileType, IndexNode, Metadata,
+};
+use crate::libs::spinlock::SpinLockGuard;
+use crate::net::event_poll::EPollEventType;
+use crate::{libs::spinlock::SpinLock, time::PosixTimeSpec};
+use alloc::{
+    string::String,
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+use system_error::SystemError;
+
+use super::{DevFS, DeviceINode};
+
+/// /dev/random、/dev/urandom的文件私有信息，用于记录打开模式（是否为非阻塞）
+#[derive(Debug, Clone)]
+pub struct RandomFilePrivateData {
+    mode: FileMode,
+}
+
+impl RandomFilePrivateData {
+    pub fn new(mode: FileMode) -> Self {
+        Self { mode }
+    }
+
+    pub fn set_mode(&mut self, mode: FileMode) {
+        self.mode = mode;
+    }
+}
+
+/// /dev/random与/dev/urandom
+///
+/// 两者使用同一个CRNG，区别在于/dev/random会阻塞到CRNG完成初始化
+///
+/// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/drivers/char/random.c#1531
+#[derive(Debug)]
+pub struct RandomInode {
+    /// 指向自身的弱引用
+    self_ref: Weak<LockedRandomInode>,
+    /// 指向inode所在的文件系统对象的指针
+    fs: Weak<DevFS>,
+    /// INode 元数据
+    metadata: Metadata,
+    /// 在CRNG完成初始化之前，读取是否需要阻塞
+    blocking: bool,
+}
+
+#[derive(Debug)]
+pub struct LockedRandomInode(SpinLock<RandomInode>);
+
+impl LockedRandomInode {
+    /// ## 参数
+    ///
+    /// - `blocking`: 为true时创建/dev/random，否则创建/dev/urandom
+    pub fn new(blocking: bool) -> Arc<Self> {
+        let minor = if blocking { 8 } else { 9 };
+        let inode = RandomInode {
+            self_ref: Weak::default(),
+            fs: Weak::default(),
+            metadata: Metadata {
+                dev_id: 1,
+                inode_id: generate_inode_id(),
+                size: 0,
+                blk_size: 0,
+                blocks: 0,
+                atime: PosixTimeSpec::default(),
+                mtime: PosixTimeSpec::default(),
+                ctime: PosixTimeSpec::default(),
+                file_type: FileType::CharDevice,
+                mode: ModeType::from_bits_truncate(0o666),
+                nlinks: 1,
+                uid: 0,
+                gid: 0,
+                raw_dev: DeviceNumber::new(Major::MEM_MAJOR, minor),
+            },
+            blocking,
+        };
+
+        let result = Arc::new(LockedRandomInode(SpinLock::new(inode)));
+        result.0.lock().self_ref = Arc::downgrade(&result);
+
+        return result;
+    }
+}
+
+impl DeviceINode for LockedRandomInode {
+    fn set_fs(&self, fs: Weak<DevFS>) {
+        self.0.lock().fs = fs;
+    }
+}
+
+impl IndexNode for LockedRandomInode {
+    fn as_any_ref(&self) -> &dyn core::any::Any {
+        self
+    }
+
+    fn open(
+        &self,
+        mut data: SpinLockGuard<FilePrivateData>,
+        mode: &FileMode,
+    ) -> Result<(), SystemError> {
+        *data = FilePrivateData::Random(RandomFilePrivateData::new(*mode));
+        return Ok(());
+    }
+
+    fn close(&self, _data: SpinLockGuard<FilePrivateData>) -> Result<(), SystemError> {
+        return Ok(());
+    }
+
+    fn metadata(&self) -> Result<Metadata, SystemError> {
+        return Ok(self.0.lock().metadata.clone());
+    }
+
+    fn fs(&self) -> Arc<dyn FileSystem> {
+        return self.0.lock().fs.upgrade().unwrap();
+    }
+
+    fn list(&self) -> Result<Vec<String>, SystemError> {
+        Err(SystemError::ENOSYS)
+    }
+
+    fn set_metadata(&self, metadata: &Metadata) -> Result<(), SystemError> {
+        let mut inode = self.0.lock();
+        inode.metadata.atime = metadata.atime;
+        inode.metadata.mtime = metadata.mtime;
+        inode.metadata.ctime = metadata.ctime;
+        inode.metadata.mode = metadata.mode;
+        inode.metadata.uid = metadata.uid;
+        inode.metadata.gid = metadata.gid;
+
+        return Ok(());
+    }
+
+    fn poll(&self, _private_data: &FilePrivateData) -> Result<usize, SystemError> {
+        let mut events = EPollEventType::EPOLLOUT | EPollEventType::EPOLLWRNORM;
+        if !self.0.lock().blocking || crng_ready() {
+            events |= EPollEventType::EPOLLIN | EPollEventType::EPOLLRDNORM;
+        }
+        return Ok(events.bits() as usize);
+    }
+
+    fn read_at(
+        &self,
+        _offset: usize,
+        len: usize,
+        buf: &mut [u8],
+        data: SpinLockGuard<FilePrivateData>,
+    ) -> Result<usize, SystemError> {
+        if buf.len() < len {
+            return Err(SystemError::EINVAL);
+        }
+
+        if self.0.lock().blocking && !crng_ready() {
+            let nonblock = match &*data {
+                FilePrivateData::Random(pdata) => pdata.mode.contains(FileMode::O_NONBLOCK),
+                _ => false,
+            };
+            if nonblock {
+                return Err(SystemError::EAGAIN_OR_EWOULDBLOCK);
+            }
+            drop(data);
+            wait_for_random_bytes()?;
+        }
+
+        get_random_bytes(&mut buf[..len]);
+        return Ok(len);
+    }
+
+    /// 写入的数据会被混合进输入池，但不会被记为熵
+    fn write_at(
+        &self,
+        _offset: usize,
+        len: usize,
+        buf: &[u8],
+        _data: SpinLockGuard<FilePrivateData>,
+    ) -> Result<usize, SystemError> {
+        if buf.len() < len {
+            return Err(SystemError::EINVAL);
+        }
+
+        write_pool_user(&buf[..len]);
+        Ok(len)
+    }
+}
@@ -2,6 +2,7 @@ use alloc::string::ToString;
 use core::cmp::Ordering;
 use core::intrinsics::unlikely;
 use core::{any::Any, fmt::Debug};
+use linkme::distributed_slice;
 use log::error;
 use system_error::SystemError;
 
@@ -13,10 +14,13 @@ use alloc::{
 };
 
 use crate::driver::base::block::gendisk::GenDisk;
+use crate::driver::base::block::manager::block_dev_manager;
 use crate::driver::base::device::device_number::DeviceNumber;
 use crate::filesystem::vfs::file::PageCache;
 use crate::filesystem::vfs::utils::DName;
-use crate::filesystem::vfs::{Magic, SpecialNodeData, SuperBlock};
+use crate::filesystem::vfs::{
+    FileSystemMaker, FileSystemMakerData, Magic, SpecialNodeData, SuperBlock, FSMAKER,
+};
 use crate::ipc::pipe::LockedPipeInode;
 use crate::mm::fault::{PageFaultHandler, PageFaultMessage};
 use crate::mm::VmFaultReason;
@@ -44,6 +48,9 @@ use super::{
 
 const FAT_MAX_NAMELEN: u64 = 255;
 
+/// 挂载时使用的文件系统类型，`name()`也返回它，使`/proc/mounts`中的类型可以直接用于挂载
+const FAT_FS_NAME: &str = "vfat";
+
 /// FAT32文件系统的最大的文件大小
 pub const MAX_FILE_SIZE: u64 = 0xffff_ffff;
 
@@ -274,7 +281,7 @@ impl FileSystem for FATFileSystem {
     }
 
     fn name(&self) -> &str {
-        "fat"
+        FAT_FS_NAME
     }
 
     fn super_block(&self) -> SuperBlock {
@@ -1210,8 +1217,26 @@ impl FATFileSystem {
         self.gendisk.write_at_bytes(&zeros, offset)?;
         return Ok(());
     }
+
+    /// 供mount使用的构造函数，挂载源为块设备（或其分区）的路径，例如`/dev/sda1`
+    pub fn make_fatfs(
+        data: &FileSystemMakerData,
+    ) -> Result<Arc<dyn FileSystem + 'static>, SystemError> {
+        let gendisk = block_dev_manager()
+            .lookup_gendisk_by_path(data.source())
+            .ok_or(SystemError::ENODEV)?;
+        let fs = FATFileSystem::new(gendisk)?;
+        return Ok(fs);
+    }
 }
 
+#[distributed_slice(FSMAKER)]
+static FATFSMAKER: FileSystemMaker = FileSystemMaker::new(
+    FAT_FS_NAME,
+    &(FATFileSystem::make_fatfs
+        as fn(&FileSystemMakerData) -> Result<Arc<dyn FileSystem + 'static>, SystemError>),
+);
+
 impl Drop for FATFileSystem {
     fn drop(&mut self) {
         let r = self.umount();
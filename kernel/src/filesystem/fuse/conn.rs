@@ -0,0 +1,527 @@
+//! FUSE连接
+//!
+//! 每次打开/dev/fuse都会创建一个连接。文件系统把VFS的操作转换为请求，放入连接的队列中，
+//! 然后睡眠，直到守护进程从/dev/fuse读取请求，并把回复写回/dev/fuse。
+//!
+//! 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/fs/fuse/dev.c
+
+use core::{
+    mem::size_of,
+    sync::atomic::{AtomicU64, AtomicUsize, Ordering},
+};
+
+use alloc::{
+    collections::{BTreeMap, VecDeque},
+    sync::Arc,
+    vec::Vec,
+};
+use log::warn;
+use system_error::SystemError;
+
+use crate::{
+    libs::{spinlock::SpinLock, wait_queue::WaitQueue},
+    process::ProcessManager,
+    sched::SchedMode,
+};
+
+use super::protocol::{
+    opcode::*, parse_dirents, FuseAbi, FuseAttrOut, FuseCreateIn, FuseDirent, FuseEntryOut,
+    FuseForgetIn, FuseGetattrIn, FuseInHeader, FuseInitIn, FuseInitOut, FuseOpenIn, FuseOpenOut,
+    FuseOutHeader, FuseReadIn, FuseReleaseIn, FuseSetattrIn, FuseWriteIn, FuseWriteOut,
+    FUSE_KERNEL_MINOR_VERSION, FUSE_KERNEL_VERSION,
+};
+
+/// 单个READ、READDIR请求最多读取的字节数
+pub const FUSE_MAX_READ: usize = 128 * 1024;
+/// 守护进程没有在INIT中给出max_write时使用的值
+const FUSE_DEFAULT_MAX_WRITE: u32 = 4096;
+
+/// 等待回复的请求
+#[derive(Debug)]
+pub struct FuseRequest {
+    unique: u64,
+    opcode: u32,
+    /// 完整的请求消息，包括请求头
+    data: Vec<u8>,
+    /// 不等待回复的请求（例如RELEASE）
+    background: bool,
+    reply: SpinLock<Option<Result<Vec<u8>, SystemError>>>,
+    wait_queue: WaitQueue,
+}
+
+impl FuseRequest {
+    fn is_done(&self) -> bool {
+        self.reply.lock().is_some()
+    }
+
+    fn complete(&self, reply: Result<Vec<u8>, SystemError>) {
+        *self.reply.lock() = Some(reply);
+        self.wait_queue.wakeup_all(None);
+    }
+
+    /// 守护进程不会回复FORGET
+    fn noreply(&self) -> bool {
+        self.opcode == FUSE_FORGET
+    }
+}
+
+#[derive(Debug)]
+struct InnerFuseConn {
+    /// 为false时，连接已经断开（/dev/fuse被关闭，或者文件系统被卸载）
+    connected: bool,
+    /// 每个连接只能挂载一次
+    mounted: bool,
+    /// 是否已经收到了INIT的回复
+    initialized: bool,
+    /// 尚未被守护进程读取的请求
+    pending: VecDeque<Arc<FuseRequest>>,
+    /// 已经被守护进程读取，正在等待回复的请求
+    processing: BTreeMap<u64, Arc<FuseRequest>>,
+    /// 单个WRITE请求最多写入的字节数
+    max_write: u32,
+}
+
+/// FUSE连接
+#[derive(Debug)]
+pub struct FuseConn {
+    inner: SpinLock<InnerFuseConn>,
+    /// 守护进程在这里等待新的请求
+    read_wait: WaitQueue,
+    /// 文件系统的操作在这里等待INIT完成
+    init_wait: WaitQueue,
+    next_unique: AtomicU64,
+    /// 打开了这个连接的/dev/fuse文件的数量
+    dev_count: AtomicUsize,
+}
+
+impl FuseConn {
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self {
+            inner: SpinLock::new(InnerFuseConn {
+                connected: true,
+                mounted: false,
+                initialized: false,
+                pending: VecDeque::new(),
+                processing: BTreeMap::new(),
+                max_write: FUSE_DEFAULT_MAX_WRITE,
+            }),
+            read_wait: WaitQueue::default(),
+            init_wait: WaitQueue::default(),
+            next_unique: AtomicU64::new(1),
+            dev_count: AtomicUsize::new(1),
+        })
+    }
+
+    pub fn get_dev(&self) {
+        self.dev_count.fetch_add(1, Ordering::SeqCst);
+    }
+
+    /// 关闭/dev/fuse文件，最后一个文件被关闭时断开连接
+    pub fn put_dev(&self) {
+        if self.dev_count.fetch_sub(1, Ordering::SeqCst) == 1 {
+            self.abort();
+        }
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.inner.lock().connected
+    }
+
+    pub fn max_write(&self) -> usize {
+        self.inner.lock().max_write as usize
+    }
+
+    /// 断开连接，所有未完成的请求以ENOTCONN失败
+    ///
+    /// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/fs/fuse/dev.c#2173
+    pub fn abort(&self) {
+        let requests: Vec<Arc<FuseRequest>> = {
+            let mut inner = self.inner.lock();
+            if !inner.connected {
+                return;
+            }
+            inner.connected = false;
+            let mut requests: Vec<_> = inner.pending.drain(..).collect();
+            requests.extend(core::mem::take(&mut inner.processing).into_values());
+            requests
+        };
+
+        for req in requests {
+            req.complete(Err(SystemError::ENOTCONN));
+        }
+        self.read_wait.wakeup_all(None);
+        self.init_wait.wakeup_all(None);
+    }
+
+    /// 挂载时调用，向守护进程发送INIT
+    pub fn mount(&self) -> Result<(), SystemError> {
+        {
+            let mut inner = self.inner.lock();
+            if !inner.connected {
+                return Err(SystemError::ENOTCONN);
+            }
+            if inner.mounted {
+                return Err(SystemError::EINVAL);
+            }
+            inner.mounted = true;
+        }
+
+        let init = FuseInitIn {
+            major: FUSE_KERNEL_VERSION,
+            minor: FUSE_KERNEL_MINOR_VERSION,
+            max_readahead: 0,
+            flags: 0,
+        };
+        // 守护进程通常在mount返回之后才开始处理请求，因此不能在这里等待回复
+        self.queue(FUSE_INIT, 0, &[init.as_bytes()], true)?;
+        Ok(())
+    }
+
+    fn process_init_reply(&self, reply: &Result<Vec<u8>, SystemError>) {
+        let out = match reply {
+            Ok(data) if data.len() >= 8 => FuseInitOut::read_from_prefix(data),
+            _ => {
+                warn!("fuse: INIT failed: {:?}", reply.as_ref().err());
+                self.abort();
+                return;
+            }
+        };
+        if out.major != FUSE_KERNEL_VERSION {
+            warn!(
+                "fuse: unsupported protocol version {}.{}",
+                out.major, out.minor
+            );
+            self.abort();
+            return;
+        }
+
+        let mut inner = self.inner.lock();
+        if out.minor >= 5 {
+            inner.max_write = out.max_write.max(FUSE_DEFAULT_MAX_WRITE);
+        }
+        inner.initialized = true;
+        drop(inner);
+        self.init_wait.wakeup_all(None);
+    }
+
+    fn initialized_or_dead(&self) -> bool {
+        let inner = self.inner.lock();
+        inner.initialized || !inner.connected
+    }
+
+    /// 把请求放入队列，并唤醒守护进程
+    fn queue(
+        &self,
+        opcode: u32,
+        nodeid: u64,
+        args: &[&[u8]],
+        background: bool,
+    ) -> Result<Arc<FuseRequest>, SystemError> {
+        let len = size_of::<FuseInHeader>() + args.iter().map(|a| a.len()).sum::<usize>();
+        let pcb = ProcessManager::current_pcb();
+        let cred = pcb.cred();
+        let header = FuseInHeader {
+            len: len as u32,
+            opcode,
+            unique: self.next_unique.fetch_add(1, Ordering::Relaxed),
+            nodeid,
+            uid: cred.fsuid.data() as u32,
+            gid: cred.fsgid.data() as u32,
+            pid: pcb.pid().data() as u32,
+            padding: 0,
+        };
+
+        let mut data = Vec::with_capacity(len);
+        data.extend_from_slice(header.as_bytes());
+        for arg in args {
+            data.extend_from_slice(arg);
+        }
+        let req = Arc::new(FuseRequest {
+            unique: header.unique,
+            opcode,
+            data,
+            background,
+            reply: SpinLock::new(None),
+            wait_queue: WaitQueue::default(),
+        });
+
+        let mut inner = self.inner.lock();
+        if !inner.connected {
+            return Err(SystemError::ENOTCONN);
+        }
+        inner.pending.push_back(req.clone());
+        drop(inner);
+        self.read_wait.wakeup(None);
+        Ok(req)
+    }
+
+    /// 发送请求，并等待守护进程回复
+    ///
+    /// ## 返回值
+    ///
+    /// 回复中除回复头以外的部分。守护进程返回错误时，返回对应的`SystemError`
+    fn request(&self, opcode: u32, nodeid: u64, args: &[&[u8]]) -> Result<Vec<u8>, SystemError> {
+        if wq_wait_event_interruptible!(self.init_wait, self.initialized_or_dead(), {}).is_err() {
+            return Err(SystemError::ERESTARTSYS);
+        }
+
+        let req = self.queue(opcode, nodeid, args, false)?;
+        if wq_wait_event_interruptible!(req.wait_queue, req.is_done(), {}).is_err() {
+            // 被信号打断。如果守护进程还没有读取请求，可以安全地重新执行系统调用
+            let mut inner = self.inner.lock();
+            if !req.is_done() {
+                let unread = inner.pending.iter().any(|r| Arc::ptr_eq(r, &req));
+                if unread {
+                    inner.pending.retain(|r| !Arc::ptr_eq(r, &req));
+                    return Err(SystemError::ERESTARTSYS);
+                }
+                inner.processing.remove(&req.unique);
+                return Err(SystemError::EINTR);
+            }
+        }
+
+        let reply = req.reply.lock().take();
+        reply.unwrap_or(Err(SystemError::EIO))
+    }
+
+    /// 发送不需要等待回复的请求（FORGET、RELEASE等）
+    ///
+    /// 不会睡眠，因此可以在inode析构时调用
+    fn send_background(&self, opcode: u32, nodeid: u64, args: &[&[u8]]) {
+        // 连接已断开时，守护进程也不再需要这个请求
+        self.queue(opcode, nodeid, args, true).ok();
+    }
+
+    /// 守护进程读取一个请求，返回读取的字节数
+    pub fn read_request(&self, buf: &mut [u8], nonblock: bool) -> Result<usize, SystemError> {
+        loop {
+            let mut inner = self.inner.lock();
+            if !inner.connected {
+                return Err(SystemError::ENODEV);
+            }
+
+            if let Some(req) = inner.pending.pop_front() {
+                if req.data.len() > buf.len() {
+                    // 缓冲区放不下这个请求，与linux一样让请求以EIO失败
+                    drop(inner);
+                    req.complete(Err(SystemError::EIO));
+                    return Err(SystemError::EIO);
+                }
+                if !req.noreply() {
+                    inner.processing.insert(req.unique, req.clone());
+                }
+                drop(inner);
+
+                buf[..req.data.len()].copy_from_slice(&req.data);
+                return Ok(req.data.len());
+            }
+            drop(inner);
+
+            if nonblock {
+                return Err(SystemError::EAGAIN_OR_EWOULDBLOCK);
+            }
+            if wq_wait_event_interruptible!(self.read_wait, self.has_pending_or_dead(), {}).is_err()
+            {
+                return Err(SystemError::ERESTARTSYS);
+            }
+        }
+    }
+
+    fn has_pending_or_dead(&self) -> bool {
+        let inner = self.inner.lock();
+        !inner.pending.is_empty() || !inner.connected
+    }
+
+    /// 守护进程写入一个回复，返回写入的字节数
+    ///
+    /// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/fs/fuse/dev.c#1874
+    pub fn write_reply(&self, buf: &[u8]) -> Result<usize, SystemError> {
+        let header = FuseOutHeader::read_from(buf).map_err(|_| SystemError::EINVAL)?;
+        if header.len as usize != buf.len() {
+            return Err(SystemError::EINVAL);
+        }
+        if header.error > 0 || header.error <= -512 {
+            return Err(SystemError::EINVAL);
+        }
+        // 通知消息（unique为0）尚不支持
+        if header.unique == 0 {
+            return Err(SystemError::EINVAL);
+        }
+        let payload = &buf[size_of::<FuseOutHeader>()..];
+        if header.error != 0 && !payload.is_empty() {
+            return Err(SystemError::EINVAL);
+        }
+
+        let req = {
+            let mut inner = self.inner.lock();
+            if !inner.connected {
+                return Err(SystemError::ENOENT);
+            }
+            inner
+                .processing
+                .remove(&header.unique)
+                .ok_or(SystemError::ENOENT)?
+        };
+
+        let reply = if header.error != 0 {
+            Err(SystemError::from_posix_errno(header.error).unwrap_or(SystemError::EIO))
+        } else {
+            Ok(payload.to_vec())
+        };
+        if req.opcode == FUSE_INIT {
+            self.process_init_reply(&reply);
+        }
+        if !req.background {
+            req.complete(reply);
+        }
+        Ok(buf.len())
+    }
+
+    /// 是否有等待读取的请求（用于poll）
+    pub fn has_pending(&self) -> bool {
+        !self.inner.lock().pending.is_empty()
+    }
+
+    pub fn lookup(&self, parent: u64, name: &str) -> Result<FuseEntryOut, SystemError> {
+        let reply = self.request(FUSE_LOOKUP, parent, &[name.as_bytes(), b"\0"])?;
+        let entry = FuseEntryOut::read_from(&reply)?;
+        // nodeid为0表示文件不存在，并且守护进程希望缓存这个结果
+        if entry.nodeid == 0 {
+            return Err(SystemError::ENOENT);
+        }
+        Ok(entry)
+    }
+
+    /// 告诉守护进程，内核不再使用某个节点
+    pub fn forget(&self, nodeid: u64, nlookup: u64) {
+        let arg = FuseForgetIn { nlookup };
+        self.send_background(FUSE_FORGET, nodeid, &[arg.as_bytes()]);
+    }
+
+    pub fn getattr(&self, nodeid: u64) -> Result<FuseAttrOut, SystemError> {
+        let arg = FuseGetattrIn::default();
+        let reply = self.request(FUSE_GETATTR, nodeid, &[arg.as_bytes()])?;
+        FuseAttrOut::read_from(&reply)
+    }
+
+    pub fn setattr(&self, nodeid: u64, arg: &FuseSetattrIn) -> Result<FuseAttrOut, SystemError> {
+        let reply = self.request(FUSE_SETATTR, nodeid, &[arg.as_bytes()])?;
+        FuseAttrOut::read_from(&reply)
+    }
+
+    pub fn readlink(&self, nodeid: u64) -> Result<Vec<u8>, SystemError> {
+        self.request(FUSE_READLINK, nodeid, &[])
+    }
+
+    /// 打开文件或目录，返回文件句柄
+    pub fn open(&self, nodeid: u64, flags: u32, dir: bool) -> Result<u64, SystemError> {
+        let opcode = if dir { FUSE_OPENDIR } else { FUSE_OPEN };
+        let arg = FuseOpenIn {
+            flags,
+            open_flags: 0,
+        };
+        let reply = self.request(opcode, nodeid, &[arg.as_bytes()])?;
+        Ok(FuseOpenOut::read_from(&reply)?.fh)
+    }
+
+    /// 释放文件句柄，不等待回复
+    pub fn release(&self, nodeid: u64, fh: u64, flags: u32, dir: bool) {
+        let opcode = if dir { FUSE_RELEASEDIR } else { FUSE_RELEASE };
+        let arg = FuseReleaseIn {
+            fh,
+            flags,
+            ..Default::default()
+        };
+        self.send_background(opcode, nodeid, &[arg.as_bytes()]);
+    }
+
+    /// 在目录parent下创建并打开文件，返回新文件的属性与文件句柄
+    pub fn create(
+        &self,
+        parent: u64,
+        name: &str,
+        flags: u32,
+        mode: u32,
+    ) -> Result<(FuseEntryOut, FuseOpenOut), SystemError> {
+        let arg = FuseCreateIn {
+            flags,
+            mode,
+            umask: 0,
+            open_flags: 0,
+        };
+        let reply = self.request(
+            FUSE_CREATE,
+            parent,
+            &[arg.as_bytes(), name.as_bytes(), b"\0"],
+        )?;
+        let entry = FuseEntryOut::read_from(&reply)?;
+        let open = FuseOpenOut::read_from(&reply[size_of::<FuseEntryOut>()..])?;
+        Ok((entry, open))
+    }
+
+    pub fn unlink(&self, parent: u64, name: &str) -> Result<(), SystemError> {
+        self.request(FUSE_UNLINK, parent, &[name.as_bytes(), b"\0"])?;
+        Ok(())
+    }
+
+    /// 从已打开的文件中读取数据，单次最多读取`FUSE_MAX_READ`字节
+    pub fn read(
+        &self,
+        nodeid: u64,
+        fh: u64,
+        offset: u64,
+        buf: &mut [u8],
+    ) -> Result<usize, SystemError> {
+        let size = buf.len().min(FUSE_MAX_READ);
+        let arg = FuseReadIn {
+            fh,
+            offset,
+            size: size as u32,
+            ..Default::default()
+        };
+        let reply = self.request(FUSE_READ, nodeid, &[arg.as_bytes()])?;
+        let n = reply.len().min(size);
+        buf[..n].copy_from_slice(&reply[..n]);
+        Ok(n)
+    }
+
+    /// 向已打开的文件写入数据，单次最多写入max_write字节
+    pub fn write(
+        &self,
+        nodeid: u64,
+        fh: u64,
+        offset: u64,
+        buf: &[u8],
+    ) -> Result<usize, SystemError> {
+        let size = buf.len().min(self.max_write());
+        let arg = FuseWriteIn {
+            fh,
+            offset,
+            size: size as u32,
+            ..Default::default()
+        };
+        let reply = self.request(FUSE_WRITE, nodeid, &[arg.as_bytes(), &buf[..size]])?;
+        let written = FuseWriteOut::read_from(&reply)?.size as usize;
+        if written > size {
+            return Err(SystemError::EIO);
+        }
+        Ok(written)
+    }
+
+    /// 读取已打开的目录中，从offset开始的目录项
+    pub fn readdir(
+        &self,
+        nodeid: u64,
+        fh: u64,
+        offset: u64,
+    ) -> Result<Vec<FuseDirent>, SystemError> {
+        let arg = FuseReadIn {
+            fh,
+            offset,
+            size: FUSE_MAX_READ as u32,
+            ..Default::default()
+        };
+        let reply = self.request(FUSE_READDIR, nodeid, &[arg.as_bytes()])?;
+        parse_dirents(&reply)
+    }
+}
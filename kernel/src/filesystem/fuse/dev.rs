@@ -0,0 +1,194 @@
+//! /dev/fuse字符设备
+//!
+//! 每次打开都会创建一个新的FUSE连接。守护进程把文件描述符通过`fd=`挂载选项交给内核，
+//! 之后从这个文件描述符读取请求，并写入回复。
+//!
+//! 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/fs/fuse/dev.c#2297
+
+use core::any::Any;
+
+use alloc::{
+    string::String,
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+use system_error::SystemError;
+use unified_init::macros::unified_init;
+
+use crate::{
+    driver::base::device::device_number::{DeviceNumber, Major},
+    filesystem::{
+        devfs::{devfs_register, DevFS, DeviceINode},
+        vfs::{
+            core::generate_inode_id, file::FileMode, syscall::ModeType, FilePrivateData,
+            FileSystem, FileType, IndexNode, Metadata,
+        },
+    },
+    init::initcall::INITCALL_DEVICE,
+    libs::spinlock::{SpinLock, SpinLockGuard},
+    net::event_poll::EPollEventType,
+    time::PosixTimeSpec,
+};
+
+use super::{conn::FuseConn, protocol::FUSE_MIN_READ_BUFFER};
+
+/// /dev/fuse的次设备号
+const FUSE_MINOR: u32 = 229;
+
+/// /dev/fuse的文件私有信息
+#[derive(Debug, Clone)]
+pub struct FuseDevPrivateData {
+    conn: Arc<FuseConn>,
+    mode: FileMode,
+}
+
+impl FuseDevPrivateData {
+    pub fn conn(&self) -> &Arc<FuseConn> {
+        &self.conn
+    }
+
+    pub fn set_mode(&mut self, mode: FileMode) {
+        self.mode = mode;
+    }
+}
+
+/// /dev/fuse
+#[derive(Debug)]
+pub struct FuseDevInode {
+    fs: SpinLock<Weak<DevFS>>,
+    metadata: Metadata,
+}
+
+impl FuseDevInode {
+    fn new() -> Arc<Self> {
+        let metadata = Metadata {
+            dev_id: 1,
+            inode_id: generate_inode_id(),
+            size: 0,
+            blk_size: 0,
+            blocks: 0,
+            atime: PosixTimeSpec::default(),
+            mtime: PosixTimeSpec::default(),
+            ctime: PosixTimeSpec::default(),
+            file_type: FileType::CharDevice,
+            mode: ModeType::from_bits_truncate(0o666),
+            nlinks: 1,
+            uid: 0,
+            gid: 0,
+            raw_dev: DeviceNumber::new(Major::MISC_MAJOR, FUSE_MINOR),
+        };
+
+        Arc::new(Self {
+            fs: SpinLock::new(Weak::default()),
+            metadata,
+        })
+    }
+
+    fn private_data(data: &FilePrivateData) -> Result<&FuseDevPrivateData, SystemError> {
+        match data {
+            FilePrivateData::FuseDev(pdata) => Ok(pdata),
+            _ => Err(SystemError::EPERM),
+        }
+    }
+}
+
+impl DeviceINode for FuseDevInode {
+    fn set_fs(&self, fs: Weak<DevFS>) {
+        *self.fs.lock() = fs;
+    }
+}
+
+impl IndexNode for FuseDevInode {
+    fn as_any_ref(&self) -> &dyn Any {
+        self
+    }
+
+    fn open(
+        &self,
+        mut data: SpinLockGuard<FilePrivateData>,
+        mode: &FileMode,
+    ) -> Result<(), SystemError> {
+        // 复制文件描述符时，新的文件与原来的文件共享同一个连接
+        if let FilePrivateData::FuseDev(pdata) = &*data {
+            pdata.conn.get_dev();
+            return Ok(());
+        }
+
+        *data = FilePrivateData::FuseDev(FuseDevPrivateData {
+            conn: FuseConn::new(),
+            mode: *mode,
+        });
+        Ok(())
+    }
+
+    fn close(&self, data: SpinLockGuard<FilePrivateData>) -> Result<(), SystemError> {
+        if let FilePrivateData::FuseDev(pdata) = &*data {
+            pdata.conn.put_dev();
+        }
+        Ok(())
+    }
+
+    fn metadata(&self) -> Result<Metadata, SystemError> {
+        Ok(self.metadata.clone())
+    }
+
+    fn fs(&self) -> Arc<dyn FileSystem> {
+        self.fs.lock().upgrade().unwrap()
+    }
+
+    fn list(&self) -> Result<Vec<String>, SystemError> {
+        Err(SystemError::ENOTDIR)
+    }
+
+    fn poll(&self, private_data: &FilePrivateData) -> Result<usize, SystemError> {
+        let conn = Self::private_data(private_data)?.conn();
+        let mut events = EPollEventType::EPOLLOUT | EPollEventType::EPOLLWRNORM;
+        if !conn.is_connected() {
+            events |= EPollEventType::EPOLLERR;
+        } else if conn.has_pending() {
+            events |= EPollEventType::EPOLLIN | EPollEventType::EPOLLRDNORM;
+        }
+        Ok(events.bits() as usize)
+    }
+
+    /// 读取一个请求
+    fn read_at(
+        &self,
+        _offset: usize,
+        len: usize,
+        buf: &mut [u8],
+        data: SpinLockGuard<FilePrivateData>,
+    ) -> Result<usize, SystemError> {
+        let len = len.min(buf.len());
+        if len < FUSE_MIN_READ_BUFFER {
+            return Err(SystemError::EINVAL);
+        }
+
+        let pdata = Self::private_data(&data)?;
+        let conn = pdata.conn.clone();
+        let nonblock = pdata.mode.contains(FileMode::O_NONBLOCK);
+        drop(data);
+
+        conn.read_request(&mut buf[..len], nonblock)
+    }
+
+    /// 写入一个回复
+    fn write_at(
+        &self,
+        _offset: usize,
+        len: usize,
+        buf: &[u8],
+        data: SpinLockGuard<FilePrivateData>,
+    ) -> Result<usize, SystemError> {
+        let len = len.min(buf.len());
+        let conn = Self::private_data(&data)?.conn.clone();
+        drop(data);
+
+        conn.write_reply(&buf[..len])
+    }
+}
+
+#[unified_init(INITCALL_DEVICE)]
+fn fuse_dev_init() -> Result<(), SystemError> {
+    devfs_register("fuse", FuseDevInode::new())
+}
@@ -0,0 +1,687 @@
+//! FUSE文件系统
+//!
+//! 把VFS的操作转换为FUSE请求，发送给守护进程，并等待回复。
+//! 与v9fs一样不缓存目录项，文件属性则按照守护进程给出的attr_valid缓存。
+//!
+//! 目前支持LOOKUP、GETATTR、SETATTR、READLINK、OPEN、READ、WRITE、READDIR、CREATE、UNLINK、RELEASE，
+//! 其他操作（例如mkdir、rename）返回ENOSYS。
+//!
+//! 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/fs/fuse/inode.c
+
+use core::any::Any;
+
+use alloc::{
+    string::{String, ToString},
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+use linkme::distributed_slice;
+use system_error::SystemError;
+
+use crate::{
+    driver::base::device::device_number::{DeviceNumber, Major},
+    filesystem::vfs::{
+        file::{FileMode, FilePrivateData},
+        syscall::ModeType,
+        utils::DName,
+        FileSystem, FileSystemMaker, FileSystemMakerData, FileType, FsInfo, IndexNode, InodeId,
+        Magic, Metadata, SuperBlock, FSMAKER,
+    },
+    libs::spinlock::{SpinLock, SpinLockGuard},
+    mm::{fault::PageFaultMessage, VmFaultReason},
+    process::ProcessManager,
+    time::{Instant, PosixTimeSpec},
+};
+
+use super::{
+    conn::{FuseConn, FUSE_MAX_READ},
+    protocol::{FuseAttr, FuseAttrOut, FuseDirent, FuseSetattrIn, FuseSetattrValid, FUSE_ROOT_ID},
+};
+
+/// 文件名的最大长度
+const FUSE_MAX_NAMELEN: u64 = 255;
+
+/// 已打开的FUSE文件的私有信息
+#[derive(Debug, Clone)]
+pub struct FuseFilePrivateData {
+    /// 守护进程在OPEN时返回的文件句柄
+    fh: u64,
+    /// OPEN时使用的标志
+    flags: u32,
+}
+
+/// FUSE文件系统
+#[derive(Debug)]
+pub struct FuseFS {
+    conn: Arc<FuseConn>,
+    root_inode: Arc<LockedFuseInode>,
+    /// 单个READ请求最多读取的字节数
+    max_read: usize,
+}
+
+impl Drop for FuseFS {
+    fn drop(&mut self) {
+        // 卸载后，守护进程读取/dev/fuse时会得到ENODEV，从而退出
+        self.conn.abort();
+    }
+}
+
+/// FUSE文件系统的inode
+#[derive(Debug)]
+pub struct LockedFuseInode(SpinLock<FuseInode>);
+
+#[derive(Debug)]
+pub struct FuseInode {
+    conn: Arc<FuseConn>,
+    /// 守护进程中的节点号
+    nodeid: u64,
+    /// LOOKUP成功的次数，inode被释放时通过FORGET告诉守护进程
+    nlookup: u64,
+    /// 父目录（根目录为None）
+    parent: Option<Arc<LockedFuseInode>>,
+    /// 指向自身的弱引用
+    self_ref: Weak<LockedFuseInode>,
+    fs: Weak<FuseFS>,
+    name: DName,
+    /// 最近一次从守护进程取得的元数据
+    metadata: Metadata,
+    /// 元数据失效的时间（微秒）
+    attr_expire: i64,
+}
+
+impl Drop for FuseInode {
+    fn drop(&mut self) {
+        if self.nodeid != FUSE_ROOT_ID && self.nlookup > 0 {
+            self.conn.forget(self.nodeid, self.nlookup);
+        }
+    }
+}
+
+impl FuseFS {
+    /// 挂载FUSE文件系统
+    ///
+    /// ## 参数
+    ///
+    /// - `data.options`: `fd=`为已打开的/dev/fuse的文件描述符，`rootmode=`为根目录的类型（八进制），
+    ///   两者都是必需的。另外支持`max_read=`，`user_id=`、`group_id=`、`default_permissions`、
+    ///   `allow_other`会被接受，但权限检查由守护进程负责
+    pub fn make_fusefs(data: &FileSystemMakerData) -> Result<Arc<dyn FileSystem>, SystemError> {
+        let mut fd = None;
+        let mut rootmode = None;
+        let mut max_read = FUSE_MAX_READ;
+        for (key, value) in data.options() {
+            match (key, value) {
+                ("fd", Some(v)) => fd = Some(v.parse::<i32>().map_err(|_| SystemError::EINVAL)?),
+                ("rootmode", Some(v)) => {
+                    rootmode = Some(u32::from_str_radix(v, 8).map_err(|_| SystemError::EINVAL)?)
+                }
+                ("max_read", Some(v)) => {
+                    let v = v.parse::<usize>().map_err(|_| SystemError::EINVAL)?;
+                    max_read = max_read.min(v.max(1));
+                }
+                ("user_id" | "group_id" | "blksize", Some(_)) => {}
+                ("default_permissions" | "allow_other", None) => {}
+                _ => return Err(SystemError::EINVAL),
+            }
+        }
+        let fd = fd.ok_or(SystemError::EINVAL)?;
+        let rootmode = ModeType::from_bits_truncate(rootmode.ok_or(SystemError::EINVAL)?);
+
+        let file = ProcessManager::current_pcb()
+            .fd_table()
+            .read()
+            .get_file_by_fd(fd)
+            .ok_or(SystemError::EBADF)?;
+        let conn = match &*file.private_data.lock() {
+            FilePrivateData::FuseDev(pdata) => pdata.conn().clone(),
+            _ => return Err(SystemError::EINVAL),
+        };
+        conn.mount()?;
+
+        // 与linux一样，根目录的属性在第一次GETATTR之前只有类型是已知的
+        let attr = FuseAttr {
+            ino: FUSE_ROOT_ID,
+            mode: rootmode.bits(),
+            nlink: 2,
+            ..Default::default()
+        };
+        let root_inode = LockedFuseInode::new(&conn, FUSE_ROOT_ID, 0, None, DName::default());
+        root_inode.0.lock().metadata = attr_to_metadata(&attr);
+        let fs = Arc::new(FuseFS {
+            conn,
+            root_inode: root_inode.clone(),
+            max_read,
+        });
+        root_inode.0.lock().fs = Arc::downgrade(&fs);
+
+        Ok(fs)
+    }
+}
+
+#[distributed_slice(FSMAKER)]
+static FUSEFSMAKER: FileSystemMaker = FileSystemMaker::new(
+    "fuse",
+    &(FuseFS::make_fusefs
+        as fn(&FileSystemMakerData) -> Result<Arc<dyn FileSystem + 'static>, SystemError>),
+);
+
+impl FileSystem for FuseFS {
+    fn root_inode(&self) -> Arc<dyn IndexNode> {
+        self.root_inode.clone()
+    }
+
+    fn info(&self) -> FsInfo {
+        FsInfo {
+            blk_dev_id: 0,
+            max_name_len: FUSE_MAX_NAMELEN as usize,
+        }
+    }
+
+    fn as_any_ref(&self) -> &dyn Any {
+        self
+    }
+
+    fn name(&self) -> &str {
+        "fuse"
+    }
+
+    fn super_block(&self) -> SuperBlock {
+        SuperBlock::new(Magic::FUSE_MAGIC, 4096, FUSE_MAX_NAMELEN)
+    }
+
+    /// 文件没有页缓存，暂不支持mmap
+    unsafe fn fault(&self, _pfm: &mut PageFaultMessage) -> VmFaultReason {
+        VmFaultReason::VM_FAULT_SIGBUS
+    }
+
+    unsafe fn map_pages(
+        &self,
+        _pfm: &mut PageFaultMessage,
+        _start_pgoff: usize,
+        _end_pgoff: usize,
+    ) -> VmFaultReason {
+        VmFaultReason::VM_FAULT_SIGBUS
+    }
+}
+
+/// 把旧格式的32位dev_t解码为设备号
+///
+/// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/include/linux/kdev_t.h#new_decode_dev
+fn decode_dev(rdev: u32) -> DeviceNumber {
+    let major = (rdev & 0xfff00) >> 8;
+    let minor = (rdev & 0xff) | ((rdev >> 12) & 0xfff00);
+    DeviceNumber::new(Major::new(major), minor)
+}
+
+/// 把守护进程返回的属性转换为元数据
+fn attr_to_metadata(attr: &FuseAttr) -> Metadata {
+    let mode = ModeType::from_bits_truncate(attr.mode);
+    let file_type = match mode & ModeType::S_IFMT {
+        ModeType::S_IFDIR => FileType::Dir,
+        ModeType::S_IFLNK => FileType::SymLink,
+        ModeType::S_IFCHR => FileType::CharDevice,
+        ModeType::S_IFBLK => FileType::BlockDevice,
+        ModeType::S_IFIFO => FileType::Pipe,
+        ModeType::S_IFSOCK => FileType::Socket,
+        _ => FileType::File,
+    };
+
+    Metadata {
+        dev_id: 0,
+        inode_id: InodeId::new(attr.ino as usize),
+        size: attr.size as i64,
+        blk_size: attr.blksize as usize,
+        blocks: attr.blocks as usize,
+        atime: PosixTimeSpec::new(attr.atime as i64, attr.atimensec as i64),
+        mtime: PosixTimeSpec::new(attr.mtime as i64, attr.mtimensec as i64),
+        ctime: PosixTimeSpec::new(attr.ctime as i64, attr.ctimensec as i64),
+        file_type,
+        mode: mode & !ModeType::S_IFMT,
+        nlinks: attr.nlink as usize,
+        uid: attr.uid as usize,
+        gid: attr.gid as usize,
+        raw_dev: decode_dev(attr.rdev),
+    }
+}
+
+/// 取出open时得到的文件句柄
+fn file_fh(data: &FilePrivateData) -> Option<u64> {
+    match data {
+        FilePrivateData::Fuse(pdata) => Some(pdata.fh),
+        _ => None,
+    }
+}
+
+/// 计算属性的失效时间
+fn attr_expire(valid: u64, valid_nsec: u32) -> i64 {
+    let valid_us = i64::try_from(valid)
+        .unwrap_or(i64::MAX)
+        .saturating_mul(1_000_000)
+        .saturating_add(valid_nsec as i64 / 1000);
+    Instant::now().total_micros().saturating_add(valid_us)
+}
+
+impl LockedFuseInode {
+    fn new(
+        conn: &Arc<FuseConn>,
+        nodeid: u64,
+        nlookup: u64,
+        parent: Option<Arc<LockedFuseInode>>,
+        name: DName,
+    ) -> Arc<Self> {
+        let fs = parent
+            .as_ref()
+            .map(|p| p.0.lock().fs.clone())
+            .unwrap_or_default();
+
+        let inode = Arc::new(Self(SpinLock::new(FuseInode {
+            conn: conn.clone(),
+            nodeid,
+            nlookup,
+            parent,
+            self_ref: Weak::new(),
+            fs,
+            name,
+            metadata: Metadata::default(),
+            attr_expire: 0,
+        })));
+        inode.0.lock().self_ref = Arc::downgrade(&inode);
+
+        inode
+    }
+
+    fn conn(&self) -> Arc<FuseConn> {
+        self.0.lock().conn.clone()
+    }
+
+    fn nodeid(&self) -> u64 {
+        self.0.lock().nodeid
+    }
+
+    fn file_type(&self) -> FileType {
+        self.0.lock().metadata.file_type
+    }
+
+    fn check_dir(&self) -> Result<(), SystemError> {
+        if self.file_type() != FileType::Dir {
+            return Err(SystemError::ENOTDIR);
+        }
+        Ok(())
+    }
+
+    fn max_read(&self) -> usize {
+        self.0
+            .lock()
+            .fs
+            .upgrade()
+            .map(|fs| fs.max_read)
+            .unwrap_or(FUSE_MAX_READ)
+    }
+
+    /// 用GETATTR、SETATTR的回复更新元数据
+    fn update_attr(&self, out: &FuseAttrOut) -> Metadata {
+        let metadata = attr_to_metadata(&out.attr);
+        let mut guard = self.0.lock();
+        guard.metadata = metadata.clone();
+        guard.attr_expire = attr_expire(out.attr_valid, out.attr_valid_nsec);
+        metadata
+    }
+
+    fn setattr(&self, arg: &FuseSetattrIn) -> Result<(), SystemError> {
+        let out = self.conn().setattr(self.nodeid(), arg)?;
+        self.update_attr(&out);
+        Ok(())
+    }
+
+    /// 在当前目录下查找name，返回新的inode
+    fn lookup(&self, name: &str) -> Result<Arc<LockedFuseInode>, SystemError> {
+        self.check_dir()?;
+        let (conn, nodeid, self_arc) = {
+            let guard = self.0.lock();
+            (
+                guard.conn.clone(),
+                guard.nodeid,
+                guard.self_ref.upgrade().ok_or(SystemError::ENOENT)?,
+            )
+        };
+
+        let entry = conn.lookup(nodeid, name)?;
+        let inode = LockedFuseInode::new(&conn, entry.nodeid, 1, Some(self_arc), name.into());
+        let mut guard = inode.0.lock();
+        guard.metadata = attr_to_metadata(&entry.attr);
+        guard.attr_expire = attr_expire(entry.attr_valid, entry.attr_valid_nsec);
+        drop(guard);
+
+        Ok(inode)
+    }
+
+    /// 使用已打开的文件句柄执行f
+    ///
+    /// 文件不是通过open打开的时（例如内核内部的读写），临时打开一个文件句柄
+    ///
+    /// 请求会睡眠，因此调用者需要先从文件私有信息中取出文件句柄，并释放私有信息的锁
+    fn with_fh<R>(
+        &self,
+        fh: Option<u64>,
+        write: bool,
+        f: impl FnOnce(&FuseConn, u64, u64) -> Result<R, SystemError>,
+    ) -> Result<R, SystemError> {
+        let conn = self.conn();
+        let nodeid = self.nodeid();
+        if let Some(fh) = fh {
+            return f(&conn, nodeid, fh);
+        }
+
+        let flags = if write {
+            FileMode::O_WRONLY.bits()
+        } else {
+            FileMode::O_RDONLY.bits()
+        };
+        let fh = conn.open(nodeid, flags, false)?;
+        let r = f(&conn, nodeid, fh);
+        conn.release(nodeid, fh, flags, false);
+        r
+    }
+
+    /// 读取目录中的所有目录项
+    fn read_dir(&self) -> Result<Vec<FuseDirent>, SystemError> {
+        self.check_dir()?;
+        let conn = self.conn();
+        let nodeid = self.nodeid();
+        let flags = (FileMode::O_RDONLY | FileMode::O_DIRECTORY).bits();
+        let fh = conn.open(nodeid, flags, true)?;
+
+        let mut result = Vec::new();
+        let mut offset = 0;
+        let r = loop {
+            match conn.readdir(nodeid, fh, offset) {
+                Ok(entries) if entries.is_empty() => break Ok(()),
+                Ok(entries) => {
+                    offset = entries.last().unwrap().off;
+                    result.extend(entries);
+                }
+                Err(e) => break Err(e),
+            }
+        };
+        conn.release(nodeid, fh, flags, true);
+        r.map(|_| result)
+    }
+}
+
+impl IndexNode for LockedFuseInode {
+    fn open(
+        &self,
+        mut data: SpinLockGuard<FilePrivateData>,
+        mode: &FileMode,
+    ) -> Result<(), SystemError> {
+        // 目录在读取时才打开
+        if self.file_type() != FileType::File {
+            return Ok(());
+        }
+
+        // 没有协商FUSE_ATOMIC_O_TRUNC，因此O_TRUNC由之后的SETATTR完成
+        let flags = (*mode
+            & !(FileMode::O_CREAT | FileMode::O_EXCL | FileMode::O_NOCTTY | FileMode::O_TRUNC))
+            .bits();
+        let fh = self.conn().open(self.nodeid(), flags, false)?;
+        *data = FilePrivateData::Fuse(FuseFilePrivateData { fh, flags });
+        Ok(())
+    }
+
+    fn close(&self, data: SpinLockGuard<FilePrivateData>) -> Result<(), SystemError> {
+        if let FilePrivateData::Fuse(pdata) = &*data {
+            self.conn()
+                .release(self.nodeid(), pdata.fh, pdata.flags, false);
+        }
+        Ok(())
+    }
+
+    fn read_at(
+        &self,
+        offset: usize,
+        len: usize,
+        buf: &mut [u8],
+        data: SpinLockGuard<FilePrivateData>,
+    ) -> Result<usize, SystemError> {
+        let len = core::cmp::min(len, buf.len());
+        let buf = &mut buf[..len];
+
+        match self.file_type() {
+            FileType::Dir => return Err(SystemError::EISDIR),
+            FileType::SymLink => {
+                let target = self.conn().readlink(self.nodeid())?;
+                if offset >= target.len() {
+                    return Ok(0);
+                }
+                let n = core::cmp::min(len, target.len() - offset);
+                buf[..n].copy_from_slice(&target[offset..offset + n]);
+                return Ok(n);
+            }
+            _ => {}
+        }
+
+        let fh = file_fh(&data);
+        drop(data);
+        let max_read = self.max_read();
+        self.with_fh(fh, false, |conn, nodeid, fh| {
+            let mut done = 0;
+            while done < len {
+                let chunk = core::cmp::min(len - done, max_read);
+                let n = conn.read(
+                    nodeid,
+                    fh,
+                    (offset + done) as u64,
+                    &mut buf[done..done + chunk],
+                )?;
+                done += n;
+                // 读到的数据比请求的少，说明已经到达文件末尾
+                if n < chunk {
+                    break;
+                }
+            }
+            Ok(done)
+        })
+    }
+
+    fn write_at(
+        &self,
+        offset: usize,
+        len: usize,
+        buf: &[u8],
+        data: SpinLockGuard<FilePrivateData>,
+    ) -> Result<usize, SystemError> {
+        let len = core::cmp::min(len, buf.len());
+        if self.file_type() == FileType::Dir {
+            return Err(SystemError::EISDIR);
+        }
+
+        let fh = file_fh(&data);
+        drop(data);
+        let done = self.with_fh(fh, true, |conn, nodeid, fh| {
+            let mut done = 0;
+            while done < len {
+                let n = conn.write(nodeid, fh, (offset + done) as u64, &buf[done..len])?;
+                if n == 0 {
+                    break;
+                }
+                done += n;
+            }
+            Ok(done)
+        })?;
+
+        let mut guard = self.0.lock();
+        let end = (offset + done) as i64;
+        if end > guard.metadata.size {
+            guard.metadata.size = end;
+        }
+        Ok(done)
+    }
+
+    fn metadata(&self) -> Result<Metadata, SystemError> {
+        {
+            let guard = self.0.lock();
+            if Instant::now().total_micros() < guard.attr_expire {
+                return Ok(guard.metadata.clone());
+            }
+        }
+
+        let out = self.conn().getattr(self.nodeid())?;
+        Ok(self.update_attr(&out))
+    }
+
+    fn set_metadata(&self, metadata: &Metadata) -> Result<(), SystemError> {
+        let old = self.0.lock().metadata.clone();
+        let mut arg = FuseSetattrIn::default();
+        let mut valid = FuseSetattrValid::empty();
+
+        if metadata.mode != old.mode {
+            valid |= FuseSetattrValid::MODE;
+            arg.mode = (metadata.mode & !ModeType::S_IFMT).bits();
+        }
+        if metadata.uid != old.uid {
+            valid |= FuseSetattrValid::UID;
+            arg.uid = metadata.uid as u32;
+        }
+        if metadata.gid != old.gid {
+            valid |= FuseSetattrValid::GID;
+            arg.gid = metadata.gid as u32;
+        }
+        if metadata.atime != old.atime {
+            valid |= FuseSetattrValid::ATIME;
+            arg.atime = metadata.atime.tv_sec as u64;
+            arg.atimensec = metadata.atime.tv_nsec as u32;
+        }
+        if metadata.mtime != old.mtime {
+            valid |= FuseSetattrValid::MTIME;
+            arg.mtime = metadata.mtime.tv_sec as u64;
+            arg.mtimensec = metadata.mtime.tv_nsec as u32;
+        }
+        if valid.is_empty() {
+            return Ok(());
+        }
+
+        arg.valid = valid.bits();
+        self.setattr(&arg)
+    }
+
+    fn resize(&self, len: usize) -> Result<(), SystemError> {
+        self.truncate(len)
+    }
+
+    fn truncate(&self, len: usize) -> Result<(), SystemError> {
+        if self.file_type() == FileType::Dir {
+            return Err(SystemError::EISDIR);
+        }
+        let arg = FuseSetattrIn {
+            valid: FuseSetattrValid::SIZE.bits(),
+            size: len as u64,
+            ..Default::default()
+        };
+        self.setattr(&arg)
+    }
+
+    fn create_with_data(
+        &self,
+        name: &str,
+        file_type: FileType,
+        mode: ModeType,
+        _data: usize,
+    ) -> Result<Arc<dyn IndexNode>, SystemError> {
+        self.check_dir()?;
+        // MKDIR、MKNOD等请求尚未实现
+        if file_type != FileType::File {
+            return Err(SystemError::ENOSYS);
+        }
+        if name.len() > FUSE_MAX_NAMELEN as usize {
+            return Err(SystemError::ENAMETOOLONG);
+        }
+
+        let (conn, nodeid, self_arc) = {
+            let guard = self.0.lock();
+            (
+                guard.conn.clone(),
+                guard.nodeid,
+                guard.self_ref.upgrade().ok_or(SystemError::ENOENT)?,
+            )
+        };
+        let flags = (FileMode::O_CREAT | FileMode::O_EXCL | FileMode::O_WRONLY).bits();
+        let mode = ModeType::S_IFREG.bits() | (mode & !ModeType::S_IFMT).bits();
+        let (entry, open) = conn.create(nodeid, name, flags, mode)?;
+        // VFS会在之后单独打开这个文件，因此先释放CREATE打开的文件句柄
+        conn.release(entry.nodeid, open.fh, flags, false);
+
+        let inode = LockedFuseInode::new(&conn, entry.nodeid, 1, Some(self_arc), name.into());
+        let mut guard = inode.0.lock();
+        guard.metadata = attr_to_metadata(&entry.attr);
+        guard.attr_expire = attr_expire(entry.attr_valid, entry.attr_valid_nsec);
+        drop(guard);
+
+        Ok(inode)
+    }
+
+    fn unlink(&self, name: &str) -> Result<(), SystemError> {
+        self.check_dir()?;
+        self.conn().unlink(self.nodeid(), name)
+    }
+
+    fn find(&self, name: &str) -> Result<Arc<dyn IndexNode>, SystemError> {
+        self.check_dir()?;
+        let guard = self.0.lock();
+        match name {
+            "" | "." => {
+                return guard
+                    .self_ref
+                    .upgrade()
+                    .map(|i| i as Arc<dyn IndexNode>)
+                    .ok_or(SystemError::ENOENT)
+            }
+            ".." => {
+                return guard
+                    .parent
+                    .clone()
+                    .or_else(|| guard.self_ref.upgrade())
+                    .map(|i| i as Arc<dyn IndexNode>)
+                    .ok_or(SystemError::ENOENT)
+            }
+            _ => {}
+        }
+        drop(guard);
+
+        Ok(self.lookup(name)?)
+    }
+
+    fn get_entry_name(&self, ino: InodeId) -> Result<String, SystemError> {
+        self.read_dir()?
+            .into_iter()
+            .find(|e| InodeId::new(e.ino as usize) == ino && e.name != "." && e.name != "..")
+            .map(|e| e.name)
+            .ok_or(SystemError::ENOENT)
+    }
+
+    fn fs(&self) -> Arc<dyn FileSystem> {
+        self.0.lock().fs.upgrade().unwrap()
+    }
+
+    fn as_any_ref(&self) -> &dyn Any {
+        self
+    }
+
+    fn list(&self) -> Result<Vec<String>, SystemError> {
+        let mut names: Vec<String> = self.read_dir()?.into_iter().map(|e| e.name).collect();
+        // 部分守护进程不会返回"."和".."
+        for special in ["..", "."] {
+            if !names.iter().any(|n| n == special) {
+                names.insert(0, special.to_string());
+            }
+        }
+        Ok(names)
+    }
+
+    fn dname(&self) -> Result<DName, SystemError> {
+        Ok(self.0.lock().name.clone())
+    }
+
+    fn parent(&self) -> Result<Arc<dyn IndexNode>, SystemError> {
+        self.find("..")
+    }
+}
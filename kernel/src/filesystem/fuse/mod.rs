@@ -0,0 +1,10 @@
+//! FUSE：在用户态实现的文件系统
+//!
+//! 守护进程打开/dev/fuse，然后执行
+//! `mount -t fuse -o fd=<fd>,rootmode=40000 <name> <dir>`挂载文件系统。
+//! 之后对挂载点的访问会被转换为FUSE请求，守护进程从/dev/fuse读取请求，处理后把回复写回/dev/fuse。
+
+pub mod conn;
+pub mod dev;
+pub mod fs;
+pub mod protocol;
@@ -0,0 +1,22 @@
+//! FUSE协议的消息格式
+//!
+//! 与内核无关的部分（结构体、操作码与目录项的解析）位于`fuse_protocol` crate中
+//!
+//! 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/include/uapi/linux/fuse.h
+
+pub use fuse_protocol::*;
+
+bitflags! {
+    /// fuse_setattr_in中有效的字段
+    ///
+    /// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/include/uapi/linux/fuse.h#303
+    pub struct FuseSetattrValid: u32 {
+        const MODE = 1 << 0;
+        const UID = 1 << 1;
+        const GID = 1 << 2;
+        const SIZE = 1 << 3;
+        const ATIME = 1 << 4;
+        const MTIME = 1 << 5;
+        const FH = 1 << 6;
+    }
+}
@@ -0,0 +1,834 @@
+//! inotify：监视文件系统中的文件与目录的变化
+//!
+//! 每个inotify实例对应一个`InotifyInode`，它持有若干个watch，并维护一个事件队列。
+//! 所有的watch同时被登记在全局的`INOTIFY_MARKS`中，VFS在操作文件时通过`fsnotify_*`系列函数
+//! 查找被监视的inode，并把事件投递到对应的inotify实例。
+//!
+//! 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/fs/notify/inotify/inotify_user.c
+
+use core::{
+    any::Any,
+    mem::size_of,
+    sync::atomic::{AtomicU32, AtomicUsize, Ordering},
+};
+
+use alloc::{
+    collections::{BTreeMap, LinkedList, VecDeque},
+    string::{String, ToString},
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+use system_error::SystemError;
+
+use crate::{
+    filesystem::vfs::{
+        fcntl::AtFlags,
+        file::{File, FileMode},
+        syscall::ModeType,
+        utils::{inode_identity, user_path_at},
+        FilePrivateData, FileSystem, FileType, IndexNode, Metadata, MAX_PATHLEN,
+        VFS_MAX_FOLLOW_SYMLINK_TIMES,
+    },
+    libs::{
+        casting::DowncastArc,
+        spinlock::{SpinLock, SpinLockGuard},
+        wait_queue::WaitQueue,
+    },
+    net::event_poll::{EPollEventType, EPollItem, EventPoll, KernelIoctlData},
+    process::ProcessManager,
+    sched::{schedule, SchedMode},
+    syscall::{user_access::check_and_clone_cstr, Syscall},
+};
+
+/// 每个inotify实例的事件队列最多容纳的事件数
+const INOTIFY_MAX_QUEUED_EVENTS: usize = 16384;
+/// 每个inotify实例最多拥有的watch数
+const INOTIFY_MAX_USER_WATCHES: usize = 8192;
+/// `IN_ALL_EVENTS`中的事件数
+const INOTIFY_EVENT_BITS: usize = 12;
+/// 按inode统计watch数时使用的桶数
+const INOTIFY_MARK_BUCKETS: usize = 64;
+
+bitflags! {
+    /// inotify_init1的标志
+    pub struct InotifyFlags: u32 {
+        const IN_NONBLOCK = FileMode::O_NONBLOCK.bits();
+        const IN_CLOEXEC = FileMode::O_CLOEXEC.bits();
+    }
+
+    /// inotify的事件与inotify_add_watch的标志
+    ///
+    /// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/include/uapi/linux/inotify.h
+    pub struct InotifyMask: u32 {
+        /// 文件被读取
+        const IN_ACCESS = 0x00000001;
+        /// 文件被写入
+        const IN_MODIFY = 0x00000002;
+        /// 元数据被修改
+        const IN_ATTRIB = 0x00000004;
+        /// 以可写方式打开的文件被关闭
+        const IN_CLOSE_WRITE = 0x00000008;
+        /// 以只读方式打开的文件被关闭
+        const IN_CLOSE_NOWRITE = 0x00000010;
+        /// 文件被打开
+        const IN_OPEN = 0x00000020;
+        /// 文件被移出被监视的目录
+        const IN_MOVED_FROM = 0x00000040;
+        /// 文件被移入被监视的目录
+        const IN_MOVED_TO = 0x00000080;
+        /// 在被监视的目录中创建了文件
+        const IN_CREATE = 0x00000100;
+        /// 被监视的目录中的文件被删除
+        const IN_DELETE = 0x00000200;
+        /// 被监视的文件被删除
+        const IN_DELETE_SELF = 0x00000400;
+        /// 被监视的文件被移动
+        const IN_MOVE_SELF = 0x00000800;
+
+        /// 被监视的文件所在的文件系统被卸载
+        const IN_UNMOUNT = 0x00002000;
+        /// 事件队列溢出
+        const IN_Q_OVERFLOW = 0x00004000;
+        /// watch被移除
+        const IN_IGNORED = 0x00008000;
+
+        /// 只监视目录
+        const IN_ONLYDIR = 0x01000000;
+        /// 不跟随符号链接
+        const IN_DONT_FOLLOW = 0x02000000;
+        /// 不再报告已经从目录中删除的文件的事件
+        const IN_EXCL_UNLINK = 0x04000000;
+        /// 只创建新的watch，inode已经被监视时返回EEXIST
+        const IN_MASK_CREATE = 0x10000000;
+        /// 把掩码添加到已有的watch上，而不是替换它
+        const IN_MASK_ADD = 0x20000000;
+        /// 事件的对象是一个目录
+        const IN_ISDIR = 0x40000000;
+        /// 只报告一次事件，之后移除watch
+        const IN_ONESHOT = 0x80000000;
+
+        const IN_CLOSE = Self::IN_CLOSE_WRITE.bits() | Self::IN_CLOSE_NOWRITE.bits();
+        const IN_MOVE = Self::IN_MOVED_FROM.bits() | Self::IN_MOVED_TO.bits();
+        const IN_ALL_EVENTS = 0x00000fff;
+    }
+}
+
+/// 用户空间的`struct inotify_event`（不包括其后的文件名）
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+struct PosixInotifyEvent {
+    wd: i32,
+    mask: u32,
+    cookie: u32,
+    len: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct InotifyEvent {
+    wd: i32,
+    mask: InotifyMask,
+    cookie: u32,
+    name: Option<String>,
+}
+
+impl InotifyEvent {
+    /// 文件名以'\0'结尾，并且填充到`struct inotify_event`大小的整数倍
+    fn name_len(&self) -> usize {
+        match &self.name {
+            Some(name) => (name.len() + 1).next_multiple_of(size_of::<PosixInotifyEvent>()),
+            None => 0,
+        }
+    }
+
+    fn size(&self) -> usize {
+        size_of::<PosixInotifyEvent>() + self.name_len()
+    }
+
+    fn write_to(&self, buf: &mut [u8]) {
+        let header = PosixInotifyEvent {
+            wd: self.wd,
+            mask: self.mask.bits(),
+            cookie: self.cookie,
+            len: self.name_len() as u32,
+        };
+        let header_size = size_of::<PosixInotifyEvent>();
+        // SAFETY: PosixInotifyEvent是repr(C)的，并且没有填充字节
+        let bytes =
+            unsafe { core::slice::from_raw_parts(&header as *const _ as *const u8, header_size) };
+        buf[..header_size].copy_from_slice(bytes);
+
+        let name_buf = &mut buf[header_size..self.size()];
+        name_buf.fill(0);
+        if let Some(name) = &self.name {
+            name_buf[..name.len()].copy_from_slice(name.as_bytes());
+        }
+    }
+}
+
+#[derive(Debug)]
+struct InotifyInner {
+    events: VecDeque<InotifyEvent>,
+    /// wd -> 被监视的inode。watch持有inode的引用，保证其在被监视期间不会被释放
+    watches: BTreeMap<i32, Arc<dyn IndexNode>>,
+    next_wd: i32,
+}
+
+/// inotify实例
+#[derive(Debug)]
+pub struct InotifyInode {
+    inner: SpinLock<InotifyInner>,
+    flags: InotifyFlags,
+    wait_queue: WaitQueue,
+    epitems: SpinLock<LinkedList<Arc<EPollItem>>>,
+}
+
+/// 一个inode上的watch
+#[derive(Debug)]
+struct InotifyMark {
+    group: Weak<InotifyInode>,
+    wd: i32,
+    mask: InotifyMask,
+}
+
+/// 被监视的inode -> 其上的watch
+static INOTIFY_MARKS: SpinLock<BTreeMap<usize, Vec<InotifyMark>>> = SpinLock::new(BTreeMap::new());
+/// `INOTIFY_MARKS`中的watch数。没有任何watch时，VFS的通知函数直接返回
+static NR_INOTIFY_MARKS: AtomicUsize = AtomicUsize::new(0);
+/// 关注每种事件的watch数（下标为事件的位号）
+///
+/// 读写文件等频繁的操作先检查这里，没有watch关注的事件不需要查找`INOTIFY_MARKS`
+static NR_EVENT_MARKS: [AtomicUsize; INOTIFY_EVENT_BITS] =
+    [const { AtomicUsize::new(0) }; INOTIFY_EVENT_BITS];
+/// 按inode分桶统计的watch数，桶为0时，桶里的inode一定没有被监视，不需要获取`INOTIFY_MARKS`的锁
+static INODE_MARK_BUCKETS: [AtomicUsize; INOTIFY_MARK_BUCKETS] =
+    [const { AtomicUsize::new(0) }; INOTIFY_MARK_BUCKETS];
+/// 用于关联IN_MOVED_FROM与IN_MOVED_TO事件
+static MOVE_COOKIE: AtomicU32 = AtomicU32::new(1);
+
+impl InotifyInode {
+    fn new(flags: InotifyFlags) -> Self {
+        Self {
+            inner: SpinLock::new(InotifyInner {
+                events: VecDeque::new(),
+                watches: BTreeMap::new(),
+                next_wd: 1,
+            }),
+            flags,
+            wait_queue: WaitQueue::default(),
+            epitems: SpinLock::new(LinkedList::new()),
+        }
+    }
+
+    /// 把事件加入队列，并唤醒等待者
+    ///
+    /// 与队尾的事件相同时合并，队列满时丢弃事件并报告IN_Q_OVERFLOW
+    fn queue_event(&self, event: InotifyEvent) {
+        let mut inner = self.inner.lock();
+        if inner.events.back() == Some(&event) {
+            return;
+        }
+        if inner.events.len() >= INOTIFY_MAX_QUEUED_EVENTS {
+            let overflow = InotifyEvent {
+                wd: -1,
+                mask: InotifyMask::IN_Q_OVERFLOW,
+                cookie: 0,
+                name: None,
+            };
+            if inner.events.back() != Some(&overflow) {
+                inner.events.push_back(overflow);
+            }
+        } else {
+            inner.events.push_back(event);
+        }
+        drop(inner);
+
+        self.wait_queue.wakeup_all(None);
+        EventPoll::wakeup_epoll(
+            &self.epitems,
+            EPollEventType::EPOLLIN | EPollEventType::EPOLLRDNORM,
+        )
+        .ok();
+    }
+
+    /// 添加或修改对inode的监视，返回watch的描述符
+    fn add_watch(
+        self: &Arc<Self>,
+        inode: Arc<dyn IndexNode>,
+        mask: InotifyMask,
+    ) -> Result<i32, SystemError> {
+        let key = inode_identity(&inode);
+        let events = mask
+            & (InotifyMask::IN_ALL_EVENTS | InotifyMask::IN_ONESHOT | InotifyMask::IN_EXCL_UNLINK);
+
+        let mut marks = INOTIFY_MARKS.lock();
+        let inode_marks = marks.entry(key).or_default();
+        if let Some(mark) = inode_marks
+            .iter_mut()
+            .find(|mark| mark.group.as_ptr() == Arc::as_ptr(self))
+        {
+            if mask.contains(InotifyMask::IN_MASK_CREATE) {
+                return Err(SystemError::EEXIST);
+            }
+            account_events(mark.mask, false);
+            if mask.contains(InotifyMask::IN_MASK_ADD) {
+                mark.mask |= events;
+            } else {
+                mark.mask = events;
+            }
+            account_events(mark.mask, true);
+            return Ok(mark.wd);
+        }
+
+        let mut inner = self.inner.lock();
+        if inner.watches.len() >= INOTIFY_MAX_USER_WATCHES {
+            if inode_marks.is_empty() {
+                marks.remove(&key);
+            }
+            return Err(SystemError::ENOSPC);
+        }
+        let wd = inner.next_wd;
+        inner.next_wd += 1;
+        inner.watches.insert(wd, inode);
+        drop(inner);
+
+        inode_marks.push(InotifyMark {
+            group: Arc::downgrade(self),
+            wd,
+            mask: events,
+        });
+        account_mark(key, events, true);
+        return Ok(wd);
+    }
+
+    /// 移除watch，并报告IN_IGNORED
+    fn rm_watch(self: &Arc<Self>, wd: i32) -> Result<(), SystemError> {
+        let inode = self
+            .inner
+            .lock()
+            .watches
+            .remove(&wd)
+            .ok_or(SystemError::EINVAL)?;
+        remove_mark(inode_identity(&inode), Arc::as_ptr(self));
+        self.queue_event(InotifyEvent {
+            wd,
+            mask: InotifyMask::IN_IGNORED,
+            cookie: 0,
+            name: None,
+        });
+        return Ok(());
+    }
+}
+
+impl Drop for InotifyInode {
+    fn drop(&mut self) {
+        let watches = core::mem::take(&mut self.inner.lock().watches);
+        for inode in watches.values() {
+            remove_mark(inode_identity(inode), self as *const Self);
+        }
+    }
+}
+
+/// 从全局表中移除`group`在inode上的watch
+fn remove_mark(key: usize, group: *const InotifyInode) {
+    let mut marks = INOTIFY_MARKS.lock();
+    if let Some(inode_marks) = marks.get_mut(&key) {
+        inode_marks.retain(|mark| {
+            if mark.group.as_ptr() != group {
+                return true;
+            }
+            account_mark(key, mark.mask, false);
+            false
+        });
+        if inode_marks.is_empty() {
+            marks.remove(&key);
+        }
+    }
+}
+
+impl IndexNode for InotifyInode {
+    fn open(
+        &self,
+        _data: SpinLockGuard<FilePrivateData>,
+        _mode: &FileMode,
+    ) -> Result<(), SystemError> {
+        Ok(())
+    }
+
+    fn close(&self, _data: SpinLockGuard<FilePrivateData>) -> Result<(), SystemError> {
+        Ok(())
+    }
+
+    /// # 读取事件
+    ///
+    /// 尽可能多地读取完整的事件。缓冲区连一个事件都放不下时返回EINVAL
+    fn read_at(
+        &self,
+        _offset: usize,
+        len: usize,
+        buf: &mut [u8],
+        _data: SpinLockGuard<FilePrivateData>,
+    ) -> Result<usize, SystemError> {
+        let len = len.min(buf.len());
+        loop {
+            let mut inner = self.inner.lock();
+            if !inner.events.is_empty() {
+                let mut pos = 0;
+                while let Some(event) = inner.events.front() {
+                    let size = event.size();
+                    if pos + size > len {
+                        break;
+                    }
+                    event.write_to(&mut buf[pos..pos + size]);
+                    pos += size;
+                    inner.events.pop_front();
+                }
+                if pos == 0 {
+                    return Err(SystemError::EINVAL);
+                }
+                return Ok(pos);
+            }
+
+            if self.flags.contains(InotifyFlags::IN_NONBLOCK) {
+                return Err(SystemError::EAGAIN_OR_EWOULDBLOCK);
+            }
+            // 先加入等待队列再释放锁，避免错过唤醒
+            let r = self.wait_queue.prepare_to_wait_event(true);
+            drop(inner);
+            r?;
+            schedule(SchedMode::SM_NONE);
+            self.wait_queue.finish_wait();
+        }
+    }
+
+    fn write_at(
+        &self,
+        _offset: usize,
+        _len: usize,
+        _buf: &[u8],
+        _data: SpinLockGuard<FilePrivateData>,
+    ) -> Result<usize, SystemError> {
+        Err(SystemError::EINVAL)
+    }
+
+    /// 事件队列不为空时可读
+    fn poll(&self, _private_data: &FilePrivateData) -> Result<usize, SystemError> {
+        let mut events = EPollEventType::empty();
+        if !self.inner.lock().events.is_empty() {
+            events |= EPollEventType::EPOLLIN | EPollEventType::EPOLLRDNORM;
+        }
+        return Ok(events.bits() as usize);
+    }
+
+    fn metadata(&self) -> Result<Metadata, SystemError> {
+        let meta = Metadata {
+            mode: ModeType::from_bits_truncate(0o600),
+            file_type: FileType::File,
+            ..Default::default()
+        };
+        Ok(meta)
+    }
+
+    fn kernel_ioctl(
+        &self,
+        arg: Arc<dyn KernelIoctlData>,
+        _data: &FilePrivateData,
+    ) -> Result<usize, SystemError> {
+        let epitem = arg
+            .arc_any()
+            .downcast::<EPollItem>()
+            .map_err(|_| SystemError::EFAULT)?;
+        self.epitems.lock().push_back(epitem);
+        Ok(0)
+    }
+
+    fn fs(&self) -> Arc<dyn FileSystem> {
+        panic!("Inotify does not have a filesystem")
+    }
+
+    fn as_any_ref(&self) -> &dyn Any {
+        self
+    }
+
+    fn list(&self) -> Result<Vec<String>, SystemError> {
+        Err(SystemError::ENOTDIR)
+    }
+}
+
+impl InotifyInode {
+    pub fn remove_epoll(&self, epoll: &Weak<SpinLock<EventPoll>>) -> Result<(), SystemError> {
+        let is_remove = !self
+            .epitems
+            .lock_irqsave()
+            .extract_if(|x| x.epoll().ptr_eq(epoll))
+            .collect::<Vec<_>>()
+            .is_empty();
+
+        if is_remove {
+            return Ok(());
+        }
+
+        Err(SystemError::ENOENT)
+    }
+}
+
+/// 向监视inode的inotify实例投递事件
+///
+/// ## 参数
+///
+/// - `mask`: 事件，可以带有IN_ISDIR
+/// - `name`: 事件的对象在被监视的目录中的名字，事件的对象是inode自身时为None
+/// - `cookie`: 用于关联IN_MOVED_FROM与IN_MOVED_TO事件
+fn fsnotify(inode: &Arc<dyn IndexNode>, mask: InotifyMask, name: Option<&str>, cookie: u32) {
+    let key = inode_identity(inode);
+    if mark_bucket(key).load(Ordering::Relaxed) == 0 {
+        return;
+    }
+    let event_mask = mask & InotifyMask::IN_ALL_EVENTS;
+    let mut targets = Vec::new();
+    let mut marks = INOTIFY_MARKS.lock();
+    let Some(inode_marks) = marks.get_mut(&key) else {
+        return;
+    };
+    inode_marks.retain(|mark| {
+        if !mark.mask.intersects(event_mask) {
+            return true;
+        }
+        let Some(group) = mark.group.upgrade() else {
+            return true;
+        };
+        let oneshot = mark.mask.contains(InotifyMask::IN_ONESHOT);
+        targets.push((group, mark.wd, oneshot));
+        if oneshot {
+            account_mark(key, mark.mask, false);
+        }
+        !oneshot
+    });
+    if inode_marks.is_empty() {
+        marks.remove(&key);
+    }
+    drop(marks);
+
+    for (group, wd, oneshot) in targets {
+        group.queue_event(InotifyEvent {
+            wd,
+            mask,
+            cookie,
+            name: name.map(|name| name.to_string()),
+        });
+        if oneshot {
+            group.inner.lock().watches.remove(&wd);
+            group.queue_event(InotifyEvent {
+                wd,
+                mask: InotifyMask::IN_IGNORED,
+                cookie: 0,
+                name: None,
+            });
+        }
+    }
+}
+
+/// 移除inode上的所有watch，并报告IN_IGNORED（inode被删除时）
+fn fsnotify_destroy_marks(inode: &Arc<dyn IndexNode>) {
+    let key = inode_identity(inode);
+    let Some(inode_marks) = INOTIFY_MARKS.lock().remove(&key) else {
+        return;
+    };
+    for mark in inode_marks.iter() {
+        account_mark(key, mark.mask, false);
+    }
+    for mark in inode_marks {
+        if let Some(group) = mark.group.upgrade() {
+            group.inner.lock().watches.remove(&mark.wd);
+            group.queue_event(InotifyEvent {
+                wd: mark.wd,
+                mask: InotifyMask::IN_IGNORED,
+                cookie: 0,
+                name: None,
+            });
+        }
+    }
+}
+
+#[inline(always)]
+fn fsnotify_enabled() -> bool {
+    NR_INOTIFY_MARKS.load(Ordering::Relaxed) != 0
+}
+
+/// 是否有watch关注`mask`中的事件
+#[inline(always)]
+fn fsnotify_wanted(mask: InotifyMask) -> bool {
+    let mask = (mask & InotifyMask::IN_ALL_EVENTS).bits();
+    (0..INOTIFY_EVENT_BITS)
+        .any(|bit| mask & (1 << bit) != 0 && NR_EVENT_MARKS[bit].load(Ordering::Relaxed) != 0)
+}
+
+#[inline(always)]
+fn mark_bucket(key: usize) -> &'static AtomicUsize {
+    // inode的地址至少是16字节对齐的
+    &INODE_MARK_BUCKETS[(key >> 4) % INOTIFY_MARK_BUCKETS]
+}
+
+/// 更新watch的事件计数
+fn account_events(mask: InotifyMask, add: bool) {
+    let mask = (mask & InotifyMask::IN_ALL_EVENTS).bits();
+    for (bit, count) in NR_EVENT_MARKS.iter().enumerate() {
+        if mask & (1 << bit) == 0 {
+            continue;
+        }
+        if add {
+            count.fetch_add(1, Ordering::SeqCst);
+        } else {
+            count.fetch_sub(1, Ordering::SeqCst);
+        }
+    }
+}
+
+/// 在inode `key`上添加或移除watch时，更新无锁快速路径使用的计数
+fn account_mark(key: usize, mask: InotifyMask, add: bool) {
+    if add {
+        NR_INOTIFY_MARKS.fetch_add(1, Ordering::SeqCst);
+        mark_bucket(key).fetch_add(1, Ordering::SeqCst);
+    } else {
+        NR_INOTIFY_MARKS.fetch_sub(1, Ordering::SeqCst);
+        mark_bucket(key).fetch_sub(1, Ordering::SeqCst);
+    }
+    account_events(mask, add);
+}
+
+fn is_dir_mask(inode: &Arc<dyn IndexNode>) -> InotifyMask {
+    match inode.metadata() {
+        Ok(metadata) if metadata.file_type == FileType::Dir => InotifyMask::IN_ISDIR,
+        _ => InotifyMask::empty(),
+    }
+}
+
+/// 向inode自身，以及监视其父目录的inotify实例报告事件
+///
+/// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/fs/notify/fsnotify.c#177
+fn fsnotify_parent(inode: &Arc<dyn IndexNode>, mask: InotifyMask) {
+    if !fsnotify_wanted(mask) {
+        return;
+    }
+    let mask = mask | is_dir_mask(inode);
+    fsnotify(inode, mask, None, 0);
+
+    let Ok(parent) = inode.parent() else {
+        return;
+    };
+    // 文件系统的根目录的父目录是它自己
+    if inode_identity(&parent) == inode_identity(inode) {
+        return;
+    }
+    if let Ok(name) = inode.dname() {
+        fsnotify(&parent, mask, Some(name.as_ref()), 0);
+    }
+}
+
+/// 文件被读取
+pub fn fsnotify_access(inode: &Arc<dyn IndexNode>) {
+    fsnotify_parent(inode, InotifyMask::IN_ACCESS);
+}
+
+/// 文件被写入或截断
+pub fn fsnotify_modify(inode: &Arc<dyn IndexNode>) {
+    fsnotify_parent(inode, InotifyMask::IN_MODIFY);
+}
+
+/// 文件的元数据被修改
+pub fn fsnotify_attrib(inode: &Arc<dyn IndexNode>) {
+    fsnotify_parent(inode, InotifyMask::IN_ATTRIB);
+}
+
+/// 文件被打开
+pub fn fsnotify_open(inode: &Arc<dyn IndexNode>) {
+    fsnotify_parent(inode, InotifyMask::IN_OPEN);
+}
+
+/// 文件被关闭
+pub fn fsnotify_close(inode: &Arc<dyn IndexNode>, mode: FileMode) {
+    let mask = if mode.accmode() == FileMode::O_RDONLY.bits() {
+        InotifyMask::IN_CLOSE_NOWRITE
+    } else {
+        InotifyMask::IN_CLOSE_WRITE
+    };
+    fsnotify_parent(inode, mask);
+}
+
+/// 在目录`dir`中创建了名为`name`的文件`inode`（包括mkdir、mknod与link）
+pub fn fsnotify_create(dir: &Arc<dyn IndexNode>, name: &str, inode: &Arc<dyn IndexNode>) {
+    if !fsnotify_enabled() {
+        return;
+    }
+    fsnotify(
+        dir,
+        InotifyMask::IN_CREATE | is_dir_mask(inode),
+        Some(name),
+        0,
+    );
+}
+
+/// 为link创建了新的目录项：目录中出现了新的文件，并且文件的链接数发生了变化
+pub fn fsnotify_link(dir: &Arc<dyn IndexNode>, name: &str, inode: &Arc<dyn IndexNode>) {
+    if !fsnotify_enabled() {
+        return;
+    }
+    fsnotify(inode, InotifyMask::IN_ATTRIB, None, 0);
+    fsnotify_create(dir, name, inode);
+}
+
+/// 目录`dir`中名为`name`的文件`inode`被删除（包括unlink与rmdir）
+///
+/// 文件的最后一个链接被删除时，报告IN_DELETE_SELF，并移除它上面的所有watch
+///
+/// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/include/linux/fsnotify.h#240
+pub fn fsnotify_delete(dir: &Arc<dyn IndexNode>, name: &str, inode: &Arc<dyn IndexNode>) {
+    if !fsnotify_enabled() {
+        return;
+    }
+    let metadata = inode.metadata();
+    let is_dir = matches!(&metadata, Ok(m) if m.file_type == FileType::Dir);
+    let isdir_mask = if is_dir {
+        InotifyMask::IN_ISDIR
+    } else {
+        InotifyMask::empty()
+    };
+
+    // 目录被删除，或者文件已经没有链接时，文件本身被删除了
+    if is_dir || metadata.map_or(true, |m| m.nlinks == 0) {
+        fsnotify(inode, InotifyMask::IN_DELETE_SELF, None, 0);
+        fsnotify_destroy_marks(inode);
+    } else {
+        fsnotify(inode, InotifyMask::IN_ATTRIB, None, 0);
+    }
+    fsnotify(dir, InotifyMask::IN_DELETE | isdir_mask, Some(name), 0);
+}
+
+/// 文件`inode`从`old_dir`中的`old_name`被移动到`new_dir`中的`new_name`
+///
+/// `target`为被覆盖的文件
+///
+/// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/include/linux/fsnotify.h#146
+pub fn fsnotify_move(
+    old_dir: &Arc<dyn IndexNode>,
+    old_name: &str,
+    new_dir: &Arc<dyn IndexNode>,
+    new_name: &str,
+    inode: &Arc<dyn IndexNode>,
+    target: Option<&Arc<dyn IndexNode>>,
+) {
+    if !fsnotify_enabled() {
+        return;
+    }
+    let isdir_mask = is_dir_mask(inode);
+    let cookie = MOVE_COOKIE.fetch_add(1, Ordering::SeqCst);
+    fsnotify(
+        old_dir,
+        InotifyMask::IN_MOVED_FROM | isdir_mask,
+        Some(old_name),
+        cookie,
+    );
+    fsnotify(
+        new_dir,
+        InotifyMask::IN_MOVED_TO | isdir_mask,
+        Some(new_name),
+        cookie,
+    );
+    if let Some(target) = target {
+        fsnotify(target, InotifyMask::IN_DELETE_SELF, None, 0);
+        fsnotify_destroy_marks(target);
+    }
+    fsnotify(inode, InotifyMask::IN_MOVE_SELF, None, 0);
+}
+
+/// 获取inotify实例
+fn inotify_from_fd(fd: i32) -> Result<Arc<InotifyInode>, SystemError> {
+    let file = ProcessManager::current_pcb()
+        .fd_table()
+        .read()
+        .get_file_by_fd(fd)
+        .ok_or(SystemError::EBADF)?;
+    return file
+        .inode()
+        .downcast_arc::<InotifyInode>()
+        .ok_or(SystemError::EINVAL);
+}
+
+impl Syscall {
+    /// # 创建一个inotify实例
+    ///
+    /// ## 参数
+    /// - `flags`: IN_NONBLOCK与IN_CLOEXEC
+    ///
+    /// See: https://man7.org/linux/man-pages/man2/inotify_init1.2.html
+    pub fn inotify_init1(flags: u32) -> Result<usize, SystemError> {
+        let flags = InotifyFlags::from_bits(flags).ok_or(SystemError::EINVAL)?;
+        let mut mode = FileMode::O_RDONLY;
+        if flags.contains(InotifyFlags::IN_CLOEXEC) {
+            mode |= FileMode::O_CLOEXEC;
+        }
+        if flags.contains(InotifyFlags::IN_NONBLOCK) {
+            mode |= FileMode::O_NONBLOCK;
+        }
+        let file = File::new(Arc::new(InotifyInode::new(flags)), mode)?;
+        let binding = ProcessManager::current_pcb().fd_table();
+        let mut fd_table_guard = binding.write();
+        return fd_table_guard.alloc_fd(file, None).map(|fd| fd as usize);
+    }
+
+    /// # 监视一个文件或目录
+    ///
+    /// ## 参数
+    /// - `fd`: inotify实例
+    /// - `pathname`: 被监视的文件的路径
+    /// - `mask`: 要监视的事件与标志
+    ///
+    /// ## 返回值
+    /// - `Ok(usize)`: watch的描述符。文件已经被该实例监视时，返回原来的描述符
+    ///
+    /// See: https://man7.org/linux/man-pages/man2/inotify_add_watch.2.html
+    pub fn inotify_add_watch(
+        fd: i32,
+        pathname: *const u8,
+        mask: u32,
+    ) -> Result<usize, SystemError> {
+        let mask = InotifyMask::from_bits_truncate(mask);
+        if !mask.intersects(InotifyMask::IN_ALL_EVENTS) {
+            return Err(SystemError::EINVAL);
+        }
+        if mask.contains(InotifyMask::IN_MASK_ADD | InotifyMask::IN_MASK_CREATE) {
+            return Err(SystemError::EINVAL);
+        }
+        let group = inotify_from_fd(fd)?;
+
+        let path = check_and_clone_cstr(pathname, Some(MAX_PATHLEN))?
+            .into_string()
+            .map_err(|_| SystemError::EINVAL)?;
+        if path.is_empty() {
+            return Err(SystemError::ENOENT);
+        }
+        let (inode_begin, path) = user_path_at(
+            &ProcessManager::current_pcb(),
+            AtFlags::AT_FDCWD.bits(),
+            &path,
+        )?;
+        let inode = if mask.contains(InotifyMask::IN_DONT_FOLLOW) {
+            inode_begin.lookup(&path)?
+        } else {
+            inode_begin.lookup_follow_symlink(&path, VFS_MAX_FOLLOW_SYMLINK_TIMES)?
+        };
+        if mask.contains(InotifyMask::IN_ONLYDIR) && inode.metadata()?.file_type != FileType::Dir {
+            return Err(SystemError::ENOTDIR);
+        }
+
+        return group.add_watch(inode, mask).map(|wd| wd as usize);
+    }
+
+    /// # 移除一个watch
+    ///
+    /// See: https://man7.org/linux/man-pages/man2/inotify_rm_watch.2.html
+    pub fn inotify_rm_watch(fd: i32, wd: i32) -> Result<usize, SystemError> {
+        let group = inotify_from_fd(fd)?;
+        group.rm_watch(wd)?;
+        return Ok(0);
+    }
+}
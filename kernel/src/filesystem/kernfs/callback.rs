@@ -1,4 +1,5 @@
 use crate::{
+    cgroup::cgroupfs::CgroupKernPrivateData,
     filesystem::{sysfs::SysFSKernPrivateData, vfs::PollStatus},
     libs::spinlock::SpinLockGuard,
 };
@@ -86,6 +87,7 @@ impl<'a> KernCallbackData<'a> {
 #[derive(Debug)]
 pub enum KernInodePrivateData {
     SysFS(SysFSKernPrivateData),
+    Cgroup(CgroupKernPrivateData),
 }
 
 impl KernInodePrivateData {
@@ -95,6 +97,9 @@ impl KernInodePrivateData {
             KernInodePrivateData::SysFS(private_data) => {
                 return private_data.callback_read(buf, offset);
             }
+            KernInodePrivateData::Cgroup(private_data) => {
+                return private_data.callback_read(buf, offset);
+            }
         }
     }
 
@@ -104,6 +109,9 @@ impl KernInodePrivateData {
             KernInodePrivateData::SysFS(private_data) => {
                 return private_data.callback_write(buf, offset);
             }
+            KernInodePrivateData::Cgroup(private_data) => {
+                return private_data.callback_write(buf, offset);
+            }
         }
     }
 }
@@ -31,6 +31,24 @@ pub mod callback;
 #[derive(Debug)]
 pub struct KernFS {
     root_inode: Arc<KernFSInode>,
+    /// 用户态对目录进行mkdir/rmdir时的回调（为None时不允许从用户态创建、删除目录）
+    syscall_ops: Option<&'static dyn KernFSSyscallOps>,
+}
+
+/// KernFS的使用者（例如cgroupfs）提供的，处理用户态目录操作的接口
+///
+/// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/include/linux/kernfs.h#317
+pub trait KernFSSyscallOps: Send + Sync + Debug {
+    /// 用户态在`parent`下创建名为`name`的目录
+    fn mkdir(
+        &self,
+        parent: &Arc<KernFSInode>,
+        name: &str,
+        mode: ModeType,
+    ) -> Result<Arc<KernFSInode>, SystemError>;
+
+    /// 用户态删除目录`inode`
+    fn rmdir(&self, inode: &Arc<KernFSInode>) -> Result<(), SystemError>;
 }
 
 impl FileSystem for KernFS {
@@ -67,9 +85,19 @@ impl KernFS {
     pub const KERNFS_BLOCK_SIZE: u64 = 512;
     #[allow(dead_code)]
     pub fn new() -> Arc<Self> {
+        return Self::do_new(None);
+    }
+
+    /// 创建一个允许用户态通过`syscall_ops`创建、删除目录的KernFS
+    pub fn new_with_syscall_ops(syscall_ops: &'static dyn KernFSSyscallOps) -> Arc<Self> {
+        return Self::do_new(Some(syscall_ops));
+    }
+
+    fn do_new(syscall_ops: Option<&'static dyn KernFSSyscallOps>) -> Arc<Self> {
         let root_inode = Self::create_root_inode();
         let fs = Arc::new(Self {
             root_inode: root_inode.clone(),
+            syscall_ops,
         });
 
         {
@@ -188,13 +216,22 @@ impl IndexNode for KernFSInode {
 
     fn create_with_data(
         &self,
-        _name: &str,
-        _file_type: FileType,
-        _mode: ModeType,
+        name: &str,
+        file_type: FileType,
+        mode: ModeType,
         _data: usize,
     ) -> Result<Arc<dyn IndexNode>, SystemError> {
-        // 应当通过kernfs的其它方法来创建文件，而不能从用户态直接调用此方法。
-        return Err(SystemError::ENOSYS);
+        // 只有提供了syscall_ops的kernfs才允许从用户态创建目录，
+        // 其余情况应当通过kernfs的其它方法来创建文件，而不能从用户态直接调用此方法。
+        let ops = self.syscall_ops().ok_or(SystemError::ENOSYS)?;
+        if file_type != FileType::Dir {
+            return Err(SystemError::EPERM);
+        }
+        if self.children.lock().contains_key(name) {
+            return Err(SystemError::EEXIST);
+        }
+        let inode = ops.mkdir(&self.self_ref.upgrade().unwrap(), name, mode)?;
+        return Ok(inode);
     }
 
     fn link(&self, _name: &str, _other: &Arc<dyn IndexNode>) -> Result<(), SystemError> {
@@ -207,9 +244,19 @@ impl IndexNode for KernFSInode {
         return Err(SystemError::ENOSYS);
     }
 
-    fn rmdir(&self, _name: &str) -> Result<(), SystemError> {
-        // 应当通过kernfs的其它方法来操作文件，而不能从用户态直接调用此方法。
-        return Err(SystemError::ENOSYS);
+    fn rmdir(&self, name: &str) -> Result<(), SystemError> {
+        // 只有提供了syscall_ops的kernfs才允许从用户态删除目录
+        let ops = self.syscall_ops().ok_or(SystemError::ENOSYS)?;
+        let inode = self
+            .children
+            .lock()
+            .get(name)
+            .cloned()
+            .ok_or(SystemError::ENOENT)?;
+        if inode.inode_type != KernInodeType::Dir {
+            return Err(SystemError::ENOTDIR);
+        }
+        return ops.rmdir(&inode);
     }
 
     fn move_to(
@@ -610,6 +657,10 @@ impl KernFSInode {
         return &self.name;
     }
 
+    fn syscall_ops(&self) -> Option<&'static dyn KernFSSyscallOps> {
+        return self.fs.read().upgrade()?.syscall_ops;
+    }
+
     pub fn parent(&self) -> Option<Arc<KernFSInode>> {
         return self.inner.read().parent.upgrade();
     }
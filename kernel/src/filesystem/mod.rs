@@ -2,9 +2,15 @@ pub mod devfs;
 pub mod devpts;
 pub mod eventfd;
 pub mod fat;
+pub mod fuse;
+pub mod inotify;
 pub mod kernfs;
 pub mod mbr;
+pub mod overlayfs;
 pub mod procfs;
 pub mod ramfs;
+pub mod select;
 pub mod sysfs;
+pub mod tmpfs;
+pub mod v9fs;
 pub mod vfs;
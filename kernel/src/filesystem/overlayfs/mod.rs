@@ -0,0 +1,927 @@
+//! overlayfs：把只读的下层目录与可写的上层目录合并为一个文件系统
+//!
+//! - 查找时从上层到下层依次查找，遇到whiteout（设备号为0:0的字符设备）时停止，表示文件已被删除
+//! - 同名的目录会被合并，设置了`trusted.overlay.opaque`扩展属性的目录则不再与下层合并
+//! - 修改下层的文件之前，先把文件以及它所有的祖先目录复制到上层（copy-up）
+//!
+//! 挂载选项为`lowerdir=/lower1:/lower2,upperdir=/upper,workdir=/work`，
+//! 没有upperdir时，整个文件系统是只读的。
+//!
+//! 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/fs/overlayfs/
+
+use core::any::Any;
+
+use alloc::{
+    collections::{BTreeMap, BTreeSet},
+    string::String,
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+use linkme::distributed_slice;
+use mount_options::overlayfs::OvlOptions;
+use system_error::SystemError;
+
+use crate::{
+    driver::base::device::device_number::DeviceNumber,
+    libs::{
+        casting::DowncastArc,
+        mutex::Mutex,
+        spinlock::{SpinLock, SpinLockGuard},
+    },
+    mm::{fault::PageFaultMessage, VmFaultReason},
+    process::ProcessManager,
+};
+
+use super::vfs::{
+    fcntl::AtFlags,
+    file::{FileMode, FilePrivateData, PageCache},
+    syscall::ModeType,
+    utils::{user_path_at, DName},
+    xattr::XattrFlags,
+    FileSystem, FileSystemMaker, FileSystemMakerData, FileType, FsInfo, IndexNode, InodeId, Magic,
+    Metadata, SpecialNodeData, SuperBlock, FSMAKER, VFS_MAX_FOLLOW_SYMLINK_TIMES,
+};
+
+/// overlayfs的文件名的最大长度
+const OVL_MAX_NAMELEN: usize = 255;
+/// overlayfs自己使用的扩展属性的前缀，对用户不可见
+const OVL_XATTR_PREFIX: &str = "trusted.overlay.";
+/// 值为"y"时，表示目录不与下层的同名目录合并
+const OVL_XATTR_OPAQUE: &str = "trusted.overlay.opaque";
+/// copy-up时在workdir中使用的临时文件名
+const OVL_COPY_UP_TMPNAME: &str = "#ovl-copy-up";
+/// copy-up时每次复制的字节数
+const OVL_COPY_CHUNK_SIZE: usize = 64 * 1024;
+
+/// 判断inode是否为whiteout
+fn is_whiteout(inode: &Arc<dyn IndexNode>) -> Result<bool, SystemError> {
+    let metadata = inode.metadata()?;
+    Ok(metadata.file_type == FileType::CharDevice && metadata.raw_dev == DeviceNumber::default())
+}
+
+/// 判断目录是否为opaque
+fn is_opaque(inode: &Arc<dyn IndexNode>) -> bool {
+    matches!(inode.getxattr(OVL_XATTR_OPAQUE), Ok(value) if value == b"y")
+}
+
+/// 在某一层的目录中查找，不存在时返回None
+fn lookup_layer(
+    dir: &Arc<dyn IndexNode>,
+    name: &str,
+) -> Result<Option<Arc<dyn IndexNode>>, SystemError> {
+    match dir.find(name) {
+        Ok(inode) => Ok(Some(inode)),
+        Err(SystemError::ENOENT) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// 在上层目录中创建whiteout，遮住下层的同名文件
+fn create_whiteout(upper_dir: &Arc<dyn IndexNode>, name: &str) -> Result<(), SystemError> {
+    upper_dir.mknod(name, ModeType::S_IFCHR, DeviceNumber::default())?;
+    Ok(())
+}
+
+/// 删除上层目录中名为`name`的whiteout，为在这里创建新的文件做准备
+///
+/// ## 返回值
+///
+/// - `Ok(true)`: 删除了一个whiteout
+/// - `Ok(false)`: 上层目录中不存在名为`name`的文件
+fn clear_whiteout(upper_dir: &Arc<dyn IndexNode>, name: &str) -> Result<bool, SystemError> {
+    match lookup_layer(upper_dir, name)? {
+        Some(inode) if is_whiteout(&inode)? => {
+            upper_dir.unlink(name)?;
+            Ok(true)
+        }
+        Some(_) => Err(SystemError::EEXIST),
+        None => Ok(false),
+    }
+}
+
+/// 复制普通文件的内容
+fn copy_data(from: &Arc<dyn IndexNode>, to: &Arc<dyn IndexNode>) -> Result<(), SystemError> {
+    let mut buf = vec![0u8; OVL_COPY_CHUNK_SIZE];
+    let mut offset = 0;
+    loop {
+        let len = from.read_at(
+            offset,
+            buf.len(),
+            &mut buf,
+            SpinLock::new(FilePrivateData::Unused).lock(),
+        )?;
+        if len == 0 {
+            return Ok(());
+        }
+
+        let mut written = 0;
+        while written < len {
+            let n = to.write_at(
+                offset + written,
+                len - written,
+                &buf[written..len],
+                SpinLock::new(FilePrivateData::Unused).lock(),
+            )?;
+            if n == 0 {
+                return Err(SystemError::EIO);
+            }
+            written += n;
+        }
+        offset += len;
+    }
+}
+
+/// 解析挂载选项中的目录
+///
+/// 与Linux的ovl_mount_dir_check一致，路径不是目录时返回EINVAL
+///
+/// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/fs/overlayfs/params.c
+fn resolve_dir(path: &str) -> Result<Arc<dyn IndexNode>, SystemError> {
+    let (begin, rest) = user_path_at(
+        &ProcessManager::current_pcb(),
+        AtFlags::AT_FDCWD.bits(),
+        path,
+    )?;
+    let inode = begin.lookup_follow_symlink(&rest, VFS_MAX_FOLLOW_SYMLINK_TIMES)?;
+    if inode.metadata()?.file_type != FileType::Dir {
+        return Err(SystemError::EINVAL);
+    }
+    Ok(inode)
+}
+
+#[derive(Debug)]
+pub struct OverlayFS {
+    root_inode: Arc<OvlInode>,
+    /// 上层目录，为None时文件系统只读
+    upper: Option<Arc<dyn IndexNode>>,
+    /// copy-up时暂存文件的目录，与上层目录位于同一个文件系统
+    work: Option<Arc<dyn IndexNode>>,
+    /// 串行化所有的copy-up
+    copy_up_lock: Mutex<()>,
+}
+
+impl OverlayFS {
+    pub fn make_overlayfs(
+        data: &FileSystemMakerData,
+    ) -> Result<Arc<dyn FileSystem + 'static>, SystemError> {
+        let options = OvlOptions::parse(data.options())?;
+        let lowers = options
+            .lowerdirs
+            .into_iter()
+            .map(resolve_dir)
+            .collect::<Result<Vec<_>, SystemError>>()?;
+        let upper = options.upperdir.map(resolve_dir).transpose()?;
+        // 与Linux一致，没有upperdir时忽略workdir
+        let work = match &upper {
+            Some(upper) => {
+                let work = options.workdir.map(resolve_dir).transpose()?;
+                if let Some(work) = &work {
+                    // 临时文件需要移动到上层目录中，因此两者必须位于同一个文件系统
+                    if Arc::as_ptr(&work.fs()) as *const () != Arc::as_ptr(&upper.fs()) as *const ()
+                    {
+                        return Err(SystemError::EINVAL);
+                    }
+                }
+                work
+            }
+            None => None,
+        };
+
+        let fs = Arc::new_cyclic(|fs_ref: &Weak<OverlayFS>| {
+            let root_inode = Arc::new_cyclic(|self_ref: &Weak<OvlInode>| OvlInode {
+                fs: fs_ref.clone(),
+                self_ref: self_ref.clone(),
+                file_type: FileType::Dir,
+                ino: lowers[0]
+                    .metadata()
+                    .map(|metadata| metadata.inode_id)
+                    .unwrap_or(InodeId::new(0)),
+                lowers: lowers.clone(),
+                inner: SpinLock::new(InnerOvlInode {
+                    parent: None,
+                    name: DName::default(),
+                    upper: upper.clone(),
+                    children: BTreeMap::new(),
+                }),
+            });
+            OverlayFS {
+                root_inode,
+                upper,
+                work,
+                copy_up_lock: Mutex::new(()),
+            }
+        });
+        return Ok(fs);
+    }
+
+    /// 文件系统的统计信息来自上层（只读时来自最上面的下层）
+    fn stat_layer(&self) -> Arc<dyn IndexNode> {
+        self.upper
+            .clone()
+            .unwrap_or_else(|| self.root_inode.lowers[0].clone())
+    }
+
+    /// 找到被映射的文件真正所在的文件系统
+    fn real_fs(pfm: &PageFaultMessage) -> Option<Arc<dyn FileSystem>> {
+        let file = pfm.vma().lock_irqsave().vm_file()?;
+        let real = file.inode().page_cache()?.inode()?.upgrade()?;
+        Some(real.fs())
+    }
+}
+
+#[distributed_slice(FSMAKER)]
+static OVERLAYFSMAKER: FileSystemMaker = FileSystemMaker::new(
+    "overlay",
+    &(OverlayFS::make_overlayfs
+        as fn(&FileSystemMakerData) -> Result<Arc<dyn FileSystem + 'static>, SystemError>),
+);
+
+impl FileSystem for OverlayFS {
+    fn root_inode(&self) -> Arc<dyn IndexNode> {
+        return self.root_inode.clone();
+    }
+
+    fn info(&self) -> FsInfo {
+        return FsInfo {
+            blk_dev_id: 0,
+            max_name_len: OVL_MAX_NAMELEN,
+        };
+    }
+
+    fn as_any_ref(&self) -> &dyn Any {
+        self
+    }
+
+    fn name(&self) -> &str {
+        "overlay"
+    }
+
+    fn super_block(&self) -> SuperBlock {
+        let mut super_block = self.stat_layer().fs().super_block();
+        super_block.magic = Magic::OVERLAYFS_MAGIC;
+        super_block
+    }
+
+    /// 页缓存属于真正的文件，交给它所在的文件系统处理
+    unsafe fn fault(&self, pfm: &mut PageFaultMessage) -> VmFaultReason {
+        match Self::real_fs(pfm) {
+            Some(fs) => fs.fault(pfm),
+            None => VmFaultReason::VM_FAULT_SIGBUS,
+        }
+    }
+
+    unsafe fn map_pages(
+        &self,
+        pfm: &mut PageFaultMessage,
+        start_pgoff: usize,
+        end_pgoff: usize,
+    ) -> VmFaultReason {
+        match Self::real_fs(pfm) {
+            Some(fs) => fs.map_pages(pfm, start_pgoff, end_pgoff),
+            None => VmFaultReason::VM_FAULT_SIGBUS,
+        }
+    }
+}
+
+/// overlayfs中的inode，对应上层与下层中的同名文件
+#[derive(Debug)]
+pub struct OvlInode {
+    fs: Weak<OverlayFS>,
+    self_ref: Weak<OvlInode>,
+    file_type: FileType,
+    /// 有下层文件时使用下层文件的inode号，copy-up之后保持不变
+    ino: InodeId,
+    /// 下层中的同名文件，按照从上到下的顺序排列。只有目录会有多个
+    lowers: Vec<Arc<dyn IndexNode>>,
+    inner: SpinLock<InnerOvlInode>,
+}
+
+#[derive(Debug)]
+struct InnerOvlInode {
+    /// 父目录，根目录为None。持有父目录的强引用，使得copy-up时总能找到祖先目录
+    parent: Option<Arc<OvlInode>>,
+    name: DName,
+    /// 上层中的文件，copy-up之前为None
+    upper: Option<Arc<dyn IndexNode>>,
+    /// 查找过的子目录项，保证同一个文件只对应一个OvlInode
+    children: BTreeMap<DName, Weak<OvlInode>>,
+}
+
+impl OvlInode {
+    fn inner(&self) -> SpinLockGuard<InnerOvlInode> {
+        self.inner.lock()
+    }
+
+    fn fs_arc(&self) -> Arc<OverlayFS> {
+        self.fs.upgrade().unwrap()
+    }
+
+    fn upper(&self) -> Option<Arc<dyn IndexNode>> {
+        self.inner().upper.clone()
+    }
+
+    /// 实际提供数据的文件：有上层文件时为上层文件，否则为最上面的下层文件
+    fn real(&self) -> Arc<dyn IndexNode> {
+        self.upper().unwrap_or_else(|| self.lowers[0].clone())
+    }
+
+    /// 获取父目录，根目录的父目录是自身
+    fn parent_inode(&self) -> Arc<OvlInode> {
+        self.inner()
+            .parent
+            .clone()
+            .unwrap_or_else(|| self.self_ref.upgrade().unwrap())
+    }
+
+    fn is_dir(&self) -> bool {
+        self.file_type == FileType::Dir
+    }
+
+    /// 把`other`转换为同一个overlayfs中的inode
+    fn same_fs_inode(&self, other: &Arc<dyn IndexNode>) -> Result<Arc<OvlInode>, SystemError> {
+        other
+            .clone()
+            .downcast_arc::<OvlInode>()
+            .filter(|other| Weak::ptr_eq(&other.fs, &self.fs))
+            .ok_or(SystemError::EXDEV)
+    }
+
+    fn new_child(
+        &self,
+        name: &str,
+        upper: Option<Arc<dyn IndexNode>>,
+        lowers: Vec<Arc<dyn IndexNode>>,
+    ) -> Result<Arc<OvlInode>, SystemError> {
+        let real = upper
+            .as_ref()
+            .or(lowers.first())
+            .ok_or(SystemError::ENOENT)?;
+        let file_type = real.metadata()?.file_type;
+        let ino = match lowers.first() {
+            Some(lower) => lower.metadata()?.inode_id,
+            None => real.metadata()?.inode_id,
+        };
+
+        Ok(Arc::new_cyclic(|self_ref| OvlInode {
+            fs: self.fs.clone(),
+            self_ref: self_ref.clone(),
+            file_type,
+            ino,
+            lowers,
+            inner: SpinLock::new(InnerOvlInode {
+                parent: self.self_ref.upgrade(),
+                name: DName::from(name),
+                upper,
+                children: BTreeMap::new(),
+            }),
+        }))
+    }
+
+    /// 把子目录项加入缓存。`replace`为false且缓存中已有仍在使用的inode时，返回缓存中的inode
+    fn cache_child(&self, name: &str, child: Arc<OvlInode>, replace: bool) -> Arc<OvlInode> {
+        let mut inner = self.inner();
+        let name = DName::from(name);
+        if !replace {
+            if let Some(cached) = inner.children.get(&name).and_then(Weak::upgrade) {
+                return cached;
+            }
+        }
+        inner.children.insert(name, Arc::downgrade(&child));
+        child
+    }
+
+    /// 在合并后的目录中查找
+    fn lookup(&self, name: &str) -> Result<Arc<OvlInode>, SystemError> {
+        if let Some(child) = self
+            .inner()
+            .children
+            .get(&DName::from(name))
+            .and_then(Weak::upgrade)
+        {
+            return Ok(child);
+        }
+
+        let mut upper = None;
+        // 是否不再向下层查找
+        let mut stop = false;
+        if let Some(dir) = self.upper() {
+            if let Some(inode) = lookup_layer(&dir, name)? {
+                if is_whiteout(&inode)? {
+                    return Err(SystemError::ENOENT);
+                }
+                stop = inode.metadata()?.file_type != FileType::Dir || is_opaque(&inode);
+                upper = Some(inode);
+            }
+        }
+
+        let mut lowers = Vec::new();
+        for dir in self.lowers.iter() {
+            if stop {
+                break;
+            }
+            let Some(inode) = lookup_layer(dir, name)? else {
+                continue;
+            };
+            if is_whiteout(&inode)? {
+                break;
+            }
+
+            let is_dir = inode.metadata()?.file_type == FileType::Dir;
+            // 目录只与下层的同名目录合并
+            if (upper.is_some() || !lowers.is_empty()) && !is_dir {
+                break;
+            }
+            stop = !is_dir || is_opaque(&inode);
+            lowers.push(inode);
+        }
+
+        let child = self.new_child(name, upper, lowers)?;
+        Ok(self.cache_child(name, child, false))
+    }
+
+    fn lookup_optional(&self, name: &str) -> Result<Option<Arc<OvlInode>>, SystemError> {
+        match self.lookup(name) {
+            Ok(inode) => Ok(Some(inode)),
+            Err(SystemError::ENOENT) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// 确保文件在上层存在，返回上层的文件
+    ///
+    /// 从最近的已经位于上层的祖先目录开始，依次向下复制
+    fn copy_up(&self) -> Result<Arc<dyn IndexNode>, SystemError> {
+        if let Some(upper) = self.upper() {
+            return Ok(upper);
+        }
+
+        let fs = self.fs_arc();
+        if fs.upper.is_none() {
+            return Err(SystemError::EROFS);
+        }
+        let _guard = fs.copy_up_lock.lock();
+
+        let mut chain = Vec::new();
+        let mut cur = self.self_ref.upgrade().unwrap();
+        while cur.upper().is_none() {
+            let parent = cur.parent_inode();
+            chain.push(cur);
+            cur = parent;
+        }
+        for inode in chain.into_iter().rev() {
+            inode.do_copy_up(&fs)?;
+        }
+
+        Ok(self.upper().unwrap())
+    }
+
+    fn do_copy_up(&self, fs: &OverlayFS) -> Result<(), SystemError> {
+        let name = self.inner().name.clone();
+        let upper_dir = self.parent_inode().upper().unwrap();
+        let lower = self.lowers[0].clone();
+        let metadata = lower.metadata()?;
+        let perm = ModeType::from_bits_truncate(metadata.mode.bits() & 0o7777);
+
+        // 普通文件先在workdir中复制好，再移动到上层，避免上层出现只复制了一半的文件
+        let staging = match (&fs.work, self.file_type) {
+            (Some(work), FileType::File) => {
+                // 清理上次失败的copy-up留下的临时文件
+                if lookup_layer(work, OVL_COPY_UP_TMPNAME)?.is_some() {
+                    work.unlink(OVL_COPY_UP_TMPNAME)?;
+                }
+                Some(work.clone())
+            }
+            _ => None,
+        };
+        let (dir, new_name) = match &staging {
+            Some(work) => (work.clone(), OVL_COPY_UP_TMPNAME),
+            None => (upper_dir.clone(), name.as_ref()),
+        };
+
+        let upper = match self.file_type {
+            FileType::Dir => dir.create(new_name, FileType::Dir, perm)?,
+            FileType::File => {
+                let upper = dir.create(new_name, FileType::File, perm)?;
+                copy_data(&lower, &upper).inspect_err(|_| {
+                    dir.unlink(new_name).ok();
+                })?;
+                upper
+            }
+            FileType::SymLink => {
+                let mut target = vec![0u8; metadata.size as usize];
+                let len = lower.read_at(
+                    0,
+                    target.len(),
+                    &mut target,
+                    SpinLock::new(FilePrivateData::Unused).lock(),
+                )?;
+                let upper = dir.create(new_name, FileType::SymLink, perm)?;
+                upper.write_at(
+                    0,
+                    len,
+                    &target[..len],
+                    SpinLock::new(FilePrivateData::Unused).lock(),
+                )?;
+                upper
+            }
+            file_type => {
+                let kind = match file_type {
+                    FileType::CharDevice => ModeType::S_IFCHR,
+                    FileType::BlockDevice => ModeType::S_IFBLK,
+                    FileType::Pipe => ModeType::S_IFIFO,
+                    FileType::Socket => ModeType::S_IFSOCK,
+                    _ => return Err(SystemError::EINVAL),
+                };
+                dir.mknod(new_name, kind | perm, metadata.raw_dev)?
+            }
+        };
+
+        // 复制属性。上层文件系统可能不支持扩展属性，忽略复制扩展属性时的错误
+        let mut upper_metadata = upper.metadata()?;
+        upper_metadata.mode = perm;
+        upper_metadata.uid = metadata.uid;
+        upper_metadata.gid = metadata.gid;
+        upper_metadata.atime = metadata.atime;
+        upper_metadata.mtime = metadata.mtime;
+        upper_metadata.ctime = metadata.ctime;
+        upper.set_metadata(&upper_metadata)?;
+        if let Ok(names) = lower.listxattr() {
+            for xname in names.iter().filter(|n| !n.starts_with(OVL_XATTR_PREFIX)) {
+                if let Ok(value) = lower.getxattr(xname) {
+                    upper.setxattr(xname, &value, XattrFlags::empty()).ok();
+                }
+            }
+        }
+
+        if let Some(work) = staging {
+            work.move_to(OVL_COPY_UP_TMPNAME, &upper_dir, name.as_ref())
+                .inspect_err(|_| {
+                    work.unlink(OVL_COPY_UP_TMPNAME).ok();
+                })?;
+        }
+
+        self.inner().upper = Some(upper);
+        Ok(())
+    }
+
+    /// 在合并后的目录中创建文件
+    ///
+    /// ## 参数
+    ///
+    /// - `is_dir`: 创建的是否为目录
+    /// - `create`: 在上层目录中创建文件
+    fn do_create(
+        &self,
+        name: &str,
+        is_dir: bool,
+        create: impl FnOnce(&Arc<dyn IndexNode>) -> Result<Arc<dyn IndexNode>, SystemError>,
+    ) -> Result<Arc<OvlInode>, SystemError> {
+        if !self.is_dir() {
+            return Err(SystemError::ENOTDIR);
+        }
+        if name.len() > OVL_MAX_NAMELEN {
+            return Err(SystemError::ENAMETOOLONG);
+        }
+        if self.lookup_optional(name)?.is_some() {
+            return Err(SystemError::EEXIST);
+        }
+
+        let upper_dir = self.copy_up()?;
+        let whiteout = clear_whiteout(&upper_dir, name)?;
+        let upper = create(&upper_dir)?;
+        // 新的目录取代了被删除的下层目录，不能再与之合并
+        if whiteout && is_dir {
+            upper
+                .setxattr(OVL_XATTR_OPAQUE, b"y", XattrFlags::empty())
+                .inspect_err(|_| {
+                    upper_dir.rmdir(name).ok();
+                    create_whiteout(&upper_dir, name).ok();
+                })?;
+        }
+
+        let child = self.new_child(name, Some(upper), Vec::new())?;
+        Ok(self.cache_child(name, child, true))
+    }
+
+    /// 删除合并后的目录项：删除上层的文件，如果下层也有同名文件，则用whiteout遮住
+    fn do_remove(&self, name: &str, child: &Arc<OvlInode>) -> Result<(), SystemError> {
+        let upper_dir = self.copy_up()?;
+        if let Some(child_upper) = child.upper() {
+            if child.is_dir() {
+                // 合并后的目录已经为空，上层目录中只可能剩下whiteout
+                for entry in child_upper.list()? {
+                    if entry != "." && entry != ".." {
+                        child_upper.unlink(&entry)?;
+                    }
+                }
+                upper_dir.rmdir(name)?;
+            } else {
+                upper_dir.unlink(name)?;
+            }
+        }
+        if !child.lowers.is_empty() {
+            create_whiteout(&upper_dir, name)?;
+        }
+
+        self.inner().children.remove(&DName::from(name));
+        Ok(())
+    }
+}
+
+impl IndexNode for OvlInode {
+    /// 以可写方式打开下层的普通文件时，先把它复制到上层
+    fn open(
+        &self,
+        data: SpinLockGuard<FilePrivateData>,
+        mode: &FileMode,
+    ) -> Result<(), SystemError> {
+        if self.file_type == FileType::File && mode.accmode() != FileMode::O_RDONLY.bits() {
+            self.copy_up()?;
+        }
+        self.real().open(data, mode)
+    }
+
+    fn close(&self, data: SpinLockGuard<FilePrivateData>) -> Result<(), SystemError> {
+        self.real().close(data)
+    }
+
+    fn read_at(
+        &self,
+        offset: usize,
+        len: usize,
+        buf: &mut [u8],
+        data: SpinLockGuard<FilePrivateData>,
+    ) -> Result<usize, SystemError> {
+        self.real().read_at(offset, len, buf, data)
+    }
+
+    fn write_at(
+        &self,
+        offset: usize,
+        len: usize,
+        buf: &[u8],
+        data: SpinLockGuard<FilePrivateData>,
+    ) -> Result<usize, SystemError> {
+        self.copy_up()?.write_at(offset, len, buf, data)
+    }
+
+    fn poll(&self, private_data: &FilePrivateData) -> Result<usize, SystemError> {
+        self.real().poll(private_data)
+    }
+
+    fn metadata(&self) -> Result<Metadata, SystemError> {
+        let mut metadata = self.real().metadata()?;
+        metadata.inode_id = self.ino;
+        Ok(metadata)
+    }
+
+    fn set_metadata(&self, metadata: &Metadata) -> Result<(), SystemError> {
+        self.copy_up()?.set_metadata(metadata)
+    }
+
+    fn resize(&self, len: usize) -> Result<(), SystemError> {
+        self.copy_up()?.resize(len)
+    }
+
+    fn truncate(&self, len: usize) -> Result<(), SystemError> {
+        self.copy_up()?.truncate(len)
+    }
+
+    fn create_with_data(
+        &self,
+        name: &str,
+        file_type: FileType,
+        mode: ModeType,
+        data: usize,
+    ) -> Result<Arc<dyn IndexNode>, SystemError> {
+        let child = self.do_create(name, file_type == FileType::Dir, |upper_dir| {
+            upper_dir.create_with_data(name, file_type, mode, data)
+        })?;
+        Ok(child)
+    }
+
+    fn mknod(
+        &self,
+        filename: &str,
+        mode: ModeType,
+        dev_t: DeviceNumber,
+    ) -> Result<Arc<dyn IndexNode>, SystemError> {
+        let child = self.do_create(filename, false, |upper_dir| {
+            upper_dir.mknod(filename, mode, dev_t)
+        })?;
+        Ok(child)
+    }
+
+    fn link(&self, name: &str, other: &Arc<dyn IndexNode>) -> Result<(), SystemError> {
+        let other = self.same_fs_inode(other)?;
+        if other.is_dir() {
+            return Err(SystemError::EPERM);
+        }
+        if !self.is_dir() {
+            return Err(SystemError::ENOTDIR);
+        }
+        if self.lookup_optional(name)?.is_some() {
+            return Err(SystemError::EEXIST);
+        }
+
+        let other_upper = other.copy_up()?;
+        let upper_dir = self.copy_up()?;
+        clear_whiteout(&upper_dir, name)?;
+        upper_dir.link(name, &other_upper)?;
+        // 两个名字对应同一个文件
+        self.cache_child(name, other, true);
+        Ok(())
+    }
+
+    fn unlink(&self, name: &str) -> Result<(), SystemError> {
+        let child = self.lookup(name)?;
+        if child.is_dir() {
+            return Err(SystemError::EISDIR);
+        }
+        self.do_remove(name, &child)
+    }
+
+    fn rmdir(&self, name: &str) -> Result<(), SystemError> {
+        let child = self.lookup(name)?;
+        if !child.is_dir() {
+            return Err(SystemError::ENOTDIR);
+        }
+        if child.list()?.iter().any(|n| n != "." && n != "..") {
+            return Err(SystemError::ENOTEMPTY);
+        }
+        self.do_remove(name, &child)
+    }
+
+    fn move_to(
+        &self,
+        old_name: &str,
+        target: &Arc<dyn IndexNode>,
+        new_name: &str,
+    ) -> Result<(), SystemError> {
+        let target = self.same_fs_inode(target)?;
+        if !target.is_dir() {
+            return Err(SystemError::ENOTDIR);
+        }
+        if new_name.len() > OVL_MAX_NAMELEN {
+            return Err(SystemError::ENAMETOOLONG);
+        }
+        let child = self.lookup(old_name)?;
+        // 与Linux不开启redirect_dir时一致，不能重命名与下层合并的目录，用户态程序会退回到复制
+        if child.is_dir() && !child.lowers.is_empty() {
+            return Err(SystemError::EXDEV);
+        }
+
+        // 目标位置已有文件时先将其删除
+        if let Some(victim) = target.lookup_optional(new_name)? {
+            if Arc::ptr_eq(&victim, &child) {
+                return Ok(());
+            }
+            match (child.is_dir(), victim.is_dir()) {
+                (true, false) => return Err(SystemError::ENOTDIR),
+                (false, true) => return Err(SystemError::EISDIR),
+                (true, true) => target.rmdir(new_name)?,
+                (false, false) => target.unlink(new_name)?,
+            }
+        }
+
+        let child_upper = child.copy_up()?;
+        let src_upper_dir = self.copy_up()?;
+        let dst_upper_dir = target.copy_up()?;
+        let whiteout = clear_whiteout(&dst_upper_dir, new_name)?;
+        src_upper_dir.move_to(old_name, &dst_upper_dir, new_name)?;
+        if !child.lowers.is_empty() {
+            create_whiteout(&src_upper_dir, old_name)?;
+        }
+        // 移动过来的目录取代了被删除的下层目录，不能再与之合并
+        if whiteout && child.is_dir() {
+            child_upper.setxattr(OVL_XATTR_OPAQUE, b"y", XattrFlags::empty())?;
+        }
+
+        self.inner().children.remove(&DName::from(old_name));
+        {
+            let mut inner = child.inner();
+            inner.parent = target.self_ref.upgrade();
+            inner.name = DName::from(new_name);
+        }
+        target.cache_child(new_name, child, true);
+        Ok(())
+    }
+
+    fn find(&self, name: &str) -> Result<Arc<dyn IndexNode>, SystemError> {
+        if !self.is_dir() {
+            return Err(SystemError::ENOTDIR);
+        }
+
+        match name {
+            "" | "." => Ok(self.self_ref.upgrade().ok_or(SystemError::ENOENT)?),
+            ".." => self.parent(),
+            name => Ok(self.lookup(name)?),
+        }
+    }
+
+    fn get_entry_name(&self, ino: InodeId) -> Result<String, SystemError> {
+        for name in self.list()? {
+            if name == "." || name == ".." {
+                continue;
+            }
+            if self.find(&name)?.metadata()?.inode_id == ino {
+                return Ok(name);
+            }
+        }
+        Err(SystemError::ENOENT)
+    }
+
+    fn ioctl(
+        &self,
+        cmd: u32,
+        data: usize,
+        private_data: &FilePrivateData,
+    ) -> Result<usize, SystemError> {
+        self.real().ioctl(cmd, data, private_data)
+    }
+
+    fn fs(&self) -> Arc<dyn FileSystem> {
+        self.fs_arc()
+    }
+
+    fn as_any_ref(&self) -> &dyn Any {
+        self
+    }
+
+    /// 合并所有层的目录项：上层的文件遮住下层的同名文件，whiteout遮住下层的文件但自身不可见
+    fn list(&self) -> Result<Vec<String>, SystemError> {
+        if !self.is_dir() {
+            return Err(SystemError::ENOTDIR);
+        }
+
+        let mut names = vec![String::from("."), String::from("..")];
+        let mut seen = BTreeSet::new();
+        for layer in self.upper().iter().chain(self.lowers.iter()) {
+            for name in layer.list()? {
+                if name == "." || name == ".." || seen.contains(&name) {
+                    continue;
+                }
+                if !is_whiteout(&layer.find(&name)?)? {
+                    names.push(name.clone());
+                }
+                seen.insert(name);
+            }
+        }
+
+        // 顺便清理已经被释放的缓存项
+        self.inner()
+            .children
+            .retain(|_, child| child.strong_count() > 0);
+        Ok(names)
+    }
+
+    fn sync(&self) -> Result<(), SystemError> {
+        match self.upper() {
+            Some(upper) => upper.sync(),
+            None => Ok(()),
+        }
+    }
+
+    fn special_node(&self) -> Option<SpecialNodeData> {
+        self.real().special_node()
+    }
+
+    fn getxattr(&self, name: &str) -> Result<Vec<u8>, SystemError> {
+        if name.starts_with(OVL_XATTR_PREFIX) {
+            return Err(SystemError::ENODATA);
+        }
+        self.real().getxattr(name)
+    }
+
+    fn setxattr(&self, name: &str, value: &[u8], flags: XattrFlags) -> Result<(), SystemError> {
+        if name.starts_with(OVL_XATTR_PREFIX) {
+            return Err(SystemError::EPERM);
+        }
+        self.copy_up()?.setxattr(name, value, flags)
+    }
+
+    fn listxattr(&self) -> Result<Vec<String>, SystemError> {
+        let mut names = self.real().listxattr()?;
+        names.retain(|name| !name.starts_with(OVL_XATTR_PREFIX));
+        Ok(names)
+    }
+
+    fn removexattr(&self, name: &str) -> Result<(), SystemError> {
+        if name.starts_with(OVL_XATTR_PREFIX) {
+            return Err(SystemError::EPERM);
+        }
+        self.copy_up()?.removexattr(name)
+    }
+
+    fn dname(&self) -> Result<DName, SystemError> {
+        Ok(self.inner().name.clone())
+    }
+
+    fn parent(&self) -> Result<Arc<dyn IndexNode>, SystemError> {
+        Ok(self.parent_inode())
+    }
+
+    fn page_cache(&self) -> Option<Arc<PageCache>> {
+        self.real().page_cache()
+    }
+}
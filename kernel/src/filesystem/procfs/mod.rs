@@ -15,7 +15,7 @@ use crate::{
     arch::mm::LockedFrameAllocator,
     driver::base::device::device_number::DeviceNumber,
     filesystem::vfs::{
-        core::{generate_inode_id, ROOT_INODE},
+        core::{generate_inode_id, init_root_inode, ROOT_INODE},
         FileType,
     },
     libs::{
@@ -24,12 +24,18 @@ use crate::{
         spinlock::{SpinLock, SpinLockGuard},
     },
     mm::allocator::page_frame::FrameAllocator,
-    process::{Pid, ProcessManager},
+    process::{
+        capability::capable,
+        coredump::{core_pattern, set_core_pattern},
+        cred::CAPFlags,
+        namespace::{NsRef, NsType},
+        Pid, ProcessManager,
+    },
     time::PosixTimeSpec,
 };
 
 use super::vfs::{
-    file::{FileMode, FilePrivateData},
+    file::{File, FileMode, FilePrivateData},
     syscall::ModeType,
     utils::DName,
     FileSystem, FsInfo, IndexNode, InodeId, Magic, Metadata, SuperBlock,
@@ -50,6 +56,16 @@ pub enum ProcFileType {
     ProcMeminfo = 1,
     /// kmsg
     ProcKmsg = 2,
+    /// 进程所在的各类namespace，用于setns
+    ProcNsIpc = 3,
+    ProcNsMnt = 4,
+    ProcNsPid = 5,
+    ProcNsUts = 6,
+    ProcNsNet = 7,
+    /// 进程所在的cgroup
+    ProcCgroup = 8,
+    /// core文件的路径模板，/proc/sys/kernel/core_pattern
+    ProcCorePattern = 9,
     //todo: 其他文件类型
     ///默认文件类型
     Default,
@@ -61,10 +77,41 @@ impl From<u8> for ProcFileType {
             0 => ProcFileType::ProcStatus,
             1 => ProcFileType::ProcMeminfo,
             2 => ProcFileType::ProcKmsg,
+            3 => ProcFileType::ProcNsIpc,
+            4 => ProcFileType::ProcNsMnt,
+            5 => ProcFileType::ProcNsPid,
+            6 => ProcFileType::ProcNsUts,
+            7 => ProcFileType::ProcNsNet,
+            8 => ProcFileType::ProcCgroup,
+            9 => ProcFileType::ProcCorePattern,
             _ => ProcFileType::Default,
         }
     }
 }
+
+impl ProcFileType {
+    /// namespace文件对应的namespace类型
+    fn ns_type(&self) -> Option<NsType> {
+        match self {
+            ProcFileType::ProcNsIpc => Some(NsType::Ipc),
+            ProcFileType::ProcNsMnt => Some(NsType::Mnt),
+            ProcFileType::ProcNsPid => Some(NsType::Pid),
+            ProcFileType::ProcNsUts => Some(NsType::Uts),
+            ProcFileType::ProcNsNet => Some(NsType::Net),
+            _ => None,
+        }
+    }
+
+    fn from_ns_type(ns_type: NsType) -> Self {
+        match ns_type {
+            NsType::Ipc => ProcFileType::ProcNsIpc,
+            NsType::Mnt => ProcFileType::ProcNsMnt,
+            NsType::Pid => ProcFileType::ProcNsPid,
+            NsType::Uts => ProcFileType::ProcNsUts,
+            NsType::Net => ProcFileType::ProcNsNet,
+        }
+    }
+}
 /// @brief 节点私有信息结构体
 /// @usage 用于传入各类文件所需的信息
 #[derive(Debug)]
@@ -94,11 +141,16 @@ pub struct ProcFS {
 #[derive(Debug, Clone)]
 pub struct ProcfsFilePrivateData {
     data: Vec<u8>,
+    /// 打开/proc/<pid>/ns/下的文件时引用的namespace
+    ns: Option<NsRef>,
 }
 
 impl ProcfsFilePrivateData {
     pub fn new() -> Self {
-        return ProcfsFilePrivateData { data: Vec::new() };
+        return ProcfsFilePrivateData {
+            data: Vec::new(),
+            ns: None,
+        };
     }
 }
 
@@ -199,6 +251,8 @@ impl ProcFSInode {
 
         // kthread
         pdata.append(&mut format!("\nKthread:\t{}", pcb.is_kthread() as usize).into());
+        pdata.append(&mut format!("\nNoNewPrivs:\t{}", pcb.no_new_privs() as usize).into());
+        pdata.append(&mut format!("\nSeccomp:\t{}", pcb.seccomp_mode() as usize).into());
 
         pdata.append(&mut format!("\ncpu_id:\t{}", cpu_id).as_bytes().to_owned());
         pdata.append(&mut format!("\npriority:\t{:?}", priority).as_bytes().to_owned());
@@ -266,6 +320,55 @@ impl ProcFSInode {
         return Ok((data.len() * size_of::<u8>()) as i64);
     }
 
+    /// 打开 ns 目录下的文件，内容为namespace的类型与编号，例如`uts:[4026531838]`
+    fn open_ns(&self, pdata: &mut ProcfsFilePrivateData) -> Result<i64, SystemError> {
+        let pid = self.fdata.pid;
+        let pcb = ProcessManager::find(pid).ok_or(SystemError::ESRCH)?;
+        let ns = pcb.ns_ref(self.fdata.ftype.ns_type().unwrap());
+
+        let data: &mut Vec<u8> = &mut pdata.data;
+        data.append(
+            &mut format!("{}:[{}]\n", ns.ns_type().name(), ns.inum())
+                .as_bytes()
+                .to_owned(),
+        );
+        pdata.ns = Some(ns);
+
+        // 去除多余的\0
+        self.trim_string(data);
+
+        return Ok((data.len() * size_of::<u8>()) as i64);
+    }
+
+    /// 打开 cgroup 文件，内容为进程在cgroup v2层级中的路径，例如`0::/ci/job1`
+    fn open_cgroup(&self, pdata: &mut ProcfsFilePrivateData) -> Result<i64, SystemError> {
+        let pid = self.fdata.pid;
+        let pcb = ProcessManager::find(pid).ok_or(SystemError::ESRCH)?;
+
+        let data: &mut Vec<u8> = &mut pdata.data;
+        data.append(
+            &mut format!("0::{}\n", pcb.cgroup().path())
+                .as_bytes()
+                .to_owned(),
+        );
+
+        // 去除多余的\0
+        self.trim_string(data);
+
+        return Ok((data.len() * size_of::<u8>()) as i64);
+    }
+
+    /// 打开 core_pattern 文件，内容为当前的core_pattern
+    fn open_core_pattern(&self, pdata: &mut ProcfsFilePrivateData) -> Result<i64, SystemError> {
+        let data: &mut Vec<u8> = &mut pdata.data;
+        data.append(&mut format!("{}\n", core_pattern()).as_bytes().to_owned());
+
+        // 去除多余的\0
+        self.trim_string(data);
+
+        return Ok((data.len() * size_of::<u8>()) as i64);
+    }
+
     /// proc文件系统读取函数
     fn proc_read(
         &self,
@@ -395,6 +498,30 @@ impl ProcFS {
             panic!("create ksmg error");
         }
 
+        // 创建sys/kernel/core_pattern文件
+        let binding = inode
+            .create("sys", FileType::Dir, ModeType::from_bits_truncate(0o555))
+            .and_then(|sys| {
+                sys.create("kernel", FileType::Dir, ModeType::from_bits_truncate(0o555))
+            })
+            .and_then(|kernel| {
+                kernel.create(
+                    "core_pattern",
+                    FileType::File,
+                    ModeType::from_bits_truncate(0o644),
+                )
+            });
+        if let Ok(core_pattern) = binding {
+            let core_pattern_file = core_pattern
+                .as_any_ref()
+                .downcast_ref::<LockedProcFSInode>()
+                .unwrap();
+            core_pattern_file.0.lock().fdata.pid = Pid::new(0);
+            core_pattern_file.0.lock().fdata.ftype = ProcFileType::ProcCorePattern;
+        } else {
+            panic!("create core_pattern error");
+        }
+
         return result;
     }
 
@@ -423,6 +550,36 @@ impl ProcFS {
         status_file.0.lock().fdata.pid = pid;
         status_file.0.lock().fdata.ftype = ProcFileType::ProcStatus;
 
+        // cgroup文件
+        let binding: Arc<dyn IndexNode> = pid_dir.create(
+            "cgroup",
+            FileType::File,
+            ModeType::from_bits_truncate(0o444),
+        )?;
+        let cgroup_file: &LockedProcFSInode = binding
+            .as_any_ref()
+            .downcast_ref::<LockedProcFSInode>()
+            .unwrap();
+        cgroup_file.0.lock().fdata.pid = pid;
+        cgroup_file.0.lock().fdata.ftype = ProcFileType::ProcCgroup;
+
+        // ns目录
+        let ns_dir: Arc<dyn IndexNode> =
+            pid_dir.create("ns", FileType::Dir, ModeType::from_bits_truncate(0o511))?;
+        for ns_type in NsType::ALL {
+            let binding: Arc<dyn IndexNode> = ns_dir.create(
+                ns_type.name(),
+                FileType::File,
+                ModeType::from_bits_truncate(0o444),
+            )?;
+            let ns_file: &LockedProcFSInode = binding
+                .as_any_ref()
+                .downcast_ref::<LockedProcFSInode>()
+                .unwrap();
+            ns_file.0.lock().fdata.pid = pid;
+            ns_file.0.lock().fdata.ftype = ProcFileType::from_ns_type(ns_type);
+        }
+
         //todo: 创建其他文件
 
         return Ok(());
@@ -437,6 +594,12 @@ impl ProcFS {
         let pid_dir: Arc<dyn IndexNode> = proc.find(&pid.to_string())?;
         // 删除进程文件夹下文件
         pid_dir.unlink("status")?;
+        pid_dir.unlink("cgroup")?;
+        let ns_dir: Arc<dyn IndexNode> = pid_dir.find("ns")?;
+        for ns_type in NsType::ALL {
+            ns_dir.unlink(ns_type.name())?;
+        }
+        pid_dir.unlink("ns")?;
 
         // 查看进程文件是否还存在
         // let pf= pid_dir.find("status").expect("Cannot find status");
@@ -461,11 +624,22 @@ impl IndexNode for LockedProcFSInode {
         if let FileType::Dir = inode.metadata.file_type {
             return Ok(());
         }
+        // 复制ns文件的文件描述符时，沿用原文件引用的namespace，而不是重新查找进程
+        if let FilePrivateData::Procfs(ProcfsFilePrivateData { ns: Some(_), .. }) = &*data {
+            return Ok(());
+        }
         let mut private_data = ProcfsFilePrivateData::new();
         // 根据文件类型获取相应数据
         let file_size = match inode.fdata.ftype {
             ProcFileType::ProcStatus => inode.open_status(&mut private_data)?,
             ProcFileType::ProcMeminfo => inode.open_meminfo(&mut private_data)?,
+            ProcFileType::ProcNsIpc
+            | ProcFileType::ProcNsMnt
+            | ProcFileType::ProcNsPid
+            | ProcFileType::ProcNsUts
+            | ProcFileType::ProcNsNet => inode.open_ns(&mut private_data)?,
+            ProcFileType::ProcCgroup => inode.open_cgroup(&mut private_data)?,
+            ProcFileType::ProcCorePattern => inode.open_core_pattern(&mut private_data)?,
             _ => {
                 todo!()
             }
@@ -523,6 +697,19 @@ impl IndexNode for LockedProcFSInode {
             ProcFileType::ProcMeminfo => {
                 return inode.proc_read(offset, len, buf, &mut private_data)
             }
+            ProcFileType::ProcNsIpc
+            | ProcFileType::ProcNsMnt
+            | ProcFileType::ProcNsPid
+            | ProcFileType::ProcNsUts
+            | ProcFileType::ProcNsNet => {
+                return inode.proc_read(offset, len, buf, &mut private_data)
+            }
+            ProcFileType::ProcCgroup => {
+                return inode.proc_read(offset, len, buf, &mut private_data)
+            }
+            ProcFileType::ProcCorePattern => {
+                return inode.proc_read(offset, len, buf, &mut private_data)
+            }
             ProcFileType::ProcKmsg => (),
             ProcFileType::Default => (),
         };
@@ -544,12 +731,29 @@ impl IndexNode for LockedProcFSInode {
 
     fn write_at(
         &self,
-        _offset: usize,
-        _len: usize,
-        _buf: &[u8],
+        offset: usize,
+        len: usize,
+        buf: &[u8],
         _data: SpinLockGuard<FilePrivateData>,
     ) -> Result<usize, SystemError> {
-        return Err(SystemError::ENOSYS);
+        if buf.len() < len {
+            return Err(SystemError::EINVAL);
+        }
+        let inode: SpinLockGuard<ProcFSInode> = self.0.lock();
+        match inode.fdata.ftype {
+            ProcFileType::ProcCorePattern => {
+                // 每次写入都会替换整个core_pattern
+                if offset != 0 {
+                    return Err(SystemError::EINVAL);
+                }
+                if !capable(CAPFlags::CAP_SYS_ADMIN) {
+                    return Err(SystemError::EPERM);
+                }
+                set_core_pattern(&buf[..len])?;
+                return Ok(len);
+            }
+            _ => return Err(SystemError::ENOSYS),
+        }
     }
 
     fn fs(&self) -> Arc<dyn FileSystem> {
@@ -804,7 +1008,8 @@ impl IndexNode for LockedProcFSInode {
 
 /// @brief 向procfs注册进程
 pub fn procfs_register_pid(pid: Pid) -> Result<(), SystemError> {
-    let procfs_inode = ROOT_INODE().find("proc")?;
+    // procfs是全局的，不受进程所在mount namespace的影响
+    let procfs_inode = init_root_inode().find("proc")?;
 
     let procfs_inode = procfs_inode
         .downcast_ref::<LockedProcFSInode>()
@@ -821,7 +1026,7 @@ pub fn procfs_register_pid(pid: Pid) -> Result<(), SystemError> {
 /// @brief 在ProcFS中,解除进程的注册
 pub fn procfs_unregister_pid(pid: Pid) -> Result<(), SystemError> {
     // 获取procfs实例
-    let procfs_inode: Arc<dyn IndexNode> = ROOT_INODE().find("proc")?;
+    let procfs_inode: Arc<dyn IndexNode> = init_root_inode().find("proc")?;
 
     let procfs_inode: &LockedProcFSInode = procfs_inode
         .downcast_ref::<LockedProcFSInode>()
@@ -852,3 +1057,11 @@ pub fn procfs_init() -> Result<(), SystemError> {
 
     return result.unwrap();
 }
+
+/// 若`file`是打开/proc/<pid>/ns/目录下的文件得到的，则返回打开时所引用的namespace
+pub fn procfs_ns_ref(file: &File) -> Option<NsRef> {
+    match &*file.private_data.lock() {
+        FilePrivateData::Procfs(pdata) => pdata.ns.clone(),
+        _ => None,
+    }
+}
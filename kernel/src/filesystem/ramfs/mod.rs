@@ -22,8 +22,12 @@ use alloc::{
 use system_error::SystemError;
 
 use super::vfs::{
-    file::FilePrivateData, syscall::ModeType, utils::DName, FileSystem, FileSystemMaker, FsInfo,
-    IndexNode, InodeId, Metadata, SpecialNodeData,
+    file::FilePrivateData,
+    syscall::ModeType,
+    utils::DName,
+    xattr::{SimpleXattrs, XattrFlags},
+    FileSystem, FileSystemMaker, FileSystemMakerData, FsInfo, IndexNode, InodeId, Metadata,
+    SpecialNodeData,
 };
 
 use linkme::distributed_slice;
@@ -68,6 +72,8 @@ pub struct RamFSInode {
     special_node: Option<SpecialNodeData>,
 
     name: DName,
+    /// 扩展属性
+    xattrs: SimpleXattrs,
 }
 
 impl FileSystem for RamFS {
@@ -129,6 +135,7 @@ impl RamFS {
             fs: Weak::default(),
             special_node: None,
             name: Default::default(),
+            xattrs: SimpleXattrs::new(),
         })));
 
         let result: Arc<RamFS> = Arc::new(RamFS {
@@ -147,7 +154,9 @@ impl RamFS {
         return result;
     }
 
-    pub fn make_ramfs() -> Result<Arc<dyn FileSystem + 'static>, SystemError> {
+    pub fn make_ramfs(
+        _data: &FileSystemMakerData,
+    ) -> Result<Arc<dyn FileSystem + 'static>, SystemError> {
         let fs = RamFS::new();
         return Ok(fs);
     }
@@ -155,7 +164,8 @@ impl RamFS {
 #[distributed_slice(FSMAKER)]
 static RAMFSMAKER: FileSystemMaker = FileSystemMaker::new(
     "ramfs",
-    &(RamFS::make_ramfs as fn() -> Result<Arc<dyn FileSystem + 'static>, SystemError>),
+    &(RamFS::make_ramfs
+        as fn(&FileSystemMakerData) -> Result<Arc<dyn FileSystem + 'static>, SystemError>),
 );
 
 impl IndexNode for LockedRamFSInode {
@@ -331,6 +341,7 @@ impl IndexNode for LockedRamFSInode {
             fs: inode.fs.clone(),
             special_node: None,
             name: name.clone(),
+            xattrs: SimpleXattrs::new(),
         })));
 
         // 初始化inode的自引用的weak指针
@@ -554,7 +565,7 @@ impl IndexNode for LockedRamFSInode {
         &self,
         filename: &str,
         mode: ModeType,
-        _dev_t: DeviceNumber,
+        dev_t: DeviceNumber,
     ) -> Result<Arc<dyn IndexNode>, SystemError> {
         let mut inode = self.0.lock();
         if inode.metadata.file_type != FileType::Dir {
@@ -593,6 +604,7 @@ impl IndexNode for LockedRamFSInode {
             fs: inode.fs.clone(),
             special_node: None,
             name: filename.clone(),
+            xattrs: SimpleXattrs::new(),
         })));
 
         nod.0.lock().self_ref = Arc::downgrade(&nod);
@@ -604,11 +616,13 @@ impl IndexNode for LockedRamFSInode {
             // 设置special_node
             nod.0.lock().special_node = Some(SpecialNodeData::Pipe(pipe_inode));
         } else if mode.contains(ModeType::S_IFBLK) {
-            nod.0.lock().metadata.file_type = FileType::BlockDevice;
-            unimplemented!()
+            let mut nod_inode = nod.0.lock();
+            nod_inode.metadata.file_type = FileType::BlockDevice;
+            nod_inode.metadata.raw_dev = dev_t;
         } else if mode.contains(ModeType::S_IFCHR) {
-            nod.0.lock().metadata.file_type = FileType::CharDevice;
-            unimplemented!()
+            let mut nod_inode = nod.0.lock();
+            nod_inode.metadata.file_type = FileType::CharDevice;
+            nod_inode.metadata.raw_dev = dev_t;
         }
 
         inode.children.insert(filename, nod.clone());
@@ -619,6 +633,28 @@ impl IndexNode for LockedRamFSInode {
         return self.0.lock().special_node.clone();
     }
 
+    fn getxattr(&self, name: &str) -> Result<Vec<u8>, SystemError> {
+        self.0.lock().xattrs.get(name)
+    }
+
+    fn setxattr(&self, name: &str, value: &[u8], flags: XattrFlags) -> Result<(), SystemError> {
+        let mut inode = self.0.lock();
+        inode.xattrs.set(name, value, flags)?;
+        inode.metadata.ctime = PosixTimeSpec::now();
+        return Ok(());
+    }
+
+    fn listxattr(&self) -> Result<Vec<String>, SystemError> {
+        Ok(self.0.lock().xattrs.list())
+    }
+
+    fn removexattr(&self, name: &str) -> Result<(), SystemError> {
+        let mut inode = self.0.lock();
+        inode.xattrs.remove(name)?;
+        inode.metadata.ctime = PosixTimeSpec::now();
+        return Ok(());
+    }
+
     fn dname(&self) -> Result<DName, SystemError> {
         Ok(self.0.lock().name.clone())
     }
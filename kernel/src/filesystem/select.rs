@@ -0,0 +1,367 @@
+//! select与pselect6
+//!
+//! 文件的就绪状态通过[`IndexNode::poll`](crate::filesystem::vfs::IndexNode::poll)获取。
+//! 需要等待时，所有被监听的文件都会被加入一个临时的epoll，任何一个文件的状态发生变化时，
+//! epoll都会唤醒当前进程，然后重新检查所有文件的状态。
+//!
+//! 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/fs/select.c
+
+use core::mem::size_of;
+
+use alloc::{sync::Arc, vec, vec::Vec};
+use system_error::SystemError;
+
+use crate::{
+    arch::ipc::signal::SigSet,
+    ipc::signal::{restore_saved_sigmask_unless, set_user_sigmask},
+    net::event_poll::{EPollCtlOption, EPollEvent, EPollEventType, EventPoll},
+    process::ProcessManager,
+    syscall::{
+        user_access::{UserBufferReader, UserBufferWriter},
+        Syscall,
+    },
+    time::{syscall::PosixTimeval, Duration, Instant, PosixTimeSpec},
+};
+
+use super::vfs::file::{File, FileDescriptorVec, FileMode};
+
+/// fd_set中每个元素的位数
+const NFDBITS: usize = 8 * size_of::<usize>();
+
+/// 使文件在读集合中就绪的事件
+const POLLIN_SET: u32 = EPollEventType::EPOLLRDNORM.bits()
+    | EPollEventType::EPOLLRDBAND.bits()
+    | EPollEventType::EPOLLIN.bits()
+    | EPollEventType::EPOLLHUP.bits()
+    | EPollEventType::EPOLLERR.bits();
+/// 使文件在写集合中就绪的事件
+const POLLOUT_SET: u32 = EPollEventType::EPOLLWRBAND.bits()
+    | EPollEventType::EPOLLWRNORM.bits()
+    | EPollEventType::EPOLLOUT.bits()
+    | EPollEventType::EPOLLERR.bits();
+/// 使文件在异常集合中就绪的事件
+const POLLEX_SET: u32 = EPollEventType::EPOLLPRI.bits();
+
+/// 不支持poll的文件（例如普通文件）总是可读写的
+const DEFAULT_POLLMASK: u32 = EPollEventType::EPOLLIN.bits()
+    | EPollEventType::EPOLLOUT.bits()
+    | EPollEventType::EPOLLRDNORM.bits()
+    | EPollEventType::EPOLLWRNORM.bits();
+
+/// 用户空间的fd_set，每一位对应一个文件描述符
+#[derive(Clone)]
+struct FdSet {
+    /// 用户空间的地址，为空表示用户不关心这个集合
+    addr: *mut usize,
+    bits: Vec<usize>,
+}
+
+impl FdSet {
+    fn from_user(addr: *mut usize, nfds: usize) -> Result<Self, SystemError> {
+        let words = nfds.div_ceil(NFDBITS);
+        let bits = if addr.is_null() || words == 0 {
+            vec![0; words]
+        } else {
+            let reader =
+                UserBufferReader::new(addr as *const usize, words * size_of::<usize>(), true)?;
+            reader.read_from_user::<usize>(0)?.to_vec()
+        };
+        return Ok(Self { addr, bits });
+    }
+
+    fn is_set(&self, fd: usize) -> bool {
+        return self.bits[fd / NFDBITS] & (1 << (fd % NFDBITS)) != 0;
+    }
+
+    fn set(&mut self, fd: usize) {
+        self.bits[fd / NFDBITS] |= 1 << (fd % NFDBITS);
+    }
+
+    fn clear(&mut self) {
+        self.bits.fill(0);
+    }
+
+    fn copy_to_user(&self) -> Result<(), SystemError> {
+        if self.addr.is_null() || self.bits.is_empty() {
+            return Ok(());
+        }
+        let mut writer =
+            UserBufferWriter::new(self.addr, self.bits.len() * size_of::<usize>(), true)?;
+        writer.copy_to_user(&self.bits, 0)?;
+        return Ok(());
+    }
+}
+
+/// 被监听的一个文件
+struct SelectEntry {
+    fd: usize,
+    file: Arc<File>,
+    /// 感兴趣的事件
+    events: u32,
+}
+
+/// 检查所有文件的状态，把就绪的文件记录到`sets`中，返回就绪的位的数量
+fn select_scan(entries: &[SelectEntry], requested: &[FdSet; 3], sets: &mut [FdSet; 3]) -> usize {
+    sets.iter_mut().for_each(FdSet::clear);
+    let mut count = 0;
+    for entry in entries {
+        let revents = entry
+            .file
+            .poll()
+            .map(|events| events as u32)
+            .unwrap_or(DEFAULT_POLLMASK);
+        for (i, mask) in [POLLIN_SET, POLLOUT_SET, POLLEX_SET]
+            .into_iter()
+            .enumerate()
+        {
+            if requested[i].is_set(entry.fd) && revents & mask != 0 {
+                sets[i].set(entry.fd);
+                count += 1;
+            }
+        }
+    }
+    return count;
+}
+
+/// 把所有文件加入一个临时的epoll，以便在文件状态变化时被唤醒
+///
+/// 不支持epoll的文件会被跳过，它们的状态只会在被其他文件唤醒或超时之后重新检查
+fn select_register(entries: &[SelectEntry]) -> Result<Arc<File>, SystemError> {
+    let ep_file = Arc::new(EventPoll::create_epoll_file(FileMode::empty())?);
+    for entry in entries {
+        let mut event = EPollEvent::default();
+        event.set_events(entry.events);
+        let _ = EventPoll::epoll_ctl_with_epfile(
+            ep_file.clone(),
+            EPollCtlOption::Add,
+            entry.fd as i32,
+            entry.file.clone(),
+            &mut event,
+            false,
+        );
+    }
+    return Ok(ep_file);
+}
+
+/// # select的实际执行函数
+///
+/// ## 参数
+/// - `sets`: 读、写、异常三个集合，返回时被替换为就绪的文件
+/// - `deadline`: 超时的时刻，为None时一直等待
+///
+/// ## 返回值
+/// - `Ok(usize)`: 就绪的位的数量，超时返回0
+fn do_select(
+    nfds: usize,
+    sets: &mut [FdSet; 3],
+    deadline: Option<Instant>,
+) -> Result<usize, SystemError> {
+    let fd_table = ProcessManager::current_pcb().fd_table();
+    let mut entries = Vec::new();
+    for fd in 0..nfds {
+        let mut events = 0;
+        for (i, mask) in [POLLIN_SET, POLLOUT_SET, POLLEX_SET]
+            .into_iter()
+            .enumerate()
+        {
+            if sets[i].is_set(fd) {
+                events |= mask;
+            }
+        }
+        if events == 0 {
+            continue;
+        }
+        let file = fd_table
+            .read()
+            .get_file_by_fd(fd as i32)
+            .ok_or(SystemError::EBADF)?;
+        entries.push(SelectEntry { fd, file, events });
+    }
+
+    // 用户传入的集合，sets用于保存结果
+    let requested = sets.clone();
+    let mut ep_file = None;
+    let max_events = entries.len().max(1);
+    let mut epoll_events = vec![EPollEvent::default(); max_events];
+    loop {
+        let count = select_scan(&entries, &requested, sets);
+        let timed_out = deadline.is_some_and(|deadline| Instant::now() >= deadline);
+        if count > 0 || timed_out {
+            return Ok(count);
+        }
+
+        if ep_file.is_none() {
+            ep_file = Some(select_register(&entries)?);
+        }
+        let timespec = deadline.map(|deadline| PosixTimeSpec::from(deadline - Instant::now()));
+        EventPoll::epoll_wait_with_file(
+            ep_file.clone().unwrap(),
+            &mut epoll_events,
+            max_events as i32,
+            timespec,
+        )?;
+    }
+}
+
+/// # select与pselect6的公共部分
+///
+/// ## 参数
+/// - `timeout`: 相对的超时时间，为None时一直等待
+///
+/// ## 返回值
+/// - `(Result<usize, SystemError>, Option<PosixTimeSpec>)`: select的结果，以及剩余的超时时间
+fn core_sys_select(
+    nfds: i32,
+    readfds: *mut usize,
+    writefds: *mut usize,
+    exceptfds: *mut usize,
+    timeout: Option<PosixTimeSpec>,
+) -> (Result<usize, SystemError>, Option<PosixTimeSpec>) {
+    if nfds < 0 {
+        return (Err(SystemError::EINVAL), None);
+    }
+    let nfds = (nfds as usize).min(FileDescriptorVec::PROCESS_MAX_FD);
+
+    let deadline = timeout.map(|timeout| Instant::now() + Duration::from(timeout));
+
+    let result = (|| {
+        let mut sets = [
+            FdSet::from_user(readfds, nfds)?,
+            FdSet::from_user(writefds, nfds)?,
+            FdSet::from_user(exceptfds, nfds)?,
+        ];
+        let count = do_select(nfds, &mut sets, deadline)?;
+        for set in sets.iter() {
+            set.copy_to_user()?;
+        }
+        Ok(count)
+    })();
+
+    let remaining = deadline.map(|deadline| {
+        let now = Instant::now();
+        if now >= deadline {
+            PosixTimeSpec::new(0, 0)
+        } else {
+            PosixTimeSpec::from(deadline - now)
+        }
+    });
+    return (result, remaining);
+}
+
+/// 检查用户传入的超时时间是否合法
+fn check_timeout(timeout: &PosixTimeSpec) -> Result<(), SystemError> {
+    if timeout.tv_sec < 0 || !(0..1000000000).contains(&timeout.tv_nsec) {
+        return Err(SystemError::EINVAL);
+    }
+    return Ok(());
+}
+
+/// pselect6的第六个参数
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct PselectSigmask {
+    pub sigmask: *const SigSet,
+    pub sigsetsize: usize,
+}
+
+impl Syscall {
+    /// # 等待一组文件描述符就绪
+    ///
+    /// ## 参数
+    /// - `nfds`: 最大的文件描述符加1
+    /// - `readfds`/`writefds`/`exceptfds`: 分别监听可读、可写与异常，返回时只保留就绪的文件描述符
+    /// - `timeout`: 为NULL时一直等待。返回时被更新为剩余的时间
+    ///
+    /// See: https://man7.org/linux/man-pages/man2/select.2.html
+    pub fn select(
+        nfds: i32,
+        readfds: *mut usize,
+        writefds: *mut usize,
+        exceptfds: *mut usize,
+        timeout: *mut PosixTimeval,
+    ) -> Result<usize, SystemError> {
+        let timespec = if timeout.is_null() {
+            None
+        } else {
+            let reader = UserBufferReader::new(timeout, size_of::<PosixTimeval>(), true)?;
+            let timeval = *reader.read_one_from_user::<PosixTimeval>(0)?;
+            // 与Linux一致，tv_usec中超过一秒的部分计入tv_sec
+            let timespec = PosixTimeSpec::new(
+                timeval
+                    .tv_sec
+                    .saturating_add((timeval.tv_usec / 1000000) as i64),
+                (timeval.tv_usec % 1000000) as i64 * 1000,
+            );
+            check_timeout(&timespec)?;
+            Some(timespec)
+        };
+
+        let (result, remaining) = core_sys_select(nfds, readfds, writefds, exceptfds, timespec);
+        if let Some(remaining) = remaining {
+            let timeval = PosixTimeval {
+                tv_sec: remaining.tv_sec,
+                tv_usec: (remaining.tv_nsec / 1000) as _,
+            };
+            // 与Linux一致，无法写回剩余时间时不报告错误
+            if let Ok(mut writer) = UserBufferWriter::new(timeout, size_of::<PosixTimeval>(), true)
+            {
+                let _ = writer.copy_one_to_user(&timeval, 0);
+            }
+        }
+        return result;
+    }
+
+    /// # 在等待期间使用指定的屏蔽信号的select
+    ///
+    /// ## 参数
+    /// - `timeout`: 以timespec表示的超时时间
+    /// - `sig`: 指向`{ const sigset_t *ss; size_t ss_len; }`，为NULL或者ss为NULL时不改变屏蔽信号
+    ///
+    /// See: https://man7.org/linux/man-pages/man2/pselect6.2.html
+    pub fn pselect6(
+        nfds: i32,
+        readfds: *mut usize,
+        writefds: *mut usize,
+        exceptfds: *mut usize,
+        timeout: *mut PosixTimeSpec,
+        sig: *const PselectSigmask,
+    ) -> Result<usize, SystemError> {
+        let timespec = if timeout.is_null() {
+            None
+        } else {
+            let reader = UserBufferReader::new(timeout, size_of::<PosixTimeSpec>(), true)?;
+            let timespec = *reader.read_one_from_user::<PosixTimeSpec>(0)?;
+            check_timeout(&timespec)?;
+            Some(timespec)
+        };
+
+        let mut sigmask = None;
+        if !sig.is_null() {
+            let reader = UserBufferReader::new(sig, size_of::<PselectSigmask>(), true)?;
+            let sig = *reader.read_one_from_user::<PselectSigmask>(0)?;
+            if !sig.sigmask.is_null() {
+                if sig.sigsetsize != size_of::<SigSet>() {
+                    return Err(SystemError::EINVAL);
+                }
+                let reader = UserBufferReader::new(sig.sigmask, size_of::<SigSet>(), true)?;
+                sigmask = Some(*reader.read_one_from_user::<SigSet>(0)?);
+            }
+        }
+
+        if let Some(mut sigmask) = sigmask {
+            set_user_sigmask(&mut sigmask);
+        }
+        let (result, remaining) = core_sys_select(nfds, readfds, writefds, exceptfds, timespec);
+        if sigmask.is_some() {
+            restore_saved_sigmask_unless(result == Err(SystemError::EINTR));
+        }
+
+        if let Some(remaining) = remaining {
+            if let Ok(mut writer) = UserBufferWriter::new(timeout, size_of::<PosixTimeSpec>(), true)
+            {
+                let _ = writer.copy_one_to_user(&remaining, 0);
+            }
+        }
+        return result;
+    }
+}
@@ -0,0 +1,905 @@
+//! tmpfs：数据保存在页缓存中的内存文件系统
+//!
+//! 与ramfs不同，tmpfs的文件内容直接保存在文件的[`PageCache`]中，mmap时映射的就是这些页面。
+//! 文件系统的容量（size=）与inode数量（nr_inodes=）都有上限，超出时返回ENOSPC，
+//! 默认上限为物理内存的一半，避免写满tmpfs时耗尽内存。
+//!
+//! 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/mm/shmem.c
+
+use core::any::Any;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use alloc::{
+    collections::BTreeMap,
+    string::{String, ToString},
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+use linkme::distributed_slice;
+use mount_options::tmpfs::TmpfsOptions;
+use system_error::SystemError;
+
+use crate::{
+    arch::{mm::LockedFrameAllocator, MMArch},
+    driver::base::device::device_number::DeviceNumber,
+    ipc::pipe::LockedPipeInode,
+    libs::{
+        casting::DowncastArc,
+        spinlock::{SpinLock, SpinLockGuard},
+    },
+    mm::{
+        allocator::page_frame::{
+            deallocate_page_frames, FrameAllocator, PageFrameCount, PhysPageFrame,
+        },
+        fault::{PageFaultHandler, PageFaultMessage},
+        page::{page_manager_lock_irqsave, Page, PageFlags},
+        MemoryManagementArch, VmFaultReason,
+    },
+    process::ProcessManager,
+    time::PosixTimeSpec,
+};
+
+use super::vfs::{
+    file::{FileMode, FilePrivateData, PageCache},
+    syscall::ModeType,
+    utils::DName,
+    xattr::{SimpleXattrs, XattrFlags},
+    FileSystem, FileSystemMaker, FileSystemMakerData, FileType, FsInfo, IndexNode, InodeId, Magic,
+    Metadata, SpecialNodeData, SuperBlock, FSMAKER,
+};
+
+/// tmpfs的inode名称的最大长度
+const TMPFS_MAX_NAMELEN: usize = 255;
+
+/// 按照物理内存的大小解析挂载选项
+fn parse_options(data: &FileSystemMakerData) -> Result<TmpfsOptions, SystemError> {
+    let total_pages = unsafe { LockedFrameAllocator.usage() }.total().data();
+    return TmpfsOptions::parse(data.options(), total_pages, MMArch::PAGE_SIZE);
+}
+
+/// 在不超过上限的前提下增加计数
+fn reserve(counter: &AtomicUsize, limit: Option<usize>, count: usize) -> Result<(), SystemError> {
+    counter
+        .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |used| {
+            let new = used.checked_add(count)?;
+            match limit {
+                Some(limit) if new > limit => None,
+                _ => Some(new),
+            }
+        })
+        .map(|_| ())
+        .map_err(|_| SystemError::ENOSPC)
+}
+
+/// tmpfs文件系统
+#[derive(Debug)]
+pub struct TmpFS {
+    /// 根目录
+    root_inode: Arc<LockedTmpfsInode>,
+    options: TmpfsOptions,
+    /// 已经使用的页面数
+    used_blocks: AtomicUsize,
+    /// 已经创建的inode数
+    used_inodes: AtomicUsize,
+}
+
+impl TmpFS {
+    pub fn new(data: &FileSystemMakerData) -> Result<Arc<Self>, SystemError> {
+        let options = parse_options(data)?;
+        let root = TmpfsInode::new(
+            Weak::default(),
+            DName::default(),
+            FileType::Dir,
+            ModeType::from_bits_truncate(options.mode),
+            DeviceNumber::default(),
+        );
+
+        let result = Arc::new(TmpFS {
+            root_inode: Arc::new(LockedTmpfsInode(SpinLock::new(root))),
+            options,
+            used_blocks: AtomicUsize::new(0),
+            used_inodes: AtomicUsize::new(0),
+        });
+        reserve(&result.used_inodes, options.max_inodes, 1)?;
+
+        let mut root_guard = result.root_inode.0.lock();
+        root_guard.parent = Arc::downgrade(&result.root_inode);
+        root_guard.self_ref = Arc::downgrade(&result.root_inode);
+        root_guard.fs = Arc::downgrade(&result);
+        drop(root_guard);
+
+        return Ok(result);
+    }
+
+    pub fn make_tmpfs(
+        data: &FileSystemMakerData,
+    ) -> Result<Arc<dyn FileSystem + 'static>, SystemError> {
+        let fs = TmpFS::new(data)?;
+        return Ok(fs);
+    }
+
+    /// 分配一个清零的页面，并计入文件系统的容量
+    ///
+    /// 页面同时计费到当前进程所在的cgroup，在页面被释放时撤销
+    fn alloc_page(&self) -> Result<Arc<Page>, SystemError> {
+        reserve(&self.used_blocks, self.options.max_blocks, 1)?;
+        let memcg = ProcessManager::current_pcb().cgroup();
+        if let Err(e) = memcg.try_charge_memory(1) {
+            self.used_blocks.fetch_sub(1, Ordering::SeqCst);
+            return Err(e);
+        }
+        let paddr = match unsafe { LockedFrameAllocator.allocate_one() } {
+            Some(paddr) => paddr,
+            None => {
+                memcg.uncharge_memory(1);
+                self.used_blocks.fetch_sub(1, Ordering::SeqCst);
+                return Err(SystemError::ENOMEM);
+            }
+        };
+        unsafe {
+            core::ptr::write_bytes(
+                MMArch::phys_2_virt(paddr).unwrap().data() as *mut u8,
+                0,
+                MMArch::PAGE_SIZE,
+            );
+        }
+
+        let page = Arc::new(Page::new(true, paddr));
+        let mut page_guard = page.write_irqsave();
+        page_guard.add_flags(PageFlags::PG_UPTODATE | PageFlags::PG_SWAPBACKED);
+        page_guard.set_memcg(Some(memcg));
+        drop(page_guard);
+        page_manager_lock_irqsave().insert(paddr, &page);
+        return Ok(page);
+    }
+}
+
+#[distributed_slice(FSMAKER)]
+static TMPFSMAKER: FileSystemMaker = FileSystemMaker::new(
+    "tmpfs",
+    &(TmpFS::make_tmpfs
+        as fn(&FileSystemMakerData) -> Result<Arc<dyn FileSystem + 'static>, SystemError>),
+);
+
+impl FileSystem for TmpFS {
+    fn root_inode(&self) -> Arc<dyn IndexNode> {
+        return self.root_inode.clone();
+    }
+
+    fn info(&self) -> FsInfo {
+        return FsInfo {
+            blk_dev_id: 0,
+            max_name_len: TMPFS_MAX_NAMELEN,
+        };
+    }
+
+    fn as_any_ref(&self) -> &dyn Any {
+        self
+    }
+
+    fn name(&self) -> &str {
+        "tmpfs"
+    }
+
+    fn super_block(&self) -> SuperBlock {
+        let mut super_block = SuperBlock::new(
+            Magic::TMPFS_MAGIC,
+            MMArch::PAGE_SIZE as u64,
+            TMPFS_MAX_NAMELEN as u64,
+        );
+        super_block.frsize = MMArch::PAGE_SIZE as u64;
+        // 没有上限时，与Linux一致，容量与inode数都报告为0
+        if let Some(max_blocks) = self.options.max_blocks {
+            let free = max_blocks.saturating_sub(self.used_blocks.load(Ordering::SeqCst));
+            super_block.blocks = max_blocks as u64;
+            super_block.bfree = free as u64;
+            super_block.bavail = free as u64;
+        }
+        if let Some(max_inodes) = self.options.max_inodes {
+            super_block.files = max_inodes as u64;
+            super_block.ffree =
+                max_inodes.saturating_sub(self.used_inodes.load(Ordering::SeqCst)) as u64;
+        }
+        return super_block;
+    }
+
+    unsafe fn fault(&self, pfm: &mut PageFaultMessage) -> VmFaultReason {
+        // 文件中的空洞在被映射时才分配页面，此后就与普通的页缓存一样处理
+        let vma = pfm.vma();
+        let file = vma.lock_irqsave().vm_file().expect("no vm_file in vma");
+        let inode = file
+            .inode()
+            .page_cache()
+            .and_then(|page_cache| page_cache.inode())
+            .and_then(|inode| inode.upgrade())
+            .and_then(|inode| inode.downcast_arc::<LockedTmpfsInode>())
+            .expect("tmpfs fault on a non-tmpfs file");
+        let file_pgoff = pfm.file_pgoff().expect("no file_pgoff");
+
+        let mut guard = inode.0.lock();
+        // 访问超出文件末尾的页面
+        if file_pgoff >= guard.size.div_ceil(MMArch::PAGE_SIZE) {
+            return VmFaultReason::VM_FAULT_SIGBUS;
+        }
+        match guard.get_or_alloc_page(file_pgoff) {
+            Ok(_) => {}
+            Err(SystemError::ENOMEM) => return VmFaultReason::VM_FAULT_OOM,
+            Err(_) => return VmFaultReason::VM_FAULT_SIGBUS,
+        }
+        drop(guard);
+
+        PageFaultHandler::filemap_fault(pfm)
+    }
+
+    unsafe fn map_pages(
+        &self,
+        pfm: &mut PageFaultMessage,
+        start_pgoff: usize,
+        end_pgoff: usize,
+    ) -> VmFaultReason {
+        PageFaultHandler::filemap_map_pages(pfm, start_pgoff, end_pgoff)
+    }
+}
+
+/// tmpfs的inode
+#[derive(Debug)]
+struct LockedTmpfsInode(SpinLock<TmpfsInode>);
+
+/// tmpfs的inode(不包含锁)
+#[derive(Debug)]
+pub struct TmpfsInode {
+    /// 指向父Inode的弱引用
+    parent: Weak<LockedTmpfsInode>,
+    /// 指向自身的弱引用
+    self_ref: Weak<LockedTmpfsInode>,
+    /// 子Inode的B树
+    children: BTreeMap<DName, Arc<LockedTmpfsInode>>,
+    /// 普通文件与符号链接的数据页
+    page_cache: Option<Arc<PageCache>>,
+    /// 文件的大小
+    size: usize,
+    /// 已经分配的数据页数
+    nr_pages: usize,
+    /// 当前inode的元数据
+    metadata: Metadata,
+    /// 指向inode所在的文件系统对象的指针
+    fs: Weak<TmpFS>,
+    /// 指向特殊节点
+    special_node: Option<SpecialNodeData>,
+
+    name: DName,
+    /// 扩展属性
+    xattrs: SimpleXattrs,
+}
+
+impl TmpfsInode {
+    fn new(
+        fs: Weak<TmpFS>,
+        name: DName,
+        file_type: FileType,
+        mode: ModeType,
+        raw_dev: DeviceNumber,
+    ) -> Self {
+        let cred = ProcessManager::current_pcb().cred();
+        let now = PosixTimeSpec::now();
+        let mut metadata = Metadata::new(file_type, mode);
+        metadata.blk_size = MMArch::PAGE_SIZE;
+        metadata.atime = now;
+        metadata.mtime = now;
+        metadata.ctime = now;
+        metadata.uid = cred.fsuid.data();
+        metadata.gid = cred.fsgid.data();
+        metadata.raw_dev = raw_dev;
+        if file_type == FileType::Dir {
+            metadata.nlinks = 2;
+        }
+
+        return Self {
+            parent: Weak::default(),
+            self_ref: Weak::default(),
+            children: BTreeMap::new(),
+            page_cache: None,
+            size: 0,
+            nr_pages: 0,
+            metadata,
+            fs,
+            special_node: None,
+            name,
+            xattrs: SimpleXattrs::new(),
+        };
+    }
+
+    /// 获取文件的第index个页面，如果是空洞，则分配一个新的页面
+    fn get_or_alloc_page(&mut self, index: usize) -> Result<Arc<Page>, SystemError> {
+        let page_cache = self.page_cache.clone().ok_or(SystemError::EINVAL)?;
+        if let Some(page) = page_cache.get_page(index) {
+            return Ok(page);
+        }
+
+        let fs = self.fs.upgrade().ok_or(SystemError::EIO)?;
+        let page = fs.alloc_page()?;
+        page.write_irqsave()
+            .set_page_cache_index(Some(page_cache.clone()), Some(index));
+        page_cache.add_page(index, &page);
+        self.nr_pages += 1;
+        return Ok(page);
+    }
+
+    /// 释放文件中从第start个页面开始的所有页面
+    fn free_pages_from(&mut self, start: usize) {
+        let Some(page_cache) = self.page_cache.clone() else {
+            return;
+        };
+        let fs = self.fs.upgrade();
+        for index in start..self.size.div_ceil(MMArch::PAGE_SIZE) {
+            let Some(page) = page_cache.get_page(index) else {
+                continue;
+            };
+            page_cache.remove_page(index);
+            free_page(page);
+            self.nr_pages -= 1;
+            if let Some(fs) = &fs {
+                fs.used_blocks.fetch_sub(1, Ordering::SeqCst);
+            }
+        }
+    }
+
+    /// 把[start, end)范围内已经分配的页面清零
+    fn zero_range(&self, start: usize, end: usize) {
+        let Some(page_cache) = &self.page_cache else {
+            return;
+        };
+        let mut offset = start;
+        while offset < end {
+            let page_offset = offset % MMArch::PAGE_SIZE;
+            let len = (MMArch::PAGE_SIZE - page_offset).min(end - offset);
+            if let Some(page) = page_cache.get_page(offset / MMArch::PAGE_SIZE) {
+                unsafe { page_bytes(&page)[page_offset..page_offset + len].fill(0) };
+            }
+            offset += len;
+        }
+    }
+
+    /// 修改文件的大小，缩小时释放多出来的页面
+    fn set_size(&mut self, len: usize) -> Result<(), SystemError> {
+        match self.metadata.file_type {
+            FileType::Dir => return Err(SystemError::EISDIR),
+            FileType::File | FileType::SymLink => {}
+            _ => return Err(SystemError::EINVAL),
+        }
+
+        if len < self.size {
+            // 最后一个页面中超出文件末尾的部分需要清零，以免再次扩大文件时读到旧的数据
+            let page_end = len.div_ceil(MMArch::PAGE_SIZE) * MMArch::PAGE_SIZE;
+            self.zero_range(len, page_end.min(self.size));
+            self.free_pages_from(len.div_ceil(MMArch::PAGE_SIZE));
+        } else {
+            // 通过mmap写入的数据可能越过了文件末尾
+            let page_end = self.size.div_ceil(MMArch::PAGE_SIZE) * MMArch::PAGE_SIZE;
+            self.zero_range(self.size, page_end.min(len));
+        }
+        self.size = len;
+        let now = PosixTimeSpec::now();
+        self.metadata.mtime = now;
+        self.metadata.ctime = now;
+        return Ok(());
+    }
+}
+
+impl Drop for TmpfsInode {
+    fn drop(&mut self) {
+        // 最后一个引用消失时（已经没有目录项指向它，也没有被打开），释放所有的数据页
+        self.free_pages_from(0);
+        if let Some(fs) = self.fs.upgrade() {
+            fs.used_inodes.fetch_sub(1, Ordering::SeqCst);
+        }
+    }
+}
+
+/// 获取页面对应的内存
+///
+/// ## Safety
+///
+/// 调用者需要持有页面所属inode的锁
+unsafe fn page_bytes<'a>(page: &Arc<Page>) -> &'a mut [u8] {
+    let vaddr = MMArch::phys_2_virt(page.read_irqsave().phys_address()).unwrap();
+    core::slice::from_raw_parts_mut(vaddr.data() as *mut u8, MMArch::PAGE_SIZE)
+}
+
+/// 释放一个不再属于文件的页面。如果页面仍然被映射，则等到最后一个映射被解除时再释放
+fn free_page(page: Arc<Page>) {
+    let mut page_manager_guard = page_manager_lock_irqsave();
+    let paddr = page.read_irqsave().phys_address();
+    if page.read_irqsave().map_count() > 0 {
+        page.write_irqsave().set_dealloc_when_zero(true);
+        return;
+    }
+    drop(page);
+    unsafe {
+        deallocate_page_frames(
+            PhysPageFrame::new(paddr),
+            PageFrameCount::new(1),
+            &mut page_manager_guard,
+        );
+    }
+}
+
+impl LockedTmpfsInode {
+    /// 在当前目录下创建一个新的inode
+    fn do_create(
+        &self,
+        name: &str,
+        file_type: FileType,
+        mode: ModeType,
+        raw_dev: DeviceNumber,
+    ) -> Result<Arc<LockedTmpfsInode>, SystemError> {
+        if name.len() > TMPFS_MAX_NAMELEN {
+            return Err(SystemError::ENAMETOOLONG);
+        }
+        let name = DName::from(name);
+        let mut inode = self.0.lock();
+        if inode.metadata.file_type != FileType::Dir {
+            return Err(SystemError::ENOTDIR);
+        }
+        if inode.children.contains_key(&name) {
+            return Err(SystemError::EEXIST);
+        }
+
+        let fs = inode.fs.upgrade().ok_or(SystemError::EIO)?;
+        reserve(&fs.used_inodes, fs.options.max_inodes, 1)?;
+
+        let mut new_inode =
+            TmpfsInode::new(inode.fs.clone(), name.clone(), file_type, mode, raw_dev);
+        new_inode.parent = inode.self_ref.clone();
+        let result = Arc::new(LockedTmpfsInode(SpinLock::new(new_inode)));
+
+        let mut guard = result.0.lock();
+        guard.self_ref = Arc::downgrade(&result);
+        match file_type {
+            FileType::File | FileType::SymLink => {
+                guard.page_cache = Some(PageCache::new(Some(
+                    Arc::downgrade(&result) as Weak<dyn IndexNode>
+                )));
+            }
+            FileType::Pipe => {
+                guard.special_node = Some(SpecialNodeData::Pipe(LockedPipeInode::new()));
+            }
+            FileType::Dir => inode.metadata.nlinks += 1,
+            _ => {}
+        }
+        drop(guard);
+
+        let now = PosixTimeSpec::now();
+        inode.metadata.mtime = now;
+        inode.metadata.ctime = now;
+        inode.children.insert(name, result.clone());
+        return Ok(result);
+    }
+}
+
+impl IndexNode for LockedTmpfsInode {
+    fn truncate(&self, len: usize) -> Result<(), SystemError> {
+        let mut inode = self.0.lock();
+        if inode.metadata.file_type == FileType::Dir {
+            return Err(SystemError::EINVAL);
+        }
+        if inode.size > len {
+            inode.set_size(len)?;
+        }
+        return Ok(());
+    }
+
+    fn close(&self, _data: SpinLockGuard<FilePrivateData>) -> Result<(), SystemError> {
+        return Ok(());
+    }
+
+    fn open(
+        &self,
+        _data: SpinLockGuard<FilePrivateData>,
+        _mode: &FileMode,
+    ) -> Result<(), SystemError> {
+        return Ok(());
+    }
+
+    fn read_at(
+        &self,
+        offset: usize,
+        len: usize,
+        buf: &mut [u8],
+        _data: SpinLockGuard<FilePrivateData>,
+    ) -> Result<usize, SystemError> {
+        if buf.len() < len {
+            return Err(SystemError::EINVAL);
+        }
+        let inode = self.0.lock();
+        if inode.metadata.file_type == FileType::Dir {
+            return Err(SystemError::EISDIR);
+        }
+        let page_cache = inode.page_cache.as_ref().ok_or(SystemError::EINVAL)?;
+
+        let start = inode.size.min(offset);
+        let end = inode.size.min(offset.saturating_add(len));
+        let mut pos = start;
+        while pos < end {
+            let page_offset = pos % MMArch::PAGE_SIZE;
+            let chunk = (MMArch::PAGE_SIZE - page_offset).min(end - pos);
+            let dst = &mut buf[pos - start..pos - start + chunk];
+            match page_cache.get_page(pos / MMArch::PAGE_SIZE) {
+                Some(page) => dst.copy_from_slice(unsafe {
+                    &page_bytes(&page)[page_offset..page_offset + chunk]
+                }),
+                // 空洞读出来是0
+                None => dst.fill(0),
+            }
+            pos += chunk;
+        }
+        return Ok(end - start);
+    }
+
+    fn write_at(
+        &self,
+        offset: usize,
+        len: usize,
+        buf: &[u8],
+        _data: SpinLockGuard<FilePrivateData>,
+    ) -> Result<usize, SystemError> {
+        if buf.len() < len {
+            return Err(SystemError::EINVAL);
+        }
+        let end = offset.checked_add(len).ok_or(SystemError::EFBIG)?;
+        let mut inode = self.0.lock();
+        if inode.metadata.file_type == FileType::Dir {
+            return Err(SystemError::EISDIR);
+        }
+        if inode.page_cache.is_none() {
+            return Err(SystemError::EINVAL);
+        }
+
+        // 扩大文件之前，先清零最后一个页面中超出文件末尾的部分
+        if offset > inode.size {
+            let page_end = inode.size.div_ceil(MMArch::PAGE_SIZE) * MMArch::PAGE_SIZE;
+            inode.zero_range(inode.size, page_end.min(offset));
+        }
+
+        let mut pos = offset;
+        while pos < end {
+            let page_offset = pos % MMArch::PAGE_SIZE;
+            let chunk = (MMArch::PAGE_SIZE - page_offset).min(end - pos);
+            let page = match inode.get_or_alloc_page(pos / MMArch::PAGE_SIZE) {
+                Ok(page) => page,
+                // 已经写入了一部分数据时，返回已写入的长度
+                Err(e) if pos == offset => return Err(e),
+                Err(_) => break,
+            };
+            unsafe {
+                page_bytes(&page)[page_offset..page_offset + chunk]
+                    .copy_from_slice(&buf[pos - offset..pos - offset + chunk]);
+            }
+            pos += chunk;
+        }
+
+        inode.size = inode.size.max(pos);
+        let now = PosixTimeSpec::now();
+        inode.metadata.mtime = now;
+        inode.metadata.ctime = now;
+        return Ok(pos - offset);
+    }
+
+    fn fs(&self) -> Arc<dyn FileSystem> {
+        return self.0.lock().fs.upgrade().unwrap();
+    }
+
+    fn as_any_ref(&self) -> &dyn Any {
+        self
+    }
+
+    fn metadata(&self) -> Result<Metadata, SystemError> {
+        let inode = self.0.lock();
+        let mut metadata = inode.metadata.clone();
+        metadata.size = inode.size as i64;
+        metadata.blocks = inode.nr_pages * (MMArch::PAGE_SIZE / 512);
+        return Ok(metadata);
+    }
+
+    fn set_metadata(&self, metadata: &Metadata) -> Result<(), SystemError> {
+        let mut inode = self.0.lock();
+        inode.metadata.atime = metadata.atime;
+        inode.metadata.mtime = metadata.mtime;
+        inode.metadata.ctime = metadata.ctime;
+        inode.metadata.mode = metadata.mode;
+        inode.metadata.uid = metadata.uid;
+        inode.metadata.gid = metadata.gid;
+        return Ok(());
+    }
+
+    fn resize(&self, len: usize) -> Result<(), SystemError> {
+        self.0.lock().set_size(len)
+    }
+
+    fn create_with_data(
+        &self,
+        name: &str,
+        file_type: FileType,
+        mode: ModeType,
+        data: usize,
+    ) -> Result<Arc<dyn IndexNode>, SystemError> {
+        return Ok(self.do_create(name, file_type, mode, DeviceNumber::from(data as u32))?);
+    }
+
+    fn link(&self, name: &str, other: &Arc<dyn IndexNode>) -> Result<(), SystemError> {
+        let other: &LockedTmpfsInode = other
+            .downcast_ref::<LockedTmpfsInode>()
+            .ok_or(SystemError::EXDEV)?;
+        let name = DName::from(name);
+        let mut inode = self.0.lock();
+        let mut other_locked = other.0.lock();
+
+        if inode.metadata.file_type != FileType::Dir {
+            return Err(SystemError::ENOTDIR);
+        }
+        if other_locked.metadata.file_type == FileType::Dir {
+            return Err(SystemError::EPERM);
+        }
+        if inode.children.contains_key(&name) {
+            return Err(SystemError::EEXIST);
+        }
+
+        inode
+            .children
+            .insert(name, other_locked.self_ref.upgrade().unwrap());
+        other_locked.metadata.nlinks += 1;
+        other_locked.metadata.ctime = PosixTimeSpec::now();
+        return Ok(());
+    }
+
+    fn unlink(&self, name: &str) -> Result<(), SystemError> {
+        let mut inode = self.0.lock();
+        if inode.metadata.file_type != FileType::Dir {
+            return Err(SystemError::ENOTDIR);
+        }
+        if name == "." || name == ".." {
+            return Err(SystemError::ENOTEMPTY);
+        }
+
+        let name = DName::from(name);
+        let to_delete = inode.children.get(&name).ok_or(SystemError::ENOENT)?;
+        let mut to_delete_guard = to_delete.0.lock();
+        if to_delete_guard.metadata.file_type == FileType::Dir {
+            return Err(SystemError::EISDIR);
+        }
+        to_delete_guard.metadata.nlinks -= 1;
+        to_delete_guard.metadata.ctime = PosixTimeSpec::now();
+        drop(to_delete_guard);
+
+        // 数据页在最后一个引用消失时随inode一起释放
+        inode.children.remove(&name);
+        return Ok(());
+    }
+
+    fn rmdir(&self, name: &str) -> Result<(), SystemError> {
+        let name = DName::from(name);
+        let mut inode = self.0.lock();
+        if inode.metadata.file_type != FileType::Dir {
+            return Err(SystemError::ENOTDIR);
+        }
+        let to_delete = inode.children.get(&name).ok_or(SystemError::ENOENT)?;
+        let mut to_delete_guard = to_delete.0.lock();
+        if to_delete_guard.metadata.file_type != FileType::Dir {
+            return Err(SystemError::ENOTDIR);
+        }
+        if !to_delete_guard.children.is_empty() {
+            return Err(SystemError::ENOTEMPTY);
+        }
+        to_delete_guard.metadata.nlinks = 0;
+        drop(to_delete_guard);
+
+        inode.metadata.nlinks -= 1;
+        inode.children.remove(&name);
+        return Ok(());
+    }
+
+    fn move_to(
+        &self,
+        old_name: &str,
+        target: &Arc<dyn IndexNode>,
+        new_name: &str,
+    ) -> Result<(), SystemError> {
+        let inode_to_move = self
+            .find(old_name)?
+            .downcast_arc::<LockedTmpfsInode>()
+            .ok_or(SystemError::EINVAL)?;
+        let target = target
+            .clone()
+            .downcast_arc::<LockedTmpfsInode>()
+            .ok_or(SystemError::EXDEV)?;
+        if new_name.len() > TMPFS_MAX_NAMELEN {
+            return Err(SystemError::ENAMETOOLONG);
+        }
+        let is_dir = inode_to_move.0.lock().metadata.file_type == FileType::Dir;
+        let new_name = DName::from(new_name);
+
+        // 目标位置已经存在同名的文件时，将其替换
+        let mut target_guard = target.0.lock();
+        if target_guard.metadata.file_type != FileType::Dir {
+            return Err(SystemError::ENOTDIR);
+        }
+        if let Some(victim) = target_guard.children.get(&new_name) {
+            if Arc::ptr_eq(victim, &inode_to_move) {
+                return Ok(());
+            }
+            let mut victim_guard = victim.0.lock();
+            match (is_dir, victim_guard.metadata.file_type == FileType::Dir) {
+                (true, false) => return Err(SystemError::ENOTDIR),
+                (false, true) => return Err(SystemError::EISDIR),
+                (true, true) if !victim_guard.children.is_empty() => {
+                    return Err(SystemError::ENOTEMPTY)
+                }
+                (true, true) => victim_guard.metadata.nlinks = 0,
+                (false, false) => victim_guard.metadata.nlinks -= 1,
+            }
+            drop(victim_guard);
+            if is_dir {
+                target_guard.metadata.nlinks -= 1;
+            }
+            target_guard.children.remove(&new_name);
+        }
+        target_guard
+            .children
+            .insert(new_name.clone(), inode_to_move.clone());
+        if is_dir {
+            target_guard.metadata.nlinks += 1;
+        }
+        let now = PosixTimeSpec::now();
+        target_guard.metadata.mtime = now;
+        target_guard.metadata.ctime = now;
+        drop(target_guard);
+
+        let mut moved_guard = inode_to_move.0.lock();
+        moved_guard.name = new_name;
+        moved_guard.parent = Arc::downgrade(&target);
+        moved_guard.metadata.ctime = now;
+        drop(moved_guard);
+
+        // 从原来的目录中删除
+        let mut self_guard = self.0.lock();
+        let old_name = DName::from(old_name);
+        if self_guard
+            .children
+            .get(&old_name)
+            .is_some_and(|inode| Arc::ptr_eq(inode, &inode_to_move))
+        {
+            self_guard.children.remove(&old_name);
+            if is_dir {
+                self_guard.metadata.nlinks -= 1;
+            }
+        }
+        self_guard.metadata.mtime = now;
+        self_guard.metadata.ctime = now;
+        return Ok(());
+    }
+
+    fn find(&self, name: &str) -> Result<Arc<dyn IndexNode>, SystemError> {
+        let inode = self.0.lock();
+        if inode.metadata.file_type != FileType::Dir {
+            return Err(SystemError::ENOTDIR);
+        }
+
+        match name {
+            "" | "." => {
+                return Ok(inode.self_ref.upgrade().ok_or(SystemError::ENOENT)?);
+            }
+            ".." => {
+                return Ok(inode.parent.upgrade().ok_or(SystemError::ENOENT)?);
+            }
+            name => {
+                let name = DName::from(name);
+                return Ok(inode
+                    .children
+                    .get(&name)
+                    .ok_or(SystemError::ENOENT)?
+                    .clone());
+            }
+        }
+    }
+
+    fn get_entry_name(&self, ino: InodeId) -> Result<String, SystemError> {
+        let inode = self.0.lock();
+        if inode.metadata.file_type != FileType::Dir {
+            return Err(SystemError::ENOTDIR);
+        }
+
+        match ino.into() {
+            0 => {
+                return Ok(String::from("."));
+            }
+            1 => {
+                return Ok(String::from(".."));
+            }
+            ino => {
+                return inode
+                    .children
+                    .iter()
+                    .find(|(_, v)| v.0.lock().metadata.inode_id.into() == ino)
+                    .map(|(k, _)| k.to_string())
+                    .ok_or(SystemError::ENOENT);
+            }
+        }
+    }
+
+    fn list(&self) -> Result<Vec<String>, SystemError> {
+        let inode = self.0.lock();
+        if inode.metadata.file_type != FileType::Dir {
+            return Err(SystemError::ENOTDIR);
+        }
+
+        let mut keys: Vec<String> = Vec::new();
+        keys.push(String::from("."));
+        keys.push(String::from(".."));
+        keys.extend(inode.children.keys().map(|k| k.to_string()));
+        return Ok(keys);
+    }
+
+    fn mknod(
+        &self,
+        filename: &str,
+        mode: ModeType,
+        dev_t: DeviceNumber,
+    ) -> Result<Arc<dyn IndexNode>, SystemError> {
+        let file_type = match mode.bits() & ModeType::S_IFMT.bits() {
+            0 => FileType::File,
+            bits if bits == ModeType::S_IFREG.bits() => FileType::File,
+            bits if bits == ModeType::S_IFIFO.bits() => FileType::Pipe,
+            bits if bits == ModeType::S_IFCHR.bits() => FileType::CharDevice,
+            bits if bits == ModeType::S_IFBLK.bits() => FileType::BlockDevice,
+            bits if bits == ModeType::S_IFSOCK.bits() => FileType::Socket,
+            _ => return Err(SystemError::EINVAL),
+        };
+        if file_type == FileType::File {
+            return self.create(filename, file_type, mode);
+        }
+        return Ok(self.do_create(filename, file_type, mode, dev_t)?);
+    }
+
+    fn special_node(&self) -> Option<SpecialNodeData> {
+        return self.0.lock().special_node.clone();
+    }
+
+    fn page_cache(&self) -> Option<Arc<PageCache>> {
+        self.0.lock().page_cache.clone()
+    }
+
+    fn getxattr(&self, name: &str) -> Result<Vec<u8>, SystemError> {
+        self.0.lock().xattrs.get(name)
+    }
+
+    fn setxattr(&self, name: &str, value: &[u8], flags: XattrFlags) -> Result<(), SystemError> {
+        let mut inode = self.0.lock();
+        inode.xattrs.set(name, value, flags)?;
+        inode.metadata.ctime = PosixTimeSpec::now();
+        return Ok(());
+    }
+
+    fn listxattr(&self) -> Result<Vec<String>, SystemError> {
+        Ok(self.0.lock().xattrs.list())
+    }
+
+    fn removexattr(&self, name: &str) -> Result<(), SystemError> {
+        let mut inode = self.0.lock();
+        inode.xattrs.remove(name)?;
+        inode.metadata.ctime = PosixTimeSpec::now();
+        return Ok(());
+    }
+
+    fn dname(&self) -> Result<DName, SystemError> {
+        Ok(self.0.lock().name.clone())
+    }
+
+    fn parent(&self) -> Result<Arc<dyn IndexNode>, SystemError> {
+        self.0
+            .lock()
+            .parent
+            .upgrade()
+            .map(|item| item as Arc<dyn IndexNode>)
+            .ok_or(SystemError::EINVAL)
+    }
+}
@@ -0,0 +1,452 @@
+//! 9P2000.L客户端
+//!
+//! 客户端与具体的传输方式无关，每个请求都同步地等待服务端回复。
+//!
+//! 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/net/9p/client.c
+
+use core::{
+    fmt::Debug,
+    sync::atomic::{AtomicU32, Ordering},
+};
+
+use alloc::{string::String, sync::Arc, vec::Vec};
+use log::warn;
+use system_error::SystemError;
+
+use crate::libs::{mutex::Mutex, spinlock::SpinLock};
+
+use super::protocol::{
+    msg, P9Attr, P9Dirent, P9Qid, P9Reader, P9SetAttr, P9StatFs, P9Writer, P9_HDRSZ, P9_IOHDRSZ,
+    P9_MAXWELEM, P9_NOFID, P9_NONUNAME, P9_NOTAG, P9_PROTO_2000L, P9_READDIRHDRSZ, P9_STATS_BASIC,
+};
+
+/// 9P消息的传输通道
+pub trait P9Transport: Send + Sync + Debug {
+    /// 发送一个请求，并等待回复
+    ///
+    /// ## 返回值
+    ///
+    /// 回复的长度
+    fn request(&self, req: &[u8], resp: &mut [u8]) -> Result<usize, SystemError>;
+
+    /// 传输通道支持的最大消息长度
+    fn max_msize(&self) -> u32;
+}
+
+/// 普通请求使用的tag（请求是串行的，因此只需要一个）
+const P9_TAG: u16 = 1;
+
+/// 9P2000.L客户端
+#[derive(Debug)]
+pub struct V9fsClient {
+    trans: Arc<dyn P9Transport>,
+    /// 协商后的最大消息长度
+    msize: u32,
+    /// 下一个可用的fid
+    next_fid: AtomicU32,
+    /// 回复缓冲区，同时用于串行化请求
+    resp_buf: Mutex<Vec<u8>>,
+    /// 等待释放的fid
+    pending_clunks: SpinLock<Vec<u32>>,
+}
+
+impl V9fsClient {
+    /// 创建客户端，并与服务端协商协议版本
+    pub fn new(trans: Arc<dyn P9Transport>, msize: u32) -> Result<Arc<Self>, SystemError> {
+        let msize = core::cmp::min(msize, trans.max_msize());
+        let mut client = Self {
+            trans,
+            msize,
+            next_fid: AtomicU32::new(0),
+            resp_buf: Mutex::new(alloc::vec![0; msize as usize]),
+            pending_clunks: SpinLock::new(Vec::new()),
+        };
+
+        let (msize, version) = client.rpc_tag(
+            msg::TVERSION,
+            P9_NOTAG,
+            |w| {
+                w.u32(msize).str(P9_PROTO_2000L);
+            },
+            |r| Ok((r.u32()?, r.str()?)),
+        )?;
+
+        if version != P9_PROTO_2000L {
+            warn!("9p: server doesn't support {P9_PROTO_2000L} (got {version})");
+            return Err(SystemError::EPROTONOSUPPORT);
+        }
+        if (msize as usize) < P9_IOHDRSZ + 1 {
+            return Err(SystemError::EREMOTEIO);
+        }
+        client.msize = core::cmp::min(client.msize, msize);
+
+        Ok(Arc::new(client))
+    }
+
+    /// 分配一个新的fid
+    pub fn alloc_fid(&self) -> u32 {
+        self.next_fid.fetch_add(1, Ordering::Relaxed)
+    }
+
+    /// 单次读写能够传输的最大数据量
+    pub fn iounit(&self) -> usize {
+        self.msize as usize - P9_IOHDRSZ
+    }
+
+    fn rpc<R>(
+        &self,
+        ty: u8,
+        build: impl FnOnce(&mut P9Writer),
+        parse: impl FnOnce(&mut P9Reader) -> Result<R, SystemError>,
+    ) -> Result<R, SystemError> {
+        self.flush_clunks();
+        self.rpc_tag(ty, P9_TAG, build, parse)
+    }
+
+    /// 释放之前延迟释放的fid
+    fn flush_clunks(&self) {
+        let fids = core::mem::take(&mut *self.pending_clunks.lock_irqsave());
+        for fid in fids {
+            if let Err(e) = self.clunk(fid) {
+                warn!("9p: failed to clunk fid {fid}: {e:?}");
+            }
+        }
+    }
+
+    /// 发送请求并解析回复
+    ///
+    /// 服务端返回Rlerror时，把其中的错误码转换为`SystemError`
+    fn rpc_tag<R>(
+        &self,
+        ty: u8,
+        tag: u16,
+        build: impl FnOnce(&mut P9Writer),
+        parse: impl FnOnce(&mut P9Reader) -> Result<R, SystemError>,
+    ) -> Result<R, SystemError> {
+        let mut w = P9Writer::new(ty, tag);
+        build(&mut w);
+        let req = w.finish();
+        if req.len() > self.msize as usize {
+            return Err(SystemError::EMSGSIZE);
+        }
+
+        let mut resp_buf = self.resp_buf.lock();
+        let len = self.trans.request(&req, &mut resp_buf)?;
+        if len < P9_HDRSZ {
+            return Err(SystemError::EIO);
+        }
+
+        let mut r = P9Reader::new(&resp_buf[..len]);
+        let size = r.u32()? as usize;
+        let rty = r.u8()?;
+        let _tag = r.u16()?;
+        if size > len {
+            return Err(SystemError::EIO);
+        }
+        let mut r = P9Reader::new(&resp_buf[P9_HDRSZ..size]);
+
+        if rty == msg::RLERROR {
+            let ecode = r.u32()? as i32;
+            return Err(SystemError::from_posix_errno(-ecode).unwrap_or(SystemError::EIO));
+        }
+        if rty != msg::reply_of(ty) {
+            warn!("9p: unexpected reply type {rty} for request {ty}");
+            return Err(SystemError::EIO);
+        }
+
+        parse(&mut r)
+    }
+
+    /// 挂载服务端导出的文件系统，返回根目录的qid
+    pub fn attach(&self, fid: u32, uname: &str, aname: &str) -> Result<P9Qid, SystemError> {
+        self.rpc(
+            msg::TATTACH,
+            |w| {
+                w.u32(fid)
+                    .u32(P9_NOFID)
+                    .str(uname)
+                    .str(aname)
+                    .u32(P9_NONUNAME);
+            },
+            |r| r.qid(),
+        )
+    }
+
+    /// 从fid出发，沿着names走到newfid
+    ///
+    /// names为空时，newfid成为fid的一个副本
+    pub fn walk(&self, fid: u32, newfid: u32, names: &[&str]) -> Result<Vec<P9Qid>, SystemError> {
+        if names.len() > P9_MAXWELEM {
+            return Err(SystemError::ENAMETOOLONG);
+        }
+
+        let qids = self.rpc(
+            msg::TWALK,
+            |w| {
+                w.u32(fid).u32(newfid).u16(names.len() as u16);
+                for name in names {
+                    w.str(name);
+                }
+            },
+            |r| {
+                let n = r.u16()? as usize;
+                let mut qids = Vec::with_capacity(n);
+                for _ in 0..n {
+                    qids.push(r.qid()?);
+                }
+                Ok(qids)
+            },
+        )?;
+
+        // 只走过了部分路径时，newfid不会被创建
+        if qids.len() != names.len() {
+            return Err(SystemError::ENOENT);
+        }
+        Ok(qids)
+    }
+
+    /// 打开fid，返回(qid, iounit)
+    pub fn lopen(&self, fid: u32, flags: u32) -> Result<(P9Qid, u32), SystemError> {
+        self.rpc(
+            msg::TLOPEN,
+            |w| {
+                w.u32(fid).u32(flags);
+            },
+            |r| Ok((r.qid()?, r.u32()?)),
+        )
+    }
+
+    /// 在目录fid下创建并打开文件，之后fid指向新文件
+    pub fn lcreate(
+        &self,
+        fid: u32,
+        name: &str,
+        flags: u32,
+        mode: u32,
+        gid: u32,
+    ) -> Result<(P9Qid, u32), SystemError> {
+        self.rpc(
+            msg::TLCREATE,
+            |w| {
+                w.u32(fid).str(name).u32(flags).u32(mode).u32(gid);
+            },
+            |r| Ok((r.qid()?, r.u32()?)),
+        )
+    }
+
+    /// 从已打开的fid中读取数据
+    pub fn read(&self, fid: u32, offset: u64, buf: &mut [u8]) -> Result<usize, SystemError> {
+        let count = core::cmp::min(buf.len(), self.iounit()) as u32;
+        self.rpc(
+            msg::TREAD,
+            |w| {
+                w.u32(fid).u64(offset).u32(count);
+            },
+            |r| {
+                let n = r.u32()? as usize;
+                let data = r.bytes(n)?;
+                let n = core::cmp::min(n, buf.len());
+                buf[..n].copy_from_slice(&data[..n]);
+                Ok(n)
+            },
+        )
+    }
+
+    /// 向已打开的fid写入数据
+    pub fn write(&self, fid: u32, offset: u64, buf: &[u8]) -> Result<usize, SystemError> {
+        let count = core::cmp::min(buf.len(), self.iounit());
+        self.rpc(
+            msg::TWRITE,
+            |w| {
+                w.u32(fid)
+                    .u64(offset)
+                    .u32(count as u32)
+                    .bytes(&buf[..count]);
+            },
+            |r| Ok(r.u32()? as usize),
+        )
+    }
+
+    /// 在下一次请求时释放fid
+    ///
+    /// 用于不能睡眠的上下文（例如inode被析构时）
+    pub fn clunk_deferred(&self, fid: u32) {
+        self.pending_clunks.lock_irqsave().push(fid);
+    }
+
+    /// 释放fid
+    pub fn clunk(&self, fid: u32) -> Result<(), SystemError> {
+        self.rpc(
+            msg::TCLUNK,
+            |w| {
+                w.u32(fid);
+            },
+            |_| Ok(()),
+        )
+    }
+
+    pub fn getattr(&self, fid: u32) -> Result<P9Attr, SystemError> {
+        self.rpc(
+            msg::TGETATTR,
+            |w| {
+                w.u32(fid).u64(P9_STATS_BASIC);
+            },
+            |r| r.attr(),
+        )
+    }
+
+    pub fn setattr(&self, fid: u32, attr: &P9SetAttr) -> Result<(), SystemError> {
+        self.rpc(
+            msg::TSETATTR,
+            |w| {
+                w.u32(fid)
+                    .u32(attr.valid)
+                    .u32(attr.mode)
+                    .u32(attr.uid)
+                    .u32(attr.gid)
+                    .u64(attr.size)
+                    .u64(attr.atime.tv_sec as u64)
+                    .u64(attr.atime.tv_nsec as u64)
+                    .u64(attr.mtime.tv_sec as u64)
+                    .u64(attr.mtime.tv_nsec as u64);
+            },
+            |_| Ok(()),
+        )
+    }
+
+    /// 读取已打开的目录fid中，从offset开始的目录项
+    pub fn readdir(&self, fid: u32, offset: u64) -> Result<Vec<P9Dirent>, SystemError> {
+        let count = (self.msize as usize - P9_READDIRHDRSZ) as u32;
+        self.rpc(
+            msg::TREADDIR,
+            |w| {
+                w.u32(fid).u64(offset).u32(count);
+            },
+            |r| {
+                let n = r.u32()? as usize;
+                let mut r = P9Reader::new(r.bytes(n)?);
+                let mut entries = Vec::new();
+                while r.remaining() > 0 {
+                    entries.push(r.dirent()?);
+                }
+                Ok(entries)
+            },
+        )
+    }
+
+    pub fn mkdir(&self, dfid: u32, name: &str, mode: u32, gid: u32) -> Result<P9Qid, SystemError> {
+        self.rpc(
+            msg::TMKDIR,
+            |w| {
+                w.u32(dfid).str(name).u32(mode).u32(gid);
+            },
+            |r| r.qid(),
+        )
+    }
+
+    pub fn mknod(
+        &self,
+        dfid: u32,
+        name: &str,
+        mode: u32,
+        major: u32,
+        minor: u32,
+        gid: u32,
+    ) -> Result<P9Qid, SystemError> {
+        self.rpc(
+            msg::TMKNOD,
+            |w| {
+                w.u32(dfid)
+                    .str(name)
+                    .u32(mode)
+                    .u32(major)
+                    .u32(minor)
+                    .u32(gid);
+            },
+            |r| r.qid(),
+        )
+    }
+
+    pub fn symlink(
+        &self,
+        dfid: u32,
+        name: &str,
+        target: &str,
+        gid: u32,
+    ) -> Result<P9Qid, SystemError> {
+        self.rpc(
+            msg::TSYMLINK,
+            |w| {
+                w.u32(dfid).str(name).str(target).u32(gid);
+            },
+            |r| r.qid(),
+        )
+    }
+
+    pub fn readlink(&self, fid: u32) -> Result<String, SystemError> {
+        self.rpc(
+            msg::TREADLINK,
+            |w| {
+                w.u32(fid);
+            },
+            |r| r.str(),
+        )
+    }
+
+    /// 在目录dfid下创建名为name、指向fid的硬链接
+    pub fn link(&self, dfid: u32, fid: u32, name: &str) -> Result<(), SystemError> {
+        self.rpc(
+            msg::TLINK,
+            |w| {
+                w.u32(dfid).u32(fid).str(name);
+            },
+            |_| Ok(()),
+        )
+    }
+
+    pub fn unlinkat(&self, dfid: u32, name: &str, flags: u32) -> Result<(), SystemError> {
+        self.rpc(
+            msg::TUNLINKAT,
+            |w| {
+                w.u32(dfid).str(name).u32(flags);
+            },
+            |_| Ok(()),
+        )
+    }
+
+    pub fn renameat(
+        &self,
+        old_dfid: u32,
+        old_name: &str,
+        new_dfid: u32,
+        new_name: &str,
+    ) -> Result<(), SystemError> {
+        self.rpc(
+            msg::TRENAMEAT,
+            |w| {
+                w.u32(old_dfid).str(old_name).u32(new_dfid).str(new_name);
+            },
+            |_| Ok(()),
+        )
+    }
+
+    pub fn statfs(&self, fid: u32) -> Result<P9StatFs, SystemError> {
+        self.rpc(
+            msg::TSTATFS,
+            |w| {
+                w.u32(fid);
+            },
+            |r| r.statfs(),
+        )
+    }
+
+    pub fn fsync(&self, fid: u32) -> Result<(), SystemError> {
+        self.rpc(
+            msg::TFSYNC,
+            |w| {
+                w.u32(fid).u32(0);
+            },
+            |_| Ok(()),
+        )
+    }
+}
@@ -0,0 +1,739 @@
+//! 9P2000.L文件系统
+//!
+//! 每个inode持有一个指向服务端文件的fid，读写时再按需打开额外的fid。
+//! 目前不缓存目录项和文件属性（相当于linux的`cache=none`），
+//! 因此宿主机上的修改能够立即被看到。
+//!
+//! 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/fs/9p/
+
+use core::any::Any;
+
+use alloc::{
+    string::{String, ToString},
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+use linkme::distributed_slice;
+use log::warn;
+use system_error::SystemError;
+
+use crate::{
+    driver::base::device::device_number::{DeviceNumber, Major},
+    filesystem::vfs::{
+        file::{FileMode, FilePrivateData, PageCache},
+        syscall::ModeType,
+        utils::DName,
+        FileSystem, FileSystemMaker, FileSystemMakerData, FileType, FsInfo, IndexNode, InodeId,
+        Magic, Metadata, SuperBlock, FSMAKER,
+    },
+    libs::spinlock::{SpinLock, SpinLockGuard},
+    mm::{
+        fault::{PageFaultHandler, PageFaultMessage},
+        VmFaultReason,
+    },
+    process::ProcessManager,
+};
+
+use super::{
+    client::V9fsClient,
+    protocol::{
+        open_flags::{P9_DOTL_DIRECTORY, P9_DOTL_RDONLY, P9_DOTL_WRONLY},
+        P9Attr, P9SetAttr, P9SetattrValid, P9_AT_REMOVEDIR,
+    },
+    trans_virtio::virtio_9p_find_channel,
+};
+
+/// 默认的最大消息长度
+const V9FS_DEFAULT_MSIZE: u32 = 128 * 1024;
+/// 文件名的最大长度
+const V9FS_MAX_NAMELEN: u64 = 255;
+
+/// 9P文件系统
+#[derive(Debug)]
+pub struct V9fs {
+    client: Arc<V9fsClient>,
+    root_inode: Arc<LockedV9fsInode>,
+}
+
+/// 9P文件系统的inode
+#[derive(Debug)]
+pub struct LockedV9fsInode(SpinLock<V9fsInode>);
+
+#[derive(Debug)]
+pub struct V9fsInode {
+    client: Arc<V9fsClient>,
+    /// 指向服务端文件的fid（未打开，仅用于walk、getattr等操作）
+    fid: u32,
+    /// 用于读取的已打开的fid
+    rd_fid: Option<u32>,
+    /// 用于写入的已打开的fid
+    wr_fid: Option<u32>,
+    /// 父目录（根目录为None）
+    parent: Option<Arc<LockedV9fsInode>>,
+    /// 指向自身的弱引用
+    self_ref: Weak<LockedV9fsInode>,
+    fs: Weak<V9fs>,
+    name: DName,
+    /// 最近一次从服务端取得的元数据
+    metadata: Metadata,
+    page_cache: Option<Arc<PageCache>>,
+}
+
+impl Drop for V9fsInode {
+    fn drop(&mut self) {
+        // 析构时可能处于不能睡眠的上下文，因此把fid留到下一次请求时释放
+        for fid in [Some(self.fid), self.rd_fid, self.wr_fid]
+            .into_iter()
+            .flatten()
+        {
+            self.client.clunk_deferred(fid);
+        }
+    }
+}
+
+impl V9fs {
+    /// 挂载9P文件系统
+    ///
+    /// ## 参数
+    ///
+    /// - `data.source`: virtio-9p设备的挂载标签
+    /// - `data.options`: 支持`trans=virtio`、`msize=`、`aname=`、`uname=`
+    pub fn make_v9fs(data: &FileSystemMakerData) -> Result<Arc<dyn FileSystem>, SystemError> {
+        let mut msize = V9FS_DEFAULT_MSIZE;
+        let mut aname = "";
+        let mut uname = "root";
+        for (key, value) in data.options() {
+            match (key, value) {
+                ("trans", Some("virtio")) => {}
+                ("trans", Some(_)) => return Err(SystemError::EINVAL),
+                ("msize", Some(v)) => {
+                    msize = v.parse().map_err(|_| SystemError::EINVAL)?;
+                }
+                ("aname", Some(v)) => aname = v,
+                ("uname", Some(v)) => uname = v,
+                _ => {
+                    warn!("9p: unknown mount option: {key}");
+                }
+            }
+        }
+
+        let channel = virtio_9p_find_channel(data.source()).ok_or(SystemError::ENOENT)?;
+        let client = V9fsClient::new(channel, msize)?;
+
+        let root_fid = client.alloc_fid();
+        client.attach(root_fid, uname, aname)?;
+        let attr = match client.getattr(root_fid) {
+            Ok(attr) => attr,
+            Err(e) => {
+                client.clunk_deferred(root_fid);
+                return Err(e);
+            }
+        };
+
+        let root_inode = LockedV9fsInode::new(&client, root_fid, None, DName::default(), &attr);
+        let fs = Arc::new(V9fs {
+            client,
+            root_inode: root_inode.clone(),
+        });
+        root_inode.0.lock().fs = Arc::downgrade(&fs);
+
+        Ok(fs)
+    }
+}
+
+#[distributed_slice(FSMAKER)]
+static V9FSMAKER: FileSystemMaker = FileSystemMaker::new(
+    "9p",
+    &(V9fs::make_v9fs
+        as fn(&FileSystemMakerData) -> Result<Arc<dyn FileSystem + 'static>, SystemError>),
+);
+
+impl FileSystem for V9fs {
+    fn root_inode(&self) -> Arc<dyn IndexNode> {
+        self.root_inode.clone()
+    }
+
+    fn info(&self) -> FsInfo {
+        FsInfo {
+            blk_dev_id: 0,
+            max_name_len: V9FS_MAX_NAMELEN as usize,
+        }
+    }
+
+    fn as_any_ref(&self) -> &dyn Any {
+        self
+    }
+
+    fn name(&self) -> &str {
+        "9p"
+    }
+
+    fn super_block(&self) -> SuperBlock {
+        let root_fid = self.root_inode.0.lock().fid;
+        match self.client.statfs(root_fid) {
+            Ok(st) => {
+                let mut sb = SuperBlock::new(Magic::V9FS_MAGIC, st.bsize as u64, st.namelen as u64);
+                sb.blocks = st.blocks;
+                sb.bfree = st.bfree;
+                sb.bavail = st.bavail;
+                sb.files = st.files;
+                sb.ffree = st.ffree;
+                sb.fsid = st.fsid;
+                sb
+            }
+            Err(_) => SuperBlock::new(Magic::V9FS_MAGIC, 4096, V9FS_MAX_NAMELEN),
+        }
+    }
+
+    unsafe fn fault(&self, pfm: &mut PageFaultMessage) -> VmFaultReason {
+        PageFaultHandler::filemap_fault(pfm)
+    }
+
+    unsafe fn map_pages(
+        &self,
+        pfm: &mut PageFaultMessage,
+        start_pgoff: usize,
+        end_pgoff: usize,
+    ) -> VmFaultReason {
+        PageFaultHandler::filemap_map_pages(pfm, start_pgoff, end_pgoff)
+    }
+}
+
+/// 把linux的dev_t解码为设备号
+///
+/// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/include/linux/kdev_t.h#new_decode_dev
+fn decode_dev(rdev: u64) -> DeviceNumber {
+    let major = ((rdev >> 8) & 0xfff) | ((rdev >> 32) & !0xfff);
+    let minor = (rdev & 0xff) | ((rdev >> 12) & !0xff);
+    DeviceNumber::new(Major::new(major as u32), minor as u32)
+}
+
+/// 把服务端返回的属性转换为元数据
+fn attr_to_metadata(attr: &P9Attr) -> Metadata {
+    let mode = ModeType::from_bits_truncate(attr.mode);
+    let file_type = match mode & ModeType::S_IFMT {
+        ModeType::S_IFDIR => FileType::Dir,
+        ModeType::S_IFLNK => FileType::SymLink,
+        ModeType::S_IFCHR => FileType::CharDevice,
+        ModeType::S_IFBLK => FileType::BlockDevice,
+        ModeType::S_IFIFO => FileType::Pipe,
+        ModeType::S_IFSOCK => FileType::Socket,
+        _ => FileType::File,
+    };
+
+    Metadata {
+        dev_id: 0,
+        inode_id: InodeId::new(attr.qid.path as usize),
+        size: attr.size as i64,
+        blk_size: attr.blksize as usize,
+        blocks: attr.blocks as usize,
+        atime: attr.atime,
+        mtime: attr.mtime,
+        ctime: attr.ctime,
+        file_type,
+        mode: mode & !ModeType::S_IFMT,
+        nlinks: attr.nlink as usize,
+        uid: attr.uid as usize,
+        gid: attr.gid as usize,
+        raw_dev: decode_dev(attr.rdev),
+    }
+}
+
+/// 新建文件时使用的gid
+fn current_fsgid() -> u32 {
+    ProcessManager::current_pcb().cred().fsgid.data() as u32
+}
+
+impl LockedV9fsInode {
+    fn new(
+        client: &Arc<V9fsClient>,
+        fid: u32,
+        parent: Option<Arc<LockedV9fsInode>>,
+        name: DName,
+        attr: &P9Attr,
+    ) -> Arc<Self> {
+        let metadata = attr_to_metadata(attr);
+        let is_file = metadata.file_type == FileType::File;
+        let fs = parent
+            .as_ref()
+            .map(|p| p.0.lock().fs.clone())
+            .unwrap_or_default();
+
+        let inode = Arc::new(Self(SpinLock::new(V9fsInode {
+            client: client.clone(),
+            fid,
+            rd_fid: None,
+            wr_fid: None,
+            parent,
+            self_ref: Weak::new(),
+            fs,
+            name,
+            metadata,
+            page_cache: None,
+        })));
+
+        let mut guard = inode.0.lock();
+        guard.self_ref = Arc::downgrade(&inode);
+        if is_file {
+            guard.page_cache = Some(PageCache::new(Some(
+                Arc::downgrade(&inode) as Weak<dyn IndexNode>
+            )));
+        }
+        drop(guard);
+
+        inode
+    }
+
+    fn client(&self) -> Arc<V9fsClient> {
+        self.0.lock().client.clone()
+    }
+
+    fn fid(&self) -> u32 {
+        self.0.lock().fid
+    }
+
+    /// 获取用于读或写的已打开的fid，如果还没有，就打开一个
+    fn io_fid(&self, write: bool) -> Result<u32, SystemError> {
+        let (client, fid) = {
+            let guard = self.0.lock();
+            let opened = if write { guard.wr_fid } else { guard.rd_fid };
+            if let Some(opened) = opened {
+                return Ok(opened);
+            }
+            (guard.client.clone(), guard.fid)
+        };
+
+        let flags = match (write, self.0.lock().metadata.file_type) {
+            (true, _) => P9_DOTL_WRONLY,
+            (false, FileType::Dir) => P9_DOTL_RDONLY | P9_DOTL_DIRECTORY,
+            (false, _) => P9_DOTL_RDONLY,
+        };
+        let newfid = Self::open_fid(&client, fid, flags)?;
+
+        let mut guard = self.0.lock();
+        let slot = if write {
+            &mut guard.wr_fid
+        } else {
+            &mut guard.rd_fid
+        };
+        match slot {
+            // 其他线程已经抢先打开了
+            Some(opened) => {
+                let opened = *opened;
+                drop(guard);
+                client.clunk_deferred(newfid);
+                Ok(opened)
+            }
+            None => {
+                *slot = Some(newfid);
+                Ok(newfid)
+            }
+        }
+    }
+
+    /// 复制fid并以flags打开
+    fn open_fid(client: &Arc<V9fsClient>, fid: u32, flags: u32) -> Result<u32, SystemError> {
+        let newfid = client.alloc_fid();
+        client.walk(fid, newfid, &[])?;
+        if let Err(e) = client.lopen(newfid, flags) {
+            client.clunk_deferred(newfid);
+            return Err(e);
+        }
+        Ok(newfid)
+    }
+
+    /// 从服务端重新获取元数据
+    fn refresh(&self) -> Result<Metadata, SystemError> {
+        let (client, fid) = {
+            let guard = self.0.lock();
+            (guard.client.clone(), guard.fid)
+        };
+        let metadata = attr_to_metadata(&client.getattr(fid)?);
+        self.0.lock().metadata = metadata.clone();
+        Ok(metadata)
+    }
+
+    fn check_dir(&self) -> Result<(), SystemError> {
+        if self.0.lock().metadata.file_type != FileType::Dir {
+            return Err(SystemError::ENOTDIR);
+        }
+        Ok(())
+    }
+
+    fn setattr(&self, attr: &P9SetAttr) -> Result<(), SystemError> {
+        let (client, fid) = {
+            let guard = self.0.lock();
+            (guard.client.clone(), guard.fid)
+        };
+        client.setattr(fid, attr)?;
+        self.refresh()?;
+        Ok(())
+    }
+
+    /// 在当前目录下查找name，返回新的inode
+    fn lookup(&self, name: &str) -> Result<Arc<LockedV9fsInode>, SystemError> {
+        self.check_dir()?;
+        let (client, fid, self_arc) = {
+            let guard = self.0.lock();
+            (
+                guard.client.clone(),
+                guard.fid,
+                guard.self_ref.upgrade().ok_or(SystemError::ENOENT)?,
+            )
+        };
+
+        let newfid = client.alloc_fid();
+        client.walk(fid, newfid, &[name])?;
+        let attr = match client.getattr(newfid) {
+            Ok(attr) => attr,
+            Err(e) => {
+                client.clunk_deferred(newfid);
+                return Err(e);
+            }
+        };
+
+        Ok(LockedV9fsInode::new(
+            &client,
+            newfid,
+            Some(self_arc),
+            DName::from(name),
+            &attr,
+        ))
+    }
+
+    /// 读取目录中的所有目录项
+    fn read_dir(&self) -> Result<Vec<super::protocol::P9Dirent>, SystemError> {
+        self.check_dir()?;
+        let client = self.client();
+        let dfid = Self::open_fid(&client, self.fid(), P9_DOTL_RDONLY | P9_DOTL_DIRECTORY)?;
+
+        let mut result = Vec::new();
+        let mut offset = 0;
+        let r = loop {
+            match client.readdir(dfid, offset) {
+                Ok(entries) if entries.is_empty() => break Ok(()),
+                Ok(entries) => {
+                    offset = entries.last().unwrap().offset;
+                    result.extend(entries);
+                }
+                Err(e) => break Err(e),
+            }
+        };
+        client.clunk(dfid)?;
+        r.map(|_| result)
+    }
+}
+
+impl IndexNode for LockedV9fsInode {
+    fn open(
+        &self,
+        _data: SpinLockGuard<FilePrivateData>,
+        mode: &FileMode,
+    ) -> Result<(), SystemError> {
+        if self.0.lock().metadata.file_type != FileType::File {
+            return Ok(());
+        }
+
+        // 提前打开fid，使权限错误在open时就能返回
+        let accmode = mode.accmode();
+        if accmode == FileMode::O_RDONLY.bits() || accmode == FileMode::O_RDWR.bits() {
+            self.io_fid(false)?;
+        }
+        if accmode == FileMode::O_WRONLY.bits() || accmode == FileMode::O_RDWR.bits() {
+            self.io_fid(true)?;
+        }
+        Ok(())
+    }
+
+    fn close(&self, _data: SpinLockGuard<FilePrivateData>) -> Result<(), SystemError> {
+        Ok(())
+    }
+
+    fn read_at(
+        &self,
+        offset: usize,
+        len: usize,
+        buf: &mut [u8],
+        _data: SpinLockGuard<FilePrivateData>,
+    ) -> Result<usize, SystemError> {
+        let len = core::cmp::min(len, buf.len());
+        let buf = &mut buf[..len];
+
+        match self.0.lock().metadata.file_type {
+            FileType::Dir => return Err(SystemError::EISDIR),
+            FileType::SymLink => {
+                let target = self.client().readlink(self.fid())?;
+                let target = target.as_bytes();
+                if offset >= target.len() {
+                    return Ok(0);
+                }
+                let n = core::cmp::min(len, target.len() - offset);
+                buf[..n].copy_from_slice(&target[offset..offset + n]);
+                return Ok(n);
+            }
+            _ => {}
+        }
+
+        let client = self.client();
+        let fid = self.io_fid(false)?;
+        let mut done = 0;
+        while done < len {
+            let n = client.read(fid, (offset + done) as u64, &mut buf[done..])?;
+            if n == 0 {
+                break;
+            }
+            done += n;
+        }
+        Ok(done)
+    }
+
+    fn write_at(
+        &self,
+        offset: usize,
+        len: usize,
+        buf: &[u8],
+        _data: SpinLockGuard<FilePrivateData>,
+    ) -> Result<usize, SystemError> {
+        let len = core::cmp::min(len, buf.len());
+        if self.0.lock().metadata.file_type == FileType::Dir {
+            return Err(SystemError::EISDIR);
+        }
+
+        let client = self.client();
+        let fid = self.io_fid(true)?;
+        let mut done = 0;
+        while done < len {
+            let n = client.write(fid, (offset + done) as u64, &buf[done..len])?;
+            if n == 0 {
+                break;
+            }
+            done += n;
+        }
+
+        let mut guard = self.0.lock();
+        let end = (offset + done) as i64;
+        if end > guard.metadata.size {
+            guard.metadata.size = end;
+        }
+        Ok(done)
+    }
+
+    fn metadata(&self) -> Result<Metadata, SystemError> {
+        self.refresh()
+    }
+
+    fn set_metadata(&self, metadata: &Metadata) -> Result<(), SystemError> {
+        let old = self.0.lock().metadata.clone();
+        let mut attr = P9SetAttr::default();
+        let mut valid = P9SetattrValid::empty();
+
+        if metadata.mode != old.mode {
+            valid |= P9SetattrValid::MODE;
+            attr.mode = (metadata.mode & !ModeType::S_IFMT).bits();
+        }
+        if metadata.uid != old.uid {
+            valid |= P9SetattrValid::UID;
+            attr.uid = metadata.uid as u32;
+        }
+        if metadata.gid != old.gid {
+            valid |= P9SetattrValid::GID;
+            attr.gid = metadata.gid as u32;
+        }
+        if metadata.atime != old.atime {
+            valid |= P9SetattrValid::ATIME | P9SetattrValid::ATIME_SET;
+            attr.atime = metadata.atime;
+        }
+        if metadata.mtime != old.mtime {
+            valid |= P9SetattrValid::MTIME | P9SetattrValid::MTIME_SET;
+            attr.mtime = metadata.mtime;
+        }
+        if valid.is_empty() {
+            return Ok(());
+        }
+
+        attr.valid = valid.bits();
+        self.setattr(&attr)
+    }
+
+    fn resize(&self, len: usize) -> Result<(), SystemError> {
+        self.truncate(len)
+    }
+
+    fn truncate(&self, len: usize) -> Result<(), SystemError> {
+        if self.0.lock().metadata.file_type == FileType::Dir {
+            return Err(SystemError::EISDIR);
+        }
+        let attr = P9SetAttr {
+            valid: P9SetattrValid::SIZE.bits(),
+            size: len as u64,
+            ..Default::default()
+        };
+        self.setattr(&attr)
+    }
+
+    fn create_with_data(
+        &self,
+        name: &str,
+        file_type: FileType,
+        mode: ModeType,
+        _data: usize,
+    ) -> Result<Arc<dyn IndexNode>, SystemError> {
+        self.check_dir()?;
+        let client = self.client();
+        let dfid = self.fid();
+        let mode = (mode & !ModeType::S_IFMT).bits();
+        let gid = current_fsgid();
+
+        match file_type {
+            FileType::File => {
+                // lcreate会让fid指向新文件，因此需要先复制一份目录的fid
+                let newfid = client.alloc_fid();
+                client.walk(dfid, newfid, &[])?;
+                let r = client.lcreate(newfid, name, P9_DOTL_WRONLY, mode, gid);
+                client.clunk(newfid)?;
+                r?;
+            }
+            FileType::Dir => {
+                client.mkdir(dfid, name, mode, gid)?;
+            }
+            FileType::Pipe => {
+                client.mknod(dfid, name, mode | ModeType::S_IFIFO.bits(), 0, 0, gid)?;
+            }
+            FileType::Socket => {
+                client.mknod(dfid, name, mode | ModeType::S_IFSOCK.bits(), 0, 0, gid)?;
+            }
+            _ => return Err(SystemError::EINVAL),
+        }
+
+        Ok(self.lookup(name)?)
+    }
+
+    fn mknod(
+        &self,
+        filename: &str,
+        mode: ModeType,
+        dev_t: DeviceNumber,
+    ) -> Result<Arc<dyn IndexNode>, SystemError> {
+        self.check_dir()?;
+        self.client().mknod(
+            self.fid(),
+            filename,
+            mode.bits(),
+            dev_t.major().data(),
+            dev_t.minor(),
+            current_fsgid(),
+        )?;
+        Ok(self.lookup(filename)?)
+    }
+
+    fn link(&self, name: &str, other: &Arc<dyn IndexNode>) -> Result<(), SystemError> {
+        self.check_dir()?;
+        let other = other
+            .as_any_ref()
+            .downcast_ref::<LockedV9fsInode>()
+            .ok_or(SystemError::EXDEV)?;
+        if other.0.lock().metadata.file_type == FileType::Dir {
+            return Err(SystemError::EISDIR);
+        }
+        self.client().link(self.fid(), other.fid(), name)
+    }
+
+    fn unlink(&self, name: &str) -> Result<(), SystemError> {
+        self.check_dir()?;
+        self.client().unlinkat(self.fid(), name, 0)
+    }
+
+    fn rmdir(&self, name: &str) -> Result<(), SystemError> {
+        self.check_dir()?;
+        self.client().unlinkat(self.fid(), name, P9_AT_REMOVEDIR)
+    }
+
+    fn move_to(
+        &self,
+        old_name: &str,
+        target: &Arc<dyn IndexNode>,
+        new_name: &str,
+    ) -> Result<(), SystemError> {
+        self.check_dir()?;
+        let target = target
+            .as_any_ref()
+            .downcast_ref::<LockedV9fsInode>()
+            .ok_or(SystemError::EXDEV)?;
+        target.check_dir()?;
+        self.client()
+            .renameat(self.fid(), old_name, target.fid(), new_name)
+    }
+
+    fn find(&self, name: &str) -> Result<Arc<dyn IndexNode>, SystemError> {
+        self.check_dir()?;
+        let guard = self.0.lock();
+        match name {
+            "" | "." => {
+                return guard
+                    .self_ref
+                    .upgrade()
+                    .map(|i| i as Arc<dyn IndexNode>)
+                    .ok_or(SystemError::ENOENT)
+            }
+            ".." => {
+                return guard
+                    .parent
+                    .clone()
+                    .or_else(|| guard.self_ref.upgrade())
+                    .map(|i| i as Arc<dyn IndexNode>)
+                    .ok_or(SystemError::ENOENT)
+            }
+            _ => {}
+        }
+        drop(guard);
+
+        Ok(self.lookup(name)?)
+    }
+
+    fn get_entry_name(&self, ino: InodeId) -> Result<String, SystemError> {
+        self.read_dir()?
+            .into_iter()
+            .find(|e| InodeId::new(e.qid.path as usize) == ino && e.name != "." && e.name != "..")
+            .map(|e| e.name)
+            .ok_or(SystemError::ENOENT)
+    }
+
+    fn fs(&self) -> Arc<dyn FileSystem> {
+        self.0.lock().fs.upgrade().unwrap()
+    }
+
+    fn as_any_ref(&self) -> &dyn Any {
+        self
+    }
+
+    fn list(&self) -> Result<Vec<String>, SystemError> {
+        let mut names: Vec<String> = self.read_dir()?.into_iter().map(|e| e.name).collect();
+        // 部分服务端不会返回"."和".."
+        for special in ["..", "."] {
+            if !names.iter().any(|n| n == special) {
+                names.insert(0, special.to_string());
+            }
+        }
+        Ok(names)
+    }
+
+    fn sync(&self) -> Result<(), SystemError> {
+        let wr_fid = self.0.lock().wr_fid;
+        if let Some(fid) = wr_fid {
+            self.client().fsync(fid)?;
+        }
+        Ok(())
+    }
+
+    fn dname(&self) -> Result<DName, SystemError> {
+        Ok(self.0.lock().name.clone())
+    }
+
+    fn parent(&self) -> Result<Arc<dyn IndexNode>, SystemError> {
+        self.find("..")
+    }
+
+    fn page_cache(&self) -> Option<Arc<PageCache>> {
+        self.0.lock().page_cache.clone()
+    }
+}
@@ -0,0 +1,10 @@
+//! 9P2000.L共享文件夹文件系统
+//!
+//! 通过virtio-9p与宿主机共享目录，例如在qemu中使用
+//! `-virtfs local,path=<dir>,mount_tag=<tag>,security_model=mapped-xattr`，
+//! 之后在系统内执行`mount -t 9p -o trans=virtio <tag> <dir>`即可挂载。
+
+pub mod client;
+pub mod fs;
+pub mod protocol;
+pub mod trans_virtio;
@@ -0,0 +1,344 @@
+//! 9P2000.L协议的消息编解码
+//!
+//! 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/include/net/9p/9p.h
+//! 以及 https://github.com/chaos/diod/blob/master/protocol.md
+
+use alloc::{string::String, vec::Vec};
+use system_error::SystemError;
+
+use crate::time::PosixTimeSpec;
+
+/// 协议版本
+pub const P9_PROTO_2000L: &str = "9P2000.L";
+
+/// 消息头的长度：size[4] type[1] tag[2]
+pub const P9_HDRSZ: usize = 7;
+/// Tread/Twrite除数据以外的开销：头部 + fid[4] offset[8] count[4]
+pub const P9_IOHDRSZ: usize = P9_HDRSZ + 16;
+/// Treaddir除数据以外的开销：头部 + count[4]
+pub const P9_READDIRHDRSZ: usize = P9_HDRSZ + 4;
+
+/// 不使用的tag（仅用于Tversion）
+pub const P9_NOTAG: u16 = !0;
+/// 不使用的fid
+pub const P9_NOFID: u32 = !0;
+/// 不指定的uid
+pub const P9_NONUNAME: u32 = !0;
+
+/// Twalk一次最多能走过的路径分量
+pub const P9_MAXWELEM: usize = 16;
+
+/// 9P2000.L消息类型
+///
+/// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/include/net/9p/9p.h#139
+#[allow(dead_code)]
+pub mod msg {
+    pub const RLERROR: u8 = 7;
+    pub const TSTATFS: u8 = 8;
+    pub const TLOPEN: u8 = 12;
+    pub const TLCREATE: u8 = 14;
+    pub const TSYMLINK: u8 = 16;
+    pub const TMKNOD: u8 = 18;
+    pub const TREADLINK: u8 = 22;
+    pub const TGETATTR: u8 = 24;
+    pub const TSETATTR: u8 = 26;
+    pub const TREADDIR: u8 = 40;
+    pub const TFSYNC: u8 = 50;
+    pub const TLINK: u8 = 70;
+    pub const TMKDIR: u8 = 72;
+    pub const TRENAMEAT: u8 = 74;
+    pub const TUNLINKAT: u8 = 76;
+    pub const TVERSION: u8 = 100;
+    pub const TATTACH: u8 = 104;
+    pub const TWALK: u8 = 110;
+    pub const TREAD: u8 = 116;
+    pub const TWRITE: u8 = 118;
+    pub const TCLUNK: u8 = 120;
+
+    /// 回复的类型总是请求的类型加一
+    #[inline(always)]
+    pub const fn reply_of(t: u8) -> u8 {
+        t + 1
+    }
+}
+
+/// Tlopen/Tlcreate使用的打开标志（与linux的O_*一致）
+#[allow(dead_code)]
+pub mod open_flags {
+    pub const P9_DOTL_RDONLY: u32 = 0o0;
+    pub const P9_DOTL_WRONLY: u32 = 0o1;
+    pub const P9_DOTL_RDWR: u32 = 0o2;
+    pub const P9_DOTL_CREATE: u32 = 0o100;
+    pub const P9_DOTL_EXCL: u32 = 0o200;
+    pub const P9_DOTL_TRUNC: u32 = 0o1000;
+    pub const P9_DOTL_APPEND: u32 = 0o2000;
+    pub const P9_DOTL_DIRECTORY: u32 = 0o200000;
+}
+
+/// Tgetattr请求的字段
+pub const P9_STATS_BASIC: u64 = 0x0000_07ff;
+
+bitflags! {
+    /// Tsetattr中有效的字段
+    ///
+    /// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/include/net/9p/9p.h#407
+    pub struct P9SetattrValid: u32 {
+        const MODE = 0x0000_0001;
+        const UID = 0x0000_0002;
+        const GID = 0x0000_0004;
+        const SIZE = 0x0000_0008;
+        const ATIME = 0x0000_0010;
+        const MTIME = 0x0000_0020;
+        const CTIME = 0x0000_0040;
+        const ATIME_SET = 0x0000_0080;
+        const MTIME_SET = 0x0000_0100;
+    }
+}
+
+/// Tunlinkat删除目录时使用的标志
+pub const P9_AT_REMOVEDIR: u32 = 0x200;
+
+/// qid.type中的目录标志
+pub const P9_QTDIR: u8 = 0x80;
+/// qid.type中的符号链接标志
+pub const P9_QTSYMLINK: u8 = 0x02;
+
+/// 服务端文件的唯一标识
+#[derive(Debug, Clone, Copy, Default)]
+pub struct P9Qid {
+    pub ty: u8,
+    pub version: u32,
+    pub path: u64,
+}
+
+/// Rgetattr返回的文件属性
+#[derive(Debug, Clone, Default)]
+pub struct P9Attr {
+    pub qid: P9Qid,
+    pub mode: u32,
+    pub uid: u32,
+    pub gid: u32,
+    pub nlink: u64,
+    pub rdev: u64,
+    pub size: u64,
+    pub blksize: u64,
+    pub blocks: u64,
+    pub atime: PosixTimeSpec,
+    pub mtime: PosixTimeSpec,
+    pub ctime: PosixTimeSpec,
+}
+
+/// Tsetattr的参数
+#[derive(Debug, Clone, Default)]
+pub struct P9SetAttr {
+    pub valid: u32,
+    pub mode: u32,
+    pub uid: u32,
+    pub gid: u32,
+    pub size: u64,
+    pub atime: PosixTimeSpec,
+    pub mtime: PosixTimeSpec,
+}
+
+/// Rstatfs返回的文件系统信息
+#[derive(Debug, Clone, Default)]
+pub struct P9StatFs {
+    pub ty: u32,
+    pub bsize: u32,
+    pub blocks: u64,
+    pub bfree: u64,
+    pub bavail: u64,
+    pub files: u64,
+    pub ffree: u64,
+    pub fsid: u64,
+    pub namelen: u32,
+}
+
+/// Rreaddir中的一个目录项
+#[derive(Debug, Clone)]
+pub struct P9Dirent {
+    pub qid: P9Qid,
+    pub offset: u64,
+    pub name: String,
+}
+
+/// 构造9P请求
+#[derive(Debug)]
+pub struct P9Writer {
+    buf: Vec<u8>,
+}
+
+impl P9Writer {
+    /// 创建一个请求，并写入消息头（size稍后由`finish`填写）
+    pub fn new(ty: u8, tag: u16) -> Self {
+        let mut w = Self {
+            buf: Vec::with_capacity(64),
+        };
+        w.u32(0);
+        w.u8(ty);
+        w.u16(tag);
+        w
+    }
+
+    pub fn u8(&mut self, v: u8) -> &mut Self {
+        self.buf.push(v);
+        self
+    }
+
+    pub fn u16(&mut self, v: u16) -> &mut Self {
+        self.buf.extend_from_slice(&v.to_le_bytes());
+        self
+    }
+
+    pub fn u32(&mut self, v: u32) -> &mut Self {
+        self.buf.extend_from_slice(&v.to_le_bytes());
+        self
+    }
+
+    pub fn u64(&mut self, v: u64) -> &mut Self {
+        self.buf.extend_from_slice(&v.to_le_bytes());
+        self
+    }
+
+    /// 写入字符串：len[2] data[len]
+    pub fn str(&mut self, s: &str) -> &mut Self {
+        self.u16(s.len() as u16);
+        self.buf.extend_from_slice(s.as_bytes());
+        self
+    }
+
+    /// 写入原始数据（长度需要调用者事先写入）
+    pub fn bytes(&mut self, data: &[u8]) -> &mut Self {
+        self.buf.extend_from_slice(data);
+        self
+    }
+
+    /// 填写消息长度，返回完整的消息
+    pub fn finish(mut self) -> Vec<u8> {
+        let len = self.buf.len() as u32;
+        self.buf[..4].copy_from_slice(&len.to_le_bytes());
+        self.buf
+    }
+}
+
+/// 解析9P回复
+#[derive(Debug)]
+pub struct P9Reader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> P9Reader<'a> {
+    pub fn new(buf: &'a [u8]) -> Self {
+        Self { buf, pos: 0 }
+    }
+
+    /// 剩余未读取的数据
+    pub fn remaining(&self) -> usize {
+        self.buf.len() - self.pos
+    }
+
+    pub fn bytes(&mut self, len: usize) -> Result<&'a [u8], SystemError> {
+        if self.remaining() < len {
+            return Err(SystemError::EIO);
+        }
+        let r = &self.buf[self.pos..self.pos + len];
+        self.pos += len;
+        Ok(r)
+    }
+
+    pub fn u8(&mut self) -> Result<u8, SystemError> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    pub fn u16(&mut self) -> Result<u16, SystemError> {
+        Ok(u16::from_le_bytes(self.bytes(2)?.try_into().unwrap()))
+    }
+
+    pub fn u32(&mut self) -> Result<u32, SystemError> {
+        Ok(u32::from_le_bytes(self.bytes(4)?.try_into().unwrap()))
+    }
+
+    pub fn u64(&mut self) -> Result<u64, SystemError> {
+        Ok(u64::from_le_bytes(self.bytes(8)?.try_into().unwrap()))
+    }
+
+    pub fn str(&mut self) -> Result<String, SystemError> {
+        let len = self.u16()? as usize;
+        let data = self.bytes(len)?;
+        core::str::from_utf8(data)
+            .map(String::from)
+            .map_err(|_| SystemError::EILSEQ)
+    }
+
+    pub fn qid(&mut self) -> Result<P9Qid, SystemError> {
+        Ok(P9Qid {
+            ty: self.u8()?,
+            version: self.u32()?,
+            path: self.u64()?,
+        })
+    }
+
+    fn timespec(&mut self) -> Result<PosixTimeSpec, SystemError> {
+        let sec = self.u64()? as i64;
+        let nsec = self.u64()? as i64;
+        Ok(PosixTimeSpec::new(sec, nsec))
+    }
+
+    /// 解析Rgetattr
+    pub fn attr(&mut self) -> Result<P9Attr, SystemError> {
+        let _valid = self.u64()?;
+        let qid = self.qid()?;
+        let mode = self.u32()?;
+        let uid = self.u32()?;
+        let gid = self.u32()?;
+        let nlink = self.u64()?;
+        let rdev = self.u64()?;
+        let size = self.u64()?;
+        let blksize = self.u64()?;
+        let blocks = self.u64()?;
+        let atime = self.timespec()?;
+        let mtime = self.timespec()?;
+        let ctime = self.timespec()?;
+        // btime, gen, data_version暂不使用
+
+        Ok(P9Attr {
+            qid,
+            mode,
+            uid,
+            gid,
+            nlink,
+            rdev,
+            size,
+            blksize,
+            blocks,
+            atime,
+            mtime,
+            ctime,
+        })
+    }
+
+    /// 解析Rstatfs
+    pub fn statfs(&mut self) -> Result<P9StatFs, SystemError> {
+        Ok(P9StatFs {
+            ty: self.u32()?,
+            bsize: self.u32()?,
+            blocks: self.u64()?,
+            bfree: self.u64()?,
+            bavail: self.u64()?,
+            files: self.u64()?,
+            ffree: self.u64()?,
+            fsid: self.u64()?,
+            namelen: self.u32()?,
+        })
+    }
+
+    /// 解析Rreaddir中的一个目录项
+    pub fn dirent(&mut self) -> Result<P9Dirent, SystemError> {
+        let qid = self.qid()?;
+        let offset = self.u64()?;
+        let _ty = self.u8()?;
+        let name = self.str()?;
+        Ok(P9Dirent { qid, offset, name })
+    }
+}
@@ -0,0 +1,628 @@
+//! virtio-9p传输层
+//!
+//! 每个virtio-9p设备带有一个挂载标签（mount tag），挂载时通过
+//! `mount -t 9p <tag> <dir>`选择对应的设备。
+//!
+//! 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/net/9p/trans_virtio.c
+
+use core::{
+    any::Any,
+    fmt::Debug,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use alloc::{
+    collections::LinkedList,
+    string::{String, ToString},
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+use log::{error, info};
+use system_error::SystemError;
+use unified_init::macros::unified_init;
+use virtio_drivers::transport::Transport;
+
+use crate::{
+    driver::{
+        base::{
+            class::Class,
+            device::{
+                bus::Bus,
+                driver::{Driver, DriverCommonData},
+                Device, DeviceCommonData, DeviceId, DeviceType, IdTable,
+            },
+            kobject::{KObjType, KObject, KObjectCommonData, KObjectState, LockedKObjectState},
+            kset::KSet,
+        },
+        virtio::{
+            queue::{virtio_begin_init, virtio_reset, virtqueue_wait_used, SplitVirtQueue},
+            sysfs::{virtio_bus, virtio_device_manager, virtio_driver_manager},
+            transport::VirtIOTransport,
+            VirtIODevice, VirtIODeviceIndex, VirtIODriver, VirtIODriverCommonData, VirtioDeviceId,
+            VIRTIO_VENDOR_ID,
+        },
+    },
+    exception::{irqdesc::IrqReturn, IrqNumber},
+    filesystem::kernfs::KernFSInode,
+    init::initcall::INITCALL_POSTCORE,
+    libs::{
+        lazy_init::Lazy,
+        mutex::Mutex,
+        rwlock::{RwLockReadGuard, RwLockWriteGuard},
+        spinlock::{SpinLock, SpinLockGuard},
+    },
+    sched::completion::Completion,
+    time::Duration,
+};
+
+use super::client::P9Transport;
+
+const VIRTIO_9P_BASENAME: &str = "virtio_9p";
+
+/// 设备的配置空间中带有挂载标签
+const VIRTIO_9P_MOUNT_TAG: u64 = 1 << 0;
+
+/// virtio-9p设备只有一个请求队列
+const VIRTIO_9P_QUEUE: u16 = 0;
+const VIRTIO_9P_QUEUE_SIZE: u16 = 16;
+
+/// 传输层支持的最大消息长度
+///
+/// 每个请求只使用一块连续的缓冲区，因此这里不需要受限于队列长度
+const VIRTIO_9P_MAX_MSIZE: u32 = 512 * 1024;
+
+/// 等待服务端响应的超时时间
+///
+/// 服务端处理一个请求可能需要访问宿主机上较慢的存储，因此比其它virtio设备的超时时间更长
+const VIRTIO_9P_REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+static VIRTIO_9P_DRIVER: Lazy<Arc<VirtIO9pDriver>> = Lazy::new();
+
+#[inline(always)]
+fn virtio_9p_driver() -> Arc<VirtIO9pDriver> {
+    VIRTIO_9P_DRIVER.get().clone()
+}
+
+/// 根据挂载标签查找virtio-9p设备
+pub fn virtio_9p_find_channel(tag: &str) -> Option<Arc<VirtIO9pDevice>> {
+    if !VIRTIO_9P_DRIVER.initialized() {
+        return None;
+    }
+
+    virtio_9p_driver()
+        .devices()
+        .into_iter()
+        .filter_map(|dev| dev.arc_any().downcast::<VirtIO9pDevice>().ok())
+        .find(|dev| dev.tag == tag)
+}
+
+pub fn virtio_9p(
+    transport: VirtIOTransport,
+    dev_id: Arc<DeviceId>,
+    dev_parent: Option<Arc<dyn Device>>,
+) {
+    let device = VirtIO9pDevice::new(transport, dev_id);
+    if let Some(device) = device {
+        if let Some(dev_parent) = dev_parent {
+            device.set_dev_parent(Some(Arc::downgrade(&dev_parent)));
+        }
+        virtio_device_manager()
+            .device_add(device.clone() as Arc<dyn VirtIODevice>)
+            .expect("Add virtio 9p failed");
+    }
+}
+
+/// virtio-9p配置空间的头部，之后紧跟着`tag_len`字节的挂载标签
+#[repr(C)]
+struct Virtio9pConfig {
+    tag_len: u16,
+}
+
+/// virtio 9p device
+#[derive(Debug)]
+#[cast_to([sync] VirtIODevice)]
+#[cast_to([sync] Device)]
+pub struct VirtIO9pDevice {
+    dev_id: Arc<DeviceId>,
+    /// 挂载标签
+    tag: String,
+    /// 串行化请求
+    request_lock: Mutex<()>,
+    /// 服务端处理完请求后，由中断处理函数唤醒等待者
+    completion: Completion,
+    /// 设备超时未响应而被复位后置位，之后的请求直接失败
+    broken: AtomicBool,
+    inner: SpinLock<InnerVirtIO9pDevice>,
+    locked_kobj_state: LockedKObjectState,
+}
+
+unsafe impl Send for VirtIO9pDevice {}
+unsafe impl Sync for VirtIO9pDevice {}
+
+impl VirtIO9pDevice {
+    pub fn new(mut transport: VirtIOTransport, dev_id: Arc<DeviceId>) -> Option<Arc<Self>> {
+        let irq = transport.irq().map(|irq| IrqNumber::new(irq.data()));
+        let r = virtio_begin_init(&mut transport, VIRTIO_9P_MOUNT_TAG).and_then(|features| {
+            let tag = Self::read_tag(&transport, features)?;
+            let queue = SplitVirtQueue::new(&mut transport, VIRTIO_9P_QUEUE, VIRTIO_9P_QUEUE_SIZE)?;
+            Ok((tag, queue))
+        });
+        let (tag, queue) = match r {
+            Ok(r) => r,
+            Err(e) => {
+                error!("VirtIO9pDevice '{dev_id:?}' create failed: {:?}", e);
+                return None;
+            }
+        };
+        transport.finish_init();
+        info!("virtio-9p: found device with mount tag '{tag}'");
+
+        let dev = Arc::new(Self {
+            dev_id,
+            tag,
+            request_lock: Mutex::new(()),
+            completion: Completion::new(),
+            broken: AtomicBool::new(false),
+            locked_kobj_state: LockedKObjectState::default(),
+            inner: SpinLock::new(InnerVirtIO9pDevice {
+                transport,
+                queue,
+                name: None,
+                virtio_index: None,
+                device_common: DeviceCommonData::default(),
+                kobject_common: KObjectCommonData::default(),
+                irq,
+            }),
+        });
+
+        Some(dev)
+    }
+
+    /// 从配置空间读取挂载标签
+    fn read_tag(transport: &VirtIOTransport, features: u64) -> Result<String, SystemError> {
+        if features & VIRTIO_9P_MOUNT_TAG == 0 {
+            return Err(SystemError::EINVAL);
+        }
+
+        let config = transport
+            .config_space::<Virtio9pConfig>()
+            .map_err(|_| SystemError::EINVAL)?;
+        let tag_len =
+            unsafe { core::ptr::addr_of!((*config.as_ptr()).tag_len).read_volatile() } as usize;
+        let tag_ptr = unsafe { config.as_ptr().cast::<u8>().add(2) };
+        let tag: Vec<u8> = (0..tag_len)
+            .map(|i| unsafe { tag_ptr.add(i).read_volatile() })
+            .collect();
+
+        String::from_utf8(tag).map_err(|_| SystemError::EINVAL)
+    }
+
+    fn inner(&self) -> SpinLockGuard<InnerVirtIO9pDevice> {
+        self.inner.lock_irqsave()
+    }
+
+    /// 挂载标签
+    pub fn tag(&self) -> &str {
+        &self.tag
+    }
+}
+
+impl P9Transport for VirtIO9pDevice {
+    fn request(&self, req: &[u8], resp: &mut [u8]) -> Result<usize, SystemError> {
+        let _guard = self.request_lock.lock();
+        if self.broken.load(Ordering::Acquire) {
+            return Err(SystemError::EIO);
+        }
+
+        let token = {
+            let mut inner = self.inner();
+            let inner = &mut *inner;
+            // req与resp在请求完成或设备被复位之前不会被释放
+            let token = unsafe { inner.queue.add(&[req], &mut [&mut *resp])? };
+            inner.queue.notify(&mut inner.transport);
+            token
+        };
+
+        // 睡眠等待服务端处理完成。等待期间不持有自旋锁，以免长时间关中断
+        let completion = self.inner().irq.map(|_| &self.completion);
+        let r = virtqueue_wait_used(completion, VIRTIO_9P_REQUEST_TIMEOUT, || {
+            let mut inner = self.inner();
+            while let Some((t, len)) = inner.queue.pop_used() {
+                if t == token {
+                    return Some(len);
+                }
+            }
+            None
+        });
+        match r {
+            Ok(len) => Ok(len as usize),
+            Err(e) => {
+                // 复位设备，使其不再访问调用者的缓冲区
+                error!(
+                    "virtio-9p: request on '{}' failed: {:?}, resetting device",
+                    self.tag, e
+                );
+                self.broken.store(true, Ordering::Release);
+                virtio_reset(&mut self.inner().transport);
+                Err(SystemError::EIO)
+            }
+        }
+    }
+
+    fn max_msize(&self) -> u32 {
+        VIRTIO_9P_MAX_MSIZE
+    }
+}
+
+struct InnerVirtIO9pDevice {
+    transport: VirtIOTransport,
+    queue: SplitVirtQueue,
+    name: Option<String>,
+    virtio_index: Option<VirtIODeviceIndex>,
+    device_common: DeviceCommonData,
+    kobject_common: KObjectCommonData,
+    irq: Option<IrqNumber>,
+}
+
+impl Debug for InnerVirtIO9pDevice {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("InnerVirtIO9pDevice").finish()
+    }
+}
+
+impl VirtIODevice for VirtIO9pDevice {
+    fn irq(&self) -> Option<IrqNumber> {
+        self.inner().irq
+    }
+
+    fn handle_irq(&self, _irq: IrqNumber) -> Result<IrqReturn, SystemError> {
+        if self.inner().transport.ack_interrupt() {
+            self.completion.complete();
+            Ok(IrqReturn::Handled)
+        } else {
+            Ok(IrqReturn::NotHandled)
+        }
+    }
+
+    fn dev_id(&self) -> &Arc<DeviceId> {
+        &self.dev_id
+    }
+
+    fn set_device_name(&self, name: String) {
+        self.inner().name = Some(name);
+    }
+
+    fn device_name(&self) -> String {
+        self.inner()
+            .name
+            .clone()
+            .unwrap_or_else(|| VIRTIO_9P_BASENAME.to_string())
+    }
+
+    fn set_virtio_device_index(&self, index: VirtIODeviceIndex) {
+        self.inner().virtio_index = Some(index);
+    }
+
+    fn virtio_device_index(&self) -> Option<VirtIODeviceIndex> {
+        self.inner().virtio_index
+    }
+
+    fn device_type_id(&self) -> u32 {
+        virtio_drivers::transport::DeviceType::_9P as u32
+    }
+
+    fn vendor(&self) -> u32 {
+        VIRTIO_VENDOR_ID.into()
+    }
+}
+
+impl Device for VirtIO9pDevice {
+    fn dev_type(&self) -> DeviceType {
+        DeviceType::Char
+    }
+
+    fn id_table(&self) -> IdTable {
+        IdTable::new(VIRTIO_9P_BASENAME.to_string(), None)
+    }
+
+    fn bus(&self) -> Option<Weak<dyn Bus>> {
+        self.inner().device_common.bus.clone()
+    }
+
+    fn set_bus(&self, bus: Option<Weak<dyn Bus>>) {
+        self.inner().device_common.bus = bus;
+    }
+
+    fn class(&self) -> Option<Arc<dyn Class>> {
+        let mut guard = self.inner();
+        let r = guard.device_common.class.clone()?.upgrade();
+        if r.is_none() {
+            guard.device_common.class = None;
+        }
+
+        return r;
+    }
+
+    fn set_class(&self, class: Option<Weak<dyn Class>>) {
+        self.inner().device_common.class = class;
+    }
+
+    fn driver(&self) -> Option<Arc<dyn Driver>> {
+        let r = self.inner().device_common.driver.clone()?.upgrade();
+        if r.is_none() {
+            self.inner().device_common.driver = None;
+        }
+
+        return r;
+    }
+
+    fn set_driver(&self, driver: Option<Weak<dyn Driver>>) {
+        self.inner().device_common.driver = driver;
+    }
+
+    fn is_dead(&self) -> bool {
+        false
+    }
+
+    fn can_match(&self) -> bool {
+        self.inner().device_common.can_match
+    }
+
+    fn set_can_match(&self, can_match: bool) {
+        self.inner().device_common.can_match = can_match;
+    }
+
+    fn state_synced(&self) -> bool {
+        true
+    }
+
+    fn dev_parent(&self) -> Option<Weak<dyn Device>> {
+        self.inner().device_common.get_parent_weak_or_clear()
+    }
+
+    fn set_dev_parent(&self, parent: Option<Weak<dyn Device>>) {
+        self.inner().device_common.parent = parent;
+    }
+}
+
+impl KObject for VirtIO9pDevice {
+    fn as_any_ref(&self) -> &dyn Any {
+        self
+    }
+
+    fn set_inode(&self, inode: Option<Arc<KernFSInode>>) {
+        self.inner().kobject_common.kern_inode = inode;
+    }
+
+    fn inode(&self) -> Option<Arc<KernFSInode>> {
+        self.inner().kobject_common.kern_inode.clone()
+    }
+
+    fn parent(&self) -> Option<Weak<dyn KObject>> {
+        self.inner().kobject_common.parent.clone()
+    }
+
+    fn set_parent(&self, parent: Option<Weak<dyn KObject>>) {
+        self.inner().kobject_common.parent = parent;
+    }
+
+    fn kset(&self) -> Option<Arc<KSet>> {
+        self.inner().kobject_common.kset.clone()
+    }
+
+    fn set_kset(&self, kset: Option<Arc<KSet>>) {
+        self.inner().kobject_common.kset = kset;
+    }
+
+    fn kobj_type(&self) -> Option<&'static dyn KObjType> {
+        self.inner().kobject_common.kobj_type
+    }
+
+    fn name(&self) -> String {
+        self.device_name()
+    }
+
+    fn set_name(&self, _name: String) {
+        // do nothing
+    }
+
+    fn kobj_state(&self) -> RwLockReadGuard<KObjectState> {
+        self.locked_kobj_state.read()
+    }
+
+    fn kobj_state_mut(&self) -> RwLockWriteGuard<KObjectState> {
+        self.locked_kobj_state.write()
+    }
+
+    fn set_kobj_state(&self, state: KObjectState) {
+        *self.locked_kobj_state.write() = state;
+    }
+
+    fn set_kobj_type(&self, ktype: Option<&'static dyn KObjType>) {
+        self.inner().kobject_common.kobj_type = ktype;
+    }
+}
+
+#[unified_init(INITCALL_POSTCORE)]
+fn virtio_9p_driver_init() -> Result<(), SystemError> {
+    let driver = VirtIO9pDriver::new();
+    virtio_driver_manager()
+        .register(driver.clone() as Arc<dyn VirtIODriver>)
+        .expect("Add virtio 9p driver failed");
+    VIRTIO_9P_DRIVER.init(driver);
+
+    return Ok(());
+}
+
+#[derive(Debug)]
+#[cast_to([sync] VirtIODriver)]
+#[cast_to([sync] Driver)]
+struct VirtIO9pDriver {
+    inner: SpinLock<InnerVirtIO9pDriver>,
+    kobj_state: LockedKObjectState,
+}
+
+impl VirtIO9pDriver {
+    pub fn new() -> Arc<Self> {
+        let inner = InnerVirtIO9pDriver {
+            virtio_driver_common: VirtIODriverCommonData::default(),
+            driver_common: DriverCommonData::default(),
+            kobj_common: KObjectCommonData::default(),
+        };
+
+        let id_table = VirtioDeviceId::new(
+            virtio_drivers::transport::DeviceType::_9P as u32,
+            VIRTIO_VENDOR_ID.into(),
+        );
+        let result = VirtIO9pDriver {
+            inner: SpinLock::new(inner),
+            kobj_state: LockedKObjectState::default(),
+        };
+        result.add_virtio_id(id_table);
+
+        return Arc::new(result);
+    }
+
+    fn inner(&self) -> SpinLockGuard<InnerVirtIO9pDriver> {
+        return self.inner.lock();
+    }
+}
+
+#[derive(Debug)]
+struct InnerVirtIO9pDriver {
+    virtio_driver_common: VirtIODriverCommonData,
+    driver_common: DriverCommonData,
+    kobj_common: KObjectCommonData,
+}
+
+impl VirtIODriver for VirtIO9pDriver {
+    fn probe(&self, device: &Arc<dyn VirtIODevice>) -> Result<(), SystemError> {
+        let _dev = device
+            .clone()
+            .arc_any()
+            .downcast::<VirtIO9pDevice>()
+            .map_err(|_| {
+                error!(
+                "VirtIO9pDriver::probe() failed: device is not a VirtIO 9p device. Device: '{:?}'",
+                device.name()
+            );
+                SystemError::EINVAL
+            })?;
+
+        return Ok(());
+    }
+
+    fn virtio_id_table(&self) -> LinkedList<VirtioDeviceId> {
+        self.inner().virtio_driver_common.id_table.clone()
+    }
+
+    fn add_virtio_id(&self, id: VirtioDeviceId) {
+        self.inner().virtio_driver_common.id_table.push_back(id);
+    }
+}
+
+impl Driver for VirtIO9pDriver {
+    fn id_table(&self) -> Option<IdTable> {
+        Some(IdTable::new(VIRTIO_9P_BASENAME.to_string(), None))
+    }
+
+    fn add_device(&self, device: Arc<dyn Device>) {
+        let iface = device
+            .arc_any()
+            .downcast::<VirtIO9pDevice>()
+            .expect("VirtIO9pDriver::add_device() failed: device is not a VirtIO9pDevice");
+
+        self.inner()
+            .driver_common
+            .devices
+            .push(iface as Arc<dyn Device>);
+    }
+
+    fn delete_device(&self, device: &Arc<dyn Device>) {
+        let _iface = device
+            .clone()
+            .arc_any()
+            .downcast::<VirtIO9pDevice>()
+            .expect("VirtIO9pDriver::delete_device() failed: device is not a VirtIO9pDevice");
+
+        let mut guard = self.inner();
+        let index = guard
+            .driver_common
+            .devices
+            .iter()
+            .position(|dev| Arc::ptr_eq(device, dev))
+            .expect("VirtIO9pDriver::delete_device() failed: device not found");
+
+        guard.driver_common.devices.remove(index);
+    }
+
+    fn devices(&self) -> Vec<Arc<dyn Device>> {
+        self.inner().driver_common.devices.clone()
+    }
+
+    fn bus(&self) -> Option<Weak<dyn Bus>> {
+        Some(Arc::downgrade(&virtio_bus()) as Weak<dyn Bus>)
+    }
+
+    fn set_bus(&self, _bus: Option<Weak<dyn Bus>>) {
+        // do nothing
+    }
+}
+
+impl KObject for VirtIO9pDriver {
+    fn as_any_ref(&self) -> &dyn Any {
+        self
+    }
+
+    fn set_inode(&self, inode: Option<Arc<KernFSInode>>) {
+        self.inner().kobj_common.kern_inode = inode;
+    }
+
+    fn inode(&self) -> Option<Arc<KernFSInode>> {
+        self.inner().kobj_common.kern_inode.clone()
+    }
+
+    fn parent(&self) -> Option<Weak<dyn KObject>> {
+        self.inner().kobj_common.parent.clone()
+    }
+
+    fn set_parent(&self, parent: Option<Weak<dyn KObject>>) {
+        self.inner().kobj_common.parent = parent;
+    }
+
+    fn kset(&self) -> Option<Arc<KSet>> {
+        self.inner().kobj_common.kset.clone()
+    }
+
+    fn set_kset(&self, kset: Option<Arc<KSet>>) {
+        self.inner().kobj_common.kset = kset;
+    }
+
+    fn kobj_type(&self) -> Option<&'static dyn KObjType> {
+        self.inner().kobj_common.kobj_type
+    }
+
+    fn set_kobj_type(&self, ktype: Option<&'static dyn KObjType>) {
+        self.inner().kobj_common.kobj_type = ktype;
+    }
+
+    fn name(&self) -> String {
+        VIRTIO_9P_BASENAME.to_string()
+    }
+
+    fn set_name(&self, _name: String) {
+        // do nothing
+    }
+
+    fn kobj_state(&self) -> RwLockReadGuard<KObjectState> {
+        self.kobj_state.read()
+    }
+
+    fn kobj_state_mut(&self) -> RwLockWriteGuard<KObjectState> {
+        self.kobj_state.write()
+    }
+
+    fn set_kobj_state(&self, state: KObjectState) {
+        *self.kobj_state.write() = state;
+    }
+}
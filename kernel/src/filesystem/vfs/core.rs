@@ -1,33 +1,50 @@
-use core::{hint::spin_loop, sync::atomic::Ordering};
+use core::sync::atomic::Ordering;
 
-use alloc::sync::Arc;
-use log::{error, info};
+use alloc::{
+    string::{String, ToString},
+    sync::Arc,
+};
+use log::info;
 use system_error::SystemError;
 
+use crate::producefs;
+
 use crate::{
     driver::base::block::manager::block_dev_manager,
     filesystem::{
         devfs::devfs_init,
-        fat::fs::FATFileSystem,
+        inotify::{fsnotify_create, fsnotify_delete},
         procfs::procfs_init,
         ramfs::RamFS,
         sysfs::sysfs_init,
-        vfs::{mount::MountFS, syscall::ModeType, AtomicInodeId, FileSystem, FileType},
+        vfs::{
+            mount::MountFS, syscall::ModeType, AtomicInodeId, FileSystem, FileSystemMakerData,
+            FileType, FSMAKER,
+        },
     },
+    init::boot_params,
+    libs::{casting::DowncastArc, rwlock::RwLock},
     process::ProcessManager,
 };
 
 use super::{
     fcntl::AtFlags,
     file::FileMode,
-    mount::{init_mountlist, MOUNT_LIST},
+    mount::{init_mountlist, set_init_mount_list, MountFSInode, MountList, MOUNT_LIST},
     syscall::UmountFlag,
     utils::{rsplit_path, user_path_at},
     IndexNode, InodeId, VFS_MAX_FOLLOW_SYMLINK_TIMES,
 };
 
 /// 当没有指定根文件系统时，尝试的根文件系统列表
-const ROOTFS_TRY_LIST: [&str; 4] = ["/dev/sda1", "/dev/sda", "/dev/vda1", "/dev/vda"];
+const ROOTFS_TRY_LIST: [&str; 6] = [
+    "/dev/sda1",
+    "/dev/sda",
+    "/dev/vda1",
+    "/dev/vda",
+    "/dev/nvme0n1p1",
+    "/dev/nvme0n1",
+];
 
 /// @brief 原子地生成新的Inode号。
 /// 请注意，所有的inode号都需要通过该函数来生成.全局的inode号，除了以下两个特殊的以外，都是唯一的
@@ -39,15 +56,36 @@ pub fn generate_inode_id() -> InodeId {
     return INO.fetch_add(InodeId::new(1), Ordering::SeqCst);
 }
 
-static mut __ROOT_INODE: Option<Arc<dyn IndexNode>> = None;
+/// init mount namespace的根节点，迁移根文件系统以及pivot_root时会被替换
+static INIT_ROOT_INODE: RwLock<Option<Arc<dyn IndexNode>>> = RwLock::new(None);
 
-/// @brief 获取全局的根节点
+/// @brief 获取当前进程所在mount namespace的根节点
+///
+/// 对于init mount namespace（以及进程管理初始化完成之前），返回全局的根节点
 #[inline(always)]
 #[allow(non_snake_case)]
 pub fn ROOT_INODE() -> Arc<dyn IndexNode> {
-    unsafe {
-        return __ROOT_INODE.as_ref().unwrap().clone();
+    if ProcessManager::initialized() {
+        if let Some(root) = ProcessManager::current_pcb().nsproxy().mnt_ns.root_inode() {
+            return root;
+        }
     }
+    init_root_inode()
+}
+
+/// @brief 获取全局的根节点，即init mount namespace的根节点
+#[inline(always)]
+pub fn init_root_inode() -> Arc<dyn IndexNode> {
+    return INIT_ROOT_INODE.read().as_ref().unwrap().clone();
+}
+
+/// 替换init mount namespace的挂载树及其挂载列表
+pub fn set_init_root(root_fs: Arc<MountFS>, mount_list: Arc<MountList>) {
+    let root_inode = root_fs.mountpoint_root_inode();
+    // 持有根节点的写锁，使并发的替换不会让根节点与挂载列表不一致
+    let mut root = INIT_ROOT_INODE.write();
+    *root = Some(root_inode);
+    set_init_mount_list(mount_list);
 }
 
 /// 初始化虚拟文件系统
@@ -58,9 +96,7 @@ pub fn vfs_init() -> Result<(), SystemError> {
     let mount_fs = MountFS::new(ramfs, None);
     let root_inode = mount_fs.root_inode();
     init_mountlist();
-    unsafe {
-        __ROOT_INODE = Some(root_inode.clone());
-    }
+    *INIT_ROOT_INODE.write() = Some(root_inode.clone());
 
     procfs_init().expect("Failed to initialize procfs");
 
@@ -103,53 +139,124 @@ fn migrate_virtual_filesystem(new_fs: Arc<dyn FileSystem>) -> Result<(), SystemE
         .mount_from(ROOT_INODE().find("sys").expect("sys not mounted!"))
         .expect("Failed to migrate filesystem of sys");
 
-    unsafe {
-        // drop旧的Root inode
-        let old_root_inode = __ROOT_INODE.take().unwrap();
-        // 设置全局的新的ROOT Inode
-        __ROOT_INODE = Some(new_root_inode.clone());
-        drop(old_root_inode);
-    }
+    // 设置全局的新的ROOT Inode，并在释放锁之后drop旧的Root inode
+    let old_root_inode = INIT_ROOT_INODE.write().replace(new_root_inode.clone());
+    drop(old_root_inode);
 
     info!("VFS: Migrate filesystems done!");
 
     return Ok(());
 }
 
+/// 挂载根文件系统
+///
+/// 根设备、文件系统类型以及挂载选项分别由内核命令行的`root=`、`rootfstype=`以及`rootflags=`指定。
+/// 未指定`rootfstype=`时，使用vfat；未指定`root=`时，依次尝试`ROOTFS_TRY_LIST`中的块设备。
+///
+/// 如果无法挂载根文件系统，内核将会panic。
+///
+/// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/init/do_mounts.c#395
 pub fn mount_root_fs() -> Result<(), SystemError> {
     info!("Try to mount root fs...");
     block_dev_manager().print_gendisks();
 
-    let gendisk = ROOTFS_TRY_LIST
-        .iter()
-        .find_map(|&path| {
-            if let Some(gd) = block_dev_manager().lookup_gendisk_by_path(path) {
-                info!("Use {} as rootfs", path);
-                return Some(gd);
-            }
-            return None;
-        })
-        .ok_or(SystemError::ENODEV)?;
-
-    let fatfs: Result<Arc<FATFileSystem>, SystemError> = FATFileSystem::new(gendisk);
-    if fatfs.is_err() {
-        error!(
-            "Failed to initialize fatfs, code={:?}",
-            fatfs.as_ref().err()
-        );
-        loop {
-            spin_loop();
+    let (root, fstype, flags) = {
+        let params = boot_params().read();
+        (
+            params.cmdline_param("root").map(String::from),
+            params
+                .cmdline_param("rootfstype")
+                .unwrap_or("vfat")
+                .to_string(),
+            params.cmdline_param("rootflags").map(String::from),
+        )
+    };
+
+    let root = match root {
+        Some(root) => root,
+        None => ROOTFS_TRY_LIST
+            .iter()
+            .find(|&&path| block_dev_manager().lookup_gendisk_by_path(path).is_some())
+            .map(|&path| path.to_string())
+            .unwrap_or_else(|| {
+                panic!("VFS: Unable to mount root fs: no root device found, please specify root=")
+            }),
+    };
+    info!("Use {} as rootfs, type: {}", root, fstype);
+
+    let data = FileSystemMakerData::new(root.clone(), flags);
+    let fstype_str = fstype.as_str();
+    let new_fs = producefs!(FSMAKER, fstype_str, &data).unwrap_or_else(|e| {
+        panic!(
+            "VFS: Unable to mount root fs on {} (rootfstype={}): {:?}",
+            root, fstype, e
+        )
+    });
+
+    migrate_virtual_filesystem(new_fs).unwrap_or_else(|e| {
+        panic!(
+            "VFS: Failed to migrate virtual filesystems to rootfs: {:?}",
+            e
+        )
+    });
+    info!("Successfully migrate rootfs to {}!", fstype);
+
+    return Ok(());
+}
+
+/// # do_pivot_root - 切换当前mount namespace的根文件系统
+///
+/// 将`new_root`所在的挂载作为新的根，原来的根被挂载到`put_old`上
+///
+/// ## 参数
+///
+/// - `new_root`: 新的根，必须是一个挂载点，且不能是当前的根
+/// - `put_old`: 必须位于`new_root`之下（不能与`new_root`相同）的目录
+///
+/// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/fs/namespace.c#4148
+pub fn do_pivot_root(new_root: &str, put_old: &str) -> Result<(), SystemError> {
+    let pcb = ProcessManager::current_pcb();
+    let resolve = |path: &str| -> Result<Arc<MountFSInode>, SystemError> {
+        let (begin, rest) = user_path_at(&pcb, AtFlags::AT_FDCWD.bits(), path)?;
+        let inode = begin.lookup_follow_symlink(&rest, VFS_MAX_FOLLOW_SYMLINK_TIMES)?;
+        if inode.metadata()?.file_type != FileType::Dir {
+            return Err(SystemError::ENOTDIR);
         }
+        inode
+            .downcast_arc::<MountFSInode>()
+            .ok_or(SystemError::EINVAL)
+    };
+    let new_root_inode = resolve(new_root)?;
+    let put_old_inode = resolve(put_old)?;
+
+    let old_root_fs = ROOT_INODE()
+        .fs()
+        .downcast_arc::<MountFS>()
+        .ok_or(SystemError::EINVAL)?;
+    let new_root_fs = new_root_inode
+        .fs()
+        .downcast_arc::<MountFS>()
+        .ok_or(SystemError::EINVAL)?;
+    // 与Linux一致，new_root是当前的根时返回EBUSY
+    if Arc::ptr_eq(&new_root_fs, &old_root_fs) {
+        return Err(SystemError::EBUSY);
     }
-    let fatfs: Arc<FATFileSystem> = fatfs.unwrap();
-    let r = migrate_virtual_filesystem(fatfs);
-    if r.is_err() {
-        error!("Failed to migrate virtual filesystem to FAT32!");
-        loop {
-            spin_loop();
-        }
+    // new_root必须是一个挂载点
+    if !new_root_inode.is_mountpoint_root()? {
+        return Err(SystemError::EINVAL);
     }
-    info!("Successfully migrate rootfs to FAT32!");
+
+    // put_old必须位于new_root之下
+    let new_root_path = new_root_inode.absolute_path()?;
+    let put_old_path = put_old_inode.absolute_path()?;
+    let put_old_rel = put_old_path
+        .strip_prefix(new_root_path.as_str())
+        .filter(|rest| rest.starts_with('/'))
+        .ok_or(SystemError::EINVAL)?;
+
+    let (root_fs, mount_list) =
+        old_root_fs.pivot_root(&MOUNT_LIST(), &new_root_fs, &new_root_path, put_old_rel)?;
+    pcb.nsproxy().mnt_ns.set_root(root_fs, mount_list);
 
     return Ok(());
 }
@@ -168,7 +275,9 @@ pub fn do_mkdir_at(
         current_inode = current_inode.lookup(parent)?;
     }
     // debug!("mkdir at {:?}", current_inode.metadata()?.inode_id);
-    return current_inode.mkdir(name, ModeType::from_bits_truncate(mode.bits()));
+    let inode = current_inode.mkdir(name, ModeType::from_bits_truncate(mode.bits()))?;
+    fsnotify_create(&current_inode, name, &inode);
+    return Ok(inode);
 }
 
 /// @brief 删除文件夹
@@ -200,6 +309,7 @@ pub fn do_remove_dir(dirfd: i32, path: &str) -> Result<u64, SystemError> {
 
     // 删除文件夹
     parent_inode.rmdir(filename)?;
+    fsnotify_delete(&parent_inode, filename, &target_inode);
 
     return Ok(0);
 }
@@ -235,7 +345,9 @@ pub fn do_unlink_at(dirfd: i32, path: &str) -> Result<u64, SystemError> {
     }
 
     // 删除文件
+    let target_inode = parent_inode.find(filename)?;
     parent_inode.unlink(filename)?;
+    fsnotify_delete(&parent_inode, filename, &target_inode);
 
     return Ok(0);
 }
@@ -23,6 +23,12 @@ pub enum FcntlCommand {
     SetLock = 6,
     /// set record locking info (blocking)
     SetLockWait = 7,
+    /// get open file description lock info
+    OfdGetLock = 36,
+    /// set open file description lock (non-blocking)
+    OfdSetLock = 37,
+    /// set open file description lock (blocking)
+    OfdSetLockWait = 38,
 
     SetLease = F_LINUX_SPECIFIC_BASE,
     GetLease = F_LINUX_SPECIFIC_BASE + 1,
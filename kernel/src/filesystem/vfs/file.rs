@@ -9,15 +9,23 @@ use kdepends::xarray::XArray;
 use log::error;
 use system_error::SystemError;
 
-use super::{Dirent, FileType, IndexNode, InodeId, Metadata, SpecialNodeData};
+use super::{
+    locks::{file_locks_release, posix_locks_release},
+    Dirent, FileType, IndexNode, InodeId, Metadata, SpecialNodeData,
+};
 use crate::filesystem::eventfd::EventFdInode;
+use crate::filesystem::inotify::{fsnotify_access, fsnotify_close, fsnotify_modify, InotifyInode};
 use crate::{
     arch::MMArch,
     driver::{
         base::{block::SeekFrom, device::DevicePrivateData},
         tty::tty_device::TtyFilePrivateData,
     },
-    filesystem::procfs::ProcfsFilePrivateData,
+    filesystem::{
+        devfs::random_dev::RandomFilePrivateData,
+        fuse::{dev::FuseDevPrivateData, fs::FuseFilePrivateData},
+        procfs::ProcfsFilePrivateData,
+    },
     ipc::pipe::{LockedPipeInode, PipeFsPrivateData},
     libs::{rwlock::RwLock, spinlock::SpinLock},
     mm::{page::Page, MemoryManagementArch},
@@ -25,7 +33,7 @@ use crate::{
         event_poll::{EPollItem, EPollPrivateData, EventPoll},
         socket::SocketInode,
     },
-    process::{cred::Cred, ProcessManager},
+    process::{cred::Cred, pidfd::PidFdInode, resource::RLimitID, ProcessManager},
 };
 
 /// 文件私有信息的枚举类型
@@ -42,6 +50,12 @@ pub enum FilePrivateData {
     Tty(TtyFilePrivateData),
     /// epoll私有信息
     EPoll(EPollPrivateData),
+    /// /dev/random、/dev/urandom的私有信息
+    Random(RandomFilePrivateData),
+    /// /dev/fuse的私有信息
+    FuseDev(FuseDevPrivateData),
+    /// 已打开的FUSE文件的私有信息
+    Fuse(FuseFilePrivateData),
     /// 不需要文件私有信息
     Unused,
 }
@@ -54,8 +68,11 @@ impl Default for FilePrivateData {
 
 impl FilePrivateData {
     pub fn update_mode(&mut self, mode: FileMode) {
-        if let FilePrivateData::Pipefs(pdata) = self {
-            pdata.set_mode(mode);
+        match self {
+            FilePrivateData::Pipefs(pdata) => pdata.set_mode(mode),
+            FilePrivateData::Random(pdata) => pdata.set_mode(mode),
+            FilePrivateData::FuseDev(pdata) => pdata.set_mode(mode),
+            _ => {}
         }
     }
 }
@@ -312,6 +329,9 @@ impl File {
             self.offset
                 .fetch_add(len, core::sync::atomic::Ordering::SeqCst);
         }
+        if len > 0 {
+            fsnotify_access(&self.inode);
+        }
 
         Ok(len)
     }
@@ -329,6 +349,13 @@ impl File {
             return Err(SystemError::ENOBUFS);
         }
 
+        // 普通文件的大小受RLIMIT_FSIZE限制
+        let len = if self.file_type == FileType::File {
+            ProcessManager::current_pcb().check_rlimit_fsize(offset, len)?
+        } else {
+            len
+        };
+
         // 如果文件指针已经超过了文件大小，则需要扩展文件大小
         if offset > self.inode.metadata()?.size as usize {
             self.inode.resize(offset).map_err(|e| {
@@ -354,6 +381,9 @@ impl File {
             self.offset
                 .fetch_add(len, core::sync::atomic::Ordering::SeqCst);
         }
+        if len > 0 {
+            fsnotify_modify(&self.inode);
+        }
 
         Ok(len)
     }
@@ -401,6 +431,12 @@ impl File {
         return Ok(pos as usize);
     }
 
+    /// 获取文件操作指针的位置
+    #[inline]
+    pub fn pos(&self) -> usize {
+        return self.offset.load(Ordering::SeqCst);
+    }
+
     /// @brief 判断当前文件是否可读
     #[inline]
     pub fn readable(&self) -> Result<(), SystemError> {
@@ -557,9 +593,13 @@ impl File {
     pub fn ftruncate(&self, len: usize) -> Result<(), SystemError> {
         // 如果文件不可写，返回错误
         self.writeable()?;
+        if len > self.inode.metadata()?.size as usize {
+            ProcessManager::current_pcb().check_rlimit_fsize(len, 0)?;
+        }
 
         // 调用inode的truncate方法
         self.inode.resize(len)?;
+        fsnotify_modify(&self.inode);
         return Ok(());
     }
 
@@ -603,6 +643,15 @@ impl File {
                 inode.inner().lock().remove_epoll(epoll)
             }
             _ => {
+                if let FilePrivateData::Tty(tty_priv) = &*self.private_data.lock() {
+                    return tty_priv.tty().core().remove_epitem(epoll);
+                }
+                if let Some(inode) = self.inode.downcast_ref::<PidFdInode>() {
+                    return inode.remove_epoll(epoll);
+                }
+                if let Some(inode) = self.inode.downcast_ref::<InotifyInode>() {
+                    return inode.remove_epoll(epoll);
+                }
                 let inode = self
                     .inode
                     .downcast_ref::<EventFdInode>()
@@ -619,6 +668,8 @@ impl File {
 
 impl Drop for File {
     fn drop(&mut self) {
+        file_locks_release(self);
+        fsnotify_close(&self.inode, self.mode());
         let r: Result<(), SystemError> = self.inode.close(self.private_data.lock());
         // 打印错误信息
         if r.is_err() {
@@ -681,6 +732,12 @@ impl FileDescriptorVec {
         return size;
     }
 
+    /// 当前进程可以使用的文件描述符数量上限（受RLIMIT_NOFILE限制）
+    fn max_fd() -> usize {
+        let limit = ProcessManager::current_pcb().rlimit_cur(RLimitID::Nofile);
+        limit.min(FileDescriptorVec::PROCESS_MAX_FD as u64) as usize
+    }
+
     /// @brief 判断文件描述符序号是否合法
     ///
     /// @return true 合法
@@ -688,7 +745,7 @@ impl FileDescriptorVec {
     /// @return false 不合法
     #[inline]
     pub fn validate_fd(fd: i32) -> bool {
-        return !(fd < 0 || fd as usize > FileDescriptorVec::PROCESS_MAX_FD);
+        return !(fd < 0 || fd as usize >= FileDescriptorVec::PROCESS_MAX_FD);
     }
 
     /// 申请文件描述符，并把文件对象存入其中。
@@ -703,7 +760,11 @@ impl FileDescriptorVec {
     /// - `Ok(i32)` 申请成功，返回申请到的文件描述符
     /// - `Err(SystemError)` 申请失败，返回错误码，并且，file对象将被drop掉
     pub fn alloc_fd(&mut self, file: File, fd: Option<i32>) -> Result<i32, SystemError> {
+        let max_fd = Self::max_fd();
         if let Some(new_fd) = fd {
+            if new_fd < 0 || new_fd as usize >= max_fd {
+                return Err(SystemError::EBADF);
+            }
             let x = &mut self.fds[new_fd as usize];
             if x.is_none() {
                 *x = Some(Arc::new(file));
@@ -713,7 +774,7 @@ impl FileDescriptorVec {
             }
         } else {
             // 没有指定要申请的文件描述符编号
-            for i in 0..FileDescriptorVec::PROCESS_MAX_FD {
+            for i in 0..max_fd {
                 if self.fds[i].is_none() {
                     self.fds[i] = Some(Arc::new(file));
                     return Ok(i as i32);
@@ -745,6 +806,8 @@ impl FileDescriptorVec {
 
         // 把文件描述符数组对应位置设置为空
         let file = self.fds[fd as usize].take().unwrap();
+        // 关闭任意一个文件描述符，都会释放进程在该文件上的所有POSIX记录锁
+        posix_locks_release(&file, ProcessManager::current_pcb().tgid());
 
         return Ok(file);
     }
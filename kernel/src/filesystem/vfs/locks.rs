@@ -0,0 +1,448 @@
+//! 文件的建议锁（advisory lock）
+//!
+//! - POSIX记录锁（fcntl F_SETLK/F_SETLKW）：属于进程（线程组），锁住文件的一个字节范围。
+//!   进程关闭该文件的任意一个文件描述符，或者进程退出时，它在该文件上的所有记录锁都会被释放。
+//! - OFD锁（fcntl F_OFD_SETLK/F_OFD_SETLKW）：与POSIX记录锁语义相同，但属于打开的文件（File），
+//!   在文件被关闭时释放。OFD锁与POSIX记录锁之间会相互冲突。
+//! - flock锁：属于打开的文件，锁住整个文件，与前两种锁相互独立。
+//!
+//! 注意：目前fork时子进程会复制一份新的File，因此子进程不会继承父进程的OFD锁与flock锁。
+//!
+//! 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/fs/locks.c
+
+use alloc::{collections::BTreeMap, sync::Arc, vec::Vec};
+use file_lock::{flock_range, posix_update, FileLockType};
+use system_error::SystemError;
+
+use crate::{
+    libs::{
+        spinlock::{SpinLock, SpinLockGuard},
+        wait_queue::WaitQueue,
+    },
+    process::{Pid, ProcessManager},
+    sched::{schedule, SchedMode},
+};
+
+use super::{
+    file::{File, FileMode},
+    utils::inode_identity,
+};
+
+/// 死锁检测时，沿着等待链最多查找的次数
+const MAX_DEADLK_ITERATIONS: usize = 10;
+
+const SEEK_SET: i16 = 0;
+const SEEK_CUR: i16 = 1;
+const SEEK_END: i16 = 2;
+
+/// 用户空间的`struct flock`
+///
+/// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/include/uapi/asm-generic/fcntl.h#195
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PosixFlock {
+    pub l_type: i16,
+    pub l_whence: i16,
+    pub l_start: i64,
+    pub l_len: i64,
+    pub l_pid: i32,
+}
+
+impl PosixFlock {
+    pub const F_RDLCK: i16 = 0;
+    pub const F_WRLCK: i16 = 1;
+    pub const F_UNLCK: i16 = 2;
+}
+
+bitflags! {
+    /// flock系统调用的操作
+    pub struct FlockOperation: u32 {
+        /// 共享锁
+        const LOCK_SH = 1;
+        /// 排他锁
+        const LOCK_EX = 2;
+        /// 不阻塞
+        const LOCK_NB = 4;
+        /// 解锁
+        const LOCK_UN = 8;
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum LockOwner {
+    /// POSIX记录锁，属于线程组
+    Posix(Pid),
+    /// OFD锁，属于打开的文件
+    Ofd(usize),
+    /// flock锁，属于打开的文件
+    Flock(usize),
+}
+
+type FileLock = file_lock::FileLock<LockOwner>;
+
+/// 一个inode上的所有锁
+#[derive(Debug)]
+struct InodeLocks {
+    /// POSIX记录锁与OFD锁
+    posix: Vec<FileLock>,
+    flock: Vec<FileLock>,
+    /// 等待加锁的进程。等待者在睡眠期间持有它的引用
+    wait_queue: Arc<WaitQueue>,
+}
+
+impl InodeLocks {
+    fn new() -> Self {
+        Self {
+            posix: Vec::new(),
+            flock: Vec::new(),
+            wait_queue: Arc::new(WaitQueue::default()),
+        }
+    }
+
+    fn is_unused(&self) -> bool {
+        self.posix.is_empty() && self.flock.is_empty() && Arc::strong_count(&self.wait_queue) == 1
+    }
+}
+
+#[derive(Debug)]
+struct FileLockTable {
+    /// inode -> 该inode上的锁
+    inodes: BTreeMap<usize, InodeLocks>,
+    /// 因为POSIX记录锁而阻塞的线程：线程的pid -> (线程组的tgid, 持有锁的线程组的tgid)
+    blocked: BTreeMap<Pid, (Pid, Pid)>,
+}
+
+impl FileLockTable {
+    fn inode_locks(&mut self, key: usize) -> &mut InodeLocks {
+        self.inodes.entry(key).or_insert_with(InodeLocks::new)
+    }
+
+    fn put_inode_locks(&mut self, key: usize) {
+        if self.inodes.get(&key).is_some_and(|locks| locks.is_unused()) {
+            self.inodes.remove(&key);
+        }
+    }
+
+    /// 如果线程组`waiter`等待线程组`blocker`，是否会形成死锁
+    fn posix_deadlock(&self, waiter: Pid, blocker: Pid) -> bool {
+        let mut current = blocker;
+        for _ in 0..MAX_DEADLK_ITERATIONS {
+            if current == waiter {
+                return true;
+            }
+            match self.blocked.values().find(|(tgid, _)| *tgid == current) {
+                Some(&(_, next)) => current = next,
+                None => return false,
+            }
+        }
+        return false;
+    }
+}
+
+static FILE_LOCKS: SpinLock<FileLockTable> = SpinLock::new(FileLockTable {
+    inodes: BTreeMap::new(),
+    blocked: BTreeMap::new(),
+});
+
+/// 文件在锁表中的键
+fn lock_key(file: &File) -> usize {
+    inode_identity(&file.inode())
+}
+
+fn file_owner_id(file: &File) -> usize {
+    file as *const File as usize
+}
+
+/// 睡眠，直到锁表发生变化
+///
+/// 调用者必须持有锁表，本函数会在加入等待队列之后释放它，以避免错过唤醒
+fn wait_for_unlock(
+    table: SpinLockGuard<FileLockTable>,
+    wait_queue: Arc<WaitQueue>,
+) -> Result<(), SystemError> {
+    let r = wait_queue.prepare_to_wait_event(true);
+    drop(table);
+    if r.is_ok() {
+        schedule(SchedMode::SM_NONE);
+        wait_queue.finish_wait();
+    }
+    return r;
+}
+
+/// 把`struct flock`中描述的范围转换为[start, end]
+///
+/// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/fs/locks.c#466
+fn flock_to_range(file: &File, flock: &PosixFlock) -> Result<(u64, u64), SystemError> {
+    let base = match flock.l_whence {
+        SEEK_SET => 0,
+        SEEK_CUR => file.pos() as i64,
+        SEEK_END => file.metadata()?.size,
+        _ => return Err(SystemError::EINVAL),
+    };
+    return flock_range(base, flock.l_start, flock.l_len);
+}
+
+fn flock_lock_type(file: &File, l_type: i16) -> Result<Option<FileLockType>, SystemError> {
+    let accmode = file.mode().accmode();
+    match l_type {
+        PosixFlock::F_RDLCK => {
+            if accmode == FileMode::O_WRONLY.bits() {
+                return Err(SystemError::EBADF);
+            }
+            Ok(Some(FileLockType::Read))
+        }
+        PosixFlock::F_WRLCK => {
+            if accmode == FileMode::O_RDONLY.bits() {
+                return Err(SystemError::EBADF);
+            }
+            Ok(Some(FileLockType::Write))
+        }
+        PosixFlock::F_UNLCK => Ok(None),
+        _ => Err(SystemError::EINVAL),
+    }
+}
+
+fn posix_owner(file: &File, ofd: bool) -> LockOwner {
+    if ofd {
+        LockOwner::Ofd(file_owner_id(file))
+    } else {
+        LockOwner::Posix(ProcessManager::current_pcb().tgid())
+    }
+}
+
+/// # 检查能否加上`flock`描述的锁（F_GETLK、F_OFD_GETLK）
+///
+/// 存在冲突的锁时，把它的信息写回`flock`；否则把`flock.l_type`设置为F_UNLCK
+///
+/// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/fs/locks.c#2194
+pub fn posix_test_lock(file: &File, flock: &mut PosixFlock, ofd: bool) -> Result<(), SystemError> {
+    if ofd && flock.l_pid != 0 {
+        return Err(SystemError::EINVAL);
+    }
+    let lock_type = match flock.l_type {
+        PosixFlock::F_RDLCK => FileLockType::Read,
+        PosixFlock::F_WRLCK => FileLockType::Write,
+        _ => return Err(SystemError::EINVAL),
+    };
+    let (start, end) = flock_to_range(file, flock)?;
+    let request = FileLock {
+        owner: posix_owner(file, ofd),
+        lock_type,
+        start,
+        end,
+    };
+
+    let key = lock_key(file);
+    let table = FILE_LOCKS.lock();
+    let conflict = table
+        .inodes
+        .get(&key)
+        .and_then(|locks| locks.posix.iter().find(|l| l.conflicts_with(&request)))
+        .copied();
+    drop(table);
+
+    match conflict {
+        Some(lock) => {
+            flock.l_type = match lock.lock_type {
+                FileLockType::Read => PosixFlock::F_RDLCK,
+                FileLockType::Write => PosixFlock::F_WRLCK,
+            };
+            flock.l_whence = SEEK_SET;
+            flock.l_start = lock.start as i64;
+            flock.l_len = if lock.end == u64::MAX {
+                0
+            } else {
+                (lock.end - lock.start + 1) as i64
+            };
+            // OFD锁不属于任何进程
+            flock.l_pid = match lock.owner {
+                LockOwner::Posix(tgid) => tgid.data() as i32,
+                _ => -1,
+            };
+        }
+        None => flock.l_type = PosixFlock::F_UNLCK,
+    }
+    return Ok(());
+}
+
+/// # 加锁或解锁（F_SETLK、F_SETLKW、F_OFD_SETLK、F_OFD_SETLKW）
+///
+/// ## 参数
+///
+/// - `wait`: 存在冲突的锁时是否等待
+/// - `ofd`: 是否为OFD锁
+///
+/// ## 返回值
+///
+/// - `Err(SystemError::EAGAIN_OR_EWOULDBLOCK)`: 存在冲突的锁，并且不等待
+/// - `Err(SystemError::EDEADLK)`: 等待会形成死锁
+/// - `Err(SystemError::ERESTARTSYS)`: 等待时被信号打断
+///
+/// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/fs/locks.c#2287
+pub fn posix_lock_file(
+    file: &File,
+    flock: &PosixFlock,
+    wait: bool,
+    ofd: bool,
+) -> Result<(), SystemError> {
+    if ofd && flock.l_pid != 0 {
+        return Err(SystemError::EINVAL);
+    }
+    let lock_type = flock_lock_type(file, flock.l_type)?;
+    let (start, end) = flock_to_range(file, flock)?;
+    let owner = posix_owner(file, ofd);
+    let current = ProcessManager::current_pcb();
+    let key = lock_key(file);
+
+    loop {
+        let mut table = FILE_LOCKS.lock();
+        let locks = table.inode_locks(key);
+        let Some(lock_type) = lock_type else {
+            posix_update(&mut locks.posix, owner, None, start, end);
+            locks.wait_queue.wakeup_all(None);
+            table.put_inode_locks(key);
+            return Ok(());
+        };
+
+        let request = FileLock {
+            owner,
+            lock_type,
+            start,
+            end,
+        };
+        let Some(blocker) = locks
+            .posix
+            .iter()
+            .find(|l| l.conflicts_with(&request))
+            .copied()
+        else {
+            posix_update(&mut locks.posix, owner, Some(lock_type), start, end);
+            // 写锁降级为读锁后，可能有等待者能够加锁了
+            locks.wait_queue.wakeup_all(None);
+            return Ok(());
+        };
+
+        if !wait {
+            table.put_inode_locks(key);
+            return Err(SystemError::EAGAIN_OR_EWOULDBLOCK);
+        }
+
+        let wait_queue = locks.wait_queue.clone();
+        if let (LockOwner::Posix(waiter), LockOwner::Posix(holder)) = (owner, blocker.owner) {
+            if table.posix_deadlock(waiter, holder) {
+                drop(wait_queue);
+                table.put_inode_locks(key);
+                return Err(SystemError::EDEADLK);
+            }
+            table.blocked.insert(current.pid(), (waiter, holder));
+        }
+
+        let r = wait_for_unlock(table, wait_queue);
+        let mut table = FILE_LOCKS.lock();
+        table.blocked.remove(&current.pid());
+        table.put_inode_locks(key);
+        drop(table);
+        r?;
+    }
+}
+
+/// # 对整个文件加flock锁或解锁
+///
+/// 转换已有锁的类型时，会先释放原来的锁，因此转换不是原子的（与Linux一致）
+///
+/// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/fs/locks.c#2097
+pub fn flock_lock_file(file: &File, operation: FlockOperation) -> Result<(), SystemError> {
+    let lock_type = match operation - FlockOperation::LOCK_NB {
+        FlockOperation::LOCK_SH => Some(FileLockType::Read),
+        FlockOperation::LOCK_EX => Some(FileLockType::Write),
+        FlockOperation::LOCK_UN => None,
+        _ => return Err(SystemError::EINVAL),
+    };
+    let nonblock = operation.contains(FlockOperation::LOCK_NB);
+    let owner = LockOwner::Flock(file_owner_id(file));
+    let key = lock_key(file);
+
+    loop {
+        let mut table = FILE_LOCKS.lock();
+        let locks = table.inode_locks(key);
+        if let Some(index) = locks.flock.iter().position(|l| l.owner == owner) {
+            let old = locks.flock.remove(index);
+            if Some(old.lock_type) == lock_type {
+                locks.flock.push(old);
+                return Ok(());
+            }
+            locks.wait_queue.wakeup_all(None);
+        }
+
+        let Some(lock_type) = lock_type else {
+            table.put_inode_locks(key);
+            return Ok(());
+        };
+        let request = FileLock {
+            owner,
+            lock_type,
+            start: 0,
+            end: u64::MAX,
+        };
+        if !locks.flock.iter().any(|l| l.conflicts_with(&request)) {
+            locks.flock.push(request);
+            return Ok(());
+        }
+
+        if nonblock {
+            table.put_inode_locks(key);
+            return Err(SystemError::EAGAIN_OR_EWOULDBLOCK);
+        }
+
+        let wait_queue = locks.wait_queue.clone();
+        let r = wait_for_unlock(table, wait_queue);
+        FILE_LOCKS.lock().put_inode_locks(key);
+        r?;
+    }
+}
+
+/// 释放线程组`tgid`在文件所在inode上的所有POSIX记录锁
+///
+/// 进程关闭一个文件描述符时调用
+///
+/// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/fs/locks.c#2576
+pub fn posix_locks_release(file: &File, tgid: Pid) {
+    let mut table = FILE_LOCKS.lock();
+    if table.inodes.is_empty() {
+        return;
+    }
+    let key = lock_key(file);
+    let Some(locks) = table.inodes.get_mut(&key) else {
+        return;
+    };
+    let owner = LockOwner::Posix(tgid);
+    if locks.posix.iter().any(|l| l.owner == owner) {
+        locks.posix.retain(|l| l.owner != owner);
+        locks.wait_queue.wakeup_all(None);
+        table.put_inode_locks(key);
+    }
+}
+
+/// 释放属于一个打开的文件的OFD锁与flock锁
+///
+/// 文件被关闭（File被释放）时调用
+///
+/// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/fs/locks.c#2640
+pub fn file_locks_release(file: &File) {
+    let mut table = FILE_LOCKS.lock();
+    if table.inodes.is_empty() {
+        return;
+    }
+    let key = lock_key(file);
+    let Some(locks) = table.inodes.get_mut(&key) else {
+        return;
+    };
+    let id = file_owner_id(file);
+    let owned = |l: &FileLock| l.owner == LockOwner::Ofd(id) || l.owner == LockOwner::Flock(id);
+    if locks.posix.iter().any(owned) || locks.flock.iter().any(owned) {
+        locks.posix.retain(|l| !owned(l));
+        locks.flock.retain(|l| !owned(l));
+        locks.wait_queue.wakeup_all(None);
+        table.put_inode_locks(key);
+    }
+}
@@ -1,10 +1,13 @@
 pub mod core;
 pub mod fcntl;
 pub mod file;
+pub mod locks;
 pub mod mount;
 pub mod open;
+pub mod splice;
 pub mod syscall;
 pub mod utils;
+pub mod xattr;
 
 use ::core::{any::Any, fmt::Debug, sync::atomic::AtomicUsize};
 use alloc::{string::String, sync::Arc, vec::Vec};
@@ -29,6 +32,7 @@ use self::{
     file::{FileMode, PageCache},
     syscall::ModeType,
     utils::DName,
+    xattr::XattrFlags,
 };
 pub use self::{core::ROOT_INODE, file::FilePrivateData, mount::MountFS};
 
@@ -532,6 +536,37 @@ pub trait IndexNode: Any + Sync + Send + Debug + CastFromSync {
         None
     }
 
+    /// # 获取扩展属性的值
+    ///
+    /// 调用者负责检查命名空间与权限，见[`xattr::vfs_getxattr`]
+    ///
+    /// ## 返回值
+    ///
+    /// - `Err(SystemError::ENODATA)`: 属性不存在
+    /// - `Err(SystemError::EOPNOTSUPP_OR_ENOTSUP)`: 文件系统不支持扩展属性
+    fn getxattr(&self, _name: &str) -> Result<Vec<u8>, SystemError> {
+        return Err(SystemError::EOPNOTSUPP_OR_ENOTSUP);
+    }
+
+    /// # 设置扩展属性的值
+    ///
+    /// ## 参数
+    ///
+    /// - `flags`: XATTR_CREATE时属性必须不存在，XATTR_REPLACE时属性必须已经存在
+    fn setxattr(&self, _name: &str, _value: &[u8], _flags: XattrFlags) -> Result<(), SystemError> {
+        return Err(SystemError::EOPNOTSUPP_OR_ENOTSUP);
+    }
+
+    /// # 列出所有扩展属性的名字
+    fn listxattr(&self) -> Result<Vec<String>, SystemError> {
+        return Err(SystemError::EOPNOTSUPP_OR_ENOTSUP);
+    }
+
+    /// # 删除扩展属性
+    fn removexattr(&self, _name: &str) -> Result<(), SystemError> {
+        return Err(SystemError::EOPNOTSUPP_OR_ENOTSUP);
+    }
+
     /// # dname - 返回目录名
     ///
     /// 此函数用于返回一个目录名。
@@ -563,11 +598,10 @@ pub trait IndexNode: Any + Sync + Send + Debug + CastFromSync {
         return self.find("..");
     }
 
+    /// 获取文件的页缓存，没有使用页缓存的文件系统返回None
+    ///
+    /// splice等路径会对任意文件调用这个方法，因此默认实现不能输出错误日志
     fn page_cache(&self) -> Option<Arc<PageCache>> {
-        log::error!(
-            "function page_cache() has not yet been implemented for inode:{}",
-            crate::libs::name::get_type_name(&self)
-        );
         None
     }
 }
@@ -801,6 +835,10 @@ bitflags! {
         const PROC_MAGIC = 0x9fa0;
         const RAMFS_MAGIC = 0x858458f6;
         const MOUNT_MAGIC = 61267;
+        const V9FS_MAGIC = 0x01021997;
+        const TMPFS_MAGIC = 0x01021994;
+        const OVERLAYFS_MAGIC = 0x794c7630;
+        const FUSE_MAGIC = 0x65735546;
     }
 }
 
@@ -898,12 +936,45 @@ impl FileSystemMaker {
         FileSystemMaker { function, name }
     }
 
-    pub fn call(&self) -> Result<Arc<dyn FileSystem>, SystemError> {
-        (self.function)()
+    pub fn call(&self, data: &FileSystemMakerData) -> Result<Arc<dyn FileSystem>, SystemError> {
+        (self.function)(data)
+    }
+}
+
+/// 创建文件系统时，由mount传入的参数
+#[derive(Debug, Default)]
+pub struct FileSystemMakerData {
+    /// 挂载源（例如块设备路径、9p的挂载标签）
+    source: String,
+    /// 挂载选项（mount的data参数，按字符串解析）
+    options: Option<String>,
+}
+
+impl FileSystemMakerData {
+    pub fn new(source: String, options: Option<String>) -> Self {
+        Self { source, options }
+    }
+
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// 以逗号分隔的挂载选项，形如`key`或`key=value`
+    pub fn options(&self) -> impl Iterator<Item = (&str, Option<&str>)> {
+        self.options
+            .as_deref()
+            .unwrap_or("")
+            .split(',')
+            .filter(|opt| !opt.is_empty())
+            .map(|opt| match opt.split_once('=') {
+                Some((key, value)) => (key, Some(value)),
+                None => (opt, None),
+            })
     }
 }
 
-pub type FileSystemNewFunction = fn() -> Result<Arc<dyn FileSystem>, SystemError>;
+pub type FileSystemNewFunction =
+    fn(data: &FileSystemMakerData) -> Result<Arc<dyn FileSystem>, SystemError>;
 
 #[macro_export]
 macro_rules! define_filesystem_maker_slice {
@@ -919,9 +990,9 @@ macro_rules! define_filesystem_maker_slice {
 /// 调用指定数组中的所有初始化器
 #[macro_export]
 macro_rules! producefs {
-    ($initializer_slice:ident,$filesystem:ident) => {
+    ($initializer_slice:ident,$filesystem:ident,$data:expr) => {
         match $initializer_slice.iter().find(|&m| m.name == $filesystem) {
-            Some(maker) => maker.call(),
+            Some(maker) => maker.call($data),
             None => {
                 log::error!("mismatch filesystem type : {}", $filesystem);
                 Err(SystemError::EINVAL)
@@ -8,6 +8,7 @@ use alloc::{
     collections::BTreeMap,
     string::{String, ToString},
     sync::{Arc, Weak},
+    vec::Vec,
 };
 use system_error::SystemError;
 
@@ -20,17 +21,17 @@ use crate::{
         spinlock::{SpinLock, SpinLockGuard},
     },
     mm::{fault::PageFaultMessage, VmFaultReason},
+    process::ProcessManager,
 };
 
 use super::{
     file::{FileMode, PageCache},
     syscall::ModeType,
     utils::DName,
-    FilePrivateData, FileSystem, FileType, IndexNode, InodeId, Magic, SuperBlock,
+    xattr::XattrFlags,
+    FilePrivateData, FileSystem, FileType, IndexNode, InodeId, SuperBlock,
 };
 
-const MOUNTFS_BLOCK_SIZE: u64 = 512;
-const MOUNTFS_MAX_NAMELEN: u64 = 64;
 /// @brief 挂载文件系统
 /// 挂载文件系统的时候，套了MountFS这一层，以实现文件系统的递归挂载
 #[derive(Debug)]
@@ -107,6 +108,125 @@ impl MountFS {
         self.self_ref.upgrade().unwrap()
     }
 
+    /// 递归地复制以当前MountFS为根的挂载树，用于创建新的mount namespace
+    ///
+    /// 新的挂载树与原挂载树共享底层的文件系统，但此后在其中一棵树上的挂载与卸载不会影响另一棵树。
+    ///
+    /// ## 参数
+    ///
+    /// - `self_mountpoint`: 新的MountFS在新挂载树中的挂载点
+    /// - `mapping`: 用于记录原MountFS与新MountFS的对应关系
+    pub fn copy_tree(
+        &self,
+        self_mountpoint: Option<Arc<MountFSInode>>,
+        mapping: &mut Vec<(Arc<MountFS>, Arc<MountFS>)>,
+    ) -> Arc<MountFS> {
+        let new_fs = MountFS::new(self.inner_filesystem.clone(), self_mountpoint);
+        mapping.push((self.self_ref(), new_fs.clone()));
+
+        let mountpoints = self.mountpoints.lock().clone();
+        for (inode_id, child) in mountpoints {
+            // 子文件系统的挂载点需要指向新的父MountFS
+            let mountpoint = child.self_mountpoint.as_ref().map(|mp| {
+                Arc::new_cyclic(|self_ref| MountFSInode {
+                    inner_inode: mp.inner_inode.clone(),
+                    mount_fs: new_fs.clone(),
+                    self_ref: self_ref.clone(),
+                })
+            });
+            let new_child = child.copy_tree(mountpoint, mapping);
+            new_fs.mountpoints.lock().insert(inode_id, new_child);
+        }
+
+        return new_fs;
+    }
+
+    /// # pivot_root - 以`new_root`为根，生成一棵新的挂载树
+    ///
+    /// 当前的挂载树（即`self`，不含`new_root`所在的挂载）被挂载到新的挂载树中的`put_old`上。
+    /// 原挂载树保持不变，调用者需要把新的挂载树设置为mount namespace的根。
+    ///
+    /// ## 参数
+    ///
+    /// - `old_list`: 当前挂载树的挂载列表
+    /// - `new_root`: 新的根，必须是当前挂载树中的某个挂载
+    /// - `new_root_path`: `new_root`在当前挂载树中的绝对路径
+    /// - `put_old`: 旧的根在新的挂载树中的绝对路径
+    ///
+    /// ## 返回值
+    ///
+    /// - `Ok((Arc<MountFS>, Arc<MountList>))`: 新的挂载树的根，以及新的挂载列表
+    ///
+    /// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/fs/namespace.c#4148
+    pub fn pivot_root(
+        &self,
+        old_list: &MountList,
+        new_root: &Arc<MountFS>,
+        new_root_path: &str,
+        put_old: &str,
+    ) -> Result<(Arc<MountFS>, Arc<MountList>), SystemError> {
+        let mut new_mapping = Vec::new();
+        let new_tree = new_root.copy_tree(None, &mut new_mapping);
+
+        // 绝对路径会从当前进程的根开始查找，因此使用相对路径
+        let put_old_inode = new_tree
+            .mountpoint_root_inode()
+            .lookup(put_old.trim_start_matches('/'))?
+            .downcast_arc::<MountFSInode>()
+            .ok_or(SystemError::EINVAL)?;
+        if put_old_inode.metadata()?.file_type != FileType::Dir {
+            return Err(SystemError::ENOTDIR);
+        }
+        if put_old_inode.is_mountpoint_root()? {
+            return Err(SystemError::EBUSY);
+        }
+
+        let mut old_mapping = Vec::new();
+        let old_tree = self.copy_tree(Some(put_old_inode.clone()), &mut old_mapping);
+        // new_root已经成为新的根，需要把它从旧的挂载树中移除
+        if let Some((_, moved)) = old_mapping
+            .iter()
+            .find(|(old, _)| Arc::ptr_eq(old, new_root))
+        {
+            moved.umount()?;
+        }
+        put_old_inode
+            .mount_fs
+            .mountpoints
+            .lock()
+            .insert(put_old_inode.metadata()?.inode_id, old_tree.clone());
+
+        // 位于new_root之下的挂载点去掉new_root的路径前缀，其余的挂载点加上put_old的路径前缀
+        let map_fs = |mapping: &[(Arc<MountFS>, Arc<MountFS>)], fs: &Arc<MountFS>| {
+            mapping
+                .iter()
+                .find(|(old, _)| Arc::ptr_eq(old, fs))
+                .map(|(_, new)| new.clone())
+        };
+        let mut list = BTreeMap::new();
+        for (path, fs) in old_list.0.read().iter() {
+            if Arc::ptr_eq(fs, new_root) || core::ptr::eq(fs.as_ref(), self) {
+                continue;
+            }
+            let path = path.as_ref();
+            match path.strip_prefix(new_root_path) {
+                Some(rest) if rest.starts_with('/') => {
+                    if let Some(new_fs) = map_fs(&new_mapping, fs) {
+                        list.insert(MountPath::from(rest), new_fs);
+                    }
+                }
+                _ => {
+                    if let Some(new_fs) = map_fs(&old_mapping, fs) {
+                        list.insert(MountPath::from(format!("{}{}", put_old, path)), new_fs);
+                    }
+                }
+            }
+        }
+        list.insert(MountPath::from(put_old), old_tree);
+
+        return Ok((new_tree, Arc::new(MountList(RwLock::new(list)))));
+    }
+
     /// 卸载文件系统
     /// # Errors
     /// 如果当前文件系统是根文件系统，那么将会返回`EINVAL`
@@ -142,8 +262,29 @@ impl MountFSInode {
         }
     }
 
+    /// 当前inode在具体文件系统中对应的inode
+    pub fn inner_inode(&self) -> Arc<dyn IndexNode> {
+        return self.inner_inode.clone();
+    }
+
+    /// @brief 取出同一个挂载中的另一个MountFSInode包裹的inode，供link、move_to传给内层文件系统
+    ///
+    /// 两个inode不在同一个挂载中时返回EXDEV
+    fn same_mount_inner(
+        &self,
+        other: &Arc<dyn IndexNode>,
+    ) -> Result<Arc<dyn IndexNode>, SystemError> {
+        match other.clone().downcast_arc::<MountFSInode>() {
+            Some(other) if Arc::ptr_eq(&other.mount_fs, &self.mount_fs) => {
+                Ok(other.inner_inode.clone())
+            }
+            Some(_) => Err(SystemError::EXDEV),
+            None => Ok(other.clone()),
+        }
+    }
+
     /// @brief 判断当前inode是否为它所在的文件系统的root inode
-    fn is_mountpoint_root(&self) -> Result<bool, SystemError> {
+    pub(super) fn is_mountpoint_root(&self) -> Result<bool, SystemError> {
         return Ok(self.inner_inode.fs().root_inode().metadata()?.inode_id
             == self.inner_inode.metadata()?.inode_id);
     }
@@ -319,7 +460,8 @@ impl IndexNode for MountFSInode {
     }
 
     fn link(&self, name: &str, other: &Arc<dyn IndexNode>) -> Result<(), SystemError> {
-        return self.inner_inode.link(name, other);
+        let other = self.same_mount_inner(other)?;
+        return self.inner_inode.link(name, &other);
     }
 
     /// @brief 在挂载文件系统中删除文件/文件夹
@@ -356,7 +498,8 @@ impl IndexNode for MountFSInode {
         target: &Arc<dyn IndexNode>,
         new_name: &str,
     ) -> Result<(), SystemError> {
-        return self.inner_inode.move_to(old_name, target, new_name);
+        let target = self.same_mount_inner(target)?;
+        return self.inner_inode.move_to(old_name, &target, new_name);
     }
 
     fn find(&self, name: &str) -> Result<Arc<dyn IndexNode>, SystemError> {
@@ -483,6 +626,22 @@ impl IndexNode for MountFSInode {
         self.inner_inode.special_node()
     }
 
+    fn getxattr(&self, name: &str) -> Result<Vec<u8>, SystemError> {
+        self.inner_inode.getxattr(name)
+    }
+
+    fn setxattr(&self, name: &str, value: &[u8], flags: XattrFlags) -> Result<(), SystemError> {
+        self.inner_inode.setxattr(name, value, flags)
+    }
+
+    fn listxattr(&self) -> Result<Vec<String>, SystemError> {
+        self.inner_inode.listxattr()
+    }
+
+    fn removexattr(&self, name: &str) -> Result<(), SystemError> {
+        self.inner_inode.removexattr(name)
+    }
+
     #[inline]
     fn poll(&self, private_data: &FilePrivateData) -> Result<usize, SystemError> {
         self.inner_inode.poll(private_data)
@@ -532,8 +691,9 @@ impl FileSystem for MountFS {
     fn name(&self) -> &str {
         "mountfs"
     }
+    /// statfs报告的是被挂载的文件系统的信息
     fn super_block(&self) -> SuperBlock {
-        SuperBlock::new(Magic::MOUNT_MAGIC, MOUNTFS_BLOCK_SIZE, MOUNTFS_MAX_NAMELEN)
+        self.inner_filesystem.super_block()
     }
 
     unsafe fn fault(&self, pfm: &mut PageFaultMessage) -> VmFaultReason {
@@ -559,7 +719,7 @@ impl FileSystem for MountFS {
 /// assert_eq!(format!("{:?}", map), "{\"/\", \"/bin\", \"/dev\", \"/proc\", \"/sys\"}");
 /// // {"/", "/bin", "/dev", "/proc", "/sys"}
 /// ```
-#[derive(PartialEq, Eq, Debug)]
+#[derive(PartialEq, Eq, Debug, Clone)]
 pub struct MountPath(String);
 
 impl From<&str> for MountPath {
@@ -604,7 +764,8 @@ impl Ord for MountPath {
 // 维护一个挂载点的记录，以支持特定于文件系统的索引
 pub struct MountList(RwLock<BTreeMap<MountPath, Arc<MountFS>>>);
 // pub struct MountList(Option<Arc<MountListInner>>);
-static mut __MOUNTS_LIST: Option<Arc<MountList>> = None;
+/// init mount namespace的挂载列表，pivot_root时会被替换
+static INIT_MOUNT_LIST: RwLock<Option<Arc<MountList>>> = RwLock::new(None);
 
 /// # init_mountlist - 初始化挂载列表
 ///
@@ -619,23 +780,36 @@ static mut __MOUNTS_LIST: Option<Arc<MountList>> = None;
 /// - 无
 #[inline(always)]
 pub fn init_mountlist() {
-    unsafe {
-        __MOUNTS_LIST = Some(Arc::new(MountList(RwLock::new(BTreeMap::new()))));
-    }
+    *INIT_MOUNT_LIST.write() = Some(Arc::new(MountList(RwLock::new(BTreeMap::new()))));
 }
 
-/// # MOUNT_LIST - 获取全局挂载列表
+/// # MOUNT_LIST - 获取当前挂载列表
 ///
-/// 该函数用于获取一个对全局挂载列表的引用。全局挂载列表是系统中所有挂载点的集合。
+/// 该函数用于获取当前进程所在mount namespace的挂载列表。
+/// 对于init mount namespace（以及进程管理初始化完成之前），返回全局挂载列表。
 ///
 /// ## 返回值
-/// - &'static Arc<MountList>: 返回全局挂载列表的引用。
+/// - Arc<MountList>: 返回挂载列表的引用。
 #[inline(always)]
 #[allow(non_snake_case)]
-pub fn MOUNT_LIST() -> &'static Arc<MountList> {
-    unsafe {
-        return __MOUNTS_LIST.as_ref().unwrap();
+pub fn MOUNT_LIST() -> Arc<MountList> {
+    if ProcessManager::initialized() {
+        if let Some(list) = ProcessManager::current_pcb().nsproxy().mnt_ns.mount_list() {
+            return list;
+        }
     }
+    init_mount_list()
+}
+
+/// 获取init mount namespace的挂载列表
+#[inline(always)]
+pub fn init_mount_list() -> Arc<MountList> {
+    return INIT_MOUNT_LIST.read().as_ref().unwrap().clone();
+}
+
+/// 替换init mount namespace的挂载列表
+pub(super) fn set_init_mount_list(list: Arc<MountList>) {
+    *INIT_MOUNT_LIST.write() = Some(list);
 }
 
 impl MountList {
@@ -707,10 +881,29 @@ impl MountList {
     pub fn remove<T: Into<MountPath>>(&self, path: T) -> Option<Arc<MountFS>> {
         self.0.write().remove(&path.into())
     }
+
+    /// # copy_with - 为复制得到的挂载树生成挂载列表
+    ///
+    /// ## 参数
+    ///
+    /// - `mapping`: 原MountFS与新MountFS的对应关系，由`MountFS::copy_tree`生成
+    ///
+    /// ## 返回值
+    ///
+    /// - `Arc<MountList>`: 新的挂载列表，其中的挂载点均指向新挂载树中的MountFS
+    pub fn copy_with(&self, mapping: &[(Arc<MountFS>, Arc<MountFS>)]) -> Arc<MountList> {
+        let mut list = BTreeMap::new();
+        for (path, fs) in self.0.read().iter() {
+            if let Some((_, new_fs)) = mapping.iter().find(|(old, _)| Arc::ptr_eq(old, fs)) {
+                list.insert(path.clone(), new_fs.clone());
+            }
+        }
+        return Arc::new(MountList(RwLock::new(list)));
+    }
 }
 
 impl Debug for MountList {
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
-        f.debug_map().entries(MOUNT_LIST().0.read().iter()).finish()
+        f.debug_map().entries(self.0.read().iter()).finish()
     }
 }
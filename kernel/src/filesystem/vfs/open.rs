@@ -6,13 +6,18 @@ use super::{
     fcntl::AtFlags,
     file::{File, FileMode},
     syscall::{ModeType, OpenHow, OpenHowResolve},
-    utils::{rsplit_path, user_path_at},
-    FileType, IndexNode, MAX_PATHLEN, ROOT_INODE, VFS_MAX_FOLLOW_SYMLINK_TIMES,
+    utils::{generic_permission, rsplit_path, user_path_at, MAY_EXEC, MAY_WRITE},
+    FileType, IndexNode, MAX_PATHLEN, VFS_MAX_FOLLOW_SYMLINK_TIMES,
 };
 use crate::filesystem::vfs::syscall::UtimensFlags;
 use crate::time::{syscall::PosixTimeval, PosixTimeSpec};
 use crate::{
-    driver::base::block::SeekFrom, process::ProcessManager,
+    driver::base::block::SeekFrom,
+    filesystem::inotify::{fsnotify_attrib, fsnotify_create, fsnotify_open},
+    process::{
+        cred::{CAPFlags, Kgid},
+        ProcessControlBlock, ProcessManager,
+    },
     syscall::user_access::check_and_clone_cstr,
 };
 use alloc::string::String;
@@ -64,6 +69,101 @@ pub fn do_fchmodat(dirfd: i32, path: *const u8, _mode: ModeType) -> Result<usize
     return Ok(0);
 }
 
+/// 修改文件的属主与属组
+///
+/// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/fs/open.c#754
+pub fn do_fchownat(
+    dirfd: i32,
+    path: *const u8,
+    uid: usize,
+    gid: usize,
+    flags: i32,
+) -> Result<usize, SystemError> {
+    let flags = AtFlags::from_bits(flags).ok_or(SystemError::EINVAL)?;
+    if !(AtFlags::AT_SYMLINK_NOFOLLOW | AtFlags::AT_EMPTY_PATH).contains(flags) {
+        return Err(SystemError::EINVAL);
+    }
+
+    let path = check_and_clone_cstr(path, Some(MAX_PATHLEN))?;
+    let path = path.to_str().map_err(|_| SystemError::EINVAL)?;
+    if path.is_empty() && flags.contains(AtFlags::AT_EMPTY_PATH) {
+        return do_fchown(dirfd, uid, gid);
+    }
+
+    let (inode_begin, path) = user_path_at(&ProcessManager::current_pcb(), dirfd, path)?;
+    let inode = if flags.contains(AtFlags::AT_SYMLINK_NOFOLLOW) {
+        inode_begin.lookup(path.as_str())?
+    } else {
+        inode_begin.lookup_follow_symlink(path.as_str(), VFS_MAX_FOLLOW_SYMLINK_TIMES)?
+    };
+    return chown_common(&inode, uid, gid);
+}
+
+pub fn do_fchown(fd: i32, uid: usize, gid: usize) -> Result<usize, SystemError> {
+    let inode = ProcessManager::current_pcb()
+        .fd_table()
+        .read()
+        .get_file_by_fd(fd)
+        .ok_or(SystemError::EBADF)?
+        .inode();
+    return chown_common(&inode, uid, gid);
+}
+
+/// 修改inode的属主与属组，uid或gid为-1时表示不修改
+///
+///
+/// - 只有拥有CAP_CHOWN的进程才能修改文件的属主
+/// - 文件的属主可以将属组修改为自己所在的组，其它情况需要拥有CAP_CHOWN
+///
+/// 修改成功后，非目录文件的set-user-ID位与set-group-ID位会被清除
+///
+/// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/fs/attr.c#24
+fn chown_common(inode: &Arc<dyn IndexNode>, uid: usize, gid: usize) -> Result<usize, SystemError> {
+    let uid = Some(uid as u32).filter(|id| *id != u32::MAX);
+    let gid = Some(gid as u32).filter(|id| *id != u32::MAX);
+    let mut meta = inode.metadata()?;
+    let cred = ProcessManager::current_pcb().cred();
+    let privileged = cred.has_capability(CAPFlags::CAP_CHOWN);
+    let is_owner = cred.fsuid.data() == meta.uid;
+
+    if let Some(uid) = uid {
+        let allowed = is_owner && uid as usize == meta.uid;
+        if !allowed && !privileged {
+            return Err(SystemError::EPERM);
+        }
+    }
+    if let Some(gid) = gid {
+        let kgid = Kgid::new(gid as usize);
+        let in_group = cred.fsgid == kgid
+            || cred
+                .group_info
+                .as_ref()
+                .is_some_and(|groups| groups.gids.contains(&kgid));
+        let allowed = is_owner && (gid as usize == meta.gid || in_group);
+        if !allowed && !privileged {
+            return Err(SystemError::EPERM);
+        }
+    }
+
+    if let Some(uid) = uid {
+        meta.uid = uid as usize;
+    }
+    if let Some(gid) = gid {
+        meta.gid = gid as usize;
+    }
+    if meta.file_type != FileType::Dir {
+        meta.mode.remove(ModeType::S_ISUID);
+        if meta.mode.contains(ModeType::S_IXGRP) {
+            meta.mode.remove(ModeType::S_ISGID);
+        }
+    }
+    meta.ctime = PosixTimeSpec::now();
+    inode.set_metadata(&meta)?;
+    fsnotify_attrib(inode);
+
+    return Ok(0);
+}
+
 pub(super) fn do_sys_open(
     dfd: i32,
     path: &str,
@@ -82,19 +182,62 @@ fn do_sys_openat2(
     follow_symlink: bool,
 ) -> Result<usize, SystemError> {
     // debug!("open path: {}, how: {:?}", path, how);
+    let pcb = ProcessManager::current_pcb();
+    let file = do_filp_open(&pcb, dirfd, path, &how, follow_symlink)?;
+    fsnotify_open(&file.inode());
+
+    // 把文件对象存入pcb
+    let r = pcb
+        .fd_table()
+        .write()
+        .alloc_fd(file, None)
+        .map(|fd| fd as usize);
+
+    return r;
+}
+
+/// 以`pcb`的身份打开文件，必要时创建它
+///
+/// - 创建文件时需要对父目录有写与搜索权限，新文件属于`pcb`的fsuid与fsgid，权限为`how.mode`
+/// - 指定`O_CREAT | O_EXCL`时，文件（包括指向任何位置的符号链接）已经存在则返回EEXIST
+/// - 指定`O_NOFOLLOW`时，路径的最后一级是符号链接则返回ELOOP
+///
+/// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/fs/namei.c#3568
+pub fn do_filp_open(
+    pcb: &Arc<ProcessControlBlock>,
+    dirfd: i32,
+    path: &str,
+    how: &OpenHow,
+    follow_symlink: bool,
+) -> Result<File, SystemError> {
     let path = path.trim();
+    let (inode_begin, path) = user_path_at(pcb, dirfd, path)?;
+    let excl = how.o_flags.contains(FileMode::O_CREAT | FileMode::O_EXCL);
+    let follow = follow_symlink && !excl && !how.o_flags.contains(FileMode::O_NOFOLLOW);
 
-    let (inode_begin, path) = user_path_at(&ProcessManager::current_pcb(), dirfd, path)?;
-    let inode: Result<Arc<dyn IndexNode>, SystemError> = inode_begin.lookup_follow_symlink(
-        &path,
-        if follow_symlink {
-            VFS_MAX_FOLLOW_SYMLINK_TIMES
-        } else {
-            0
-        },
-    );
+    let (filename, parent_path) = rsplit_path(&path);
+    // 查找父目录
+    let parent_inode: Arc<dyn IndexNode> = match parent_path {
+        Some(parent_path) => {
+            inode_begin.lookup_follow_symlink(parent_path, VFS_MAX_FOLLOW_SYMLINK_TIMES)?
+        }
+        None => inode_begin,
+    };
+    let inode = if filename.is_empty() {
+        Ok(parent_inode.clone())
+    } else {
+        parent_inode.lookup_follow_symlink(
+            filename,
+            if follow {
+                VFS_MAX_FOLLOW_SYMLINK_TIMES
+            } else {
+                0
+            },
+        )
+    };
 
     let inode: Arc<dyn IndexNode> = match inode {
+        Ok(_) if excl => return Err(SystemError::EEXIST),
         Ok(inode) => inode,
         Err(errno) => {
             // 文件不存在，且需要创建
@@ -102,16 +245,21 @@ fn do_sys_openat2(
                 && !how.o_flags.contains(FileMode::O_DIRECTORY)
                 && errno == SystemError::ENOENT
             {
-                let (filename, parent_path) = rsplit_path(&path);
-                // 查找父目录
-                let parent_inode: Arc<dyn IndexNode> =
-                    ROOT_INODE().lookup(parent_path.unwrap_or("/"))?;
+                let cred = pcb.cred();
+                generic_permission(&parent_inode.metadata()?, &cred, MAY_WRITE | MAY_EXEC)?;
                 // 创建文件
                 let inode: Arc<dyn IndexNode> = parent_inode.create(
                     filename,
                     FileType::File,
-                    ModeType::from_bits_truncate(0o755),
+                    how.mode & ModeType::S_IALLUGO,
                 )?;
+                let mut metadata = inode.metadata()?;
+                if metadata.uid != cred.fsuid.data() || metadata.gid != cred.fsgid.data() {
+                    metadata.uid = cred.fsuid.data();
+                    metadata.gid = cred.fsgid.data();
+                    inode.set_metadata(&metadata)?;
+                }
+                fsnotify_create(&parent_inode, filename, &inode);
                 inode
             } else {
                 // 不需要创建文件，因此返回错误码
@@ -121,6 +269,10 @@ fn do_sys_openat2(
     };
 
     let file_type: FileType = inode.metadata()?.file_type;
+    // 不跟随符号链接，而路径的最后一级是符号链接
+    if file_type == FileType::SymLink && how.o_flags.contains(FileMode::O_NOFOLLOW) {
+        return Err(SystemError::ELOOP);
+    }
     // 如果要打开的是文件夹，而目标不是文件夹
     if how.o_flags.contains(FileMode::O_DIRECTORY) && file_type != FileType::Dir {
         return Err(SystemError::ENOTDIR);
@@ -142,14 +294,8 @@ fn do_sys_openat2(
     {
         file.ftruncate(0)?;
     }
-    // 把文件对象存入pcb
-    let r = ProcessManager::current_pcb()
-        .fd_table()
-        .write()
-        .alloc_fd(file, None)
-        .map(|fd| fd as usize);
 
-    return r;
+    return Ok(file);
 }
 
 /// On Linux, futimens() is a library function implemented on top of
@@ -207,6 +353,7 @@ pub fn do_utimensat(
         meta.mtime = now;
         inode.set_metadata(&meta).unwrap();
     }
+    fsnotify_attrib(&inode);
     return Ok(0);
 }
 
@@ -230,5 +377,6 @@ pub fn do_utimes(path: &str, times: Option<[PosixTimeval; 2]>) -> Result<usize,
         meta.mtime = now;
         inode.set_metadata(&meta)?;
     }
+    fsnotify_attrib(&inode);
     return Ok(0);
 }
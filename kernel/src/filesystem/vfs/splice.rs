@@ -0,0 +1,465 @@
+//! 在文件描述符之间直接传输数据：sendfile、splice、tee与copy_file_range
+//!
+//! 数据在内核中直接从源文件传递到目标文件，不需要经过用户空间：
+//! - 源文件使用页缓存（例如FAT、9p、tmpfs）时，页缓存中页面的数据被直接交给目标文件，不经过中转缓冲区
+//! - 管道之间（splice与tee）直接在两个管道的环形缓冲区之间复制
+//! - 只有没有页缓存的文件、不在页缓存中的页面，以及socket等不能重新读取的文件，才经由中转缓冲区复制
+//!
+//! 管道的数据保存在固定大小的环形缓冲区中，而页缓存中的页面没有引用计数来阻止被回收，
+//! 因此不能像Linux一样把页面本身挂到管道上，页面的数据会被复制进管道的环形缓冲区。
+//!
+//! 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/fs/splice.c
+
+use core::mem::size_of;
+
+use alloc::{sync::Arc, vec, vec::Vec};
+use system_error::SystemError;
+
+use crate::{
+    arch::MMArch,
+    driver::base::block::SeekFrom,
+    ipc::pipe::LockedPipeInode,
+    libs::casting::DowncastArc,
+    mm::{page::Page, MemoryManagementArch},
+    process::ProcessManager,
+    syscall::{
+        user_access::{UserBufferReader, UserBufferWriter},
+        Syscall,
+    },
+};
+
+use super::{
+    file::{File, FileMode},
+    utils::inode_identity,
+    FileType,
+};
+
+bitflags! {
+    /// splice与tee的标志
+    pub struct SpliceFlags: u32 {
+        /// 移动页面而不是复制（只是提示，目前被忽略）
+        const SPLICE_F_MOVE = 0x01;
+        /// 不在管道上阻塞
+        const SPLICE_F_NONBLOCK = 0x02;
+        /// 后面还有更多数据要发送（只是提示，目前被忽略）
+        const SPLICE_F_MORE = 0x04;
+        /// 只对vmsplice有意义
+        const SPLICE_F_GIFT = 0x08;
+    }
+}
+
+/// 没有页缓存时，每次经由中转缓冲区复制的最大数据量
+const SPLICE_CHUNK_SIZE: usize = 64 * 1024;
+
+/// 一次调用最多传输的字节数，与read/write的限制相同
+const MAX_RW_COUNT: usize = i32::MAX as usize & !(MMArch::PAGE_SIZE - 1);
+
+fn get_file(fd: i32) -> Result<Arc<File>, SystemError> {
+    return ProcessManager::current_pcb()
+        .fd_table()
+        .read()
+        .get_file_by_fd(fd)
+        .ok_or(SystemError::EBADF);
+}
+
+/// 如果文件是管道，返回管道的inode
+fn file_pipe(file: &File) -> Option<Arc<LockedPipeInode>> {
+    return file.inode().downcast_arc::<LockedPipeInode>();
+}
+
+/// 读取用户传入的文件偏移量，指针为空时返回`None`
+fn read_user_offset(offset: *mut i64) -> Result<Option<usize>, SystemError> {
+    if offset.is_null() {
+        return Ok(None);
+    }
+    let reader = UserBufferReader::new(offset as *const i64, size_of::<i64>(), true)?;
+    let offset = *reader.read_one_from_user::<i64>(0)?;
+    if offset < 0 {
+        return Err(SystemError::EINVAL);
+    }
+    return Ok(Some(offset as usize));
+}
+
+fn write_user_offset(offset: *mut i64, value: usize) -> Result<(), SystemError> {
+    let mut writer = UserBufferWriter::new(offset, size_of::<i64>(), true)?;
+    writer.copy_one_to_user(&(value as i64), 0)?;
+    return Ok(());
+}
+
+/// 传输结束之后更新偏移量：用户传入了偏移量时写回用户空间，否则更新文件的读写位置
+fn update_offset(file: &File, offset: *mut i64, value: usize) -> Result<(), SystemError> {
+    if offset.is_null() {
+        file.lseek(SeekFrom::SeekSet(value as i64))?;
+        return Ok(());
+    }
+    return write_user_offset(offset, value);
+}
+
+/// 页面中的数据
+///
+/// ## Safety
+///
+/// 调用者需要保证页面在使用期间没有被释放
+unsafe fn page_data<'a>(page: &Arc<Page>) -> &'a [u8] {
+    let vaddr = MMArch::phys_2_virt(page.read_irqsave().phys_address()).unwrap();
+    core::slice::from_raw_parts(vaddr.data() as *const u8, MMArch::PAGE_SIZE)
+}
+
+/// # 从文件的`pos`处读出最多`len`字节的数据，交给`sink`写出
+///
+/// 文件使用页缓存时，页缓存中已有的页面直接交给`sink`，不经过中转缓冲区；
+/// 不在页缓存中的页面，以及没有页缓存的文件，才通过read读到中转缓冲区中。
+///
+/// `sink`返回实际写出的字节数，少于交给它的数据量时传输结束。
+/// 因为每次都从确定的偏移量读取，没有写出的数据不会丢失
+///
+/// ## 返回值
+///
+/// - `Ok(usize)`: 传输的字节数。已经传输了部分数据之后发生的错误会被忽略
+fn file_to_sink(
+    in_file: &File,
+    mut pos: usize,
+    len: usize,
+    mut sink: impl FnMut(&[u8]) -> Result<usize, SystemError>,
+) -> Result<usize, SystemError> {
+    let page_cache = in_file.inode().page_cache();
+    let size = match page_cache {
+        Some(_) => in_file.metadata()?.size.max(0) as usize,
+        None => usize::MAX,
+    };
+    let mut buf: Vec<u8> = Vec::new();
+    let mut done = 0;
+    while done < len {
+        let page_offset = pos % MMArch::PAGE_SIZE;
+        let cached = page_cache
+            .as_ref()
+            .and_then(|page_cache| page_cache.get_page(pos / MMArch::PAGE_SIZE));
+        let step = match cached {
+            Some(page) => {
+                let n = (len - done)
+                    .min(MMArch::PAGE_SIZE - page_offset)
+                    .min(size.saturating_sub(pos));
+                if n == 0 {
+                    break;
+                }
+                // 页面被Arc持有，sink返回之前不会被释放
+                let data = unsafe { page_data(&page) };
+                sink(&data[page_offset..page_offset + n]).map(|written| (n, written))
+            }
+            None => {
+                // 有页缓存时只读到页面边界，下一个页面可能已经在页缓存中
+                let n = match page_cache {
+                    Some(_) => (len - done).min(MMArch::PAGE_SIZE - page_offset),
+                    None => (len - done).min(SPLICE_CHUNK_SIZE),
+                };
+                if buf.len() < n {
+                    buf.resize(n, 0);
+                }
+                match in_file.pread(pos, n, &mut buf[..n]) {
+                    Ok(0) => break,
+                    Ok(read) => sink(&buf[..read]).map(|written| (read, written)),
+                    Err(e) => Err(e),
+                }
+            }
+        };
+        match step {
+            Ok((read, written)) => {
+                pos += written;
+                done += written;
+                if written < read {
+                    break;
+                }
+            }
+            Err(e) if done == 0 => return Err(e),
+            Err(_) => break,
+        }
+    }
+    return Ok(done);
+}
+
+/// 把数据写入管道，必要时等待管道中出现空闲空间
+///
+/// 非阻塞模式下，写入部分数据之后管道满了就返回
+fn write_to_pipe(pipe: &LockedPipeInode, buf: &[u8], nonblock: bool) -> Result<usize, SystemError> {
+    let mut written = 0;
+    while written < buf.len() {
+        if let Err(e) = pipe.wait_for_space(nonblock) {
+            if written > 0 {
+                break;
+            }
+            return Err(e);
+        }
+        written += pipe.push(&buf[written..])?;
+    }
+    return Ok(written);
+}
+
+/// 检查文件能否作为copy_file_range的源或目标
+fn check_regular_file(file: &File) -> Result<(), SystemError> {
+    match file.file_type() {
+        FileType::File => Ok(()),
+        FileType::Dir => Err(SystemError::EISDIR),
+        _ => Err(SystemError::EINVAL),
+    }
+}
+
+/// 从管道传输数据到管道
+fn splice_pipe_to_pipe(
+    ipipe: &LockedPipeInode,
+    opipe: &LockedPipeInode,
+    len: usize,
+    nonblock: bool,
+) -> Result<usize, SystemError> {
+    if !ipipe.wait_for_data(nonblock)? {
+        return Ok(0);
+    }
+    opipe.wait_for_space(nonblock)?;
+    return ipipe.transfer_to(opipe, len, true);
+}
+
+/// 从管道传输数据到文件
+fn splice_from_pipe(
+    ipipe: &LockedPipeInode,
+    out_file: &File,
+    out_off: *mut i64,
+    len: usize,
+    nonblock: bool,
+) -> Result<usize, SystemError> {
+    let out_pos = read_user_offset(out_off)?;
+    if out_pos.is_some() && out_file.file_type() != FileType::File {
+        return Err(SystemError::ESPIPE);
+    }
+    if !ipipe.wait_for_data(nonblock)? {
+        return Ok(0);
+    }
+
+    let mut buf: Vec<u8> = vec![0u8; len.min(SPLICE_CHUNK_SIZE)];
+    let n = ipipe.peek(&mut buf);
+    let written = match out_pos {
+        Some(pos) => {
+            let written = out_file.pwrite(pos, n, &buf[..n])?;
+            write_user_offset(out_off, pos + written)?;
+            written
+        }
+        None => out_file.write(n, &buf[..n])?,
+    };
+    // 只取出已经写出的数据
+    ipipe.consume(written)?;
+    return Ok(written);
+}
+
+/// 从文件传输数据到管道
+fn splice_to_pipe(
+    in_file: &File,
+    in_off: *mut i64,
+    opipe: &LockedPipeInode,
+    len: usize,
+    nonblock: bool,
+) -> Result<usize, SystemError> {
+    let in_pos = read_user_offset(in_off)?;
+    let seekable = in_file.file_type() == FileType::File;
+    if in_pos.is_some() && !seekable {
+        return Err(SystemError::ESPIPE);
+    }
+    let space = opipe.wait_for_space(nonblock)?;
+    let len = len.min(space);
+
+    if seekable {
+        let pos = in_pos.unwrap_or(in_file.pos());
+        let n = file_to_sink(in_file, pos, len, |buf| opipe.push(buf))?;
+        update_offset(in_file, in_off, pos + n)?;
+        return Ok(n);
+    }
+
+    // 不能重新读取的文件（例如socket与字符设备），读出的数据必须全部写入管道
+    let mut buf = vec![0u8; len];
+    let n = in_file.read(len, &mut buf)?;
+    return write_to_pipe(opipe, &buf[..n], false);
+}
+
+impl Syscall {
+    /// # 在两个文件描述符之间传输数据
+    ///
+    /// ## 参数
+    /// - `out_fd`: 目标文件，可以是任意可写的文件，例如socket或管道
+    /// - `in_fd`: 源文件，必须是普通文件
+    /// - `offset`: 为NULL时从源文件的读写位置开始读取，并更新读写位置；
+    ///    否则从`*offset`处开始读取，并把结束的位置写回`*offset`，不改变源文件的读写位置
+    /// - `count`: 最多传输的字节数
+    ///
+    /// See: https://man7.org/linux/man-pages/man2/sendfile.2.html
+    pub fn sendfile(
+        out_fd: i32,
+        in_fd: i32,
+        offset: *mut i64,
+        count: usize,
+    ) -> Result<usize, SystemError> {
+        let in_file = get_file(in_fd)?;
+        let out_file = get_file(out_fd)?;
+        in_file.readable().map_err(|_| SystemError::EBADF)?;
+        out_file.writeable().map_err(|_| SystemError::EBADF)?;
+        if in_file.file_type() != FileType::File {
+            return Err(SystemError::EINVAL);
+        }
+        if out_file.mode().contains(FileMode::O_APPEND) {
+            return Err(SystemError::EINVAL);
+        }
+
+        let count = count.min(MAX_RW_COUNT);
+        let pos = read_user_offset(offset)?.unwrap_or(in_file.pos());
+        let n = match file_pipe(&out_file) {
+            Some(opipe) => {
+                let nonblock = out_file.mode().contains(FileMode::O_NONBLOCK);
+                file_to_sink(&in_file, pos, count, |buf| {
+                    write_to_pipe(&opipe, buf, nonblock)
+                })?
+            }
+            None => file_to_sink(&in_file, pos, count, |buf| out_file.write(buf.len(), buf))?,
+        };
+        update_offset(&in_file, offset, pos + n)?;
+        return Ok(n);
+    }
+
+    /// # 在管道与文件之间移动数据
+    ///
+    /// `fd_in`与`fd_out`中至少有一个是管道。管道一侧的偏移量必须为NULL
+    ///
+    /// ## 参数
+    /// - `off_in`/`off_out`: 非管道一侧的偏移量，含义与sendfile的`offset`相同
+    /// - `flags`: SpliceFlags，目前只有SPLICE_F_NONBLOCK起作用
+    ///
+    /// See: https://man7.org/linux/man-pages/man2/splice.2.html
+    pub fn splice(
+        fd_in: i32,
+        off_in: *mut i64,
+        fd_out: i32,
+        off_out: *mut i64,
+        len: usize,
+        flags: u32,
+    ) -> Result<usize, SystemError> {
+        let flags = SpliceFlags::from_bits(flags).ok_or(SystemError::EINVAL)?;
+        let in_file = get_file(fd_in)?;
+        let out_file = get_file(fd_out)?;
+        in_file.readable().map_err(|_| SystemError::EBADF)?;
+        out_file.writeable().map_err(|_| SystemError::EBADF)?;
+        if len == 0 {
+            return Ok(0);
+        }
+
+        let len = len.min(MAX_RW_COUNT);
+        let nonblock = flags.contains(SpliceFlags::SPLICE_F_NONBLOCK)
+            || in_file.mode().contains(FileMode::O_NONBLOCK)
+            || out_file.mode().contains(FileMode::O_NONBLOCK);
+
+        match (file_pipe(&in_file), file_pipe(&out_file)) {
+            (Some(ipipe), Some(opipe)) => {
+                if !off_in.is_null() || !off_out.is_null() {
+                    return Err(SystemError::ESPIPE);
+                }
+                if Arc::ptr_eq(&ipipe, &opipe) {
+                    return Err(SystemError::EINVAL);
+                }
+                splice_pipe_to_pipe(&ipipe, &opipe, len, nonblock)
+            }
+            (Some(ipipe), None) => {
+                if !off_in.is_null() {
+                    return Err(SystemError::ESPIPE);
+                }
+                if !off_out.is_null() && out_file.mode().contains(FileMode::O_APPEND) {
+                    return Err(SystemError::EINVAL);
+                }
+                splice_from_pipe(&ipipe, &out_file, off_out, len, nonblock)
+            }
+            (None, Some(opipe)) => {
+                if !off_out.is_null() {
+                    return Err(SystemError::ESPIPE);
+                }
+                splice_to_pipe(&in_file, off_in, &opipe, len, nonblock)
+            }
+            (None, None) => Err(SystemError::EINVAL),
+        }
+    }
+
+    /// # 复制管道中的数据到另一个管道，不取出源管道中的数据
+    ///
+    /// See: https://man7.org/linux/man-pages/man2/tee.2.html
+    pub fn tee(fd_in: i32, fd_out: i32, len: usize, flags: u32) -> Result<usize, SystemError> {
+        let flags = SpliceFlags::from_bits(flags).ok_or(SystemError::EINVAL)?;
+        let in_file = get_file(fd_in)?;
+        let out_file = get_file(fd_out)?;
+        in_file.readable().map_err(|_| SystemError::EBADF)?;
+        out_file.writeable().map_err(|_| SystemError::EBADF)?;
+
+        let (Some(ipipe), Some(opipe)) = (file_pipe(&in_file), file_pipe(&out_file)) else {
+            return Err(SystemError::EINVAL);
+        };
+        if Arc::ptr_eq(&ipipe, &opipe) {
+            return Err(SystemError::EINVAL);
+        }
+        if len == 0 {
+            return Ok(0);
+        }
+
+        let nonblock = flags.contains(SpliceFlags::SPLICE_F_NONBLOCK)
+            || in_file.mode().contains(FileMode::O_NONBLOCK)
+            || out_file.mode().contains(FileMode::O_NONBLOCK);
+        if !ipipe.wait_for_data(nonblock)? {
+            return Ok(0);
+        }
+        opipe.wait_for_space(nonblock)?;
+        return ipipe.transfer_to(&opipe, len, false);
+    }
+
+    /// # 在两个普通文件之间复制数据
+    ///
+    /// ## 参数
+    /// - `off_in`/`off_out`: 含义与sendfile的`offset`相同
+    /// - `flags`: 必须为0
+    ///
+    /// See: https://man7.org/linux/man-pages/man2/copy_file_range.2.html
+    pub fn copy_file_range(
+        fd_in: i32,
+        off_in: *mut i64,
+        fd_out: i32,
+        off_out: *mut i64,
+        len: usize,
+        flags: u32,
+    ) -> Result<usize, SystemError> {
+        if flags != 0 {
+            return Err(SystemError::EINVAL);
+        }
+        let in_file = get_file(fd_in)?;
+        let out_file = get_file(fd_out)?;
+        in_file.readable().map_err(|_| SystemError::EBADF)?;
+        out_file.writeable().map_err(|_| SystemError::EBADF)?;
+        if out_file.mode().contains(FileMode::O_APPEND) {
+            return Err(SystemError::EBADF);
+        }
+        check_regular_file(&in_file)?;
+        check_regular_file(&out_file)?;
+
+        let len = len.min(MAX_RW_COUNT);
+        let pos_in = read_user_offset(off_in)?.unwrap_or(in_file.pos());
+        let pos_out = read_user_offset(off_out)?.unwrap_or(out_file.pos());
+        // 同一个文件中的源区域与目标区域不能重叠
+        if inode_identity(&in_file.inode()) == inode_identity(&out_file.inode())
+            && pos_in < pos_out + len
+            && pos_out < pos_in + len
+        {
+            return Err(SystemError::EINVAL);
+        }
+        if len == 0 {
+            return Ok(0);
+        }
+
+        let mut out_pos = pos_out;
+        let n = file_to_sink(&in_file, pos_in, len, |buf| {
+            let written = out_file.pwrite(out_pos, buf.len(), buf)?;
+            out_pos += written;
+            Ok(written)
+        })?;
+        update_offset(&in_file, off_in, pos_in + n)?;
+        update_offset(&out_file, off_out, pos_out + n)?;
+        return Ok(n);
+    }
+}
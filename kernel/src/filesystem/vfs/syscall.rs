@@ -8,11 +8,15 @@ use system_error::SystemError;
 use crate::producefs;
 use crate::syscall::user_access::UserBufferReader;
 use crate::{
+    arch::MMArch,
     driver::base::{block::SeekFrom, device::device_number::DeviceNumber},
-    filesystem::vfs::{core as Vcore, file::FileDescriptorVec},
+    filesystem::{
+        inotify::{fsnotify_create, fsnotify_link, fsnotify_move},
+        vfs::{core as Vcore, file::FileDescriptorVec},
+    },
     libs::rwlock::RwLockWriteGuard,
-    mm::{verify_area, VirtAddr},
-    process::ProcessManager,
+    mm::{verify_area, MemoryManagementArch, VirtAddr},
+    process::{capability::capable, cred::CAPFlags, ProcessManager},
     syscall::{
         user_access::{self, check_and_clone_cstr, UserBufferWriter},
         Syscall,
@@ -24,9 +28,15 @@ use super::{
     core::{do_mkdir_at, do_remove_dir, do_unlink_at},
     fcntl::{AtFlags, FcntlCommand, FD_CLOEXEC},
     file::{File, FileMode},
-    open::{do_faccessat, do_fchmodat, do_sys_open, do_utimensat, do_utimes},
+    locks::{flock_lock_file, posix_lock_file, posix_test_lock, FlockOperation, PosixFlock},
+    open::{
+        do_faccessat, do_fchmodat, do_fchown, do_fchownat, do_sys_open, do_utimensat, do_utimes,
+    },
     utils::{rsplit_path, user_path_at},
-    Dirent, FileType, IndexNode, SuperBlock, FSMAKER, MAX_PATHLEN, ROOT_INODE,
+    xattr::{
+        do_getxattr, do_listxattr, do_removexattr, do_setxattr, xattr_fd_inode, xattr_path_inode,
+    },
+    Dirent, FileSystemMakerData, FileType, IndexNode, SuperBlock, FSMAKER, MAX_PATHLEN, ROOT_INODE,
     VFS_MAX_FOLLOW_SYMLINK_TIMES,
 };
 
@@ -868,7 +878,9 @@ impl Syscall {
             new_begin_inode.lookup_follow_symlink(new_parent_path.unwrap_or("/"), symlink_times)?;
 
         // 被调用者利用downcast_ref判断两inode是否为同一文件系统
-        return new_parent.link(new_name, &old_inode).map(|_| 0);
+        new_parent.link(new_name, &old_inode)?;
+        fsnotify_link(&new_parent, new_name, &old_inode);
+        return Ok(0);
     }
 
     pub fn link(old: *const u8, new: *const u8) -> Result<usize, SystemError> {
@@ -1019,7 +1031,17 @@ impl Syscall {
         let (new_filename, new_parent_path) = rsplit_path(&new_remain_path);
         let new_parent_inode = ROOT_INODE()
             .lookup_follow_symlink(new_parent_path.unwrap_or("/"), VFS_MAX_FOLLOW_SYMLINK_TIMES)?;
+        let moved_inode = old_parent_inode.find(old_filename)?;
+        let target_inode = new_parent_inode.find(new_filename).ok();
         old_parent_inode.move_to(old_filename, &new_parent_inode, new_filename)?;
+        fsnotify_move(
+            &old_parent_inode,
+            old_filename,
+            &new_parent_inode,
+            new_filename,
+            &moved_inode,
+            target_inode.as_ref(),
+        );
         return Ok(0);
     }
 
@@ -1130,10 +1152,11 @@ impl Syscall {
     /// - `fd`：文件描述符
     /// - `cmd`：命令
     /// - `arg`：参数
-    pub fn fcntl(fd: i32, cmd: FcntlCommand, arg: i32) -> Result<usize, SystemError> {
+    pub fn fcntl(fd: i32, cmd: FcntlCommand, arg: usize) -> Result<usize, SystemError> {
         // debug!("fcntl ({cmd:?}) fd: {fd}, arg={arg}");
         match cmd {
             FcntlCommand::DupFd | FcntlCommand::DupFdCloexec => {
+                let arg = arg as i32;
                 if arg < 0 || arg as usize >= FileDescriptorVec::PROCESS_MAX_FD {
                     return Err(SystemError::EBADF);
                 }
@@ -1221,6 +1244,41 @@ impl Syscall {
 
                 return Err(SystemError::EBADF);
             }
+            FcntlCommand::GetLock | FcntlCommand::OfdGetLock => {
+                let file = ProcessManager::current_pcb()
+                    .fd_table()
+                    .read()
+                    .get_file_by_fd(fd)
+                    .ok_or(SystemError::EBADF)?;
+                let reader =
+                    UserBufferReader::new(arg as *const PosixFlock, size_of::<PosixFlock>(), true)?;
+                let mut flock = *reader.read_one_from_user::<PosixFlock>(0)?;
+                posix_test_lock(&file, &mut flock, cmd == FcntlCommand::OfdGetLock)?;
+                let mut writer =
+                    UserBufferWriter::new(arg as *mut PosixFlock, size_of::<PosixFlock>(), true)?;
+                writer.copy_one_to_user(&flock, 0)?;
+                return Ok(0);
+            }
+            FcntlCommand::SetLock
+            | FcntlCommand::SetLockWait
+            | FcntlCommand::OfdSetLock
+            | FcntlCommand::OfdSetLockWait => {
+                let file = ProcessManager::current_pcb()
+                    .fd_table()
+                    .read()
+                    .get_file_by_fd(fd)
+                    .ok_or(SystemError::EBADF)?;
+                let reader =
+                    UserBufferReader::new(arg as *const PosixFlock, size_of::<PosixFlock>(), true)?;
+                let flock = *reader.read_one_from_user::<PosixFlock>(0)?;
+                let wait = matches!(
+                    cmd,
+                    FcntlCommand::SetLockWait | FcntlCommand::OfdSetLockWait
+                );
+                let ofd = matches!(cmd, FcntlCommand::OfdSetLock | FcntlCommand::OfdSetLockWait);
+                posix_lock_file(&file, &flock, wait, ofd)?;
+                return Ok(0);
+            }
             _ => {
                 // TODO: unimplemented
                 // 未实现的命令，返回0，不报错。
@@ -1231,6 +1289,25 @@ impl Syscall {
         }
     }
 
+    /// # 对整个文件加建议锁或解锁
+    ///
+    /// ## 参数
+    ///
+    /// - `fd`：文件描述符
+    /// - `operation`：LOCK_SH、LOCK_EX或LOCK_UN，可以与LOCK_NB组合
+    ///
+    /// See: https://man7.org/linux/man-pages/man2/flock.2.html
+    pub fn flock(fd: i32, operation: u32) -> Result<usize, SystemError> {
+        let operation = FlockOperation::from_bits(operation).ok_or(SystemError::EINVAL)?;
+        let file = ProcessManager::current_pcb()
+            .fd_table()
+            .read()
+            .get_file_by_fd(fd)
+            .ok_or(SystemError::EBADF)?;
+        flock_lock_file(&file, operation)?;
+        return Ok(0);
+    }
+
     /// # ftruncate
     ///
     /// ## 描述
@@ -1502,7 +1579,8 @@ impl Syscall {
         let parent_inode: Arc<dyn IndexNode> = ROOT_INODE()
             .lookup_follow_symlink(parent_path.unwrap_or("/"), VFS_MAX_FOLLOW_SYMLINK_TIMES)?;
         // 创建nod
-        parent_inode.mknod(filename, mode, dev_t)?;
+        let inode = parent_inode.mknod(filename, mode, dev_t)?;
+        fsnotify_create(&parent_inode, filename, &inode);
 
         return Ok(0);
     }
@@ -1604,6 +1682,34 @@ impl Syscall {
         );
     }
 
+    pub fn chown(pathname: *const u8, uid: usize, gid: usize) -> Result<usize, SystemError> {
+        return do_fchownat(AtFlags::AT_FDCWD.bits(), pathname, uid, gid, 0);
+    }
+
+    pub fn lchown(pathname: *const u8, uid: usize, gid: usize) -> Result<usize, SystemError> {
+        return do_fchownat(
+            AtFlags::AT_FDCWD.bits(),
+            pathname,
+            uid,
+            gid,
+            AtFlags::AT_SYMLINK_NOFOLLOW.bits(),
+        );
+    }
+
+    pub fn fchownat(
+        dirfd: i32,
+        pathname: *const u8,
+        uid: usize,
+        gid: usize,
+        flags: i32,
+    ) -> Result<usize, SystemError> {
+        return do_fchownat(dirfd, pathname, uid, gid, flags);
+    }
+
+    pub fn fchown(fd: i32, uid: usize, gid: usize) -> Result<usize, SystemError> {
+        return do_fchown(fd, uid, gid);
+    }
+
     pub fn fchmod(fd: i32, mode: u32) -> Result<usize, SystemError> {
         let _mode = ModeType::from_bits(mode).ok_or(SystemError::EINVAL)?;
         let binding = ProcessManager::current_pcb().fd_table();
@@ -1619,26 +1725,29 @@ impl Syscall {
     }
     /// #挂载文件系统
     ///
-    /// 用于挂载文件系统,目前仅支持ramfs挂载
+    /// 用于挂载文件系统
     ///
     /// ## 参数:
     ///
-    /// - source       挂载设备(暂时不支持)
+    /// - source       挂载源（由具体的文件系统解释，例如9p的挂载标签）
     /// - target       挂载目录
     /// - filesystemtype   文件系统
     /// - mountflags     挂载选项（暂未实现）
-    /// - data        带数据挂载
+    /// - data        带数据挂载（按以逗号分隔的字符串选项解析）
     ///
     /// ## 返回值
     /// - Ok(0): 挂载成功
     /// - Err(SystemError) :挂载过程中出错
     pub fn mount(
-        _source: *const u8,
+        source: *const u8,
         target: *const u8,
         filesystemtype: *const u8,
         _mountflags: usize,
-        _data: *const c_void,
+        data: *const c_void,
     ) -> Result<usize, SystemError> {
+        if !capable(CAPFlags::CAP_SYS_ADMIN) {
+            return Err(SystemError::EPERM);
+        }
         let target = user_access::check_and_clone_cstr(target, Some(MAX_PATHLEN))?
             .into_string()
             .map_err(|_| SystemError::EINVAL)?;
@@ -1646,7 +1755,25 @@ impl Syscall {
         let fstype_str = user_access::check_and_clone_cstr(filesystemtype, Some(MAX_PATHLEN))?;
         let fstype_str = fstype_str.to_str().map_err(|_| SystemError::EINVAL)?;
 
-        let fstype = producefs!(FSMAKER, fstype_str)?;
+        let source = if source.is_null() {
+            String::new()
+        } else {
+            user_access::check_and_clone_cstr(source, Some(MAX_PATHLEN))?
+                .into_string()
+                .map_err(|_| SystemError::EINVAL)?
+        };
+        let options = if data.is_null() {
+            None
+        } else {
+            Some(
+                user_access::check_and_clone_cstr(data as *const u8, Some(MMArch::PAGE_SIZE))?
+                    .into_string()
+                    .map_err(|_| SystemError::EINVAL)?,
+            )
+        };
+        let data = FileSystemMakerData::new(source, options);
+
+        let fstype = producefs!(FSMAKER, fstype_str, &data)?;
 
         Vcore::do_mount(fstype, &target)?;
 
@@ -1662,6 +1789,9 @@ impl Syscall {
     ///
     /// [umount(2) — Linux manual page](https://www.man7.org/linux/man-pages/man2/umount.2.html)
     pub fn umount2(target: *const u8, flags: i32) -> Result<(), SystemError> {
+        if !capable(CAPFlags::CAP_SYS_ADMIN) {
+            return Err(SystemError::EPERM);
+        }
         let target = user_access::check_and_clone_cstr(target, Some(MAX_PATHLEN))?
             .into_string()
             .map_err(|_| SystemError::EINVAL)?;
@@ -1673,6 +1803,27 @@ impl Syscall {
         return Ok(());
     }
 
+    /// # 切换当前mount namespace的根文件系统
+    ///
+    /// 将`new_root`所在的挂载作为新的根，原来的根文件系统被挂载到`put_old`上。
+    /// initramfs中的init程序可以借此切换到真正的根文件系统，随后卸载`put_old`。
+    ///
+    /// See: https://man7.org/linux/man-pages/man2/pivot_root.2.html
+    pub fn pivot_root(new_root: *const u8, put_old: *const u8) -> Result<usize, SystemError> {
+        if !capable(CAPFlags::CAP_SYS_ADMIN) {
+            return Err(SystemError::EPERM);
+        }
+        let new_root = user_access::check_and_clone_cstr(new_root, Some(MAX_PATHLEN))?
+            .into_string()
+            .map_err(|_| SystemError::EINVAL)?;
+        let put_old = user_access::check_and_clone_cstr(put_old, Some(MAX_PATHLEN))?
+            .into_string()
+            .map_err(|_| SystemError::EINVAL)?;
+
+        Vcore::do_pivot_root(&new_root, &put_old)?;
+        return Ok(0);
+    }
+
     pub fn sys_utimensat(
         dirfd: i32,
         pathname: *const u8,
@@ -1714,6 +1865,99 @@ impl Syscall {
         };
         do_utimes(&pathname, times)
     }
+
+    /// # 获取扩展属性的值
+    ///
+    /// ## 参数
+    ///
+    /// - `size`: 缓冲区的大小，为0时只返回属性值的长度
+    /// - `follow_symlink`: 是否跟随路径最后一项的符号链接（lgetxattr不跟随）
+    ///
+    /// See: https://man7.org/linux/man-pages/man2/getxattr.2.html
+    pub fn getxattr(
+        path: *const u8,
+        name: *const u8,
+        value: *mut u8,
+        size: usize,
+        follow_symlink: bool,
+    ) -> Result<usize, SystemError> {
+        let inode = xattr_path_inode(path, follow_symlink)?;
+        return do_getxattr(&inode, name, value, size);
+    }
+
+    pub fn fgetxattr(
+        fd: i32,
+        name: *const u8,
+        value: *mut u8,
+        size: usize,
+    ) -> Result<usize, SystemError> {
+        return do_getxattr(&xattr_fd_inode(fd)?, name, value, size);
+    }
+
+    /// # 设置扩展属性的值
+    ///
+    /// ## 参数
+    ///
+    /// - `flags`: XATTR_CREATE或XATTR_REPLACE，为0时属性不存在则创建，存在则替换
+    /// - `follow_symlink`: 是否跟随路径最后一项的符号链接（lsetxattr不跟随）
+    ///
+    /// See: https://man7.org/linux/man-pages/man2/setxattr.2.html
+    pub fn setxattr(
+        path: *const u8,
+        name: *const u8,
+        value: *const u8,
+        size: usize,
+        flags: u32,
+        follow_symlink: bool,
+    ) -> Result<usize, SystemError> {
+        let inode = xattr_path_inode(path, follow_symlink)?;
+        return do_setxattr(&inode, name, value, size, flags);
+    }
+
+    pub fn fsetxattr(
+        fd: i32,
+        name: *const u8,
+        value: *const u8,
+        size: usize,
+        flags: u32,
+    ) -> Result<usize, SystemError> {
+        return do_setxattr(&xattr_fd_inode(fd)?, name, value, size, flags);
+    }
+
+    /// # 列出扩展属性的名字
+    ///
+    /// 每个名字以'\0'结尾。`size`为0时只返回所需的缓冲区大小
+    ///
+    /// See: https://man7.org/linux/man-pages/man2/listxattr.2.html
+    pub fn listxattr(
+        path: *const u8,
+        list: *mut u8,
+        size: usize,
+        follow_symlink: bool,
+    ) -> Result<usize, SystemError> {
+        let inode = xattr_path_inode(path, follow_symlink)?;
+        return do_listxattr(&inode, list, size);
+    }
+
+    pub fn flistxattr(fd: i32, list: *mut u8, size: usize) -> Result<usize, SystemError> {
+        return do_listxattr(&xattr_fd_inode(fd)?, list, size);
+    }
+
+    /// # 删除扩展属性
+    ///
+    /// See: https://man7.org/linux/man-pages/man2/removexattr.2.html
+    pub fn removexattr(
+        path: *const u8,
+        name: *const u8,
+        follow_symlink: bool,
+    ) -> Result<usize, SystemError> {
+        let inode = xattr_path_inode(path, follow_symlink)?;
+        return do_removexattr(&inode, name);
+    }
+
+    pub fn fremovexattr(fd: i32, name: *const u8) -> Result<usize, SystemError> {
+        return do_removexattr(&xattr_fd_inode(fd)?, name);
+    }
 }
 
 #[repr(C)]
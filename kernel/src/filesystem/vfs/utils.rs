@@ -5,9 +5,69 @@ use core::hash::Hash;
 use alloc::{string::String, sync::Arc};
 use system_error::SystemError;
 
-use crate::process::ProcessControlBlock;
+use crate::{
+    libs::casting::DowncastArc,
+    process::{
+        cred::{CAPFlags, Cred, Kgid},
+        ProcessControlBlock,
+    },
+};
+
+use super::{fcntl::AtFlags, mount::MountFSInode, FileType, IndexNode, Metadata, ROOT_INODE};
+
+/// 权限检查时要求的访问方式，与linux的MAY_EXEC、MAY_WRITE、MAY_READ取值一致
+pub const MAY_EXEC: u32 = 0o1;
+pub const MAY_WRITE: u32 = 0o2;
+pub const MAY_READ: u32 = 0o4;
+
+/// 获取inode对象的地址，用于在文件锁、inotify等全局表中标识一个文件
+///
+/// 挂载点的MountFSInode只是一层包装，因此使用其内部的inode
+pub fn inode_identity(inode: &Arc<dyn IndexNode>) -> usize {
+    let inode = match inode.clone().downcast_arc::<MountFSInode>() {
+        Some(mount_inode) => mount_inode.inner_inode(),
+        None => inode.clone(),
+    };
+    Arc::as_ptr(&inode) as *const () as usize
+}
 
-use super::{fcntl::AtFlags, FileType, IndexNode, ROOT_INODE};
+/// 按照文件的属主、属组与权限位，检查凭证`cred`能否以`mask`指定的方式访问文件
+///
+/// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/fs/namei.c#345
+pub fn generic_permission(metadata: &Metadata, cred: &Cred, mask: u32) -> Result<(), SystemError> {
+    let gid = Kgid::new(metadata.gid);
+    let in_group = cred.fsgid == gid
+        || cred
+            .group_info
+            .as_ref()
+            .is_some_and(|groups| groups.gids.contains(&gid));
+    let shift = if cred.fsuid.data() == metadata.uid {
+        6
+    } else if in_group {
+        3
+    } else {
+        0
+    };
+    if (metadata.mode.bits() >> shift) & mask == mask {
+        return Ok(());
+    }
+
+    let is_dir = metadata.file_type == FileType::Dir;
+    // 目录的搜索权限，以及所有的读权限
+    if cred.has_capability(CAPFlags::CAP_DAC_READ_SEARCH)
+        && mask & MAY_WRITE == 0
+        && (is_dir || mask & MAY_EXEC == 0)
+    {
+        return Ok(());
+    }
+    // 普通文件至少有一个执行位时，才能越过权限检查执行它
+    if cred.has_capability(CAPFlags::CAP_DAC_OVERRIDE)
+        && (mask & MAY_EXEC == 0 || is_dir || metadata.mode.bits() & 0o111 != 0)
+    {
+        return Ok(());
+    }
+    return Err(SystemError::EACCES);
+}
 
 /// @brief 切分路径字符串，返回最左侧那一级的目录名和剩余的部分。
 ///
@@ -0,0 +1,335 @@
+//! 扩展属性（extended attributes）
+//!
+//! 属性名由命名空间前缀与名字组成，目前支持以下命名空间：
+//! - `user.`: 只能用于普通文件与目录，读写属性需要对文件有相应的读写权限
+//! - `trusted.`: 只有拥有CAP_SYS_ADMIN的进程才能读写，其他进程列出属性名时看不到它们
+//! - `security.`: 所有进程都可以读取，写入需要CAP_SYS_ADMIN（`security.capability`需要CAP_SETFCAP）
+//!
+//! 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/fs/xattr.c
+
+use alloc::{
+    collections::BTreeMap,
+    string::{String, ToString},
+    sync::Arc,
+    vec::Vec,
+};
+use system_error::SystemError;
+
+use crate::{
+    filesystem::inotify::fsnotify_attrib,
+    process::{cred::CAPFlags, ProcessManager},
+    syscall::user_access::{check_and_clone_cstr, UserBufferReader, UserBufferWriter},
+};
+
+use super::{
+    fcntl::AtFlags,
+    syscall::ModeType,
+    utils::{generic_permission, user_path_at, MAY_READ, MAY_WRITE},
+    FileType, IndexNode, MAX_PATHLEN, VFS_MAX_FOLLOW_SYMLINK_TIMES,
+};
+
+/// 属性名的最大长度
+pub const XATTR_NAME_MAX: usize = 255;
+/// 属性值的最大长度
+pub const XATTR_SIZE_MAX: usize = 65536;
+/// listxattr返回的属性名列表的最大长度
+pub const XATTR_LIST_MAX: usize = 65536;
+
+bitflags! {
+    /// setxattr的标志
+    pub struct XattrFlags: u32 {
+        /// 属性必须不存在
+        const XATTR_CREATE = 0x1;
+        /// 属性必须已经存在
+        const XATTR_REPLACE = 0x2;
+    }
+}
+
+/// 扩展属性的命名空间
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum XattrNamespace {
+    User,
+    Trusted,
+    Security,
+}
+
+impl XattrNamespace {
+    const PREFIXES: [(&'static str, XattrNamespace); 3] = [
+        ("user.", XattrNamespace::User),
+        ("trusted.", XattrNamespace::Trusted),
+        ("security.", XattrNamespace::Security),
+    ];
+
+    /// 根据属性名的前缀获取命名空间，前缀之后的名字不能为空
+    pub fn from_name(name: &str) -> Result<Self, SystemError> {
+        for (prefix, namespace) in Self::PREFIXES {
+            if let Some(suffix) = name.strip_prefix(prefix) {
+                if suffix.is_empty() {
+                    return Err(SystemError::EINVAL);
+                }
+                return Ok(namespace);
+            }
+        }
+        return Err(SystemError::EOPNOTSUPP_OR_ENOTSUP);
+    }
+}
+
+/// 保存在内存中的扩展属性，供没有持久化存储的文件系统使用
+///
+/// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/fs/xattr.c#1059
+#[derive(Debug, Default, Clone)]
+pub struct SimpleXattrs {
+    attrs: BTreeMap<String, Vec<u8>>,
+}
+
+impl SimpleXattrs {
+    pub const fn new() -> Self {
+        Self {
+            attrs: BTreeMap::new(),
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Result<Vec<u8>, SystemError> {
+        self.attrs.get(name).cloned().ok_or(SystemError::ENODATA)
+    }
+
+    pub fn set(&mut self, name: &str, value: &[u8], flags: XattrFlags) -> Result<(), SystemError> {
+        let exists = self.attrs.contains_key(name);
+        if exists && flags.contains(XattrFlags::XATTR_CREATE) {
+            return Err(SystemError::EEXIST);
+        }
+        if !exists && flags.contains(XattrFlags::XATTR_REPLACE) {
+            return Err(SystemError::ENODATA);
+        }
+        self.attrs.insert(name.to_string(), value.to_vec());
+        return Ok(());
+    }
+
+    pub fn remove(&mut self, name: &str) -> Result<(), SystemError> {
+        self.attrs
+            .remove(name)
+            .map(|_| ())
+            .ok_or(SystemError::ENODATA)
+    }
+
+    pub fn list(&self) -> Vec<String> {
+        self.attrs.keys().cloned().collect()
+    }
+}
+
+/// 检查当前进程能否读写文件的扩展属性
+///
+/// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/fs/xattr.c#83
+fn xattr_permission(
+    inode: &Arc<dyn IndexNode>,
+    name: &str,
+    write: bool,
+) -> Result<(), SystemError> {
+    let namespace = XattrNamespace::from_name(name)?;
+    let cred = ProcessManager::current_pcb().cred();
+    match namespace {
+        XattrNamespace::Trusted => {
+            if !cred.has_capability(CAPFlags::CAP_SYS_ADMIN) {
+                return Err(if write {
+                    SystemError::EPERM
+                } else {
+                    SystemError::ENODATA
+                });
+            }
+            Ok(())
+        }
+        XattrNamespace::Security => {
+            if write {
+                let cap = if name == "security.capability" {
+                    CAPFlags::CAP_SETFCAP
+                } else {
+                    CAPFlags::CAP_SYS_ADMIN
+                };
+                if !cred.has_capability(cap) {
+                    return Err(SystemError::EPERM);
+                }
+            }
+            Ok(())
+        }
+        XattrNamespace::User => {
+            let metadata = inode.metadata()?;
+            // 用户属性只能用于普通文件与目录，否则可能被用来绕过对设备文件等的权限控制
+            if metadata.file_type != FileType::File && metadata.file_type != FileType::Dir {
+                return Err(if write {
+                    SystemError::EPERM
+                } else {
+                    SystemError::ENODATA
+                });
+            }
+            // 设置了粘滞位的目录，只有属主能够修改其用户属性
+            if write
+                && metadata.file_type == FileType::Dir
+                && metadata.mode.contains(ModeType::S_ISVTX)
+                && cred.fsuid.data() != metadata.uid
+                && !cred.has_capability(CAPFlags::CAP_FOWNER)
+            {
+                return Err(SystemError::EPERM);
+            }
+            generic_permission(&metadata, &cred, if write { MAY_WRITE } else { MAY_READ })
+        }
+    }
+}
+
+pub fn vfs_getxattr(inode: &Arc<dyn IndexNode>, name: &str) -> Result<Vec<u8>, SystemError> {
+    xattr_permission(inode, name, false)?;
+    return inode.getxattr(name);
+}
+
+pub fn vfs_setxattr(
+    inode: &Arc<dyn IndexNode>,
+    name: &str,
+    value: &[u8],
+    flags: XattrFlags,
+) -> Result<(), SystemError> {
+    xattr_permission(inode, name, true)?;
+    inode.setxattr(name, value, flags)?;
+    fsnotify_attrib(inode);
+    return Ok(());
+}
+
+/// 获取属性名列表，每个属性名以'\0'结尾。没有权限访问的属性不会被列出
+pub fn vfs_listxattr(inode: &Arc<dyn IndexNode>) -> Result<Vec<u8>, SystemError> {
+    let privileged = ProcessManager::current_pcb()
+        .cred()
+        .has_capability(CAPFlags::CAP_SYS_ADMIN);
+    let mut list = Vec::new();
+    for name in inode.listxattr()? {
+        if !privileged && XattrNamespace::from_name(&name) == Ok(XattrNamespace::Trusted) {
+            continue;
+        }
+        list.extend_from_slice(name.as_bytes());
+        list.push(0);
+    }
+    return Ok(list);
+}
+
+pub fn vfs_removexattr(inode: &Arc<dyn IndexNode>, name: &str) -> Result<(), SystemError> {
+    xattr_permission(inode, name, true)?;
+    inode.removexattr(name)?;
+    fsnotify_attrib(inode);
+    return Ok(());
+}
+
+/// 获取路径对应的inode
+///
+/// ## 参数
+///
+/// - `follow_symlink`: 路径的最后一项是符号链接时，是否跟随它（l*系列的系统调用不跟随）
+pub fn xattr_path_inode(
+    path: *const u8,
+    follow_symlink: bool,
+) -> Result<Arc<dyn IndexNode>, SystemError> {
+    let path = check_and_clone_cstr(path, Some(MAX_PATHLEN))?
+        .into_string()
+        .map_err(|_| SystemError::EINVAL)?;
+    if path.is_empty() {
+        return Err(SystemError::ENOENT);
+    }
+    let (inode_begin, path) = user_path_at(
+        &ProcessManager::current_pcb(),
+        AtFlags::AT_FDCWD.bits(),
+        &path,
+    )?;
+    return inode_begin.lookup_follow_symlink(
+        &path,
+        if follow_symlink {
+            VFS_MAX_FOLLOW_SYMLINK_TIMES
+        } else {
+            0
+        },
+    );
+}
+
+/// 获取文件描述符对应的inode
+pub fn xattr_fd_inode(fd: i32) -> Result<Arc<dyn IndexNode>, SystemError> {
+    let file = ProcessManager::current_pcb()
+        .fd_table()
+        .read()
+        .get_file_by_fd(fd)
+        .ok_or(SystemError::EBADF)?;
+    return Ok(file.inode());
+}
+
+/// 从用户空间读取属性名
+fn xattr_name_from_user(name: *const u8) -> Result<String, SystemError> {
+    let name = check_and_clone_cstr(name, Some(XATTR_NAME_MAX + 1))?
+        .into_string()
+        .map_err(|_| SystemError::EINVAL)?;
+    if name.is_empty() || name.len() > XATTR_NAME_MAX {
+        return Err(SystemError::ERANGE);
+    }
+    return Ok(name);
+}
+
+/// 把数据写入大小为`size`的用户缓冲区
+///
+/// `size`为0时只返回数据的长度，缓冲区太小时返回ERANGE
+fn xattr_copy_to_user(data: &[u8], buf: *mut u8, size: usize) -> Result<usize, SystemError> {
+    if size == 0 {
+        return Ok(data.len());
+    }
+    if data.len() > size {
+        return Err(SystemError::ERANGE);
+    }
+    if !data.is_empty() {
+        let mut writer = UserBufferWriter::new(buf, data.len(), true)?;
+        writer.copy_to_user(data, 0)?;
+    }
+    return Ok(data.len());
+}
+
+pub fn do_getxattr(
+    inode: &Arc<dyn IndexNode>,
+    name: *const u8,
+    value: *mut u8,
+    size: usize,
+) -> Result<usize, SystemError> {
+    let name = xattr_name_from_user(name)?;
+    let data = vfs_getxattr(inode, &name)?;
+    return xattr_copy_to_user(&data, value, size.min(XATTR_SIZE_MAX));
+}
+
+pub fn do_setxattr(
+    inode: &Arc<dyn IndexNode>,
+    name: *const u8,
+    value: *const u8,
+    size: usize,
+    flags: u32,
+) -> Result<usize, SystemError> {
+    let flags = XattrFlags::from_bits(flags).ok_or(SystemError::EINVAL)?;
+    let name = xattr_name_from_user(name)?;
+    if size > XATTR_SIZE_MAX {
+        return Err(SystemError::E2BIG);
+    }
+    let data = if size > 0 {
+        let reader = UserBufferReader::new(value, size, true)?;
+        reader.read_from_user::<u8>(0)?.to_vec()
+    } else {
+        Vec::new()
+    };
+    vfs_setxattr(inode, &name, &data, flags)?;
+    return Ok(0);
+}
+
+pub fn do_listxattr(
+    inode: &Arc<dyn IndexNode>,
+    list: *mut u8,
+    size: usize,
+) -> Result<usize, SystemError> {
+    let data = vfs_listxattr(inode)?;
+    if data.len() > XATTR_LIST_MAX {
+        return Err(SystemError::E2BIG);
+    }
+    return xattr_copy_to_user(&data, list, size.min(XATTR_LIST_MAX));
+}
+
+pub fn do_removexattr(inode: &Arc<dyn IndexNode>, name: *const u8) -> Result<usize, SystemError> {
+    let name = xattr_name_from_user(name)?;
+    vfs_removexattr(inode, &name)?;
+    return Ok(0);
+}
@@ -20,6 +20,8 @@ pub struct BootParams {
     pub arch: ArchBootParams,
     boot_command_line: [u8; Self::BOOT_COMMAND_LINE_SIZE],
     pub acpi: BootloaderAcpiArg,
+    /// bootloader加载的initramfs所在的物理内存区域（起始地址，大小）
+    pub initrd: Option<(PhysAddr, usize)>,
 }
 
 impl BootParams {
@@ -29,6 +31,7 @@ impl BootParams {
         arch: ArchBootParams::DEFAULT,
         boot_command_line: [0u8; Self::BOOT_COMMAND_LINE_SIZE],
         acpi: BootloaderAcpiArg::NotProvided,
+        initrd: None,
     };
 
     /// 开机命令行参数字符串最大大小
@@ -49,6 +52,22 @@ impl BootParams {
         core::str::from_utf8(self.boot_cmdline()).unwrap()
     }
 
+    /// 获取开机命令行中形如`name=value`的参数的值
+    ///
+    /// 同一个参数出现多次时，以最后一次为准。
+    /// 对于不带`=`的参数（例如`ro`），返回空字符串
+    pub fn cmdline_param(&self, name: &str) -> Option<&str> {
+        let cmdline = self.boot_cmdline_str();
+        let cmdline = cmdline.split('\0').next().unwrap_or("");
+        cmdline
+            .split_ascii_whitespace()
+            .filter_map(|param| match param.split_once('=') {
+                Some((key, value)) => (key == name).then_some(value),
+                None => (param == name).then_some(""),
+            })
+            .last()
+    }
+
     pub fn bootloader_name(&self) -> Option<&str> {
         self.bootloader_name.as_deref()
     }
@@ -119,6 +138,10 @@ pub trait BootCallbacks: Send + Sync {
     ///
     /// 该函数应该把内核命令行参数追加到`boot_params().boot_cmdline`中
     fn init_kernel_cmdline(&self) -> Result<(), SystemError>;
+    /// 初始化initramfs的位置
+    ///
+    /// 该函数应该把initramfs所在的物理内存区域写入`boot_params().initrd`中
+    fn init_initramfs(&self) -> Result<(), SystemError>;
     /// 初始化帧缓冲区信息
     ///
     /// - 该函数应该把帧缓冲区信息写入`scinfo`中。
@@ -155,6 +178,12 @@ pub(super) fn boot_callback_except_early() {
             log::error!("Failed to init kernel cmdline: {:?}", e);
         })
         .ok();
+    boot_callbacks()
+        .init_initramfs()
+        .inspect_err(|e| {
+            log::error!("Failed to init initramfs: {:?}", e);
+        })
+        .ok();
 
     let mut boot_params = boot_params().write();
     boot_params.bootloader_name = boot_callbacks()
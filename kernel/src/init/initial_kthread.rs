@@ -2,21 +2,28 @@
 
 use core::sync::atomic::{compiler_fence, Ordering};
 
-use alloc::{ffi::CString, string::ToString};
-use log::{debug, error};
+use alloc::{
+    ffi::CString,
+    string::{String, ToString},
+};
+use log::{debug, error, info};
 use system_error::SystemError;
 
 use crate::{
     arch::{interrupt::TrapFrame, process::arch_switch_to_user},
     driver::{net::e1000e::e1000e::e1000e_init, virtio::virtio::virtio_probe},
-    filesystem::vfs::core::mount_root_fs,
+    filesystem::vfs::{core::mount_root_fs, ROOT_INODE, VFS_MAX_FOLLOW_SYMLINK_TIMES},
+    libs::spinlock::SpinLock,
     net::net_core::net_init,
     process::{kthread::KernelThreadMechanism, stdio::stdio_init, ProcessFlags, ProcessManager},
     smp::smp_init,
     syscall::Syscall,
 };
 
-use super::initcall::do_initcalls;
+use super::{boot_params, initcall::do_initcalls, initramfs::populate_rootfs};
+
+/// initramfs中的init程序的路径。为None时，说明没有使用initramfs作为根文件系统
+static RAMDISK_EXECUTE_COMMAND: SpinLock<Option<String>> = SpinLock::new(None);
 
 pub fn initial_kernel_thread() -> i32 {
     kernel_init().unwrap_or_else(|err| {
@@ -37,8 +44,11 @@ fn kernel_init() -> Result<(), SystemError> {
     crate::driver::disk::ahci::ahci_init()
         .inspect_err(|e| log::error!("ahci_init failed: {:?}", e))
         .ok();
+    crate::driver::block::nvme::nvme_init()
+        .inspect_err(|e| log::error!("nvme_init failed: {:?}", e))
+        .ok();
     virtio_probe();
-    mount_root_fs().expect("Failed to mount root fs");
+    prepare_namespace();
     e1000e_init();
     net_init().unwrap_or_else(|err| {
         error!("Failed to initialize network: {:?}", err);
@@ -60,6 +70,33 @@ fn kenrel_init_freeable() -> Result<(), SystemError> {
     return Ok(());
 }
 
+/// 准备根文件系统
+///
+/// 如果initramfs中存在init程序（由`rdinit=`指定，默认为`/init`），
+/// 则由它负责挂载真正的根文件系统，并通过pivot_root切换过去；
+/// 否则由内核根据命令行参数挂载根文件系统。
+///
+/// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/init/main.c#1566
+fn prepare_namespace() {
+    let rdinit = boot_params()
+        .read()
+        .cmdline_param("rdinit")
+        .unwrap_or("/init")
+        .to_string();
+
+    if populate_rootfs()
+        && ROOT_INODE()
+            .lookup_follow_symlink(&rdinit, VFS_MAX_FOLLOW_SYMLINK_TIMES)
+            .is_ok()
+    {
+        info!("Using initramfs as rootfs, init: {}", rdinit);
+        *RAMDISK_EXECUTE_COMMAND.lock() = Some(rdinit);
+        return;
+    }
+
+    mount_root_fs().expect("Failed to mount root fs");
+}
+
 /// 切换到用户态
 #[inline(never)]
 fn switch_to_user() -> ! {
@@ -73,8 +110,9 @@ fn switch_to_user() -> ! {
     drop(current_pcb);
 
     let mut trap_frame = TrapFrame::new();
-    // 逐个尝试运行init进程
-    if try_to_run_init_process("/bin/dragonreach", &mut trap_frame).is_err()
+    // 优先运行指定的init程序，否则逐个尝试运行默认的init进程
+    if !try_to_run_requested_init(&mut trap_frame)
+        && try_to_run_init_process("/bin/dragonreach", &mut trap_frame).is_err()
         && try_to_run_init_process("/bin/init", &mut trap_frame).is_err()
         && try_to_run_init_process("/bin/sh", &mut trap_frame).is_err()
     {
@@ -87,6 +125,32 @@ fn switch_to_user() -> ! {
     unsafe { arch_switch_to_user(trap_frame) };
 }
 
+/// 尝试运行initramfs中的init程序，以及命令行中`init=`指定的init程序
+///
+/// ## 返回值
+///
+/// - `true`: 成功运行了其中之一
+/// - `false`: 两者均未指定，或initramfs中的init程序运行失败且未指定`init=`
+fn try_to_run_requested_init(trap_frame: &mut TrapFrame) -> bool {
+    let ramdisk_execute_command = RAMDISK_EXECUTE_COMMAND.lock().take();
+    if let Some(path) = ramdisk_execute_command {
+        if try_to_run_init_process(&path, trap_frame).is_ok() {
+            return true;
+        }
+        error!("Failed to execute {} from initramfs", path);
+    }
+
+    let execute_command = boot_params().read().cmdline_param("init").map(String::from);
+    if let Some(path) = execute_command {
+        if try_to_run_init_process(&path, trap_frame).is_ok() {
+            return true;
+        }
+        panic!("Requested init {} failed.", path);
+    }
+
+    return false;
+}
+
 fn try_to_run_init_process(path: &str, trap_frame: &mut TrapFrame) -> Result<(), SystemError> {
     if let Err(e) = run_init_process(path, trap_frame) {
         if e != SystemError::ENOENT {
@@ -0,0 +1,270 @@
+//! initramfs：bootloader加载的cpio归档（newc格式），在挂载根文件系统之前被解压到ramfs中
+//!
+//! 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/init/initramfs.c
+
+use alloc::{collections::BTreeMap, sync::Arc};
+use log::{error, info, warn};
+use system_error::SystemError;
+
+use crate::{
+    arch::MMArch,
+    driver::base::device::device_number::{DeviceNumber, Major},
+    filesystem::vfs::{
+        syscall::ModeType, utils::rsplit_path, FilePrivateData, FileType, IndexNode, ROOT_INODE,
+    },
+    libs::spinlock::SpinLock,
+    mm::MemoryManagementArch,
+    time::PosixTimeSpec,
+};
+
+use super::boot_params;
+
+/// newc格式的cpio头部的大小
+const CPIO_HEADER_SIZE: usize = 110;
+/// 归档结束的标志
+const CPIO_TRAILER: &str = "TRAILER!!!";
+
+/// newc格式的cpio头部
+///
+/// 除了6字节的magic之外，每个字段都是8个十六进制字符
+#[derive(Debug)]
+struct CpioHeader {
+    ino: u32,
+    mode: u32,
+    uid: u32,
+    gid: u32,
+    nlink: u32,
+    mtime: u32,
+    filesize: usize,
+    dev_major: u32,
+    dev_minor: u32,
+    rdev_major: u32,
+    rdev_minor: u32,
+    namesize: usize,
+}
+
+impl CpioHeader {
+    fn parse(buf: &[u8]) -> Result<Self, SystemError> {
+        if buf.len() < CPIO_HEADER_SIZE {
+            return Err(SystemError::EINVAL);
+        }
+        // 070702格式带有校验和，但我们不检查它
+        let magic = &buf[0..6];
+        if magic != b"070701" && magic != b"070702" {
+            return Err(SystemError::EINVAL);
+        }
+
+        let field = |index: usize| -> Result<u32, SystemError> {
+            let start = 6 + index * 8;
+            let s =
+                core::str::from_utf8(&buf[start..start + 8]).map_err(|_| SystemError::EINVAL)?;
+            u32::from_str_radix(s, 16).map_err(|_| SystemError::EINVAL)
+        };
+
+        Ok(Self {
+            ino: field(0)?,
+            mode: field(1)?,
+            uid: field(2)?,
+            gid: field(3)?,
+            nlink: field(4)?,
+            mtime: field(5)?,
+            filesize: field(6)? as usize,
+            dev_major: field(7)?,
+            dev_minor: field(8)?,
+            rdev_major: field(9)?,
+            rdev_minor: field(10)?,
+            namesize: field(11)? as usize,
+        })
+    }
+}
+
+/// cpio中的文件名与文件内容都按4字节对齐
+#[inline(always)]
+fn cpio_align(x: usize) -> usize {
+    (x + 3) & !3
+}
+
+/// 判断initramfs是否被压缩过（目前只支持未压缩的cpio归档）
+fn compression_name(data: &[u8]) -> Option<&'static str> {
+    match data {
+        [0x1f, 0x8b, ..] => Some("gzip"),
+        [0xfd, b'7', b'z', b'X', b'Z', ..] => Some("xz"),
+        [0x28, 0xb5, 0x2f, 0xfd, ..] => Some("zstd"),
+        [b'B', b'Z', b'h', ..] => Some("bzip2"),
+        [0x02, 0x21, 0x4c, 0x18, ..] => Some("lz4"),
+        _ => None,
+    }
+}
+
+struct Unpacker {
+    root: Arc<dyn IndexNode>,
+    /// 记录带有多个硬链接的普通文件：(dev_major, dev_minor, ino) -> 第一次出现时创建的inode
+    hardlinks: BTreeMap<(u32, u32, u32), Arc<dyn IndexNode>>,
+}
+
+impl Unpacker {
+    fn new(root: Arc<dyn IndexNode>) -> Self {
+        Self {
+            root,
+            hardlinks: BTreeMap::new(),
+        }
+    }
+
+    /// 解压一个或多个首尾相接的cpio归档
+    fn unpack(&mut self, data: &[u8]) -> Result<(), SystemError> {
+        let mut pos = 0;
+        while pos < data.len() {
+            // 相接的归档之间可能以0填充
+            if data[pos] == 0 {
+                pos += 1;
+                continue;
+            }
+
+            let header = CpioHeader::parse(&data[pos..])?;
+            let name_start = pos + CPIO_HEADER_SIZE;
+            let name_end = name_start + header.namesize;
+            let data_start = cpio_align(name_end);
+            let data_end = data_start + header.filesize;
+            if header.namesize == 0 || data_end > data.len() {
+                return Err(SystemError::EINVAL);
+            }
+            // 文件名以'\0'结尾
+            let name = core::str::from_utf8(&data[name_start..name_end - 1])
+                .map_err(|_| SystemError::EINVAL)?;
+            pos = cpio_align(data_end);
+
+            if name == CPIO_TRAILER {
+                self.hardlinks.clear();
+                continue;
+            }
+
+            if let Err(e) = self.do_entry(&header, name, &data[data_start..data_end]) {
+                warn!("initramfs: failed to create '{}': {:?}", name, e);
+            }
+        }
+        return Ok(());
+    }
+
+    fn do_entry(
+        &mut self,
+        header: &CpioHeader,
+        name: &str,
+        content: &[u8],
+    ) -> Result<(), SystemError> {
+        let name = name.trim_start_matches("./").trim_matches('/');
+        if name.is_empty() || name == "." {
+            return Ok(());
+        }
+
+        let (filename, parent) = rsplit_path(name);
+        let parent = match parent {
+            Some(parent) => self.root.lookup(parent)?,
+            None => self.root.clone(),
+        };
+
+        let perm = ModeType::from_bits_truncate(header.mode & 0o7777);
+        let file_type = ModeType::from_bits_truncate(header.mode) & ModeType::S_IFMT;
+        let inode = if file_type == ModeType::S_IFDIR {
+            // 目录已经存在时（例如已经挂载了devfs的/dev），保留原来的目录
+            if let Ok(inode) = parent.find(filename) {
+                if inode.metadata()?.file_type == FileType::Dir {
+                    return Ok(());
+                }
+            }
+            parent.mkdir(filename, perm)?
+        } else if file_type == ModeType::S_IFREG {
+            let key = (header.dev_major, header.dev_minor, header.ino);
+            if header.nlink >= 2 {
+                if let Some(target) = self.hardlinks.get(&key) {
+                    parent.link(filename, target)?;
+                    // 硬链接的数据只会出现在其中一项中
+                    if !content.is_empty() {
+                        Self::write_content(target, content)?;
+                    }
+                    return Ok(());
+                }
+            }
+            let inode = parent.create(filename, FileType::File, perm)?;
+            Self::write_content(&inode, content)?;
+            if header.nlink >= 2 {
+                self.hardlinks.insert(key, inode.clone());
+            }
+            inode
+        } else if file_type == ModeType::S_IFLNK {
+            let inode = parent.create(filename, FileType::SymLink, perm)?;
+            Self::write_content(&inode, content)?;
+            inode
+        } else if file_type == ModeType::S_IFCHR || file_type == ModeType::S_IFBLK {
+            let dev = DeviceNumber::new(Major::new(header.rdev_major), header.rdev_minor);
+            let file_type = if file_type == ModeType::S_IFCHR {
+                FileType::CharDevice
+            } else {
+                FileType::BlockDevice
+            };
+            parent.create_with_data(filename, file_type, perm, dev.data() as usize)?
+        } else if file_type == ModeType::S_IFIFO {
+            parent.mknod(filename, perm | ModeType::S_IFIFO, DeviceNumber::default())?
+        } else {
+            warn!("initramfs: unsupported file type of '{}', skipped", name);
+            return Ok(());
+        };
+
+        let mut metadata = inode.metadata()?;
+        metadata.uid = header.uid as usize;
+        metadata.gid = header.gid as usize;
+        metadata.mtime = PosixTimeSpec::new(header.mtime as i64, 0);
+        metadata.ctime = metadata.mtime;
+        metadata.atime = metadata.mtime;
+        inode.set_metadata(&metadata)?;
+
+        return Ok(());
+    }
+
+    fn write_content(inode: &Arc<dyn IndexNode>, content: &[u8]) -> Result<(), SystemError> {
+        inode.write_at(
+            0,
+            content.len(),
+            content,
+            SpinLock::new(FilePrivateData::Unused).lock(),
+        )?;
+        return Ok(());
+    }
+}
+
+/// 把bootloader加载的initramfs解压到根文件系统（ramfs）中
+///
+/// ## 返回值
+///
+/// - `true`: 存在initramfs，并且已经将其解压
+/// - `false`: 没有initramfs，或者无法识别其格式
+pub fn populate_rootfs() -> bool {
+    let Some((base, size)) = boot_params().read().initrd else {
+        return false;
+    };
+    if size == 0 {
+        return false;
+    }
+
+    let Some(vaddr) = (unsafe { MMArch::phys_2_virt(base) }) else {
+        error!("initramfs: invalid physical address {:?}", base);
+        return false;
+    };
+    // SAFETY: initramfs所在的物理内存已经被保留，并且位于内核的线性映射区域内
+    let data = unsafe { core::slice::from_raw_parts(vaddr.data() as *const u8, size) };
+
+    if let Some(name) = compression_name(data) {
+        error!(
+            "initramfs: {} compressed initramfs is not supported, please use an uncompressed cpio archive",
+            name
+        );
+        return false;
+    }
+
+    info!("Unpacking initramfs: base={:?}, size={:#x}", base, size);
+    if let Err(e) = Unpacker::new(ROOT_INODE()).unpack(data) {
+        error!("Initramfs unpacking failed: {:?}", e);
+        return false;
+    }
+    info!("Initramfs unpacked.");
+    return true;
+}
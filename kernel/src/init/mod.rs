@@ -6,6 +6,7 @@ pub mod boot;
 pub mod init;
 pub mod initcall;
 pub mod initial_kthread;
+pub mod initramfs;
 
 /// 启动参数
 static BOOT_PARAMS: RwLock<BootParams> = RwLock::new(BootParams::new());
@@ -178,6 +178,168 @@ impl LockedPipeInode {
         let inode = self.inner.lock();
         return !inode.buf_full() || inode.reader == 0;
     }
+
+    /// # 等待管道中有数据可读（供splice与tee使用）
+    ///
+    /// ## 返回值
+    ///
+    /// - `Ok(true)`: 管道中有数据
+    /// - `Ok(false)`: 管道为空，并且已经没有写者
+    pub fn wait_for_data(&self, nonblock: bool) -> Result<bool, SystemError> {
+        loop {
+            let inode = self.inner.lock();
+            if inode.valid_cnt > 0 {
+                return Ok(true);
+            }
+            if inode.writer == 0 {
+                return Ok(false);
+            }
+            drop(inode);
+
+            if nonblock {
+                return Err(SystemError::EAGAIN_OR_EWOULDBLOCK);
+            }
+            let r = wq_wait_event_interruptible!(self.read_wait_queue, self.readable(), {});
+            if r.is_err() {
+                return Err(SystemError::ERESTARTSYS);
+            }
+        }
+    }
+
+    /// # 等待管道中有空闲空间（供splice与tee使用）
+    ///
+    /// ## 返回值
+    ///
+    /// - `Ok(usize)`: 管道中空闲空间的大小
+    /// - `Err(SystemError::EPIPE)`: 已经没有读者
+    pub fn wait_for_space(&self, nonblock: bool) -> Result<usize, SystemError> {
+        loop {
+            let inode = self.inner.lock();
+            if inode.reader == 0 {
+                return Err(SystemError::EPIPE);
+            }
+            if !inode.buf_full() {
+                return Ok(PIPE_BUFF_SIZE - inode.valid_cnt as usize);
+            }
+            drop(inode);
+
+            if nonblock {
+                return Err(SystemError::EAGAIN_OR_EWOULDBLOCK);
+            }
+            let r = wq_wait_event_interruptible!(self.write_wait_queue, self.writeable(), {});
+            if r.is_err() {
+                return Err(SystemError::ERESTARTSYS);
+            }
+        }
+    }
+
+    /// 把管道头部的数据复制到`buf`中，但不将其取出，返回复制的字节数
+    pub fn peek(&self, buf: &mut [u8]) -> usize {
+        let inode = self.inner.lock();
+        let num = buf.len().min(inode.valid_cnt as usize);
+        let start = inode.read_pos as usize;
+        let first = num.min(PIPE_BUFF_SIZE - start);
+        buf[..first].copy_from_slice(&inode.data[start..start + first]);
+        buf[first..num].copy_from_slice(&inode.data[..num - first]);
+        return num;
+    }
+
+    /// 丢弃管道头部`len`字节的数据，一般在[`LockedPipeInode::peek`]之后调用
+    ///
+    /// TODO: peek与consume之间没有阻止其他读者读取管道，两者并发时数据可能被重复读出
+    pub fn consume(&self, len: usize) -> Result<(), SystemError> {
+        let mut inode = self.inner.lock();
+        let len = len.min(inode.valid_cnt as usize);
+        inode.read_pos = (inode.read_pos + len as i32) % PIPE_BUFF_SIZE as i32;
+        inode.valid_cnt -= len as i32;
+        return self.wakeup_all(&inode);
+    }
+
+    /// 向管道写入尽可能多的数据，不会阻塞，返回写入的字节数
+    pub fn push(&self, buf: &[u8]) -> Result<usize, SystemError> {
+        let mut inode = self.inner.lock();
+        if inode.reader == 0 {
+            return Err(SystemError::EPIPE);
+        }
+        let num = buf.len().min(PIPE_BUFF_SIZE - inode.valid_cnt as usize);
+        let start = inode.write_pos as usize;
+        let first = num.min(PIPE_BUFF_SIZE - start);
+        inode.data[start..start + first].copy_from_slice(&buf[..first]);
+        inode.data[..num - first].copy_from_slice(&buf[first..num]);
+        inode.write_pos = (inode.write_pos + num as i32) % PIPE_BUFF_SIZE as i32;
+        inode.valid_cnt += num as i32;
+        self.wakeup_all(&inode)?;
+        return Ok(num);
+    }
+
+    /// # 把管道头部最多`len`字节的数据直接复制到管道`dst`中（供splice与tee使用）
+    ///
+    /// 数据在两个管道的环形缓冲区之间直接复制，不经过中转缓冲区。
+    /// `consume`为true时从当前管道中取出这些数据（splice），否则保留（tee）
+    ///
+    /// ## 返回值
+    ///
+    /// - `Ok(usize)`: 复制的字节数
+    /// - `Err(SystemError::EPIPE)`: `dst`已经没有读者
+    pub fn transfer_to(
+        &self,
+        dst: &LockedPipeInode,
+        len: usize,
+        consume: bool,
+    ) -> Result<usize, SystemError> {
+        // 按照地址顺序加锁，避免两个方向同时传输时死锁
+        let (mut src_inode, mut dst_inode) = if (self as *const Self) < (dst as *const Self) {
+            let src_inode = self.inner.lock();
+            (src_inode, dst.inner.lock())
+        } else {
+            let dst_inode = dst.inner.lock();
+            (self.inner.lock(), dst_inode)
+        };
+        if dst_inode.reader == 0 {
+            return Err(SystemError::EPIPE);
+        }
+
+        let num = len
+            .min(src_inode.valid_cnt as usize)
+            .min(PIPE_BUFF_SIZE - dst_inode.valid_cnt as usize);
+        let mut copied = 0;
+        while copied < num {
+            let rpos = (src_inode.read_pos as usize + copied) % PIPE_BUFF_SIZE;
+            let wpos = dst_inode.write_pos as usize;
+            let chunk = (num - copied)
+                .min(PIPE_BUFF_SIZE - rpos)
+                .min(PIPE_BUFF_SIZE - wpos);
+            dst_inode.data[wpos..wpos + chunk].copy_from_slice(&src_inode.data[rpos..rpos + chunk]);
+            dst_inode.write_pos = ((wpos + chunk) % PIPE_BUFF_SIZE) as i32;
+            dst_inode.valid_cnt += chunk as i32;
+            copied += chunk;
+        }
+        if consume {
+            src_inode.read_pos = (src_inode.read_pos + num as i32) % PIPE_BUFF_SIZE as i32;
+            src_inode.valid_cnt -= num as i32;
+        }
+
+        self.wakeup_all(&src_inode)?;
+        dst.wakeup_all(&dst_inode)?;
+        return Ok(num);
+    }
+
+    /// 内核直接读写管道中的数据之后，唤醒等待的读者、写者以及epoll
+    fn wakeup_all(&self, inode: &InnerPipeInode) -> Result<(), SystemError> {
+        self.read_wait_queue
+            .wakeup_all(Some(ProcessState::Blocked(true)));
+        self.write_wait_queue
+            .wakeup_all(Some(ProcessState::Blocked(true)));
+
+        let mut events = EPollEventType::empty();
+        if inode.valid_cnt != 0 {
+            events |= EPollEventType::EPOLLIN | EPollEventType::EPOLLRDNORM;
+        }
+        if !inode.buf_full() {
+            events |= EPollEventType::EPOLLOUT | EPollEventType::EPOLLWRNORM;
+        }
+        return EventPoll::wakeup_epoll(&inode.epitems, events);
+    }
 }
 
 impl IndexNode for LockedPipeInode {
@@ -1,48 +1,28 @@
 use crate::{
     arch::mm::LockedFrameAllocator,
     filesystem::vfs::syscall::ModeType,
-    libs::{
-        align::page_align_up,
-        spinlock::{SpinLock, SpinLockGuard},
-    },
+    libs::align::page_align_up,
     mm::{
         allocator::page_frame::{FrameAllocator, PageFrameCount, PhysPageFrame},
         page::{page_manager_lock_irqsave, Page},
         PhysAddr,
     },
-    process::{Pid, ProcessManager},
+    process::{namespace::ipc::IpcNamespace, Pid, ProcessManager},
     syscall::user_access::{UserBufferReader, UserBufferWriter},
     time::PosixTimeSpec,
 };
-use alloc::{sync::Arc, vec::Vec};
-use core::sync::atomic::{compiler_fence, Ordering};
+use alloc::{
+    sync::{Arc, Weak},
+    vec::Vec,
+};
 use hashbrown::{HashMap, HashSet};
 use ida::IdAllocator;
-use log::info;
 use num::ToPrimitive;
 use system_error::SystemError;
 
-pub static mut SHM_MANAGER: Option<SpinLock<ShmManager>> = None;
-
 /// 用于创建新的私有IPC对象
 pub const IPC_PRIVATE: ShmKey = ShmKey::new(0);
 
-/// 初始化SHM_MANAGER
-pub fn shm_manager_init() {
-    info!("shm_manager_init");
-    let shm_manager = SpinLock::new(ShmManager::new());
-
-    compiler_fence(Ordering::SeqCst);
-    unsafe { SHM_MANAGER = Some(shm_manager) };
-    compiler_fence(Ordering::SeqCst);
-
-    info!("shm_manager_init done");
-}
-
-pub fn shm_manager_lock() -> SpinLockGuard<'static, ShmManager> {
-    unsafe { SHM_MANAGER.as_ref().unwrap().lock() }
-}
-
 int_like!(ShmId, usize);
 int_like!(ShmKey, usize);
 
@@ -120,14 +100,17 @@ pub struct ShmManager {
     id2shm: HashMap<ShmId, KernelShm>,
     /// ShmKey映射ShmId表
     key2id: HashMap<ShmKey, ShmId>,
+    /// 当前共享内存管理器所属的IPC namespace
+    ipc_ns: Weak<IpcNamespace>,
 }
 
 impl ShmManager {
-    pub fn new() -> Self {
+    pub fn new(ipc_ns: Weak<IpcNamespace>) -> Self {
         ShmManager {
             id_allocator: IdAllocator::new(0, usize::MAX - 1).unwrap(),
             id2shm: HashMap::new(),
             key2id: HashMap::new(),
+            ipc_ns,
         }
     }
 
@@ -166,7 +149,7 @@ impl ShmManager {
         let mut cur_phys = PhysPageFrame::new(phys_page.0);
         for _ in 0..page_count.data() {
             let page = Arc::new(Page::new(true, cur_phys.phys_address()));
-            page.write_irqsave().set_shm_id(shm_id);
+            page.write_irqsave().set_shm_id(shm_id, self.ipc_ns.clone());
             let paddr = cur_phys.phys_address();
             page_manager_guard.insert(paddr, &page);
             cur_phys = cur_phys.next();
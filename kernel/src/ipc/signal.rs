@@ -8,7 +8,9 @@ use crate::{
     arch::ipc::signal::{SigCode, SigFlags, SigSet, Signal},
     ipc::signal_types::SigactionType,
     libs::spinlock::SpinLockGuard,
-    process::{pid::PidType, Pid, ProcessControlBlock, ProcessFlags, ProcessManager},
+    process::{
+        exit::StopEvent, pid::PidType, Pid, ProcessControlBlock, ProcessFlags, ProcessManager,
+    },
 };
 
 use super::signal_types::{
@@ -82,9 +84,24 @@ impl Signal {
 
         let pcb = pcb.unwrap();
         // println!("Target pcb = {:?}", pcb.as_ref().unwrap());
+        retval = self.send_signal_info_to_pcb(info, pcb);
+        return retval;
+    }
+
+    /// 向已经找到的目标进程发送信号
+    ///
+    /// 用于调用者已经持有pcb的场景（例如pidfd），避免pid被复用后把信号发给无关的进程
+    pub fn send_signal_info_to_pcb(
+        &self,
+        info: Option<&mut SigInfo>,
+        pcb: Arc<ProcessControlBlock>,
+    ) -> Result<i32, SystemError> {
+        if !self.is_valid() {
+            return Err(SystemError::EINVAL);
+        }
         compiler_fence(core::sync::atomic::Ordering::SeqCst);
         // 发送信号
-        retval = self.send_signal(info, pcb.clone(), PidType::PID);
+        let retval = self.send_signal(info, pcb, PidType::PID);
 
         compiler_fence(core::sync::atomic::Ordering::SeqCst);
         return retval;
@@ -283,6 +300,14 @@ impl Signal {
             pcb.sig_info_mut()
                 .sig_shared_pending_mut()
                 .flush_by_mask(&flush);
+            if pcb
+                .sched_info()
+                .inner_lock_read_irqsave()
+                .state()
+                .is_stopped()
+            {
+                pcb.notify_parent_cldstop(StopEvent::Continued);
+            }
             let _r = ProcessManager::wakeup_stop(&pcb);
             // TODO 对每个子线程 flush mask
             // 这里需要补充一段逻辑，详见https://code.dragonos.org.cn/xref/linux-6.1.9/kernel/signal.c#952
@@ -469,3 +494,37 @@ pub fn set_current_sig_blocked(new_set: &mut SigSet) {
     recalc_sigpending();
     drop(guard);
 }
+
+/// 临时替换当前进程的屏蔽信号，原来的屏蔽信号保存在saved_sigmask中。
+/// 供pselect、epoll_pwait等在等待期间使用用户指定的屏蔽信号的系统调用使用
+///
+/// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/kernel/signal.c#3202
+pub fn set_user_sigmask(new_set: &mut SigSet) {
+    let pcb = ProcessManager::current_pcb();
+    let old_set = *pcb.sig_info_irqsave().sig_block();
+    set_current_sig_blocked(new_set);
+    pcb.sig_info_mut().set_saved_sigmask(old_set);
+}
+
+/// 恢复由[`set_user_sigmask`]保存的屏蔽信号
+pub fn restore_saved_sigmask() {
+    let saved = ProcessManager::current_pcb()
+        .sig_info_mut()
+        .take_saved_sigmask();
+    if let Some(mut saved) = saved {
+        set_current_sig_blocked(&mut saved);
+    }
+}
+
+/// 系统调用返回前恢复由[`set_user_sigmask`]保存的屏蔽信号
+///
+/// 如果系统调用被信号打断，那么要先在临时的屏蔽信号下处理信号，
+/// 等到信号处理程序返回时才恢复原来的屏蔽信号，因此这时保留saved_sigmask，由信号处理流程来恢复
+///
+/// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/include/linux/sched/signal.h#539
+pub fn restore_saved_sigmask_unless(interrupted: bool) {
+    if interrupted {
+        return;
+    }
+    restore_saved_sigmask();
+}
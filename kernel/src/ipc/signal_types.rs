@@ -341,13 +341,21 @@ impl SigInfo {
 pub enum SigType {
     Kill(Pid),
     Alarm(Pid),
+    /// seccomp过滤器阻止了系统调用
+    SigSys {
+        /// 系统调用指令的地址
+        call_addr: usize,
+        /// 系统调用号
+        syscall: i32,
+        /// 系统调用所属的架构（AUDIT_ARCH_*）
+        arch: u32,
+    },
     // 后续完善下列中的具体字段
     // Timer,
     // Rt,
     // SigChild,
     // SigFault,
     // SigPoll,
-    // SigSys,
 }
 
 impl SigInfo {
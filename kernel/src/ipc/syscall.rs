@@ -3,6 +3,7 @@ use core::{
     sync::atomic::compiler_fence,
 };
 
+use alloc::sync::Arc;
 use log::{error, warn};
 use system_error::SystemError;
 
@@ -15,7 +16,7 @@ use crate::{
         file::{File, FileMode},
         FilePrivateData,
     },
-    ipc::shm::{shm_manager_lock, IPC_PRIVATE},
+    ipc::shm::IPC_PRIVATE,
     libs::align::page_align_up,
     libs::spinlock::SpinLock,
     mm::{
@@ -25,7 +26,7 @@ use crate::{
         ucontext::{AddressSpace, VMA},
         VirtAddr, VmFlags,
     },
-    process::{Pid, ProcessManager},
+    process::{cred::CAPFlags, Pid, ProcessControlBlock, ProcessManager},
     syscall::{
         user_access::{UserBufferReader, UserBufferWriter},
         Syscall,
@@ -92,6 +93,97 @@ impl Syscall {
         Ok(0)
     }
 
+    /// 检查当前进程是否有权限向`target`发送信号
+    ///
+    /// 发送者的ruid或euid与接收者的ruid或suid相同，或者发送者拥有CAP_KILL时才允许发送
+    ///
+    /// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/kernel/signal.c#800
+    pub fn check_kill_permission(target: &Arc<ProcessControlBlock>) -> Result<(), SystemError> {
+        let cred = ProcessManager::current_pcb().cred();
+        let tcred = target.cred();
+        if cred.euid == tcred.suid
+            || cred.euid == tcred.uid
+            || cred.uid == tcred.suid
+            || cred.uid == tcred.uid
+            || cred.has_capability(CAPFlags::CAP_KILL)
+        {
+            return Ok(());
+        }
+        return Err(SystemError::EPERM);
+    }
+
+    /// # kill系统调用
+    ///
+    /// 根据`pid`的取值向一个进程、一个进程组或者多个进程发送信号，`pid`都是当前pid namespace中的编号：
+    ///
+    /// - `pid > 0`: 发送给进程`pid`
+    /// - `pid == 0`: 发送给当前进程所在进程组中的所有进程
+    /// - `pid == -1`: 发送给当前进程有权限发送信号的所有进程，1号进程和当前进程除外
+    /// - `pid < -1`: 发送给进程组`-pid`中的所有进程
+    ///
+    /// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/kernel/signal.c#kill_something_info
+    pub fn kill_something(pid: i32, sig: c_int) -> Result<usize, SystemError> {
+        if pid > 0 {
+            // 将当前pid namespace中的pid转换为全局pid
+            let pcb =
+                ProcessManager::find_vpid(Pid::new(pid as usize)).ok_or(SystemError::ESRCH)?;
+            Self::check_kill_permission(&pcb)?;
+            return Self::kill(pcb.pid(), sig);
+        }
+        // -INT_MIN无法表示为进程组号
+        if pid == i32::MIN {
+            return Err(SystemError::ESRCH);
+        }
+        if Signal::from(sig) == Signal::INVALID {
+            return Err(SystemError::EINVAL);
+        }
+
+        let current = ProcessManager::current_pcb();
+        let ns = current.active_pid_ns();
+        // 只有在当前pid namespace中可见的进程才能成为目标
+        let visible = ProcessManager::processes()
+            .into_iter()
+            .filter(|pcb| pcb.pid_nr_ns(&ns).is_some());
+
+        if pid == -1 {
+            let mut retval = Ok(0);
+            let mut count = 0;
+            for pcb in visible {
+                if pcb.pid_nr_ns(&ns) == Some(Pid::new(1)) || pcb.tgid() == current.tgid() {
+                    continue;
+                }
+                let r = Self::check_kill_permission(&pcb).and_then(|_| Self::kill(pcb.pid(), sig));
+                if let Err(e) = r {
+                    if e != SystemError::EPERM {
+                        retval = Err(e);
+                    }
+                }
+                count += 1;
+            }
+            return if count > 0 {
+                retval
+            } else {
+                Err(SystemError::ESRCH)
+            };
+        }
+
+        let pgid = if pid == 0 {
+            current.basic().pgid()
+        } else {
+            ns.global_pid(Pid::new(pid.unsigned_abs() as usize))
+                .ok_or(SystemError::ESRCH)?
+        };
+        // 只要有一个进程发送成功就返回成功，否则返回最后一个错误
+        let mut retval = Err(SystemError::ESRCH);
+        for pcb in visible.filter(|pcb| pcb.basic().pgid() == pgid) {
+            let r = Self::check_kill_permission(&pcb).and_then(|_| Self::kill(pcb.pid(), sig));
+            if retval.is_err() {
+                retval = r;
+            }
+        }
+        return retval;
+    }
+
     pub fn kill(pid: Pid, sig: c_int) -> Result<usize, SystemError> {
         let sig = Signal::from(sig);
         if sig == Signal::INVALID {
@@ -265,7 +357,8 @@ impl Syscall {
             return Err(SystemError::ENOSYS);
         }
 
-        let mut shm_manager_guard = shm_manager_lock();
+        let ipc_ns = ProcessManager::current_pcb().nsproxy().ipc_ns.clone();
+        let mut shm_manager_guard = ipc_ns.shm_manager_lock();
         match key {
             // 创建共享内存段
             IPC_PRIVATE => shm_manager_guard.add(key, size, shmflg),
@@ -306,7 +399,8 @@ impl Syscall {
     /// 成功：映射到共享内存的虚拟内存区域起始地址
     /// 失败：错误码
     pub fn shmat(id: ShmId, vaddr: VirtAddr, shmflg: ShmFlags) -> Result<usize, SystemError> {
-        let mut shm_manager_guard = shm_manager_lock();
+        let ipc_ns = ProcessManager::current_pcb().nsproxy().ipc_ns.clone();
+        let mut shm_manager_guard = ipc_ns.shm_manager_lock();
         let current_address_space = AddressSpace::current()?;
         let mut address_write_guard = current_address_space.write();
 
@@ -444,10 +538,12 @@ impl Syscall {
         let mut page_manager_guard = page_manager_lock_irqsave();
         let page = page_manager_guard.get(&paddr).ok_or(SystemError::EINVAL)?;
         let shm_id = page.read_irqsave().shm_id().ok_or(SystemError::EINVAL)?;
+        // 共享页可能是在其它IPC namespace中连接的，因此使用共享页所属的namespace
+        let ipc_ns = page.read_irqsave().ipc_ns().ok_or(SystemError::EINVAL)?;
         drop(page_manager_guard);
 
         // 获取对应共享页管理信息
-        let mut shm_manager_guard = shm_manager_lock();
+        let mut shm_manager_guard = ipc_ns.shm_manager_lock();
         let kernel_shm = shm_manager_guard
             .get_mut(&shm_id)
             .ok_or(SystemError::EINVAL)?;
@@ -481,7 +577,8 @@ impl Syscall {
         user_buf: *const u8,
         from_user: bool,
     ) -> Result<usize, SystemError> {
-        let mut shm_manager_guard = shm_manager_lock();
+        let ipc_ns = ProcessManager::current_pcb().nsproxy().ipc_ns.clone();
+        let mut shm_manager_guard = ipc_ns.shm_manager_lock();
 
         match cmd {
             // 查看共享内存元信息
@@ -46,6 +46,7 @@ mod arch;
 mod libs;
 #[macro_use]
 mod include;
+mod cgroup;
 mod debug;
 mod driver; // 如果driver依赖了libs，应该在libs后面导出
 mod exception;
@@ -0,0 +1,180 @@
+//! BLAKE2s哈希函数（RFC 7693）
+//!
+//! 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/lib/crypto/blake2s.c
+
+/// BLAKE2s的分组大小（字节）
+pub const BLAKE2S_BLOCK_SIZE: usize = 64;
+/// BLAKE2s的最大输出长度（字节）
+pub const BLAKE2S_HASH_SIZE: usize = 32;
+/// BLAKE2s的最大密钥长度（字节）
+pub const BLAKE2S_KEY_SIZE: usize = 32;
+
+const BLAKE2S_IV: [u32; 8] = [
+    0x6a09_e667,
+    0xbb67_ae85,
+    0x3c6e_f372,
+    0xa54f_f53a,
+    0x510e_527f,
+    0x9b05_688c,
+    0x1f83_d9ab,
+    0x5be0_cd19,
+];
+
+const BLAKE2S_SIGMA: [[usize; 16]; 10] = [
+    [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15],
+    [14, 10, 4, 8, 9, 15, 13, 6, 1, 12, 0, 2, 11, 7, 5, 3],
+    [11, 8, 12, 0, 5, 2, 15, 13, 10, 14, 3, 6, 7, 1, 9, 4],
+    [7, 9, 3, 1, 13, 12, 11, 14, 2, 6, 5, 10, 4, 0, 15, 8],
+    [9, 0, 5, 7, 2, 4, 10, 15, 14, 1, 11, 12, 6, 8, 3, 13],
+    [2, 12, 6, 10, 0, 11, 8, 3, 4, 13, 7, 5, 15, 14, 1, 9],
+    [12, 5, 1, 15, 14, 13, 4, 10, 0, 7, 6, 3, 9, 2, 8, 11],
+    [13, 11, 7, 14, 12, 1, 3, 9, 5, 0, 15, 4, 8, 6, 2, 10],
+    [6, 15, 14, 9, 11, 3, 0, 8, 12, 2, 13, 7, 1, 4, 10, 5],
+    [10, 2, 8, 4, 7, 6, 1, 5, 15, 11, 9, 14, 3, 12, 13, 0],
+];
+
+/// BLAKE2s的哈希状态
+#[derive(Clone)]
+pub struct Blake2sState {
+    h: [u32; 8],
+    t: [u32; 2],
+    f: [u32; 2],
+    buf: [u8; BLAKE2S_BLOCK_SIZE],
+    buflen: usize,
+    outlen: usize,
+}
+
+impl Blake2sState {
+    /// 创建一个不带密钥的哈希状态
+    pub const fn new(outlen: usize) -> Self {
+        let mut h = BLAKE2S_IV;
+        h[0] ^= 0x0101_0000 ^ outlen as u32;
+        Self {
+            h,
+            t: [0; 2],
+            f: [0; 2],
+            buf: [0; BLAKE2S_BLOCK_SIZE],
+            buflen: 0,
+            outlen,
+        }
+    }
+
+    /// 创建一个带密钥的哈希状态（用作PRF）
+    pub fn new_keyed(outlen: usize, key: &[u8]) -> Self {
+        assert!(outlen > 0 && outlen <= BLAKE2S_HASH_SIZE);
+        assert!(!key.is_empty() && key.len() <= BLAKE2S_KEY_SIZE);
+
+        let mut state = Self::new(outlen);
+        state.h[0] ^= (key.len() as u32) << 8;
+        state.buf[..key.len()].copy_from_slice(key);
+        state.buflen = BLAKE2S_BLOCK_SIZE;
+        state
+    }
+
+    fn increment_counter(&mut self, inc: u32) {
+        let (t0, carry) = self.t[0].overflowing_add(inc);
+        self.t[0] = t0;
+        if carry {
+            self.t[1] = self.t[1].wrapping_add(1);
+        }
+    }
+
+    fn compress(&mut self, block: &[u8; BLAKE2S_BLOCK_SIZE]) {
+        let mut m = [0u32; 16];
+        for (i, chunk) in block.chunks_exact(4).enumerate() {
+            m[i] = u32::from_le_bytes(chunk.try_into().unwrap());
+        }
+
+        let mut v = [0u32; 16];
+        v[..8].copy_from_slice(&self.h);
+        v[8..12].copy_from_slice(&BLAKE2S_IV[..4]);
+        v[12] = self.t[0] ^ BLAKE2S_IV[4];
+        v[13] = self.t[1] ^ BLAKE2S_IV[5];
+        v[14] = self.f[0] ^ BLAKE2S_IV[6];
+        v[15] = self.f[1] ^ BLAKE2S_IV[7];
+
+        #[inline(always)]
+        fn g(v: &mut [u32; 16], a: usize, b: usize, c: usize, d: usize, x: u32, y: u32) {
+            v[a] = v[a].wrapping_add(v[b]).wrapping_add(x);
+            v[d] = (v[d] ^ v[a]).rotate_right(16);
+            v[c] = v[c].wrapping_add(v[d]);
+            v[b] = (v[b] ^ v[c]).rotate_right(12);
+            v[a] = v[a].wrapping_add(v[b]).wrapping_add(y);
+            v[d] = (v[d] ^ v[a]).rotate_right(8);
+            v[c] = v[c].wrapping_add(v[d]);
+            v[b] = (v[b] ^ v[c]).rotate_right(7);
+        }
+
+        for s in BLAKE2S_SIGMA.iter() {
+            g(&mut v, 0, 4, 8, 12, m[s[0]], m[s[1]]);
+            g(&mut v, 1, 5, 9, 13, m[s[2]], m[s[3]]);
+            g(&mut v, 2, 6, 10, 14, m[s[4]], m[s[5]]);
+            g(&mut v, 3, 7, 11, 15, m[s[6]], m[s[7]]);
+            g(&mut v, 0, 5, 10, 15, m[s[8]], m[s[9]]);
+            g(&mut v, 1, 6, 11, 12, m[s[10]], m[s[11]]);
+            g(&mut v, 2, 7, 8, 13, m[s[12]], m[s[13]]);
+            g(&mut v, 3, 4, 9, 14, m[s[14]], m[s[15]]);
+        }
+
+        for i in 0..8 {
+            self.h[i] ^= v[i] ^ v[i + 8];
+        }
+    }
+
+    /// 向哈希状态中追加数据
+    ///
+    /// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/lib/crypto/blake2s.c#24
+    pub fn update(&mut self, mut data: &[u8]) {
+        if data.is_empty() {
+            return;
+        }
+
+        let fill = BLAKE2S_BLOCK_SIZE - self.buflen;
+        if data.len() > fill {
+            self.buf[self.buflen..].copy_from_slice(&data[..fill]);
+            self.increment_counter(BLAKE2S_BLOCK_SIZE as u32);
+            let block = self.buf;
+            self.compress(&block);
+            self.buflen = 0;
+            data = &data[fill..];
+        }
+
+        // 最后一个分组需要留到final时再压缩，因为它需要设置结束标志
+        while data.len() > BLAKE2S_BLOCK_SIZE {
+            self.increment_counter(BLAKE2S_BLOCK_SIZE as u32);
+            self.compress(data[..BLAKE2S_BLOCK_SIZE].try_into().unwrap());
+            data = &data[BLAKE2S_BLOCK_SIZE..];
+        }
+
+        self.buf[self.buflen..self.buflen + data.len()].copy_from_slice(data);
+        self.buflen += data.len();
+    }
+
+    /// 结束哈希计算，把结果写入out（长度为创建时指定的outlen）
+    ///
+    /// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/lib/crypto/blake2s.c#46
+    pub fn finalize(mut self, out: &mut [u8]) {
+        self.f[0] = u32::MAX;
+        self.buf[self.buflen..].fill(0);
+        self.increment_counter(self.buflen as u32);
+        let block = self.buf;
+        self.compress(&block);
+
+        let mut hash = [0u8; BLAKE2S_HASH_SIZE];
+        for (i, chunk) in hash.chunks_exact_mut(4).enumerate() {
+            chunk.copy_from_slice(&self.h[i].to_le_bytes());
+        }
+        out[..self.outlen].copy_from_slice(&hash[..self.outlen]);
+    }
+}
+
+/// 一次性计算（可带密钥的）BLAKE2s哈希
+pub fn blake2s(out: &mut [u8], data: &[u8], key: &[u8]) {
+    let mut state = if key.is_empty() {
+        Blake2sState::new(out.len())
+    } else {
+        Blake2sState::new_keyed(out.len(), key)
+    };
+    state.update(data);
+    state.finalize(out);
+}
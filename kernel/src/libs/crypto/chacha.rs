@@ -0,0 +1,73 @@
+//! ChaCha20分组函数
+//!
+//! 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/lib/crypto/chacha.c
+
+/// ChaCha的状态字数
+pub const CHACHA_STATE_WORDS: usize = 16;
+/// ChaCha的分组大小（字节）
+pub const CHACHA_BLOCK_SIZE: usize = 64;
+/// ChaCha的密钥大小（字节）
+pub const CHACHA_KEY_SIZE: usize = 32;
+
+/// "expand 32-byte k"
+const CHACHA_CONSTANTS: [u32; 4] = [0x6170_7865, 0x3320_646e, 0x7962_2d32, 0x6b20_6574];
+
+#[inline(always)]
+fn quarter_round(x: &mut [u32; CHACHA_STATE_WORDS], a: usize, b: usize, c: usize, d: usize) {
+    x[a] = x[a].wrapping_add(x[b]);
+    x[d] = (x[d] ^ x[a]).rotate_left(16);
+    x[c] = x[c].wrapping_add(x[d]);
+    x[b] = (x[b] ^ x[c]).rotate_left(12);
+    x[a] = x[a].wrapping_add(x[b]);
+    x[d] = (x[d] ^ x[a]).rotate_left(8);
+    x[c] = x[c].wrapping_add(x[d]);
+    x[b] = (x[b] ^ x[c]).rotate_left(7);
+}
+
+/// 使用密钥、64位计数器和64位nonce初始化ChaCha状态
+///
+/// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/include/crypto/chacha.h#68
+pub fn chacha_init(
+    key: &[u8; CHACHA_KEY_SIZE],
+    counter: u64,
+    nonce: u64,
+) -> [u32; CHACHA_STATE_WORDS] {
+    let mut state = [0u32; CHACHA_STATE_WORDS];
+    state[..4].copy_from_slice(&CHACHA_CONSTANTS);
+    for (i, chunk) in key.chunks_exact(4).enumerate() {
+        state[4 + i] = u32::from_le_bytes(chunk.try_into().unwrap());
+    }
+    state[12] = counter as u32;
+    state[13] = (counter >> 32) as u32;
+    state[14] = nonce as u32;
+    state[15] = (nonce >> 32) as u32;
+    state
+}
+
+/// 生成一个ChaCha20密钥流分组，并把状态中的64位计数器加一
+///
+/// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/lib/crypto/chacha.c#66
+pub fn chacha20_block(state: &mut [u32; CHACHA_STATE_WORDS], out: &mut [u8; CHACHA_BLOCK_SIZE]) {
+    let mut x = *state;
+    for _ in 0..10 {
+        quarter_round(&mut x, 0, 4, 8, 12);
+        quarter_round(&mut x, 1, 5, 9, 13);
+        quarter_round(&mut x, 2, 6, 10, 14);
+        quarter_round(&mut x, 3, 7, 11, 15);
+
+        quarter_round(&mut x, 0, 5, 10, 15);
+        quarter_round(&mut x, 1, 6, 11, 12);
+        quarter_round(&mut x, 2, 7, 8, 13);
+        quarter_round(&mut x, 3, 4, 9, 14);
+    }
+
+    for (i, chunk) in out.chunks_exact_mut(4).enumerate() {
+        chunk.copy_from_slice(&x[i].wrapping_add(state[i]).to_le_bytes());
+    }
+
+    let (counter, carry) = state[12].overflowing_add(1);
+    state[12] = counter;
+    if carry {
+        state[13] = state[13].wrapping_add(1);
+    }
+}
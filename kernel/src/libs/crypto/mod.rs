@@ -0,0 +1,6 @@
+//! 内核中使用的密码学原语
+//!
+//! 目前仅供随机数子系统使用，因此只实现了其所需的最小集合。
+
+pub mod blake2s;
+pub mod chacha;
@@ -16,7 +16,7 @@ use log::error;
 use system_error::SystemError;
 
 use crate::{
-    arch::{CurrentElfArch, MMArch},
+    arch::{interrupt::TrapFrame, CurrentElfArch, MMArch},
     driver::base::block::SeekFrom,
     filesystem::vfs::file::File,
     libs::align::page_align_up,
@@ -40,6 +40,14 @@ use super::rwlock::RwLockWriteGuard;
 pub trait ElfArch: Clone + Copy + Debug {
     const ELF_ET_DYN_BASE: usize;
     const ELF_PAGE_SIZE: usize;
+    /// 生成的ELF文件（例如core dump）的e_machine字段
+    const ELF_MACHINE: u16;
+
+    /// core dump中NT_PRSTATUS保存的通用寄存器组，布局与Linux的`struct user_regs_struct`相同
+    type Gregset: Copy;
+
+    /// 根据陷入内核时保存的栈帧，生成当前进程的通用寄存器组
+    fn core_regs(frame: &TrapFrame) -> Self::Gregset;
 }
 
 #[derive(Debug)]
@@ -829,6 +837,13 @@ impl BinaryLoader for ElfLoader {
         self.create_auxv(param, program_entrypoint, phdr_vaddr, &ehdr)?;
 
         // debug!("auxv create ok");
+        // 保存auxv，生成core dump时使用
+        user_vm.saved_auxv = param
+            .init_info()
+            .auxv
+            .iter()
+            .map(|(&k, &v)| (k as usize, v))
+            .collect();
         user_vm.start_code = start_code.unwrap_or(VirtAddr::new(0));
         user_vm.end_code = end_code.unwrap_or(VirtAddr::new(0));
         user_vm.start_data = start_data.unwrap_or(VirtAddr::new(0));
@@ -1,6 +1,7 @@
 pub mod align;
 pub mod casting;
 pub mod cpumask;
+pub mod crypto;
 pub mod elf;
 #[macro_use]
 pub mod int_like;
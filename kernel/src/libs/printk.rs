@@ -10,7 +10,7 @@ use super::lib_ui::textui::{textui_putstr, FontColor};
 
 use crate::{
     driver::tty::{
-        tty_driver::TtyOperation, tty_port::tty_port,
+        console::console_write, tty_driver::TtyOperation, tty_port::tty_port,
         virtual_terminal::virtual_console::CURRENT_VCNUM,
     },
     filesystem::procfs::{
@@ -57,6 +57,9 @@ impl PrintkWriter {
         } else {
             let _ = textui_putstr(s, FontColor::WHITE, FontColor::BLACK);
         }
+
+        // 同步输出到已注册的内核消息控制台（例如hvc）
+        console_write(s.as_bytes());
     }
 }
 
@@ -5,7 +5,6 @@ use core::{
 
 use crate::{
     arch::{mm::LockedFrameAllocator, MMArch},
-    ipc::shm::shm_manager_lock,
     libs::spinlock::SpinLockGuard,
     mm::{MemoryManagementArch, PhysAddr, VirtAddr},
 };
@@ -370,10 +369,19 @@ pub unsafe fn deallocate_page_frames(
         let page = page_manager_guard.get(&paddr);
 
         if let Some(page) = page {
-            // 如果page是共享页，将其共享页信息从SHM_MANAGER中删去
+            // 如果page是共享页，将其共享页信息从所属IPC namespace的共享内存管理器中删去
             let page_guard = page.read_irqsave();
             if page_guard.shared() {
-                shm_manager_lock().free_id(&page_guard.shm_id().unwrap());
+                if let Some(ipc_ns) = page_guard.ipc_ns() {
+                    ipc_ns
+                        .shm_manager_lock()
+                        .free_id(&page_guard.shm_id().unwrap());
+                }
+            }
+
+            // 撤销该页在cgroup中的计费
+            if let Some(memcg) = page_guard.memcg() {
+                memcg.uncharge_memory(1);
             }
         }
 
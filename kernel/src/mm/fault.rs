@@ -9,6 +9,7 @@ use alloc::sync::Arc;
 
 use crate::{
     arch::{mm::PageMapper, MMArch},
+    cgroup::Cgroup,
     libs::align::align_down,
     mm::{
         page::{page_manager_lock_irqsave, EntryFlags},
@@ -108,6 +109,11 @@ impl<'a> PageFaultMessage<'a> {
     pub fn flags(&self) -> FaultFlags {
         self.flags
     }
+
+    #[inline(always)]
+    pub fn file_pgoff(&self) -> Option<usize> {
+        self.file_pgoff
+    }
 }
 
 /// 缺页中断处理结构体
@@ -239,6 +245,10 @@ impl PageFaultHandler {
     pub unsafe fn do_anonymous_page(pfm: &mut PageFaultMessage) -> VmFaultReason {
         let address = pfm.address_aligned_down();
         let vma = pfm.vma.clone();
+        let memcg = match Self::memcg_charge() {
+            Some(memcg) => memcg,
+            None => return VmFaultReason::VM_FAULT_OOM,
+        };
         let guard = vma.lock_irqsave();
         let mapper = &mut pfm.mapper;
 
@@ -255,13 +265,25 @@ impl PageFaultHandler {
             let paddr = mapper.translate(address).unwrap().0;
             let mut page_manager_guard = page_manager_lock_irqsave();
             let page = page_manager_guard.get_unwrap(&paddr);
-            page.write_irqsave().insert_vma(vma.clone());
+            let mut page_guard = page.write_irqsave();
+            page_guard.insert_vma(vma.clone());
+            page_guard.set_memcg(Some(memcg));
             VmFaultReason::VM_FAULT_COMPLETED
         } else {
+            memcg.uncharge_memory(1);
             VmFaultReason::VM_FAULT_OOM
         }
     }
 
+    /// 在为缺页分配新页之前，向当前进程所在的cgroup计费一个页
+    ///
+    /// 计费失败时返回None，此时memory控制器已经向当前进程发送了SIGKILL
+    fn memcg_charge() -> Option<Arc<Cgroup>> {
+        let memcg = ProcessManager::current_pcb().cgroup();
+        memcg.try_charge_memory(1).ok()?;
+        return Some(memcg);
+    }
+
     /// 处理文件映射页的缺页异常
     /// ## 参数
     ///
@@ -294,7 +316,8 @@ impl PageFaultHandler {
     /// ## 返回值
     /// - VmFaultReason: 页面错误处理信息标志
     pub unsafe fn do_cow_fault(pfm: &mut PageFaultMessage) -> VmFaultReason {
-        let mut ret = Self::filemap_fault(pfm);
+        let fs = pfm.vma().lock_irqsave().vm_file().unwrap().inode().fs();
+        let mut ret = fs.fault(pfm);
 
         if unlikely(ret.intersects(
             VmFaultReason::VM_FAULT_ERROR
@@ -308,13 +331,19 @@ impl PageFaultHandler {
         let cache_page = pfm.page.clone().unwrap();
         let mapper = &mut pfm.mapper;
 
+        let memcg = match Self::memcg_charge() {
+            Some(memcg) => memcg,
+            None => return VmFaultReason::VM_FAULT_OOM,
+        };
         let cow_page_phys = mapper.allocator_mut().allocate_one();
         if cow_page_phys.is_none() {
+            memcg.uncharge_memory(1);
             return VmFaultReason::VM_FAULT_OOM;
         }
         let cow_page_phys = cow_page_phys.unwrap();
 
         let cow_page = Arc::new(Page::new(false, cow_page_phys));
+        cow_page.write_irqsave().set_memcg(Some(memcg));
         pfm.cow_page = Some(cow_page.clone());
 
         //复制PageCache内容到新的页内
@@ -372,7 +401,11 @@ impl PageFaultHandler {
     /// ## 返回值
     /// - VmFaultReason: 页面错误处理信息标志
     pub unsafe fn do_shared_fault(pfm: &mut PageFaultMessage) -> VmFaultReason {
-        let mut ret = Self::filemap_fault(pfm);
+        let fs = pfm.vma().lock_irqsave().vm_file().unwrap().inode().fs();
+        let mut ret = fs.fault(pfm);
+        if unlikely(ret.intersects(VmFaultReason::VM_FAULT_ERROR)) {
+            return ret;
+        }
 
         let cache_page = pfm.page.clone().expect("no cache_page in PageFaultMessage");
 
@@ -455,37 +488,19 @@ impl PageFaultHandler {
             old_page.write_irqsave().add_flags(PageFlags::PG_DIRTY);
 
             VmFaultReason::VM_FAULT_COMPLETED
-        } else if vma.is_anonymous() {
-            // 私有匿名映射，根据引用计数判断是否拷贝页面
-            if map_count == 1 {
-                let table = mapper.get_table(address, 0).unwrap();
-                let i = table.index_of(address).unwrap();
-                entry.set_flags(new_flags);
-                table.set_entry(i, entry);
-                VmFaultReason::VM_FAULT_COMPLETED
-            } else if let Some(flush) = mapper.map(address, new_flags) {
-                let mut page_manager_guard = page_manager_lock_irqsave();
-                let old_page = page_manager_guard.get_unwrap(&old_paddr);
-                old_page.write_irqsave().remove_vma(&vma);
-                // drop(page_manager_guard);
-
-                flush.flush();
-                let paddr = mapper.translate(address).unwrap().0;
-                // let mut page_manager_guard = page_manager_lock_irqsave();
-                let page = page_manager_guard.get_unwrap(&paddr);
-                page.write_irqsave().insert_vma(vma.clone());
-
-                (MMArch::phys_2_virt(paddr).unwrap().data() as *mut u8).copy_from_nonoverlapping(
-                    MMArch::phys_2_virt(old_paddr).unwrap().data() as *mut u8,
-                    MMArch::PAGE_SIZE,
-                );
-
-                VmFaultReason::VM_FAULT_COMPLETED
-            } else {
-                VmFaultReason::VM_FAULT_OOM
-            }
+        } else if vma.is_anonymous() && map_count == 1 {
+            // 私有匿名映射，且只有当前进程映射了该页，直接修改页表项保护位
+            let table = mapper.get_table(address, 0).unwrap();
+            let i = table.index_of(address).unwrap();
+            entry.set_flags(new_flags);
+            table.set_entry(i, entry);
+            VmFaultReason::VM_FAULT_COMPLETED
         } else {
-            // 私有文件映射，必须拷贝页面
+            // 私有文件映射，或被多个进程映射的私有匿名页，必须拷贝页面
+            let memcg = match Self::memcg_charge() {
+                Some(memcg) => memcg,
+                None => return VmFaultReason::VM_FAULT_OOM,
+            };
             if let Some(flush) = mapper.map(address, new_flags) {
                 let mut page_manager_guard = page_manager_lock_irqsave();
                 let old_page = page_manager_guard.get_unwrap(&old_paddr);
@@ -496,7 +511,10 @@ impl PageFaultHandler {
                 let paddr = mapper.translate(address).unwrap().0;
                 // let mut page_manager_guard = page_manager_lock_irqsave();
                 let page = page_manager_guard.get_unwrap(&paddr);
-                page.write_irqsave().insert_vma(vma.clone());
+                let mut page_guard = page.write_irqsave();
+                page_guard.insert_vma(vma.clone());
+                page_guard.set_memcg(Some(memcg));
+                drop(page_guard);
 
                 (MMArch::phys_2_virt(paddr).unwrap().data() as *mut u8).copy_from_nonoverlapping(
                     MMArch::phys_2_virt(old_paddr).unwrap().data() as *mut u8,
@@ -505,6 +523,7 @@ impl PageFaultHandler {
 
                 VmFaultReason::VM_FAULT_COMPLETED
             } else {
+                memcg.uncharge_memory(1);
                 VmFaultReason::VM_FAULT_OOM
             }
         }
@@ -653,10 +672,21 @@ impl PageFaultHandler {
             ret = VmFaultReason::VM_FAULT_MAJOR;
             // let mut buf: Vec<u8> = vec![0; MMArch::PAGE_SIZE];
 
+            // 新的PageCache页计费到触发缺页的进程所在的cgroup，在页面被逐出或释放时撤销
+            let memcg = match Self::memcg_charge() {
+                Some(memcg) => memcg,
+                None => return VmFaultReason::VM_FAULT_OOM,
+            };
             let allocator = mapper.allocator_mut();
 
             // 分配一个物理页面作为加入PageCache的新页
-            let new_cache_page = allocator.allocate_one().unwrap();
+            let new_cache_page = match allocator.allocate_one() {
+                Some(paddr) => paddr,
+                None => {
+                    memcg.uncharge_memory(1);
+                    return VmFaultReason::VM_FAULT_OOM;
+                }
+            };
             // (MMArch::phys_2_virt(new_cache_page).unwrap().data() as *mut u8)
             //     .copy_from_nonoverlapping(buf.as_mut_ptr(), MMArch::PAGE_SIZE);
             file.pread(
@@ -673,6 +703,7 @@ impl PageFaultHandler {
             pfm.page = Some(page.clone());
 
             page.write_irqsave().add_flags(PageFlags::PG_LRU);
+            page.write_irqsave().set_memcg(Some(memcg));
             page_manager_lock_irqsave().insert(new_cache_page, &page);
             page_reclaimer_lock_irqsave().insert_page(new_cache_page, &page);
             page_cache.add_page(file_pgoff, &page);
@@ -6,7 +6,6 @@ use crate::{
     arch::MMArch,
     driver::serial::serial8250::send_to_default_serial8250_port,
     filesystem::procfs::kmsg::kmsg_init,
-    ipc::shm::shm_manager_init,
     libs::printk::PrintkWriter,
     mm::{
         allocator::slab::slab_init,
@@ -59,8 +58,6 @@ pub unsafe fn mm_init() {
     kmsg_init();
     // enable PAGE_MANAGER
     page_manager_init();
-    // enable SHM_MANAGER
-    shm_manager_init();
     // enable PAGE_RECLAIMER
     page_reclaimer_init();
 
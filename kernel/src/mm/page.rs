@@ -9,13 +9,14 @@ use core::{
 use system_error::SystemError;
 use unified_init::macros::unified_init;
 
-use alloc::sync::Arc;
+use alloc::sync::{Arc, Weak};
 use hashbrown::{HashMap, HashSet};
 use log::{error, info};
 use lru::LruCache;
 
 use crate::{
     arch::{interrupt::ipi::send_ipi, mm::LockedFrameAllocator, MMArch},
+    cgroup::Cgroup,
     exception::ipi::{IpiKind, IpiTarget},
     filesystem::vfs::{file::PageCache, FilePrivateData},
     init::initcall::INITCALL_CORE,
@@ -24,7 +25,7 @@ use crate::{
         rwlock::{RwLock, RwLockReadGuard, RwLockWriteGuard},
         spinlock::{SpinLock, SpinLockGuard},
     },
-    process::{ProcessControlBlock, ProcessManager},
+    process::{namespace::ipc::IpcNamespace, ProcessControlBlock, ProcessManager},
     time::{sleep::nanosleep, PosixTimeSpec},
 };
 
@@ -200,6 +201,10 @@ impl PageReclaimer {
             }
             page_cache.remove_page(page.read_irqsave().index().unwrap());
             page_manager_lock_irqsave().remove_page(&paddr);
+            // 页面被逐出PageCache，撤销其在cgroup中的计费
+            if let Some(memcg) = page.write_irqsave().memcg.take() {
+                memcg.uncharge_memory(1);
+            }
             if page.read_irqsave().flags.contains(PageFlags::PG_DIRTY) {
                 Self::page_writeback(&page, true);
             }
@@ -338,6 +343,10 @@ pub struct InnerPage {
     free_when_zero: bool,
     /// 共享页id（如果是共享页）
     shm_id: Option<ShmId>,
+    /// 共享页所属的IPC namespace（如果是共享页）
+    ipc_ns: Option<Weak<IpcNamespace>>,
+    /// 页面被计费到的cgroup（如果有）
+    memcg: Option<Arc<Cgroup>>,
     /// 映射到当前page的VMA
     anon_vma: HashSet<Arc<LockedVMA>>,
     /// 标志
@@ -357,6 +366,8 @@ impl InnerPage {
             shared,
             free_when_zero: dealloc_when_zero,
             shm_id: None,
+            ipc_ns: None,
+            memcg: None,
             anon_vma: HashSet::new(),
             flags: PageFlags::empty(),
             phys_addr,
@@ -390,6 +401,10 @@ impl InnerPage {
         self.shm_id
     }
 
+    pub fn ipc_ns(&self) -> Option<Arc<IpcNamespace>> {
+        self.ipc_ns.as_ref()?.upgrade()
+    }
+
     pub fn index(&self) -> Option<usize> {
         self.index
     }
@@ -415,8 +430,17 @@ impl InnerPage {
         self.index = index;
     }
 
-    pub fn set_shm_id(&mut self, shm_id: ShmId) {
+    pub fn set_shm_id(&mut self, shm_id: ShmId, ipc_ns: Weak<IpcNamespace>) {
         self.shm_id = Some(shm_id);
+        self.ipc_ns = Some(ipc_ns);
+    }
+
+    pub fn memcg(&self) -> Option<Arc<Cgroup>> {
+        self.memcg.clone()
+    }
+
+    pub fn set_memcg(&mut self, memcg: Option<Arc<Cgroup>>) {
+        self.memcg = memcg;
     }
 
     pub fn set_dealloc_when_zero(&mut self, dealloc_when_zero: bool) {
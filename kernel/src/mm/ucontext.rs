@@ -22,12 +22,12 @@ use crate::{
     exception::InterruptArch,
     filesystem::vfs::file::File,
     libs::{
-        align::page_align_up,
+        align::{page_align_down, page_align_up},
         rwlock::RwLock,
         spinlock::{SpinLock, SpinLockGuard},
     },
     mm::page::page_manager_lock_irqsave,
-    process::ProcessManager,
+    process::{resource::RLimitID, ProcessManager},
     syscall::user_access::{UserBufferReader, UserBufferWriter},
 };
 
@@ -126,6 +126,9 @@ pub struct InnerAddressSpace {
     pub end_code: VirtAddr,
     pub start_data: VirtAddr,
     pub end_data: VirtAddr,
+
+    /// execve时传给程序的auxv（不包含结尾的AT_NULL），生成core dump时使用
+    pub saved_auxv: Vec<(usize, usize)>,
 }
 
 impl InnerAddressSpace {
@@ -143,10 +146,11 @@ impl InnerAddressSpace {
             end_code: VirtAddr(0),
             start_data: VirtAddr(0),
             end_data: VirtAddr(0),
+            saved_auxv: Vec::new(),
         };
         if create_stack {
             // debug!("to create user stack.");
-            result.new_user_stack(UserStack::DEFAULT_USER_STACK_SIZE)?;
+            result.new_user_stack(UserStack::initial_size())?;
         }
 
         return Ok(result);
@@ -176,6 +180,7 @@ impl InnerAddressSpace {
 
         // 拷贝空洞
         new_guard.mappings.vm_holes = self.mappings.vm_holes.clone();
+        new_guard.saved_auxv = self.saved_auxv.clone();
 
         for vma in self.mappings.vmas.iter() {
             // TODO: 增加对VMA是否为文件映射的判断，如果是的话，就跳过
@@ -217,12 +222,24 @@ impl InnerAddressSpace {
         let stack = self.user_stack.as_mut().unwrap();
 
         bytes = page_align_up(bytes);
+        let limit = ProcessManager::current_pcb().rlimit_cur(RLimitID::Stack);
+        if (stack.mapped_size + bytes) as u64 > limit {
+            return Err(SystemError::ENOMEM);
+        }
         stack.mapped_size += bytes;
         let len = stack.stack_bottom - stack.mapped_size;
         self.map_anonymous(len, bytes, prot_flags, map_flags, false, false)?;
         return Ok(());
     }
 
+    /// 判断地址空间再增加`bytes`字节后，是否仍在当前进程的RLIMIT_AS之内
+    ///
+    /// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/mm/mmap.c#3671
+    pub fn may_expand_vm(&self, bytes: usize) -> bool {
+        let limit = ProcessManager::current_pcb().rlimit_cur(RLimitID::As);
+        (self.mappings.total_vm() + bytes) as u64 <= limit
+    }
+
     /// 判断当前的地址空间是否是当前进程的地址空间
     #[inline]
     pub fn is_current(&self) -> bool {
@@ -441,6 +458,16 @@ impl InnerAddressSpace {
         if page_count == PageFrameCount::new(0) {
             return Err(SystemError::EINVAL);
         }
+        // MAP_FIXED会替换掉请求范围内原有的映射，这部分不计入新增的大小
+        let replaced = match addr {
+            Some(vaddr) if map_flags.contains(MapFlags::MAP_FIXED) => self
+                .mappings
+                .overlap_bytes(VirtRegion::new(vaddr, page_count.bytes())),
+            _ => 0,
+        };
+        if !self.may_expand_vm(page_count.bytes() - replaced) {
+            return Err(SystemError::ENOMEM);
+        }
         // debug!("mmap: addr: {addr:?}, page_count: {page_count:?}, prot_flags: {prot_flags:?}, map_flags: {map_flags:?}");
 
         // 找到未使用的区域
@@ -757,6 +784,14 @@ impl InnerAddressSpace {
         let old_brk = self.brk;
 
         if new_brk > self.brk {
+            // 堆与数据段的总大小受RLIMIT_DATA限制
+            let limit = ProcessManager::current_pcb().rlimit_cur(RLimitID::Data);
+            let data_size = (new_brk - self.brk_start)
+                + self.end_data.data().saturating_sub(self.start_data.data());
+            if data_size as u64 > limit {
+                return Err(SystemError::ENOMEM);
+            }
+
             let len = new_brk - self.brk;
             let prot_flags = ProtFlags::PROT_READ | ProtFlags::PROT_WRITE | ProtFlags::PROT_EXEC;
             let map_flags = MapFlags::MAP_PRIVATE | MapFlags::MAP_ANONYMOUS | MapFlags::MAP_FIXED;
@@ -1062,6 +1097,23 @@ impl UserMappings {
     pub fn iter_vmas(&self) -> hashbrown::hash_set::Iter<Arc<LockedVMA>> {
         return self.vmas.iter();
     }
+
+    /// 所有VMA的总大小（字节）
+    pub fn total_vm(&self) -> usize {
+        self.vmas
+            .iter()
+            .map(|vma| vma.lock_irqsave().region().size())
+            .sum()
+    }
+
+    /// 已有的VMA与`region`重叠部分的总大小（字节）
+    pub fn overlap_bytes(&self, region: VirtRegion) -> usize {
+        self.vmas
+            .iter()
+            .filter_map(|vma| vma.lock_irqsave().region().intersect(&region))
+            .map(|overlap| overlap.size())
+            .sum()
+    }
 }
 
 impl Default for UserMappings {
@@ -1714,6 +1766,19 @@ impl UserStack {
     /// 用户栈的保护页数量
     pub const GUARD_PAGES_NUM: usize = 4;
 
+    /// 新的地址空间中初始映射的用户栈大小
+    ///
+    /// 与保护页一起不超过当前进程的RLIMIT_STACK。riscv64上还不支持用户栈的按需增长，
+    /// 这就是用户栈的全部大小
+    pub fn initial_size() -> usize {
+        let limit = ProcessManager::current_pcb().rlimit_cur(RLimitID::Stack);
+        let guard_size = Self::GUARD_PAGES_NUM * MMArch::PAGE_SIZE;
+        let limit = usize::try_from(limit)
+            .unwrap_or(usize::MAX)
+            .saturating_sub(guard_size);
+        page_align_down(limit).clamp(MMArch::PAGE_SIZE, Self::DEFAULT_USER_STACK_SIZE)
+    }
+
     /// 创建一个用户栈
     pub fn new(
         vm: &mut InnerAddressSpace,
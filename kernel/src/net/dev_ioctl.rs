@@ -0,0 +1,192 @@
+//! 网络设备相关的socket ioctl
+//!
+//! 与linux一样，这些命令作用于调用者所在的network namespace中的网卡，可以在任意socket上执行。
+//! 由于还没有实现rtnetlink，创建veth网卡对使用DragonOS私有的`SIOCVETHCREATE`命令。
+//!
+//! 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/net/core/dev_ioctl.c
+
+use alloc::sync::Arc;
+use smoltcp::wire::{IpCidr, Ipv4Address, Ipv4Cidr};
+use system_error::SystemError;
+
+use crate::{
+    driver::net::{veth::veth_pair_create, NetDevice},
+    process::{capability::capable, cred::CAPFlags, namespace::net::NetNamespace, ProcessManager},
+    syscall::user_access::{UserBufferReader, UserBufferWriter},
+};
+
+/// 网卡名称的最大长度（包括结尾的'\0'）
+const IFNAMSIZ: usize = 16;
+
+const AF_INET: u16 = 2;
+
+/// 获取网卡的IPv4地址
+pub const SIOCGIFADDR: u32 = 0x8915;
+/// 设置网卡的IPv4地址
+pub const SIOCSIFADDR: u32 = 0x8916;
+/// 获取网卡的子网掩码
+pub const SIOCGIFNETMASK: u32 = 0x891b;
+/// 设置网卡的子网掩码
+pub const SIOCSIFNETMASK: u32 = 0x891c;
+/// 创建一对veth网卡（DragonOS私有，位于SIOCDEVPRIVATE的范围内）
+pub const SIOCVETHCREATE: u32 = 0x89f0;
+
+/// struct sockaddr_in
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+struct SockAddrIn {
+    family: u16,
+    /// 网络字节序
+    port: u16,
+    addr: [u8; 4],
+    zero: [u8; 8],
+}
+
+/// struct ifreq，这里只使用其中的sockaddr成员
+///
+/// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/include/uapi/linux/if.h#234
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+struct IfReq {
+    name: [u8; IFNAMSIZ],
+    addr: SockAddrIn,
+    /// union的剩余部分
+    pad: [u8; 8],
+}
+
+/// SIOCVETHCREATE的参数
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+struct VethCreateReq {
+    /// 输出：位于调用者的network namespace中的一端的名称
+    name: [u8; IFNAMSIZ],
+    /// 输出：另一端的名称
+    peer_name: [u8; IFNAMSIZ],
+    /// 输入：另一端所在的network namespace（/proc/<pid>/ns/net的文件描述符），为-1时与调用者相同
+    peer_netns_fd: i32,
+    pad: i32,
+}
+
+/// 执行网络设备相关的ioctl
+///
+/// ## 返回值
+/// - `Err(SystemError::ENOSYS)`: 不是网络设备相关的命令
+pub fn dev_ioctl(cmd: u32, data: usize) -> Result<usize, SystemError> {
+    let netns = ProcessManager::current_pcb().nsproxy().net_ns.clone();
+    match cmd {
+        SIOCGIFADDR | SIOCGIFNETMASK => {
+            let mut ifr = read_from_user::<IfReq>(data)?;
+            let dev = find_device(&netns, &ifr)?;
+            let cidr = ipv4_cidr(&dev).ok_or(SystemError::EADDRNOTAVAIL)?;
+            let addr = if cmd == SIOCGIFADDR {
+                cidr.address()
+            } else {
+                cidr.netmask()
+            };
+            ifr.addr = SockAddrIn {
+                family: AF_INET,
+                addr: addr.0,
+                ..Default::default()
+            };
+            write_to_user(data, &ifr)?;
+            Ok(0)
+        }
+        SIOCSIFADDR | SIOCSIFNETMASK => {
+            if !capable(CAPFlags::CAP_NET_ADMIN) {
+                return Err(SystemError::EPERM);
+            }
+            let ifr = read_from_user::<IfReq>(data)?;
+            if ifr.addr.family != AF_INET {
+                return Err(SystemError::EINVAL);
+            }
+            let dev = find_device(&netns, &ifr)?;
+            let addr = Ipv4Address(ifr.addr.addr);
+            let cidr = if cmd == SIOCSIFADDR {
+                // 与linux一样，保留原有的子网掩码，没有地址时使用地址类别对应的掩码
+                let prefix_len = match ipv4_cidr(&dev) {
+                    Some(old) => old.prefix_len(),
+                    None => classful_prefix_len(addr),
+                };
+                Ipv4Cidr::new(addr, prefix_len)
+            } else {
+                let old = ipv4_cidr(&dev).ok_or(SystemError::EADDRNOTAVAIL)?;
+                let prefix_len = netmask_prefix_len(addr).ok_or(SystemError::EINVAL)?;
+                Ipv4Cidr::new(old.address(), prefix_len)
+            };
+            dev.update_ip_addrs(&[IpCidr::Ipv4(cidr)])?;
+            Ok(0)
+        }
+        SIOCVETHCREATE => {
+            if !capable(CAPFlags::CAP_NET_ADMIN) {
+                return Err(SystemError::EPERM);
+            }
+            let mut req = read_from_user::<VethCreateReq>(data)?;
+            let peer_netns = if req.peer_netns_fd == -1 {
+                netns.clone()
+            } else {
+                NetNamespace::from_fd(req.peer_netns_fd)?
+            };
+            let (veth, peer) = veth_pair_create(&netns, &peer_netns);
+            req.name = name_to_bytes(&veth.iface_name());
+            req.peer_name = name_to_bytes(&peer.iface_name());
+            write_to_user(data, &req)?;
+            Ok(0)
+        }
+        _ => Err(SystemError::ENOSYS),
+    }
+}
+
+fn read_from_user<T: Copy>(data: usize) -> Result<T, SystemError> {
+    let reader = UserBufferReader::new(data as *const T, core::mem::size_of::<T>(), true)?;
+    Ok(*reader.read_one_from_user::<T>(0)?)
+}
+
+fn write_to_user<T: Copy>(data: usize, value: &T) -> Result<(), SystemError> {
+    let mut writer = UserBufferWriter::new(data as *mut T, core::mem::size_of::<T>(), true)?;
+    writer.copy_one_to_user(value, 0)
+}
+
+fn find_device(netns: &NetNamespace, ifr: &IfReq) -> Result<Arc<dyn NetDevice>, SystemError> {
+    let len = ifr.name.iter().position(|&c| c == 0).unwrap_or(IFNAMSIZ);
+    let name = core::str::from_utf8(&ifr.name[..len]).map_err(|_| SystemError::EINVAL)?;
+    netns.find_device(name).ok_or(SystemError::ENODEV)
+}
+
+fn ipv4_cidr(dev: &Arc<dyn NetDevice>) -> Option<Ipv4Cidr> {
+    dev.inner_iface()
+        .lock()
+        .ip_addrs()
+        .iter()
+        .find_map(|cidr| match cidr {
+            IpCidr::Ipv4(cidr) if !cidr.address().is_unspecified() => Some(*cidr),
+            _ => None,
+        })
+}
+
+fn name_to_bytes(name: &str) -> [u8; IFNAMSIZ] {
+    let mut buf = [0u8; IFNAMSIZ];
+    let len = name.len().min(IFNAMSIZ - 1);
+    buf[..len].copy_from_slice(&name.as_bytes()[..len]);
+    buf
+}
+
+/// 地址类别对应的前缀长度
+///
+/// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/net/ipv4/devinet.c#inet_abc_len
+fn classful_prefix_len(addr: Ipv4Address) -> u8 {
+    match addr.0[0] {
+        0..=127 => 8,
+        128..=191 => 16,
+        _ => 24,
+    }
+}
+
+/// 把子网掩码转换为前缀长度，掩码不连续时返回None
+fn netmask_prefix_len(mask: Ipv4Address) -> Option<u8> {
+    let mask = u32::from_be_bytes(mask.0);
+    let prefix_len = mask.leading_ones();
+    if mask.checked_shl(prefix_len).unwrap_or(0) != 0 {
+        return None;
+    }
+    Some(prefix_len as u8)
+}
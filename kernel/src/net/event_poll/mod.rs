@@ -240,6 +240,21 @@ impl EventPoll {
     /// ### 返回值
     /// - 成功则返回Ok(fd)，否则返回Err
     pub fn do_create_epoll(flags: FileMode) -> Result<usize, SystemError> {
+        let ep_file = Self::create_epoll_file(flags)?;
+
+        let current_pcb = ProcessManager::current_pcb();
+        let fd_table = current_pcb.fd_table();
+        let mut fd_table_guard = fd_table.write();
+
+        let fd = fd_table_guard.alloc_fd(ep_file, None)?;
+
+        Ok(fd as usize)
+    }
+
+    /// ## 创建epoll文件，但不将其加入文件描述符表
+    ///
+    /// select等需要在内核中临时使用epoll的地方可以直接使用返回的文件
+    pub fn create_epoll_file(flags: FileMode) -> Result<File, SystemError> {
         if !flags.difference(FileMode::O_CLOEXEC).is_empty() {
             return Err(SystemError::EINVAL);
         }
@@ -259,13 +274,7 @@ impl EventPoll {
         // 设置ep_file的FilePrivateData
         ep_file.private_data = SpinLock::new(FilePrivateData::EPoll(EPollPrivateData { epoll }));
 
-        let current_pcb = ProcessManager::current_pcb();
-        let fd_table = current_pcb.fd_table();
-        let mut fd_table_guard = fd_table.write();
-
-        let fd = fd_table_guard.alloc_fd(ep_file, None)?;
-
-        Ok(fd as usize)
+        Ok(ep_file)
     }
 
     /// ## epoll_ctl的具体实现
@@ -297,6 +306,22 @@ impl EventPoll {
             .get_file_by_fd(fd)
             .ok_or(SystemError::EBADF)?;
 
+        Self::epoll_ctl_with_epfile(ep_file, op, fd, dst_file, epds, nonblock)
+    }
+
+    /// ## 对epoll文件进行增删改，参数的含义与[`EventPoll::do_epoll_ctl`]相同
+    ///
+    /// ### 参数
+    /// - ep_file: epoll文件
+    /// - dst_file: fd对应的文件
+    pub fn epoll_ctl_with_epfile(
+        ep_file: Arc<File>,
+        op: EPollCtlOption,
+        fd: i32,
+        dst_file: Arc<File>,
+        epds: &mut EPollEvent,
+        nonblock: bool,
+    ) -> Result<usize, SystemError> {
         // 检查是否允许 EPOLLWAKEUP
         if op != EPollCtlOption::Del {
             epds.events &= !EPollEventType::EPOLLWAKEUP.bits();
@@ -412,6 +437,18 @@ impl EventPoll {
 
         drop(fd_table_guard);
 
+        return Self::epoll_wait_with_file(ep_file, epoll_event, max_events, timespec);
+    }
+
+    /// ## 在epoll文件上等待事件，参数的含义与[`EventPoll::do_epoll_wait`]相同
+    pub fn epoll_wait_with_file(
+        ep_file: Arc<File>,
+        epoll_event: &mut [EPollEvent],
+        max_events: i32,
+        timespec: Option<PosixTimeSpec>,
+    ) -> Result<usize, SystemError> {
+        let current_pcb = ProcessManager::current_pcb();
+
         // 确保是epoll file
         if !Self::is_epoll_file(&ep_file) {
             return Err(SystemError::EINVAL);
@@ -614,6 +651,14 @@ impl EventPoll {
             return Err(SystemError::ENOSYS);
         }
 
+        // 这个标志是用与电源管理相关，暂时不支持
+        if epitem.event.read().events & EPollEventType::EPOLLWAKEUP.bits() != 0 {
+            return Err(SystemError::ENOSYS);
+        }
+
+        // 先挂到文件上，文件不支持epoll时不会在epoll中留下epitem
+        dst_file.add_epoll(epitem.clone())?;
+
         epoll_guard.ep_items.insert(epitem.fd, epitem.clone());
 
         // 检查文件是否已经有事件发生
@@ -627,12 +672,6 @@ impl EventPoll {
 
         // TODO： 嵌套epoll？
 
-        // 这个标志是用与电源管理相关，暂时不支持
-        if epitem.event.read().events & EPollEventType::EPOLLWAKEUP.bits() != 0 {
-            return Err(SystemError::ENOSYS);
-        }
-
-        dst_file.add_epoll(epitem.clone())?;
         Ok(())
     }
 
@@ -718,14 +757,18 @@ impl EventPoll {
         epitems: &SpinLock<LinkedList<Arc<EPollItem>>>,
         pollflags: EPollEventType,
     ) -> Result<(), SystemError> {
-        let mut epitems_guard = epitems.try_lock_irqsave()?;
-        // 一次只取一个，因为一次也只有一个进程能拿到对应文件的🔓
-        if let Some(epitem) = epitems_guard.pop_front() {
-            let epoll = epitem.epoll().upgrade().unwrap();
-            let mut epoll_guard = epoll.try_lock()?;
-            let binding = epitem.clone();
-            let event_guard = binding.event().read();
-            let ep_events = EPollEventType::from_bits_truncate(event_guard.events());
+        let epitems_guard = epitems.try_lock_irqsave()?;
+        // 同一个文件可能被多个epoll（包括select使用的临时epoll）监听，需要逐一通知
+        for epitem in epitems_guard.iter() {
+            // epoll正在被释放
+            let Some(epoll) = epitem.epoll().upgrade() else {
+                continue;
+            };
+            // 拿不到锁时跳过，避免与持有epoll锁的一方死锁
+            let Ok(mut epoll_guard) = epoll.try_lock() else {
+                continue;
+            };
+            let ep_events = EPollEventType::from_bits_truncate(epitem.event().read().events());
 
             // 检查事件合理性以及是否有感兴趣的事件
             if !(ep_events
@@ -749,8 +792,6 @@ impl EventPoll {
                     }
                 }
             }
-
-            epitems_guard.push_back(epitem);
         }
         Ok(())
     }
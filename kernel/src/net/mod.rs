@@ -3,24 +3,18 @@ use core::{
     sync::atomic::AtomicUsize,
 };
 
-use alloc::{collections::BTreeMap, sync::Arc};
+use alloc::sync::Arc;
 
-use crate::{driver::net::NetDevice, libs::rwlock::RwLock};
 use smoltcp::wire::IpEndpoint;
 
-use self::socket::SocketInode;
+use self::socket::{vsock::VsockEndpoint, SocketInode};
 
+pub mod dev_ioctl;
 pub mod event_poll;
 pub mod net_core;
 pub mod socket;
 pub mod syscall;
 
-lazy_static! {
-    /// # 所有网络接口的列表
-    /// 这个列表在中断上下文会使用到，因此需要irqsave
-    pub static ref NET_DEVICES: RwLock<BTreeMap<usize, Arc<dyn NetDevice>>> = RwLock::new(BTreeMap::new());
-}
-
 /// 生成网络接口的id (全局自增)
 pub fn generate_iface_id() -> usize {
     static IFACE_ID: AtomicUsize = AtomicUsize::new(0);
@@ -45,6 +39,8 @@ pub enum Endpoint {
     Ip(Option<IpEndpoint>),
     /// inode端点
     Inode(Option<Arc<SocketInode>>),
+    /// vsock端点
+    Vsock(VsockEndpoint),
     // todo: 增加NetLink机制后，增加NetLink端点
 }
 
@@ -1,20 +1,23 @@
-use alloc::{boxed::Box, collections::BTreeMap, sync::Arc};
+use alloc::boxed::Box;
 use log::{debug, info, warn};
-use smoltcp::{socket::dhcpv4, wire};
+use smoltcp::{iface::SocketSet, socket::dhcpv4, wire};
 use system_error::SystemError;
 
 use crate::{
-    driver::net::{NetDevice, Operstate},
-    libs::rwlock::RwLockReadGuard,
-    net::{socket::SocketPollMethod, NET_DEVICES},
+    driver::net::{veth::veth_has_pending_frames, Operstate},
+    net::socket::SocketPollMethod,
+    process::namespace::net::{all_net_namespaces, NetNamespace, INIT_NET_NS},
     time::timer::{next_n_ms_timer_jiffies, Timer, TimerFunction},
 };
 
 use super::{
     event_poll::{EPollEventType, EventPoll},
-    socket::{handle::GlobalSocketHandle, inet::TcpSocket, HANDLE_MAP, SOCKET_SET},
+    socket::{handle::GlobalSocketHandle, inet::TcpSocket, HANDLE_MAP},
 };
 
+/// 一次轮询中，为了让veth在不同network namespace之间转发的数据包被处理，最多轮询所有namespace的次数
+const MAX_POLL_ROUNDS: usize = 16;
+
 /// The network poll function, which will be called by timer.
 ///
 /// The main purpose of this function is to poll all network interfaces.
@@ -42,7 +45,7 @@ pub fn net_init() -> Result<(), SystemError> {
 }
 
 fn dhcp_query() -> Result<(), SystemError> {
-    let binding = NET_DEVICES.write_irqsave();
+    let binding = INIT_NET_NS.devices().write_irqsave();
 
     //由于现在os未实现在用户态为网卡动态分配内存，而lo网卡的id最先分配且ip固定不能被分配
     //所以特判取用id为1的网卡（也就是virto_net）
@@ -59,13 +62,15 @@ fn dhcp_query() -> Result<(), SystemError> {
     // IMPORTANT: This should be removed in production.
     dhcp_socket.set_max_lease_duration(Some(smoltcp::time::Duration::from_secs(10)));
 
-    let dhcp_handle = SOCKET_SET.lock_irqsave().add(dhcp_socket);
+    let dhcp_handle = INIT_NET_NS.socket_set().lock_irqsave().add(dhcp_socket);
 
     const DHCP_TRY_ROUND: u8 = 10;
     for i in 0..DHCP_TRY_ROUND {
         debug!("DHCP try round: {}", i);
-        net_face.poll(&mut SOCKET_SET.lock_irqsave()).ok();
-        let mut binding = SOCKET_SET.lock_irqsave();
+        net_face
+            .poll(&mut INIT_NET_NS.socket_set().lock_irqsave())
+            .ok();
+        let mut binding = INIT_NET_NS.socket_set().lock_irqsave();
         let event = binding.get_mut::<dhcpv4::Socket>(dhcp_handle).poll();
 
         match event {
@@ -123,77 +128,87 @@ fn dhcp_query() -> Result<(), SystemError> {
     return Err(SystemError::ETIMEDOUT);
 }
 
+/// 轮询所有network namespace中的网卡
+///
+/// veth网卡发送的数据包会进入另一个namespace中的网卡，因此当veth上仍有待处理的数据包时，
+/// 需要再次轮询，直到数据包被处理完毕（最多轮询`MAX_POLL_ROUNDS`次）
 pub fn poll_ifaces() {
-    let guard: RwLockReadGuard<BTreeMap<usize, Arc<dyn NetDevice>>> = NET_DEVICES.read_irqsave();
-    if guard.len() == 0 {
+    let namespaces = all_net_namespaces();
+    if namespaces
+        .iter()
+        .all(|netns| netns.devices().read_irqsave().is_empty())
+    {
         warn!("poll_ifaces: No net driver found!");
         return;
     }
-    let mut sockets = SOCKET_SET.lock_irqsave();
-    for (_, iface) in guard.iter() {
-        iface.poll(&mut sockets).ok();
+    for _ in 0..MAX_POLL_ROUNDS {
+        for netns in namespaces.iter() {
+            let mut sockets = netns.socket_set().lock_irqsave();
+            poll_netns(netns, &mut sockets).ok();
+        }
+        if !veth_has_pending_frames() {
+            break;
+        }
     }
-    let _ = send_event(&sockets);
 }
 
-/// 对ifaces进行轮询，最多对SOCKET_SET尝试times次加锁。
+/// 对ifaces进行轮询，对每个network namespace的socket集合最多尝试times次加锁。
 ///
 /// @return 轮询成功，返回Ok(())
 /// @return 加锁超时，返回SystemError::EAGAIN_OR_EWOULDBLOCK
 /// @return 没有网卡，返回SystemError::ENODEV
 pub fn poll_ifaces_try_lock(times: u16) -> Result<(), SystemError> {
-    let mut i = 0;
-    while i < times {
-        let guard: RwLockReadGuard<BTreeMap<usize, Arc<dyn NetDevice>>> =
-            NET_DEVICES.read_irqsave();
-        if guard.len() == 0 {
-            warn!("poll_ifaces: No net driver found!");
-            // 没有网卡，返回错误
-            return Err(SystemError::ENODEV);
-        }
-        let sockets = SOCKET_SET.try_lock_irqsave();
-        // 加锁失败，继续尝试
-        if sockets.is_err() {
+    let namespaces = all_net_namespaces();
+    if namespaces
+        .iter()
+        .all(|netns| netns.devices().read_irqsave().is_empty())
+    {
+        warn!("poll_ifaces: No net driver found!");
+        // 没有网卡，返回错误
+        return Err(SystemError::ENODEV);
+    }
+
+    let mut result = Ok(());
+    for netns in namespaces.iter() {
+        let mut i = 0;
+        while i < times {
+            // 加锁失败，继续尝试
+            if let Ok(mut sockets) = netns.socket_set().try_lock_irqsave() {
+                poll_netns(netns, &mut sockets)?;
+                break;
+            }
             i += 1;
-            continue;
         }
-
-        let mut sockets = sockets.unwrap();
-        for (_, iface) in guard.iter() {
-            iface.poll(&mut sockets).ok();
+        if i == times {
+            // 尝试次数用完，返回错误
+            result = Err(SystemError::EAGAIN_OR_EWOULDBLOCK);
         }
-        send_event(&sockets)?;
-        return Ok(());
     }
-    // 尝试次数用完，返回错误
-    return Err(SystemError::EAGAIN_OR_EWOULDBLOCK);
+    return result;
 }
 
-/// 对ifaces进行轮询，最多对SOCKET_SET尝试一次加锁。
+/// 对ifaces进行轮询，对每个network namespace的socket集合最多尝试一次加锁。
 ///
 /// @return 轮询成功，返回Ok(())
 /// @return 加锁超时，返回SystemError::EAGAIN_OR_EWOULDBLOCK
 /// @return 没有网卡，返回SystemError::ENODEV
 pub fn poll_ifaces_try_lock_onetime() -> Result<(), SystemError> {
-    let guard: RwLockReadGuard<BTreeMap<usize, Arc<dyn NetDevice>>> = NET_DEVICES.read_irqsave();
-    if guard.len() == 0 {
-        warn!("poll_ifaces: No net driver found!");
-        // 没有网卡，返回错误
-        return Err(SystemError::ENODEV);
-    }
-    let mut sockets = SOCKET_SET.try_lock_irqsave()?;
-    for (_, iface) in guard.iter() {
-        iface.poll(&mut sockets).ok();
+    poll_ifaces_try_lock(1)
+}
+
+/// 轮询一个network namespace中的所有网卡，并分发socket上的事件
+fn poll_netns(netns: &NetNamespace, sockets: &mut SocketSet<'static>) -> Result<(), SystemError> {
+    for (_, iface) in netns.devices().read_irqsave().iter() {
+        iface.poll(sockets).ok();
     }
-    send_event(&sockets)?;
-    return Ok(());
+    send_event(netns, sockets)
 }
 
 /// ### 处理轮询后的事件
-fn send_event(sockets: &smoltcp::iface::SocketSet) -> Result<(), SystemError> {
+fn send_event(netns: &NetNamespace, sockets: &SocketSet) -> Result<(), SystemError> {
     for (handle, socket_type) in sockets.iter() {
         let handle_guard = HANDLE_MAP.read_irqsave();
-        let global_handle = GlobalSocketHandle::new_smoltcp_handle(handle);
+        let global_handle = GlobalSocketHandle::new_smoltcp_handle(netns.inum(), handle);
         let item: Option<&super::socket::SocketHandleItem> = handle_guard.get(&global_handle);
         if item.is_none() {
             continue;
@@ -10,7 +10,9 @@ int_like!(KernelHandle, usize);
 /// 比如，在socket被关闭时，自动释放socket的资源，通知系统的其他组件。
 #[derive(Debug, Hash, Eq, PartialEq, Clone, Copy)]
 pub enum GlobalSocketHandle {
-    Smoltcp(SocketHandle),
+    /// smoltcp的socket句柄。由于每个network namespace有各自的SocketSet，
+    /// 因此需要同时记录socket所在的network namespace的编号
+    Smoltcp(usize, SocketHandle),
     Kernel(KernelHandle),
 }
 
@@ -18,8 +20,8 @@ static KERNEL_HANDLE_IDA: SpinLock<IdAllocator> =
     SpinLock::new(IdAllocator::new(0, usize::MAX).unwrap());
 
 impl GlobalSocketHandle {
-    pub fn new_smoltcp_handle(handle: SocketHandle) -> Self {
-        return Self::Smoltcp(handle);
+    pub fn new_smoltcp_handle(netns_inum: usize, handle: SocketHandle) -> Self {
+        return Self::Smoltcp(netns_inum, handle);
     }
 
     pub fn new_kernel_handle() -> Self {
@@ -27,7 +29,7 @@ impl GlobalSocketHandle {
     }
 
     pub fn smoltcp_handle(&self) -> Option<SocketHandle> {
-        if let Self::Smoltcp(sh) = *self {
+        if let Self::Smoltcp(_, sh) = *self {
             return Some(sh);
         }
         None
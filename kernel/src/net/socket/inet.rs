@@ -9,15 +9,13 @@ use system_error::SystemError;
 use crate::{
     driver::net::NetDevice,
     libs::rwlock::RwLock,
-    net::{
-        event_poll::EPollEventType, net_core::poll_ifaces, Endpoint, Protocol, ShutdownType,
-        NET_DEVICES,
-    },
+    net::{event_poll::EPollEventType, net_core::poll_ifaces, Endpoint, Protocol, ShutdownType},
+    process::{namespace::net::NetNamespace, ProcessManager},
 };
 
 use super::{
     handle::GlobalSocketHandle, PosixSocketHandleItem, Socket, SocketHandleItem, SocketMetadata,
-    SocketOptions, SocketPollMethod, SocketType, HANDLE_MAP, PORT_MANAGER, SOCKET_SET,
+    SocketOptions, SocketPollMethod, SocketType, HANDLE_MAP,
 };
 
 /// @brief 表示原始的socket。原始套接字绕过传输层协议（如 TCP 或 UDP）并提供对网络层协议（如 IP）的直接访问。
@@ -33,6 +31,8 @@ pub struct RawSocket {
     /// socket的metadata
     metadata: SocketMetadata,
     posix_item: Arc<PosixSocketHandleItem>,
+    /// socket所在的network namespace
+    netns: Arc<NetNamespace>,
 }
 
 impl RawSocket {
@@ -66,8 +66,12 @@ impl RawSocket {
             tx_buffer,
         );
 
-        // 把socket添加到socket集合中，并得到socket的句柄
-        let handle = GlobalSocketHandle::new_smoltcp_handle(SOCKET_SET.lock_irqsave().add(socket));
+        // 把socket添加到当前network namespace的socket集合中，并得到socket的句柄
+        let netns = ProcessManager::current_pcb().nsproxy().net_ns.clone();
+        let handle = GlobalSocketHandle::new_smoltcp_handle(
+            netns.inum(),
+            netns.socket_set().lock_irqsave().add(socket),
+        );
 
         let metadata = SocketMetadata::new(
             SocketType::Raw,
@@ -84,6 +88,7 @@ impl RawSocket {
             header_included: false,
             metadata,
             posix_item,
+            netns,
         };
     }
 }
@@ -94,7 +99,7 @@ impl Socket for RawSocket {
     }
 
     fn close(&mut self) {
-        let mut socket_set_guard = SOCKET_SET.lock_irqsave();
+        let mut socket_set_guard = self.netns.socket_set().lock_irqsave();
         if let smoltcp::socket::Socket::Udp(mut sock) =
             socket_set_guard.remove(self.handle.smoltcp_handle().unwrap())
         {
@@ -108,7 +113,7 @@ impl Socket for RawSocket {
         poll_ifaces();
         loop {
             // 如何优化这里？
-            let mut socket_set_guard = SOCKET_SET.lock_irqsave();
+            let mut socket_set_guard = self.netns.socket_set().lock_irqsave();
             let socket =
                 socket_set_guard.get_mut::<raw::Socket>(self.handle.smoltcp_handle().unwrap());
 
@@ -138,7 +143,7 @@ impl Socket for RawSocket {
     fn write(&self, buf: &[u8], to: Option<Endpoint>) -> Result<usize, SystemError> {
         // 如果用户发送的数据包，包含IP头，则直接发送
         if self.header_included {
-            let mut socket_set_guard = SOCKET_SET.lock_irqsave();
+            let mut socket_set_guard = self.netns.socket_set().lock_irqsave();
             let socket =
                 socket_set_guard.get_mut::<raw::Socket>(self.handle.smoltcp_handle().unwrap());
             match socket.send_slice(buf) {
@@ -153,12 +158,14 @@ impl Socket for RawSocket {
             // 如果用户发送的数据包，不包含IP头，则需要自己构造IP头
 
             if let Some(Endpoint::Ip(Some(endpoint))) = to {
-                let mut socket_set_guard = SOCKET_SET.lock_irqsave();
+                let mut socket_set_guard = self.netns.socket_set().lock_irqsave();
                 let socket: &mut raw::Socket =
                     socket_set_guard.get_mut::<raw::Socket>(self.handle.smoltcp_handle().unwrap());
 
-                // 暴力解决方案：只考虑0号网卡。 TODO：考虑多网卡的情况！！！
-                let iface = NET_DEVICES.read_irqsave().get(&0).unwrap().clone();
+                let iface = self
+                    .netns
+                    .route_iface(&endpoint.addr)
+                    .ok_or(SystemError::ENETUNREACH)?;
 
                 // 构造IP头
                 let ipv4_src_addr: Option<wire::Ipv4Address> =
@@ -245,6 +252,8 @@ pub struct UdpSocket {
     remote_endpoint: Option<Endpoint>, // 记录远程endpoint提供给connect()， 应该使用IP地址。
     metadata: SocketMetadata,
     posix_item: Arc<PosixSocketHandleItem>,
+    /// socket所在的network namespace
+    netns: Arc<NetNamespace>,
 }
 
 impl UdpSocket {
@@ -271,9 +280,12 @@ impl UdpSocket {
         );
         let socket = udp::Socket::new(rx_buffer, tx_buffer);
 
-        // 把socket添加到socket集合中，并得到socket的句柄
-        let handle: GlobalSocketHandle =
-            GlobalSocketHandle::new_smoltcp_handle(SOCKET_SET.lock_irqsave().add(socket));
+        // 把socket添加到当前network namespace的socket集合中，并得到socket的句柄
+        let netns = ProcessManager::current_pcb().nsproxy().net_ns.clone();
+        let handle: GlobalSocketHandle = GlobalSocketHandle::new_smoltcp_handle(
+            netns.inum(),
+            netns.socket_set().lock_irqsave().add(socket),
+        );
 
         let metadata = SocketMetadata::new(
             SocketType::Udp,
@@ -290,6 +302,7 @@ impl UdpSocket {
             remote_endpoint: None,
             metadata,
             posix_item,
+            netns,
         };
     }
 
@@ -297,10 +310,15 @@ impl UdpSocket {
         if let Endpoint::Ip(Some(mut ip)) = endpoint {
             // 端口为0则分配随机端口
             if ip.port == 0 {
-                ip.port = PORT_MANAGER.get_ephemeral_port(self.metadata.socket_type)?;
+                ip.port = self
+                    .netns
+                    .port_manager()
+                    .get_ephemeral_port(self.metadata.socket_type)?;
             }
             // 检测端口是否已被占用
-            PORT_MANAGER.bind_port(self.metadata.socket_type, ip.port)?;
+            self.netns
+                .port_manager()
+                .bind_port(self.metadata.socket_type, ip.port)?;
 
             let bind_res = if ip.addr.is_unspecified() {
                 socket.bind(ip.port)
@@ -324,7 +342,13 @@ impl Socket for UdpSocket {
     }
 
     fn close(&mut self) {
-        let mut socket_set_guard = SOCKET_SET.lock_irqsave();
+        if let Some(Endpoint::Ip(Some(ip))) = self.endpoint() {
+            self.netns
+                .port_manager()
+                .unbind_port(self.metadata.socket_type, ip.port);
+        }
+
+        let mut socket_set_guard = self.netns.socket_set().lock_irqsave();
         if let smoltcp::socket::Socket::Udp(mut sock) =
             socket_set_guard.remove(self.handle.smoltcp_handle().unwrap())
         {
@@ -339,7 +363,7 @@ impl Socket for UdpSocket {
         loop {
             // debug!("Wait22 to Read");
             poll_ifaces();
-            let mut socket_set_guard = SOCKET_SET.lock_irqsave();
+            let mut socket_set_guard = self.netns.socket_set().lock_irqsave();
             let socket =
                 socket_set_guard.get_mut::<udp::Socket>(self.handle.smoltcp_handle().unwrap());
 
@@ -373,7 +397,7 @@ impl Socket for UdpSocket {
         };
         // debug!("udp write: remote = {:?}", remote_endpoint);
 
-        let mut socket_set_guard = SOCKET_SET.lock_irqsave();
+        let mut socket_set_guard = self.netns.socket_set().lock_irqsave();
         let socket = socket_set_guard.get_mut::<udp::Socket>(self.handle.smoltcp_handle().unwrap());
         // debug!("is open()={}", socket.is_open());
         // debug!("socket endpoint={:?}", socket.endpoint());
@@ -398,14 +422,14 @@ impl Socket for UdpSocket {
     }
 
     fn bind(&mut self, endpoint: Endpoint) -> Result<(), SystemError> {
-        let mut sockets = SOCKET_SET.lock_irqsave();
+        let mut sockets = self.netns.socket_set().lock_irqsave();
         let socket = sockets.get_mut::<udp::Socket>(self.handle.smoltcp_handle().unwrap());
         // debug!("UDP Bind to {:?}", endpoint);
         return self.do_bind(socket, endpoint);
     }
 
     fn poll(&self) -> EPollEventType {
-        let sockets = SOCKET_SET.lock_irqsave();
+        let sockets = self.netns.socket_set().lock_irqsave();
         let socket = sockets.get::<udp::Socket>(self.handle.smoltcp_handle().unwrap());
 
         return SocketPollMethod::udp_poll(
@@ -446,7 +470,7 @@ impl Socket for UdpSocket {
     }
 
     fn endpoint(&self) -> Option<Endpoint> {
-        let sockets = SOCKET_SET.lock_irqsave();
+        let sockets = self.netns.socket_set().lock_irqsave();
         let socket = sockets.get::<udp::Socket>(self.handle.smoltcp_handle().unwrap());
         let listen_endpoint = socket.endpoint();
 
@@ -493,6 +517,8 @@ pub struct TcpSocket {
     is_listening: bool,
     metadata: SocketMetadata,
     posix_item: Arc<PosixSocketHandleItem>,
+    /// socket所在的network namespace
+    netns: Arc<NetNamespace>,
 }
 
 impl TcpSocket {
@@ -513,9 +539,14 @@ impl TcpSocket {
     ///
     /// @return 返回创建的tcp的socket
     pub fn new(options: SocketOptions) -> Self {
-        // 创建handles数组并把socket添加到socket集合中，并得到socket的句柄
+        // 创建handles数组并把socket添加到当前network namespace的socket集合中，并得到socket的句柄
+        let netns = ProcessManager::current_pcb().nsproxy().net_ns.clone();
         let handles: Vec<GlobalSocketHandle> = vec![GlobalSocketHandle::new_smoltcp_handle(
-            SOCKET_SET.lock_irqsave().add(Self::create_new_socket()),
+            netns.inum(),
+            netns
+                .socket_set()
+                .lock_irqsave()
+                .add(Self::create_new_socket()),
         )];
 
         let metadata = SocketMetadata::new(
@@ -534,6 +565,7 @@ impl TcpSocket {
             is_listening: false,
             metadata,
             posix_item,
+            netns,
         };
     }
 
@@ -573,7 +605,7 @@ impl TcpSocket {
 
     /// listening状态的posix socket是需要特殊处理的
     fn tcp_poll_listening(&self) -> EPollEventType {
-        let socketset_guard = SOCKET_SET.lock_irqsave();
+        let socketset_guard = self.netns.socket_set().lock_irqsave();
 
         let can_accept = self.handles.iter().any(|h| {
             if let Some(sh) = h.smoltcp_handle() {
@@ -598,9 +630,15 @@ impl Socket for TcpSocket {
     }
 
     fn close(&mut self) {
+        if let Some(Endpoint::Ip(Some(ip))) = self.endpoint() {
+            self.netns
+                .port_manager()
+                .unbind_port(self.metadata.socket_type, ip.port);
+        }
+
         for handle in self.handles.iter() {
             {
-                let mut socket_set_guard = SOCKET_SET.lock_irqsave();
+                let mut socket_set_guard = self.netns.socket_set().lock_irqsave();
                 let smoltcp_handle = handle.smoltcp_handle().unwrap();
                 socket_set_guard
                     .get_mut::<smoltcp::socket::tcp::Socket>(smoltcp_handle)
@@ -608,7 +646,8 @@ impl Socket for TcpSocket {
                 drop(socket_set_guard);
             }
             poll_ifaces();
-            SOCKET_SET
+            self.netns
+                .socket_set()
                 .lock_irqsave()
                 .remove(handle.smoltcp_handle().unwrap());
             // debug!("[Socket] [TCP] Close: {:?}", handle);
@@ -629,7 +668,7 @@ impl Socket for TcpSocket {
         // debug!("tcp socket:read, socket'len={}",self.handle.len());
         loop {
             poll_ifaces();
-            let mut socket_set_guard = SOCKET_SET.lock_irqsave();
+            let mut socket_set_guard = self.netns.socket_set().lock_irqsave();
 
             let socket = socket_set_guard
                 .get_mut::<tcp::Socket>(self.handles.first().unwrap().smoltcp_handle().unwrap());
@@ -691,7 +730,7 @@ impl Socket for TcpSocket {
         }
         // debug!("tcp socket:write, socket'len={}",self.handle.len());
 
-        let mut socket_set_guard = SOCKET_SET.lock_irqsave();
+        let mut socket_set_guard = self.netns.socket_set().lock_irqsave();
 
         let socket = socket_set_guard
             .get_mut::<tcp::Socket>(self.handles.first().unwrap().smoltcp_handle().unwrap());
@@ -726,7 +765,7 @@ impl Socket for TcpSocket {
 
         assert!(self.handles.len() == 1);
 
-        let mut socket_set_guard = SOCKET_SET.lock_irqsave();
+        let mut socket_set_guard = self.netns.socket_set().lock_irqsave();
         // debug!("tcp socket:poll, socket'len={}",self.handle.len());
 
         let socket = socket_set_guard
@@ -741,19 +780,27 @@ impl Socket for TcpSocket {
     }
 
     fn connect(&mut self, endpoint: Endpoint) -> Result<(), SystemError> {
-        let mut sockets = SOCKET_SET.lock_irqsave();
+        let mut sockets = self.netns.socket_set().lock_irqsave();
         // debug!("tcp socket:connect, socket'len={}", self.handles.len());
 
         let socket =
             sockets.get_mut::<tcp::Socket>(self.handles.first().unwrap().smoltcp_handle().unwrap());
 
         if let Endpoint::Ip(Some(ip)) = endpoint {
-            let temp_port = PORT_MANAGER.get_ephemeral_port(self.metadata.socket_type)?;
+            let temp_port = self
+                .netns
+                .port_manager()
+                .get_ephemeral_port(self.metadata.socket_type)?;
             // 检测端口是否被占用
-            PORT_MANAGER.bind_port(self.metadata.socket_type, temp_port)?;
+            self.netns
+                .port_manager()
+                .bind_port(self.metadata.socket_type, temp_port)?;
 
             // debug!("temp_port: {}", temp_port);
-            let iface: Arc<dyn NetDevice> = NET_DEVICES.write_irqsave().get(&0).unwrap().clone();
+            let iface: Arc<dyn NetDevice> = self
+                .netns
+                .route_iface(&ip.addr)
+                .ok_or(SystemError::ENETUNREACH)?;
             let mut inner_iface = iface.inner_iface().lock();
             // debug!("to connect: {ip:?}");
 
@@ -765,7 +812,7 @@ impl Socket for TcpSocket {
                     drop(sockets);
                     loop {
                         poll_ifaces();
-                        let mut sockets = SOCKET_SET.lock_irqsave();
+                        let mut sockets = self.netns.socket_set().lock_irqsave();
                         let socket = sockets.get_mut::<tcp::Socket>(
                             self.handles.first().unwrap().smoltcp_handle().unwrap(),
                         );
@@ -811,7 +858,7 @@ impl Socket for TcpSocket {
         // );
 
         let local_endpoint = self.local_endpoint.ok_or(SystemError::EINVAL)?;
-        let mut sockets = SOCKET_SET.lock_irqsave();
+        let mut sockets = self.netns.socket_set().lock_irqsave();
         // 获取handle的数量
         let handlen = self.handles.len();
         let backlog = handlen.max(backlog);
@@ -824,7 +871,8 @@ impl Socket for TcpSocket {
 
         self.handles.extend((handlen..backlog).map(|_| {
             let socket = Self::create_new_socket();
-            let handle = GlobalSocketHandle::new_smoltcp_handle(sockets.add(socket));
+            let handle =
+                GlobalSocketHandle::new_smoltcp_handle(self.netns.inum(), sockets.add(socket));
             let mut handle_item = SocketHandleItem::new(Arc::downgrade(&self.posix_item));
             handle_item.is_posix_listen = true;
             handle_guard.insert(handle, handle_item);
@@ -853,11 +901,16 @@ impl Socket for TcpSocket {
     fn bind(&mut self, endpoint: Endpoint) -> Result<(), SystemError> {
         if let Endpoint::Ip(Some(mut ip)) = endpoint {
             if ip.port == 0 {
-                ip.port = PORT_MANAGER.get_ephemeral_port(self.metadata.socket_type)?;
+                ip.port = self
+                    .netns
+                    .port_manager()
+                    .get_ephemeral_port(self.metadata.socket_type)?;
             }
 
             // 检测端口是否已被占用
-            PORT_MANAGER.bind_port(self.metadata.socket_type, ip.port)?;
+            self.netns
+                .port_manager()
+                .bind_port(self.metadata.socket_type, ip.port)?;
             // debug!("tcp socket:bind, socket'len={}",self.handle.len());
 
             self.local_endpoint = Some(ip);
@@ -888,7 +941,7 @@ impl Socket for TcpSocket {
             poll_ifaces();
             // debug!("tcp socket:accept, socket'len={}", self.handle_list.len());
 
-            let mut sockset = SOCKET_SET.lock_irqsave();
+            let mut sockset = self.netns.socket_set().lock_irqsave();
             // Get the corresponding activated handler
             let global_handle_index = self.handles.iter().position(|handle| {
                 let con_smol_sock = sockset.get::<tcp::Socket>(handle.smoltcp_handle().unwrap());
@@ -907,7 +960,10 @@ impl Socket for TcpSocket {
 
                 let tcp_socket = Self::create_new_socket();
 
-                let new_handle = GlobalSocketHandle::new_smoltcp_handle(sockset.add(tcp_socket));
+                let new_handle = GlobalSocketHandle::new_smoltcp_handle(
+                    self.netns.inum(),
+                    sockset.add(tcp_socket),
+                );
 
                 // let handle in TcpSock be the new empty handle, and return the old connected handle
                 let old_handle = core::mem::replace(&mut self.handles[handle_index], new_handle);
@@ -926,6 +982,7 @@ impl Socket for TcpSocket {
                     is_listening: false,
                     metadata,
                     posix_item: Arc::new(PosixSocketHandleItem::new(None)),
+                    netns: self.netns.clone(),
                 });
 
                 {
@@ -967,7 +1024,7 @@ impl Socket for TcpSocket {
         let mut result: Option<Endpoint> = self.local_endpoint.map(|x| Endpoint::Ip(Some(x)));
 
         if result.is_none() {
-            let sockets = SOCKET_SET.lock_irqsave();
+            let sockets = self.netns.socket_set().lock_irqsave();
             // debug!("tcp socket:endpoint, socket'len={}",self.handle.len());
 
             let socket =
@@ -980,7 +1037,7 @@ impl Socket for TcpSocket {
     }
 
     fn peer_endpoint(&self) -> Option<Endpoint> {
-        let sockets = SOCKET_SET.lock_irqsave();
+        let sockets = self.netns.socket_set().lock_irqsave();
         // debug!("tcp socket:peer_endpoint, socket'len={}",self.handle.len());
 
         let socket =
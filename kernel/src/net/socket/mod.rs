@@ -9,10 +9,7 @@ use alloc::{
 };
 use hashbrown::HashMap;
 use log::warn;
-use smoltcp::{
-    iface::SocketSet,
-    socket::{self, raw, tcp, udp},
-};
+use smoltcp::socket::{self, raw, tcp, udp};
 use system_error::SystemError;
 
 use crate::{
@@ -26,7 +23,7 @@ use crate::{
         spinlock::{SpinLock, SpinLockGuard},
         wait_queue::EventWaitQueue,
     },
-    process::{Pid, ProcessManager},
+    process::{capability::capable, cred::CAPFlags, Pid, ProcessManager},
     sched::{schedule, SchedMode},
 };
 
@@ -34,9 +31,11 @@ use self::{
     handle::GlobalSocketHandle,
     inet::{RawSocket, TcpSocket, UdpSocket},
     unix::{SeqpacketSocket, StreamSocket},
+    vsock::VsockStreamSocket,
 };
 
 use super::{
+    dev_ioctl::dev_ioctl,
     event_poll::{EPollEventType, EPollItem, EventPoll},
     Endpoint, Protocol, ShutdownType,
 };
@@ -44,16 +43,12 @@ use super::{
 pub mod handle;
 pub mod inet;
 pub mod unix;
+pub mod vsock;
 
 lazy_static! {
-    /// 所有socket的集合
-    /// TODO: 优化这里，自己实现SocketSet！！！现在这样的话，不管全局有多少个网卡，每个时间点都只会有1个进程能够访问socket
-    pub static ref SOCKET_SET: SpinLock<SocketSet<'static >> = SpinLock::new(SocketSet::new(vec![]));
     /// SocketHandle表，每个SocketHandle对应一个SocketHandleItem，
     /// 注意！：在网卡中断中需要拿到这张表的🔓，在获取读锁时应该确保关中断避免死锁
     pub static ref HANDLE_MAP: RwLock<HashMap<GlobalSocketHandle, SocketHandleItem>> = RwLock::new(HashMap::new());
-    /// 端口管理器
-    pub static ref PORT_MANAGER: PortManager = PortManager::new();
 }
 
 /* For setsockopt(2) */
@@ -77,7 +72,18 @@ pub(super) fn new_socket(
         AddressFamily::INet => match socket_type {
             PosixSocketType::Stream => Box::new(TcpSocket::new(SocketOptions::default())),
             PosixSocketType::Datagram => Box::new(UdpSocket::new(SocketOptions::default())),
-            PosixSocketType::Raw => Box::new(RawSocket::new(protocol, SocketOptions::default())),
+            PosixSocketType::Raw => {
+                if !capable(CAPFlags::CAP_NET_RAW) {
+                    return Err(SystemError::EPERM);
+                }
+                Box::new(RawSocket::new(protocol, SocketOptions::default()))
+            }
+            _ => {
+                return Err(SystemError::EINVAL);
+            }
+        },
+        AddressFamily::Vsock => match socket_type {
+            PosixSocketType::Stream => Box::new(VsockStreamSocket::new(SocketOptions::default())),
             _ => {
                 return Err(SystemError::EINVAL);
             }
@@ -308,10 +314,6 @@ impl SocketInode {
                 return Ok(());
             }
 
-            if let Some(Endpoint::Ip(Some(ip))) = socket.endpoint() {
-                PORT_MANAGER.unbind_port(socket.metadata().socket_type, ip.port);
-            }
-
             socket.clear_epoll()?;
 
             HANDLE_MAP
@@ -347,6 +349,16 @@ impl IndexNode for SocketInode {
         self.do_close()
     }
 
+    /// 网络设备相关的命令（例如SIOCSIFADDR）作用于调用者所在的network namespace
+    fn ioctl(
+        &self,
+        cmd: u32,
+        data: usize,
+        _private_data: &FilePrivateData,
+    ) -> Result<usize, SystemError> {
+        dev_ioctl(cmd, data)
+    }
+
     fn read_at(
         &self,
         _offset: usize,
@@ -590,6 +602,8 @@ pub enum SocketType {
     Udp,
     /// unix域的 Socket
     Unix,
+    /// vsock的 Socket
+    Vsock,
 }
 
 bitflags! {
@@ -0,0 +1,973 @@
+//! AF_VSOCK流式套接字
+//!
+//! vsock用于虚拟机与宿主机之间的通信，地址由(cid, port)组成，宿主机的cid固定为2。
+//! 这里实现了与传输方式无关的连接管理和流控，具体的数据包收发由`VsockTransport`
+//! （目前只有virtio-vsock）完成。
+//!
+//! 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/net/vmw_vsock/af_vsock.c
+//! 以及 https://code.dragonos.org.cn/xref/linux-6.6.21/net/vmw_vsock/virtio_transport_common.c
+
+use core::fmt::Debug;
+
+use alloc::{
+    boxed::Box,
+    collections::{BTreeMap, BTreeSet, VecDeque},
+    sync::Arc,
+};
+use log::warn;
+use system_error::SystemError;
+
+use crate::{
+    libs::{rwlock::RwLock, spinlock::SpinLock, wait_queue::WaitQueue},
+    net::{
+        event_poll::{EPollEventType, EventPoll},
+        Endpoint, ShutdownType,
+    },
+    process::ProcessManager,
+    sched::SchedMode,
+    time::timer::{next_n_ms_timer_jiffies, Timer, WakeUpHelper},
+};
+
+use super::{
+    handle::GlobalSocketHandle, PosixSocketHandleItem, Socket, SocketHandleItem, SocketMetadata,
+    SocketOptions, SocketType, HANDLE_MAP,
+};
+
+/// 任意cid
+pub const VMADDR_CID_ANY: u32 = u32::MAX;
+/// 宿主机的cid
+pub const VMADDR_CID_HOST: u32 = 2;
+/// 任意端口
+pub const VMADDR_PORT_ANY: u32 = u32::MAX;
+
+/// 自动分配端口的起始值
+const VSOCK_EPHEMERAL_PORT_START: u32 = 49152;
+
+/// 每个连接的接收缓冲区大小（即通告给对端的buf_alloc）
+const VSOCK_BUF_ALLOC: u32 = 256 * 1024;
+/// 当对端认为的剩余空间小于该值时，主动发送CREDIT_UPDATE
+const VSOCK_CREDIT_UPDATE_THRESHOLD: u32 = 64 * 1024;
+
+/// 发起连接后等待对端响应的时间（毫秒）
+///
+/// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/net/vmw_vsock/af_vsock.c#134
+const VSOCK_DEFAULT_CONNECT_TIMEOUT_MS: u64 = 2000;
+
+/// 流式套接字的数据包类型
+pub const VIRTIO_VSOCK_TYPE_STREAM: u16 = 1;
+
+/// 数据包的操作类型
+///
+/// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/include/uapi/linux/virtio_vsock.h#80
+pub mod op {
+    pub const VIRTIO_VSOCK_OP_INVALID: u16 = 0;
+    pub const VIRTIO_VSOCK_OP_REQUEST: u16 = 1;
+    pub const VIRTIO_VSOCK_OP_RESPONSE: u16 = 2;
+    pub const VIRTIO_VSOCK_OP_RST: u16 = 3;
+    pub const VIRTIO_VSOCK_OP_SHUTDOWN: u16 = 4;
+    pub const VIRTIO_VSOCK_OP_RW: u16 = 5;
+    pub const VIRTIO_VSOCK_OP_CREDIT_UPDATE: u16 = 6;
+    pub const VIRTIO_VSOCK_OP_CREDIT_REQUEST: u16 = 7;
+}
+
+use op::*;
+
+/// vsock的端点
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VsockEndpoint {
+    pub cid: u32,
+    pub port: u32,
+}
+
+impl VsockEndpoint {
+    pub const fn new(cid: u32, port: u32) -> Self {
+        Self { cid, port }
+    }
+}
+
+/// vsock数据包的头部（小端序，紧凑排列）
+#[derive(Debug, Clone, Copy, Default)]
+pub struct VsockHeader {
+    pub src_cid: u64,
+    pub dst_cid: u64,
+    pub src_port: u32,
+    pub dst_port: u32,
+    pub len: u32,
+    pub ty: u16,
+    pub op: u16,
+    pub flags: u32,
+    pub buf_alloc: u32,
+    pub fwd_cnt: u32,
+}
+
+impl VsockHeader {
+    /// 头部的长度
+    pub const SIZE: usize = 44;
+
+    pub fn to_bytes(&self) -> [u8; Self::SIZE] {
+        let mut buf = [0u8; Self::SIZE];
+        buf[0..8].copy_from_slice(&self.src_cid.to_le_bytes());
+        buf[8..16].copy_from_slice(&self.dst_cid.to_le_bytes());
+        buf[16..20].copy_from_slice(&self.src_port.to_le_bytes());
+        buf[20..24].copy_from_slice(&self.dst_port.to_le_bytes());
+        buf[24..28].copy_from_slice(&self.len.to_le_bytes());
+        buf[28..30].copy_from_slice(&self.ty.to_le_bytes());
+        buf[30..32].copy_from_slice(&self.op.to_le_bytes());
+        buf[32..36].copy_from_slice(&self.flags.to_le_bytes());
+        buf[36..40].copy_from_slice(&self.buf_alloc.to_le_bytes());
+        buf[40..44].copy_from_slice(&self.fwd_cnt.to_le_bytes());
+        buf
+    }
+
+    pub fn from_bytes(buf: &[u8]) -> Option<Self> {
+        if buf.len() < Self::SIZE {
+            return None;
+        }
+        let u16_at = |i: usize| u16::from_le_bytes(buf[i..i + 2].try_into().unwrap());
+        let u32_at = |i: usize| u32::from_le_bytes(buf[i..i + 4].try_into().unwrap());
+        let u64_at = |i: usize| u64::from_le_bytes(buf[i..i + 8].try_into().unwrap());
+        Some(Self {
+            src_cid: u64_at(0),
+            dst_cid: u64_at(8),
+            src_port: u32_at(16),
+            dst_port: u32_at(20),
+            len: u32_at(24),
+            ty: u16_at(28),
+            op: u16_at(30),
+            flags: u32_at(32),
+            buf_alloc: u32_at(36),
+            fwd_cnt: u32_at(40),
+        })
+    }
+}
+
+/// vsock数据包的传输层
+pub trait VsockTransport: Send + Sync + Debug {
+    /// 本机的cid
+    fn guest_cid(&self) -> u32;
+
+    /// 发送一个数据包
+    fn send_pkt(&self, hdr: &VsockHeader, data: &[u8]) -> Result<(), SystemError>;
+
+    /// 单个数据包能携带的最大数据量
+    fn max_pkt_size(&self) -> usize;
+}
+
+static VSOCK_TRANSPORT: RwLock<Option<Arc<dyn VsockTransport>>> = RwLock::new(None);
+
+/// 注册vsock的传输层（目前只支持一个）
+pub fn vsock_register_transport(transport: Arc<dyn VsockTransport>) -> Result<(), SystemError> {
+    let mut guard = VSOCK_TRANSPORT.write_irqsave();
+    if guard.is_some() {
+        return Err(SystemError::EEXIST);
+    }
+    *guard = Some(transport);
+    Ok(())
+}
+
+fn vsock_transport() -> Result<Arc<dyn VsockTransport>, SystemError> {
+    VSOCK_TRANSPORT
+        .read_irqsave()
+        .clone()
+        .ok_or(SystemError::ENODEV)
+}
+
+/// 连接的唯一标识：(本地端口, 对端cid, 对端端口)
+type VsockConnKey = (u32, u32, u32);
+
+/// 全局的连接表
+#[derive(Debug)]
+struct VsockTable {
+    listeners: BTreeMap<u32, Arc<VsockListener>>,
+    conns: BTreeMap<VsockConnKey, Arc<VsockConnection>>,
+    /// 被套接字绑定的端口
+    bound_ports: BTreeSet<u32>,
+    next_ephemeral_port: u32,
+}
+
+impl VsockTable {
+    const fn new() -> Self {
+        Self {
+            listeners: BTreeMap::new(),
+            conns: BTreeMap::new(),
+            bound_ports: BTreeSet::new(),
+            next_ephemeral_port: VSOCK_EPHEMERAL_PORT_START,
+        }
+    }
+
+    fn bind_port(&mut self, port: u32) -> Result<u32, SystemError> {
+        if port != VMADDR_PORT_ANY {
+            if !self.bound_ports.insert(port) {
+                return Err(SystemError::EADDRINUSE);
+            }
+            return Ok(port);
+        }
+
+        for _ in VSOCK_EPHEMERAL_PORT_START..VMADDR_PORT_ANY {
+            let port = self.next_ephemeral_port;
+            self.next_ephemeral_port = if port == VMADDR_PORT_ANY - 1 {
+                VSOCK_EPHEMERAL_PORT_START
+            } else {
+                port + 1
+            };
+            if self.bound_ports.insert(port) {
+                return Ok(port);
+            }
+        }
+        Err(SystemError::EADDRINUSE)
+    }
+}
+
+static VSOCK_TABLE: SpinLock<VsockTable> = SpinLock::new(VsockTable::new());
+
+/// 唤醒等待在套接字上的进程以及epoll
+fn vsock_notify(
+    wait_queue: &WaitQueue,
+    posix_item: &PosixSocketHandleItem,
+    events: EPollEventType,
+) {
+    wait_queue.wakeup_all(None);
+    if events.is_empty() {
+        return;
+    }
+    posix_item.wakeup_any(events.bits() as u64);
+    let _ = EventPoll::wakeup_epoll(&posix_item.epitems, events);
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum VsockConnState {
+    Connecting,
+    Connected,
+    Closed,
+}
+
+#[derive(Debug)]
+struct InnerVsockConnection {
+    state: VsockConnState,
+    /// 连接失败的原因
+    error: Option<SystemError>,
+    rx_buf: VecDeque<u8>,
+    /// 已经被读取的字节数
+    fwd_cnt: u32,
+    /// 最近一次告知对端的fwd_cnt
+    last_fwd_cnt: u32,
+    /// 已经发送的字节数
+    tx_cnt: u32,
+    peer_buf_alloc: u32,
+    peer_fwd_cnt: u32,
+    /// 对端关闭的方向
+    peer_shutdown: ShutdownType,
+    /// 本端关闭的方向
+    shutdown: ShutdownType,
+}
+
+impl InnerVsockConnection {
+    /// 对端还能接收的字节数
+    fn credit(&self) -> u32 {
+        self.peer_buf_alloc
+            .saturating_sub(self.tx_cnt.wrapping_sub(self.peer_fwd_cnt))
+    }
+}
+
+/// 一个vsock连接
+#[derive(Debug)]
+struct VsockConnection {
+    local: VsockEndpoint,
+    peer: VsockEndpoint,
+    inner: SpinLock<InnerVsockConnection>,
+    wait_queue: WaitQueue,
+    posix_item: Arc<PosixSocketHandleItem>,
+}
+
+impl VsockConnection {
+    fn new(
+        local: VsockEndpoint,
+        peer: VsockEndpoint,
+        state: VsockConnState,
+        posix_item: Arc<PosixSocketHandleItem>,
+    ) -> Arc<Self> {
+        Arc::new(Self {
+            local,
+            peer,
+            inner: SpinLock::new(InnerVsockConnection {
+                state,
+                error: None,
+                rx_buf: VecDeque::new(),
+                fwd_cnt: 0,
+                last_fwd_cnt: 0,
+                tx_cnt: 0,
+                peer_buf_alloc: 0,
+                peer_fwd_cnt: 0,
+                peer_shutdown: ShutdownType::empty(),
+                shutdown: ShutdownType::empty(),
+            }),
+            wait_queue: WaitQueue::default(),
+            posix_item,
+        })
+    }
+
+    fn key(&self) -> VsockConnKey {
+        (self.local.port, self.peer.cid, self.peer.port)
+    }
+
+    fn state(&self) -> VsockConnState {
+        self.inner.lock_irqsave().state
+    }
+
+    fn send_pkt(&self, op: u16, flags: u32, data: &[u8]) -> Result<(), SystemError> {
+        let hdr = {
+            let mut inner = self.inner.lock_irqsave();
+            inner.last_fwd_cnt = inner.fwd_cnt;
+            VsockHeader {
+                src_cid: self.local.cid as u64,
+                dst_cid: self.peer.cid as u64,
+                src_port: self.local.port,
+                dst_port: self.peer.port,
+                len: data.len() as u32,
+                ty: VIRTIO_VSOCK_TYPE_STREAM,
+                op,
+                flags,
+                buf_alloc: VSOCK_BUF_ALLOC,
+                fwd_cnt: inner.fwd_cnt,
+            }
+        };
+        vsock_transport()?.send_pkt(&hdr, data)
+    }
+
+    fn notify(&self, events: EPollEventType) {
+        vsock_notify(&self.wait_queue, &self.posix_item, events);
+    }
+
+    /// 处理对端发来的数据包
+    fn recv_pkt(&self, hdr: &VsockHeader, data: &[u8]) {
+        let mut events = EPollEventType::empty();
+        let mut reply = None;
+        let mut remove = false;
+
+        {
+            let mut inner = self.inner.lock_irqsave();
+            inner.peer_buf_alloc = hdr.buf_alloc;
+            inner.peer_fwd_cnt = hdr.fwd_cnt;
+
+            match hdr.op {
+                VIRTIO_VSOCK_OP_RESPONSE => {
+                    if inner.state == VsockConnState::Connecting {
+                        inner.state = VsockConnState::Connected;
+                        events |= EPollEventType::EPOLLOUT | EPollEventType::EPOLLWRNORM;
+                    }
+                }
+                VIRTIO_VSOCK_OP_RW => {
+                    if inner.state != VsockConnState::Connected {
+                        return;
+                    }
+                    if inner.rx_buf.len() + data.len() > VSOCK_BUF_ALLOC as usize {
+                        warn!("vsock: peer {:?} exceeded its credit", self.peer);
+                        return;
+                    }
+                    inner.rx_buf.extend(data);
+                    events |= EPollEventType::EPOLLIN | EPollEventType::EPOLLRDNORM;
+                }
+                VIRTIO_VSOCK_OP_CREDIT_UPDATE => {
+                    events |= EPollEventType::EPOLLOUT | EPollEventType::EPOLLWRNORM;
+                }
+                VIRTIO_VSOCK_OP_CREDIT_REQUEST => {
+                    reply = Some(VIRTIO_VSOCK_OP_CREDIT_UPDATE);
+                }
+                VIRTIO_VSOCK_OP_SHUTDOWN => {
+                    inner.peer_shutdown |= ShutdownType::from_bits_truncate(hdr.flags as u8);
+                    events |= EPollEventType::EPOLLIN
+                        | EPollEventType::EPOLLRDNORM
+                        | EPollEventType::EPOLLRDHUP;
+                    if inner.peer_shutdown == ShutdownType::SHUTDOWN_MASK {
+                        // 对端已经完全关闭，回复RST结束连接
+                        inner.state = VsockConnState::Closed;
+                        reply = Some(VIRTIO_VSOCK_OP_RST);
+                        remove = true;
+                        events |= EPollEventType::EPOLLHUP;
+                    }
+                }
+                VIRTIO_VSOCK_OP_RST => {
+                    if inner.state == VsockConnState::Connecting {
+                        inner.error = Some(SystemError::ECONNRESET);
+                    }
+                    inner.state = VsockConnState::Closed;
+                    inner.peer_shutdown = ShutdownType::SHUTDOWN_MASK;
+                    remove = true;
+                    events |= EPollEventType::EPOLLIN
+                        | EPollEventType::EPOLLRDHUP
+                        | EPollEventType::EPOLLHUP;
+                }
+                _ => {}
+            }
+        }
+
+        if remove {
+            VSOCK_TABLE.lock_irqsave().conns.remove(&self.key());
+        }
+        if let Some(op) = reply {
+            self.send_pkt(op, 0, &[]).ok();
+        }
+        self.notify(events);
+    }
+
+    fn readable(&self) -> bool {
+        let inner = self.inner.lock_irqsave();
+        !inner.rx_buf.is_empty()
+            || inner.state == VsockConnState::Closed
+            || inner.peer_shutdown.contains(ShutdownType::SEND_SHUTDOWN)
+            || inner.shutdown.contains(ShutdownType::RCV_SHUTDOWN)
+    }
+
+    fn writable(&self) -> bool {
+        let inner = self.inner.lock_irqsave();
+        inner.credit() > 0
+            || inner.state == VsockConnState::Closed
+            || inner.peer_shutdown.contains(ShutdownType::RCV_SHUTDOWN)
+            || inner.shutdown.contains(ShutdownType::SEND_SHUTDOWN)
+    }
+
+    fn recv(&self, buf: &mut [u8]) -> Result<usize, SystemError> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        wq_wait_event_interruptible!(self.wait_queue, self.readable(), {})
+            .map_err(|_| SystemError::ERESTARTSYS)?;
+
+        let (len, update) = {
+            let mut inner = self.inner.lock_irqsave();
+            let len = core::cmp::min(buf.len(), inner.rx_buf.len());
+            for (dst, src) in buf.iter_mut().zip(inner.rx_buf.drain(..len)) {
+                *dst = src;
+            }
+            inner.fwd_cnt = inner.fwd_cnt.wrapping_add(len as u32);
+
+            // 对端认为我们还能接收的字节数
+            let peer_view = VSOCK_BUF_ALLOC
+                - inner.rx_buf.len() as u32
+                - inner.fwd_cnt.wrapping_sub(inner.last_fwd_cnt);
+            let update = len > 0
+                && inner.state == VsockConnState::Connected
+                && peer_view < VSOCK_CREDIT_UPDATE_THRESHOLD;
+            (len, update)
+        };
+
+        if update {
+            self.send_pkt(VIRTIO_VSOCK_OP_CREDIT_UPDATE, 0, &[]).ok();
+        }
+        Ok(len)
+    }
+
+    fn send(&self, buf: &[u8]) -> Result<usize, SystemError> {
+        let max_pkt_size = vsock_transport()?.max_pkt_size();
+        let mut sent = 0;
+        while sent < buf.len() {
+            let r = wq_wait_event_interruptible!(self.wait_queue, self.writable(), {});
+            if r.is_err() {
+                return if sent > 0 {
+                    Ok(sent)
+                } else {
+                    Err(SystemError::ERESTARTSYS)
+                };
+            }
+
+            let len = {
+                let inner = self.inner.lock_irqsave();
+                if inner.state != VsockConnState::Connected
+                    || inner.shutdown.contains(ShutdownType::SEND_SHUTDOWN)
+                    || inner.peer_shutdown.contains(ShutdownType::RCV_SHUTDOWN)
+                {
+                    return if sent > 0 {
+                        Ok(sent)
+                    } else {
+                        Err(SystemError::EPIPE)
+                    };
+                }
+                core::cmp::min(
+                    core::cmp::min(buf.len() - sent, inner.credit() as usize),
+                    max_pkt_size,
+                )
+            };
+
+            self.send_pkt(VIRTIO_VSOCK_OP_RW, 0, &buf[sent..sent + len])?;
+            let mut inner = self.inner.lock_irqsave();
+            inner.tx_cnt = inner.tx_cnt.wrapping_add(len as u32);
+            sent += len;
+        }
+        Ok(sent)
+    }
+
+    fn shutdown(&self, how: ShutdownType) -> Result<(), SystemError> {
+        {
+            let mut inner = self.inner.lock_irqsave();
+            if inner.state != VsockConnState::Connected {
+                return Err(SystemError::ENOTCONN);
+            }
+            inner.shutdown |= how;
+        }
+        self.send_pkt(VIRTIO_VSOCK_OP_SHUTDOWN, how.bits() as u32, &[])?;
+        self.notify(EPollEventType::empty());
+        Ok(())
+    }
+
+    /// 关闭连接
+    ///
+    /// 先告知对端不再收发数据，随后直接复位连接，而不等待对端确认
+    fn close(&self) {
+        let old = core::mem::replace(&mut self.inner.lock_irqsave().state, VsockConnState::Closed);
+        VSOCK_TABLE.lock_irqsave().conns.remove(&self.key());
+        if old == VsockConnState::Connected {
+            self.send_pkt(
+                VIRTIO_VSOCK_OP_SHUTDOWN,
+                ShutdownType::SHUTDOWN_MASK.bits() as u32,
+                &[],
+            )
+            .ok();
+        }
+        if old != VsockConnState::Closed {
+            self.send_pkt(VIRTIO_VSOCK_OP_RST, 0, &[]).ok();
+        }
+        self.notify(EPollEventType::empty());
+    }
+
+    fn poll(&self) -> EPollEventType {
+        let inner = self.inner.lock_irqsave();
+        let mut events = EPollEventType::empty();
+
+        if inner.state == VsockConnState::Connecting {
+            return events;
+        }
+        if inner.state == VsockConnState::Closed
+            || (inner.shutdown == ShutdownType::SHUTDOWN_MASK)
+            || (inner.peer_shutdown == ShutdownType::SHUTDOWN_MASK)
+        {
+            events |= EPollEventType::EPOLLHUP;
+        }
+        if !inner.rx_buf.is_empty() {
+            events |= EPollEventType::EPOLLIN | EPollEventType::EPOLLRDNORM;
+        }
+        if inner.peer_shutdown.contains(ShutdownType::SEND_SHUTDOWN)
+            || inner.shutdown.contains(ShutdownType::RCV_SHUTDOWN)
+        {
+            events |=
+                EPollEventType::EPOLLIN | EPollEventType::EPOLLRDNORM | EPollEventType::EPOLLRDHUP;
+        }
+        if inner.state == VsockConnState::Connected
+            && !inner.shutdown.contains(ShutdownType::SEND_SHUTDOWN)
+            && inner.credit() > 0
+        {
+            events |= EPollEventType::EPOLLOUT | EPollEventType::EPOLLWRNORM;
+        }
+        events
+    }
+}
+
+/// 监听中的端口
+#[derive(Debug)]
+struct VsockListener {
+    backlog: usize,
+    /// 已建立、等待accept的连接
+    pending: SpinLock<VecDeque<Arc<VsockConnection>>>,
+    wait_queue: WaitQueue,
+    posix_item: Arc<PosixSocketHandleItem>,
+}
+
+/// 处理从传输层收到的数据包
+///
+/// 由传输层在进程上下文中调用
+pub fn vsock_recv_pkt(hdr: &VsockHeader, data: &[u8]) {
+    let local = VsockEndpoint::new(hdr.dst_cid as u32, hdr.dst_port);
+    let peer = VsockEndpoint::new(hdr.src_cid as u32, hdr.src_port);
+
+    if hdr.ty == VIRTIO_VSOCK_TYPE_STREAM {
+        let (conn, listener) = {
+            let table = VSOCK_TABLE.lock_irqsave();
+            (
+                table.conns.get(&(local.port, peer.cid, peer.port)).cloned(),
+                table.listeners.get(&local.port).cloned(),
+            )
+        };
+
+        if let Some(conn) = conn {
+            conn.recv_pkt(hdr, data);
+            return;
+        }
+
+        if hdr.op == VIRTIO_VSOCK_OP_REQUEST {
+            if let Some(listener) = listener {
+                if vsock_accept_request(&listener, local, peer, hdr).is_ok() {
+                    return;
+                }
+            }
+        }
+    }
+
+    if hdr.op != VIRTIO_VSOCK_OP_RST {
+        vsock_reply_reset(hdr);
+    }
+}
+
+/// 为监听的端口建立新的连接
+fn vsock_accept_request(
+    listener: &Arc<VsockListener>,
+    local: VsockEndpoint,
+    peer: VsockEndpoint,
+    hdr: &VsockHeader,
+) -> Result<(), SystemError> {
+    if listener.pending.lock_irqsave().len() >= listener.backlog {
+        return Err(SystemError::ECONNREFUSED);
+    }
+
+    let conn = VsockConnection::new(
+        local,
+        peer,
+        VsockConnState::Connected,
+        Arc::new(PosixSocketHandleItem::new(None)),
+    );
+    {
+        let mut inner = conn.inner.lock_irqsave();
+        inner.peer_buf_alloc = hdr.buf_alloc;
+        inner.peer_fwd_cnt = hdr.fwd_cnt;
+    }
+
+    VSOCK_TABLE
+        .lock_irqsave()
+        .conns
+        .insert(conn.key(), conn.clone());
+    if let Err(e) = conn.send_pkt(VIRTIO_VSOCK_OP_RESPONSE, 0, &[]) {
+        VSOCK_TABLE.lock_irqsave().conns.remove(&conn.key());
+        return Err(e);
+    }
+
+    listener.pending.lock_irqsave().push_back(conn);
+    vsock_notify(
+        &listener.wait_queue,
+        &listener.posix_item,
+        EPollEventType::EPOLL_LISTEN_CAN_ACCEPT,
+    );
+    Ok(())
+}
+
+/// 对无法处理的数据包回复RST
+fn vsock_reply_reset(hdr: &VsockHeader) {
+    let Ok(transport) = vsock_transport() else {
+        return;
+    };
+    let rst = VsockHeader {
+        src_cid: hdr.dst_cid,
+        dst_cid: hdr.src_cid,
+        src_port: hdr.dst_port,
+        dst_port: hdr.src_port,
+        ty: hdr.ty,
+        op: VIRTIO_VSOCK_OP_RST,
+        ..Default::default()
+    };
+    transport.send_pkt(&rst, &[]).ok();
+}
+
+/// 传输层被复位（例如虚拟机迁移），所有的连接都失效
+pub fn vsock_transport_reset() {
+    let conns: alloc::vec::Vec<_> = core::mem::take(&mut VSOCK_TABLE.lock_irqsave().conns)
+        .into_values()
+        .collect();
+    for conn in conns {
+        {
+            let mut inner = conn.inner.lock_irqsave();
+            if inner.state == VsockConnState::Connecting {
+                inner.error = Some(SystemError::ECONNRESET);
+            }
+            inner.state = VsockConnState::Closed;
+            inner.peer_shutdown = ShutdownType::SHUTDOWN_MASK;
+        }
+        conn.notify(EPollEventType::EPOLLHUP | EPollEventType::EPOLLIN);
+    }
+}
+
+#[derive(Debug, Clone)]
+enum VsockSocketState {
+    Unconnected,
+    Listening(Arc<VsockListener>),
+    Connected(Arc<VsockConnection>),
+}
+
+/// AF_VSOCK的流式套接字
+#[derive(Debug, Clone)]
+pub struct VsockStreamSocket {
+    metadata: SocketMetadata,
+    handle: GlobalSocketHandle,
+    posix_item: Arc<PosixSocketHandleItem>,
+    local: Option<VsockEndpoint>,
+    /// 本地端口是否由该套接字占用（accept得到的套接字与监听套接字共享端口）
+    owns_port: bool,
+    state: VsockSocketState,
+}
+
+impl VsockStreamSocket {
+    /// 默认的元数据缓冲区大小
+    pub const DEFAULT_METADATA_BUF_SIZE: usize = 1024;
+
+    pub fn new(options: SocketOptions) -> Self {
+        let metadata = SocketMetadata::new(
+            SocketType::Vsock,
+            VSOCK_BUF_ALLOC as usize,
+            VSOCK_BUF_ALLOC as usize,
+            Self::DEFAULT_METADATA_BUF_SIZE,
+            options,
+        );
+
+        Self {
+            metadata,
+            handle: GlobalSocketHandle::new_kernel_handle(),
+            posix_item: Arc::new(PosixSocketHandleItem::new(None)),
+            local: None,
+            owns_port: false,
+            state: VsockSocketState::Unconnected,
+        }
+    }
+
+    fn do_bind(&mut self, endpoint: VsockEndpoint) -> Result<VsockEndpoint, SystemError> {
+        let guest_cid = vsock_transport()?.guest_cid();
+        if endpoint.cid != VMADDR_CID_ANY && endpoint.cid != guest_cid {
+            return Err(SystemError::EADDRNOTAVAIL);
+        }
+
+        let port = VSOCK_TABLE.lock_irqsave().bind_port(endpoint.port)?;
+        let local = VsockEndpoint::new(guest_cid, port);
+        self.local = Some(local);
+        self.owns_port = true;
+        Ok(local)
+    }
+
+    fn conn(&self) -> Result<&Arc<VsockConnection>, SystemError> {
+        match &self.state {
+            VsockSocketState::Connected(conn) => Ok(conn),
+            _ => Err(SystemError::ENOTCONN),
+        }
+    }
+}
+
+impl Socket for VsockStreamSocket {
+    fn read(&self, buf: &mut [u8]) -> (Result<usize, SystemError>, Endpoint) {
+        match self.conn() {
+            Ok(conn) => (conn.recv(buf), Endpoint::Vsock(conn.peer)),
+            Err(e) => (Err(e), Endpoint::Vsock(VsockEndpoint::new(0, 0))),
+        }
+    }
+
+    fn write(&self, buf: &[u8], _to: Option<Endpoint>) -> Result<usize, SystemError> {
+        self.conn()?.send(buf)
+    }
+
+    fn connect(&mut self, endpoint: Endpoint) -> Result<(), SystemError> {
+        let Endpoint::Vsock(peer) = endpoint else {
+            return Err(SystemError::EINVAL);
+        };
+        match self.state {
+            VsockSocketState::Connected(_) => return Err(SystemError::EISCONN),
+            VsockSocketState::Listening(_) => return Err(SystemError::EINVAL),
+            VsockSocketState::Unconnected => {}
+        }
+
+        let local = match self.local {
+            Some(local) => local,
+            None => self.do_bind(VsockEndpoint::new(VMADDR_CID_ANY, VMADDR_PORT_ANY))?,
+        };
+
+        let conn = VsockConnection::new(
+            local,
+            peer,
+            VsockConnState::Connecting,
+            self.posix_item.clone(),
+        );
+        {
+            let mut table = VSOCK_TABLE.lock_irqsave();
+            if table.conns.contains_key(&conn.key()) {
+                return Err(SystemError::EADDRINUSE);
+            }
+            table.conns.insert(conn.key(), conn.clone());
+        }
+
+        if let Err(e) = conn.send_pkt(VIRTIO_VSOCK_OP_REQUEST, 0, &[]) {
+            VSOCK_TABLE.lock_irqsave().conns.remove(&conn.key());
+            return Err(e);
+        }
+
+        // 对端一直没有响应时，由定时器唤醒当前进程
+        let timer = Timer::new(
+            WakeUpHelper::new(ProcessManager::current_pcb()),
+            next_n_ms_timer_jiffies(VSOCK_DEFAULT_CONNECT_TIMEOUT_MS),
+        );
+        timer.activate();
+        let r = wq_wait_event_interruptible!(
+            conn.wait_queue,
+            conn.state() != VsockConnState::Connecting || timer.timeout(),
+            {}
+        );
+        timer.cancel();
+        if r.is_err() {
+            conn.close();
+            return Err(SystemError::EINTR);
+        }
+        if conn.state() == VsockConnState::Connecting {
+            conn.close();
+            return Err(SystemError::ETIMEDOUT);
+        }
+
+        let mut inner = conn.inner.lock_irqsave();
+        if inner.state != VsockConnState::Connected {
+            return Err(inner.error.take().unwrap_or(SystemError::ECONNREFUSED));
+        }
+        drop(inner);
+
+        self.state = VsockSocketState::Connected(conn);
+        Ok(())
+    }
+
+    fn bind(&mut self, endpoint: Endpoint) -> Result<(), SystemError> {
+        let Endpoint::Vsock(endpoint) = endpoint else {
+            return Err(SystemError::EINVAL);
+        };
+        if self.local.is_some() {
+            return Err(SystemError::EINVAL);
+        }
+        self.do_bind(endpoint)?;
+        Ok(())
+    }
+
+    fn shutdown(&mut self, how: ShutdownType) -> Result<(), SystemError> {
+        self.conn()?.shutdown(how)
+    }
+
+    fn listen(&mut self, backlog: usize) -> Result<(), SystemError> {
+        let local = self.local.ok_or(SystemError::EINVAL)?;
+        let backlog = backlog.max(1);
+        match &self.state {
+            VsockSocketState::Connected(_) => return Err(SystemError::EINVAL),
+            VsockSocketState::Listening(listener) if listener.backlog == backlog => {
+                return Ok(());
+            }
+            _ => {}
+        }
+
+        let listener = Arc::new(VsockListener {
+            backlog,
+            pending: SpinLock::new(VecDeque::new()),
+            wait_queue: WaitQueue::default(),
+            posix_item: self.posix_item.clone(),
+        });
+        if let VsockSocketState::Listening(old) = &self.state {
+            core::mem::swap(
+                &mut *listener.pending.lock_irqsave(),
+                &mut *old.pending.lock_irqsave(),
+            );
+        }
+
+        VSOCK_TABLE
+            .lock_irqsave()
+            .listeners
+            .insert(local.port, listener.clone());
+        self.state = VsockSocketState::Listening(listener);
+        Ok(())
+    }
+
+    fn accept(&mut self) -> Result<(Box<dyn Socket>, Endpoint), SystemError> {
+        let VsockSocketState::Listening(listener) = &self.state else {
+            return Err(SystemError::EINVAL);
+        };
+
+        let conn = loop {
+            wq_wait_event_interruptible!(
+                listener.wait_queue,
+                !listener.pending.lock_irqsave().is_empty(),
+                {}
+            )
+            .map_err(|_| SystemError::ERESTARTSYS)?;
+
+            if let Some(conn) = listener.pending.lock_irqsave().pop_front() {
+                break conn;
+            }
+        };
+
+        let mut socket = VsockStreamSocket::new(self.metadata.options);
+        socket.posix_item = conn.posix_item.clone();
+        socket.local = Some(conn.local);
+        let peer = conn.peer;
+        socket.state = VsockSocketState::Connected(conn);
+
+        HANDLE_MAP.write_irqsave().insert(
+            socket.handle,
+            SocketHandleItem::new(Arc::downgrade(&socket.posix_item)),
+        );
+
+        Ok((Box::new(socket), Endpoint::Vsock(peer)))
+    }
+
+    fn endpoint(&self) -> Option<Endpoint> {
+        self.local.map(Endpoint::Vsock)
+    }
+
+    fn peer_endpoint(&self) -> Option<Endpoint> {
+        self.conn().ok().map(|conn| Endpoint::Vsock(conn.peer))
+    }
+
+    fn poll(&self) -> EPollEventType {
+        match &self.state {
+            VsockSocketState::Unconnected => EPollEventType::empty(),
+            VsockSocketState::Listening(listener) => {
+                if listener.pending.lock_irqsave().is_empty() {
+                    EPollEventType::empty()
+                } else {
+                    EPollEventType::EPOLL_LISTEN_CAN_ACCEPT
+                }
+            }
+            VsockSocketState::Connected(conn) => conn.poll(),
+        }
+    }
+
+    fn metadata(&self) -> SocketMetadata {
+        self.metadata.clone()
+    }
+
+    fn box_clone(&self) -> Box<dyn Socket> {
+        Box::new(self.clone())
+    }
+
+    fn socket_handle(&self) -> GlobalSocketHandle {
+        self.handle
+    }
+
+    fn as_any_ref(&self) -> &dyn core::any::Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn core::any::Any {
+        self
+    }
+
+    fn close(&mut self) {
+        match core::mem::replace(&mut self.state, VsockSocketState::Unconnected) {
+            VsockSocketState::Connected(conn) => conn.close(),
+            VsockSocketState::Listening(listener) => {
+                if let Some(local) = self.local {
+                    VSOCK_TABLE.lock_irqsave().listeners.remove(&local.port);
+                }
+                let pending = core::mem::take(&mut *listener.pending.lock_irqsave());
+                for conn in pending {
+                    conn.close();
+                }
+            }
+            VsockSocketState::Unconnected => {}
+        }
+
+        if self.owns_port {
+            if let Some(local) = self.local {
+                VSOCK_TABLE.lock_irqsave().bound_ports.remove(&local.port);
+            }
+            self.owns_port = false;
+        }
+    }
+
+    fn posix_item(&self) -> Arc<PosixSocketHandleItem> {
+        self.posix_item.clone()
+    }
+}
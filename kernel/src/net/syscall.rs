@@ -19,7 +19,7 @@ use crate::{
 };
 
 use super::{
-    socket::{new_socket, PosixSocketType, Socket, SocketInode},
+    socket::{new_socket, vsock::VsockEndpoint, PosixSocketType, Socket, SocketInode},
     Endpoint, Protocol, ShutdownType,
 };
 
@@ -539,6 +539,20 @@ pub struct SockAddrNl {
     nl_groups: u32,
 }
 
+/// vsock的地址
+///
+/// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/include/uapi/linux/vm_sockets.h#177
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct SockAddrVm {
+    pub svm_family: u16,
+    pub svm_reserved1: u16,
+    pub svm_port: u32,
+    pub svm_cid: u32,
+    pub svm_flags: u8,
+    pub svm_zero: [u8; 3],
+}
+
 #[repr(C)]
 #[derive(Debug, Clone, Copy)]
 pub struct SockAddrPlaceholder {
@@ -554,6 +568,7 @@ pub union SockAddr {
     pub addr_un: SockAddrUn,
     pub addr_ll: SockAddrLl,
     pub addr_nl: SockAddrNl,
+    pub addr_vm: SockAddrVm,
     pub addr_ph: SockAddrPlaceholder,
 }
 
@@ -613,6 +628,17 @@ impl SockAddr {
                     // TODO: support netlink socket
                     return Err(SystemError::EINVAL);
                 }
+                AddressFamily::Vsock => {
+                    if len < addr.len()? {
+                        return Err(SystemError::EINVAL);
+                    }
+
+                    let addr_vm: SockAddrVm = addr.addr_vm;
+                    return Ok(Endpoint::Vsock(VsockEndpoint::new(
+                        addr_vm.svm_cid,
+                        addr_vm.svm_port,
+                    )));
+                }
                 _ => {
                     return Err(SystemError::EINVAL);
                 }
@@ -626,6 +652,7 @@ impl SockAddr {
             AddressFamily::INet => Ok(core::mem::size_of::<SockAddrIn>()),
             AddressFamily::Packet => Ok(core::mem::size_of::<SockAddrLl>()),
             AddressFamily::Netlink => Ok(core::mem::size_of::<SockAddrNl>()),
+            AddressFamily::Vsock => Ok(core::mem::size_of::<SockAddrVm>()),
             AddressFamily::Unix => Err(SystemError::EINVAL),
             _ => Err(SystemError::EINVAL),
         };
@@ -721,6 +748,19 @@ impl From<Endpoint> for SockAddr {
                 return SockAddr { addr_ll };
             }
 
+            Endpoint::Vsock(vsock_endpoint) => {
+                let addr_vm = SockAddrVm {
+                    svm_family: AddressFamily::Vsock as u16,
+                    svm_reserved1: 0,
+                    svm_port: vsock_endpoint.port,
+                    svm_cid: vsock_endpoint.cid,
+                    svm_flags: 0,
+                    svm_zero: [0; 3],
+                };
+
+                return SockAddr { addr_vm };
+            }
+
             _ => {
                 // todo: support other endpoint, like Netlink...
                 unimplemented!("not support {value:?}");
@@ -1,3 +1,9 @@
+use system_error::SystemError;
+
+use crate::arch::ipc::signal::Signal;
+
+use super::exit::WaitIdInfo;
+
 /// An enumeration of the possible values for the `AT_*` constants.
 #[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
 pub enum AtType {
@@ -98,3 +104,78 @@ bitflags! {
         const WCLONE = 0x80000000;
     }
 }
+
+/// waitid的idtype参数
+///
+/// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/include/uapi/linux/wait.h#19
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum WaitIdType {
+    /// 等待任意子进程
+    All,
+    /// 等待指定pid的子进程
+    Pid,
+    /// 等待指定进程组中的子进程
+    Pgid,
+    /// 等待pidfd引用的子进程
+    PidFd,
+}
+
+impl TryFrom<u32> for WaitIdType {
+    type Error = SystemError;
+
+    fn try_from(value: u32) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(WaitIdType::All),
+            1 => Ok(WaitIdType::Pid),
+            2 => Ok(WaitIdType::Pgid),
+            3 => Ok(WaitIdType::PidFd),
+            _ => Err(SystemError::EINVAL),
+        }
+    }
+}
+
+/// waitid写入用户空间的siginfo_t（SIGCHLD的布局）
+///
+/// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/include/uapi/asm-generic/siginfo.h#29
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct WaitIdSigInfo {
+    pub si_signo: i32,
+    pub si_errno: i32,
+    pub si_code: i32,
+    _pad0: i32,
+    pub si_pid: i32,
+    pub si_uid: u32,
+    pub si_status: i32,
+    _pad1: i32,
+    pub si_utime: i64,
+    pub si_stime: i64,
+    _pad2: [u8; 80],
+}
+
+impl WaitIdSigInfo {
+    /// 构造waitid的返回信息，没有子进程的状态可以报告时，返回全零的siginfo
+    pub fn new(info: Option<&WaitIdInfo>) -> Self {
+        let mut siginfo = Self {
+            si_signo: 0,
+            si_errno: 0,
+            si_code: 0,
+            _pad0: 0,
+            si_pid: 0,
+            si_uid: 0,
+            si_status: 0,
+            _pad1: 0,
+            si_utime: 0,
+            si_stime: 0,
+            _pad2: [0; 80],
+        };
+        if let Some(info) = info {
+            siginfo.si_signo = Signal::SIGCHLD as i32;
+            siginfo.si_code = info.cause;
+            siginfo.si_pid = info.pid.data() as i32;
+            siginfo.si_uid = info.uid as u32;
+            siginfo.si_status = info.status;
+        }
+        siginfo
+    }
+}
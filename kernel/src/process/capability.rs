@@ -0,0 +1,297 @@
+//! POSIX capabilities：将超级用户的特权拆分为若干相互独立的capability
+//!
+//! 目前不支持文件capability，进程在execve时只根据uid与ambient集合计算新的capability集合。
+//!
+//! 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/kernel/capability.c
+
+use system_error::SystemError;
+
+use crate::{
+    filesystem::vfs::{syscall::ModeType, Metadata},
+    syscall::{
+        user_access::{UserBufferReader, UserBufferWriter},
+        Syscall,
+    },
+};
+
+use super::{
+    cred::{CAPFlags, Cred},
+    Pid, ProcessManager,
+};
+
+/// 只包含一组32位capability的旧版本接口
+const LINUX_CAPABILITY_VERSION_1: u32 = 0x19980330;
+const LINUX_CAPABILITY_U32S_1: usize = 1;
+/// 存在缺陷的64位接口，内核按照版本3处理
+const LINUX_CAPABILITY_VERSION_2: u32 = 0x20071026;
+const LINUX_CAPABILITY_VERSION_3: u32 = 0x20080522;
+const LINUX_CAPABILITY_U32S_3: usize = 2;
+
+/// capget/capset的头部
+///
+/// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/include/uapi/linux/capability.h#39
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CapUserHeader {
+    version: u32,
+    pid: i32,
+}
+
+/// capget/capset的数据，每个结构体保存32个capability
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CapUserData {
+    effective: u32,
+    permitted: u32,
+    inheritable: u32,
+}
+
+/// 当前进程是否拥有`cap`中的所有capability
+///
+/// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/kernel/capability.c#419
+pub fn capable(cap: CAPFlags) -> bool {
+    ProcessManager::current_pcb()
+        .cred
+        .lock()
+        .has_capability(cap)
+}
+
+impl Cred {
+    /// 凭证的effective集合中是否包含`cap`中的所有capability
+    pub fn has_capability(&self, cap: CAPFlags) -> bool {
+        self.cap_effective.contains(cap)
+    }
+
+    fn has_root_uid(&self) -> bool {
+        self.uid.data() == 0 || self.euid.data() == 0 || self.suid.data() == 0
+    }
+
+    /// set*uid修改了uid之后，根据新旧uid调整capability集合
+    ///
+    /// - ruid、euid、suid中原本有0，修改后全都不为0时，清空ambient集合。
+    ///   如果没有设置keep_caps，还会清空permitted与effective集合
+    /// - euid从0变为非0时清空effective集合，从非0变为0时将effective集合设置为permitted集合
+    ///
+    /// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/security/commoncap.c#1041
+    pub(super) fn fixup_setuid_caps(&mut self, old: &Cred) {
+        if old.has_root_uid() && !self.has_root_uid() {
+            if !self.keep_caps {
+                self.cap_permitted = CAPFlags::CAP_EMPTY_SET;
+                self.cap_effective = CAPFlags::CAP_EMPTY_SET;
+            }
+            self.cap_ambient = CAPFlags::CAP_EMPTY_SET;
+        }
+        if old.euid.data() == 0 && self.euid.data() != 0 {
+            self.cap_effective = CAPFlags::CAP_EMPTY_SET;
+        }
+        if old.euid.data() != 0 && self.euid.data() == 0 {
+            self.cap_effective = self.cap_permitted;
+        }
+    }
+
+    /// setfsuid修改了fsuid之后，根据新旧fsuid获得或失去文件系统相关的capability
+    ///
+    /// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/security/commoncap.c#1101
+    pub(super) fn fixup_setfsuid_caps(&mut self, old: &Cred) {
+        if old.fsuid.data() == 0 && self.fsuid.data() != 0 {
+            self.cap_effective.remove(CAPFlags::CAP_FS_SET);
+        }
+        if old.fsuid.data() != 0 && self.fsuid.data() == 0 {
+            self.cap_effective
+                .insert(self.cap_permitted & CAPFlags::CAP_FS_SET);
+        }
+    }
+
+    /// execve时根据可执行文件的元数据计算新的凭证
+    ///
+    /// - 文件设置了set-user-ID（set-group-ID）位时，euid（egid）变为文件的属主（属组）
+    /// - 新的euid或ruid为0时，permitted集合为bounding集合与inheritable集合的并集，
+    ///   并且在euid为0时设置effective集合为permitted集合
+    /// - 其它情况下，permitted与effective集合都等于ambient集合
+    /// - 设置了no_new_privs的进程不能通过execve获得新的特权
+    ///
+    /// 返回新的凭证是否比原来拥有更多的特权（此时进程不应被允许产生core dump）
+    ///
+    /// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/security/commoncap.c#888
+    pub(super) fn transform_for_exec(&mut self, metadata: &Metadata, no_new_privs: bool) -> bool {
+        let old = self.clone();
+
+        if !no_new_privs {
+            if metadata.mode.contains(ModeType::S_ISUID) {
+                self.seteuid(metadata.uid);
+            }
+            // 没有组执行权限的set-group-ID位表示强制锁，不改变egid
+            if metadata
+                .mode
+                .contains(ModeType::S_ISGID | ModeType::S_IXGRP)
+            {
+                self.setegid(metadata.gid);
+            }
+        }
+
+        let is_setid = self.euid != old.uid || self.egid != old.gid;
+        if is_setid {
+            self.cap_ambient = CAPFlags::CAP_EMPTY_SET;
+        }
+
+        if self.euid.data() == 0 || self.uid.data() == 0 {
+            self.cap_permitted = old.cap_bset | old.cap_inheritable;
+            self.cap_effective = if self.euid.data() == 0 {
+                self.cap_permitted
+            } else {
+                self.cap_ambient
+            };
+        } else {
+            self.cap_permitted = self.cap_ambient;
+            self.cap_effective = self.cap_ambient;
+        }
+
+        // no_new_privs：不允许获得原本没有的permitted capability
+        if no_new_privs && !old.cap_permitted.contains(self.cap_permitted) {
+            self.cap_permitted &= old.cap_permitted;
+            self.cap_effective &= old.cap_permitted;
+            self.cap_ambient &= old.cap_permitted;
+        }
+
+        let euid = self.euid.data();
+        let egid = self.egid.data();
+        self.setsuid(euid);
+        self.setfsuid(euid);
+        self.setsgid(egid);
+        self.setfsgid(egid);
+        self.keep_caps = false;
+
+        return is_setid || !old.cap_permitted.contains(self.cap_permitted);
+    }
+}
+
+/// 检查capget/capset的版本号，返回用户数据中结构体的数量
+///
+/// 版本号不支持时，将内核支持的版本号写回用户的头部并返回EINVAL
+///
+/// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/kernel/capability.c#80
+fn cap_validate_version(header: *mut CapUserHeader) -> Result<usize, SystemError> {
+    let reader = UserBufferReader::new(header, core::mem::size_of::<CapUserHeader>(), true)?;
+    let version = reader.read_one_from_user::<CapUserHeader>(0)?.version;
+
+    match version {
+        LINUX_CAPABILITY_VERSION_1 => Ok(LINUX_CAPABILITY_U32S_1),
+        LINUX_CAPABILITY_VERSION_2 | LINUX_CAPABILITY_VERSION_3 => Ok(LINUX_CAPABILITY_U32S_3),
+        _ => {
+            let mut writer =
+                UserBufferWriter::new(header as *mut u32, core::mem::size_of::<u32>(), true)?;
+            writer.copy_one_to_user(&LINUX_CAPABILITY_VERSION_3, 0)?;
+            Err(SystemError::EINVAL)
+        }
+    }
+}
+
+impl Syscall {
+    /// # 获取进程的capability集合
+    ///
+    /// ## 参数
+    ///
+    /// - `header`: 接口版本与目标进程的pid（0表示当前进程）
+    /// - `data`: 保存结果的缓冲区，为NULL时只检查版本号
+    ///
+    /// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/kernel/capability.c#149
+    pub fn capget(
+        header: *mut CapUserHeader,
+        data: *mut CapUserData,
+    ) -> Result<usize, SystemError> {
+        let tocopy = match cap_validate_version(header) {
+            Ok(tocopy) => tocopy,
+            // 用户通过传入NULL的data来查询内核支持的版本号
+            Err(SystemError::EINVAL) if data.is_null() => return Ok(0),
+            Err(e) => return Err(e),
+        };
+        if data.is_null() {
+            return Ok(0);
+        }
+
+        let reader = UserBufferReader::new(header, core::mem::size_of::<CapUserHeader>(), true)?;
+        let pid = reader.read_one_from_user::<CapUserHeader>(0)?.pid;
+        if pid < 0 {
+            return Err(SystemError::EINVAL);
+        }
+        let cred = if pid == 0 {
+            ProcessManager::current_pcb().cred()
+        } else {
+            ProcessManager::find_vpid(Pid::new(pid as usize))
+                .ok_or(SystemError::ESRCH)?
+                .cred()
+        };
+
+        let mut writer =
+            UserBufferWriter::new(data, tocopy * core::mem::size_of::<CapUserData>(), true)?;
+        let kdata: [CapUserData; LINUX_CAPABILITY_U32S_3] = core::array::from_fn(|i| {
+            let shift = i * 32;
+            CapUserData {
+                effective: (cred.cap_effective.bits() >> shift) as u32,
+                permitted: (cred.cap_permitted.bits() >> shift) as u32,
+                inheritable: (cred.cap_inheritable.bits() >> shift) as u32,
+            }
+        });
+        writer.copy_to_user(&kdata[..tocopy], 0)?;
+
+        return Ok(0);
+    }
+
+    /// # 设置当前进程的capability集合
+    ///
+    /// 只能修改当前进程自身的capability集合，并且：
+    /// - 新的inheritable集合只能包含原有的inheritable与permitted集合中的capability（拥有CAP_SETPCAP时除外），
+    ///   并且不能超出bounding集合
+    /// - 新的permitted集合不能超出原有的permitted集合
+    /// - 新的effective集合不能超出新的permitted集合
+    ///
+    /// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/kernel/capability.c#223
+    pub fn capset(
+        header: *mut CapUserHeader,
+        data: *const CapUserData,
+    ) -> Result<usize, SystemError> {
+        let tocopy = cap_validate_version(header)?;
+
+        let reader = UserBufferReader::new(header, core::mem::size_of::<CapUserHeader>(), true)?;
+        let pid = reader.read_one_from_user::<CapUserHeader>(0)?.pid;
+        if pid != 0 && Pid::new(pid as usize) != ProcessManager::current_pcb().pid_vnr() {
+            return Err(SystemError::EPERM);
+        }
+
+        let reader =
+            UserBufferReader::new(data, tocopy * core::mem::size_of::<CapUserData>(), true)?;
+        let udata = reader.read_from_user::<CapUserData>(0)?;
+        let (mut effective, mut permitted, mut inheritable) = (0u64, 0u64, 0u64);
+        for (i, d) in udata.iter().enumerate().take(tocopy) {
+            let shift = i * 32;
+            effective |= (d.effective as u64) << shift;
+            permitted |= (d.permitted as u64) << shift;
+            inheritable |= (d.inheritable as u64) << shift;
+        }
+        let effective = CAPFlags::from_bits_truncate(effective);
+        let permitted = CAPFlags::from_bits_truncate(permitted);
+        let inheritable = CAPFlags::from_bits_truncate(inheritable);
+
+        let pcb = ProcessManager::current_pcb();
+        let mut cred = pcb.cred.lock();
+        if !cred.has_capability(CAPFlags::CAP_SETPCAP)
+            && !(cred.cap_inheritable | cred.cap_permitted).contains(inheritable)
+        {
+            return Err(SystemError::EPERM);
+        }
+        if !(cred.cap_inheritable | cred.cap_bset).contains(inheritable) {
+            return Err(SystemError::EPERM);
+        }
+        if !cred.cap_permitted.contains(permitted) || !permitted.contains(effective) {
+            return Err(SystemError::EPERM);
+        }
+
+        cred.cap_effective = effective;
+        cred.cap_permitted = permitted;
+        cred.cap_inheritable = inheritable;
+        // ambient集合中的capability必须同时存在于permitted与inheritable集合中
+        cred.cap_ambient &= permitted & inheritable;
+
+        return Ok(0);
+    }
+}
@@ -0,0 +1,671 @@
+//! 进程被信号终止时生成core dump
+//!
+//! core文件是一个ET_CORE类型的ELF文件：第一个程序头是PT_NOTE段，保存了收到信号的线程的寄存器
+//! （NT_PRSTATUS）、进程信息（NT_PRPSINFO）以及execve时传给程序的auxv（NT_AUXV）；
+//! 随后的每个PT_LOAD段对应进程地址空间中的一个VMA。
+//!
+//! 目前只保存收到信号的线程的寄存器，并且不支持将core dump通过管道交给用户程序处理。
+//!
+//! 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/fs/coredump.c
+
+use core::mem::size_of;
+
+use alloc::{
+    string::{String, ToString},
+    sync::Arc,
+    vec::Vec,
+};
+use log::{info, warn};
+use system_error::SystemError;
+
+use crate::{
+    arch::{interrupt::TrapFrame, ipc::signal::Signal, CurrentElfArch, MMArch},
+    filesystem::{
+        inotify::fsnotify_delete,
+        vfs::{
+            fcntl::AtFlags,
+            file::{File, FileMode},
+            open::do_filp_open,
+            syscall::{ModeType, OpenHow, OpenHowResolve},
+            utils::{generic_permission, rsplit_path, user_path_at, MAY_EXEC, MAY_WRITE},
+            FileType, VFS_MAX_FOLLOW_SYMLINK_TIMES,
+        },
+    },
+    libs::{elf::ElfArch, spinlock::SpinLock},
+    mm::{ucontext::AddressSpace, MemoryManagementArch, VirtAddr, VmFlags},
+    time::PosixTimeSpec,
+};
+
+use super::{
+    cred::CAPFlags,
+    prctl::{SUID_DUMP_DISABLE, TASK_COMM_LEN},
+    resource::RLimitID,
+    ProcessControlBlock, ProcessManager,
+};
+
+/// core_pattern的最大长度（包括结尾的'\0'）
+pub const CORENAME_MAX_SIZE: usize = 128;
+
+lazy_static! {
+    /// core文件的路径模板，通过/proc/sys/kernel/core_pattern读写
+    static ref CORE_PATTERN: SpinLock<String> = SpinLock::new(String::from("core"));
+}
+
+const NT_PRSTATUS: u32 = 1;
+const NT_PRPSINFO: u32 = 3;
+const NT_AUXV: u32 = 6;
+
+/// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/include/uapi/linux/elf.h#222
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+struct Elf64Ehdr {
+    e_ident: [u8; 16],
+    e_type: u16,
+    e_machine: u16,
+    e_version: u32,
+    e_entry: u64,
+    e_phoff: u64,
+    e_shoff: u64,
+    e_flags: u32,
+    e_ehsize: u16,
+    e_phentsize: u16,
+    e_phnum: u16,
+    e_shentsize: u16,
+    e_shnum: u16,
+    e_shstrndx: u16,
+}
+
+/// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/include/uapi/linux/elf.h#260
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+struct Elf64Phdr {
+    p_type: u32,
+    p_flags: u32,
+    p_offset: u64,
+    p_vaddr: u64,
+    p_paddr: u64,
+    p_filesz: u64,
+    p_memsz: u64,
+    p_align: u64,
+}
+
+/// NT_PRSTATUS的内容，所有的填充字段都显式写出，以免把未初始化的字节写入文件
+///
+/// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/include/linux/elfcore.h#35
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct ElfPrstatus {
+    /// pr_info：si_signo、si_code、si_errno
+    si_signo: i32,
+    si_code: i32,
+    si_errno: i32,
+    pr_cursig: i16,
+    _pad0: u16,
+    pr_sigpend: u64,
+    pr_sighold: u64,
+    pr_pid: i32,
+    pr_ppid: i32,
+    pr_pgrp: i32,
+    pr_sid: i32,
+    /// pr_utime、pr_stime、pr_cutime、pr_cstime，每个都是timeval
+    pr_times: [i64; 8],
+    pr_reg: <CurrentElfArch as ElfArch>::Gregset,
+    pr_fpvalid: i32,
+    _pad1: i32,
+}
+
+/// NT_PRPSINFO的内容
+///
+/// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/include/linux/elfcore.h#73
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+struct ElfPrpsinfo {
+    pr_state: i8,
+    pr_sname: u8,
+    pr_zomb: i8,
+    pr_nice: i8,
+    _pad0: u32,
+    pr_flag: u64,
+    pr_uid: u32,
+    pr_gid: u32,
+    pr_pid: i32,
+    pr_ppid: i32,
+    pr_pgrp: i32,
+    pr_sid: i32,
+    pr_fname: [u8; TASK_COMM_LEN],
+    pr_psargs: [u8; 80],
+}
+
+/// 要写入core文件的一个VMA
+#[derive(Debug)]
+struct CoreSegment {
+    start: VirtAddr,
+    end: VirtAddr,
+    vm_flags: VmFlags,
+}
+
+impl CoreSegment {
+    /// 需要写入文件的字节数。不可访问、不允许dump的VMA以及IO映射只记录地址范围
+    fn dump_size(&self) -> usize {
+        let readable = self
+            .vm_flags
+            .intersects(VmFlags::VM_READ | VmFlags::VM_WRITE | VmFlags::VM_EXEC);
+        if !readable
+            || self
+                .vm_flags
+                .intersects(VmFlags::VM_DONTDUMP | VmFlags::VM_IO)
+        {
+            return 0;
+        }
+        return self.end - self.start;
+    }
+
+    fn elf_flags(&self) -> u32 {
+        let mut flags = 0;
+        if self.vm_flags.contains(VmFlags::VM_READ) {
+            flags |= elf::abi::PF_R;
+        }
+        if self.vm_flags.contains(VmFlags::VM_WRITE) {
+            flags |= elf::abi::PF_W;
+        }
+        if self.vm_flags.contains(VmFlags::VM_EXEC) {
+            flags |= elf::abi::PF_X;
+        }
+        return flags;
+    }
+}
+
+/// 向core文件顺序写入数据，总大小受RLIMIT_CORE限制
+struct CoreDumpWriter {
+    file: File,
+    written: usize,
+    limit: usize,
+}
+
+impl CoreDumpWriter {
+    /// 写入`buf`，超出限制的部分会被丢弃，此时返回EFBIG
+    fn emit(&mut self, buf: &[u8]) -> Result<(), SystemError> {
+        let len = buf.len().min(self.limit - self.written);
+        let mut done = 0;
+        while done < len {
+            let n = self.file.write(len - done, &buf[done..len])?;
+            if n == 0 {
+                return Err(SystemError::EIO);
+            }
+            done += n;
+        }
+        self.written += len;
+
+        if len < buf.len() {
+            return Err(SystemError::EFBIG);
+        }
+        return Ok(());
+    }
+
+    /// 用0填充到`offset`
+    fn pad_to(&mut self, offset: usize) -> Result<(), SystemError> {
+        let zeros = [0u8; 512];
+        while self.written < offset {
+            let len = (offset - self.written).min(zeros.len());
+            self.emit(&zeros[..len])?;
+        }
+        return Ok(());
+    }
+}
+
+/// 获取当前的core_pattern
+pub fn core_pattern() -> String {
+    CORE_PATTERN.lock().clone()
+}
+
+/// 设置core_pattern，`pattern`在第一个换行符处截断
+pub fn set_core_pattern(pattern: &[u8]) -> Result<(), SystemError> {
+    let len = pattern
+        .iter()
+        .position(|&c| c == b'\n' || c == 0)
+        .unwrap_or(pattern.len());
+    if len >= CORENAME_MAX_SIZE {
+        return Err(SystemError::EINVAL);
+    }
+    let pattern = core::str::from_utf8(&pattern[..len]).map_err(|_| SystemError::EINVAL)?;
+
+    *CORE_PATTERN.lock() = pattern.to_string();
+    return Ok(());
+}
+
+/// 进程名，与/proc/[pid]/comm一样最多保留15个字符
+fn task_comm(pcb: &Arc<ProcessControlBlock>) -> String {
+    let basic = pcb.basic();
+    let name = basic.name();
+    let name = name.rsplit('/').next().unwrap_or(name);
+    return name.chars().take(TASK_COMM_LEN - 1).collect();
+}
+
+/// 展开core_pattern中的格式说明符，得到core文件的路径
+///
+/// 支持的说明符：
+/// - `%%`: 字符'%'
+/// - `%p`、`%P`: 线程组id
+/// - `%i`、`%I`: 线程id
+/// - `%u`、`%g`: 进程的uid与gid
+/// - `%s`: 导致core dump的信号
+/// - `%t`: core dump的时间（自UNIX纪元起的秒数）
+/// - `%h`: 主机名
+/// - `%e`: 进程名，其中的'/'会被替换为'!'
+///
+/// 不支持的说明符会被忽略。core_pattern以'|'开头（交给用户程序处理）时返回None
+///
+/// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/fs/coredump.c#197
+fn format_corename(pattern: &str, pcb: &Arc<ProcessControlBlock>, sig: Signal) -> Option<String> {
+    if pattern.starts_with('|') {
+        return None;
+    }
+
+    let mut corename = String::new();
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            corename.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('%') => corename.push('%'),
+            Some('p') | Some('P') => corename.push_str(&pcb.tgid().data().to_string()),
+            Some('i') | Some('I') => corename.push_str(&pcb.pid().data().to_string()),
+            Some('u') => corename.push_str(&pcb.cred().uid.data().to_string()),
+            Some('g') => corename.push_str(&pcb.cred().gid.data().to_string()),
+            Some('s') => corename.push_str(&(sig as usize).to_string()),
+            Some('t') => corename.push_str(&PosixTimeSpec::now().tv_sec.to_string()),
+            Some('h') => {
+                let utsname = pcb.nsproxy().uts_ns.utsname();
+                let len = utsname
+                    .nodename
+                    .iter()
+                    .position(|&c| c == 0)
+                    .unwrap_or(utsname.nodename.len());
+                corename.push_str(&String::from_utf8_lossy(&utsname.nodename[..len]));
+            }
+            Some('e') => corename.push_str(&task_comm(pcb).replace('/', "!")),
+            _ => {}
+        }
+    }
+
+    if corename.is_empty() {
+        return None;
+    }
+    return Some(corename);
+}
+
+/// 删除已经存在的core文件
+///
+/// 需要对所在目录有写与搜索权限，并且遵守目录粘滞位的限制
+fn unlink_old_core(pcb: &Arc<ProcessControlBlock>, corename: &str) -> Result<(), SystemError> {
+    let cred = pcb.cred();
+    let (inode_begin, path) = user_path_at(pcb, AtFlags::AT_FDCWD.bits(), corename)?;
+    let (filename, parent_path) = rsplit_path(&path);
+    let parent = match parent_path {
+        Some(parent_path) => {
+            inode_begin.lookup_follow_symlink(parent_path, VFS_MAX_FOLLOW_SYMLINK_TIMES)?
+        }
+        None => inode_begin,
+    };
+    let inode = match parent.find(filename) {
+        Ok(inode) => inode,
+        Err(SystemError::ENOENT) => return Ok(()),
+        Err(e) => return Err(e),
+    };
+
+    let metadata = inode.metadata()?;
+    if metadata.file_type == FileType::Dir {
+        return Err(SystemError::EISDIR);
+    }
+    let parent_metadata = parent.metadata()?;
+    generic_permission(&parent_metadata, &cred, MAY_WRITE | MAY_EXEC)?;
+    if parent_metadata.mode.contains(ModeType::S_ISVTX)
+        && metadata.uid != cred.fsuid.data()
+        && parent_metadata.uid != cred.fsuid.data()
+        && !cred.has_capability(CAPFlags::CAP_FOWNER)
+    {
+        return Err(SystemError::EPERM);
+    }
+
+    parent.unlink(filename)?;
+    fsnotify_delete(&parent, filename, &inode);
+    return Ok(());
+}
+
+/// 创建core文件
+///
+/// 与linux一致，先删除已经存在的同名文件，再以`O_CREAT | O_EXCL | O_NOFOLLOW`创建新文件，
+/// 避免通过预先放置的符号链接或硬链接写入其他文件。删除与创建都以进程的fsuid、fsgid检查目录的权限
+///
+/// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/fs/coredump.c#646
+fn open_core_file(pcb: &Arc<ProcessControlBlock>, corename: &str) -> Result<File, SystemError> {
+    unlink_old_core(pcb, corename)?;
+
+    let how = OpenHow::new(
+        FileMode::O_WRONLY | FileMode::O_CREAT | FileMode::O_EXCL | FileMode::O_NOFOLLOW,
+        ModeType::from_bits_truncate(0o600),
+        OpenHowResolve::empty(),
+    );
+    let file = do_filp_open(pcb, AtFlags::AT_FDCWD.bits(), corename, &how, false)?;
+
+    // 文件系统不一定支持设置属主，此时不能把core写进属于其他用户的文件
+    let metadata = file.inode().metadata()?;
+    if metadata.file_type != FileType::File
+        || metadata.nlinks > 1
+        || metadata.uid != pcb.cred().fsuid.data()
+    {
+        return Err(SystemError::EPERM);
+    }
+    return Ok(file);
+}
+
+fn as_bytes<T: Copy>(value: &T) -> &[u8] {
+    unsafe { core::slice::from_raw_parts(value as *const T as *const u8, size_of::<T>()) }
+}
+
+/// 向note段追加一个名字为"CORE"的note，名字与内容都按4字节对齐
+///
+/// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/fs/binfmt_elf.c#1434
+fn push_note(notes: &mut Vec<u8>, ntype: u32, desc: &[u8]) {
+    const NAME: &[u8] = b"CORE\0";
+    let pad = |notes: &mut Vec<u8>| notes.resize(notes.len().next_multiple_of(4), 0);
+
+    notes.extend_from_slice(&(NAME.len() as u32).to_ne_bytes());
+    notes.extend_from_slice(&(desc.len() as u32).to_ne_bytes());
+    notes.extend_from_slice(&ntype.to_ne_bytes());
+    notes.extend_from_slice(NAME);
+    pad(notes);
+    notes.extend_from_slice(desc);
+    pad(notes);
+}
+
+/// 生成PT_NOTE段的内容
+fn fill_notes(pcb: &Arc<ProcessControlBlock>, sig: Signal, frame: &TrapFrame) -> Vec<u8> {
+    let basic = pcb.basic();
+    let ppid = basic.ppid().data() as i32;
+    let pgrp = basic.pgid().data() as i32;
+    let saved_auxv = basic
+        .user_vm()
+        .map(|vm| vm.read().saved_auxv.clone())
+        .unwrap_or_default();
+    drop(basic);
+
+    let sig_info = pcb.sig_info_irqsave();
+    let sigpend = sig_info.sig_pending().signal().bits();
+    let sighold = sig_info.sig_block().bits();
+    drop(sig_info);
+
+    let mut notes = Vec::new();
+
+    let prstatus = ElfPrstatus {
+        si_signo: sig as i32,
+        si_code: 0,
+        si_errno: 0,
+        pr_cursig: sig as i16,
+        _pad0: 0,
+        pr_sigpend: sigpend,
+        pr_sighold: sighold,
+        pr_pid: pcb.pid().data() as i32,
+        pr_ppid: ppid,
+        pr_pgrp: pgrp,
+        pr_sid: 0,
+        pr_times: [0; 8],
+        pr_reg: CurrentElfArch::core_regs(frame),
+        pr_fpvalid: 0,
+        _pad1: 0,
+    };
+    push_note(&mut notes, NT_PRSTATUS, as_bytes(&prstatus));
+
+    let cred = pcb.cred();
+    let comm = task_comm(pcb);
+    let mut prpsinfo = ElfPrpsinfo {
+        pr_state: 0,
+        pr_sname: b'R',
+        pr_zomb: 0,
+        pr_nice: 0,
+        _pad0: 0,
+        pr_flag: pcb.flags().bits() as u64,
+        pr_uid: cred.uid.data() as u32,
+        pr_gid: cred.gid.data() as u32,
+        pr_pid: pcb.tgid().data() as i32,
+        pr_ppid: ppid,
+        pr_pgrp: pgrp,
+        pr_sid: 0,
+        pr_fname: [0; TASK_COMM_LEN],
+        pr_psargs: [0; 80],
+    };
+    let len = comm.len().min(TASK_COMM_LEN - 1);
+    prpsinfo.pr_fname[..len].copy_from_slice(&comm.as_bytes()[..len]);
+    prpsinfo.pr_psargs[..len].copy_from_slice(&comm.as_bytes()[..len]);
+    push_note(&mut notes, NT_PRPSINFO, as_bytes(&prpsinfo));
+
+    let mut auxv: Vec<u8> = Vec::new();
+    for (key, value) in saved_auxv.iter().chain(core::iter::once(&(0, 0))) {
+        auxv.extend_from_slice(&key.to_ne_bytes());
+        auxv.extend_from_slice(&value.to_ne_bytes());
+    }
+    push_note(&mut notes, NT_AUXV, &auxv);
+
+    return notes;
+}
+
+/// 收集地址空间中的VMA，按地址排序
+fn collect_segments(vm: &Arc<AddressSpace>) -> Vec<CoreSegment> {
+    let guard = vm.read();
+    let mut segments: Vec<CoreSegment> = guard
+        .mappings
+        .iter_vmas()
+        .map(|vma| {
+            let vma_guard = vma.lock_irqsave();
+            CoreSegment {
+                start: vma_guard.region().start(),
+                end: vma_guard.region().end(),
+                vm_flags: *vma_guard.vm_flags(),
+            }
+        })
+        .collect();
+    drop(guard);
+
+    segments.sort_by_key(|seg| seg.start);
+    // 程序头的数量不能超过e_phnum能表示的范围（还需要留出一个PT_NOTE段）
+    segments.truncate(elf::abi::PN_XNUM as usize - 2);
+    return segments;
+}
+
+/// 把一个VMA的内容写入core文件。尚未分配物理页的部分写入0
+fn dump_segment(
+    writer: &mut CoreDumpWriter,
+    vm: &Arc<AddressSpace>,
+    seg: &CoreSegment,
+) -> Result<(), SystemError> {
+    let mut page_buf = vec![0u8; MMArch::PAGE_SIZE];
+    let mut vaddr = seg.start;
+    while vaddr < seg.start + seg.dump_size() {
+        let guard = vm.read();
+        match guard.user_mapper.utable.translate(vaddr) {
+            Some((paddr, _)) => {
+                let kaddr = unsafe { MMArch::phys_2_virt(paddr) }.ok_or(SystemError::EFAULT)?;
+                let page = unsafe {
+                    core::slice::from_raw_parts(kaddr.data() as *const u8, MMArch::PAGE_SIZE)
+                };
+                page_buf.copy_from_slice(page);
+            }
+            None => page_buf.fill(0),
+        }
+        drop(guard);
+
+        writer.emit(&page_buf)?;
+        vaddr += MMArch::PAGE_SIZE;
+    }
+    return Ok(());
+}
+
+/// 写入完整的core文件
+///
+/// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/fs/binfmt_elf.c#2033
+fn elf_core_dump(
+    writer: &mut CoreDumpWriter,
+    pcb: &Arc<ProcessControlBlock>,
+    vm: &Arc<AddressSpace>,
+    sig: Signal,
+    frame: &TrapFrame,
+) -> Result<(), SystemError> {
+    let notes = fill_notes(pcb, sig, frame);
+    let segments = collect_segments(vm);
+
+    let phnum = segments.len() + 1;
+    let notes_offset = size_of::<Elf64Ehdr>() + phnum * size_of::<Elf64Phdr>();
+    let data_offset = (notes_offset + notes.len()).next_multiple_of(MMArch::PAGE_SIZE);
+
+    let mut e_ident = [0u8; 16];
+    e_ident[..4].copy_from_slice(&elf::abi::ELFMAGIC);
+    e_ident[elf::abi::EI_CLASS] = elf::abi::ELFCLASS64;
+    e_ident[elf::abi::EI_DATA] = elf::abi::ELFDATA2LSB;
+    e_ident[elf::abi::EI_VERSION] = elf::abi::EV_CURRENT;
+    let ehdr = Elf64Ehdr {
+        e_ident,
+        e_type: elf::abi::ET_CORE,
+        e_machine: CurrentElfArch::ELF_MACHINE,
+        e_version: elf::abi::EV_CURRENT as u32,
+        e_entry: 0,
+        e_phoff: size_of::<Elf64Ehdr>() as u64,
+        e_shoff: 0,
+        e_flags: 0,
+        e_ehsize: size_of::<Elf64Ehdr>() as u16,
+        e_phentsize: size_of::<Elf64Phdr>() as u16,
+        e_phnum: phnum as u16,
+        e_shentsize: 0,
+        e_shnum: 0,
+        e_shstrndx: 0,
+    };
+    writer.emit(as_bytes(&ehdr))?;
+
+    let note_phdr = Elf64Phdr {
+        p_type: elf::abi::PT_NOTE,
+        p_flags: 0,
+        p_offset: notes_offset as u64,
+        p_vaddr: 0,
+        p_paddr: 0,
+        p_filesz: notes.len() as u64,
+        p_memsz: 0,
+        p_align: 4,
+    };
+    writer.emit(as_bytes(&note_phdr))?;
+
+    let mut offset = data_offset;
+    for seg in segments.iter() {
+        let phdr = Elf64Phdr {
+            p_type: elf::abi::PT_LOAD,
+            p_flags: seg.elf_flags(),
+            p_offset: offset as u64,
+            p_vaddr: seg.start.data() as u64,
+            p_paddr: 0,
+            p_filesz: seg.dump_size() as u64,
+            p_memsz: (seg.end - seg.start) as u64,
+            p_align: MMArch::PAGE_SIZE as u64,
+        };
+        writer.emit(as_bytes(&phdr))?;
+        offset += seg.dump_size();
+    }
+
+    writer.emit(&notes)?;
+    writer.pad_to(data_offset)?;
+
+    for seg in segments.iter() {
+        dump_segment(writer, vm, seg)?;
+    }
+    return Ok(());
+}
+
+/// 为当前进程生成core dump
+///
+/// 进程不可dump（例如执行了set-user-ID程序），或者RLIMIT_CORE小于一页时不生成core文件。
+/// 文件的大小不会超过RLIMIT_CORE，超出的部分会被截断
+///
+/// ## 参数
+///
+/// - `sig`: 导致进程终止的信号
+/// - `frame`: 进程陷入内核时保存的栈帧
+///
+/// ## 返回值
+///
+/// 是否生成了core文件
+///
+/// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/fs/coredump.c#522
+pub fn do_coredump(sig: Signal, frame: &TrapFrame) -> bool {
+    let pcb = ProcessManager::current_pcb();
+    if pcb.dumpable() == SUID_DUMP_DISABLE {
+        return false;
+    }
+    let vm = match pcb.basic().user_vm() {
+        Some(vm) => vm,
+        None => return false,
+    };
+
+    let limit = pcb.rlimit_cur(RLimitID::Core);
+    if limit < MMArch::PAGE_SIZE as u64 {
+        return false;
+    }
+    // RLIM_INFINITY也会被转换为usize::MAX
+    let limit = limit.min(usize::MAX as u64) as usize;
+
+    let pattern = core_pattern();
+    let corename = match format_corename(&pattern, &pcb, sig) {
+        Some(corename) => corename,
+        None => {
+            warn!(
+                "pid {:?} ({}): core_pattern '{}' is not supported, core dump skipped",
+                pcb.pid(),
+                task_comm(&pcb),
+                pattern
+            );
+            return false;
+        }
+    };
+
+    let file = match open_core_file(&pcb, &corename) {
+        Ok(file) => file,
+        Err(e) => {
+            warn!(
+                "pid {:?} ({}): failed to create core file '{}': {:?}",
+                pcb.pid(),
+                task_comm(&pcb),
+                corename,
+                e
+            );
+            return false;
+        }
+    };
+
+    let mut writer = CoreDumpWriter {
+        file,
+        written: 0,
+        limit,
+    };
+    let result = elf_core_dump(&mut writer, &pcb, &vm, sig, frame);
+    let msg = match result {
+        Ok(()) => String::new(),
+        Err(SystemError::EFBIG) => String::from(" (truncated by RLIMIT_CORE)"),
+        Err(e) => {
+            warn!(
+                "pid {:?} ({}): failed to write core file '{}': {:?}",
+                pcb.pid(),
+                task_comm(&pcb),
+                corename,
+                e
+            );
+            return false;
+        }
+    };
+    info!(
+        "pid {:?} ({}) killed by signal {}, core dumped to '{}'{}",
+        pcb.pid(),
+        task_comm(&pcb),
+        sig as usize,
+        corename,
+        msg
+    );
+    return true;
+}
@@ -9,10 +9,92 @@ pub static INIT_CRED: Cred = Cred::init();
 int_like!(Kuid, AtomicKuid, usize, AtomicUsize);
 int_like!(Kgid, AtomicKgid, usize, AtomicUsize);
 
+/// 编号最大的capability
+pub const CAP_LAST_CAP: usize = 40;
+
 bitflags! {
+    /// capability集合，每一位对应一种特权
+    ///
+    /// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/include/uapi/linux/capability.h
     pub struct CAPFlags:u64{
         const CAP_EMPTY_SET = 0;
-        const CAP_FULL_SET = (1 << 41) - 1;
+        const CAP_FULL_SET = (1 << (CAP_LAST_CAP + 1)) - 1;
+
+        /// 修改文件的属主与属组
+        const CAP_CHOWN = 1 << 0;
+        /// 绕过文件的读、写、执行权限检查
+        const CAP_DAC_OVERRIDE = 1 << 1;
+        /// 绕过文件的读权限检查与目录的读、搜索权限检查
+        const CAP_DAC_READ_SEARCH = 1 << 2;
+        /// 绕过要求进程fsuid与文件属主相同的检查
+        const CAP_FOWNER = 1 << 3;
+        /// 修改文件时不清除set-user-ID与set-group-ID位
+        const CAP_FSETID = 1 << 4;
+        /// 向其它用户的进程发送信号
+        const CAP_KILL = 1 << 5;
+        /// 任意修改进程的gid与附属组
+        const CAP_SETGID = 1 << 6;
+        /// 任意修改进程的uid
+        const CAP_SETUID = 1 << 7;
+        /// 修改capability集合（例如从bounding set中移除capability）
+        const CAP_SETPCAP = 1 << 8;
+        const CAP_LINUX_IMMUTABLE = 1 << 9;
+        /// 绑定1024以下的端口
+        const CAP_NET_BIND_SERVICE = 1 << 10;
+        const CAP_NET_BROADCAST = 1 << 11;
+        /// 网络相关的管理操作
+        const CAP_NET_ADMIN = 1 << 12;
+        /// 使用RAW与PACKET套接字
+        const CAP_NET_RAW = 1 << 13;
+        const CAP_IPC_LOCK = 1 << 14;
+        const CAP_IPC_OWNER = 1 << 15;
+        const CAP_SYS_MODULE = 1 << 16;
+        const CAP_SYS_RAWIO = 1 << 17;
+        const CAP_SYS_CHROOT = 1 << 18;
+        const CAP_SYS_PTRACE = 1 << 19;
+        const CAP_SYS_PACCT = 1 << 20;
+        /// 系统管理操作，例如mount、sethostname、创建命名空间等
+        const CAP_SYS_ADMIN = 1 << 21;
+        /// 使用reboot
+        const CAP_SYS_BOOT = 1 << 22;
+        const CAP_SYS_NICE = 1 << 23;
+        /// 突破资源限制
+        const CAP_SYS_RESOURCE = 1 << 24;
+        const CAP_SYS_TIME = 1 << 25;
+        const CAP_SYS_TTY_CONFIG = 1 << 26;
+        const CAP_MKNOD = 1 << 27;
+        const CAP_LEASE = 1 << 28;
+        const CAP_AUDIT_WRITE = 1 << 29;
+        const CAP_AUDIT_CONTROL = 1 << 30;
+        const CAP_SETFCAP = 1 << 31;
+        const CAP_MAC_OVERRIDE = 1 << 32;
+        const CAP_MAC_ADMIN = 1 << 33;
+        const CAP_SYSLOG = 1 << 34;
+        const CAP_WAKE_ALARM = 1 << 35;
+        const CAP_BLOCK_SUSPEND = 1 << 36;
+        const CAP_AUDIT_READ = 1 << 37;
+        const CAP_PERFMON = 1 << 38;
+        const CAP_BPF = 1 << 39;
+        const CAP_CHECKPOINT_RESTORE = 1 << 40;
+
+        /// fsuid在0与非0之间切换时，随之获得或失去的capability
+        const CAP_FS_SET = Self::CAP_CHOWN.bits
+            | Self::CAP_DAC_OVERRIDE.bits
+            | Self::CAP_DAC_READ_SEARCH.bits
+            | Self::CAP_FOWNER.bits
+            | Self::CAP_FSETID.bits
+            | Self::CAP_LINUX_IMMUTABLE.bits
+            | Self::CAP_MAC_OVERRIDE.bits;
+    }
+}
+
+impl CAPFlags {
+    /// 将capability编号转换为对应的集合，编号不合法时返回None
+    pub fn from_cap(cap: usize) -> Option<Self> {
+        if cap > CAP_LAST_CAP {
+            return None;
+        }
+        Self::from_bits(1 << cap)
     }
 }
 
@@ -51,6 +133,8 @@ pub struct Cred {
     pub cap_bset: CAPFlags,
     /// Ambient capability set
     pub cap_ambient: CAPFlags,
+    /// 所有uid都从0变为非0时，是否保留permitted集合（PR_SET_KEEPCAPS）
+    pub keep_caps: bool,
     /// supplementary groups for euid/fsgid
     pub group_info: Option<GroupInfo>,
 }
@@ -70,7 +154,8 @@ impl Cred {
             cap_permitted: CAPFlags::CAP_FULL_SET,
             cap_effective: CAPFlags::CAP_FULL_SET,
             cap_bset: CAPFlags::CAP_FULL_SET,
-            cap_ambient: CAPFlags::CAP_FULL_SET,
+            cap_ambient: CAPFlags::CAP_EMPTY_SET,
+            keep_caps: false,
             group_info: None,
         }
     }
@@ -16,7 +16,10 @@ use crate::{
     },
 };
 
-use super::ProcessManager;
+use super::{
+    prctl::{SUID_DUMP_DISABLE, SUID_DUMP_USER},
+    ProcessManager,
+};
 
 /// 系统支持的所有二进制文件加载器的列表
 const BINARY_LOADERS: [&'static dyn BinaryLoader; 1] = [&ELF_LOADER];
@@ -161,6 +164,25 @@ impl ExecParam {
     }
 }
 
+/// ## 根据被执行的文件计算当前进程的新凭证
+///
+/// 使用加载器实际打开的文件，而不是按路径重新查找，以免文件在两次查找之间被替换。
+/// 获得了新特权的进程不允许产生core dump
+///
+/// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/fs/exec.c#begin_new_exec
+pub fn exec_transform_cred(param: &ExecParam) -> Result<(), SystemError> {
+    let metadata = param.file.metadata()?;
+    let pcb = ProcessManager::current_pcb();
+    let no_new_privs = pcb.no_new_privs();
+    let privileged = pcb.cred.lock().transform_for_exec(&metadata, no_new_privs);
+    pcb.set_dumpable(if privileged {
+        SUID_DUMP_DISABLE
+    } else {
+        SUID_DUMP_USER
+    });
+    Ok(())
+}
+
 /// ## 加载二进制文件
 pub fn load_binary_file(param: &mut ExecParam) -> Result<BinaryLoaderResult, SystemError> {
     // 读取文件头部，用于判断文件类型
@@ -1,17 +1,14 @@
-use core::intrinsics::likely;
-
-use alloc::sync::Arc;
-use log::warn;
+use alloc::{sync::Arc, vec::Vec};
 use system_error::SystemError;
 
 use crate::{
     arch::{
-        ipc::signal::{SigChildCode, Signal},
+        ipc::signal::{SigChildCode, SigFlags, Signal},
         CurrentIrqArch,
     },
     exception::InterruptArch,
     sched::{schedule, SchedMode},
-    syscall::user_access::UserBufferWriter,
+    syscall::{user_access::UserBufferWriter, Syscall},
 };
 
 use super::{
@@ -31,10 +28,11 @@ pub struct KernelWaitOption<'a> {
     pub no_task_error: Option<SystemError>,
 }
 
+/// waitid返回给用户的子进程状态
 #[derive(Debug, Clone)]
-#[allow(dead_code)]
 pub struct WaitIdInfo {
     pub pid: Pid,
+    pub uid: usize,
     pub status: i32,
     pub cause: i32,
 }
@@ -53,6 +51,46 @@ impl<'a> KernelWaitOption<'a> {
     }
 }
 
+/// 进程尚未被父进程通过wait系列系统调用获取的停止/继续事件
+///
+/// 参考 Linux 中 signal_struct 的 group_exit_code 与 SIGNAL_STOP_CONTINUED 标志
+#[derive(Debug, Default, Clone, Copy)]
+pub enum StopEvent {
+    #[default]
+    None,
+    /// 进程因为该信号而停止
+    Stopped(Signal),
+    /// 已停止的进程被SIGCONT继续运行
+    Continued,
+}
+
+impl ProcessControlBlock {
+    /// 进程停止或继续运行时，记录该事件并通知父进程
+    ///
+    /// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/kernel/signal.c#2141
+    pub fn notify_parent_cldstop(self: &Arc<Self>, event: StopEvent) {
+        // 目前只有线程组leader的停止/继续会被报告给父进程
+        if self.tgid() != self.pid() {
+            return;
+        }
+        *self.stop_event.lock_irqsave() = event;
+
+        let parent = match self.parent_pcb.read_irqsave().upgrade() {
+            Some(parent) => parent,
+            None => return,
+        };
+        parent.wait_chldexit.wakeup_all(None);
+
+        // 父进程设置了SA_NOCLDSTOP时，子进程停止或继续运行不会产生SIGCHLD
+        let nocldstop = parent.sig_struct_irqsave().handlers[Signal::SIGCHLD as usize - 1]
+            .flags()
+            .contains(SigFlags::SA_NOCLDSTOP);
+        if !nocldstop {
+            Syscall::kill(parent.pid(), Signal::SIGCHLD as i32).ok();
+        }
+    }
+}
+
 pub fn kernel_wait4(
     mut pid: i64,
     wstatus_buf: Option<UserBufferWriter<'_>>,
@@ -71,14 +109,17 @@ pub fn kernel_wait4(
         pidtype = PidType::MAX;
     } else if pid < 0 {
         pidtype = PidType::PGID;
-        warn!("kernel_wait4: currently not support pgid, default to wait for pid\n");
         pid = -pid;
     } else if pid == 0 {
         pidtype = PidType::PGID;
-        warn!("kernel_wait4: currently not support pgid, default to wait for pid\n");
-        pid = ProcessManager::current_pcb().pid().data() as i64;
+        pid = ProcessManager::current_pcb().basic().pgid().data() as i64;
     } else {
         pidtype = PidType::PID;
+        // 将当前pid namespace中的pid转换为全局pid
+        pid = ProcessManager::find_vpid(Pid(pid as usize))
+            .ok_or(SystemError::ECHILD)?
+            .pid()
+            .data() as i64;
     }
 
     let pid = Pid(pid as usize);
@@ -94,163 +135,196 @@ pub fn kernel_wait4(
 
     // 如果有wstatus_buf，则将wstatus写入用户空间
     if let Some(mut wstatus_buf) = wstatus_buf {
-        let wstatus = if let Some(ret_info) = &kwo.ret_info {
-            ret_info.status
-        } else {
-            kwo.ret_status
-        };
-        wstatus_buf.copy_one_to_user(&wstatus, 0)?;
+        wstatus_buf.copy_one_to_user(&kwo.ret_status, 0)?;
     }
 
     return Ok(r);
 }
 
-/// 参考 https://code.dragonos.org.cn/xref/linux-6.1.9/kernel/exit.c#1573
-fn do_wait(kwo: &mut KernelWaitOption) -> Result<usize, SystemError> {
-    let mut retval: Result<usize, SystemError>;
-    // todo: 在signal struct里面增加等待队列，并在这里初始化子进程退出的回调，使得子进程退出时，能唤醒当前进程。
+/// 等待子进程的状态发生变化
+///
+/// 当前进程会在自身的`wait_chldexit`等待队列上睡眠，子进程退出、停止或继续运行时会唤醒该队列。
+///
+/// ## 返回值
+///
+/// - `Ok(pid)`: 状态发生变化的子进程在当前pid namespace中的pid
+/// - `Ok(0)`: 指定了WNOHANG，且没有子进程的状态可以报告
+///
+/// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/kernel/exit.c#1633
+pub fn do_wait(kwo: &mut KernelWaitOption) -> Result<usize, SystemError> {
+    let current = ProcessManager::current_pcb();
+    let nohang = kwo.options.contains(WaitOption::WNOHANG);
 
     loop {
-        kwo.no_task_error = Some(SystemError::ECHILD);
-        let child_pcb = ProcessManager::find(kwo.pid).ok_or(SystemError::ECHILD);
-        if kwo.pid_type != PidType::MAX && child_pcb.is_err() {
-            if let Some(err) = &kwo.no_task_error {
-                retval = Err(err.clone());
-            } else {
-                retval = Ok(0);
-            }
+        // 先加入等待队列再检查子进程，避免错过检查过程中子进程发出的唤醒
+        let sleep_ret = if nohang {
+            Ok(())
+        } else {
+            current.wait_chldexit.prepare_to_wait_event(true)
+        };
 
-            if !kwo.options.contains(WaitOption::WNOHANG) {
-                retval = Err(SystemError::ERESTARTSYS);
-                if !ProcessManager::current_pcb()
-                    .sig_info_irqsave()
-                    .sig_pending()
-                    .has_pending()
-                {
-                    // todo: 增加子进程退出的回调后，这里可以直接等待在自身的child_wait等待队列上。
-                    continue;
-                } else {
-                    break;
-                }
-            } else {
-                break;
-            }
-        }
+        let irq_guard = unsafe { CurrentIrqArch::save_and_disable_irq() };
+        let r = do_wait_children(&current, kwo);
+        drop(irq_guard);
 
-        if kwo.pid_type == PidType::PID {
-            let child_pcb = child_pcb.unwrap();
-            // 获取weak引用，以便于在do_waitpid中能正常drop pcb
-            let child_weak = Arc::downgrade(&child_pcb);
-            let r = do_waitpid(child_pcb, kwo);
-            if let Some(r) = r {
-                return r;
-            } else {
-                child_weak.upgrade().unwrap().wait_queue.sleep();
-            }
-        } else if kwo.pid_type == PidType::MAX {
-            // 等待任意子进程
-            // todo: 这里有问题！如果正在for循环的过程中，子进程退出了，可能会导致父进程永远等待。
-            let current_pcb = ProcessManager::current_pcb();
-            let rd_childen = current_pcb.children.read();
-            let irq_guard = unsafe { CurrentIrqArch::save_and_disable_irq() };
-            for pid in rd_childen.iter() {
-                let pcb = ProcessManager::find(*pid).ok_or(SystemError::ECHILD)?;
-                let state = pcb.sched_info().inner_lock_read_irqsave().state();
-                if state.is_exited() {
-                    kwo.ret_status = state.exit_code().unwrap() as i32;
-                    drop(pcb);
-                    unsafe { ProcessManager::release(*pid) };
-                    return Ok((*pid).into());
-                } else {
-                    unsafe { pcb.wait_queue.sleep_without_schedule() };
-                }
-            }
-            drop(irq_guard);
-            schedule(SchedMode::SM_NONE);
-        } else {
-            // todo: 对于pgid的处理
-            warn!("kernel_wait4: currently not support {:?}", kwo.pid_type);
-            return Err(SystemError::EINVAL);
+        if r.is_some() && !nohang && sleep_ret.is_ok() {
+            current.wait_chldexit.finish_wait();
+        }
+        match r {
+            Some(r) => return r,
+            None if nohang => return Ok(0),
+            None => {}
         }
-    }
 
-    return retval;
+        // 有信号等待处理
+        sleep_ret?;
+        schedule(SchedMode::SM_NONE);
+        current.wait_chldexit.finish_wait();
+    }
 }
 
-fn do_waitpid(
-    child_pcb: Arc<ProcessControlBlock>,
+/// 遍历当前进程的子进程，寻找满足等待条件的子进程
+///
+/// ## 返回值
+///
+/// - `Some(..)`: 等待已经结束（找到了子进程，或者出错）
+/// - `None`: 存在符合条件的子进程，但是它们的状态都不需要报告
+fn do_wait_children(
+    current: &Arc<ProcessControlBlock>,
     kwo: &mut KernelWaitOption,
 ) -> Option<Result<usize, SystemError>> {
-    let state = child_pcb.sched_info().inner_lock_read_irqsave().state();
-    // 获取退出码
-    match state {
-        ProcessState::Runnable => {
-            if kwo.options.contains(WaitOption::WNOHANG)
-                || kwo.options.contains(WaitOption::WNOWAIT)
+    let children: Vec<Pid> = current.children.read_irqsave().clone();
+    let mut found = false;
+
+    for pid in children {
+        let child = match ProcessManager::find(pid) {
+            Some(child) => child,
+            None => {
+                // 已经被回收的子进程
+                current.children.write_irqsave().retain(|p| *p != pid);
+                continue;
+            }
+        };
+
+        // 线程不会通知父进程，它退出后直接被回收
+        if child.tgid() != child.pid() {
+            if child
+                .sched_info()
+                .inner_lock_read_irqsave()
+                .state()
+                .is_exited()
             {
-                if let Some(info) = &mut kwo.ret_info {
-                    *info = WaitIdInfo {
-                        pid: child_pcb.pid(),
-                        status: Signal::SIGCONT as i32,
-                        cause: SigChildCode::Continued.into(),
-                    };
-                } else {
-                    kwo.ret_status = 0xffff;
-                }
-
-                return Some(Ok(0));
+                release_child(current, child);
             }
+            continue;
         }
-        ProcessState::Blocked(_) | ProcessState::Stopped => {
-            // todo: 在stopped里面，添加code字段，表示停止的原因
-            let exitcode = 0;
-            // 由于目前不支持ptrace，因此这个值为false
-            let ptrace = false;
-
-            if (!ptrace) && (!kwo.options.contains(WaitOption::WUNTRACED)) {
-                kwo.ret_status = 0;
-                return Some(Ok(0));
-            }
 
-            if likely(!(kwo.options.contains(WaitOption::WNOWAIT))) {
-                kwo.ret_status = (exitcode << 8) | 0x7f;
-            }
-            if let Some(infop) = &mut kwo.ret_info {
-                *infop = WaitIdInfo {
-                    pid: child_pcb.pid(),
-                    status: exitcode,
-                    cause: SigChildCode::Stopped.into(),
-                };
-            }
+        let matched = match kwo.pid_type {
+            PidType::PID => child.pid() == kwo.pid,
+            PidType::PGID => child.basic().pgid() == kwo.pid,
+            _ => true,
+        };
+        if !matched {
+            continue;
+        }
+        found = true;
 
-            return Some(Ok(child_pcb.pid().data()));
+        if let Some(r) = wait_consider_task(current, child, kwo) {
+            return Some(r);
         }
-        ProcessState::Exited(status) => {
-            let pid = child_pcb.pid();
-            // debug!("wait4: child exited, pid: {:?}, status: {status}\n", pid);
+    }
 
-            if likely(!kwo.options.contains(WaitOption::WEXITED)) {
-                return None;
-            }
+    if found {
+        None
+    } else {
+        Some(Err(kwo
+            .no_task_error
+            .clone()
+            .unwrap_or(SystemError::ECHILD)))
+    }
+}
 
-            // todo: 增加对线程组的group leader的处理
+/// 检查一个子进程是否有需要报告的状态
+///
+/// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/kernel/exit.c#1371
+fn wait_consider_task(
+    current: &Arc<ProcessControlBlock>,
+    child: Arc<ProcessControlBlock>,
+    kwo: &mut KernelWaitOption,
+) -> Option<Result<usize, SystemError>> {
+    let state = child.sched_info().inner_lock_read_irqsave().state();
+    if let ProcessState::Exited(status) = state {
+        if !kwo.options.contains(WaitOption::WEXITED) {
+            return None;
+        }
+        return Some(wait_task_zombie(current, child, status, kwo));
+    }
 
-            if let Some(infop) = &mut kwo.ret_info {
-                *infop = WaitIdInfo {
-                    pid,
-                    status: status as i32,
-                    cause: SigChildCode::Exited.into(),
-                };
-            }
+    let mut event = child.stop_event.lock_irqsave();
+    let (wstatus, info_status, cause) = match *event {
+        StopEvent::Stopped(sig) if kwo.options.contains(WaitOption::WSTOPPED) => {
+            (((sig as i32) << 8) | 0x7f, sig as i32, SigChildCode::Stopped)
+        }
+        StopEvent::Continued if kwo.options.contains(WaitOption::WCONTINUED) => {
+            (0xffff, Signal::SIGCONT as i32, SigChildCode::Continued)
+        }
+        _ => return None,
+    };
+    if !kwo.options.contains(WaitOption::WNOWAIT) {
+        *event = StopEvent::None;
+    }
+    drop(event);
 
-            kwo.ret_status = status as i32;
+    let vnr = child.pid_vnr();
+    kwo.ret_status = wstatus;
+    kwo.ret_info = Some(WaitIdInfo {
+        pid: vnr,
+        uid: child.cred().uid.data(),
+        status: info_status,
+        cause: cause.into(),
+    });
+    return Some(Ok(vnr.data()));
+}
 
-            drop(child_pcb);
-            // debug!("wait4: to release {pid:?}");
-            unsafe { ProcessManager::release(pid) };
-            return Some(Ok(pid.into()));
-        }
+/// 报告一个已经退出的子进程的状态，如果没有指定WNOWAIT，则回收该子进程
+///
+/// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/kernel/exit.c#1097
+fn wait_task_zombie(
+    current: &Arc<ProcessControlBlock>,
+    child: Arc<ProcessControlBlock>,
+    status: usize,
+    kwo: &mut KernelWaitOption,
+) -> Result<usize, SystemError> {
+    let vnr = child.pid_vnr();
+    let status = status as i32;
+
+    // 退出码的低7位为导致进程终止的信号，第7位表示是否产生了core dump
+    let (info_status, cause) = if status & 0x7f == 0 {
+        ((status >> 8) & 0xff, SigChildCode::Exited)
+    } else if status & 0x80 != 0 {
+        (status & 0x7f, SigChildCode::Dumped)
+    } else {
+        (status & 0x7f, SigChildCode::Killed)
     };
 
-    return None;
+    kwo.ret_status = status;
+    kwo.ret_info = Some(WaitIdInfo {
+        pid: vnr,
+        uid: child.cred().uid.data(),
+        status: info_status,
+        cause: cause.into(),
+    });
+
+    if !kwo.options.contains(WaitOption::WNOWAIT) {
+        release_child(current, child);
+    }
+    return Ok(vnr.data());
+}
+
+/// 回收已经退出的子进程
+fn release_child(current: &Arc<ProcessControlBlock>, child: Arc<ProcessControlBlock>) {
+    let pid = child.pid();
+    drop(child);
+    current.children.write_irqsave().retain(|p| *p != pid);
+    unsafe { ProcessManager::release(pid) };
 }
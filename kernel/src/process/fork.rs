@@ -1,23 +1,31 @@
 use core::{intrinsics::unlikely, sync::atomic::Ordering};
 
-use alloc::{string::ToString, sync::Arc};
+use alloc::{string::ToString, sync::Arc, vec::Vec};
 use log::error;
 use system_error::SystemError;
 
 use crate::{
-    arch::{interrupt::TrapFrame, ipc::signal::Signal},
+    arch::{
+        interrupt::TrapFrame,
+        ipc::signal::{Signal, MAX_SIG_NUM},
+    },
+    cgroup::cgroupfs::cgroup_from_fd,
     filesystem::procfs::procfs_register_pid,
     ipc::signal::flush_signal_handlers,
     libs::rwlock::RwLock,
-    mm::VirtAddr,
+    mm::{verify_area, VirtAddr},
     process::ProcessFlags,
     sched::{sched_cgroup_fork, sched_fork},
     smp::core::smp_get_processor_id,
-    syscall::user_access::UserBufferWriter,
+    syscall::user_access::{UserBufferReader, UserBufferWriter},
 };
 
 use super::{
+    cred::CAPFlags,
     kthread::{KernelThreadPcbPrivate, WorkerPrivate},
+    pid::MAX_PID_NS_LEVEL,
+    pidfd::{pidfd_create, PidFdFlags},
+    resource::RLimitID,
     KernelStack, Pid, ProcessControlBlock, ProcessManager,
 };
 
@@ -76,6 +84,118 @@ bitflags! {
         const CLONE_SIGNAL = 0x00010000 | 0x00000800;
         /// 克隆时，将原本被设置为SIG_IGNORE的信号，设置回SIG_DEFAULT
         const CLONE_CLEAR_SIGHAND = 0x100000000;
+        /// 将子进程放入clone_args.cgroup指定的cgroup中
+        const CLONE_INTO_CGROUP = 0x200000000;
+    }
+}
+
+/// clone3系统调用从用户空间传入的参数
+///
+/// 结构体会随着内核版本增加字段，用户程序通过size参数告知其使用的版本
+///
+/// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/include/uapi/linux/sched.h#92
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PosixCloneArgs {
+    pub flags: u64,
+    pub pidfd: u64,
+    pub child_tid: u64,
+    pub parent_tid: u64,
+    pub exit_signal: u64,
+    pub stack: u64,
+    pub stack_size: u64,
+    pub tls: u64,
+    pub set_tid: u64,
+    pub set_tid_size: u64,
+    pub cgroup: u64,
+}
+
+impl PosixCloneArgs {
+    /// 第一个版本的结构体大小
+    pub const SIZE_VER0: usize = 64;
+    /// 增加了set_tid与set_tid_size
+    pub const SIZE_VER1: usize = 80;
+    /// 增加了cgroup
+    pub const SIZE_VER2: usize = 88;
+}
+
+impl TryFrom<(PosixCloneArgs, usize)> for KernelCloneArgs {
+    type Error = SystemError;
+
+    /// 检查clone3的参数，并转换为内核中的参数载体
+    ///
+    /// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/kernel/fork.c#3058
+    fn try_from((args, size): (PosixCloneArgs, usize)) -> Result<Self, Self::Error> {
+        /// 旧版clone系统调用能使用的标志位
+        const CLONE_LEGACY_FLAGS: u64 = 0xffffffff;
+        /// flags中表示退出信号的部分
+        const CSIGNAL: u64 = 0xff;
+
+        if args.set_tid_size > MAX_PID_NS_LEVEL as u64
+            || (args.set_tid == 0 && args.set_tid_size != 0)
+            || (args.set_tid != 0 && args.set_tid_size == 0)
+        {
+            return Err(SystemError::EINVAL);
+        }
+        if (args.exit_signal & !CSIGNAL) != 0 || args.exit_signal > MAX_SIG_NUM as u64 {
+            return Err(SystemError::EINVAL);
+        }
+
+        let legacy_flags = CloneFlags::from_bits_truncate(args.flags & CLONE_LEGACY_FLAGS);
+        let extra_flags =
+            CloneFlags::from_bits(args.flags & !CLONE_LEGACY_FLAGS).ok_or(SystemError::EINVAL)?;
+        let flags = legacy_flags | extra_flags;
+        if !(CloneFlags::CLONE_CLEAR_SIGHAND | CloneFlags::CLONE_INTO_CGROUP).contains(extra_flags)
+        {
+            return Err(SystemError::EINVAL);
+        }
+        if flags.contains(CloneFlags::CLONE_INTO_CGROUP)
+            && (args.cgroup > i32::MAX as u64 || size < PosixCloneArgs::SIZE_VER2)
+        {
+            return Err(SystemError::EINVAL);
+        }
+        // 不能既共享信号处理函数，又将其重置
+        if flags.contains(CloneFlags::CLONE_SIGHAND | CloneFlags::CLONE_CLEAR_SIGHAND) {
+            return Err(SystemError::EINVAL);
+        }
+        // 线程以及CLONE_PARENT创建的进程不能指定退出信号
+        if flags.intersects(CloneFlags::CLONE_THREAD | CloneFlags::CLONE_PARENT)
+            && args.exit_signal != 0
+        {
+            return Err(SystemError::EINVAL);
+        }
+
+        // 用户给出的是栈的最低地址与大小，而栈是向下增长的
+        let stack = if args.stack == 0 {
+            if args.stack_size != 0 {
+                return Err(SystemError::EINVAL);
+            }
+            0
+        } else {
+            if args.stack_size == 0 {
+                return Err(SystemError::EINVAL);
+            }
+            let stack_top = args
+                .stack
+                .checked_add(args.stack_size)
+                .ok_or(SystemError::EINVAL)?;
+            verify_area(VirtAddr::new(args.stack as usize), args.stack_size as usize)?;
+            stack_top as usize
+        };
+
+        let mut kargs = KernelCloneArgs::new();
+        kargs.flags = flags;
+        kargs.pidfd = VirtAddr::new(args.pidfd as usize);
+        kargs.child_tid = VirtAddr::new(args.child_tid as usize);
+        kargs.parent_tid = VirtAddr::new(args.parent_tid as usize);
+        kargs.exit_signal = Signal::from(args.exit_signal as usize);
+        kargs.stack = stack;
+        kargs.stack_size = args.stack_size as usize;
+        kargs.tls = args.tls as usize;
+        kargs.set_tid = VirtAddr::new(args.set_tid as usize);
+        kargs.set_tid_size = args.set_tid_size as usize;
+        kargs.cgroup = args.cgroup as i32;
+        return Ok(kargs);
     }
 }
 
@@ -293,6 +413,56 @@ impl ProcessManager {
         return Ok(());
     }
 
+    /// 从用户空间读取clone3的set_tid数组
+    ///
+    /// 指定子进程的pid需要CAP_SYS_ADMIN或CAP_CHECKPOINT_RESTORE权限
+    fn copy_set_tid(
+        current_pcb: &Arc<ProcessControlBlock>,
+        clone_args: &KernelCloneArgs,
+    ) -> Result<Vec<Pid>, SystemError> {
+        if clone_args.set_tid_size == 0 {
+            return Ok(Vec::new());
+        }
+        let cred = current_pcb.cred();
+        if !cred.has_capability(CAPFlags::CAP_SYS_ADMIN)
+            && !cred.has_capability(CAPFlags::CAP_CHECKPOINT_RESTORE)
+        {
+            return Err(SystemError::EPERM);
+        }
+
+        let reader = UserBufferReader::new(
+            clone_args.set_tid.data() as *const i32,
+            clone_args.set_tid_size * core::mem::size_of::<i32>(),
+            true,
+        )?;
+        let mut set_tid = Vec::with_capacity(clone_args.set_tid_size);
+        for &tid in reader.read_from_user::<i32>(0)? {
+            if tid <= 0 {
+                return Err(SystemError::EINVAL);
+            }
+            set_tid.push(Pid::new(tid as usize));
+        }
+        return Ok(set_tid);
+    }
+
+    /// 在当前进程中为子进程创建pidfd，并将其写入`pidfd_ptr`指向的用户空间地址
+    ///
+    /// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/kernel/fork.c#2383
+    fn copy_pidfd(pcb: &Arc<ProcessControlBlock>, pidfd_ptr: VirtAddr) -> Result<(), SystemError> {
+        let mut writer = UserBufferWriter::new(
+            pidfd_ptr.data() as *mut i32,
+            core::mem::size_of::<i32>(),
+            true,
+        )?;
+        let pidfd = pidfd_create(pcb, PidFdFlags::empty())?;
+        if let Err(e) = writer.copy_one_to_user(&pidfd, 0) {
+            let binding = ProcessManager::current_pcb().fd_table();
+            binding.write().drop_fd(pidfd).ok();
+            return Err(e);
+        }
+        return Ok(());
+    }
+
     /// 拷贝进程信息
     ///
     /// ## panic:
@@ -322,6 +492,11 @@ impl ProcessManager {
             return Err(SystemError::EINVAL);
         }
 
+        // 线程组中的线程必须位于同一个cgroup中
+        if clone_flags.contains(CloneFlags::CLONE_THREAD | CloneFlags::CLONE_INTO_CGROUP) {
+            return Err(SystemError::EINVAL);
+        }
+
         // 线程组必须共享信号，分离线程只能在线程组内启动。
         if clone_flags.contains(CloneFlags::CLONE_THREAD)
             && !clone_flags.contains(CloneFlags::CLONE_SIGHAND)
@@ -341,11 +516,17 @@ impl ProcessManager {
 
         // 如果新进程使用不同的 pid 或 namespace，
         // 则不允许它与分叉任务共享线程组。
-        if clone_flags.contains(CloneFlags::CLONE_THREAD)
-            && clone_flags.contains(CloneFlags::CLONE_NEWUSER | CloneFlags::CLONE_NEWPID)
-        {
-            return Err(SystemError::EINVAL);
-            // TODO: 判断新进程与当前进程namespace是否相同，不同则返回错误
+        if clone_flags.contains(CloneFlags::CLONE_THREAD) {
+            if clone_flags.intersects(CloneFlags::CLONE_NEWUSER | CloneFlags::CLONE_NEWPID) {
+                return Err(SystemError::EINVAL);
+            }
+            // 通过unshare(CLONE_NEWPID)改变了子进程的pid namespace之后，不能再创建线程
+            if !Arc::ptr_eq(
+                &current_pcb.active_pid_ns(),
+                &current_pcb.nsproxy().pid_ns_for_children,
+            ) {
+                return Err(SystemError::EINVAL);
+            }
         }
 
         // 如果新进程将处于不同的time namespace，
@@ -360,6 +541,17 @@ impl ProcessManager {
             return Err(SystemError::EINVAL);
         }
 
+        // 检查用户的进程数是否超出RLIMIT_NPROC（拥有CAP_SYS_RESOURCE或CAP_SYS_ADMIN的进程不受限制）
+        let cred = current_pcb.cred();
+        if !cred.has_capability(CAPFlags::CAP_SYS_RESOURCE)
+            && !cred.has_capability(CAPFlags::CAP_SYS_ADMIN)
+        {
+            let limit = current_pcb.rlimit_cur(RLimitID::Nproc);
+            if ProcessManager::nr_processes_of(cred.uid) as u64 >= limit {
+                return Err(SystemError::EAGAIN_OR_EWOULDBLOCK);
+            }
+        }
+
         // TODO: 克隆前应该锁信号处理，等待克隆完成后再处理
 
         // 克隆架构相关
@@ -383,15 +575,42 @@ impl ProcessManager {
             pcb.thread.write_irqsave().set_child_tid = Some(clone_args.child_tid);
         }
 
-        // 将子进程/线程的id存储在用户态传进的地址中
+        // 拷贝namespace，并在子进程所在的pid namespace中为其分配pid
+        let set_tid = Self::copy_set_tid(current_pcb, &clone_args)?;
+        Self::copy_namespaces(&clone_flags, current_pcb, pcb, &set_tid)?;
+
+        // 将子进程/线程在当前进程的pid namespace中的id存储在用户态传进的地址中
         if clone_flags.contains(CloneFlags::CLONE_PARENT_SETTID) {
-            let mut writer = UserBufferWriter::new(
+            let nr = pcb
+                .pid_nr_ns(&current_pcb.active_pid_ns())
+                .unwrap_or(Pid(0));
+            let r = UserBufferWriter::new(
                 clone_args.parent_tid.data() as *mut i32,
                 core::mem::size_of::<i32>(),
                 true,
-            )?;
+            )
+            .and_then(|mut writer| writer.copy_one_to_user(&(nr.0 as i32), 0));
+            if let Err(e) = r {
+                pcb.detach_pid_ns();
+                return Err(e);
+            }
+        }
 
-            writer.copy_one_to_user(&(pcb.pid().0 as i32), 0)?;
+        // 将子进程直接放入clone3指定的cgroup中
+        if clone_flags.contains(CloneFlags::CLONE_INTO_CGROUP) {
+            match cgroup_from_fd(clone_args.cgroup) {
+                Ok(cgroup) => pcb.set_cgroup(cgroup),
+                Err(e) => {
+                    pcb.detach_pid_ns();
+                    return Err(e);
+                }
+            }
+        }
+
+        // 检查子进程所在cgroup的pids.max
+        if let Err(e) = pcb.cgroup().try_charge_pids(1) {
+            pcb.detach_pid_ns();
+            return Err(e);
         }
 
         sched_fork(pcb).unwrap_or_else(|e| {
@@ -401,6 +620,12 @@ impl ProcessManager {
             )
         });
 
+        pcb.set_dumpable(current_pcb.dumpable());
+        if current_pcb.no_new_privs() {
+            pcb.set_no_new_privs();
+        }
+        pcb.copy_seccomp(current_pcb);
+
         // 拷贝标志位
         Self::copy_flags(&clone_flags, pcb).unwrap_or_else(|e| {
             panic!(
@@ -487,6 +712,15 @@ impl ProcessManager {
 
         // todo: 增加线程组相关的逻辑。 参考 https://code.dragonos.org.cn/xref/linux-6.1.9/kernel/fork.c#2437
 
+        // 为子进程创建pidfd，并将其写入用户态传进的地址中
+        if clone_flags.contains(CloneFlags::CLONE_PIDFD) {
+            if let Err(e) = Self::copy_pidfd(pcb, clone_args.pidfd) {
+                pcb.cgroup().uncharge_pids(1);
+                pcb.detach_pid_ns();
+                return Err(e);
+            }
+        }
+
         sched_cgroup_fork(pcb);
 
         Ok(())
@@ -4,10 +4,11 @@ use core::{
     hint::spin_loop,
     intrinsics::{likely, unlikely},
     mem::ManuallyDrop,
-    sync::atomic::{compiler_fence, fence, AtomicBool, AtomicUsize, Ordering},
+    sync::atomic::{compiler_fence, fence, AtomicBool, AtomicU8, AtomicUsize, Ordering},
 };
 
 use alloc::{
+    collections::LinkedList,
     ffi::CString,
     string::{String, ToString},
     sync::{Arc, Weak},
@@ -21,17 +22,18 @@ use system_error::SystemError;
 use crate::{
     arch::{
         cpu::current_cpu_id,
-        ipc::signal::{AtomicSignal, SigSet, Signal},
+        ipc::signal::{AtomicSignal, SigCode, SigSet, Signal},
         process::ArchPCBInfo,
         CurrentIrqArch,
     },
+    cgroup::{Cgroup, CGROUP_ROOT},
     driver::tty::tty_core::TtyCore,
     exception::InterruptArch,
     filesystem::{
         procfs::procfs_unregister_pid,
         vfs::{file::FileDescriptorVec, FileType},
     },
-    ipc::signal_types::{SigInfo, SigPending, SignalStruct},
+    ipc::signal_types::{SigInfo, SigPending, SigType, SignalStruct},
     libs::{
         align::AlignedBox,
         casting::DowncastArc,
@@ -50,11 +52,14 @@ use crate::{
         ucontext::AddressSpace,
         VirtAddr,
     },
-    net::socket::SocketInode,
+    net::{
+        event_poll::{EPollEventType, EPollItem, EventPoll},
+        socket::SocketInode,
+    },
     sched::completion::Completion,
     sched::{
-        cpu_rq, fair::FairSchedEntity, prio::MAX_PRIO, DequeueFlag, EnqueueFlag, OnRq, SchedMode,
-        WakeupFlags, __schedule,
+        cpu_rq, cputime::ProcessCpuTime, fair::FairSchedEntity, prio::MAX_PRIO, DequeueFlag,
+        EnqueueFlag, OnRq, SchedMode, WakeupFlags, __schedule,
     },
     smp::{
         core::smp_get_processor_id,
@@ -65,18 +70,33 @@ use crate::{
 };
 use timer::AlarmTimer;
 
-use self::{cred::Cred, kthread::WorkerPrivate};
+use self::{
+    cred::Cred,
+    exit::StopEvent,
+    kthread::WorkerPrivate,
+    namespace::{NsProxy, INIT_NSPROXY},
+    pid::{UPid, INIT_PID_NS},
+    prctl::SUID_DUMP_USER,
+    resource::RLimits,
+    seccomp::Seccomp,
+};
 
 pub mod abi;
 pub mod c_adapter;
+pub mod capability;
+pub mod coredump;
 pub mod cred;
 pub mod exec;
 pub mod exit;
 pub mod fork;
 pub mod idle;
 pub mod kthread;
+pub mod namespace;
 pub mod pid;
+pub mod pidfd;
+pub mod prctl;
 pub mod resource;
+pub mod seccomp;
 pub mod stdio;
 pub mod syscall;
 pub mod timer;
@@ -207,6 +227,48 @@ impl ProcessManager {
         return ALL_PROCESS.lock_irqsave().as_ref()?.get(&pid).cloned();
     }
 
+    /// 获取位于`cgroup`中的所有未退出的进程（线程）
+    pub fn cgroup_tasks(cgroup: &Arc<Cgroup>) -> Vec<Arc<ProcessControlBlock>> {
+        ALL_PROCESS
+            .lock_irqsave()
+            .as_ref()
+            .map(|all| {
+                all.values()
+                    .filter(|pcb| !pcb.is_exited() && Arc::ptr_eq(&pcb.cgroup(), cgroup))
+                    .cloned()
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// 获取线程组`tgid`中所有未退出的线程
+    pub fn thread_group_tasks(tgid: Pid) -> Vec<Arc<ProcessControlBlock>> {
+        ALL_PROCESS
+            .lock_irqsave()
+            .as_ref()
+            .map(|all| {
+                all.values()
+                    .filter(|pcb| !pcb.is_exited() && pcb.tgid() == tgid)
+                    .cloned()
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// 获取系统中所有未退出的进程（只包括线程组的leader）
+    pub fn processes() -> Vec<Arc<ProcessControlBlock>> {
+        ALL_PROCESS
+            .lock_irqsave()
+            .as_ref()
+            .map(|all| {
+                all.values()
+                    .filter(|pcb| !pcb.is_exited() && pcb.pid() == pcb.tgid())
+                    .cloned()
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
     /// 向系统中添加一个进程的pcb
     ///
     /// ## 参数
@@ -222,6 +284,7 @@ impl ProcessManager {
             .as_mut()
             .unwrap()
             .insert(pcb.pid(), pcb.clone());
+        Self::inc_user_processes(pcb.cred().uid);
     }
 
     /// 唤醒一个进程
@@ -354,6 +417,10 @@ impl ProcessManager {
         let current = ProcessManager::current_pcb();
         // 让INIT进程收养所有子进程
         if current.pid() != Pid(1) {
+            // pid namespace的1号进程退出时，杀死该namespace中的其它进程
+            if current.is_child_reaper() {
+                current.zap_pid_ns_processes();
+            }
             unsafe {
                 current
                     .adopt_childen()
@@ -364,25 +431,53 @@ impl ProcessManager {
                 return;
             }
             let parent_pcb = r.unwrap();
-            let r = Syscall::kill(parent_pcb.pid(), Signal::SIGCHLD as i32);
-            if r.is_err() {
-                warn!(
-                    "failed to send kill signal to {:?}'s parent pcb {:?}",
-                    current.pid(),
-                    parent_pcb.pid()
-                );
+            parent_pcb.wait_chldexit.wakeup_all(None);
+            // 向父进程发送创建时指定的退出信号（线程以及clone3指定exit_signal为0的进程不发送）
+            let exit_signal = current.exit_signal.load(Ordering::SeqCst);
+            if exit_signal != Signal::INVALID {
+                let r = Syscall::kill(parent_pcb.pid(), exit_signal as i32);
+                if r.is_err() {
+                    warn!(
+                        "failed to send kill signal to {:?}'s parent pcb {:?}",
+                        current.pid(),
+                        parent_pcb.pid()
+                    );
+                }
             }
-            // todo: 这里需要向父进程发送SIGCHLD信号
             // todo: 这里还需要根据线程组的信息，决定信号的发送
         }
     }
 
+    /// 进程退出时关闭它打开的文件，从而释放它持有的文件锁
+    ///
+    /// 文件描述符表被其他进程共享（CLONE_FILES）时，由最后一个退出的进程关闭
+    ///
+    /// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/kernel/exit.c#509
+    fn exit_files(pcb: &Arc<ProcessControlBlock>) {
+        let Some(fd_table) = pcb.basic().fd_table() else {
+            return;
+        };
+        // pcb与这里各持有一个引用
+        if Arc::strong_count(&fd_table) > 2 {
+            return;
+        }
+        let mut fd_table_guard = fd_table.write();
+        let files = (0..FileDescriptorVec::PROCESS_MAX_FD)
+            .filter_map(|fd| fd_table_guard.drop_fd(fd as i32).ok())
+            .collect::<Vec<_>>();
+        // 在释放文件描述符表的锁之后再关闭文件
+        drop(fd_table_guard);
+        drop(files);
+    }
+
     /// 退出当前进程
     ///
     /// ## 参数
     ///
     /// - `exit_code` : 进程的退出码
     pub fn exit(exit_code: usize) -> ! {
+        // 关闭文件可能需要睡眠，因此在关中断之前进行
+        Self::exit_files(&ProcessManager::current_pcb());
         // 关中断
         let _guard = unsafe { CurrentIrqArch::save_and_disable_irq() };
         let pcb = ProcessManager::current_pcb();
@@ -391,6 +486,12 @@ impl ProcessManager {
             .inner_lock_write_irqsave()
             .set_state(ProcessState::Exited(exit_code));
         pcb.wait_queue.wakeup(Some(ProcessState::Blocked(true)));
+        // 通知监听该进程的pidfd
+        EventPoll::wakeup_epoll(
+            &pcb.pidfd_epitems,
+            EPollEventType::EPOLLIN | EPollEventType::EPOLLRDNORM,
+        )
+        .ok();
 
         let rq = cpu_rq(smp_get_processor_id().data() as usize);
         let (rq, guard) = rq.self_lock();
@@ -416,6 +517,9 @@ impl ProcessManager {
 
         RobustListHead::exit_robust_list(pcb.clone());
 
+        // 线程退出后不一定会被回收，因此在退出时（而不是回收时）就从cgroup中移除计数
+        pcb.cgroup().uncharge_pids(1);
+
         // 如果是vfork出来的进程，则需要处理completion
         if thread.vfork_done.is_some() {
             thread.vfork_done.as_ref().unwrap().complete_all();
@@ -450,7 +554,12 @@ impl ProcessManager {
             //     panic!()
             // }
 
-            ALL_PROCESS.lock_irqsave().as_mut().unwrap().remove(&pid);
+            let pcb = pcb.unwrap();
+            pcb.detach_pid_ns();
+            let removed = ALL_PROCESS.lock_irqsave().as_mut().unwrap().remove(&pid);
+            if removed.is_some() {
+                Self::dec_user_processes(pcb.cred().uid);
+            }
         }
     }
 
@@ -631,6 +740,16 @@ pub struct ProcessControlBlock {
     sig_struct: SpinLock<SignalStruct>,
     /// 退出信号S
     exit_signal: AtomicSignal,
+    /// 父进程退出时，当前进程收到的信号
+    pdeath_signal: AtomicSignal,
+    /// 是否允许产生core dump
+    dumpable: AtomicU8,
+    /// 是否为子孙进程的收养者（只有线程组leader的这个字段会被使用）
+    child_subreaper: AtomicBool,
+    /// 进程及其子进程不能通过execve获得新的特权
+    ///
+    /// seccomp的TSYNC会修改同一线程组中其它线程的这个字段，因此不能放在ProcessFlags中
+    no_new_privs: AtomicBool,
 
     /// 父进程指针
     parent_pcb: RwLock<Weak<ProcessControlBlock>>,
@@ -642,6 +761,12 @@ pub struct ProcessControlBlock {
 
     /// 等待队列
     wait_queue: WaitQueue,
+    /// 子进程退出、停止或继续运行时，唤醒在此等待的当前进程
+    wait_chldexit: WaitQueue,
+    /// 尚未被父进程获取的停止/继续事件
+    stop_event: SpinLock<StopEvent>,
+    /// 通过pidfd监听当前进程退出的epoll项
+    pidfd_epitems: SpinLock<LinkedList<Arc<EPollItem>>>,
 
     /// 线程信息
     thread: RwLock<ThreadInfo>,
@@ -654,6 +779,22 @@ pub struct ProcessControlBlock {
 
     /// 进程作为主体的凭证集
     cred: SpinLock<Cred>,
+
+    /// 资源限制（只有线程组leader的这个字段会被使用）
+    rlimits: SpinLock<RLimits>,
+    /// 进程占用的CPU时间
+    cpu_time: ProcessCpuTime,
+    /// 线程组中所有线程（包括已退出的线程）占用的CPU时间之和（只有线程组leader的这个字段会被使用）
+    group_cpu_time: ProcessCpuTime,
+
+    /// 进程所在的namespace
+    nsproxy: RwLock<Arc<NsProxy>>,
+    /// 进程所在的cgroup
+    cgroup: RwLock<Arc<Cgroup>>,
+    /// seccomp状态
+    seccomp: SpinLock<Seccomp>,
+    /// 进程在其所在的pid namespace及其祖先namespace中的pid，下标为namespace的层级
+    upids: RwLock<Vec<UPid>>,
 }
 
 impl ProcessControlBlock {
@@ -692,16 +833,33 @@ impl ProcessControlBlock {
 
     #[inline(never)]
     fn do_create_pcb(name: String, kstack: KernelStack, is_idle: bool) -> Arc<Self> {
-        let (pid, ppid, cwd, cred) = if is_idle {
+        let (pid, ppid, cwd, cred, rlimits, nsproxy, cgroup) = if is_idle {
             let cred = INIT_CRED.clone();
-            (Pid(0), Pid(0), "/".to_string(), cred)
+            (
+                Pid(0),
+                Pid(0),
+                "/".to_string(),
+                cred,
+                RLimits::default(),
+                INIT_NSPROXY.clone(),
+                CGROUP_ROOT.clone(),
+            )
         } else {
             let ppid = ProcessManager::current_pcb().pid();
-            let mut cred = ProcessManager::current_pcb().cred();
-            cred.cap_permitted = cred.cap_ambient;
-            cred.cap_effective = cred.cap_ambient;
+            let cred = ProcessManager::current_pcb().cred();
             let cwd = ProcessManager::current_pcb().basic().cwd();
-            (Self::generate_pid(), ppid, cwd, cred)
+            let rlimits = ProcessManager::current_pcb().rlimits();
+            let nsproxy = ProcessManager::current_pcb().nsproxy();
+            let cgroup = ProcessManager::current_pcb().cgroup();
+            (
+                Self::generate_pid(),
+                ppid,
+                cwd,
+                cred,
+                rlimits,
+                nsproxy,
+                cgroup,
+            )
         };
 
         let basic_info = ProcessBasicInfo::new(Pid(0), ppid, name, cwd, None);
@@ -729,14 +887,31 @@ impl ProcessControlBlock {
             sig_info: RwLock::new(ProcessSignalInfo::default()),
             sig_struct: SpinLock::new(SignalStruct::new()),
             exit_signal: AtomicSignal::new(Signal::SIGCHLD),
+            pdeath_signal: AtomicSignal::new(Signal::INVALID),
+            dumpable: AtomicU8::new(SUID_DUMP_USER),
+            child_subreaper: AtomicBool::new(false),
+            no_new_privs: AtomicBool::new(false),
             parent_pcb: RwLock::new(ppcb.clone()),
             real_parent_pcb: RwLock::new(ppcb),
             children: RwLock::new(Vec::new()),
             wait_queue: WaitQueue::default(),
+            wait_chldexit: WaitQueue::default(),
+            stop_event: SpinLock::new(StopEvent::None),
+            pidfd_epitems: SpinLock::new(LinkedList::new()),
             thread: RwLock::new(ThreadInfo::new()),
             alarm_timer: SpinLock::new(None),
             robust_list: RwLock::new(None),
             cred: SpinLock::new(cred),
+            rlimits: SpinLock::new(rlimits),
+            cpu_time: ProcessCpuTime::default(),
+            group_cpu_time: ProcessCpuTime::default(),
+            nsproxy: RwLock::new(nsproxy),
+            cgroup: RwLock::new(cgroup),
+            seccomp: SpinLock::new(Seccomp::default()),
+            upids: RwLock::new(vec![UPid {
+                nr: pid,
+                ns: INIT_PID_NS.clone(),
+            }]),
         };
 
         // 初始化系统调用栈
@@ -894,6 +1069,37 @@ impl ProcessControlBlock {
         self.cred.lock().clone()
     }
 
+    fn is_exited(&self) -> bool {
+        self.sched_info
+            .inner_lock_read_irqsave()
+            .state()
+            .is_exited()
+    }
+
+    /// 进程所在的cgroup
+    #[inline(always)]
+    pub fn cgroup(&self) -> Arc<Cgroup> {
+        self.cgroup.read_irqsave().clone()
+    }
+
+    pub fn set_cgroup(&self, cgroup: Arc<Cgroup>) {
+        *self.cgroup.write_irqsave() = cgroup;
+    }
+
+    #[inline(always)]
+    pub fn cpu_time(&self) -> &ProcessCpuTime {
+        &self.cpu_time
+    }
+
+    /// 访问整个线程组占用的CPU时间，它统一保存在线程组leader的pcb中
+    pub fn with_group_cpu_time<R>(&self, f: impl FnOnce(&ProcessCpuTime) -> R) -> R {
+        let leader = self.thread.read_irqsave().group_leader();
+        match leader {
+            Some(leader) if leader.pid() != self.pid() => f(&leader.group_cpu_time),
+            _ => f(&self.group_cpu_time),
+        }
+    }
+
     /// 根据文件描述符序号，获取socket对象的Arc指针
     ///
     /// ## 参数
@@ -920,21 +1126,74 @@ impl ProcessControlBlock {
         return Some(socket);
     }
 
-    /// 当前进程退出时,让初始进程收养所有子进程
-    unsafe fn adopt_childen(&self) -> Result<(), SystemError> {
-        match ProcessManager::find(Pid(1)) {
-            Some(init_pcb) => {
-                let childen_guard = self.children.write();
-                let mut init_childen_guard = init_pcb.children.write();
+    /// 当前进程退出时，让最近的subreaper祖先进程（没有的话则是初始进程）收养所有子进程，
+    /// 并向设置了pdeath_signal的子进程发送信号
+    ///
+    /// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/kernel/exit.c#677
+    unsafe fn adopt_childen(self: &Arc<Self>) -> Result<(), SystemError> {
+        let reaper = self.find_new_reaper().ok_or(SystemError::ECHILD)?;
+        let childen = core::mem::take(&mut *self.children.write());
+        let mut pdeath_signals = Vec::new();
+
+        let mut reaper_childen_guard = reaper.children.write();
+        for pid in childen {
+            if let Some(child) = ProcessManager::find(pid) {
+                *child.parent_pcb.write_irqsave() = Arc::downgrade(&reaper);
+                *child.real_parent_pcb.write_irqsave() = Arc::downgrade(&reaper);
+                child.basic_mut().set_ppid(reaper.pid());
+
+                let sig = child.pdeath_signal();
+                if sig != Signal::INVALID {
+                    pdeath_signals.push((pid, sig));
+                }
+            }
+            reaper_childen_guard.push(pid);
+        }
+        drop(reaper_childen_guard);
+        // 收养的子进程中可能有已经退出的进程，唤醒收养者以回收它们
+        reaper.wait_chldexit.wakeup_all(None);
 
-                childen_guard.iter().for_each(|pid| {
-                    init_childen_guard.push(*pid);
-                });
+        for (pid, sig) in pdeath_signals {
+            let mut info = SigInfo::new(sig, 0, SigCode::Kernel, SigType::Kill(self.pid()));
+            sig.send_signal_info(Some(&mut info), pid).ok();
+        }
 
-                return Ok(());
+        return Ok(());
+    }
+
+    /// 寻找收养当前进程的子进程的进程
+    ///
+    /// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/kernel/exit.c#621
+    fn find_new_reaper(self: &Arc<Self>) -> Option<Arc<ProcessControlBlock>> {
+        // pid namespace的1号进程退出时，由上一层namespace的1号进程收养其子进程
+        let pid_ns = self.active_pid_ns();
+        let child_reaper = if self.is_child_reaper() {
+            pid_ns.parent().and_then(|ns| ns.child_reaper())
+        } else {
+            pid_ns.child_reaper()
+        };
+
+        let mut ancestor = self.parent_pcb.read_irqsave().upgrade();
+        while let Some(pcb) = ancestor {
+            if pcb.pid() <= Pid(1)
+                || child_reaper
+                    .as_ref()
+                    .is_some_and(|reaper| Arc::ptr_eq(reaper, &pcb))
+            {
+                break;
+            }
+            let exited = pcb
+                .sched_info()
+                .inner_lock_read_irqsave()
+                .state()
+                .is_exited();
+            if !exited && pcb.is_child_subreaper() {
+                return Some(pcb);
             }
-            _ => Err(SystemError::ECHILD),
+            ancestor = pcb.parent_pcb.read_irqsave().upgrade();
         }
+
+        child_reaper.or_else(|| ProcessManager::find(Pid(1)))
     }
 
     /// 生成进程的名字
@@ -1110,6 +1369,10 @@ impl ProcessBasicInfo {
         return self.ppid;
     }
 
+    pub fn set_ppid(&mut self, ppid: Pid) {
+        self.ppid = ppid;
+    }
+
     pub fn name(&self) -> &str {
         return &self.name;
     }
@@ -1485,6 +1748,8 @@ pub struct ProcessSignalInfo {
     sig_shared_pending: SigPending,
     // 当前进程对应的tty
     tty: Option<Arc<TtyCore>>,
+    // pselect等系统调用临时替换屏蔽信号时，保存原来的屏蔽信号，在处理完信号之后恢复
+    saved_sigmask: Option<SigSet>,
 }
 
 impl ProcessSignalInfo {
@@ -1520,6 +1785,18 @@ impl ProcessSignalInfo {
         self.tty = Some(tty);
     }
 
+    pub fn saved_sigmask(&self) -> Option<&SigSet> {
+        self.saved_sigmask.as_ref()
+    }
+
+    pub fn set_saved_sigmask(&mut self, mask: SigSet) {
+        self.saved_sigmask = Some(mask);
+    }
+
+    pub fn take_saved_sigmask(&mut self) -> Option<SigSet> {
+        self.saved_sigmask.take()
+    }
+
     /// 从 pcb 的 siginfo中取出下一个要处理的信号，先处理线程信号，再处理进程信号
     ///
     /// ## 参数
@@ -1543,6 +1820,7 @@ impl Default for ProcessSignalInfo {
             sig_pending: SigPending::default(),
             sig_shared_pending: SigPending::default(),
             tty: None,
+            saved_sigmask: None,
         }
     }
 }
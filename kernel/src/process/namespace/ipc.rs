@@ -0,0 +1,39 @@
+use alloc::sync::Arc;
+
+use crate::{
+    ipc::shm::ShmManager,
+    libs::spinlock::{SpinLock, SpinLockGuard},
+};
+
+use super::alloc_ns_inum;
+
+lazy_static! {
+    pub static ref INIT_IPC_NS: Arc<IpcNamespace> = IpcNamespace::new();
+}
+
+/// IPC namespace，隔离System V共享内存等IPC对象
+///
+/// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/include/linux/ipc_namespace.h#31
+#[derive(Debug)]
+pub struct IpcNamespace {
+    inum: usize,
+    shm_manager: SpinLock<ShmManager>,
+}
+
+impl IpcNamespace {
+    /// 创建一个新的、不包含任何IPC对象的IPC namespace
+    pub fn new() -> Arc<Self> {
+        Arc::new_cyclic(|self_ref| Self {
+            inum: alloc_ns_inum(),
+            shm_manager: SpinLock::new(ShmManager::new(self_ref.clone())),
+        })
+    }
+
+    pub fn inum(&self) -> usize {
+        self.inum
+    }
+
+    pub fn shm_manager_lock(&self) -> SpinLockGuard<ShmManager> {
+        self.shm_manager.lock()
+    }
+}
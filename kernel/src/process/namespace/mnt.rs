@@ -0,0 +1,88 @@
+use alloc::{sync::Arc, vec::Vec};
+use system_error::SystemError;
+
+use crate::{
+    filesystem::vfs::{
+        core::{init_root_inode, set_init_root},
+        mount::{init_mount_list, MountFS, MountList},
+        IndexNode,
+    },
+    libs::{casting::DowncastArc, rwlock::RwLock},
+};
+
+use super::alloc_ns_inum;
+
+lazy_static! {
+    pub static ref INIT_MNT_NS: Arc<MntNamespace> = Arc::new(MntNamespace {
+        inum: alloc_ns_inum(),
+        mounts: RwLock::new(None),
+    });
+}
+
+/// mount namespace，隔离挂载树
+///
+/// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/fs/mount.h#8
+#[derive(Debug)]
+pub struct MntNamespace {
+    inum: usize,
+    /// 私有的挂载树的根及其挂载列表。
+    ///
+    /// init mount namespace使用全局的根文件系统（迁移根文件系统时会被替换），因此为None
+    mounts: RwLock<Option<(Arc<MountFS>, Arc<MountList>)>>,
+}
+
+impl MntNamespace {
+    /// 复制当前namespace的挂载树，得到一个新的mount namespace
+    ///
+    /// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/fs/namespace.c#3719
+    pub fn copy(&self) -> Result<Arc<Self>, SystemError> {
+        let (root_fs, mount_list) = match self.mounts.read().as_ref() {
+            Some((root_fs, mount_list)) => (root_fs.clone(), mount_list.clone()),
+            None => (
+                init_root_inode()
+                    .fs()
+                    .downcast_arc::<MountFS>()
+                    .ok_or(SystemError::EINVAL)?,
+                init_mount_list(),
+            ),
+        };
+
+        let mut mapping = Vec::new();
+        let new_root = root_fs.copy_tree(None, &mut mapping);
+        let new_list = mount_list.copy_with(&mapping);
+
+        Ok(Arc::new(Self {
+            inum: alloc_ns_inum(),
+            mounts: RwLock::new(Some((new_root, new_list))),
+        }))
+    }
+
+    pub fn inum(&self) -> usize {
+        self.inum
+    }
+
+    /// 私有挂载树的根节点，init mount namespace返回None
+    pub fn root_inode(&self) -> Option<Arc<dyn IndexNode>> {
+        self.mounts
+            .read()
+            .as_ref()
+            .map(|(root_fs, _)| root_fs.mountpoint_root_inode() as Arc<dyn IndexNode>)
+    }
+
+    /// 私有挂载树的挂载列表，init mount namespace返回None
+    pub fn mount_list(&self) -> Option<Arc<MountList>> {
+        self.mounts
+            .read()
+            .as_ref()
+            .map(|(_, mount_list)| mount_list.clone())
+    }
+
+    /// 替换当前namespace的挂载树及其挂载列表，用于pivot_root
+    pub fn set_root(&self, root_fs: Arc<MountFS>, mount_list: Arc<MountList>) {
+        let mut mounts = self.mounts.write();
+        match mounts.as_mut() {
+            Some(mounts) => *mounts = (root_fs, mount_list),
+            None => set_init_root(root_fs, mount_list),
+        }
+    }
+}
@@ -0,0 +1,316 @@
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use alloc::{string::ToString, sync::Arc};
+use system_error::SystemError;
+
+use crate::{filesystem::procfs::procfs_ns_ref, libs::rwlock::RwLock, syscall::Syscall};
+
+use self::{
+    ipc::{IpcNamespace, INIT_IPC_NS},
+    mnt::{MntNamespace, INIT_MNT_NS},
+    net::{NetNamespace, INIT_NET_NS},
+    uts::{UtsNamespace, INIT_UTS_NS},
+};
+
+use super::{
+    cred::CAPFlags,
+    fork::CloneFlags,
+    pid::{PidNamespace, INIT_PID_NS},
+    Pid, ProcessControlBlock, ProcessManager,
+};
+
+pub mod ipc;
+pub mod mnt;
+pub mod net;
+pub mod uts;
+
+lazy_static! {
+    /// 初始的namespace集合，内核线程与init进程均使用它
+    pub static ref INIT_NSPROXY: Arc<NsProxy> = Arc::new(NsProxy {
+        uts_ns: INIT_UTS_NS.clone(),
+        ipc_ns: INIT_IPC_NS.clone(),
+        mnt_ns: INIT_MNT_NS.clone(),
+        pid_ns_for_children: INIT_PID_NS.clone(),
+        net_ns: INIT_NET_NS.clone(),
+    });
+}
+
+/// 动态分配的namespace编号的起始值
+///
+/// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/include/linux/proc_ns.h#43
+const PROC_DYNAMIC_FIRST: usize = 0xF0000000;
+
+/// 分配一个namespace编号，用于在/proc/<pid>/ns/下区分不同的namespace
+pub fn alloc_ns_inum() -> usize {
+    static NEXT_INUM: AtomicUsize = AtomicUsize::new(PROC_DYNAMIC_FIRST);
+    NEXT_INUM.fetch_add(1, Ordering::SeqCst)
+}
+
+/// 当前支持的namespace类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NsType {
+    Ipc,
+    Mnt,
+    Pid,
+    Uts,
+    Net,
+}
+
+impl NsType {
+    pub const ALL: [NsType; 5] = [
+        NsType::Ipc,
+        NsType::Mnt,
+        NsType::Pid,
+        NsType::Uts,
+        NsType::Net,
+    ];
+
+    /// namespace在/proc/<pid>/ns/下的文件名
+    pub fn name(&self) -> &'static str {
+        match self {
+            NsType::Ipc => "ipc",
+            NsType::Mnt => "mnt",
+            NsType::Pid => "pid",
+            NsType::Uts => "uts",
+            NsType::Net => "net",
+        }
+    }
+
+    pub fn clone_flag(&self) -> CloneFlags {
+        match self {
+            NsType::Ipc => CloneFlags::CLONE_NEWIPC,
+            NsType::Mnt => CloneFlags::CLONE_NEWNS,
+            NsType::Pid => CloneFlags::CLONE_NEWPID,
+            NsType::Uts => CloneFlags::CLONE_NEWUTS,
+            NsType::Net => CloneFlags::CLONE_NEWNET,
+        }
+    }
+}
+
+/// 对某个namespace的引用
+///
+/// 打开/proc/<pid>/ns/下的文件时获取，之后由该文件持有。即使目标进程已经退出，
+/// namespace也不会被释放，setns加入的始终是打开文件时的那个namespace。
+///
+/// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/include/linux/ns_common.h#9
+#[derive(Debug, Clone)]
+pub enum NsRef {
+    Ipc(Arc<IpcNamespace>),
+    Mnt(Arc<MntNamespace>),
+    Pid(Arc<PidNamespace>),
+    Uts(Arc<UtsNamespace>),
+    Net(Arc<NetNamespace>),
+}
+
+impl NsRef {
+    pub fn ns_type(&self) -> NsType {
+        match self {
+            NsRef::Ipc(_) => NsType::Ipc,
+            NsRef::Mnt(_) => NsType::Mnt,
+            NsRef::Pid(_) => NsType::Pid,
+            NsRef::Uts(_) => NsType::Uts,
+            NsRef::Net(_) => NsType::Net,
+        }
+    }
+
+    pub fn inum(&self) -> usize {
+        match self {
+            NsRef::Ipc(ns) => ns.inum(),
+            NsRef::Mnt(ns) => ns.inum(),
+            NsRef::Pid(ns) => ns.inum(),
+            NsRef::Uts(ns) => ns.inum(),
+            NsRef::Net(ns) => ns.inum(),
+        }
+    }
+}
+
+/// 进程所在的各类namespace的集合，可被多个进程共享
+///
+/// 注意：进程自身所在的pid namespace保存在pcb中，这里的`pid_ns_for_children`是其子进程将会被创建在的pid namespace
+///
+/// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/include/linux/nsproxy.h#31
+#[derive(Debug, Clone)]
+pub struct NsProxy {
+    pub uts_ns: Arc<UtsNamespace>,
+    pub ipc_ns: Arc<IpcNamespace>,
+    pub mnt_ns: Arc<MntNamespace>,
+    pub pid_ns_for_children: Arc<PidNamespace>,
+    pub net_ns: Arc<NetNamespace>,
+}
+
+impl NsProxy {
+    /// 所有可以通过clone/unshare创建的namespace对应的标志位
+    const NEW_NS_FLAGS: CloneFlags = CloneFlags::CLONE_NEWNS
+        .union(CloneFlags::CLONE_NEWUTS)
+        .union(CloneFlags::CLONE_NEWIPC)
+        .union(CloneFlags::CLONE_NEWPID)
+        .union(CloneFlags::CLONE_NEWNET);
+
+    /// 根据`flags`，在当前进程的namespace的基础上创建新的namespace集合
+    ///
+    /// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/kernel/nsproxy.c#67
+    fn create_new_namespaces(
+        &self,
+        flags: &CloneFlags,
+        pcb: &Arc<ProcessControlBlock>,
+    ) -> Result<Arc<NsProxy>, SystemError> {
+        let mut new = self.clone();
+        if flags.contains(CloneFlags::CLONE_NEWUTS) {
+            new.uts_ns = self.uts_ns.copy();
+        }
+        if flags.contains(CloneFlags::CLONE_NEWIPC) {
+            new.ipc_ns = IpcNamespace::new();
+        }
+        if flags.contains(CloneFlags::CLONE_NEWNS) {
+            new.mnt_ns = self.mnt_ns.copy()?;
+        }
+        if flags.contains(CloneFlags::CLONE_NEWPID) {
+            // 只有当子进程的pid namespace与自身相同时，才能创建新的pid namespace
+            let active = pcb.active_pid_ns();
+            if !Arc::ptr_eq(&self.pid_ns_for_children, &active) {
+                return Err(SystemError::EINVAL);
+            }
+            new.pid_ns_for_children = active.create_child()?;
+        }
+        if flags.contains(CloneFlags::CLONE_NEWNET) {
+            new.net_ns = NetNamespace::new()?;
+        }
+        return Ok(Arc::new(new));
+    }
+}
+
+impl ProcessControlBlock {
+    pub fn nsproxy(&self) -> Arc<NsProxy> {
+        self.nsproxy.read_irqsave().clone()
+    }
+
+    pub fn set_nsproxy(&self, nsproxy: Arc<NsProxy>) {
+        *self.nsproxy.write_irqsave() = nsproxy;
+    }
+
+    /// 进程所在的指定类型的namespace
+    pub fn ns_ref(&self, ns_type: NsType) -> NsRef {
+        let nsproxy = self.nsproxy();
+        match ns_type {
+            NsType::Ipc => NsRef::Ipc(nsproxy.ipc_ns.clone()),
+            NsType::Mnt => NsRef::Mnt(nsproxy.mnt_ns.clone()),
+            NsType::Pid => NsRef::Pid(self.active_pid_ns()),
+            NsType::Uts => NsRef::Uts(nsproxy.uts_ns.clone()),
+            NsType::Net => NsRef::Net(nsproxy.net_ns.clone()),
+        }
+    }
+}
+
+impl ProcessManager {
+    /// 为新进程设置namespace集合，并在其pid namespace中为其分配pid
+    ///
+    /// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/kernel/nsproxy.c#151
+    pub(super) fn copy_namespaces(
+        clone_flags: &CloneFlags,
+        current_pcb: &Arc<ProcessControlBlock>,
+        new_pcb: &Arc<ProcessControlBlock>,
+        set_tid: &[Pid],
+    ) -> Result<(), SystemError> {
+        let nsproxy = current_pcb.nsproxy();
+        if clone_flags.intersects(NsProxy::NEW_NS_FLAGS) {
+            if !current_pcb.cred().has_capability(CAPFlags::CAP_SYS_ADMIN) {
+                return Err(SystemError::EPERM);
+            }
+            // 新的IPC namespace中不能共享System V信号量的undo链表
+            if clone_flags.contains(CloneFlags::CLONE_NEWIPC | CloneFlags::CLONE_SYSVSEM) {
+                return Err(SystemError::EINVAL);
+            }
+            new_pcb.set_nsproxy(nsproxy.create_new_namespaces(clone_flags, current_pcb)?);
+        } else {
+            new_pcb.set_nsproxy(nsproxy);
+        }
+
+        new_pcb.attach_pid_ns(&new_pcb.nsproxy().pid_ns_for_children, set_tid)
+    }
+}
+
+impl Syscall {
+    /// # 使当前进程脱离与其它进程共享的执行上下文
+    ///
+    /// 目前支持创建新的uts、ipc、mount、pid与network namespace，以及脱离共享的文件描述符表。
+    /// 对于CLONE_NEWPID，当前进程本身不会进入新的pid namespace，其之后创建的子进程才会。
+    ///
+    /// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/kernel/fork.c#3271
+    pub fn unshare(flags: u64) -> Result<usize, SystemError> {
+        let flags = CloneFlags::from_bits(flags).ok_or(SystemError::EINVAL)?;
+        let supported = NsProxy::NEW_NS_FLAGS
+            | CloneFlags::CLONE_THREAD
+            | CloneFlags::CLONE_FS
+            | CloneFlags::CLONE_SIGHAND
+            | CloneFlags::CLONE_VM
+            | CloneFlags::CLONE_FILES
+            | CloneFlags::CLONE_SYSVSEM;
+        if !supported.contains(flags) {
+            return Err(SystemError::EINVAL);
+        }
+
+        let pcb = ProcessManager::current_pcb();
+        if flags.intersects(NsProxy::NEW_NS_FLAGS) {
+            if !pcb.cred().has_capability(CAPFlags::CAP_SYS_ADMIN) {
+                return Err(SystemError::EPERM);
+            }
+            let new_nsproxy = pcb.nsproxy().create_new_namespaces(&flags, &pcb)?;
+            pcb.set_nsproxy(new_nsproxy);
+        }
+
+        if flags.contains(CloneFlags::CLONE_FILES) {
+            let new_fd_table = pcb.basic().fd_table().unwrap().read().clone();
+            pcb.basic_mut()
+                .set_fd_table(Some(Arc::new(RwLock::new(new_fd_table))));
+        }
+
+        return Ok(0);
+    }
+
+    /// # 使当前进程加入`fd`所指向的namespace
+    ///
+    /// `fd`需要是打开/proc/<pid>/ns/下的文件得到的文件描述符。
+    /// `nstype`为0时表示允许加入任意类型的namespace，否则需要与`fd`对应的namespace类型一致。
+    ///
+    /// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/kernel/nsproxy.c#545
+    pub fn setns(fd: i32, nstype: u64) -> Result<usize, SystemError> {
+        let pcb = ProcessManager::current_pcb();
+        let file = pcb
+            .fd_table()
+            .read()
+            .get_file_by_fd(fd)
+            .ok_or(SystemError::EBADF)?;
+        let ns = procfs_ns_ref(&file).ok_or(SystemError::EINVAL)?;
+        let ns_type = ns.ns_type();
+        if nstype != 0 && nstype != ns_type.clone_flag().bits() {
+            return Err(SystemError::EINVAL);
+        }
+
+        if !pcb.cred().has_capability(CAPFlags::CAP_SYS_ADMIN) {
+            return Err(SystemError::EPERM);
+        }
+
+        let mut new = NsProxy::clone(&pcb.nsproxy());
+        match ns {
+            NsRef::Ipc(ns) => new.ipc_ns = ns,
+            NsRef::Mnt(ns) => new.mnt_ns = ns,
+            NsRef::Uts(ns) => new.uts_ns = ns,
+            NsRef::Net(ns) => new.net_ns = ns,
+            NsRef::Pid(ns) => {
+                // 只能进入当前pid namespace的子孙namespace
+                if !pcb.active_pid_ns().is_ancestor_of(&ns) {
+                    return Err(SystemError::EINVAL);
+                }
+                new.pid_ns_for_children = ns;
+            }
+        }
+        pcb.set_nsproxy(Arc::new(new));
+
+        // 进入新的mount namespace后，工作目录位于新namespace的根目录
+        if ns_type == NsType::Mnt {
+            pcb.basic_mut().set_cwd("/".to_string());
+        }
+
+        return Ok(0);
+    }
+}
@@ -0,0 +1,161 @@
+use core::fmt::Debug;
+
+use alloc::{
+    collections::BTreeMap,
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+use smoltcp::{iface::SocketSet, wire::IpAddress};
+use system_error::SystemError;
+
+use crate::{
+    driver::net::{
+        loopback::{LoopbackDriver, LoopbackInterface},
+        NetDeivceState, NetDevice,
+    },
+    filesystem::procfs::procfs_ns_ref,
+    libs::{rwlock::RwLock, spinlock::SpinLock},
+    net::socket::PortManager,
+    process::ProcessManager,
+};
+
+use super::{alloc_ns_inum, NsRef};
+
+lazy_static! {
+    /// 初始的network namespace，物理网卡与全局的lo网卡均注册在这里
+    pub static ref INIT_NET_NS: Arc<NetNamespace> = NetNamespace::new_empty();
+    /// 所有存活的network namespace，用于网络轮询
+    static ref NET_NAMESPACES: SpinLock<BTreeMap<usize, Weak<NetNamespace>>> =
+        SpinLock::new(BTreeMap::new());
+}
+
+/// network namespace，隔离网络接口、socket以及端口
+///
+/// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/include/net/net_namespace.h#61
+pub struct NetNamespace {
+    inum: usize,
+    /// 该namespace中的网络接口，key为网卡的id。
+    /// 这个列表在中断上下文会使用到，因此需要irqsave
+    devices: RwLock<BTreeMap<usize, Arc<dyn NetDevice>>>,
+    /// 该namespace中所有smoltcp socket的集合
+    socket_set: SpinLock<SocketSet<'static>>,
+    /// 端口管理器
+    port_manager: PortManager,
+}
+
+impl NetNamespace {
+    fn new_empty() -> Arc<Self> {
+        let netns = Arc::new(Self {
+            inum: alloc_ns_inum(),
+            devices: RwLock::new(BTreeMap::new()),
+            socket_set: SpinLock::new(SocketSet::new(vec![])),
+            port_manager: PortManager::new(),
+        });
+        NET_NAMESPACES
+            .lock_irqsave()
+            .insert(netns.inum, Arc::downgrade(&netns));
+        netns
+    }
+
+    /// 创建一个新的network namespace，其中只包含一个lo网卡
+    ///
+    /// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/net/core/net_namespace.c#471
+    pub fn new() -> Result<Arc<Self>, SystemError> {
+        let netns = Self::new_empty();
+
+        // sysfs尚未区分network namespace，因此这里的lo网卡不注册到sysfs中，避免与全局的lo重名
+        let lo = LoopbackInterface::new(LoopbackDriver::new());
+        lo.set_net_state(NetDeivceState::__LINK_STATE_START);
+        netns.register_device(lo);
+
+        Ok(netns)
+    }
+
+    pub fn inum(&self) -> usize {
+        self.inum
+    }
+
+    pub fn devices(&self) -> &RwLock<BTreeMap<usize, Arc<dyn NetDevice>>> {
+        &self.devices
+    }
+
+    pub fn socket_set(&self) -> &SpinLock<SocketSet<'static>> {
+        &self.socket_set
+    }
+
+    pub fn port_manager(&self) -> &PortManager {
+        &self.port_manager
+    }
+
+    /// 获取文件描述符`fd`（/proc/<pid>/ns/net）所引用的network namespace
+    pub fn from_fd(fd: i32) -> Result<Arc<Self>, SystemError> {
+        let file = ProcessManager::current_pcb()
+            .fd_table()
+            .read()
+            .get_file_by_fd(fd)
+            .ok_or(SystemError::EBADF)?;
+        match procfs_ns_ref(&file) {
+            Some(NsRef::Net(netns)) => Ok(netns),
+            _ => Err(SystemError::EINVAL),
+        }
+    }
+
+    /// 按名称查找该namespace中的网卡
+    pub fn find_device(&self, name: &str) -> Option<Arc<dyn NetDevice>> {
+        self.devices
+            .read_irqsave()
+            .values()
+            .find(|dev| dev.iface_name() == name)
+            .cloned()
+    }
+
+    /// 将网卡加入到该namespace中
+    pub fn register_device(&self, dev: Arc<dyn NetDevice>) {
+        self.devices.write_irqsave().insert(dev.nic_id(), dev);
+    }
+
+    /// 将网卡从该namespace中移除
+    pub fn unregister_device(&self, nic_id: usize) -> Option<Arc<dyn NetDevice>> {
+        self.devices.write_irqsave().remove(&nic_id)
+    }
+
+    /// 选择用于发往`dst`的网卡：优先选择与`dst`处于同一子网的网卡，否则使用id最小的网卡
+    pub fn route_iface(&self, dst: &IpAddress) -> Option<Arc<dyn NetDevice>> {
+        let devices = self.devices.read_irqsave();
+        devices
+            .values()
+            .find(|dev| {
+                dev.inner_iface()
+                    .lock()
+                    .ip_addrs()
+                    .iter()
+                    .any(|cidr| cidr.contains_addr(dst))
+            })
+            .or_else(|| devices.values().next())
+            .cloned()
+    }
+}
+
+impl Debug for NetNamespace {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("NetNamespace")
+            .field("inum", &self.inum)
+            .field("devices", &self.devices.read_irqsave().keys())
+            .finish()
+    }
+}
+
+impl Drop for NetNamespace {
+    fn drop(&mut self) {
+        NET_NAMESPACES.lock_irqsave().remove(&self.inum);
+    }
+}
+
+/// 获取所有存活的network namespace
+pub fn all_net_namespaces() -> Vec<Arc<NetNamespace>> {
+    NET_NAMESPACES
+        .lock_irqsave()
+        .values()
+        .filter_map(|netns| netns.upgrade())
+        .collect()
+}
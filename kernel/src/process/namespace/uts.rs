@@ -0,0 +1,101 @@
+use alloc::sync::Arc;
+use system_error::SystemError;
+
+use crate::{
+    libs::spinlock::SpinLock,
+    process::{capability::capable, cred::CAPFlags, syscall::PosixNewUtsName, ProcessManager},
+    syscall::{user_access::UserBufferReader, Syscall},
+};
+
+use super::alloc_ns_inum;
+
+/// 主机名与域名的最大长度（不包括结尾的'\0'）
+pub const UTS_NAME_LEN: usize = 64;
+
+lazy_static! {
+    pub static ref INIT_UTS_NS: Arc<UtsNamespace> = Arc::new(UtsNamespace {
+        inum: alloc_ns_inum(),
+        name: SpinLock::new(PosixNewUtsName::new()),
+    });
+}
+
+/// UTS namespace，隔离主机名与域名
+///
+/// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/include/linux/utsname.h#24
+#[derive(Debug)]
+pub struct UtsNamespace {
+    inum: usize,
+    name: SpinLock<PosixNewUtsName>,
+}
+
+impl UtsNamespace {
+    /// 复制一个新的UTS namespace，新namespace的初始内容与当前namespace相同
+    pub fn copy(&self) -> Arc<Self> {
+        Arc::new(Self {
+            inum: alloc_ns_inum(),
+            name: SpinLock::new(*self.name.lock()),
+        })
+    }
+
+    pub fn inum(&self) -> usize {
+        self.inum
+    }
+
+    pub fn utsname(&self) -> PosixNewUtsName {
+        *self.name.lock()
+    }
+
+    pub fn set_hostname(&self, name: &[u8]) {
+        let mut guard = self.name.lock();
+        guard.nodename.fill(0);
+        guard.nodename[..name.len()].copy_from_slice(name);
+    }
+
+    pub fn set_domainname(&self, name: &[u8]) {
+        let mut guard = self.name.lock();
+        guard.domainname.fill(0);
+        guard.domainname[..name.len()].copy_from_slice(name);
+    }
+}
+
+impl Syscall {
+    /// 设置当前UTS namespace的主机名
+    ///
+    /// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/kernel/sys.c#1352
+    pub fn sethostname(name: *const u8, len: usize) -> Result<usize, SystemError> {
+        let buf = Self::read_uts_name(name, len)?;
+        ProcessManager::current_pcb()
+            .nsproxy()
+            .uts_ns
+            .set_hostname(&buf);
+        return Ok(0);
+    }
+
+    /// 设置当前UTS namespace的域名
+    ///
+    /// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/kernel/sys.c#1405
+    pub fn setdomainname(name: *const u8, len: usize) -> Result<usize, SystemError> {
+        let buf = Self::read_uts_name(name, len)?;
+        ProcessManager::current_pcb()
+            .nsproxy()
+            .uts_ns
+            .set_domainname(&buf);
+        return Ok(0);
+    }
+
+    fn read_uts_name(name: *const u8, len: usize) -> Result<[u8; UTS_NAME_LEN], SystemError> {
+        if !capable(CAPFlags::CAP_SYS_ADMIN) {
+            return Err(SystemError::EPERM);
+        }
+        if len > UTS_NAME_LEN {
+            return Err(SystemError::EINVAL);
+        }
+
+        let mut buf = [0u8; UTS_NAME_LEN];
+        if len > 0 {
+            let reader = UserBufferReader::new(name, len, true)?;
+            reader.copy_from_user(&mut buf[..len], 0)?;
+        }
+        return Ok(buf);
+    }
+}
@@ -1,3 +1,19 @@
+use alloc::{
+    collections::BTreeMap,
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+use ida::IdAllocator;
+use system_error::SystemError;
+
+use crate::{
+    arch::ipc::signal::{SigCode, Signal},
+    ipc::signal_types::{SigInfo, SigType},
+    libs::spinlock::SpinLock,
+};
+
+use super::{namespace::alloc_ns_inum, Pid, ProcessControlBlock, ProcessManager};
+
 #[allow(dead_code)]
 #[derive(Debug, Clone, Copy)]
 #[repr(u8)]
@@ -16,3 +32,319 @@ impl PartialEq for PidType {
         *self as u8 == *other as u8
     }
 }
+
+/// pid namespace的最大嵌套层数
+///
+/// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/include/linux/pid_namespace.h#13
+pub const MAX_PID_NS_LEVEL: usize = 32;
+
+/// 非init pid namespace中pid的最大值
+const PID_NS_PID_MAX: usize = 4194304;
+
+lazy_static! {
+    /// 初始pid namespace，其中的pid与全局pid相同
+    pub static ref INIT_PID_NS: Arc<PidNamespace> = PidNamespace::new_init();
+}
+
+/// pid namespace
+///
+/// 每个进程在其所在的pid namespace及其所有祖先namespace中都拥有一个pid。
+/// 内核内部始终使用init pid namespace中的pid（即全局pid），
+/// 只在与用户态交互的时候，才转换为当前进程所在namespace中的pid。
+///
+/// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/include/linux/pid_namespace.h#23
+#[derive(Debug)]
+pub struct PidNamespace {
+    /// namespace的层级，init pid namespace为0
+    level: usize,
+    parent: Option<Arc<PidNamespace>>,
+    inum: usize,
+    inner: SpinLock<InnerPidNamespace>,
+}
+
+#[derive(Debug)]
+struct InnerPidNamespace {
+    /// 本namespace的pid分配器（init pid namespace不使用）
+    pid_allocator: Option<IdAllocator>,
+    /// 本namespace中的pid到全局pid的映射
+    pid_map: BTreeMap<Pid, Pid>,
+    /// 本namespace中的1号进程，负责收养本namespace中的孤儿进程
+    child_reaper: Weak<ProcessControlBlock>,
+    /// 1号进程退出后，不能再在本namespace中创建进程
+    dead: bool,
+}
+
+/// 进程在某一层pid namespace中的pid
+///
+/// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/include/linux/pid.h#54
+#[derive(Debug, Clone)]
+pub struct UPid {
+    pub nr: Pid,
+    pub ns: Arc<PidNamespace>,
+}
+
+impl PidNamespace {
+    fn new_init() -> Arc<Self> {
+        Arc::new(Self {
+            level: 0,
+            parent: None,
+            inum: alloc_ns_inum(),
+            inner: SpinLock::new(InnerPidNamespace {
+                pid_allocator: None,
+                pid_map: BTreeMap::new(),
+                child_reaper: Weak::new(),
+                dead: false,
+            }),
+        })
+    }
+
+    /// 创建一个以当前namespace为父namespace的pid namespace
+    ///
+    /// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/kernel/pid_namespace.c#72
+    pub fn create_child(self: &Arc<Self>) -> Result<Arc<Self>, SystemError> {
+        let level = self.level + 1;
+        if level > MAX_PID_NS_LEVEL {
+            return Err(SystemError::ENOSPC);
+        }
+
+        Ok(Arc::new(Self {
+            level,
+            parent: Some(self.clone()),
+            inum: alloc_ns_inum(),
+            inner: SpinLock::new(InnerPidNamespace {
+                pid_allocator: IdAllocator::new(1, PID_NS_PID_MAX),
+                pid_map: BTreeMap::new(),
+                child_reaper: Weak::new(),
+                dead: false,
+            }),
+        }))
+    }
+
+    pub fn level(&self) -> usize {
+        self.level
+    }
+
+    pub fn parent(&self) -> Option<Arc<PidNamespace>> {
+        self.parent.clone()
+    }
+
+    pub fn inum(&self) -> usize {
+        self.inum
+    }
+
+    /// 本namespace的1号进程
+    pub fn child_reaper(&self) -> Option<Arc<ProcessControlBlock>> {
+        if self.level == 0 {
+            return ProcessManager::find(Pid(1));
+        }
+        self.inner.lock_irqsave().child_reaper.upgrade()
+    }
+
+    /// 判断`other`是否为当前namespace或者其子孙namespace
+    pub fn is_ancestor_of(self: &Arc<Self>, other: &Arc<PidNamespace>) -> bool {
+        let mut ns = Some(other.clone());
+        while let Some(cur) = ns {
+            if cur.level < self.level {
+                return false;
+            }
+            if Arc::ptr_eq(&cur, self) {
+                return true;
+            }
+            ns = cur.parent.clone();
+        }
+        return false;
+    }
+
+    /// 将本namespace中的pid转换为全局pid
+    pub fn global_pid(&self, nr: Pid) -> Option<Pid> {
+        if self.level == 0 {
+            return Some(nr);
+        }
+        self.inner.lock_irqsave().pid_map.get(&nr).cloned()
+    }
+
+    /// 本namespace（包括子孙namespace）中所有进程的全局pid
+    pub fn global_pids(&self) -> Vec<Pid> {
+        self.inner
+            .lock_irqsave()
+            .pid_map
+            .values()
+            .cloned()
+            .collect()
+    }
+
+    /// 在本namespace中为全局pid为`global`的进程分配pid
+    ///
+    /// ## 参数
+    ///
+    /// - `global`: 进程的全局pid
+    /// - `set_tid`: 要求分配的pid，为None时自动分配
+    fn alloc_nr(&self, global: Pid, set_tid: Option<Pid>) -> Result<Pid, SystemError> {
+        let mut inner = self.inner.lock_irqsave();
+        if inner.dead {
+            return Err(SystemError::ENOMEM);
+        }
+        let nr = match set_tid {
+            Some(nr) => {
+                if nr.data() == 0 || nr.data() >= PID_NS_PID_MAX {
+                    return Err(SystemError::EINVAL);
+                }
+                // 1号进程创建之前，只能指定pid为1
+                if nr.data() != 1 && inner.child_reaper.upgrade().is_none() {
+                    return Err(SystemError::EINVAL);
+                }
+                inner
+                    .pid_allocator
+                    .as_mut()
+                    .unwrap()
+                    .alloc_specific(nr.data())
+                    .ok_or(SystemError::EEXIST)?
+            }
+            None => inner
+                .pid_allocator
+                .as_mut()
+                .unwrap()
+                .alloc()
+                .ok_or(SystemError::EAGAIN_OR_EWOULDBLOCK)?,
+        };
+        inner.pid_map.insert(Pid(nr), global);
+        return Ok(Pid(nr));
+    }
+
+    fn free_nr(&self, nr: Pid) {
+        let mut inner = self.inner.lock_irqsave();
+        inner.pid_map.remove(&nr);
+        inner.pid_allocator.as_mut().unwrap().free(nr.data());
+    }
+}
+
+impl ProcessControlBlock {
+    /// 进程当前所在的pid namespace
+    ///
+    /// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/kernel/pid.c#496
+    pub fn active_pid_ns(&self) -> Arc<PidNamespace> {
+        self.upids.read_irqsave().last().unwrap().ns.clone()
+    }
+
+    /// 进程在指定pid namespace中的pid，若进程在该namespace中不可见，则返回None
+    pub fn pid_nr_ns(&self, ns: &Arc<PidNamespace>) -> Option<Pid> {
+        let upids = self.upids.read_irqsave();
+        let upid = upids.get(ns.level)?;
+        if Arc::ptr_eq(&upid.ns, ns) {
+            return Some(upid.nr);
+        }
+        return None;
+    }
+
+    /// 从当前进程的角度看到的pid，若不可见则为0
+    ///
+    /// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/kernel/pid.c#507
+    pub fn pid_vnr(&self) -> Pid {
+        let ns = ProcessManager::current_pcb().active_pid_ns();
+        self.pid_nr_ns(&ns).unwrap_or(Pid(0))
+    }
+
+    /// 从当前进程的角度看到的线程组id，若不可见则为0
+    pub fn tgid_vnr(self: &Arc<Self>) -> Pid {
+        self.thread_group_leader().pid_vnr()
+    }
+
+    /// 在`ns`及其所有非init的祖先namespace中为进程分配pid
+    ///
+    /// `set_tid[i]`为进程在第`ns.level - i`层namespace中要求分配的pid（来自clone3）。
+    /// 全局pid在创建pcb时就已经分配，因此不能为init pid namespace指定pid。
+    ///
+    /// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/kernel/pid.c#161
+    pub(super) fn attach_pid_ns(
+        self: &Arc<Self>,
+        ns: &Arc<PidNamespace>,
+        set_tid: &[Pid],
+    ) -> Result<(), SystemError> {
+        if set_tid.len() > ns.level + 1 {
+            return Err(SystemError::EINVAL);
+        }
+        if set_tid.len() == ns.level + 1 && set_tid[ns.level] != self.pid() {
+            return Err(SystemError::EINVAL);
+        }
+
+        let init_upid = UPid {
+            nr: self.pid(),
+            ns: INIT_PID_NS.clone(),
+        };
+        let mut upids = vec![init_upid; ns.level + 1];
+
+        let mut cur = ns.clone();
+        while cur.level > 0 {
+            let tid = set_tid.get(ns.level - cur.level).cloned();
+            match cur.alloc_nr(self.pid(), tid) {
+                Ok(nr) => {
+                    upids[cur.level] = UPid {
+                        nr,
+                        ns: cur.clone(),
+                    }
+                }
+                Err(e) => {
+                    for upid in upids.iter().skip(cur.level + 1) {
+                        upid.ns.free_nr(upid.nr);
+                    }
+                    return Err(e);
+                }
+            }
+            cur = cur.parent.clone().unwrap();
+        }
+
+        // 新namespace中的第一个进程成为该namespace的1号进程
+        if ns.level > 0 && upids[ns.level].nr == Pid(1) {
+            ns.inner.lock_irqsave().child_reaper = Arc::downgrade(self);
+        }
+
+        *self.upids.write_irqsave() = upids;
+        return Ok(());
+    }
+
+    /// 释放进程在所有非init pid namespace中的pid
+    pub(super) fn detach_pid_ns(&self) {
+        let mut upids = self.upids.write_irqsave();
+        for upid in upids.drain(1..) {
+            upid.ns.free_nr(upid.nr);
+        }
+    }
+
+    /// 当前进程是否为其所在pid namespace的1号进程
+    pub fn is_child_reaper(self: &Arc<Self>) -> bool {
+        let ns = self.active_pid_ns();
+        ns.level > 0
+            && ns
+                .child_reaper()
+                .map(|reaper| Arc::ptr_eq(&reaper, self))
+                .unwrap_or(false)
+    }
+
+    /// pid namespace的1号进程退出时，杀死该namespace中的所有进程，
+    /// 并禁止在该namespace中继续创建进程
+    ///
+    /// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/kernel/pid_namespace.c#189
+    pub(super) fn zap_pid_ns_processes(self: &Arc<Self>) {
+        let ns = self.active_pid_ns();
+        ns.inner.lock_irqsave().dead = true;
+        for pid in ns.global_pids() {
+            if pid == self.pid() {
+                continue;
+            }
+            let sig = Signal::SIGKILL;
+            let mut info = SigInfo::new(sig, 0, SigCode::Kernel, SigType::Kill(self.pid()));
+            sig.send_signal_info(Some(&mut info), pid).ok();
+        }
+    }
+}
+
+impl ProcessManager {
+    /// 根据当前进程所在pid namespace中的pid查找进程
+    ///
+    /// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/kernel/pid.c#421
+    pub fn find_vpid(nr: Pid) -> Option<Arc<ProcessControlBlock>> {
+        let ns = ProcessManager::current_pcb().active_pid_ns();
+        let pid = ns.global_pid(nr)?;
+        return ProcessManager::find(pid);
+    }
+}
@@ -0,0 +1,275 @@
+//! pidfd：引用一个进程的文件描述符
+//!
+//! 进程退出后，pidfd变为可读，因此可以通过epoll同时监听多个进程的退出。
+//! 进程被回收后，pidfd还会报告EPOLLHUP。
+//!
+//! 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/kernel/pid.c#594
+
+use core::{any::Any, ffi::c_int};
+
+use alloc::{
+    string::String,
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+use system_error::SystemError;
+
+use crate::{
+    arch::ipc::signal::{SigCode, Signal},
+    filesystem::vfs::{
+        file::{File, FileMode},
+        syscall::ModeType,
+        FilePrivateData, FileSystem, FileType, IndexNode, Metadata,
+    },
+    ipc::signal_types::{SigInfo, SigType},
+    libs::spinlock::{SpinLock, SpinLockGuard},
+    net::event_poll::{EPollEventType, EPollItem, EventPoll, KernelIoctlData},
+    syscall::Syscall,
+};
+
+use super::{Pid, ProcessControlBlock, ProcessManager};
+
+bitflags! {
+    pub struct PidFdFlags: u32 {
+        /// 以非阻塞的方式打开pidfd，对其进行waitid时不会阻塞
+        const PIDFD_NONBLOCK = 0o0004000;
+    }
+}
+
+#[derive(Debug)]
+pub struct PidFdInode {
+    /// 进程的全局pid
+    pid: Pid,
+    pcb: Weak<ProcessControlBlock>,
+}
+
+impl PidFdInode {
+    pub fn new(pcb: &Arc<ProcessControlBlock>) -> Self {
+        Self {
+            pid: pcb.pid(),
+            pcb: Arc::downgrade(pcb),
+        }
+    }
+
+    /// pidfd引用的进程的全局pid
+    pub fn pid(&self) -> Pid {
+        self.pid
+    }
+
+    /// pidfd引用的、尚未退出的进程
+    ///
+    /// 进程已经被回收，或者已经退出（僵尸态）时返回None
+    pub fn target(&self) -> Option<Arc<ProcessControlBlock>> {
+        let pcb = self.pcb.upgrade()?;
+        if pcb
+            .sched_info()
+            .inner_lock_read_irqsave()
+            .state()
+            .is_exited()
+        {
+            return None;
+        }
+        Some(pcb)
+    }
+
+    pub fn remove_epoll(&self, epoll: &Weak<SpinLock<EventPoll>>) -> Result<(), SystemError> {
+        let pcb = self.pcb.upgrade().ok_or(SystemError::ENOENT)?;
+        let is_remove = !pcb
+            .pidfd_epitems
+            .lock_irqsave()
+            .extract_if(|x| x.epoll().ptr_eq(epoll))
+            .collect::<Vec<_>>()
+            .is_empty();
+
+        if is_remove {
+            return Ok(());
+        }
+
+        Err(SystemError::ENOENT)
+    }
+}
+
+impl IndexNode for PidFdInode {
+    fn open(
+        &self,
+        _data: SpinLockGuard<FilePrivateData>,
+        _mode: &FileMode,
+    ) -> Result<(), SystemError> {
+        Ok(())
+    }
+
+    fn close(&self, _data: SpinLockGuard<FilePrivateData>) -> Result<(), SystemError> {
+        Ok(())
+    }
+
+    fn read_at(
+        &self,
+        _offset: usize,
+        _len: usize,
+        _buf: &mut [u8],
+        _data: SpinLockGuard<FilePrivateData>,
+    ) -> Result<usize, SystemError> {
+        Err(SystemError::EINVAL)
+    }
+
+    fn write_at(
+        &self,
+        _offset: usize,
+        _len: usize,
+        _buf: &[u8],
+        _data: SpinLockGuard<FilePrivateData>,
+    ) -> Result<usize, SystemError> {
+        Err(SystemError::EINVAL)
+    }
+
+    /// # 检查pidfd的状态
+    ///
+    /// 进程已经退出或者已经被回收时，pidfd可读，并报告EPOLLHUP。
+    /// 这里使用pidfd持有的pcb，而不是通过pid查找，因为pid在进程被回收后可能被复用
+    fn poll(&self, _private_data: &FilePrivateData) -> Result<usize, SystemError> {
+        let mut events = EPollEventType::empty();
+        if self.target().is_none() {
+            events |=
+                EPollEventType::EPOLLIN | EPollEventType::EPOLLRDNORM | EPollEventType::EPOLLHUP;
+        }
+        return Ok(events.bits() as usize);
+    }
+
+    fn metadata(&self) -> Result<Metadata, SystemError> {
+        let meta = Metadata {
+            mode: ModeType::from_bits_truncate(0o600),
+            file_type: FileType::File,
+            ..Default::default()
+        };
+        Ok(meta)
+    }
+
+    fn kernel_ioctl(
+        &self,
+        arg: Arc<dyn KernelIoctlData>,
+        _data: &FilePrivateData,
+    ) -> Result<usize, SystemError> {
+        let epitem = arg
+            .arc_any()
+            .downcast::<EPollItem>()
+            .map_err(|_| SystemError::EFAULT)?;
+        // 进程已经被回收时，pcb不再会产生事件，poll的结果足以反映其状态
+        if let Some(pcb) = self.pcb.upgrade() {
+            pcb.pidfd_epitems.lock_irqsave().push_back(epitem);
+        }
+        Ok(0)
+    }
+
+    fn fs(&self) -> Arc<dyn FileSystem> {
+        panic!("PidFd does not have a filesystem")
+    }
+
+    fn as_any_ref(&self) -> &dyn Any {
+        self
+    }
+
+    fn list(&self) -> Result<Vec<String>, SystemError> {
+        Err(SystemError::ENOTDIR)
+    }
+}
+
+/// 为进程创建一个pidfd，并将其加入当前进程的文件描述符表
+///
+/// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/kernel/pid.c#556
+pub fn pidfd_create(pcb: &Arc<ProcessControlBlock>, flags: PidFdFlags) -> Result<i32, SystemError> {
+    let mut mode = FileMode::O_RDWR | FileMode::O_CLOEXEC;
+    if flags.contains(PidFdFlags::PIDFD_NONBLOCK) {
+        mode |= FileMode::O_NONBLOCK;
+    }
+    let file = File::new(Arc::new(PidFdInode::new(pcb)), mode)?;
+    let binding = ProcessManager::current_pcb().fd_table();
+    let mut fd_table_guard = binding.write();
+    return fd_table_guard.alloc_fd(file, None);
+}
+
+/// 获取pidfd所引用的进程的全局pid，以及pidfd的打开模式
+pub fn pidfd_get_pid(pidfd: i32) -> Result<(Pid, FileMode), SystemError> {
+    let file = ProcessManager::current_pcb()
+        .fd_table()
+        .read()
+        .get_file_by_fd(pidfd)
+        .ok_or(SystemError::EBADF)?;
+    let inode = file.inode();
+    let pidfd_inode = inode
+        .downcast_ref::<PidFdInode>()
+        .ok_or(SystemError::EBADF)?;
+    return Ok((pidfd_inode.pid(), file.mode()));
+}
+
+impl Syscall {
+    /// # 为进程创建一个pidfd
+    ///
+    /// ## 参数
+    /// - `pid`: 进程在当前pid namespace中的pid，必须是线程组的leader
+    /// - `flags`: 只支持PIDFD_NONBLOCK
+    ///
+    /// ## 返回值
+    /// - `Ok(usize)`: 新的文件描述符，它总是带有close-on-exec标志
+    ///
+    /// See: https://man7.org/linux/man-pages/man2/pidfd_open.2.html
+    pub fn pidfd_open(pid: i32, flags: u32) -> Result<usize, SystemError> {
+        let flags = PidFdFlags::from_bits(flags).ok_or(SystemError::EINVAL)?;
+        if pid <= 0 {
+            return Err(SystemError::EINVAL);
+        }
+        let pcb = ProcessManager::find_vpid(Pid::new(pid as usize)).ok_or(SystemError::ESRCH)?;
+        if pcb.tgid() != pcb.pid() {
+            return Err(SystemError::EINVAL);
+        }
+        return pidfd_create(&pcb, flags).map(|fd| fd as usize);
+    }
+
+    /// # 向pidfd引用的进程发送信号
+    ///
+    /// ## 参数
+    /// - `pidfd`: pidfd文件描述符
+    /// - `sig`: 信号，为0时只检查权限
+    /// - `info`: 目前只支持为NULL
+    /// - `flags`: 必须为0
+    ///
+    /// See: https://man7.org/linux/man-pages/man2/pidfd_send_signal.2.html
+    pub fn pidfd_send_signal(
+        pidfd: i32,
+        sig: c_int,
+        info: usize,
+        flags: u32,
+    ) -> Result<usize, SystemError> {
+        if flags != 0 {
+            return Err(SystemError::EINVAL);
+        }
+        // TODO: 支持由用户指定siginfo
+        if info != 0 {
+            return Err(SystemError::EINVAL);
+        }
+
+        let file = ProcessManager::current_pcb()
+            .fd_table()
+            .read()
+            .get_file_by_fd(pidfd)
+            .ok_or(SystemError::EBADF)?;
+        let inode = file.inode();
+        let pcb = inode
+            .downcast_ref::<PidFdInode>()
+            .ok_or(SystemError::EBADF)?
+            .target()
+            .ok_or(SystemError::ESRCH)?;
+        Self::check_kill_permission(&pcb)?;
+        if sig == 0 {
+            return Ok(0);
+        }
+
+        let sig = Signal::from(sig);
+        if sig == Signal::INVALID {
+            return Err(SystemError::EINVAL);
+        }
+        let mut info = SigInfo::new(sig, 0, SigCode::User, SigType::Kill(pcb.pid()));
+        return sig
+            .send_signal_info_to_pcb(Some(&mut info), pcb)
+            .map(|x| x as usize);
+    }
+}
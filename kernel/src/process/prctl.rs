@@ -0,0 +1,271 @@
+use core::sync::atomic::Ordering;
+
+use alloc::sync::Arc;
+use num_traits::FromPrimitive;
+use system_error::SystemError;
+
+use crate::{
+    arch::ipc::signal::{Signal, MAX_SIG_NUM},
+    syscall::{
+        user_access::{check_and_clone_cstr, UserBufferWriter},
+        Syscall,
+    },
+};
+
+use super::{cred::CAPFlags, ProcessControlBlock, ProcessManager};
+
+/// 进程名（comm）的最大长度，包括结尾的'\0'
+pub const TASK_COMM_LEN: usize = 16;
+
+/// 进程不允许产生core dump
+pub const SUID_DUMP_DISABLE: u8 = 0;
+/// 进程允许产生core dump
+pub const SUID_DUMP_USER: u8 = 1;
+
+/// prctl系统调用支持的操作
+///
+/// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/include/uapi/linux/prctl.h
+#[derive(Debug, Clone, Copy, PartialEq, Eq, FromPrimitive)]
+pub enum PrctlOption {
+    /// 设置父进程退出时，当前进程收到的信号
+    SetPdeathsig = 1,
+    GetPdeathsig = 2,
+    GetDumpable = 3,
+    SetDumpable = 4,
+    /// 所有uid都从0变为非0时，是否保留permitted集合中的capability
+    GetKeepCaps = 7,
+    SetKeepCaps = 8,
+    /// 设置当前线程的名字
+    SetName = 15,
+    GetName = 16,
+    /// 获取或设置当前线程的seccomp模式
+    GetSeccomp = 21,
+    SetSeccomp = 22,
+    /// 读取bounding集合中是否包含某个capability
+    CapbsetRead = 23,
+    /// 从bounding集合中移除某个capability
+    CapbsetDrop = 24,
+    /// 设置当前进程为子孙进程的收养者
+    SetChildSubreaper = 36,
+    GetChildSubreaper = 37,
+    /// 禁止当前进程及其子进程通过execve获得新的特权
+    SetNoNewPrivs = 38,
+    GetNoNewPrivs = 39,
+    /// 获取set_tid_address设置的地址
+    GetTidAddress = 40,
+    /// 读取或修改ambient集合
+    CapAmbient = 47,
+}
+
+/// PR_CAP_AMBIENT的子操作
+#[derive(Debug, Clone, Copy, PartialEq, Eq, FromPrimitive)]
+pub enum PrctlCapAmbient {
+    IsSet = 1,
+    Raise = 2,
+    Lower = 3,
+    ClearAll = 4,
+}
+
+impl ProcessControlBlock {
+    /// 线程组共享的属性保存在线程组leader的pcb中
+    pub(super) fn thread_group_leader(self: &Arc<Self>) -> Arc<Self> {
+        self.thread
+            .read_irqsave()
+            .group_leader()
+            .unwrap_or_else(|| self.clone())
+    }
+
+    /// 父进程退出时，当前进程会收到的信号
+    pub fn pdeath_signal(&self) -> Signal {
+        self.pdeath_signal.load(Ordering::SeqCst)
+    }
+
+    pub fn dumpable(&self) -> u8 {
+        self.dumpable.load(Ordering::SeqCst)
+    }
+
+    pub fn set_dumpable(&self, dumpable: u8) {
+        self.dumpable.store(dumpable, Ordering::SeqCst);
+    }
+
+    /// 当前进程是否为子孙进程的收养者
+    pub fn is_child_subreaper(self: &Arc<Self>) -> bool {
+        self.thread_group_leader()
+            .child_subreaper
+            .load(Ordering::SeqCst)
+    }
+
+    pub fn no_new_privs(&self) -> bool {
+        self.no_new_privs.load(Ordering::SeqCst)
+    }
+
+    /// 设置no_new_privs，它一旦设置就不能被清除
+    pub fn set_no_new_privs(&self) {
+        self.no_new_privs.store(true, Ordering::SeqCst);
+    }
+}
+
+impl Syscall {
+    /// # 对进程进行控制
+    ///
+    /// ## 参数
+    ///
+    /// - `option`: 操作类型
+    /// - `arg2`..`arg5`: 操作的参数，含义取决于`option`
+    ///
+    /// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/kernel/sys.c#2393
+    pub fn prctl(
+        option: usize,
+        arg2: usize,
+        arg3: usize,
+        arg4: usize,
+        arg5: usize,
+    ) -> Result<usize, SystemError> {
+        let option = PrctlOption::from_usize(option).ok_or(SystemError::EINVAL)?;
+        let pcb = ProcessManager::current_pcb();
+
+        match option {
+            PrctlOption::SetPdeathsig => {
+                if arg2 > MAX_SIG_NUM {
+                    return Err(SystemError::EINVAL);
+                }
+                pcb.pdeath_signal
+                    .store(Signal::from(arg2), Ordering::SeqCst);
+            }
+            PrctlOption::GetPdeathsig => {
+                let mut writer =
+                    UserBufferWriter::new(arg2 as *mut i32, core::mem::size_of::<i32>(), true)?;
+                writer.copy_one_to_user(&(pcb.pdeath_signal() as i32), 0)?;
+            }
+            PrctlOption::GetDumpable => {
+                return Ok(pcb.dumpable() as usize);
+            }
+            PrctlOption::SetDumpable => {
+                if arg2 != SUID_DUMP_DISABLE as usize && arg2 != SUID_DUMP_USER as usize {
+                    return Err(SystemError::EINVAL);
+                }
+                pcb.set_dumpable(arg2 as u8);
+            }
+            PrctlOption::GetKeepCaps => {
+                return Ok(pcb.cred.lock().keep_caps as usize);
+            }
+            PrctlOption::SetKeepCaps => {
+                if arg2 > 1 {
+                    return Err(SystemError::EINVAL);
+                }
+                pcb.cred.lock().keep_caps = arg2 == 1;
+            }
+            PrctlOption::SetName => {
+                let name = check_and_clone_cstr(arg2 as *const u8, Some(TASK_COMM_LEN - 1))?;
+                pcb.set_name(name.to_string_lossy().into_owned());
+            }
+            PrctlOption::GetName => {
+                let mut comm = [0u8; TASK_COMM_LEN];
+                let basic = pcb.basic();
+                let name = basic.name().as_bytes();
+                let len = name.len().min(TASK_COMM_LEN - 1);
+                comm[..len].copy_from_slice(&name[..len]);
+                drop(basic);
+
+                let mut writer = UserBufferWriter::new(arg2 as *mut u8, TASK_COMM_LEN, true)?;
+                writer.copy_to_user(&comm, 0)?;
+            }
+            PrctlOption::GetSeccomp => {
+                return Self::prctl_get_seccomp();
+            }
+            PrctlOption::SetSeccomp => {
+                return Self::prctl_set_seccomp(arg2, arg3);
+            }
+            PrctlOption::CapbsetRead => {
+                let cap = CAPFlags::from_cap(arg2).ok_or(SystemError::EINVAL)?;
+                return Ok(pcb.cred.lock().cap_bset.contains(cap) as usize);
+            }
+            PrctlOption::CapbsetDrop => {
+                let cap = CAPFlags::from_cap(arg2).ok_or(SystemError::EINVAL)?;
+                let mut cred = pcb.cred.lock();
+                if !cred.has_capability(CAPFlags::CAP_SETPCAP) {
+                    return Err(SystemError::EPERM);
+                }
+                cred.cap_bset.remove(cap);
+            }
+            PrctlOption::SetChildSubreaper => {
+                pcb.thread_group_leader()
+                    .child_subreaper
+                    .store(arg2 != 0, Ordering::SeqCst);
+            }
+            PrctlOption::GetChildSubreaper => {
+                let mut writer =
+                    UserBufferWriter::new(arg2 as *mut i32, core::mem::size_of::<i32>(), true)?;
+                writer.copy_one_to_user(&(pcb.is_child_subreaper() as i32), 0)?;
+            }
+            PrctlOption::SetNoNewPrivs => {
+                // no_new_privs一旦设置就不能被清除
+                if arg2 != 1 || arg3 != 0 || arg4 != 0 || arg5 != 0 {
+                    return Err(SystemError::EINVAL);
+                }
+                pcb.set_no_new_privs();
+            }
+            PrctlOption::GetNoNewPrivs => {
+                if arg2 != 0 || arg3 != 0 || arg4 != 0 || arg5 != 0 {
+                    return Err(SystemError::EINVAL);
+                }
+                return Ok(pcb.no_new_privs() as usize);
+            }
+            PrctlOption::GetTidAddress => {
+                let addr = pcb
+                    .thread
+                    .read_irqsave()
+                    .clear_child_tid
+                    .map(|addr| addr.data())
+                    .unwrap_or(0);
+                let mut writer =
+                    UserBufferWriter::new(arg2 as *mut usize, core::mem::size_of::<usize>(), true)?;
+                writer.copy_one_to_user(&addr, 0)?;
+            }
+            PrctlOption::CapAmbient => {
+                return Self::prctl_cap_ambient(&pcb, arg2, arg3, arg4, arg5);
+            }
+        }
+
+        return Ok(0);
+    }
+
+    /// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/security/commoncap.c#1343
+    fn prctl_cap_ambient(
+        pcb: &Arc<ProcessControlBlock>,
+        op: usize,
+        cap: usize,
+        arg4: usize,
+        arg5: usize,
+    ) -> Result<usize, SystemError> {
+        let op = PrctlCapAmbient::from_usize(op).ok_or(SystemError::EINVAL)?;
+        let mut cred = pcb.cred.lock();
+
+        if op == PrctlCapAmbient::ClearAll {
+            if cap != 0 || arg4 != 0 || arg5 != 0 {
+                return Err(SystemError::EINVAL);
+            }
+            cred.cap_ambient = CAPFlags::CAP_EMPTY_SET;
+            return Ok(0);
+        }
+
+        if arg4 != 0 || arg5 != 0 {
+            return Err(SystemError::EINVAL);
+        }
+        let cap = CAPFlags::from_cap(cap).ok_or(SystemError::EINVAL)?;
+        match op {
+            PrctlCapAmbient::IsSet => return Ok(cred.cap_ambient.contains(cap) as usize),
+            PrctlCapAmbient::Raise => {
+                // 只能加入同时存在于permitted与inheritable集合中的capability
+                if !(cred.cap_permitted & cred.cap_inheritable).contains(cap) {
+                    return Err(SystemError::EPERM);
+                }
+                cred.cap_ambient.insert(cap);
+            }
+            PrctlCapAmbient::Lower => cred.cap_ambient.remove(cap),
+            PrctlCapAmbient::ClearAll => unreachable!(),
+        }
+
+        return Ok(0);
+    }
+}
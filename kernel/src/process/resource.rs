@@ -1,9 +1,26 @@
+use alloc::collections::BTreeMap;
 use num_traits::FromPrimitive;
 use system_error::SystemError;
 
-use crate::time::PosixTimeSpec;
+use crate::{
+    arch::ipc::signal::{SigCode, Signal},
+    filesystem::vfs::file::FileDescriptorVec,
+    ipc::signal_types::{SigInfo, SigType},
+    libs::spinlock::SpinLock,
+    mm::ucontext::UserStack,
+    time::{PosixTimeSpec, NSEC_PER_SEC},
+};
 
-use super::ProcessControlBlock;
+use super::{
+    capability::capable,
+    cred::{CAPFlags, Kuid},
+    ProcessControlBlock, ProcessManager,
+};
+
+/// 表示资源没有限制
+pub const RLIM_INFINITY: u64 = u64::MAX;
+/// 资源限制的种类数
+pub const RLIM_NLIMITS: usize = RLimitID::Nlimits as usize;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
 #[repr(C)]
@@ -84,6 +101,16 @@ pub struct RLimit64 {
     pub rlim_max: u64,
 }
 
+impl RLimit64 {
+    pub const fn new(rlim_cur: u64, rlim_max: u64) -> Self {
+        Self { rlim_cur, rlim_max }
+    }
+
+    pub const fn infinity() -> Self {
+        Self::new(RLIM_INFINITY, RLIM_INFINITY)
+    }
+}
+
 /// Resource limit IDs
 ///
 /// ## Note
@@ -137,7 +164,219 @@ impl TryFrom<usize> for RLimitID {
     }
 }
 
+/// 进程的资源限制表
+///
+/// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/include/asm-generic/resource.h#17
+#[derive(Debug, Clone, Copy)]
+pub struct RLimits {
+    limits: [RLimit64; RLIM_NLIMITS],
+}
+
+impl Default for RLimits {
+    /// 1号进程的资源限制，其它进程都从它继承
+    fn default() -> Self {
+        let mut limits = [RLimit64::infinity(); RLIM_NLIMITS];
+        limits[RLimitID::Stack as usize] =
+            RLimit64::new(UserStack::DEFAULT_USER_STACK_SIZE as u64, RLIM_INFINITY);
+        limits[RLimitID::Core as usize] = RLimit64::new(0, RLIM_INFINITY);
+        limits[RLimitID::Nofile as usize] = RLimit64::new(
+            FileDescriptorVec::PROCESS_MAX_FD as u64,
+            FileDescriptorVec::PROCESS_MAX_FD as u64,
+        );
+        limits[RLimitID::Memlock as usize] = RLimit64::new(8 * 1024 * 1024, 8 * 1024 * 1024);
+        limits[RLimitID::Msgqueue as usize] = RLimit64::new(819200, 819200);
+        limits[RLimitID::Nice as usize] = RLimit64::new(0, 0);
+        limits[RLimitID::Rtprio as usize] = RLimit64::new(0, 0);
+        Self { limits }
+    }
+}
+
+impl RLimits {
+    pub fn get(&self, id: RLimitID) -> RLimit64 {
+        self.limits[id as usize]
+    }
+
+    pub fn set(&mut self, id: RLimitID, limit: RLimit64) {
+        self.limits[id as usize] = limit;
+    }
+}
+
+/// 每个用户（按实际uid）拥有的进程（线程）数，用于检查RLIMIT_NPROC
+///
+/// 进程加入进程表时计入，被回收时撤销；修改实际uid时在两个用户之间转移。
+///
+/// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/kernel/ucount.c
+static USER_PROCESSES: SpinLock<BTreeMap<Kuid, usize>> = SpinLock::new(BTreeMap::new());
+
+impl ProcessManager {
+    /// 实际uid为`uid`的进程（线程）数量
+    pub fn nr_processes_of(uid: Kuid) -> usize {
+        USER_PROCESSES
+            .lock_irqsave()
+            .get(&uid)
+            .copied()
+            .unwrap_or(0)
+    }
+
+    pub(super) fn inc_user_processes(uid: Kuid) {
+        *USER_PROCESSES.lock_irqsave().entry(uid).or_insert(0) += 1;
+    }
+
+    pub(super) fn dec_user_processes(uid: Kuid) {
+        let mut guard = USER_PROCESSES.lock_irqsave();
+        if let Some(count) = guard.get_mut(&uid) {
+            *count -= 1;
+            if *count == 0 {
+                guard.remove(&uid);
+            }
+        }
+    }
+
+    /// 当前进程的实际uid由`old`变为`new`时，把它从原来的用户转移到新的用户名下
+    pub(super) fn switch_user_processes(old: Kuid, new: Kuid) {
+        if old != new {
+            Self::dec_user_processes(old);
+            Self::inc_user_processes(new);
+        }
+    }
+}
+
 impl ProcessControlBlock {
+    /// 访问进程的资源限制表
+    ///
+    /// 资源限制由整个线程组共享，因此统一保存在线程组leader的pcb中
+    fn with_rlimits<R>(&self, f: impl FnOnce(&mut RLimits) -> R) -> R {
+        let leader = self.thread.read_irqsave().group_leader();
+        match leader {
+            Some(leader) if leader.pid() != self.pid() => f(&mut leader.rlimits.lock_irqsave()),
+            _ => f(&mut self.rlimits.lock_irqsave()),
+        }
+    }
+
+    /// 获取进程的资源限制表的拷贝
+    pub fn rlimits(&self) -> RLimits {
+        self.with_rlimits(|rlimits| *rlimits)
+    }
+
+    /// 获取指定资源的限制
+    pub fn rlimit(&self, id: RLimitID) -> RLimit64 {
+        self.with_rlimits(|rlimits| rlimits.get(id))
+    }
+
+    /// 获取指定资源的软限制
+    #[inline]
+    pub fn rlimit_cur(&self, id: RLimitID) -> u64 {
+        self.rlimit(id).rlim_cur
+    }
+
+    /// 读取并（可选地）修改进程的资源限制
+    ///
+    /// 提高硬限制需要当前进程具有特权
+    ///
+    /// ## 参数
+    ///
+    /// - `id`: 资源类型
+    /// - `new_limit`: 新的资源限制，为None时只读取
+    ///
+    /// ## 返回值
+    ///
+    /// 修改前的资源限制
+    ///
+    /// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/kernel/sys.c#1474
+    pub fn do_prlimit(
+        &self,
+        id: RLimitID,
+        new_limit: Option<RLimit64>,
+    ) -> Result<RLimit64, SystemError> {
+        if let Some(new_limit) = new_limit {
+            if new_limit.rlim_cur > new_limit.rlim_max {
+                return Err(SystemError::EINVAL);
+            }
+            if id == RLimitID::Nofile
+                && new_limit.rlim_max > FileDescriptorVec::PROCESS_MAX_FD as u64
+            {
+                return Err(SystemError::EPERM);
+            }
+        }
+
+        let privileged = capable(CAPFlags::CAP_SYS_RESOURCE);
+        self.with_rlimits(|rlimits| {
+            let old = rlimits.get(id);
+            if let Some(new_limit) = new_limit {
+                if new_limit.rlim_max > old.rlim_max && !privileged {
+                    return Err(SystemError::EPERM);
+                }
+                rlimits.set(id, new_limit);
+            }
+            Ok(old)
+        })
+    }
+
+    /// 检查进程占用的CPU时间是否超出RLIMIT_CPU（在时钟中断中调用）
+    ///
+    /// 超出软限制时发送SIGXCPU，并把软限制延后一秒，使得之后每秒都会再收到一次SIGXCPU；
+    /// 超出硬限制时发送SIGKILL。
+    ///
+    /// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/kernel/time/posix-cpu-timers.c#872
+    pub fn check_rlimit_cpu(&self) {
+        let limit = self.rlimit(RLimitID::Cpu);
+        if limit.rlim_cur == RLIM_INFINITY {
+            return;
+        }
+
+        let secs = self.with_group_cpu_time(|time| time.total()) / NSEC_PER_SEC as u64;
+        let sig = if secs >= limit.rlim_max {
+            Signal::SIGKILL
+        } else if secs >= limit.rlim_cur {
+            self.with_rlimits(|rlimits| {
+                rlimits.set(
+                    RLimitID::Cpu,
+                    RLimit64::new((limit.rlim_cur + 1).min(limit.rlim_max), limit.rlim_max),
+                )
+            });
+            Signal::SIGXCPU
+        } else {
+            return;
+        };
+
+        let mut info = SigInfo::new(sig, 0, SigCode::Kernel, SigType::Kill(self.pid()));
+        sig.send_signal_info(Some(&mut info), self.pid()).ok();
+    }
+
+    /// 检查写入后的文件大小是否超出RLIMIT_FSIZE
+    ///
+    /// ## 参数
+    ///
+    /// - `offset`: 写入的起始位置
+    /// - `len`: 写入的长度
+    ///
+    /// ## 返回值
+    ///
+    /// 允许写入的长度。如果起始位置已经超出限制，则向进程发送SIGXFSZ并返回EFBIG
+    pub fn check_rlimit_fsize(&self, offset: usize, len: usize) -> Result<usize, SystemError> {
+        let limit = self.rlimit_cur(RLimitID::Fsize);
+        // 写入的结束位置溢出时一定超出限制
+        let end = (offset as u64).saturating_add(len as u64);
+        if limit == RLIM_INFINITY || end <= limit {
+            return Ok(len);
+        }
+
+        if offset as u64 >= limit {
+            let mut info = SigInfo::new(
+                Signal::SIGXFSZ,
+                0,
+                SigCode::Kernel,
+                SigType::Kill(self.pid()),
+            );
+            Signal::SIGXFSZ
+                .send_signal_info(Some(&mut info), self.pid())
+                .ok();
+            return Err(SystemError::EFBIG);
+        }
+
+        Ok((limit - offset as u64) as usize)
+    }
+
     /// 获取进程资源使用情况
     ///
     /// ## TODO
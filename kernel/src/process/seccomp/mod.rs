@@ -0,0 +1,547 @@
+//! seccomp：限制进程能够使用的系统调用
+//!
+//! - strict模式：只允许read、write、exit与rt_sigreturn，调用其它系统调用的进程会被杀死
+//! - filter模式：每次系统调用前依次执行进程安装的所有BPF过滤器，根据优先级最高的返回值决定如何处理
+//!
+//! seccomp状态属于单个线程，在fork时被子进程继承、在execve后保留，并且一旦设置就不能撤销。
+//!
+//! 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/kernel/seccomp.c
+
+use alloc::{sync::Arc, vec::Vec};
+use cbpf::{
+    bpf_check_classic, bpf_run, SockFilter, SockFprog, BPF_A, BPF_ABS, BPF_ADD, BPF_ALU, BPF_AND,
+    BPF_DIV, BPF_IMM, BPF_JA, BPF_JEQ, BPF_JGE, BPF_JGT, BPF_JMP, BPF_JSET, BPF_K, BPF_LD, BPF_LDX,
+    BPF_LEN, BPF_LSH, BPF_MAXINSNS, BPF_MEM, BPF_MISC, BPF_MUL, BPF_NEG, BPF_OR, BPF_RET, BPF_RSH,
+    BPF_ST, BPF_STX, BPF_SUB, BPF_TAX, BPF_TXA, BPF_W, BPF_X, BPF_XOR,
+};
+use log::info;
+use num_traits::FromPrimitive;
+use system_error::SystemError;
+
+use crate::{
+    arch::{
+        ipc::signal::{SigCode, Signal},
+        syscall::nr::{SYS_EXIT, SYS_READ, SYS_RT_SIGRETURN, SYS_WRITE},
+    },
+    ipc::signal_types::{SigInfo, SigType},
+    libs::spinlock::SpinLock,
+    syscall::{user_access::UserBufferReader, Syscall},
+};
+
+use super::{capability::capable, cred::CAPFlags, ProcessControlBlock, ProcessManager};
+
+// seccomp过滤器的返回值，高16位表示动作，数值越小（按有符号数比较）优先级越高
+// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/include/uapi/linux/seccomp.h#38
+
+/// 杀死整个进程
+pub const SECCOMP_RET_KILL_PROCESS: u32 = 0x8000_0000;
+/// 杀死当前线程
+pub const SECCOMP_RET_KILL_THREAD: u32 = 0x0000_0000;
+/// 不执行系统调用，并向当前线程发送SIGSYS
+pub const SECCOMP_RET_TRAP: u32 = 0x0003_0000;
+/// 不执行系统调用，返回值的低16位作为错误码返回
+pub const SECCOMP_RET_ERRNO: u32 = 0x0005_0000;
+/// 交给用户态的监听者处理（暂不支持）
+pub const SECCOMP_RET_USER_NOTIF: u32 = 0x7fc0_0000;
+/// 交给ptrace的跟踪者处理
+pub const SECCOMP_RET_TRACE: u32 = 0x7ff0_0000;
+/// 记录日志后执行系统调用
+pub const SECCOMP_RET_LOG: u32 = 0x7ffc_0000;
+/// 执行系统调用
+pub const SECCOMP_RET_ALLOW: u32 = 0x7fff_0000;
+
+const SECCOMP_RET_ACTION_FULL: u32 = 0xffff_0000;
+const SECCOMP_RET_DATA: u32 = 0x0000_ffff;
+
+/// 最大的错误码
+const MAX_ERRNO: u32 = 4095;
+
+/// 一个线程上所有过滤器的指令总数上限，每个过滤器额外按4条指令计算
+const MAX_INSNS_PER_PATH: usize = (1 << 18) / core::mem::size_of::<SockFilter>();
+
+/// strict模式下允许的系统调用
+const MODE1_SYSCALLS: [usize; 4] = [SYS_READ, SYS_WRITE, SYS_EXIT, SYS_RT_SIGRETURN];
+
+/// seccomp_data中的架构号
+///
+/// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/include/uapi/linux/audit.h#383
+#[cfg(target_arch = "x86_64")]
+const AUDIT_ARCH_CURRENT: u32 = 0xc000_003e;
+#[cfg(target_arch = "riscv64")]
+const AUDIT_ARCH_CURRENT: u32 = 0xc000_00f3;
+
+/// 串行化过滤器的安装，保证SECCOMP_FILTER_FLAG_TSYNC在检查与同步线程时看到一致的状态
+static SECCOMP_INSTALL_LOCK: SpinLock<()> = SpinLock::new(());
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, FromPrimitive)]
+pub enum SeccompMode {
+    #[default]
+    Disabled = 0,
+    Strict = 1,
+    Filter = 2,
+}
+
+/// seccomp系统调用支持的操作
+#[derive(Debug, Clone, Copy, PartialEq, Eq, FromPrimitive)]
+enum SeccompOp {
+    SetModeStrict = 0,
+    SetModeFilter = 1,
+    /// 查询内核是否支持某个过滤器返回值
+    GetActionAvail = 2,
+}
+
+bitflags! {
+    pub struct SeccompFilterFlags: u32 {
+        /// 将过滤器同步到线程组中的所有线程
+        const TSYNC = 1 << 0;
+        /// 记录除SECCOMP_RET_ALLOW以外的所有动作
+        const LOG = 1 << 1;
+        const SPEC_ALLOW = 1 << 2;
+        const NEW_LISTENER = 1 << 3;
+        /// TSYNC失败时返回ESRCH，而不是失败线程的tid
+        const TSYNC_ESRCH = 1 << 4;
+        const WAIT_KILLABLE_RECV = 1 << 5;
+    }
+}
+
+/// 过滤器读取的系统调用信息
+///
+/// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/include/uapi/linux/seccomp.h#62
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+struct SeccompData {
+    nr: i32,
+    arch: u32,
+    instruction_pointer: u64,
+    args: [u64; 6],
+}
+
+impl SeccompData {
+    fn as_bytes(&self) -> &[u8] {
+        unsafe {
+            core::slice::from_raw_parts(
+                self as *const Self as *const u8,
+                core::mem::size_of::<Self>(),
+            )
+        }
+    }
+}
+
+/// 一个已经安装的过滤器，通过prev指针与之前安装的过滤器组成一棵树（fork后父子进程共享祖先）
+#[derive(Debug)]
+pub struct SeccompFilter {
+    prog: Vec<SockFilter>,
+    /// 是否记录除SECCOMP_RET_ALLOW以外的动作
+    log: bool,
+    prev: Option<Arc<SeccompFilter>>,
+}
+
+impl SeccompFilter {
+    /// 从当前过滤器开始，依次返回它自身及之前安装的所有过滤器
+    fn iter(self: &Arc<Self>) -> impl Iterator<Item = Arc<SeccompFilter>> {
+        core::iter::successors(Some(self.clone()), |f| f.prev.clone())
+    }
+}
+
+/// 线程的seccomp状态
+#[derive(Debug, Clone, Default)]
+pub struct Seccomp {
+    mode: SeccompMode,
+    /// 最近安装的过滤器
+    filter: Option<Arc<SeccompFilter>>,
+}
+
+impl Seccomp {
+    /// 是否可以切换到`mode`：seccomp一旦启用，就只能继续在同一模式下添加限制
+    fn may_assign_mode(&self, mode: SeccompMode) -> bool {
+        self.mode == SeccompMode::Disabled || self.mode == mode
+    }
+
+    /// `filter`是否为当前过滤器或其祖先（没有过滤器时视为任意过滤器的祖先）
+    fn filter_is_ancestor_of(&self, filter: &Option<Arc<SeccompFilter>>) -> bool {
+        match (&self.filter, filter) {
+            (None, _) => true,
+            (Some(_), None) => false,
+            (Some(mine), Some(other)) => other.iter().any(|f| Arc::ptr_eq(&f, mine)),
+        }
+    }
+}
+
+impl ProcessControlBlock {
+    pub fn seccomp_mode(&self) -> SeccompMode {
+        self.seccomp.lock().mode
+    }
+
+    /// fork时继承父进程的seccomp状态
+    ///
+    /// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/kernel/fork.c#1825
+    pub(super) fn copy_seccomp(&self, parent: &ProcessControlBlock) {
+        *self.seccomp.lock() = parent.seccomp.lock().clone();
+    }
+}
+
+/// 在执行系统调用之前检查seccomp规则
+///
+/// ## 返回值
+///
+/// - None: 允许执行该系统调用
+/// - Some(ret): 跳过该系统调用，并将`ret`作为它的返回值
+///
+/// 被seccomp杀死的线程不会从这个函数返回
+///
+/// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/kernel/seccomp.c#1360
+pub fn secure_computing(syscall_num: usize, args: &[usize], ip: usize) -> Option<usize> {
+    let pcb = ProcessManager::current_pcb();
+    let guard = pcb.seccomp.lock();
+    match guard.mode {
+        SeccompMode::Disabled => None,
+        SeccompMode::Strict => {
+            drop(guard);
+            if MODE1_SYSCALLS.contains(&syscall_num) {
+                return None;
+            }
+            info!(
+                "seccomp: pid {:?} killed by strict mode, syscall {}",
+                pcb.pid(),
+                syscall_num
+            );
+            drop(pcb);
+            ProcessManager::exit(Signal::SIGKILL as usize);
+        }
+        SeccompMode::Filter => {
+            let filter = guard.filter.clone()?;
+            drop(guard);
+            drop(pcb);
+            seccomp_filter(&filter, syscall_num, args, ip)
+        }
+    }
+}
+
+/// 执行所有过滤器，返回优先级最高的返回值以及产生它的过滤器是否要求记录日志
+///
+/// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/kernel/seccomp.c#400
+fn seccomp_run_filters(filter: &Arc<SeccompFilter>, sd: &SeccompData) -> (u32, bool) {
+    let action_only = |ret: u32| (ret & SECCOMP_RET_ACTION_FULL) as i32;
+    let mut ret = SECCOMP_RET_ALLOW;
+    let mut log = false;
+    for f in filter.iter() {
+        let cur = bpf_run(&f.prog, sd.as_bytes());
+        if action_only(cur) < action_only(ret) {
+            ret = cur;
+            log = f.log;
+        }
+    }
+    return (ret, log);
+}
+
+/// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/kernel/seccomp.c#1195
+fn seccomp_filter(
+    filter: &Arc<SeccompFilter>,
+    syscall_num: usize,
+    args: &[usize],
+    ip: usize,
+) -> Option<usize> {
+    let mut sd = SeccompData {
+        nr: syscall_num as i32,
+        arch: AUDIT_ARCH_CURRENT,
+        instruction_pointer: ip as u64,
+        args: [0; 6],
+    };
+    for (dst, src) in sd.args.iter_mut().zip(args) {
+        *dst = *src as u64;
+    }
+
+    let (ret, log) = seccomp_run_filters(filter, &sd);
+    let data = ret & SECCOMP_RET_DATA;
+    let action = ret & SECCOMP_RET_ACTION_FULL;
+    let pid = ProcessManager::current_pid();
+    if log || action == SECCOMP_RET_LOG {
+        info!(
+            "seccomp: pid {:?} syscall {} action {:#x}",
+            pid, syscall_num, ret
+        );
+    }
+
+    let enosys = SystemError::ENOSYS.to_posix_errno() as usize;
+    match action {
+        SECCOMP_RET_ALLOW | SECCOMP_RET_LOG => None,
+        SECCOMP_RET_ERRNO => Some((-(data.min(MAX_ERRNO) as i32)) as usize),
+        SECCOMP_RET_TRAP => {
+            let sig = Signal::SIGSYS;
+            let mut info = SigInfo::new(
+                sig,
+                data as i32,
+                SigCode::SysSeccomp,
+                SigType::SigSys {
+                    call_addr: ip,
+                    syscall: syscall_num as i32,
+                    arch: AUDIT_ARCH_CURRENT,
+                },
+            );
+            sig.send_signal_info(Some(&mut info), pid).ok();
+            Some(enosys)
+        }
+        // 没有ptrace跟踪者与用户态监听者时，系统调用返回ENOSYS
+        SECCOMP_RET_TRACE | SECCOMP_RET_USER_NOTIF => Some(enosys),
+        _ => {
+            info!(
+                "seccomp: pid {:?} killed, syscall {} action {:#x}",
+                pid, syscall_num, ret
+            );
+            if action != SECCOMP_RET_KILL_THREAD {
+                let tgid = ProcessManager::current_pcb().tgid();
+                for task in ProcessManager::thread_group_tasks(tgid) {
+                    if task.pid() != pid {
+                        let sig = Signal::SIGKILL;
+                        let mut info =
+                            SigInfo::new(sig, 0, SigCode::Kernel, SigType::Kill(task.pid()));
+                        sig.send_signal_info(Some(&mut info), task.pid()).ok();
+                    }
+                }
+            }
+            ProcessManager::exit(Signal::SIGSYS as usize);
+        }
+    }
+}
+
+/// seccomp过滤器中，除了读取seccomp_data的指令之外，允许使用的指令
+///
+/// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/kernel/seccomp.c#300
+const SECCOMP_ALLOWED_INSNS: [u16; 38] = [
+    BPF_RET | BPF_K,
+    BPF_RET | BPF_A,
+    BPF_ALU | BPF_ADD | BPF_K,
+    BPF_ALU | BPF_ADD | BPF_X,
+    BPF_ALU | BPF_SUB | BPF_K,
+    BPF_ALU | BPF_SUB | BPF_X,
+    BPF_ALU | BPF_MUL | BPF_K,
+    BPF_ALU | BPF_MUL | BPF_X,
+    BPF_ALU | BPF_DIV | BPF_K,
+    BPF_ALU | BPF_DIV | BPF_X,
+    BPF_ALU | BPF_AND | BPF_K,
+    BPF_ALU | BPF_AND | BPF_X,
+    BPF_ALU | BPF_OR | BPF_K,
+    BPF_ALU | BPF_OR | BPF_X,
+    BPF_ALU | BPF_XOR | BPF_K,
+    BPF_ALU | BPF_XOR | BPF_X,
+    BPF_ALU | BPF_LSH | BPF_K,
+    BPF_ALU | BPF_LSH | BPF_X,
+    BPF_ALU | BPF_RSH | BPF_K,
+    BPF_ALU | BPF_RSH | BPF_X,
+    BPF_ALU | BPF_NEG,
+    BPF_LD | BPF_IMM,
+    BPF_LDX | BPF_IMM,
+    BPF_MISC | BPF_TAX,
+    BPF_MISC | BPF_TXA,
+    BPF_LD | BPF_MEM,
+    BPF_LDX | BPF_MEM,
+    BPF_ST,
+    BPF_STX,
+    BPF_JMP | BPF_JA,
+    BPF_JMP | BPF_JEQ | BPF_K,
+    BPF_JMP | BPF_JEQ | BPF_X,
+    BPF_JMP | BPF_JGE | BPF_K,
+    BPF_JMP | BPF_JGE | BPF_X,
+    BPF_JMP | BPF_JGT | BPF_K,
+    BPF_JMP | BPF_JGT | BPF_X,
+    BPF_JMP | BPF_JSET | BPF_K,
+    BPF_JMP | BPF_JSET | BPF_X,
+];
+
+/// 在通用的cBPF检查之后，进一步限制seccomp过滤器能够使用的指令
+///
+/// 只能以4字节对齐的方式读取seccomp_data，读取数据长度的指令被替换为读取常数，
+/// 其他读取输入数据的指令（B/H大小、BPF_IND、BPF_MSH等）以及`SECCOMP_ALLOWED_INSNS`之外的指令都会被拒绝，
+/// 从而保证已经安装的过滤器在运行时不会因为越界读取而返回0（SECCOMP_RET_KILL_THREAD）
+///
+/// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/kernel/seccomp.c#278
+fn seccomp_check_filter(prog: &mut [SockFilter]) -> Result<(), SystemError> {
+    let data_len = core::mem::size_of::<SeccompData>() as u32;
+    for insn in prog.iter_mut() {
+        let code = insn.code;
+        if code == BPF_LD | BPF_W | BPF_ABS {
+            if insn.k >= data_len || insn.k & 3 != 0 {
+                return Err(SystemError::EINVAL);
+            }
+        } else if code == BPF_LD | BPF_W | BPF_LEN {
+            insn.code = BPF_LD | BPF_IMM;
+            insn.k = data_len;
+        } else if code == BPF_LDX | BPF_W | BPF_LEN {
+            insn.code = BPF_LDX | BPF_IMM;
+            insn.k = data_len;
+        } else if !SECCOMP_ALLOWED_INSNS.contains(&code) {
+            return Err(SystemError::EINVAL);
+        }
+    }
+    return Ok(());
+}
+
+/// 从用户空间读取并检查过滤器程序
+///
+/// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/kernel/seccomp.c#669
+fn seccomp_prepare_user_filter(uprog: *const SockFprog) -> Result<Vec<SockFilter>, SystemError> {
+    let reader = UserBufferReader::new(uprog, core::mem::size_of::<SockFprog>(), true)?;
+    let fprog = *reader.read_one_from_user::<SockFprog>(0)?;
+    let len = fprog.len as usize;
+    if len == 0 || len > BPF_MAXINSNS {
+        return Err(SystemError::EINVAL);
+    }
+
+    let reader =
+        UserBufferReader::new(fprog.filter, len * core::mem::size_of::<SockFilter>(), true)?;
+    let mut prog = reader.read_from_user::<SockFilter>(0)?.to_vec();
+    bpf_check_classic(&prog)?;
+    seccomp_check_filter(&mut prog)?;
+    return Ok(prog);
+}
+
+/// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/kernel/seccomp.c#1923
+fn seccomp_set_mode_strict() -> Result<usize, SystemError> {
+    let pcb = ProcessManager::current_pcb();
+    let mut seccomp = pcb.seccomp.lock();
+    if !seccomp.may_assign_mode(SeccompMode::Strict) {
+        return Err(SystemError::EINVAL);
+    }
+    seccomp.mode = SeccompMode::Strict;
+    return Ok(0);
+}
+
+/// # 为当前线程安装过滤器
+///
+/// 只有设置了no_new_privs或者拥有CAP_SYS_ADMIN的进程才能安装过滤器，
+/// 避免通过过滤器干扰set-user-ID程序的执行。
+///
+/// 使用SECCOMP_FILTER_FLAG_TSYNC时，线程组中其它线程的过滤器必须是当前线程过滤器的祖先，
+/// 否则返回第一个不满足条件的线程的tid（或者在设置了TSYNC_ESRCH时返回ESRCH）。
+///
+/// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/kernel/seccomp.c#1960
+fn seccomp_set_mode_filter(flags: u32, uprog: *const SockFprog) -> Result<usize, SystemError> {
+    let flags = SeccompFilterFlags::from_bits(flags).ok_or(SystemError::EINVAL)?;
+    // 暂不支持用户态通知
+    if flags.intersects(SeccompFilterFlags::NEW_LISTENER | SeccompFilterFlags::WAIT_KILLABLE_RECV) {
+        return Err(SystemError::EINVAL);
+    }
+
+    let prog = seccomp_prepare_user_filter(uprog)?;
+
+    let pcb = ProcessManager::current_pcb();
+    if !pcb.no_new_privs() && !capable(CAPFlags::CAP_SYS_ADMIN) {
+        return Err(SystemError::EACCES);
+    }
+
+    let _install_guard = SECCOMP_INSTALL_LOCK.lock();
+    let current = pcb.seccomp.lock().clone();
+    if !current.may_assign_mode(SeccompMode::Filter) {
+        return Err(SystemError::EINVAL);
+    }
+
+    let total_insns = current
+        .filter
+        .iter()
+        .flat_map(|f| f.iter())
+        .map(|f| f.prog.len() + 4)
+        .sum::<usize>()
+        + prog.len();
+    if total_insns > MAX_INSNS_PER_PATH {
+        return Err(SystemError::ENOMEM);
+    }
+
+    let threads = if flags.contains(SeccompFilterFlags::TSYNC) {
+        let threads: Vec<Arc<ProcessControlBlock>> = ProcessManager::thread_group_tasks(pcb.tgid())
+            .into_iter()
+            .filter(|t| !Arc::ptr_eq(t, &pcb))
+            .collect();
+        for thread in threads.iter() {
+            let seccomp = thread.seccomp.lock();
+            if seccomp.mode == SeccompMode::Strict
+                || !seccomp.filter_is_ancestor_of(&current.filter)
+            {
+                if flags.contains(SeccompFilterFlags::TSYNC_ESRCH) {
+                    return Err(SystemError::ESRCH);
+                }
+                return Ok(thread.pid_vnr().data());
+            }
+        }
+        threads
+    } else {
+        Vec::new()
+    };
+
+    let filter = Arc::new(SeccompFilter {
+        prog,
+        log: flags.contains(SeccompFilterFlags::LOG),
+        prev: current.filter,
+    });
+    let nnp = pcb.no_new_privs();
+    for thread in threads.iter().chain(core::iter::once(&pcb)) {
+        let mut seccomp = thread.seccomp.lock();
+        seccomp.mode = SeccompMode::Filter;
+        seccomp.filter = Some(filter.clone());
+        drop(seccomp);
+        // 同步过滤器的同时同步no_new_privs，否则其它线程可以通过execve绕过过滤器
+        if nnp {
+            thread.set_no_new_privs();
+        }
+    }
+
+    return Ok(0);
+}
+
+impl Syscall {
+    /// # 设置当前线程的seccomp状态
+    ///
+    /// ## 参数
+    ///
+    /// - `op`: 操作类型
+    /// - `flags`: 操作的标志位
+    /// - `uargs`: 操作的参数，对于SECCOMP_SET_MODE_FILTER是sock_fprog的指针
+    ///
+    /// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/kernel/seccomp.c#2064
+    pub fn seccomp(op: usize, flags: u32, uargs: usize) -> Result<usize, SystemError> {
+        let op = SeccompOp::from_usize(op).ok_or(SystemError::EINVAL)?;
+        match op {
+            SeccompOp::SetModeStrict => {
+                if flags != 0 || uargs != 0 {
+                    return Err(SystemError::EINVAL);
+                }
+                seccomp_set_mode_strict()
+            }
+            SeccompOp::SetModeFilter => seccomp_set_mode_filter(flags, uargs as *const SockFprog),
+            SeccompOp::GetActionAvail => {
+                if flags != 0 {
+                    return Err(SystemError::EINVAL);
+                }
+                let reader =
+                    UserBufferReader::new(uargs as *const u32, core::mem::size_of::<u32>(), true)?;
+                match *reader.read_one_from_user::<u32>(0)? {
+                    SECCOMP_RET_KILL_PROCESS
+                    | SECCOMP_RET_KILL_THREAD
+                    | SECCOMP_RET_TRAP
+                    | SECCOMP_RET_ERRNO
+                    | SECCOMP_RET_TRACE
+                    | SECCOMP_RET_LOG
+                    | SECCOMP_RET_ALLOW => Ok(0),
+                    _ => Err(SystemError::EOPNOTSUPP_OR_ENOTSUP),
+                }
+            }
+        }
+    }
+
+    /// prctl(PR_GET_SECCOMP)
+    pub(super) fn prctl_get_seccomp() -> Result<usize, SystemError> {
+        Ok(ProcessManager::current_pcb().seccomp_mode() as usize)
+    }
+
+    /// prctl(PR_SET_SECCOMP, mode, filter)
+    ///
+    /// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/kernel/seccomp.c#2115
+    pub(super) fn prctl_set_seccomp(mode: usize, filter: usize) -> Result<usize, SystemError> {
+        match SeccompMode::from_usize(mode) {
+            Some(SeccompMode::Strict) => Self::seccomp(SeccompOp::SetModeStrict as usize, 0, 0),
+            Some(SeccompMode::Filter) => {
+                Self::seccomp(SeccompOp::SetModeFilter as usize, 0, filter)
+            }
+            _ => Err(SystemError::EINVAL),
+        }
+    }
+}
@@ -5,10 +5,12 @@ use log::error;
 use system_error::SystemError;
 
 use super::{
-    abi::WaitOption,
-    cred::{Kgid, Kuid},
-    exit::kernel_wait4,
-    fork::{CloneFlags, KernelCloneArgs},
+    abi::{WaitIdSigInfo, WaitIdType, WaitOption},
+    cred::{CAPFlags, Kgid, Kuid},
+    exit::{do_wait, kernel_wait4, KernelWaitOption},
+    fork::{CloneFlags, KernelCloneArgs, PosixCloneArgs},
+    pid::PidType,
+    pidfd::pidfd_get_pid,
     resource::{RLimit64, RLimitID, RUsage, RUsageWho},
     KernelStack, Pid, ProcessManager,
 };
@@ -16,34 +18,38 @@ use crate::{
     arch::{interrupt::TrapFrame, MMArch},
     filesystem::{
         procfs::procfs_register_pid,
-        vfs::{file::FileDescriptorVec, MAX_PATHLEN},
+        vfs::{file::FileMode, MAX_PATHLEN},
     },
-    mm::{ucontext::UserStack, verify_area, MemoryManagementArch, VirtAddr},
+    mm::{verify_area, MemoryManagementArch, VirtAddr},
     process::ProcessControlBlock,
     sched::completion::Completion,
     syscall::{
-        user_access::{check_and_clone_cstr, check_and_clone_cstr_array, UserBufferWriter},
+        user_access::{
+            check_and_clone_cstr, check_and_clone_cstr_array, UserBufferReader, UserBufferWriter,
+        },
         Syscall,
     },
 };
 
-//参考资料：https://code.dragonos.org.cn/xref/linux-6.1.9/include/uapi/linux/utsname.h#17
+//参考资料：https://code.dragonos.org.cn/xref/linux-6.1.9/include/uapi/linux/utsname.h#25
 #[repr(C)]
 #[derive(Debug, Clone, Copy)]
-pub struct PosixOldUtsName {
+pub struct PosixNewUtsName {
     pub sysname: [u8; 65],
     pub nodename: [u8; 65],
     pub release: [u8; 65],
     pub version: [u8; 65],
     pub machine: [u8; 65],
+    pub domainname: [u8; 65],
 }
 
-impl PosixOldUtsName {
+impl PosixNewUtsName {
     pub fn new() -> Self {
         const SYS_NAME: &[u8] = b"DragonOS";
         const NODENAME: &[u8] = b"DragonOS";
         const RELEASE: &[u8] = env!("CARGO_PKG_VERSION").as_bytes();
         const VERSION: &[u8] = env!("CARGO_PKG_VERSION").as_bytes();
+        const DOMAINNAME: &[u8] = b"(none)";
 
         #[cfg(target_arch = "x86_64")]
         const MACHINE: &[u8] = b"x86_64";
@@ -60,6 +66,7 @@ impl PosixOldUtsName {
             release: [0; 65],
             version: [0; 65],
             machine: [0; 65],
+            domainname: [0; 65],
         };
 
         r.sysname[0..SYS_NAME.len()].copy_from_slice(SYS_NAME);
@@ -67,6 +74,7 @@ impl PosixOldUtsName {
         r.release[0..RELEASE.len()].copy_from_slice(RELEASE);
         r.version[0..VERSION.len()].copy_from_slice(VERSION);
         r.machine[0..MACHINE.len()].copy_from_slice(MACHINE);
+        r.domainname[0..DOMAINNAME.len()].copy_from_slice(DOMAINNAME);
 
         return r;
     }
@@ -74,7 +82,12 @@ impl PosixOldUtsName {
 
 impl Syscall {
     pub fn fork(frame: &TrapFrame) -> Result<usize, SystemError> {
-        ProcessManager::fork(frame, CloneFlags::empty()).map(|pid| pid.into())
+        let pid = ProcessManager::fork(frame, CloneFlags::empty())?;
+        // 返回子进程在当前进程所在pid namespace中的pid
+        let vnr = ProcessManager::find(pid)
+            .map(|pcb| pcb.pid_vnr())
+            .unwrap_or(pid);
+        return Ok(vnr.into());
     }
 
     pub fn vfork(frame: &TrapFrame) -> Result<usize, SystemError> {
@@ -176,19 +189,112 @@ impl Syscall {
         return Ok(r);
     }
 
+    /// # 等待子进程的状态发生变化
+    ///
+    /// ## 参数
+    ///
+    /// - which: 等待的对象类型，见[`WaitIdType`]
+    /// - upid: 进程id、进程组id或pidfd，取决于`which`
+    /// - infop: 用于返回子进程状态的siginfo_t
+    /// - options: 等待选项，必须包含WEXITED、WSTOPPED、WCONTINUED中的至少一个
+    /// - rusage: 子进程的资源使用情况
+    ///
+    /// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/kernel/exit.c#1701
+    pub fn waitid(
+        which: u32,
+        upid: i32,
+        infop: *mut WaitIdSigInfo,
+        options: i32,
+        rusage: *mut c_void,
+    ) -> Result<usize, SystemError> {
+        let mut options = WaitOption::from_bits(options as u32).ok_or(SystemError::EINVAL)?;
+        if !options.intersects(WaitOption::WEXITED | WaitOption::WSTOPPED | WaitOption::WCONTINUED)
+        {
+            return Err(SystemError::EINVAL);
+        }
+
+        let mut pidfd_nonblock = false;
+        let (pid_type, pid) = match WaitIdType::try_from(which)? {
+            WaitIdType::All => (PidType::MAX, Pid(0)),
+            WaitIdType::Pid => {
+                if upid <= 0 {
+                    return Err(SystemError::EINVAL);
+                }
+                // 将当前pid namespace中的pid转换为全局pid
+                let pcb = ProcessManager::find_vpid(Pid::new(upid as usize))
+                    .ok_or(SystemError::ECHILD)?;
+                (PidType::PID, pcb.pid())
+            }
+            WaitIdType::Pgid => {
+                if upid < 0 {
+                    return Err(SystemError::EINVAL);
+                }
+                let pgid = if upid == 0 {
+                    ProcessManager::current_pcb().basic().pgid()
+                } else {
+                    Pid::new(upid as usize)
+                };
+                (PidType::PGID, pgid)
+            }
+            WaitIdType::PidFd => {
+                if upid < 0 {
+                    return Err(SystemError::EINVAL);
+                }
+                let (pid, mode) = pidfd_get_pid(upid)?;
+                if mode.contains(FileMode::O_NONBLOCK) {
+                    pidfd_nonblock = true;
+                    options.insert(WaitOption::WNOHANG);
+                }
+                (PidType::PID, pid)
+            }
+        };
+
+        let mut tmp_rusage = if rusage.is_null() {
+            None
+        } else {
+            Some(RUsage::default())
+        };
+
+        let mut kwo = KernelWaitOption::new(pid_type, pid, options);
+        kwo.ret_rusage = tmp_rusage.as_mut();
+        let r = do_wait(&mut kwo)?;
+        // 以非阻塞方式打开的pidfd，在子进程状态没有变化时返回EAGAIN
+        if r == 0 && pidfd_nonblock {
+            return Err(SystemError::EAGAIN_OR_EWOULDBLOCK);
+        }
+
+        if !infop.is_null() {
+            let siginfo = WaitIdSigInfo::new(if r == 0 { None } else { kwo.ret_info.as_ref() });
+            let mut infop_buf =
+                UserBufferWriter::new(infop, core::mem::size_of::<WaitIdSigInfo>(), true)?;
+            infop_buf.copy_one_to_user(&siginfo, 0)?;
+        }
+
+        if !rusage.is_null() {
+            let mut rusage_buf = UserBufferWriter::new::<RUsage>(
+                rusage as *mut RUsage,
+                core::mem::size_of::<RUsage>(),
+                true,
+            )?;
+            rusage_buf.copy_one_to_user(&tmp_rusage.unwrap(), 0)?;
+        }
+        return Ok(0);
+    }
+
     /// # 退出进程
     ///
     /// ## 参数
     ///
     /// - status: 退出状态
     pub fn exit(status: usize) -> ! {
-        ProcessManager::exit(status);
+        // 退出码保存在wait状态的第8~15位
+        ProcessManager::exit((status & 0xff) << 8);
     }
 
     /// @brief 获取当前进程的pid
     pub fn getpid() -> Result<Pid, SystemError> {
         let current_pcb = ProcessManager::current_pcb();
-        return Ok(current_pcb.tgid());
+        return Ok(current_pcb.tgid_vnr());
     }
 
     /// @brief 获取指定进程的pgid
@@ -200,9 +306,9 @@ impl Syscall {
     pub fn getpgid(mut pid: Pid) -> Result<Pid, SystemError> {
         if pid == Pid(0) {
             let current_pcb = ProcessManager::current_pcb();
-            pid = current_pcb.pid();
+            pid = current_pcb.pid_vnr();
         }
-        let target_proc = ProcessManager::find(pid).ok_or(SystemError::ESRCH)?;
+        let target_proc = ProcessManager::find_vpid(pid).ok_or(SystemError::ESRCH)?;
         return Ok(target_proc.basic().pgid());
     }
     /// @brief 获取当前进程的父进程id
@@ -210,7 +316,9 @@ impl Syscall {
     /// 若为initproc则ppid设置为0   
     pub fn getppid() -> Result<Pid, SystemError> {
         let current_pcb = ProcessManager::current_pcb();
-        return Ok(current_pcb.basic().ppid());
+        // 父进程不在当前进程的pid namespace中时（例如pid namespace的1号进程），返回0
+        let parent = current_pcb.parent_pcb.read_irqsave().upgrade();
+        return Ok(parent.map(|p| p.tgid_vnr()).unwrap_or(Pid(0)));
     }
 
     pub fn clone(
@@ -252,7 +360,8 @@ impl Syscall {
             let addr = pcb.thread.read_irqsave().set_child_tid.unwrap();
             let mut writer =
                 UserBufferWriter::new(addr.as_ptr::<i32>(), core::mem::size_of::<i32>(), true)?;
-            writer.copy_one_to_user(&(pcb.pid().data() as i32), 0)?;
+            let nr = pcb.pid_nr_ns(&pcb.active_pid_ns()).unwrap();
+            writer.copy_one_to_user(&(nr.data() as i32), 0)?;
         }
 
         ProcessManager::wakeup(&pcb).unwrap_or_else(|e| {
@@ -268,7 +377,50 @@ impl Syscall {
             vfork.wait_for_completion_interruptible()?;
         }
 
-        return Ok(pcb.pid().0);
+        return Ok(pcb.pid_vnr().0);
+    }
+
+    /// # 创建子进程或线程
+    ///
+    /// ## 参数
+    ///
+    /// - `current_trapframe`: 当前进程的trapframe
+    /// - `uargs`: 用户空间的`struct clone_args`
+    /// - `size`: 用户程序所使用的`struct clone_args`的大小，
+    ///   比内核已知的结构体更大时，多出的部分必须为0
+    ///
+    /// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/kernel/fork.c#3131
+    pub fn clone3(
+        current_trapframe: &TrapFrame,
+        uargs: *const u8,
+        size: usize,
+    ) -> Result<usize, SystemError> {
+        const KNOWN_SIZE: usize = core::mem::size_of::<PosixCloneArgs>();
+        if size < PosixCloneArgs::SIZE_VER0 {
+            return Err(SystemError::EINVAL);
+        }
+        if size > MMArch::PAGE_SIZE {
+            return Err(SystemError::E2BIG);
+        }
+
+        let reader = UserBufferReader::new(uargs, size, true)?;
+        let buf = reader.read_from_user::<u8>(0)?;
+        if buf[KNOWN_SIZE.min(size)..].iter().any(|&b| b != 0) {
+            return Err(SystemError::E2BIG);
+        }
+        let mut args = PosixCloneArgs::default();
+        let copy_len = KNOWN_SIZE.min(size);
+        // SAFETY: PosixCloneArgs由u64组成，任意字节序列都是合法的值
+        unsafe {
+            core::ptr::copy_nonoverlapping(
+                buf.as_ptr(),
+                &mut args as *mut PosixCloneArgs as *mut u8,
+                copy_len,
+            );
+        }
+
+        let clone_args = KernelCloneArgs::try_from((args, size))?;
+        return Self::clone(current_trapframe, clone_args);
     }
 
     /// 设置线程地址
@@ -278,12 +430,12 @@ impl Syscall {
 
         let pcb = ProcessManager::current_pcb();
         pcb.thread.write_irqsave().clear_child_tid = Some(VirtAddr::new(ptr));
-        Ok(pcb.pid.0)
+        Ok(pcb.pid_vnr().0)
     }
 
     pub fn gettid() -> Result<Pid, SystemError> {
         let pcb = ProcessManager::current_pcb();
-        Ok(pcb.pid)
+        Ok(pcb.pid_vnr())
     }
 
     pub fn getuid() -> Result<usize, SystemError> {
@@ -306,101 +458,219 @@ impl Syscall {
         return Ok(pcb.cred.lock().egid.data());
     }
 
+    /// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/kernel/sys.c#589
     pub fn setuid(uid: usize) -> Result<usize, SystemError> {
         let pcb = ProcessManager::current_pcb();
         let mut guard = pcb.cred.lock();
+        let old = guard.clone();
+        let kuid = Kuid::new(uid);
 
-        if guard.uid.data() == 0 {
+        if old.has_capability(CAPFlags::CAP_SETUID) {
             guard.setuid(uid);
-            guard.seteuid(uid);
             guard.setsuid(uid);
-        } else if uid == guard.uid.data() || uid == guard.suid.data() {
-            guard.seteuid(uid);
-        } else {
+        } else if kuid != old.uid && kuid != old.suid {
             return Err(SystemError::EPERM);
         }
+        guard.seteuid(uid);
+        guard.setfsuid(uid);
+        guard.fixup_setuid_caps(&old);
+        ProcessManager::switch_user_processes(old.uid, guard.uid);
 
         return Ok(0);
     }
 
+    /// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/kernel/sys.c#441
     pub fn setgid(gid: usize) -> Result<usize, SystemError> {
         let pcb = ProcessManager::current_pcb();
         let mut guard = pcb.cred.lock();
+        let kgid = Kgid::new(gid);
 
-        if guard.egid.data() == 0 {
+        if guard.has_capability(CAPFlags::CAP_SETGID) {
             guard.setgid(gid);
-            guard.setegid(gid);
             guard.setsgid(gid);
-            guard.setfsgid(gid);
-        } else if guard.gid.data() == gid || guard.sgid.data() == gid {
-            guard.setegid(gid);
-            guard.setfsgid(gid);
-        } else {
+        } else if kgid != guard.gid && kgid != guard.sgid {
             return Err(SystemError::EPERM);
         }
+        guard.setegid(gid);
+        guard.setfsgid(gid);
 
         return Ok(0);
     }
 
-    pub fn seteuid(euid: usize) -> Result<usize, SystemError> {
+    /// # 设置进程的实际uid与有效uid
+    ///
+    /// 参数为-1时表示不修改。修改了实际uid，或者将有效uid设置为与原本的实际uid不同的值时，
+    /// 保存的uid会被设置为新的有效uid。
+    ///
+    /// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/kernel/sys.c#518
+    pub fn setreuid(ruid: usize, euid: usize) -> Result<usize, SystemError> {
+        let (ruid, euid) = (id_arg(ruid), id_arg(euid));
         let pcb = ProcessManager::current_pcb();
         let mut guard = pcb.cred.lock();
+        let old = guard.clone();
+        let privileged = old.has_capability(CAPFlags::CAP_SETUID);
 
-        if euid == usize::MAX || (euid == guard.euid.data() && euid == guard.fsuid.data()) {
-            return Ok(0);
+        if let Some(ruid) = ruid {
+            if !privileged && ![old.uid, old.euid].contains(&Kuid::new(ruid)) {
+                return Err(SystemError::EPERM);
+            }
+            guard.setuid(ruid);
         }
-
-        if euid != usize::MAX {
+        if let Some(euid) = euid {
+            if !privileged && ![old.uid, old.euid, old.suid].contains(&Kuid::new(euid)) {
+                return Err(SystemError::EPERM);
+            }
             guard.seteuid(euid);
         }
 
         let euid = guard.euid.data();
+        if ruid.is_some() || guard.euid != old.uid {
+            guard.setsuid(euid);
+        }
         guard.setfsuid(euid);
+        guard.fixup_setuid_caps(&old);
+        ProcessManager::switch_user_processes(old.uid, guard.uid);
 
         return Ok(0);
     }
 
-    pub fn setegid(egid: usize) -> Result<usize, SystemError> {
+    /// # 设置进程的实际gid与有效gid
+    ///
+    /// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/kernel/sys.c#380
+    pub fn setregid(rgid: usize, egid: usize) -> Result<usize, SystemError> {
+        let (rgid, egid) = (id_arg(rgid), id_arg(egid));
         let pcb = ProcessManager::current_pcb();
         let mut guard = pcb.cred.lock();
+        let old = guard.clone();
+        let privileged = old.has_capability(CAPFlags::CAP_SETGID);
 
-        if egid == usize::MAX || (egid == guard.egid.data() && egid == guard.fsgid.data()) {
-            return Ok(0);
+        if let Some(rgid) = rgid {
+            if !privileged && ![old.gid, old.egid].contains(&Kgid::new(rgid)) {
+                return Err(SystemError::EPERM);
+            }
+            guard.setgid(rgid);
         }
-
-        if egid != usize::MAX {
+        if let Some(egid) = egid {
+            if !privileged && ![old.gid, old.egid, old.sgid].contains(&Kgid::new(egid)) {
+                return Err(SystemError::EPERM);
+            }
             guard.setegid(egid);
         }
 
         let egid = guard.egid.data();
+        if rgid.is_some() || guard.egid != old.gid {
+            guard.setsgid(egid);
+        }
         guard.setfsgid(egid);
 
         return Ok(0);
     }
 
-    pub fn setfsuid(fsuid: usize) -> Result<usize, SystemError> {
-        let fsuid = Kuid::new(fsuid);
+    /// # 设置进程的实际uid、有效uid与保存的uid
+    ///
+    /// 参数为-1时表示不修改。没有CAP_SETUID时，每个新的uid都必须是原有的三个uid之一。
+    ///
+    /// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/kernel/sys.c#640
+    pub fn setresuid(ruid: usize, euid: usize, suid: usize) -> Result<usize, SystemError> {
+        let (ruid, euid, suid) = (id_arg(ruid), id_arg(euid), id_arg(suid));
+        let pcb = ProcessManager::current_pcb();
+        let mut guard = pcb.cred.lock();
+        let old = guard.clone();
+
+        if !old.has_capability(CAPFlags::CAP_SETUID) {
+            let allowed = [old.uid, old.euid, old.suid];
+            if [ruid, euid, suid]
+                .iter()
+                .flatten()
+                .any(|id| !allowed.contains(&Kuid::new(*id)))
+            {
+                return Err(SystemError::EPERM);
+            }
+        }
+
+        if let Some(ruid) = ruid {
+            guard.setuid(ruid);
+        }
+        if let Some(euid) = euid {
+            guard.seteuid(euid);
+        }
+        if let Some(suid) = suid {
+            guard.setsuid(suid);
+        }
+        let euid = guard.euid.data();
+        guard.setfsuid(euid);
+        guard.fixup_setuid_caps(&old);
+        ProcessManager::switch_user_processes(old.uid, guard.uid);
 
+        return Ok(0);
+    }
+
+    /// # 设置进程的实际gid、有效gid与保存的gid
+    ///
+    /// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/kernel/sys.c#723
+    pub fn setresgid(rgid: usize, egid: usize, sgid: usize) -> Result<usize, SystemError> {
+        let (rgid, egid, sgid) = (id_arg(rgid), id_arg(egid), id_arg(sgid));
         let pcb = ProcessManager::current_pcb();
         let mut guard = pcb.cred.lock();
-        let old_fsuid = guard.fsuid;
 
-        if fsuid == guard.uid || fsuid == guard.euid || fsuid == guard.suid {
-            guard.setfsuid(fsuid.data());
+        if !guard.has_capability(CAPFlags::CAP_SETGID) {
+            let allowed = [guard.gid, guard.egid, guard.sgid];
+            if [rgid, egid, sgid]
+                .iter()
+                .flatten()
+                .any(|id| !allowed.contains(&Kgid::new(*id)))
+            {
+                return Err(SystemError::EPERM);
+            }
+        }
+
+        if let Some(rgid) = rgid {
+            guard.setgid(rgid);
+        }
+        if let Some(egid) = egid {
+            guard.setegid(egid);
+        }
+        if let Some(sgid) = sgid {
+            guard.setsgid(sgid);
         }
+        let egid = guard.egid.data();
+        guard.setfsgid(egid);
 
-        Ok(old_fsuid.data())
+        return Ok(0);
     }
 
-    pub fn setfsgid(fsgid: usize) -> Result<usize, SystemError> {
-        let fsgid = Kgid::new(fsgid);
+    /// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/kernel/sys.c#800
+    pub fn setfsuid(fsuid: usize) -> Result<usize, SystemError> {
+        let pcb = ProcessManager::current_pcb();
+        let mut guard = pcb.cred.lock();
+        let old = guard.clone();
+
+        if let Some(fsuid) = id_arg(fsuid) {
+            let kuid = Kuid::new(fsuid);
+            if [old.uid, old.euid, old.suid, old.fsuid].contains(&kuid)
+                || old.has_capability(CAPFlags::CAP_SETUID)
+            {
+                guard.setfsuid(fsuid);
+                guard.fixup_setfsuid_caps(&old);
+            }
+        }
+
+        Ok(old.fsuid.data())
+    }
 
+    /// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/kernel/sys.c#844
+    pub fn setfsgid(fsgid: usize) -> Result<usize, SystemError> {
         let pcb = ProcessManager::current_pcb();
         let mut guard = pcb.cred.lock();
         let old_fsgid = guard.fsgid;
 
-        if fsgid == guard.gid || fsgid == guard.egid || fsgid == guard.sgid {
-            guard.setfsgid(fsgid.data());
+        if let Some(fsgid) = id_arg(fsgid) {
+            let kgid = Kgid::new(fsgid);
+            if [guard.gid, guard.egid, guard.sgid, guard.fsgid].contains(&kgid)
+                || guard.has_capability(CAPFlags::CAP_SETGID)
+            {
+                guard.setfsgid(fsgid);
+            }
         }
 
         Ok(old_fsgid.data())
@@ -418,15 +688,13 @@ impl Syscall {
         return Ok(0);
     }
 
-    /// # 设置资源限制
-    ///
-    /// TODO: 目前暂时不支持设置资源限制，只提供读取默认值的功能
+    /// # 获取/设置资源限制
     ///
     /// ## 参数
     ///
-    /// - pid: 进程号
+    /// - pid: 进程号，为0时表示当前进程
     /// - resource: 资源类型
-    /// - new_limit: 新的资源限制
+    /// - new_limit: 新的资源限制，为NULL时不修改
     /// - old_limit: 旧的资源限制
     ///
     /// ## 返回值
@@ -434,62 +702,74 @@ impl Syscall {
     /// - 成功，0
     /// - 如果old_limit不为NULL，则返回旧的资源限制到old_limit
     ///
+    /// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/kernel/sys.c#1704
     pub fn prlimit64(
-        _pid: Pid,
+        pid: Pid,
         resource: usize,
-        _new_limit: *const RLimit64,
+        new_limit: *const RLimit64,
         old_limit: *mut RLimit64,
     ) -> Result<usize, SystemError> {
         let resource = RLimitID::try_from(resource)?;
-        let mut writer = None;
-
-        if !old_limit.is_null() {
-            writer = Some(UserBufferWriter::new(
-                old_limit,
-                core::mem::size_of::<RLimit64>(),
-                true,
-            )?);
+        if resource == RLimitID::Nlimits {
+            return Err(SystemError::EINVAL);
         }
 
-        match resource {
-            RLimitID::Stack => {
-                if let Some(mut writer) = writer {
-                    let mut rlimit = writer.buffer::<RLimit64>(0).unwrap()[0];
-                    rlimit.rlim_cur = UserStack::DEFAULT_USER_STACK_SIZE as u64;
-                    rlimit.rlim_max = UserStack::DEFAULT_USER_STACK_SIZE as u64;
-                }
-                return Ok(0);
-            }
+        let new_limit = if new_limit.is_null() {
+            None
+        } else {
+            let reader = UserBufferReader::new(new_limit, core::mem::size_of::<RLimit64>(), true)?;
+            Some(*reader.read_one_from_user::<RLimit64>(0)?)
+        };
 
-            RLimitID::Nofile => {
-                if let Some(mut writer) = writer {
-                    let mut rlimit = writer.buffer::<RLimit64>(0).unwrap()[0];
-                    rlimit.rlim_cur = FileDescriptorVec::PROCESS_MAX_FD as u64;
-                    rlimit.rlim_max = FileDescriptorVec::PROCESS_MAX_FD as u64;
-                }
-                return Ok(0);
-            }
+        let current_pcb = ProcessManager::current_pcb();
+        let target = if pid.data() == 0 {
+            current_pcb.clone()
+        } else {
+            ProcessManager::find_vpid(pid).ok_or(SystemError::ESRCH)?
+        };
 
-            RLimitID::As | RLimitID::Rss => {
-                if let Some(mut writer) = writer {
-                    let mut rlimit = writer.buffer::<RLimit64>(0).unwrap()[0];
-                    rlimit.rlim_cur = MMArch::USER_END_VADDR.data() as u64;
-                    rlimit.rlim_max = MMArch::USER_END_VADDR.data() as u64;
-                }
-                return Ok(0);
+        // 修改其它进程的资源限制时，需要与其拥有相同的uid和gid，或者拥有CAP_SYS_RESOURCE
+        if target.tgid() != current_pcb.tgid() {
+            let cred = current_pcb.cred();
+            let tcred = target.cred();
+            let same_user = cred.uid == tcred.euid
+                && cred.uid == tcred.suid
+                && cred.uid == tcred.uid
+                && cred.gid == tcred.egid
+                && cred.gid == tcred.sgid
+                && cred.gid == tcred.gid;
+            if !same_user && !cred.has_capability(CAPFlags::CAP_SYS_RESOURCE) {
+                return Err(SystemError::EPERM);
             }
+        }
 
-            _ => {
-                return Err(SystemError::ENOSYS);
-            }
+        let old = target.do_prlimit(resource, new_limit)?;
+
+        if !old_limit.is_null() {
+            let mut writer =
+                UserBufferWriter::new(old_limit, core::mem::size_of::<RLimit64>(), true)?;
+            writer.copy_one_to_user(&old, 0)?;
         }
+
+        return Ok(0);
     }
 
-    pub fn uname(name: *mut PosixOldUtsName) -> Result<usize, SystemError> {
+    pub fn uname(name: *mut PosixNewUtsName) -> Result<usize, SystemError> {
         let mut writer =
-            UserBufferWriter::new(name, core::mem::size_of::<PosixOldUtsName>(), true)?;
-        writer.copy_one_to_user(&PosixOldUtsName::new(), 0)?;
+            UserBufferWriter::new(name, core::mem::size_of::<PosixNewUtsName>(), true)?;
+        let utsname = ProcessManager::current_pcb().nsproxy().uts_ns.utsname();
+        writer.copy_one_to_user(&utsname, 0)?;
 
         return Ok(0);
     }
 }
+
+/// 将set*id系统调用中的id参数转换为Option，-1表示不修改
+fn id_arg(id: usize) -> Option<usize> {
+    let id = id as u32;
+    if id == u32::MAX {
+        None
+    } else {
+        Some(id as usize)
+    }
+}
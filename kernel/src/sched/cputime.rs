@@ -1,4 +1,4 @@
-use core::sync::atomic::{compiler_fence, AtomicUsize, Ordering};
+use core::sync::atomic::{compiler_fence, AtomicU64, AtomicUsize, Ordering};
 
 use crate::{
     arch::CurrentIrqArch, exception::InterruptArch, process::ProcessControlBlock,
@@ -69,11 +69,27 @@ impl IrqTime {
     }
 }
 
+/// 进程占用的CPU时间（纳秒）
+#[derive(Debug, Default)]
+pub struct ProcessCpuTime {
+    /// 用户态时间
+    pub utime: AtomicU64,
+    /// 内核态时间
+    pub stime: AtomicU64,
+}
+
+impl ProcessCpuTime {
+    /// 用户态与内核态时间之和
+    pub fn total(&self) -> u64 {
+        self.utime.load(Ordering::Relaxed) + self.stime.load(Ordering::Relaxed)
+    }
+}
+
 pub struct CpuTimeFunc;
 impl CpuTimeFunc {
     pub fn irqtime_account_process_tick(
-        _pcb: &Arc<ProcessControlBlock>,
-        _user_tick: bool,
+        pcb: &Arc<ProcessControlBlock>,
+        user_tick: bool,
         ticks: u64,
     ) {
         let cputime = TICK_NESC as u64 * ticks;
@@ -84,7 +100,16 @@ impl CpuTimeFunc {
             return;
         }
 
-        // TODO: update process time
+        let delta = cputime - other;
+        let account = |time: &ProcessCpuTime| {
+            if user_tick {
+                time.utime.fetch_add(delta, Ordering::Relaxed);
+            } else {
+                time.stime.fetch_add(delta, Ordering::Relaxed);
+            }
+        };
+        account(pcb.cpu_time());
+        pcb.with_group_cpu_time(account);
     }
 
     pub fn account_other_time(max: u64) -> u64 {
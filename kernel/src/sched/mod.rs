@@ -780,6 +780,7 @@ impl ProcessManager {
     pub fn update_process_times(user_tick: bool) {
         let pcb = Self::current_pcb();
         CpuTimeFunc::irqtime_account_process_tick(&pcb, user_tick, 1);
+        pcb.check_rlimit_cpu();
 
         scheduler_tick();
     }
@@ -1,10 +1,9 @@
 use crate::{
-    arch::{mm::LockedFrameAllocator, rand::rand},
+    arch::mm::LockedFrameAllocator,
+    driver::char::random::{crng_ready, get_random_bytes, wait_for_random_bytes},
     libs::rand::GRandFlags,
     mm::allocator::{page_frame::FrameAllocator, slab::slab_usage},
 };
-use alloc::vec::Vec;
-use core::cmp;
 use log::warn;
 use system_error::SystemError;
 
@@ -65,28 +64,30 @@ impl Syscall {
     }
 
     /// ## 将随机字节填入buf
-    /// ### 该系统调用与linux不一致，因为目前没有其他随机源
+    ///
+    /// 除非指定了`GRND_INSECURE`，否则会等待CRNG完成初始化（指定`GRND_NONBLOCK`时返回EAGAIN）。
+    /// `GRND_RANDOM`与不带该标志时的行为相同，二者共用同一个CRNG。
+    ///
+    /// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/drivers/char/random.c#1385
     pub fn get_random(buf: *mut u8, len: usize, flags: GRandFlags) -> Result<usize, SystemError> {
-        if flags.bits() == (GRandFlags::GRND_INSECURE.bits() | GRandFlags::GRND_RANDOM.bits()) {
+        if flags.contains(GRandFlags::GRND_INSECURE | GRandFlags::GRND_RANDOM) {
             return Err(SystemError::EINVAL);
         }
 
-        let mut writer = UserBufferWriter::new(buf, len, true)?;
-
-        let mut ret = Vec::new();
-        let mut count = 0;
-        while count < len {
-            // 对 len - count 的长度进行判断，remain_len 小于4则循环次数和 remain_len 相等
-            let remain_len = len - count;
-            let step = cmp::min(remain_len, 4);
-            let rand = rand();
-            for offset in 0..step {
-                ret.push((rand >> (offset * 2)) as u8);
-                count += 1;
+        if !crng_ready() && !flags.contains(GRandFlags::GRND_INSECURE) {
+            if flags.contains(GRandFlags::GRND_NONBLOCK) {
+                return Err(SystemError::EAGAIN_OR_EWOULDBLOCK);
             }
+            wait_for_random_bytes()?;
+        }
+
+        if len == 0 {
+            return Ok(0);
         }
 
-        writer.copy_to_user(&ret, 0)?;
+        let mut writer = UserBufferWriter::new(buf, len, true)?;
+        get_random_bytes(writer.buffer::<u8>(0)?);
+
         Ok(len)
     }
 }
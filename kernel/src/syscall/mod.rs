@@ -1,17 +1,20 @@
 use core::{
     ffi::{c_int, c_void},
-    ptr::null,
     sync::atomic::{AtomicBool, Ordering},
 };
 
 use crate::{
     arch::{ipc::signal::SigSet, syscall::nr::*},
-    filesystem::vfs::syscall::{PosixStatfs, PosixStatx},
+    filesystem::{
+        select::PselectSigmask,
+        vfs::syscall::{PosixStatfs, PosixStatx},
+    },
     ipc::shm::{ShmCtlCmd, ShmFlags, ShmId, ShmKey},
     libs::{futex::constant::FutexFlag, rand::GRandFlags},
     mm::{page::PAGE_4K_SIZE, syscall::MremapFlags},
     net::syscall::MsgHdr,
     process::{
+        abi::WaitIdSigInfo,
         fork::KernelCloneArgs,
         resource::{RLimit64, RUsage},
         ProcessFlags, ProcessManager,
@@ -35,7 +38,13 @@ use crate::{
     libs::align::page_align_up,
     mm::{verify_area, MemoryManagementArch, VirtAddr},
     net::syscall::SockAddr,
-    process::{fork::CloneFlags, syscall::PosixOldUtsName, Pid},
+    process::{
+        capability::{capable, CapUserData, CapUserHeader},
+        cred::CAPFlags,
+        fork::CloneFlags,
+        syscall::PosixNewUtsName,
+        Pid,
+    },
     time::{
         syscall::{PosixTimeZone, PosixTimeval},
         PosixTimeSpec,
@@ -197,6 +206,30 @@ impl Syscall {
                 Self::pwrite(fd, buf, len, offset)
             }
 
+            SYS_SENDFILE => {
+                Self::sendfile(args[0] as i32, args[1] as i32, args[2] as *mut i64, args[3])
+            }
+
+            SYS_SPLICE => Self::splice(
+                args[0] as i32,
+                args[1] as *mut i64,
+                args[2] as i32,
+                args[3] as *mut i64,
+                args[4],
+                args[5] as u32,
+            ),
+
+            SYS_TEE => Self::tee(args[0] as i32, args[1] as i32, args[2], args[3] as u32),
+
+            SYS_COPY_FILE_RANGE => Self::copy_file_range(
+                args[0] as i32,
+                args[1] as *mut i64,
+                args[2] as i32,
+                args[3] as *mut i64,
+                args[4],
+                args[5] as u32,
+            ),
+
             SYS_IOCTL => {
                 let fd = args[0];
                 let cmd = args[1];
@@ -283,6 +316,15 @@ impl Syscall {
                 Self::wait4(pid.into(), wstatus, options, rusage)
             }
 
+            SYS_WAITID => {
+                let which = args[0] as u32;
+                let upid = args[1] as i32;
+                let infop = args[2] as *mut WaitIdSigInfo;
+                let options = args[3] as c_int;
+                let rusage = args[4] as *mut c_void;
+                Self::waitid(which, upid, infop, options, rusage)
+            }
+
             SYS_EXIT => {
                 let exit_code = args[0];
                 Self::exit(exit_code)
@@ -367,10 +409,10 @@ impl Syscall {
                 Self::unlink(path)
             }
             SYS_KILL => {
-                let pid = Pid::new(args[0]);
+                let pid = args[0] as i32;
                 let sig = args[1] as c_int;
                 // debug!("KILL SYSCALL RECEIVED");
-                Self::kill(pid, sig)
+                Self::kill_something(pid, sig)
             }
 
             SYS_RT_SIGACTION => {
@@ -643,7 +685,7 @@ impl Syscall {
                 let fd = args[0] as i32;
                 let cmd: Option<FcntlCommand> =
                     <FcntlCommand as FromPrimitive>::from_u32(args[1] as u32);
-                let arg = args[2] as i32;
+                let arg = args[2];
                 let res = if let Some(cmd) = cmd {
                     Self::fcntl(fd, cmd, arg)
                 } else {
@@ -654,6 +696,8 @@ impl Syscall {
                 res
             }
 
+            SYS_FLOCK => Self::flock(args[0] as i32, args[1] as u32),
+
             SYS_FTRUNCATE => {
                 let fd = args[0] as i32;
                 let len = args[1];
@@ -689,9 +733,15 @@ impl Syscall {
                 clone_args.parent_tid = parent_tid;
                 clone_args.child_tid = child_tid;
                 clone_args.tls = args[4];
+                // clone系统调用通过parent_tid参数返回pidfd
+                if clone_args.flags.contains(CloneFlags::CLONE_PIDFD) {
+                    clone_args.pidfd = parent_tid;
+                }
                 Self::clone(frame, clone_args)
             }
 
+            SYS_CLONE3 => Self::clone3(frame, args[0] as *const u8, args[1]),
+
             SYS_FUTEX => {
                 let uaddr = VirtAddr::new(args[0]);
                 let operation = FutexFlag::from_bits(args[1] as u32).ok_or(SystemError::ENOSYS)?;
@@ -817,6 +867,24 @@ impl Syscall {
                 )
             }
 
+            #[cfg(target_arch = "x86_64")]
+            SYS_SELECT => Self::select(
+                args[0] as i32,
+                args[1] as *mut usize,
+                args[2] as *mut usize,
+                args[3] as *mut usize,
+                args[4] as *mut PosixTimeval,
+            ),
+
+            SYS_PSELECT6 => Self::pselect6(
+                args[0] as i32,
+                args[1] as *mut usize,
+                args[2] as *mut usize,
+                args[3] as *mut usize,
+                args[4] as *mut PosixTimeSpec,
+                args[5] as *const PselectSigmask,
+            ),
+
             // 目前为了适配musl-libc,以下系统调用先这样写着
             SYS_GETRANDOM => {
                 let flags = GRandFlags::from_bits(args[2] as u8).ok_or(SystemError::EINVAL)?;
@@ -895,12 +963,21 @@ impl Syscall {
 
             SYS_GETEUID => Self::geteuid(),
             SYS_GETEGID => Self::getegid(),
-            SYS_SETRESUID => Self::seteuid(args[1]),
-            SYS_SETRESGID => Self::setegid(args[1]),
+            SYS_SETREUID => Self::setreuid(args[0], args[1]),
+            SYS_SETREGID => Self::setregid(args[0], args[1]),
+            SYS_SETRESUID => Self::setresuid(args[0], args[1], args[2]),
+            SYS_SETRESGID => Self::setresgid(args[0], args[1], args[2]),
 
             SYS_SETFSUID => Self::setfsuid(args[0]),
             SYS_SETFSGID => Self::setfsgid(args[0]),
 
+            SYS_SECCOMP => Self::seccomp(args[0], args[1] as u32, args[2]),
+
+            SYS_CAPGET => Self::capget(args[0] as *mut CapUserHeader, args[1] as *mut CapUserData),
+            SYS_CAPSET => {
+                Self::capset(args[0] as *mut CapUserHeader, args[1] as *const CapUserData)
+            }
+
             SYS_SETSID => {
                 warn!("SYS_SETSID has not yet been implemented");
                 Ok(0)
@@ -975,10 +1052,18 @@ impl Syscall {
                 Self::umask(mask)
             }
 
-            SYS_FCHOWN => {
-                warn!("SYS_FCHOWN has not yet been implemented");
-                Ok(0)
-            }
+            #[cfg(target_arch = "x86_64")]
+            SYS_CHOWN => Self::chown(args[0] as *const u8, args[1], args[2]),
+            #[cfg(target_arch = "x86_64")]
+            SYS_LCHOWN => Self::lchown(args[0] as *const u8, args[1], args[2]),
+            SYS_FCHOWN => Self::fchown(args[0] as i32, args[1], args[2]),
+            SYS_FCHOWNAT => Self::fchownat(
+                args[0] as i32,
+                args[1] as *const u8,
+                args[2],
+                args[3],
+                args[4] as i32,
+            ),
 
             SYS_FSYNC => {
                 warn!("SYS_FSYNC has not yet been implemented");
@@ -1027,11 +1112,20 @@ impl Syscall {
                 let resource = args[0];
                 let rlimit = args[1] as *mut RLimit64;
 
+                // pid为0表示调用者自身，不需要经过pid namespace的转换
+                Self::prlimit64(Pid::new(0), resource, core::ptr::null::<RLimit64>(), rlimit)
+            }
+
+            #[cfg(target_arch = "x86_64")]
+            SYS_SETRLIMIT => {
+                let resource = args[0];
+                let rlimit = args[1] as *const RLimit64;
+
                 Self::prlimit64(
-                    ProcessManager::current_pcb().pid(),
+                    Pid::new(0),
                     resource,
-                    core::ptr::null::<RLimit64>(),
                     rlimit,
+                    core::ptr::null_mut::<RLimit64>(),
                 )
             }
 
@@ -1045,7 +1139,9 @@ impl Syscall {
                 let source = args[0] as *const u8;
                 let target = args[1] as *const u8;
                 let filesystemtype = args[2] as *const u8;
-                return Self::mount(source, target, filesystemtype, 0, null());
+                let mountflags = args[3];
+                let data = args[4] as *const c_void;
+                return Self::mount(source, target, filesystemtype, mountflags, data);
             }
 
             SYS_UMOUNT2 => {
@@ -1055,6 +1151,8 @@ impl Syscall {
                 return Ok(0);
             }
 
+            SYS_PIVOT_ROOT => Self::pivot_root(args[0] as *const u8, args[1] as *const u8),
+
             SYS_NEWFSTATAT => {
                 // todo: 这个系统调用还没有实现
 
@@ -1063,14 +1161,14 @@ impl Syscall {
 
             // SYS_SCHED_YIELD => Self::sched_yield(),
             SYS_UNAME => {
-                let name = args[0] as *mut PosixOldUtsName;
+                let name = args[0] as *mut PosixNewUtsName;
                 Self::uname(name)
             }
-            SYS_PRCTL => {
-                // todo: 这个系统调用还没有实现
-
-                Err(SystemError::EINVAL)
-            }
+            SYS_SETHOSTNAME => Self::sethostname(args[0] as *const u8, args[1]),
+            SYS_SETDOMAINNAME => Self::setdomainname(args[0] as *const u8, args[1]),
+            SYS_PRCTL => Self::prctl(args[0], args[1], args[2], args[3], args[4]),
+            SYS_UNSHARE => Self::unshare(args[0] as u64),
+            SYS_SETNS => Self::setns(args[0] as i32, args[1] as u64),
 
             #[cfg(target_arch = "x86_64")]
             SYS_ALARM => {
@@ -1138,6 +1236,67 @@ impl Syscall {
                 let flags = args[1] as u32;
                 Self::sys_eventfd(initval, flags)
             }
+            #[cfg(target_arch = "x86_64")]
+            SYS_INOTIFY_INIT => Self::inotify_init1(0),
+            SYS_INOTIFY_INIT1 => Self::inotify_init1(args[0] as u32),
+            SYS_INOTIFY_ADD_WATCH => {
+                Self::inotify_add_watch(args[0] as i32, args[1] as *const u8, args[2] as u32)
+            }
+            SYS_INOTIFY_RM_WATCH => Self::inotify_rm_watch(args[0] as i32, args[1] as i32),
+
+            SYS_SETXATTR | SYS_LSETXATTR => Self::setxattr(
+                args[0] as *const u8,
+                args[1] as *const u8,
+                args[2] as *const u8,
+                args[3],
+                args[4] as u32,
+                syscall_num == SYS_SETXATTR,
+            ),
+            SYS_FSETXATTR => Self::fsetxattr(
+                args[0] as i32,
+                args[1] as *const u8,
+                args[2] as *const u8,
+                args[3],
+                args[4] as u32,
+            ),
+            SYS_GETXATTR | SYS_LGETXATTR => Self::getxattr(
+                args[0] as *const u8,
+                args[1] as *const u8,
+                args[2] as *mut u8,
+                args[3],
+                syscall_num == SYS_GETXATTR,
+            ),
+            SYS_FGETXATTR => Self::fgetxattr(
+                args[0] as i32,
+                args[1] as *const u8,
+                args[2] as *mut u8,
+                args[3],
+            ),
+            SYS_LISTXATTR | SYS_LLISTXATTR => Self::listxattr(
+                args[0] as *const u8,
+                args[1] as *mut u8,
+                args[2],
+                syscall_num == SYS_LISTXATTR,
+            ),
+            SYS_FLISTXATTR => Self::flistxattr(args[0] as i32, args[1] as *mut u8, args[2]),
+            SYS_REMOVEXATTR | SYS_LREMOVEXATTR => Self::removexattr(
+                args[0] as *const u8,
+                args[1] as *const u8,
+                syscall_num == SYS_REMOVEXATTR,
+            ),
+            SYS_FREMOVEXATTR => Self::fremovexattr(args[0] as i32, args[1] as *const u8),
+            SYS_PIDFD_OPEN => {
+                let pid = args[0] as i32;
+                let flags = args[1] as u32;
+                Self::pidfd_open(pid, flags)
+            }
+            SYS_PIDFD_SEND_SIGNAL => {
+                let pidfd = args[0] as i32;
+                let sig = args[1] as c_int;
+                let info = args[2];
+                let flags = args[3] as u32;
+                Self::pidfd_send_signal(pidfd, sig, info, flags)
+            }
             _ => panic!("Unsupported syscall ID: {}", syscall_num),
         };
 
@@ -1171,6 +1330,9 @@ impl Syscall {
     }
 
     pub fn reboot() -> Result<usize, SystemError> {
+        if !capable(CAPFlags::CAP_SYS_BOOT) {
+            return Err(SystemError::EPERM);
+        }
         unsafe { cpu_reset() };
     }
 }
@@ -1,8 +1,11 @@
 #include <arpa/inet.h>
+#include <errno.h>
 #include <fcntl.h>
+#include <poll.h>
 #include <stdio.h>
 #include <stdlib.h>
 #include <string.h>
+#include <sys/sendfile.h>
 #include <sys/socket.h>
 #include <sys/stat.h>
 #include <unistd.h>
@@ -14,6 +17,8 @@
 #define WEB_ROOT "/var/www/html/"
 #define EXIT_CODE 1
 #define min(a, b) ((a) < (b) ? (a) : (b))
+// socket的发送缓冲区满时，等待其变为可写的超时时间
+#define SEND_RETRY_TIMEOUT_MS 1000
 
 #define DEFAULT_PAGE "/index.html"
 
@@ -88,28 +93,30 @@ void send_file(int sockfd, char *path)
     lseek(fd, 0, SEEK_SET);
     send_header(sockfd, content_length, path);
 
-    char buffer[1048576];
-    int readSize;
-    while (remaining)
+    // 由内核直接把文件内容发送到socket，不需要经过用户空间的缓冲区
+    off_t offset = 0;
+    while (remaining > 0)
     {
-        // 由于磁盘IO耗时较长，所以每次读取1MB，然后再分批发送
-        int to_read = min(1048576, remaining);
-        readSize = read(fd, &buffer, to_read);
-
-        remaining -= readSize;
-        void *p = buffer;
-        while (readSize > 0)
+        ssize_t wsize = sendfile(sockfd, fd, &offset, remaining);
+        // socket的发送缓冲区已满，等到socket可写之后再重试
+        if (wsize < 0 && (errno == ENOBUFS || errno == EAGAIN))
         {
-            int wsize = write(sockfd, p, min(readSize, MAX_RESPONSE_SIZE));
-            if (wsize <= 0)
+            struct pollfd pfd = {.fd = sockfd, .events = POLLOUT};
+            if (poll(&pfd, 1, SEND_RETRY_TIMEOUT_MS) < 0 && errno != EINTR)
             {
-                printf("send_file failed: wsize: %d\n", wsize);
+                printf("send_file failed: poll: %s\n", strerror(errno));
                 close(fd);
                 return;
             }
-            p += wsize;
-            readSize -= wsize;
+            continue;
+        }
+        if (wsize <= 0)
+        {
+            printf("send_file failed: wsize: %ld\n", wsize);
+            close(fd);
+            return;
         }
+        remaining -= wsize;
     }
 
     close(fd);
@@ -0,0 +1,462 @@
+// 测试POSIX capabilities：capget/capset、bounding集合、ambient集合，以及set*uid与execve时capability的变化
+#define _GNU_SOURCE
+#include <assert.h>
+#include <errno.h>
+#include <fcntl.h>
+#include <linux/capability.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <sys/fsuid.h>
+#include <sys/prctl.h>
+#include <sys/stat.h>
+#include <sys/syscall.h>
+#include <sys/wait.h>
+#include <unistd.h>
+
+#define CAP_BIT(cap) (1u << ((cap) & 31))
+// 拥有set-user-ID或set-group-ID位的自身的副本
+#define SETID_COPY "/tmp/test_capability_setid"
+
+struct caps
+{
+    struct __user_cap_data_struct data[_LINUX_CAPABILITY_U32S_3];
+};
+
+static void get_caps(struct caps *caps)
+{
+    struct __user_cap_header_struct header = {
+        .version = _LINUX_CAPABILITY_VERSION_3,
+        .pid = 0,
+    };
+    assert(syscall(SYS_capget, &header, caps->data) == 0);
+}
+
+static int set_caps(struct caps *caps)
+{
+    struct __user_cap_header_struct header = {
+        .version = _LINUX_CAPABILITY_VERSION_3,
+        .pid = 0,
+    };
+    return syscall(SYS_capset, &header, caps->data);
+}
+
+// 下面用到的capability编号都小于32，只需要检查第一组
+static int has_effective(int cap)
+{
+    struct caps caps;
+    get_caps(&caps);
+    return (caps.data[0].effective & CAP_BIT(cap)) != 0;
+}
+
+static int has_permitted(int cap)
+{
+    struct caps caps;
+    get_caps(&caps);
+    return (caps.data[0].permitted & CAP_BIT(cap)) != 0;
+}
+
+// 在子进程中执行func，等待子进程正常退出
+static void run_in_child(void (*func)(void))
+{
+    pid_t pid = fork();
+    assert(pid >= 0);
+    if (pid == 0)
+    {
+        func();
+        exit(0);
+    }
+    int status;
+    assert(waitpid(pid, &status, 0) == pid);
+    assert(WIFEXITED(status) && WEXITSTATUS(status) == 0);
+}
+
+static void test_version(void)
+{
+    // 版本号不支持且data为NULL时，内核把支持的版本号写回头部
+    struct __user_cap_header_struct header = {.version = 0, .pid = 0};
+    assert(syscall(SYS_capget, &header, NULL) == 0);
+    assert(header.version == _LINUX_CAPABILITY_VERSION_3);
+
+    struct caps caps;
+    header.version = 0;
+    assert(syscall(SYS_capget, &header, caps.data) == -1);
+    assert(errno == EINVAL);
+    assert(header.version == _LINUX_CAPABILITY_VERSION_3);
+
+    // 不存在的进程
+    header.pid = 0x7fffffff;
+    assert(syscall(SYS_capget, &header, caps.data) == -1);
+    assert(errno == ESRCH);
+    printf("capget version ok\n");
+}
+
+static void child_capset(void)
+{
+    struct caps caps;
+    get_caps(&caps);
+    assert(caps.data[0].effective & CAP_BIT(CAP_SETUID));
+
+    // 从effective集合中移除CAP_SETUID之后不能再修改uid
+    caps.data[0].effective &= ~CAP_BIT(CAP_SETUID);
+    assert(set_caps(&caps) == 0);
+    assert(!has_effective(CAP_SETUID));
+    assert(has_permitted(CAP_SETUID));
+    assert(setuid(1000) == -1);
+    assert(errno == EPERM);
+
+    // permitted集合中仍然有CAP_SETUID，可以重新加入effective集合
+    caps.data[0].effective |= CAP_BIT(CAP_SETUID);
+    assert(set_caps(&caps) == 0);
+    assert(has_effective(CAP_SETUID));
+
+    // effective集合不能超出permitted集合
+    caps.data[0].permitted &= ~CAP_BIT(CAP_SETUID);
+    assert(set_caps(&caps) == -1);
+    assert(errno == EPERM);
+
+    // 从permitted集合中移除之后不能再加回来
+    caps.data[0].effective &= ~CAP_BIT(CAP_SETUID);
+    assert(set_caps(&caps) == 0);
+    caps.data[0].permitted |= CAP_BIT(CAP_SETUID);
+    assert(set_caps(&caps) == -1);
+    assert(errno == EPERM);
+    assert(!has_permitted(CAP_SETUID));
+}
+
+static void child_bounding_set(void)
+{
+    assert(prctl(PR_CAPBSET_READ, CAP_NET_RAW) == 1);
+    assert(prctl(PR_CAPBSET_DROP, CAP_NET_RAW) == 0);
+    assert(prctl(PR_CAPBSET_READ, CAP_NET_RAW) == 0);
+    // 不合法的capability编号
+    assert(prctl(PR_CAPBSET_READ, 1000) == -1);
+    assert(errno == EINVAL);
+
+    // 没有CAP_SETPCAP时不能修改bounding集合
+    struct caps caps;
+    get_caps(&caps);
+    caps.data[0].effective &= ~CAP_BIT(CAP_SETPCAP);
+    assert(set_caps(&caps) == 0);
+    assert(prctl(PR_CAPBSET_DROP, CAP_SYS_ADMIN) == -1);
+    assert(errno == EPERM);
+}
+
+static void child_setuid_drop(void)
+{
+    assert(prctl(PR_GET_KEEPCAPS) == 0);
+    assert(setresuid(1000, 1000, 1000) == 0);
+    // 所有uid都不为0之后失去所有capability
+    assert(!has_permitted(CAP_SETUID));
+    assert(!has_effective(CAP_SETUID));
+    assert(setuid(0) == -1);
+    assert(errno == EPERM);
+}
+
+static void child_keep_caps(void)
+{
+    assert(prctl(PR_SET_KEEPCAPS, 1) == 0);
+    assert(prctl(PR_GET_KEEPCAPS) == 1);
+    assert(setresuid(1000, 1000, 1000) == 0);
+    // permitted集合被保留，effective集合被清空
+    assert(has_permitted(CAP_SETUID));
+    assert(!has_effective(CAP_SETUID));
+
+    struct caps caps;
+    get_caps(&caps);
+    caps.data[0].effective |= CAP_BIT(CAP_SETUID);
+    assert(set_caps(&caps) == 0);
+    assert(setuid(0) == 0);
+}
+
+static void child_seteuid(void)
+{
+    assert(seteuid(1000) == 0);
+    // ruid与suid仍然为0，只清空effective集合
+    assert(!has_effective(CAP_SETUID));
+    assert(has_permitted(CAP_SETUID));
+    assert(seteuid(0) == 0);
+    assert(has_effective(CAP_SETUID));
+}
+
+static void child_setfsuid(void)
+{
+    // fsuid不为0时只清空与文件系统相关的capability
+    setfsuid(1000);
+    assert(setfsuid(-1) == 1000);
+    assert(!has_effective(CAP_DAC_OVERRIDE));
+    assert(!has_effective(CAP_CHOWN));
+    assert(has_permitted(CAP_DAC_OVERRIDE));
+    assert(has_effective(CAP_SETUID));
+    setfsuid(0);
+    assert(has_effective(CAP_DAC_OVERRIDE));
+    assert(has_effective(CAP_CHOWN));
+}
+
+static void child_ambient(void)
+{
+    // 只能加入同时存在于permitted与inheritable集合中的capability
+    assert(prctl(PR_CAP_AMBIENT, PR_CAP_AMBIENT_RAISE, CAP_NET_RAW, 0, 0) == -1);
+    assert(errno == EPERM);
+
+    struct caps caps;
+    get_caps(&caps);
+    caps.data[0].inheritable |= CAP_BIT(CAP_NET_RAW);
+    assert(set_caps(&caps) == 0);
+    assert(prctl(PR_CAP_AMBIENT, PR_CAP_AMBIENT_RAISE, CAP_NET_RAW, 0, 0) == 0);
+    assert(prctl(PR_CAP_AMBIENT, PR_CAP_AMBIENT_IS_SET, CAP_NET_RAW, 0, 0) == 1);
+
+    // 从inheritable集合中移除时，ambient集合中的capability也被移除
+    caps.data[0].inheritable &= ~CAP_BIT(CAP_NET_RAW);
+    assert(set_caps(&caps) == 0);
+    assert(prctl(PR_CAP_AMBIENT, PR_CAP_AMBIENT_IS_SET, CAP_NET_RAW, 0, 0) == 0);
+
+    caps.data[0].inheritable |= CAP_BIT(CAP_NET_RAW);
+    assert(set_caps(&caps) == 0);
+    assert(prctl(PR_CAP_AMBIENT, PR_CAP_AMBIENT_RAISE, CAP_NET_RAW, 0, 0) == 0);
+    assert(prctl(PR_CAP_AMBIENT, PR_CAP_AMBIENT_CLEAR_ALL, 0, 0, 0) == 0);
+    assert(prctl(PR_CAP_AMBIENT, PR_CAP_AMBIENT_IS_SET, CAP_NET_RAW, 0, 0) == 0);
+}
+
+static const char *self_path;
+
+static void exec_self(const char *path, const char *mode)
+{
+    execl(path, path, mode, NULL);
+    perror("execl");
+    exit(1);
+}
+
+// root执行文件时，permitted与effective集合被设置为bounding集合，keep_caps被清除
+static void child_exec_root(void)
+{
+    struct caps caps;
+    get_caps(&caps);
+    caps.data[0].effective = 0;
+    caps.data[1].effective = 0;
+    assert(set_caps(&caps) == 0);
+    assert(prctl(PR_SET_KEEPCAPS, 1) == 0);
+    exec_self(self_path, "exec-root");
+}
+
+static int exec_root_check(void)
+{
+    assert(has_effective(CAP_SETUID));
+    assert(has_effective(CAP_SYS_ADMIN));
+    assert(prctl(PR_GET_KEEPCAPS) == 0);
+    return 0;
+}
+
+// 设置了no_new_privs之后，root进程不能通过execve重新获得已经放弃的capability
+static void child_exec_no_new_privs(void)
+{
+    struct caps caps;
+    memset(&caps, 0, sizeof(caps));
+    caps.data[0].permitted = CAP_BIT(CAP_SETUID);
+    caps.data[0].effective = CAP_BIT(CAP_SETUID);
+    assert(set_caps(&caps) == 0);
+    assert(prctl(PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) == 0);
+    exec_self(self_path, "exec-no-new-privs");
+}
+
+static int exec_no_new_privs_check(void)
+{
+    struct caps caps;
+    get_caps(&caps);
+    assert(caps.data[0].permitted == CAP_BIT(CAP_SETUID));
+    assert(caps.data[0].effective == CAP_BIT(CAP_SETUID));
+    assert(caps.data[1].permitted == 0);
+    return 0;
+}
+
+// 复制自身，并设置属主与权限
+static void make_setid_copy(uid_t uid, gid_t gid, mode_t mode)
+{
+    unlink(SETID_COPY);
+    int in = open(self_path, O_RDONLY);
+    assert(in >= 0);
+    int out = open(SETID_COPY, O_WRONLY | O_CREAT | O_EXCL, 0700);
+    assert(out >= 0);
+    char buf[4096];
+    ssize_t len;
+    while ((len = read(in, buf, sizeof(buf))) > 0)
+        assert(write(out, buf, len) == len);
+    assert(len == 0);
+    close(in);
+    close(out);
+    assert(chown(SETID_COPY, uid, gid) == 0);
+    assert(chmod(SETID_COPY, mode) == 0);
+}
+
+static void child_exec_setuid_root(void)
+{
+    assert(setresgid(1000, 1000, 1000) == 0);
+    assert(setresuid(1000, 1000, 1000) == 0);
+    exec_self(SETID_COPY, "exec-setuid-root");
+}
+
+static int exec_setuid_root_check(void)
+{
+    uid_t ruid, euid, suid;
+    assert(getresuid(&ruid, &euid, &suid) == 0);
+    assert(ruid == 1000 && euid == 0 && suid == 0);
+    assert(setfsuid(-1) == 0);
+    // euid为0时获得bounding集合中的所有capability
+    assert(has_effective(CAP_SETUID));
+    assert(has_permitted(CAP_SYS_ADMIN));
+    return 0;
+}
+
+static void child_exec_setuid_no_new_privs(void)
+{
+    assert(setresgid(1000, 1000, 1000) == 0);
+    assert(setresuid(1000, 1000, 1000) == 0);
+    assert(prctl(PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) == 0);
+    exec_self(SETID_COPY, "exec-setuid-no-new-privs");
+}
+
+static int exec_setuid_no_new_privs_check(void)
+{
+    // set-user-ID位被忽略
+    assert(getuid() == 1000 && geteuid() == 1000);
+    assert(!has_permitted(CAP_SETUID));
+    return 0;
+}
+
+static void child_exec_setgid(void)
+{
+    assert(setresgid(1000, 1000, 1000) == 0);
+    assert(setresuid(1000, 1000, 1000) == 0);
+    exec_self(SETID_COPY, "exec-setgid");
+}
+
+static int exec_setgid_check(void)
+{
+    gid_t rgid, egid, sgid;
+    assert(getresgid(&rgid, &egid, &sgid) == 0);
+    assert(rgid == 1000 && egid == 50 && sgid == 50);
+    assert(geteuid() == 1000);
+    assert(!has_permitted(CAP_SETUID));
+    return 0;
+}
+
+static void child_exec_setgid_noexec(void)
+{
+    assert(setresgid(1000, 1000, 1000) == 0);
+    assert(setresuid(1000, 1000, 1000) == 0);
+    exec_self(SETID_COPY, "exec-setgid-noexec");
+}
+
+static int exec_setgid_noexec_check(void)
+{
+    // 没有组执行权限的set-group-ID位不改变egid
+    assert(getegid() == 1000);
+    return 0;
+}
+
+// 以uid 1000执行自身，ambient集合中的CAP_NET_RAW在execve后成为permitted与effective集合
+static void child_exec_ambient(void)
+{
+    assert(prctl(PR_SET_KEEPCAPS, 1) == 0);
+    assert(setresuid(1000, 1000, 1000) == 0);
+
+    struct caps caps;
+    get_caps(&caps);
+    caps.data[0].inheritable = CAP_BIT(CAP_NET_RAW);
+    caps.data[0].effective = 0;
+    assert(set_caps(&caps) == 0);
+    assert(prctl(PR_CAP_AMBIENT, PR_CAP_AMBIENT_RAISE, CAP_NET_RAW, 0, 0) == 0);
+
+    exec_self(self_path, "exec-ambient");
+}
+
+static int exec_ambient_check(void)
+{
+    struct caps caps;
+    get_caps(&caps);
+    assert(getuid() == 1000 && geteuid() == 1000);
+    assert(caps.data[0].permitted == CAP_BIT(CAP_NET_RAW));
+    assert(caps.data[0].effective == CAP_BIT(CAP_NET_RAW));
+    assert(caps.data[1].permitted == 0);
+    assert(prctl(PR_GET_KEEPCAPS) == 0);
+    return 0;
+}
+
+int main(int argc, char **argv)
+{
+    if (argc > 1)
+    {
+        if (strcmp(argv[1], "exec-ambient") == 0)
+            return exec_ambient_check();
+        if (strcmp(argv[1], "exec-root") == 0)
+            return exec_root_check();
+        if (strcmp(argv[1], "exec-no-new-privs") == 0)
+            return exec_no_new_privs_check();
+        if (strcmp(argv[1], "exec-setuid-root") == 0)
+            return exec_setuid_root_check();
+        if (strcmp(argv[1], "exec-setuid-no-new-privs") == 0)
+            return exec_setuid_no_new_privs_check();
+        if (strcmp(argv[1], "exec-setgid") == 0)
+            return exec_setgid_check();
+        if (strcmp(argv[1], "exec-setgid-noexec") == 0)
+            return exec_setgid_noexec_check();
+        return 1;
+    }
+    // 没有路径分隔符时，通过PATH找到了自身，使用安装路径
+    self_path = strchr(argv[0], '/') ? argv[0] : "/bin/test_capability";
+    setbuf(stdout, NULL);
+
+    if (getuid() != 0)
+    {
+        printf("test_capability must be run as root\n");
+        return 1;
+    }
+    assert(has_effective(CAP_SETUID));
+
+    test_version();
+
+    run_in_child(child_capset);
+    printf("capset ok\n");
+
+    run_in_child(child_bounding_set);
+    printf("bounding set ok\n");
+
+    run_in_child(child_setuid_drop);
+    printf("setuid drops capabilities ok\n");
+
+    run_in_child(child_keep_caps);
+    printf("PR_SET_KEEPCAPS ok\n");
+
+    run_in_child(child_seteuid);
+    printf("seteuid ok\n");
+
+    run_in_child(child_setfsuid);
+    printf("setfsuid ok\n");
+
+    run_in_child(child_ambient);
+    printf("ambient set ok\n");
+
+    run_in_child(child_exec_ambient);
+    printf("ambient capabilities survive execve ok\n");
+
+    run_in_child(child_exec_root);
+    printf("execve as root ok\n");
+
+    run_in_child(child_exec_no_new_privs);
+    printf("execve with no_new_privs ok\n");
+
+    make_setid_copy(0, 0, 04755);
+    run_in_child(child_exec_setuid_root);
+    run_in_child(child_exec_setuid_no_new_privs);
+    printf("set-user-ID execve ok\n");
+
+    make_setid_copy(0, 50, 02755);
+    run_in_child(child_exec_setgid);
+    assert(chmod(SETID_COPY, 02745) == 0);
+    run_in_child(child_exec_setgid_noexec);
+    assert(unlink(SETID_COPY) == 0);
+    printf("set-group-ID execve ok\n");
+
+    printf("All capability tests passed\n");
+    return 0;
+}
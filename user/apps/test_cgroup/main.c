@@ -0,0 +1,350 @@
+// 测试cgroup v2：创建与删除cgroup、迁移进程、pids与memory控制器，以及clone3的CLONE_INTO_CGROUP
+#define _GNU_SOURCE
+#include <assert.h>
+#include <errno.h>
+#include <fcntl.h>
+#include <signal.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <sys/mman.h>
+#include <sys/stat.h>
+#include <sys/syscall.h>
+#include <sys/wait.h>
+#include <unistd.h>
+
+#define CGROUP_ROOT "/sys/fs/cgroup"
+#define TEST_CGROUP CGROUP_ROOT "/test_cgroup"
+
+#ifndef SYS_clone3
+#define SYS_clone3 435
+#endif
+
+#ifndef CLONE_INTO_CGROUP
+#define CLONE_INTO_CGROUP 0x200000000ULL
+#endif
+
+struct clone_args_v2
+{
+    uint64_t flags;
+    uint64_t pidfd;
+    uint64_t child_tid;
+    uint64_t parent_tid;
+    uint64_t exit_signal;
+    uint64_t stack;
+    uint64_t stack_size;
+    uint64_t tls;
+    uint64_t set_tid;
+    uint64_t set_tid_size;
+    uint64_t cgroup;
+};
+
+// 写入cgroup的接口文件，成功时返回0，失败时返回-errno
+static int write_file(const char *path, const char *value)
+{
+    int fd = open(path, O_WRONLY);
+    if (fd < 0)
+        return -errno;
+    ssize_t ret = write(fd, value, strlen(value));
+    int err = errno;
+    close(fd);
+    return ret < 0 ? -err : 0;
+}
+
+static void read_file(const char *path, char *buf, size_t size)
+{
+    int fd = open(path, O_RDONLY);
+    assert(fd >= 0);
+    ssize_t len = read(fd, buf, size - 1);
+    assert(len >= 0);
+    buf[len] = '\0';
+    close(fd);
+}
+
+static long read_number(const char *path)
+{
+    char buf[64];
+    read_file(path, buf, sizeof(buf));
+    return strtol(buf, NULL, 10);
+}
+
+// 读取形如"key value"的多行文件中key对应的值
+static long read_key(const char *path, const char *key)
+{
+    char buf[256];
+    read_file(path, buf, sizeof(buf));
+    size_t key_len = strlen(key);
+    for (char *line = strtok(buf, "\n"); line; line = strtok(NULL, "\n"))
+    {
+        if (strncmp(line, key, key_len) == 0 && line[key_len] == ' ')
+            return strtol(line + key_len + 1, NULL, 10);
+    }
+    return -1;
+}
+
+static void enter_cgroup(const char *cgroup)
+{
+    char path[128];
+    snprintf(path, sizeof(path), "%s/cgroup.procs", cgroup);
+    assert(write_file(path, "0") == 0);
+}
+
+static void test_hierarchy(void)
+{
+    char buf[256];
+    assert(mkdir(TEST_CGROUP "/a", 0755) == 0);
+    assert(mkdir(TEST_CGROUP "/a", 0755) == -1 && errno == EEXIST);
+    assert(mkdir(TEST_CGROUP "/a/b", 0755) == 0);
+
+    read_file(TEST_CGROUP "/a/cgroup.controllers", buf, sizeof(buf));
+    assert(strstr(buf, "pids") && strstr(buf, "memory"));
+    read_file(TEST_CGROUP "/a/pids.max", buf, sizeof(buf));
+    assert(strcmp(buf, "max\n") == 0);
+    read_file(TEST_CGROUP "/a/memory.max", buf, sizeof(buf));
+    assert(strcmp(buf, "max\n") == 0);
+
+    // 非法的上限
+    assert(write_file(TEST_CGROUP "/a/pids.max", "-1") == -EINVAL);
+    assert(write_file(TEST_CGROUP "/a/pids.max", "MAX") == -EINVAL);
+    assert(write_file(TEST_CGROUP "/a/pids.max", "99999999999999999999") == -EINVAL);
+    // pids.max不接受单位后缀
+    assert(write_file(TEST_CGROUP "/a/pids.max", "4K") == -EINVAL);
+    assert(write_file(TEST_CGROUP "/a/pids.max", "10") == 0);
+    assert(read_number(TEST_CGROUP "/a/pids.max") == 10);
+    assert(write_file(TEST_CGROUP "/a/pids.max", "max") == 0);
+    assert(write_file(TEST_CGROUP "/a/memory.max", "8M") == 0);
+    assert(read_number(TEST_CGROUP "/a/memory.max") == 8 << 20);
+    assert(write_file(TEST_CGROUP "/a/memory.max", "4k") == 0);
+    assert(read_number(TEST_CGROUP "/a/memory.max") == 4096);
+    assert(write_file(TEST_CGROUP "/a/memory.max", "1X") == -EINVAL);
+    // 乘上单位之后溢出
+    assert(write_file(TEST_CGROUP "/a/memory.max", "16777216T") == -EINVAL);
+    assert(write_file(TEST_CGROUP "/a/memory.max", "max") == 0);
+
+    // 根cgroup中没有控制器的接口文件
+    assert(access(CGROUP_ROOT "/cgroup.procs", F_OK) == 0);
+    assert(access(CGROUP_ROOT "/pids.max", F_OK) == -1 && errno == ENOENT);
+    assert(access(CGROUP_ROOT "/memory.max", F_OK) == -1 && errno == ENOENT);
+
+    // 有子cgroup时不能删除
+    assert(rmdir(TEST_CGROUP "/a") == -1 && errno == EBUSY);
+
+    // 有进程时不能删除
+    pid_t pid = fork();
+    assert(pid >= 0);
+    if (pid == 0)
+    {
+        enter_cgroup(TEST_CGROUP "/a/b");
+        pause();
+        _exit(0);
+    }
+    // 等待子进程完成迁移
+    while (read_number(TEST_CGROUP "/a/b/pids.current") != 1)
+        usleep(1000);
+    assert(read_number(TEST_CGROUP "/a/pids.current") == 1);
+    char procfs_path[64];
+    snprintf(procfs_path, sizeof(procfs_path), "/proc/%d/cgroup", pid);
+    read_file(procfs_path, buf, sizeof(buf));
+    assert(strcmp(buf, "0::/test_cgroup/a/b\n") == 0);
+    assert(rmdir(TEST_CGROUP "/a/b") == -1 && errno == EBUSY);
+
+    kill(pid, SIGKILL);
+    assert(waitpid(pid, NULL, 0) == pid);
+    assert(read_number(TEST_CGROUP "/a/pids.current") == 0);
+    assert(rmdir(TEST_CGROUP "/a/b") == 0);
+    assert(rmdir(TEST_CGROUP "/a") == 0);
+    assert(access(TEST_CGROUP "/a", F_OK) == -1 && errno == ENOENT);
+    printf("cgroup hierarchy ok\n");
+}
+
+static void test_pids_max(void)
+{
+    assert(mkdir(TEST_CGROUP "/pids", 0755) == 0);
+    assert(write_file(TEST_CGROUP "/pids/pids.max", "3") == 0);
+
+    pid_t pid = fork();
+    assert(pid >= 0);
+    if (pid == 0)
+    {
+        enter_cgroup(TEST_CGROUP "/pids");
+        // 当前进程占用一个名额，只能再创建两个子进程
+        pid_t children[4];
+        int created = 0;
+        for (int i = 0; i < 4; i++)
+        {
+            pid_t child = fork();
+            if (child == 0)
+            {
+                pause();
+                _exit(0);
+            }
+            if (child < 0)
+            {
+                assert(errno == EAGAIN);
+                continue;
+            }
+            children[created++] = child;
+        }
+        assert(created == 2);
+        assert(read_number(TEST_CGROUP "/pids/pids.current") == 3);
+        assert(read_key(TEST_CGROUP "/pids/pids.events", "max") == 2);
+        for (int i = 0; i < created; i++)
+        {
+            kill(children[i], SIGKILL);
+            waitpid(children[i], NULL, 0);
+        }
+        assert(read_number(TEST_CGROUP "/pids/pids.current") == 1);
+        _exit(0);
+    }
+    int status;
+    assert(waitpid(pid, &status, 0) == pid);
+    assert(WIFEXITED(status) && WEXITSTATUS(status) == 0);
+    assert(read_number(TEST_CGROUP "/pids/pids.current") == 0);
+    assert(rmdir(TEST_CGROUP "/pids") == 0);
+    printf("pids.max ok\n");
+}
+
+static void test_pids_max_ancestor(void)
+{
+    assert(mkdir(TEST_CGROUP "/limited", 0755) == 0);
+    assert(write_file(TEST_CGROUP "/limited/cgroup.subtree_control", "+pids") == 0);
+    assert(mkdir(TEST_CGROUP "/limited/b", 0755) == 0);
+    assert(mkdir(TEST_CGROUP "/limited/c", 0755) == 0);
+    assert(write_file(TEST_CGROUP "/limited/pids.max", "3") == 0);
+
+    // b中有两个进程
+    pid_t holders[2];
+    for (int i = 0; i < 2; i++)
+    {
+        holders[i] = fork();
+        assert(holders[i] >= 0);
+        if (holders[i] == 0)
+        {
+            enter_cgroup(TEST_CGROUP "/limited/b");
+            pause();
+            _exit(0);
+        }
+    }
+    while (read_number(TEST_CGROUP "/limited/b/pids.current") != 2)
+        usleep(1000);
+
+    pid_t pid = fork();
+    assert(pid >= 0);
+    if (pid == 0)
+    {
+        enter_cgroup(TEST_CGROUP "/limited/c");
+        // c自身没有上限，但是祖先的上限只剩下当前进程占用的一个名额
+        pid_t child = fork();
+        if (child == 0)
+            _exit(0);
+        assert(child == -1 && errno == EAGAIN);
+        // 失败的计数不会残留在c与它的祖先中
+        assert(read_number(TEST_CGROUP "/limited/c/pids.current") == 1);
+        assert(read_number(TEST_CGROUP "/limited/pids.current") == 3);
+        _exit(0);
+    }
+    int status;
+    assert(waitpid(pid, &status, 0) == pid);
+    assert(WIFEXITED(status) && WEXITSTATUS(status) == 0);
+
+    for (int i = 0; i < 2; i++)
+    {
+        kill(holders[i], SIGKILL);
+        assert(waitpid(holders[i], NULL, 0) == holders[i]);
+    }
+    assert(read_number(TEST_CGROUP "/limited/pids.current") == 0);
+    assert(rmdir(TEST_CGROUP "/limited/b") == 0);
+    assert(rmdir(TEST_CGROUP "/limited/c") == 0);
+    assert(rmdir(TEST_CGROUP "/limited") == 0);
+    printf("ancestor pids.max ok\n");
+}
+
+static void test_memory_max(void)
+{
+    assert(mkdir(TEST_CGROUP "/memory", 0755) == 0);
+    assert(write_file(TEST_CGROUP "/memory/memory.max", "4M") == 0);
+
+    pid_t pid = fork();
+    assert(pid >= 0);
+    if (pid == 0)
+    {
+        enter_cgroup(TEST_CGROUP "/memory");
+        size_t size = 16 << 20;
+        char *p = mmap(NULL, size, PROT_READ | PROT_WRITE, MAP_PRIVATE | MAP_ANONYMOUS, -1, 0);
+        assert(p != MAP_FAILED);
+        // 逐页写入，超出memory.max之后进程会被杀死
+        for (size_t i = 0; i < size; i += 4096)
+            p[i] = 1;
+        _exit(1);
+    }
+    int status;
+    assert(waitpid(pid, &status, 0) == pid);
+    assert(WIFSIGNALED(status) && WTERMSIG(status) == SIGKILL);
+    assert(read_key(TEST_CGROUP "/memory/memory.events", "oom_kill") >= 1);
+    assert(read_number(TEST_CGROUP "/memory/memory.current") <= 4 << 20);
+    assert(rmdir(TEST_CGROUP "/memory") == 0);
+    printf("memory.max ok\n");
+}
+
+static void test_clone_into_cgroup(void)
+{
+    assert(mkdir(TEST_CGROUP "/clone3", 0755) == 0);
+    int cgroup_fd = open(TEST_CGROUP "/clone3", O_RDONLY | O_DIRECTORY);
+    assert(cgroup_fd >= 0);
+
+    struct clone_args_v2 args;
+    memset(&args, 0, sizeof(args));
+    args.flags = CLONE_INTO_CGROUP;
+    args.exit_signal = SIGCHLD;
+    args.cgroup = cgroup_fd;
+    pid_t pid = syscall(SYS_clone3, &args, sizeof(args));
+    assert(pid >= 0);
+    if (pid == 0)
+    {
+        char path[64];
+        char buf[128];
+        snprintf(path, sizeof(path), "/proc/%d/cgroup", (int)syscall(SYS_getpid));
+        read_file(path, buf, sizeof(buf));
+        _exit(strcmp(buf, "0::/test_cgroup/clone3\n") == 0 ? 0 : 1);
+    }
+    int status;
+    assert(waitpid(pid, &status, 0) == pid);
+    assert(WIFEXITED(status) && WEXITSTATUS(status) == 0);
+
+    // 不是cgroup目录的文件描述符
+    int fd = open("/", O_RDONLY | O_DIRECTORY);
+    args.cgroup = fd;
+    assert(syscall(SYS_clone3, &args, sizeof(args)) == -1);
+    assert(errno == EBADF);
+    close(fd);
+
+    // 旧版本的clone_args不包含cgroup字段
+    args.cgroup = cgroup_fd;
+    assert(syscall(SYS_clone3, &args, offsetof(struct clone_args_v2, cgroup)) == -1);
+    assert(errno == EINVAL);
+
+    close(cgroup_fd);
+    assert(rmdir(TEST_CGROUP "/clone3") == 0);
+    printf("CLONE_INTO_CGROUP ok\n");
+}
+
+int main()
+{
+    setbuf(stdout, NULL);
+    assert(write_file(CGROUP_ROOT "/cgroup.subtree_control", "+memory +pids") == 0);
+    rmdir(TEST_CGROUP);
+    assert(mkdir(TEST_CGROUP, 0755) == 0);
+    assert(write_file(TEST_CGROUP "/cgroup.subtree_control", "+memory +pids") == 0);
+
+    test_hierarchy();
+    test_pids_max();
+    test_pids_max_ancestor();
+    test_memory_max();
+    test_clone_into_cgroup();
+
+    assert(rmdir(TEST_CGROUP) == 0);
+    printf("All cgroup tests passed\n");
+    return 0;
+}
@@ -0,0 +1,257 @@
+// 测试clone3：参数检查、退出信号、CLONE_CLEAR_SIGHAND、set_tid与CLONE_PIDFD
+#define _GNU_SOURCE
+#include <assert.h>
+#include <errno.h>
+#include <sched.h>
+#include <signal.h>
+#include <stdint.h>
+#include <stdio.h>
+#include <string.h>
+#include <sys/syscall.h>
+#include <sys/wait.h>
+#include <unistd.h>
+
+#ifndef SYS_clone3
+#define SYS_clone3 435
+#endif
+
+#ifndef CLONE_PIDFD
+#define CLONE_PIDFD 0x00001000
+#endif
+
+#ifndef CLONE_CLEAR_SIGHAND
+#define CLONE_CLEAR_SIGHAND 0x100000000ULL
+#endif
+
+#ifndef CLONE_INTO_CGROUP
+#define CLONE_INTO_CGROUP 0x200000000ULL
+#endif
+
+// 各个版本的clone_args的大小
+#define CLONE_ARGS_SIZE_VER0 64
+#define CLONE_ARGS_SIZE_VER1 80
+
+// pid namespace的最大嵌套层数
+#define MAX_PID_NS_LEVEL 32
+
+struct clone_args_v2
+{
+    uint64_t flags;
+    uint64_t pidfd;
+    uint64_t child_tid;
+    uint64_t parent_tid;
+    uint64_t exit_signal;
+    uint64_t stack;
+    uint64_t stack_size;
+    uint64_t tls;
+    uint64_t set_tid;
+    uint64_t set_tid_size;
+    uint64_t cgroup;
+};
+
+static pid_t sys_clone3(struct clone_args_v2 *args, size_t size)
+{
+    return syscall(SYS_clone3, args, size);
+}
+
+static void wait_exit_code(pid_t pid, int code)
+{
+    int status;
+    assert(waitpid(pid, &status, 0) == pid);
+    assert(WIFEXITED(status) && WEXITSTATUS(status) == code);
+}
+
+static void test_invalid_args(void)
+{
+    struct clone_args_v2 args;
+
+    // 结构体太小
+    memset(&args, 0, sizeof(args));
+    assert(sys_clone3(&args, CLONE_ARGS_SIZE_VER0 - 8) == -1 && errno == EINVAL);
+
+    // 内核不认识的字段不为0
+    char big[sizeof(args) + 8];
+    memset(big, 0, sizeof(big));
+    big[sizeof(args)] = 1;
+    assert(syscall(SYS_clone3, big, sizeof(big)) == -1 && errno == E2BIG);
+
+    // 只给出了栈的地址，没有给出大小
+    args.stack = (uint64_t)(uintptr_t)big;
+    assert(sys_clone3(&args, sizeof(args)) == -1 && errno == EINVAL);
+
+    // 只给出了栈的大小，没有给出地址
+    memset(&args, 0, sizeof(args));
+    args.stack_size = 0x1000;
+    assert(sys_clone3(&args, sizeof(args)) == -1 && errno == EINVAL);
+
+    // 栈的末尾超出地址空间
+    args.stack = UINT64_MAX - 0x10;
+    assert(sys_clone3(&args, sizeof(args)) == -1 && errno == EINVAL);
+
+    // 未知的标志位
+    memset(&args, 0, sizeof(args));
+    args.flags = 1ULL << 40;
+    assert(sys_clone3(&args, sizeof(args)) == -1 && errno == EINVAL);
+
+    // 不能既共享信号处理函数，又将其重置
+    args.flags = CLONE_SIGHAND | CLONE_VM | CLONE_CLEAR_SIGHAND;
+    assert(sys_clone3(&args, sizeof(args)) == -1 && errno == EINVAL);
+
+    // 非法的退出信号
+    memset(&args, 0, sizeof(args));
+    args.exit_signal = 0x100 | SIGCHLD;
+    assert(sys_clone3(&args, sizeof(args)) == -1 && errno == EINVAL);
+
+    // 线程不能指定退出信号
+    args.flags = CLONE_THREAD | CLONE_SIGHAND | CLONE_VM;
+    args.exit_signal = SIGCHLD;
+    assert(sys_clone3(&args, sizeof(args)) == -1 && errno == EINVAL);
+
+    // set_tid与set_tid_size必须同时给出
+    memset(&args, 0, sizeof(args));
+    args.set_tid_size = 1;
+    assert(sys_clone3(&args, sizeof(args)) == -1 && errno == EINVAL);
+    int32_t set_tid[MAX_PID_NS_LEVEL + 1] = {0};
+    memset(&args, 0, sizeof(args));
+    args.set_tid = (uint64_t)(uintptr_t)set_tid;
+    assert(sys_clone3(&args, sizeof(args)) == -1 && errno == EINVAL);
+
+    // set_tid的层数超过pid namespace的最大嵌套层数
+    args.set_tid_size = MAX_PID_NS_LEVEL + 1;
+    assert(sys_clone3(&args, sizeof(args)) == -1 && errno == EINVAL);
+
+    // 旧版本的结构体中没有cgroup字段
+    memset(&args, 0, sizeof(args));
+    args.flags = CLONE_INTO_CGROUP;
+    assert(sys_clone3(&args, CLONE_ARGS_SIZE_VER1) == -1 && errno == EINVAL);
+
+    // cgroup不是合法的文件描述符
+    args.cgroup = (uint64_t)INT32_MAX + 1;
+    assert(sys_clone3(&args, sizeof(args)) == -1 && errno == EINVAL);
+    printf("invalid clone3 arguments rejected\n");
+}
+
+static void test_basic(void)
+{
+    struct clone_args_v2 args;
+    memset(&args, 0, sizeof(args));
+    args.exit_signal = SIGCHLD;
+    // 使用第一个版本的结构体
+    pid_t pid = sys_clone3(&args, CLONE_ARGS_SIZE_VER0);
+    assert(pid >= 0);
+    if (pid == 0)
+        _exit(7);
+    wait_exit_code(pid, 7);
+    printf("clone3 basic ok\n");
+}
+
+static void handler(int sig)
+{
+    (void)sig;
+}
+
+static void test_clear_sighand(void)
+{
+    struct sigaction sa;
+    memset(&sa, 0, sizeof(sa));
+    sa.sa_handler = handler;
+    assert(sigaction(SIGUSR1, &sa, NULL) == 0);
+    sa.sa_handler = SIG_IGN;
+    assert(sigaction(SIGUSR2, &sa, NULL) == 0);
+
+    struct clone_args_v2 args;
+    memset(&args, 0, sizeof(args));
+    args.flags = CLONE_CLEAR_SIGHAND;
+    args.exit_signal = SIGCHLD;
+    pid_t pid = sys_clone3(&args, sizeof(args));
+    assert(pid >= 0);
+    if (pid == 0)
+    {
+        struct sigaction old;
+        // 信号处理函数被重置为默认，被忽略的信号仍然被忽略
+        if (sigaction(SIGUSR1, NULL, &old) != 0 || old.sa_handler != SIG_DFL)
+            _exit(1);
+        if (sigaction(SIGUSR2, NULL, &old) != 0 || old.sa_handler != SIG_IGN)
+            _exit(2);
+        _exit(0);
+    }
+    wait_exit_code(pid, 0);
+
+    // 父进程的信号处理函数不受影响
+    struct sigaction old;
+    assert(sigaction(SIGUSR1, NULL, &old) == 0 && old.sa_handler == handler);
+    signal(SIGUSR1, SIG_DFL);
+    signal(SIGUSR2, SIG_DFL);
+    printf("CLONE_CLEAR_SIGHAND ok\n");
+}
+
+static void test_set_tid(void)
+{
+    // 找一个没有被使用的pid
+    pid_t tid = getpid() + 100;
+    while (kill(tid, 0) == 0 || errno != ESRCH)
+        tid++;
+
+    int32_t set_tid[1] = {tid};
+    struct clone_args_v2 args;
+    memset(&args, 0, sizeof(args));
+    args.exit_signal = SIGCHLD;
+    args.set_tid = (uint64_t)(uintptr_t)set_tid;
+    args.set_tid_size = 1;
+    pid_t pid = sys_clone3(&args, sizeof(args));
+    assert(pid >= 0);
+    if (pid == 0)
+    {
+        pause();
+        _exit(0);
+    }
+    assert(pid == tid);
+
+    // pid已经被使用
+    assert(sys_clone3(&args, sizeof(args)) == -1 && errno == EEXIST);
+
+    kill(pid, SIGKILL);
+    assert(waitpid(pid, NULL, 0) == pid);
+
+    // pid必须为正数
+    set_tid[0] = -1;
+    assert(sys_clone3(&args, sizeof(args)) == -1 && errno == EINVAL);
+    printf("set_tid ok\n");
+}
+
+static void test_pidfd(void)
+{
+    int pidfd = -1;
+    struct clone_args_v2 args;
+    memset(&args, 0, sizeof(args));
+    args.flags = CLONE_PIDFD;
+    args.pidfd = (uint64_t)(uintptr_t)&pidfd;
+    args.exit_signal = SIGCHLD;
+    pid_t pid = sys_clone3(&args, sizeof(args));
+    assert(pid >= 0);
+    if (pid == 0)
+    {
+        pause();
+        _exit(0);
+    }
+    assert(pidfd >= 0);
+    // 通过pidfd向子进程发送信号
+    assert(syscall(SYS_pidfd_send_signal, pidfd, SIGKILL, NULL, 0) == 0);
+    int status;
+    assert(waitpid(pid, &status, 0) == pid);
+    assert(WIFSIGNALED(status) && WTERMSIG(status) == SIGKILL);
+    close(pidfd);
+    printf("CLONE_PIDFD ok\n");
+}
+
+int main()
+{
+    setbuf(stdout, NULL);
+    test_invalid_args();
+    test_basic();
+    test_clear_sighand();
+    test_set_tid();
+    test_pidfd();
+    printf("All clone3 tests passed\n");
+    return 0;
+}
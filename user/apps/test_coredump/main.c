@@ -0,0 +1,230 @@
+// 测试core dump：core文件的内容与权限、RLIMIT_CORE与PR_SET_DUMPABLE，以及目录权限检查与已存在的文件
+#define _GNU_SOURCE
+#include <assert.h>
+#include <elf.h>
+#include <errno.h>
+#include <fcntl.h>
+#include <signal.h>
+#include <stdio.h>
+#include <string.h>
+#include <sys/prctl.h>
+#include <sys/resource.h>
+#include <sys/stat.h>
+#include <sys/wait.h>
+#include <unistd.h>
+
+#define CORE_PATTERN "/proc/sys/kernel/core_pattern"
+#define TEST_DIR "/tmp/test_coredump"
+#define RO_DIR TEST_DIR "/ro"
+#define RW_DIR TEST_DIR "/rw"
+#define NOBODY 65534
+
+static char old_pattern[256];
+
+static void set_core_pattern(const char *pattern)
+{
+    int fd = open(CORE_PATTERN, O_WRONLY | O_TRUNC);
+    assert(fd >= 0);
+    assert(write(fd, pattern, strlen(pattern)) == (ssize_t)strlen(pattern));
+    close(fd);
+}
+
+// 子进程在`setup`之后收到SIGQUIT；`before_crash`在子进程收到信号之前由父进程执行
+static int crash_child(void (*setup)(void), void (*before_crash)(pid_t), pid_t *child)
+{
+    int sync[2];
+    assert(pipe(sync) == 0);
+    pid_t pid = fork();
+    assert(pid >= 0);
+    if (pid == 0)
+    {
+        struct rlimit rl = {RLIM_INFINITY, RLIM_INFINITY};
+        assert(setrlimit(RLIMIT_CORE, &rl) == 0);
+        if (setup)
+            setup();
+        char c;
+        assert(read(sync[0], &c, 1) == 1);
+        kill(getpid(), SIGQUIT);
+        _exit(0);
+    }
+    if (before_crash)
+        before_crash(pid);
+    assert(write(sync[1], "x", 1) == 1);
+    close(sync[0]);
+    close(sync[1]);
+
+    int status;
+    assert(waitpid(pid, &status, 0) == pid);
+    assert(WIFSIGNALED(status) && WTERMSIG(status) == SIGQUIT);
+    *child = pid;
+    return WCOREDUMP(status) != 0;
+}
+
+static void core_path(char *buf, size_t size, const char *dir, pid_t pid)
+{
+    snprintf(buf, size, "%s/core.%d", dir, pid);
+}
+
+// 检查core文件是一个ET_CORE类型的ELF文件，并返回它的属主
+static uid_t check_core_file(const char *path)
+{
+    int fd = open(path, O_RDONLY);
+    assert(fd >= 0);
+    Elf64_Ehdr ehdr;
+    assert(read(fd, &ehdr, sizeof(ehdr)) == sizeof(ehdr));
+    assert(memcmp(ehdr.e_ident, ELFMAG, SELFMAG) == 0);
+    assert(ehdr.e_type == ET_CORE);
+    assert(ehdr.e_phnum > 1);
+
+    // 第一个程序头是PT_NOTE段
+    Elf64_Phdr phdr;
+    assert(pread(fd, &phdr, sizeof(phdr), ehdr.e_phoff) == sizeof(phdr));
+    assert(phdr.p_type == PT_NOTE);
+
+    struct stat st;
+    assert(fstat(fd, &st) == 0);
+    assert(S_ISREG(st.st_mode) && (st.st_mode & 0777) == 0600);
+    assert(st.st_nlink == 1);
+    close(fd);
+    return st.st_uid;
+}
+
+static void test_core_file(void)
+{
+    set_core_pattern(TEST_DIR "/core.%p");
+    pid_t pid;
+    assert(crash_child(NULL, NULL, &pid));
+    char path[128];
+    core_path(path, sizeof(path), TEST_DIR, pid);
+    assert(check_core_file(path) == 0);
+    assert(unlink(path) == 0);
+    printf("core file ok\n");
+}
+
+static void disable_rlimit_core(void)
+{
+    struct rlimit rl = {0, 0};
+    assert(setrlimit(RLIMIT_CORE, &rl) == 0);
+}
+
+static void disable_dumpable(void)
+{
+    assert(prctl(PR_SET_DUMPABLE, 0) == 0);
+    assert(prctl(PR_GET_DUMPABLE) == 0);
+}
+
+static void test_no_dump(void)
+{
+    pid_t pid;
+    char path[128];
+    // RLIMIT_CORE为0时不生成core文件
+    assert(!crash_child(disable_rlimit_core, NULL, &pid));
+    core_path(path, sizeof(path), TEST_DIR, pid);
+    assert(access(path, F_OK) == -1 && errno == ENOENT);
+
+    // 不可dump的进程
+    assert(!crash_child(disable_dumpable, NULL, &pid));
+    core_path(path, sizeof(path), TEST_DIR, pid);
+    assert(access(path, F_OK) == -1 && errno == ENOENT);
+    printf("RLIMIT_CORE and PR_SET_DUMPABLE ok\n");
+}
+
+static void become_nobody(void)
+{
+    assert(setresgid(NOBODY, NOBODY, NOBODY) == 0);
+    assert(setresuid(NOBODY, NOBODY, NOBODY) == 0);
+    // 改变身份之后进程变为不可dump
+    assert(prctl(PR_SET_DUMPABLE, 1) == 0);
+}
+
+static void test_dir_permission(void)
+{
+    assert(mkdir(RO_DIR, 0755) == 0);
+    assert(mkdir(RW_DIR, 0755) == 0);
+    assert(chown(RW_DIR, NOBODY, NOBODY) == 0);
+    pid_t pid;
+    char path[128];
+
+    // 没有写权限的目录中不能生成core文件
+    set_core_pattern(RO_DIR "/core.%p");
+    assert(!crash_child(become_nobody, NULL, &pid));
+    core_path(path, sizeof(path), RO_DIR, pid);
+    assert(access(path, F_OK) == -1 && errno == ENOENT);
+
+    // core文件属于进程的fsuid
+    set_core_pattern(RW_DIR "/core.%p");
+    assert(crash_child(become_nobody, NULL, &pid));
+    core_path(path, sizeof(path), RW_DIR, pid);
+    assert(check_core_file(path) == NOBODY);
+    assert(unlink(path) == 0);
+
+    assert(rmdir(RO_DIR) == 0);
+    assert(rmdir(RW_DIR) == 0);
+    printf("directory permission ok\n");
+}
+
+static void make_symlink(pid_t pid)
+{
+    char path[128];
+    core_path(path, sizeof(path), TEST_DIR, pid);
+    assert(symlink(TEST_DIR "/victim", path) == 0);
+}
+
+static void make_old_core(pid_t pid)
+{
+    char path[128];
+    core_path(path, sizeof(path), TEST_DIR, pid);
+    int fd = open(path, O_WRONLY | O_CREAT, 0644);
+    assert(fd >= 0);
+    assert(write(fd, "old core", 8) == 8);
+    close(fd);
+}
+
+static void test_existing_file(void)
+{
+    set_core_pattern(TEST_DIR "/core.%p");
+    int fd = open(TEST_DIR "/victim", O_WRONLY | O_CREAT, 0644);
+    assert(fd >= 0);
+    assert(write(fd, "victim", 6) == 6);
+    close(fd);
+
+    // 不跟随core路径上的符号链接，而是替换掉符号链接本身
+    pid_t pid;
+    char path[128];
+    assert(crash_child(NULL, make_symlink, &pid));
+    core_path(path, sizeof(path), TEST_DIR, pid);
+    check_core_file(path);
+    assert(unlink(path) == 0);
+    struct stat st;
+    assert(stat(TEST_DIR "/victim", &st) == 0 && st.st_size == 6);
+    assert(unlink(TEST_DIR "/victim") == 0);
+
+    // 已经存在的旧core文件被替换
+    assert(crash_child(NULL, make_old_core, &pid));
+    core_path(path, sizeof(path), TEST_DIR, pid);
+    check_core_file(path);
+    assert(unlink(path) == 0);
+    printf("existing core path ok\n");
+}
+
+int main()
+{
+    setbuf(stdout, NULL);
+    int fd = open(CORE_PATTERN, O_RDONLY);
+    assert(fd >= 0);
+    ssize_t len = read(fd, old_pattern, sizeof(old_pattern) - 1);
+    assert(len > 0);
+    old_pattern[len - 1] = '\0';
+    close(fd);
+    mkdir(TEST_DIR, 0755);
+
+    test_core_file();
+    test_no_dump();
+    test_dir_permission();
+    test_existing_file();
+
+    set_core_pattern(old_pattern);
+    assert(rmdir(TEST_DIR) == 0);
+    printf("All coredump tests passed\n");
+    return 0;
+}
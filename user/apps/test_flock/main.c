@@ -0,0 +1,297 @@
+// 测试文件锁：flock、POSIX记录锁（F_SETLK/F_SETLKW/F_GETLK）与OFD锁
+#define _GNU_SOURCE
+#include <assert.h>
+#include <errno.h>
+#include <fcntl.h>
+#include <signal.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <sys/file.h>
+#include <sys/wait.h>
+#include <unistd.h>
+
+#define TEST_FILE "/tmp/test_flock.txt"
+
+static int set_lock(int fd, int cmd, short type, off_t start, off_t len)
+{
+    struct flock fl;
+    memset(&fl, 0, sizeof(fl));
+    fl.l_type = type;
+    fl.l_whence = SEEK_SET;
+    fl.l_start = start;
+    fl.l_len = len;
+    return fcntl(fd, cmd, &fl);
+}
+
+static struct flock get_lock(int fd, int cmd, short type, off_t start, off_t len)
+{
+    struct flock fl;
+    memset(&fl, 0, sizeof(fl));
+    fl.l_type = type;
+    fl.l_whence = SEEK_SET;
+    fl.l_start = start;
+    fl.l_len = len;
+    assert(fcntl(fd, cmd, &fl) == 0);
+    return fl;
+}
+
+static int open_test_file(void)
+{
+    int fd = open(TEST_FILE, O_RDWR | O_CREAT, 0644);
+    assert(fd >= 0);
+    return fd;
+}
+
+// 在子进程中执行func，返回子进程的退出码
+static int run_in_child(int (*func)(void))
+{
+    pid_t pid = fork();
+    assert(pid >= 0);
+    if (pid == 0)
+        _exit(func());
+    int status;
+    assert(waitpid(pid, &status, 0) == pid);
+    assert(WIFEXITED(status));
+    return WEXITSTATUS(status);
+}
+
+static int child_try_write_lock_0_9(void)
+{
+    int fd = open_test_file();
+    if (set_lock(fd, F_SETLK, F_WRLCK, 0, 10) == 0)
+        return 0;
+    return errno == EAGAIN || errno == EACCES ? 1 : 2;
+}
+
+static int child_try_read_lock_0_9(void)
+{
+    int fd = open_test_file();
+    if (set_lock(fd, F_SETLK, F_RDLCK, 0, 10) == 0)
+        return 0;
+    return errno == EAGAIN || errno == EACCES ? 1 : 2;
+}
+
+static int child_try_write_lock_20_29(void)
+{
+    int fd = open_test_file();
+    if (set_lock(fd, F_SETLK, F_WRLCK, 20, 10) == 0)
+        return 0;
+    return errno == EAGAIN || errno == EACCES ? 1 : 2;
+}
+
+static pid_t parent_pid;
+
+static int child_getlk(void)
+{
+    int fd = open_test_file();
+    struct flock fl = get_lock(fd, F_GETLK, F_WRLCK, 0, 0);
+    if (fl.l_type != F_RDLCK || fl.l_start != 0 || fl.l_len != 10 || fl.l_pid != parent_pid)
+        return 1;
+    // 没有冲突时l_type被设置为F_UNLCK
+    fl = get_lock(fd, F_GETLK, F_WRLCK, 100, 10);
+    return fl.l_type == F_UNLCK ? 0 : 2;
+}
+
+static void test_posix_locks(void)
+{
+    parent_pid = getpid();
+    int fd = open_test_file();
+
+    assert(set_lock(fd, F_SETLK, F_RDLCK, 0, 10) == 0);
+    // 读锁之间不冲突，读锁与写锁冲突
+    assert(run_in_child(child_try_read_lock_0_9) == 0);
+    assert(run_in_child(child_try_write_lock_0_9) == 1);
+    assert(run_in_child(child_try_write_lock_20_29) == 0);
+    assert(run_in_child(child_getlk) == 0);
+
+    // 同一个进程可以直接把读锁升级为写锁
+    assert(set_lock(fd, F_SETLK, F_WRLCK, 0, 10) == 0);
+    assert(run_in_child(child_try_read_lock_0_9) == 1);
+
+    // 解锁一部分之后，另一部分仍然被锁住
+    assert(set_lock(fd, F_SETLK, F_UNLCK, 5, 5) == 0);
+    assert(run_in_child(child_try_write_lock_0_9) == 1);
+    assert(set_lock(fd, F_SETLK, F_UNLCK, 0, 0) == 0);
+    assert(run_in_child(child_try_write_lock_0_9) == 0);
+
+    // 关闭同一文件的任意一个文件描述符都会释放记录锁
+    int fd2 = open_test_file();
+    assert(set_lock(fd, F_SETLK, F_WRLCK, 0, 10) == 0);
+    close(fd2);
+    assert(run_in_child(child_try_write_lock_0_9) == 0);
+
+    // 以只读方式打开的文件不能加写锁
+    int rdonly = open(TEST_FILE, O_RDONLY);
+    assert(rdonly >= 0);
+    assert(set_lock(rdonly, F_SETLK, F_WRLCK, 0, 10) == -1 && errno == EBADF);
+    close(rdonly);
+
+    // 非法的范围
+    assert(set_lock(fd, F_SETLK, F_WRLCK, -1, 10) == -1 && errno == EINVAL);
+    close(fd);
+    printf("POSIX record locks ok\n");
+}
+
+static void test_setlkw_wakeup(void)
+{
+    int fd = open_test_file();
+    int pipefd[2];
+    assert(pipe(pipefd) == 0);
+
+    pid_t pid = fork();
+    assert(pid >= 0);
+    if (pid == 0)
+    {
+        int cfd = open_test_file();
+        assert(set_lock(cfd, F_SETLK, F_WRLCK, 0, 1) == 0);
+        write(pipefd[1], "x", 1);
+        usleep(100 * 1000);
+        // 解锁后父进程被唤醒
+        assert(set_lock(cfd, F_SETLK, F_UNLCK, 0, 1) == 0);
+        pause();
+        _exit(0);
+    }
+    char c;
+    assert(read(pipefd[0], &c, 1) == 1);
+    assert(set_lock(fd, F_SETLK, F_WRLCK, 0, 1) == -1);
+    assert(set_lock(fd, F_SETLKW, F_WRLCK, 0, 1) == 0);
+    kill(pid, SIGKILL);
+    waitpid(pid, NULL, 0);
+    close(fd);
+    close(pipefd[0]);
+    close(pipefd[1]);
+    printf("F_SETLKW wakeup ok\n");
+}
+
+static void test_deadlock(void)
+{
+    int fd = open_test_file();
+    int ready[2];
+    assert(pipe(ready) == 0);
+
+    assert(set_lock(fd, F_SETLK, F_WRLCK, 0, 1) == 0);
+    pid_t pid = fork();
+    assert(pid >= 0);
+    if (pid == 0)
+    {
+        int cfd = open_test_file();
+        assert(set_lock(cfd, F_SETLK, F_WRLCK, 1, 1) == 0);
+        write(ready[1], "x", 1);
+        // 等待父进程持有的锁
+        int ret = set_lock(cfd, F_SETLKW, F_WRLCK, 0, 1);
+        _exit(ret == 0 ? 0 : 1);
+    }
+    char c;
+    assert(read(ready[0], &c, 1) == 1);
+    // 等待子进程进入睡眠
+    usleep(100 * 1000);
+    // 子进程在等待父进程，父进程再等待子进程会形成死锁
+    assert(set_lock(fd, F_SETLKW, F_WRLCK, 1, 1) == -1 && errno == EDEADLK);
+    assert(set_lock(fd, F_SETLK, F_UNLCK, 0, 1) == 0);
+    int status;
+    assert(waitpid(pid, &status, 0) == pid);
+    assert(WIFEXITED(status) && WEXITSTATUS(status) == 0);
+    close(fd);
+    close(ready[0]);
+    close(ready[1]);
+    printf("EDEADLK detection ok\n");
+}
+
+static void test_ofd_locks(void)
+{
+    int fd1 = open_test_file();
+    int fd2 = open_test_file();
+
+    // OFD锁属于打开的文件，同一个进程的两个打开的文件之间也会冲突
+    assert(set_lock(fd1, F_OFD_SETLK, F_WRLCK, 0, 10) == 0);
+    assert(set_lock(fd2, F_OFD_SETLK, F_WRLCK, 0, 10) == -1 && errno == EAGAIN);
+    struct flock fl = get_lock(fd2, F_OFD_GETLK, F_RDLCK, 0, 1);
+    assert(fl.l_type == F_WRLCK && fl.l_pid == -1);
+
+    // OFD锁与POSIX记录锁冲突
+    assert(set_lock(fd2, F_SETLK, F_RDLCK, 5, 1) == -1);
+    // 通过dup得到的文件描述符共享同一个打开的文件
+    int fd3 = dup(fd1);
+    assert(set_lock(fd3, F_OFD_SETLK, F_WRLCK, 0, 20) == 0);
+    close(fd3);
+    assert(set_lock(fd2, F_OFD_SETLK, F_WRLCK, 0, 1) == -1);
+
+    // l_pid必须为0
+    struct flock bad;
+    memset(&bad, 0, sizeof(bad));
+    bad.l_type = F_RDLCK;
+    bad.l_pid = 1;
+    assert(fcntl(fd2, F_OFD_SETLK, &bad) == -1 && errno == EINVAL);
+
+    // 关闭文件后OFD锁被释放
+    close(fd1);
+    assert(set_lock(fd2, F_OFD_SETLK, F_WRLCK, 0, 10) == 0);
+    close(fd2);
+    printf("OFD locks ok\n");
+}
+
+static int child_flock_nb_ex(void)
+{
+    int fd = open_test_file();
+    if (flock(fd, LOCK_EX | LOCK_NB) == 0)
+        return 0;
+    return errno == EWOULDBLOCK ? 1 : 2;
+}
+
+static int child_flock_nb_sh(void)
+{
+    int fd = open_test_file();
+    if (flock(fd, LOCK_SH | LOCK_NB) == 0)
+        return 0;
+    return errno == EWOULDBLOCK ? 1 : 2;
+}
+
+static void test_flock(void)
+{
+    int fd = open_test_file();
+    assert(flock(fd, LOCK_SH) == 0);
+    assert(run_in_child(child_flock_nb_sh) == 0);
+    assert(run_in_child(child_flock_nb_ex) == 1);
+
+    // 转换为排他锁
+    assert(flock(fd, LOCK_EX) == 0);
+    assert(run_in_child(child_flock_nb_sh) == 1);
+
+    // flock锁与POSIX记录锁相互独立
+    int fd2 = open_test_file();
+    assert(set_lock(fd2, F_OFD_SETLK, F_WRLCK, 0, 0) == 0);
+    close(fd2);
+
+    // 同一个进程中另一个打开的文件也会冲突
+    int other = open_test_file();
+    assert(flock(other, LOCK_EX | LOCK_NB) == -1 && errno == EWOULDBLOCK);
+
+    assert(flock(fd, LOCK_UN) == 0);
+    assert(flock(other, LOCK_EX | LOCK_NB) == 0);
+    close(other);
+    assert(run_in_child(child_flock_nb_ex) == 0);
+
+    assert(flock(fd, LOCK_SH | LOCK_EX) == -1 && errno == EINVAL);
+    close(fd);
+    printf("flock ok\n");
+}
+
+int main()
+{
+    setbuf(stdout, NULL);
+    unlink(TEST_FILE);
+    int fd = open_test_file();
+    assert(write(fd, "0123456789abcdefghij", 20) == 20);
+    close(fd);
+
+    test_posix_locks();
+    test_setlkw_wakeup();
+    test_deadlock();
+    test_ofd_locks();
+    test_flock();
+
+    unlink(TEST_FILE);
+    printf("All file lock tests passed\n");
+    return 0;
+}
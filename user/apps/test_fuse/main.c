@@ -0,0 +1,507 @@
+// 测试FUSE：子进程通过/dev/fuse实现一个内存文件系统，父进程在挂载点上读写文件、列目录，并检查守护进程退出后的行为
+#define _GNU_SOURCE
+#include <assert.h>
+#include <dirent.h>
+#include <errno.h>
+#include <fcntl.h>
+#include <linux/fuse.h>
+#include <signal.h>
+#include <stdint.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <sys/mount.h>
+#include <sys/stat.h>
+#include <sys/wait.h>
+#include <unistd.h>
+
+#define MNT "/tmp/test_fuse"
+#define MAX_NODES 8
+#define MAX_DATA 256
+#define MAX_WRITE 4096
+
+// 守护进程中的一个文件，下标加1即为节点号，0号是根目录
+struct node
+{
+    int used;
+    char name[32];
+    uint32_t mode;
+    char data[MAX_DATA];
+    size_t size;
+};
+
+static struct node nodes[MAX_NODES];
+static char req_buf[MAX_WRITE + 65536];
+static char reply_buf[MAX_WRITE + 4096];
+
+static const char hello_content[] = "Hello, FUSE!\n";
+
+static void init_nodes(void)
+{
+    memset(nodes, 0, sizeof(nodes));
+    nodes[0].used = 1;
+    nodes[0].mode = S_IFDIR | 0755;
+
+    nodes[1].used = 1;
+    strcpy(nodes[1].name, "hello");
+    nodes[1].mode = S_IFREG | 0644;
+    memcpy(nodes[1].data, hello_content, strlen(hello_content));
+    nodes[1].size = strlen(hello_content);
+
+    // 守护进程拒绝打开这个文件
+    nodes[2].used = 1;
+    strcpy(nodes[2].name, "secret");
+    nodes[2].mode = S_IFREG | 0600;
+}
+
+static struct node *get_node(uint64_t nodeid)
+{
+    if (nodeid == 0 || nodeid > MAX_NODES || !nodes[nodeid - 1].used)
+        return NULL;
+    return &nodes[nodeid - 1];
+}
+
+static uint64_t find_node(const char *name)
+{
+    for (int i = 1; i < MAX_NODES; i++)
+    {
+        if (nodes[i].used && strcmp(nodes[i].name, name) == 0)
+            return i + 1;
+    }
+    return 0;
+}
+
+static void fill_attr(uint64_t nodeid, struct fuse_attr *attr)
+{
+    struct node *node = get_node(nodeid);
+    memset(attr, 0, sizeof(*attr));
+    attr->ino = nodeid;
+    attr->size = node->size;
+    attr->blocks = (node->size + 511) / 512;
+    attr->mode = node->mode;
+    attr->nlink = S_ISDIR(node->mode) ? 2 : 1;
+    attr->blksize = 4096;
+}
+
+static void fill_entry(uint64_t nodeid, struct fuse_entry_out *entry)
+{
+    // 不缓存目录项与属性，每次访问都向守护进程查询
+    memset(entry, 0, sizeof(*entry));
+    entry->nodeid = nodeid;
+    fill_attr(nodeid, &entry->attr);
+}
+
+// 一次write写入完整的回复
+static void reply(int fd, uint64_t unique, int error, const void *data, size_t len)
+{
+    struct fuse_out_header *out = (struct fuse_out_header *)reply_buf;
+    out->len = sizeof(*out) + len;
+    out->error = error;
+    out->unique = unique;
+    if (len)
+        memcpy(reply_buf + sizeof(*out), data, len);
+    // 内核可能已经不再等待这个回复（例如RELEASE），忽略错误
+    (void)!write(fd, reply_buf, out->len);
+}
+
+static void reply_err(int fd, uint64_t unique, int err)
+{
+    reply(fd, unique, -err, NULL, 0);
+}
+
+static void do_readdir(int fd, struct fuse_in_header *in, struct fuse_read_in *arg)
+{
+    static const char *fixed[] = {".", ".."};
+    char buf[1024];
+    size_t len = 0;
+    uint64_t index = 0;
+    for (int i = -2; i < MAX_NODES; i++)
+    {
+        const char *name;
+        uint64_t ino;
+        uint32_t type;
+        if (i < 0)
+        {
+            name = fixed[i + 2];
+            ino = 1;
+            type = DT_DIR;
+        }
+        else
+        {
+            if (i == 0 || !nodes[i].used)
+                continue;
+            name = nodes[i].name;
+            ino = i + 1;
+            type = DT_REG;
+        }
+        index++;
+        // 从上次读到的位置继续
+        if (index <= arg->offset)
+            continue;
+
+        size_t namelen = strlen(name);
+        size_t reclen = FUSE_DIRENT_ALIGN(FUSE_NAME_OFFSET + namelen);
+        if (len + reclen > arg->size || len + reclen > sizeof(buf))
+            break;
+        struct fuse_dirent *dirent = (struct fuse_dirent *)(buf + len);
+        memset(dirent, 0, reclen);
+        dirent->ino = ino;
+        dirent->off = index;
+        dirent->namelen = namelen;
+        dirent->type = type;
+        memcpy(dirent->name, name, namelen);
+        len += reclen;
+    }
+    reply(fd, in->unique, 0, buf, len);
+}
+
+static uint64_t create_node(const char *name, uint32_t mode)
+{
+    for (int i = 1; i < MAX_NODES; i++)
+    {
+        if (!nodes[i].used)
+        {
+            memset(&nodes[i], 0, sizeof(nodes[i]));
+            nodes[i].used = 1;
+            snprintf(nodes[i].name, sizeof(nodes[i].name), "%s", name);
+            nodes[i].mode = S_IFREG | (mode & 07777);
+            return i + 1;
+        }
+    }
+    return 0;
+}
+
+static void handle_request(int fd, struct fuse_in_header *in, void *arg)
+{
+    struct node *node = get_node(in->nodeid);
+    switch (in->opcode)
+    {
+    case FUSE_INIT:
+    {
+        struct fuse_init_in *init = arg;
+        struct fuse_init_out out;
+        memset(&out, 0, sizeof(out));
+        out.major = FUSE_KERNEL_VERSION;
+        out.minor = 31;
+        out.max_readahead = init->max_readahead;
+        out.max_write = MAX_WRITE;
+        reply(fd, in->unique, 0, &out, sizeof(out));
+        break;
+    }
+    case FUSE_LOOKUP:
+    {
+        uint64_t nodeid = in->nodeid == FUSE_ROOT_ID ? find_node(arg) : 0;
+        if (!nodeid)
+        {
+            reply_err(fd, in->unique, ENOENT);
+            break;
+        }
+        struct fuse_entry_out out;
+        fill_entry(nodeid, &out);
+        reply(fd, in->unique, 0, &out, sizeof(out));
+        break;
+    }
+    case FUSE_FORGET:
+    case FUSE_BATCH_FORGET:
+        // 不需要回复
+        break;
+    case FUSE_GETATTR:
+    case FUSE_SETATTR:
+    {
+        if (!node)
+        {
+            reply_err(fd, in->unique, ENOENT);
+            break;
+        }
+        if (in->opcode == FUSE_SETATTR)
+        {
+            struct fuse_setattr_in *setattr = arg;
+            if (setattr->valid & FATTR_SIZE)
+            {
+                if (setattr->size > MAX_DATA)
+                {
+                    reply_err(fd, in->unique, EFBIG);
+                    break;
+                }
+                if (setattr->size > node->size)
+                    memset(node->data + node->size, 0, setattr->size - node->size);
+                node->size = setattr->size;
+            }
+            if (setattr->valid & FATTR_MODE)
+                node->mode = (node->mode & S_IFMT) | (setattr->mode & 07777);
+        }
+        struct fuse_attr_out out;
+        memset(&out, 0, sizeof(out));
+        fill_attr(in->nodeid, &out.attr);
+        reply(fd, in->unique, 0, &out, sizeof(out));
+        break;
+    }
+    case FUSE_OPEN:
+    case FUSE_OPENDIR:
+    {
+        if (!node)
+        {
+            reply_err(fd, in->unique, ENOENT);
+            break;
+        }
+        if (strcmp(node->name, "secret") == 0)
+        {
+            reply_err(fd, in->unique, EACCES);
+            break;
+        }
+        struct fuse_open_out out;
+        memset(&out, 0, sizeof(out));
+        out.fh = in->nodeid;
+        reply(fd, in->unique, 0, &out, sizeof(out));
+        break;
+    }
+    case FUSE_READ:
+    {
+        struct fuse_read_in *read_in = arg;
+        size_t len = 0;
+        if (read_in->offset < node->size)
+        {
+            len = node->size - read_in->offset;
+            if (len > read_in->size)
+                len = read_in->size;
+        }
+        reply(fd, in->unique, 0, node->data + read_in->offset, len);
+        break;
+    }
+    case FUSE_WRITE:
+    {
+        struct fuse_write_in *write_in = arg;
+        if (write_in->offset + write_in->size > MAX_DATA)
+        {
+            reply_err(fd, in->unique, EFBIG);
+            break;
+        }
+        memcpy(node->data + write_in->offset, write_in + 1, write_in->size);
+        if (write_in->offset + write_in->size > node->size)
+            node->size = write_in->offset + write_in->size;
+        struct fuse_write_out out;
+        memset(&out, 0, sizeof(out));
+        out.size = write_in->size;
+        reply(fd, in->unique, 0, &out, sizeof(out));
+        break;
+    }
+    case FUSE_CREATE:
+    {
+        struct fuse_create_in *create = arg;
+        const char *name = (const char *)(create + 1);
+        if (find_node(name))
+        {
+            reply_err(fd, in->unique, EEXIST);
+            break;
+        }
+        uint64_t nodeid = create_node(name, create->mode);
+        if (!nodeid)
+        {
+            reply_err(fd, in->unique, ENOSPC);
+            break;
+        }
+        struct
+        {
+            struct fuse_entry_out entry;
+            struct fuse_open_out open;
+        } out;
+        memset(&out, 0, sizeof(out));
+        fill_entry(nodeid, &out.entry);
+        out.open.fh = nodeid;
+        reply(fd, in->unique, 0, &out, sizeof(out));
+        break;
+    }
+    case FUSE_UNLINK:
+    {
+        uint64_t nodeid = find_node(arg);
+        if (!nodeid)
+        {
+            reply_err(fd, in->unique, ENOENT);
+            break;
+        }
+        nodes[nodeid - 1].used = 0;
+        reply_err(fd, in->unique, 0);
+        break;
+    }
+    case FUSE_READDIR:
+        do_readdir(fd, in, arg);
+        break;
+    case FUSE_RELEASE:
+    case FUSE_RELEASEDIR:
+    case FUSE_FLUSH:
+        reply_err(fd, in->unique, 0);
+        break;
+    default:
+        reply_err(fd, in->unique, ENOSYS);
+        break;
+    }
+}
+
+// 守护进程：处理请求直到连接断开
+static void run_daemon(int fd)
+{
+    init_nodes();
+    for (;;)
+    {
+        ssize_t len = read(fd, req_buf, sizeof(req_buf));
+        if (len < 0)
+        {
+            // 文件系统被卸载
+            if (errno == ENODEV)
+                _exit(0);
+            if (errno == EINTR || errno == EAGAIN)
+                continue;
+            _exit(1);
+        }
+        struct fuse_in_header *in = (struct fuse_in_header *)req_buf;
+        if ((size_t)len < sizeof(*in) || in->len != (uint32_t)len)
+            _exit(2);
+        handle_request(fd, in, in + 1);
+    }
+}
+
+// 打开/dev/fuse并挂载，在子进程中运行守护进程
+static pid_t mount_fuse(void)
+{
+    int fd = open("/dev/fuse", O_RDWR);
+    assert(fd >= 0);
+    char opts[128];
+    snprintf(opts, sizeof(opts), "fd=%d,rootmode=40000,user_id=0,group_id=0", fd);
+    assert(mount("test_fuse", MNT, "fuse", 0, opts) == 0);
+
+    pid_t pid = fork();
+    assert(pid >= 0);
+    if (pid == 0)
+        run_daemon(fd);
+    // 只有守护进程持有/dev/fuse，守护进程退出时连接断开
+    close(fd);
+    return pid;
+}
+
+static void test_mount_options(void)
+{
+    int fd = open("/dev/fuse", O_RDWR);
+    assert(fd >= 0);
+    char opts[128];
+    // 缺少rootmode
+    snprintf(opts, sizeof(opts), "fd=%d,user_id=0,group_id=0", fd);
+    assert(mount("test_fuse", MNT, "fuse", 0, opts) == -1 && errno == EINVAL);
+    // 缺少fd
+    assert(mount("test_fuse", MNT, "fuse", 0, "rootmode=40000,user_id=0,group_id=0") == -1);
+    assert(errno == EINVAL);
+    close(fd);
+
+    // fd不是/dev/fuse
+    int other = open("/", O_RDONLY);
+    assert(other >= 0);
+    snprintf(opts, sizeof(opts), "fd=%d,rootmode=40000,user_id=0,group_id=0", other);
+    assert(mount("test_fuse", MNT, "fuse", 0, opts) == -1 && errno == EINVAL);
+    close(other);
+    printf("mount options ok\n");
+}
+
+static void test_lookup_and_read(void)
+{
+    struct stat st;
+    assert(stat(MNT, &st) == 0 && S_ISDIR(st.st_mode));
+    assert(stat(MNT "/hello", &st) == 0);
+    assert(S_ISREG(st.st_mode) && (st.st_mode & 0777) == 0644);
+    assert(st.st_size == (off_t)strlen(hello_content));
+    assert(stat(MNT "/missing", &st) == -1 && errno == ENOENT);
+
+    char buf[64];
+    int fd = open(MNT "/hello", O_RDONLY);
+    assert(fd >= 0);
+    ssize_t len = read(fd, buf, sizeof(buf));
+    assert(len == (ssize_t)strlen(hello_content));
+    assert(memcmp(buf, hello_content, len) == 0);
+    assert(pread(fd, buf, 5, 7) == 5 && memcmp(buf, "FUSE!", 5) == 0);
+    // 读到文件末尾
+    assert(pread(fd, buf, sizeof(buf), 100) == 0);
+    close(fd);
+
+    // 守护进程返回的错误码传给调用者
+    assert(open(MNT "/secret", O_RDONLY) == -1 && errno == EACCES);
+    printf("lookup and read ok\n");
+}
+
+static void test_readdir(void)
+{
+    DIR *dir = opendir(MNT);
+    assert(dir);
+    int found_hello = 0, found_secret = 0, count = 0;
+    struct dirent *entry;
+    while ((entry = readdir(dir)) != NULL)
+    {
+        count++;
+        if (strcmp(entry->d_name, "hello") == 0)
+            found_hello = 1;
+        if (strcmp(entry->d_name, "secret") == 0)
+            found_secret = 1;
+    }
+    closedir(dir);
+    assert(found_hello && found_secret && count == 4);
+    printf("readdir ok\n");
+}
+
+static void test_create_write_unlink(void)
+{
+    int fd = open(MNT "/new", O_RDWR | O_CREAT | O_EXCL, 0640);
+    assert(fd >= 0);
+    assert(write(fd, "abcdef", 6) == 6);
+    char buf[16];
+    assert(pread(fd, buf, sizeof(buf), 0) == 6 && memcmp(buf, "abcdef", 6) == 0);
+
+    struct stat st;
+    assert(fstat(fd, &st) == 0 && st.st_size == 6);
+    assert((st.st_mode & 0777) == 0640);
+
+    // 截断通过SETATTR完成
+    assert(ftruncate(fd, 2) == 0);
+    assert(fstat(fd, &st) == 0 && st.st_size == 2);
+    close(fd);
+
+    assert(open(MNT "/new", O_RDWR | O_CREAT | O_EXCL, 0640) == -1 && errno == EEXIST);
+    assert(unlink(MNT "/new") == 0);
+    assert(stat(MNT "/new", &st) == -1 && errno == ENOENT);
+    assert(unlink(MNT "/new") == -1 && errno == ENOENT);
+    printf("create, write and unlink ok\n");
+}
+
+static void test_daemon_exit(void)
+{
+    pid_t pid = mount_fuse();
+    struct stat st;
+    assert(stat(MNT "/hello", &st) == 0);
+
+    // 守护进程退出后，连接断开，所有操作返回ENOTCONN
+    kill(pid, SIGKILL);
+    assert(waitpid(pid, NULL, 0) == pid);
+    assert(stat(MNT "/hello", &st) == -1 && errno == ENOTCONN);
+    assert(umount(MNT) == 0);
+    printf("daemon exit ok\n");
+}
+
+int main()
+{
+    setbuf(stdout, NULL);
+    mkdir(MNT, 0755);
+
+    test_mount_options();
+
+    pid_t pid = mount_fuse();
+    test_lookup_and_read();
+    test_readdir();
+    test_create_write_unlink();
+    // 卸载后守护进程读到ENODEV并退出
+    assert(umount(MNT) == 0);
+    int status;
+    assert(waitpid(pid, &status, 0) == pid);
+    assert(WIFEXITED(status) && WEXITSTATUS(status) == 0);
+
+    test_daemon_exit();
+
+    assert(rmdir(MNT) == 0);
+    printf("All FUSE tests passed\n");
+    return 0;
+}
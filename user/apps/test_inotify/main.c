@@ -0,0 +1,236 @@
+// 测试inotify：目录与文件事件、移动事件的cookie、IN_ONESHOT、watch的修改与移除，以及非阻塞读取
+#define _GNU_SOURCE
+#include <assert.h>
+#include <errno.h>
+#include <fcntl.h>
+#include <stdio.h>
+#include <string.h>
+#include <sys/inotify.h>
+#include <sys/stat.h>
+#include <unistd.h>
+
+#define TEST_DIR "/tmp/test_inotify"
+
+#ifndef IN_MASK_CREATE
+#define IN_MASK_CREATE 0x10000000
+#endif
+
+static char buf[4096] __attribute__((aligned(__alignof__(struct inotify_event))));
+static size_t buf_len;
+static size_t buf_pos;
+
+// 读取下一个事件，缓冲区中的事件读完之后再从文件描述符读取
+static struct inotify_event *next_event(int fd)
+{
+    if (buf_pos >= buf_len)
+    {
+        ssize_t len = read(fd, buf, sizeof(buf));
+        assert(len > 0);
+        buf_len = len;
+        buf_pos = 0;
+    }
+    struct inotify_event *event = (struct inotify_event *)(buf + buf_pos);
+    buf_pos += sizeof(struct inotify_event) + event->len;
+    return event;
+}
+
+static struct inotify_event *expect_event(int fd, int wd, uint32_t mask, const char *name)
+{
+    struct inotify_event *event = next_event(fd);
+    assert(event->wd == wd);
+    assert(event->mask == mask);
+    if (name)
+    {
+        // 文件名以'\0'结尾，并且被填充到事件头部大小的整数倍
+        assert(event->len > strlen(name) && event->len % sizeof(struct inotify_event) == 0);
+        assert(strcmp(event->name, name) == 0);
+    }
+    else
+        assert(event->len == 0);
+    return event;
+}
+
+// 非阻塞的inotify实例中没有更多事件
+static void expect_no_event(int fd)
+{
+    assert(buf_pos >= buf_len);
+    assert(read(fd, buf, sizeof(buf)) == -1 && errno == EAGAIN);
+}
+
+static void test_dir_events(int fd)
+{
+    int wd = inotify_add_watch(fd, TEST_DIR, IN_CREATE | IN_DELETE | IN_MODIFY | IN_CLOSE_WRITE | IN_MOVE);
+    assert(wd >= 0);
+
+    int file = open(TEST_DIR "/a", O_WRONLY | O_CREAT, 0644);
+    assert(file >= 0);
+    expect_event(fd, wd, IN_CREATE, "a");
+    // 连续的相同事件被合并
+    assert(write(file, "x", 1) == 1);
+    assert(write(file, "y", 1) == 1);
+    expect_event(fd, wd, IN_MODIFY, "a");
+    close(file);
+    expect_event(fd, wd, IN_CLOSE_WRITE, "a");
+
+    assert(mkdir(TEST_DIR "/sub", 0755) == 0);
+    expect_event(fd, wd, IN_CREATE | IN_ISDIR, "sub");
+
+    // IN_MOVED_FROM与IN_MOVED_TO的cookie相同
+    assert(rename(TEST_DIR "/a", TEST_DIR "/b") == 0);
+    uint32_t cookie = expect_event(fd, wd, IN_MOVED_FROM, "a")->cookie;
+    assert(cookie != 0);
+    assert(expect_event(fd, wd, IN_MOVED_TO, "b")->cookie == cookie);
+
+    assert(unlink(TEST_DIR "/b") == 0);
+    expect_event(fd, wd, IN_DELETE, "b");
+    assert(rmdir(TEST_DIR "/sub") == 0);
+    expect_event(fd, wd, IN_DELETE | IN_ISDIR, "sub");
+    expect_no_event(fd);
+
+    // 移除watch时报告IN_IGNORED
+    assert(inotify_rm_watch(fd, wd) == 0);
+    expect_event(fd, wd, IN_IGNORED, NULL);
+    assert(inotify_rm_watch(fd, wd) == -1 && errno == EINVAL);
+    close(open(TEST_DIR "/c", O_WRONLY | O_CREAT, 0644));
+    expect_no_event(fd);
+    assert(unlink(TEST_DIR "/c") == 0);
+    printf("directory events ok\n");
+}
+
+static void test_file_events(int fd)
+{
+    int file = open(TEST_DIR "/file", O_WRONLY | O_CREAT, 0644);
+    assert(file >= 0);
+    int wd = inotify_add_watch(fd, TEST_DIR "/file", IN_MODIFY | IN_ATTRIB | IN_DELETE_SELF);
+    assert(wd >= 0);
+
+    // 文件自身的事件不带文件名
+    assert(write(file, "x", 1) == 1);
+    expect_event(fd, wd, IN_MODIFY, NULL);
+    assert(fchown(file, getuid(), getgid()) == 0);
+    expect_event(fd, wd, IN_ATTRIB, NULL);
+    close(file);
+
+    // 删除链接同样会报告IN_ATTRIB，这里只关心IN_DELETE_SELF
+    assert(inotify_add_watch(fd, TEST_DIR "/file", IN_DELETE_SELF) == wd);
+    // 最后一个链接被删除时报告IN_DELETE_SELF，watch随之被移除
+    assert(unlink(TEST_DIR "/file") == 0);
+    expect_event(fd, wd, IN_DELETE_SELF, NULL);
+    expect_event(fd, wd, IN_IGNORED, NULL);
+    expect_no_event(fd);
+    printf("file events ok\n");
+}
+
+static void test_oneshot(int fd)
+{
+    int wd = inotify_add_watch(fd, TEST_DIR, IN_CREATE | IN_ONESHOT);
+    assert(wd >= 0);
+    close(open(TEST_DIR "/d", O_WRONLY | O_CREAT, 0644));
+    // 报告一次事件后watch被移除
+    expect_event(fd, wd, IN_CREATE, "d");
+    expect_event(fd, wd, IN_IGNORED, NULL);
+    close(open(TEST_DIR "/e", O_WRONLY | O_CREAT, 0644));
+    expect_no_event(fd);
+    assert(inotify_rm_watch(fd, wd) == -1 && errno == EINVAL);
+    assert(unlink(TEST_DIR "/d") == 0);
+    assert(unlink(TEST_DIR "/e") == 0);
+    printf("IN_ONESHOT ok\n");
+}
+
+static void test_modify_watch(int fd)
+{
+    int wd = inotify_add_watch(fd, TEST_DIR, IN_CREATE);
+    assert(wd >= 0);
+    // 同一个inode返回同一个watch描述符
+    assert(inotify_add_watch(fd, TEST_DIR, IN_DELETE | IN_MASK_ADD) == wd);
+    close(open(TEST_DIR "/f", O_WRONLY | O_CREAT, 0644));
+    expect_event(fd, wd, IN_CREATE, "f");
+    assert(unlink(TEST_DIR "/f") == 0);
+    expect_event(fd, wd, IN_DELETE, "f");
+
+    // 不带IN_MASK_ADD时替换原有的mask
+    assert(inotify_add_watch(fd, TEST_DIR, IN_DELETE) == wd);
+    close(open(TEST_DIR "/f", O_WRONLY | O_CREAT, 0644));
+    assert(unlink(TEST_DIR "/f") == 0);
+    expect_event(fd, wd, IN_DELETE, "f");
+
+    // IN_MASK_CREATE要求watch不存在
+    assert(inotify_add_watch(fd, TEST_DIR, IN_CREATE | IN_MASK_CREATE) == -1 && errno == EEXIST);
+    assert(inotify_add_watch(fd, TEST_DIR, IN_CREATE | IN_MASK_CREATE | IN_MASK_ADD) == -1 && errno == EINVAL);
+    assert(inotify_rm_watch(fd, wd) == 0);
+    expect_event(fd, wd, IN_IGNORED, NULL);
+    expect_no_event(fd);
+    printf("watch modification ok\n");
+}
+
+static void test_close_events(int fd)
+{
+    close(open(TEST_DIR "/h", O_WRONLY | O_CREAT, 0644));
+    int wd = inotify_add_watch(fd, TEST_DIR, IN_CLOSE_WRITE);
+    assert(wd >= 0);
+    // 只报告关心的关闭事件
+    close(open(TEST_DIR "/h", O_RDONLY));
+    expect_no_event(fd);
+    close(open(TEST_DIR "/h", O_WRONLY));
+    expect_event(fd, wd, IN_CLOSE_WRITE, "h");
+
+    assert(inotify_add_watch(fd, TEST_DIR, IN_CLOSE) == wd);
+    close(open(TEST_DIR "/h", O_RDONLY));
+    expect_event(fd, wd, IN_CLOSE_NOWRITE, "h");
+
+    // 文件名长度恰好为16字节时，加上结尾的'\0'需要填充到32字节
+    const char *long_name = "0123456789abcdef";
+    close(open(TEST_DIR "/0123456789abcdef", O_RDONLY | O_CREAT, 0644));
+    struct inotify_event *event = expect_event(fd, wd, IN_CLOSE_NOWRITE, long_name);
+    assert(event->len == 32);
+
+    assert(inotify_rm_watch(fd, wd) == 0);
+    expect_event(fd, wd, IN_IGNORED, NULL);
+    expect_no_event(fd);
+    assert(unlink(TEST_DIR "/h") == 0);
+    assert(unlink(TEST_DIR "/0123456789abcdef") == 0);
+    printf("close events ok\n");
+}
+
+static void test_invalid(int fd)
+{
+    close(open(TEST_DIR "/g", O_WRONLY | O_CREAT, 0644));
+    assert(inotify_add_watch(fd, TEST_DIR "/g", IN_CREATE | IN_ONLYDIR) == -1 && errno == ENOTDIR);
+    assert(inotify_add_watch(fd, TEST_DIR "/none", IN_CREATE) == -1 && errno == ENOENT);
+    // 没有指定任何事件
+    assert(inotify_add_watch(fd, TEST_DIR, 0) == -1 && errno == EINVAL);
+    assert(inotify_add_watch(-1, TEST_DIR, IN_CREATE) == -1 && errno == EBADF);
+    assert(inotify_init1(0x12345) == -1 && errno == EINVAL);
+
+    // 缓冲区连一个事件都放不下
+    int wd = inotify_add_watch(fd, TEST_DIR, IN_DELETE);
+    assert(wd >= 0);
+    assert(unlink(TEST_DIR "/g") == 0);
+    char small[sizeof(struct inotify_event)];
+    assert(read(fd, small, sizeof(small)) == -1 && errno == EINVAL);
+    expect_event(fd, wd, IN_DELETE, "g");
+    assert(inotify_rm_watch(fd, wd) == 0);
+    expect_event(fd, wd, IN_IGNORED, NULL);
+    printf("invalid arguments rejected\n");
+}
+
+int main()
+{
+    rmdir(TEST_DIR);
+    assert(mkdir(TEST_DIR, 0755) == 0);
+    int fd = inotify_init1(IN_NONBLOCK | IN_CLOEXEC);
+    assert(fd >= 0);
+    expect_no_event(fd);
+
+    test_dir_events(fd);
+    test_file_events(fd);
+    test_oneshot(fd);
+    test_modify_watch(fd);
+    test_close_events(fd);
+    test_invalid(fd);
+
+    close(fd);
+    assert(rmdir(TEST_DIR) == 0);
+    printf("All inotify tests passed\n");
+    return 0;
+}
@@ -0,0 +1,208 @@
+// 测试loop设备：通过loop-control分配设备，绑定与解绑后端文件，修改偏移量与大小限制
+#define _GNU_SOURCE
+#include <assert.h>
+#include <errno.h>
+#include <fcntl.h>
+#include <linux/loop.h>
+#include <stdio.h>
+#include <string.h>
+#include <sys/ioctl.h>
+#include <sys/stat.h>
+#include <unistd.h>
+
+#define IMAGE_PATH "/tmp/test_loop.img"
+#define IMAGE_SIZE (64 * 1024)
+#define SECTOR_SIZE 512
+
+// 不太可能被占用的设备编号，用于测试添加与删除
+#define SPARE_NUMBER 200
+
+static char image[IMAGE_SIZE];
+
+static int create_image(void)
+{
+    for (size_t i = 0; i < sizeof(image); i++)
+        image[i] = i / SECTOR_SIZE + i % 7;
+    int fd = open(IMAGE_PATH, O_RDWR | O_CREAT | O_TRUNC, 0644);
+    assert(fd >= 0);
+    assert(write(fd, image, sizeof(image)) == sizeof(image));
+    return fd;
+}
+
+static int open_loop(int number)
+{
+    char path[32];
+    snprintf(path, sizeof(path), "/dev/loop%d", number);
+    int fd = open(path, O_RDWR);
+    assert(fd >= 0);
+    return fd;
+}
+
+static off_t device_size(int loop_fd)
+{
+    return lseek(loop_fd, 0, SEEK_END);
+}
+
+static void test_attach_detach(int ctl, int image_fd)
+{
+    int number = ioctl(ctl, LOOP_CTL_GET_FREE);
+    assert(number >= 0);
+    int loop_fd = open_loop(number);
+
+    // 未绑定的设备
+    struct loop_info64 info;
+    assert(ioctl(loop_fd, LOOP_GET_STATUS64, &info) == -1 && errno == ENXIO);
+    assert(ioctl(loop_fd, LOOP_CLR_FD, 0) == -1 && errno == ENXIO);
+
+    assert(ioctl(loop_fd, LOOP_SET_FD, image_fd) == 0);
+    // 已经绑定的设备不能再次绑定，也不再是空闲的设备
+    assert(ioctl(loop_fd, LOOP_SET_FD, image_fd) == -1 && errno == EBUSY);
+    assert(ioctl(ctl, LOOP_CTL_GET_FREE) != number);
+    assert(ioctl(ctl, LOOP_CTL_REMOVE, number) == -1 && errno == EBUSY);
+
+    memset(&info, 0, sizeof(info));
+    assert(ioctl(loop_fd, LOOP_GET_STATUS64, &info) == 0);
+    assert(info.lo_number == (unsigned)number);
+    assert(info.lo_offset == 0 && info.lo_sizelimit == 0);
+    assert(!(info.lo_flags & LO_FLAGS_READ_ONLY));
+
+    // 设备的内容就是后端文件的内容
+    assert(device_size(loop_fd) == IMAGE_SIZE);
+    char buf[SECTOR_SIZE];
+    assert(pread(loop_fd, buf, sizeof(buf), 3 * SECTOR_SIZE) == sizeof(buf));
+    assert(memcmp(buf, image + 3 * SECTOR_SIZE, sizeof(buf)) == 0);
+
+    // 写入设备的数据会写到后端文件中
+    memset(buf, 0x5a, sizeof(buf));
+    assert(pwrite(loop_fd, buf, sizeof(buf), SECTOR_SIZE) == sizeof(buf));
+    assert(fsync(loop_fd) == 0);
+    char file_buf[SECTOR_SIZE];
+    assert(pread(image_fd, file_buf, sizeof(file_buf), SECTOR_SIZE) == sizeof(file_buf));
+    assert(memcmp(file_buf, buf, sizeof(buf)) == 0);
+    memcpy(image + SECTOR_SIZE, buf, sizeof(buf));
+
+    // 不能写到设备末尾之后
+    assert(pwrite(loop_fd, buf, sizeof(buf), IMAGE_SIZE) == -1 && errno == ENOSPC);
+
+    // 解绑之后设备为空，并且重新成为空闲的设备。
+    // Linux在最后一个使用者关闭设备时才真正解绑，因此重新打开之后再检查
+    assert(ioctl(loop_fd, LOOP_CLR_FD, 0) == 0);
+    assert(ioctl(loop_fd, LOOP_GET_STATUS64, &info) == -1 && errno == ENXIO);
+    close(loop_fd);
+    loop_fd = open_loop(number);
+    assert(device_size(loop_fd) == 0);
+    assert(pread(loop_fd, buf, sizeof(buf), 0) == 0);
+    assert(ioctl(ctl, LOOP_CTL_GET_FREE) == number);
+
+    close(loop_fd);
+    printf("attach and detach ok\n");
+}
+
+static void test_set_status(int ctl, int image_fd)
+{
+    int number = ioctl(ctl, LOOP_CTL_GET_FREE);
+    assert(number >= 0);
+    int loop_fd = open_loop(number);
+    assert(ioctl(loop_fd, LOOP_SET_FD, image_fd) == 0);
+
+    // 修改偏移量与大小限制之后，设备只包含后端文件的一部分
+    struct loop_info64 info;
+    assert(ioctl(loop_fd, LOOP_GET_STATUS64, &info) == 0);
+    info.lo_offset = 2 * SECTOR_SIZE;
+    info.lo_sizelimit = 8 * SECTOR_SIZE;
+    strcpy((char *)info.lo_file_name, "test_loop");
+    assert(ioctl(loop_fd, LOOP_SET_STATUS64, &info) == 0);
+
+    memset(&info, 0, sizeof(info));
+    assert(ioctl(loop_fd, LOOP_GET_STATUS64, &info) == 0);
+    assert(info.lo_offset == 2 * SECTOR_SIZE);
+    assert(info.lo_sizelimit == 8 * SECTOR_SIZE);
+    assert(strcmp((char *)info.lo_file_name, "test_loop") == 0);
+
+    assert(device_size(loop_fd) == 8 * SECTOR_SIZE);
+    char buf[SECTOR_SIZE];
+    assert(pread(loop_fd, buf, sizeof(buf), 0) == sizeof(buf));
+    assert(memcmp(buf, image + 2 * SECTOR_SIZE, sizeof(buf)) == 0);
+    // 读取到设备末尾为止
+    assert(pread(loop_fd, buf, sizeof(buf), 8 * SECTOR_SIZE) == 0);
+
+    assert(ioctl(loop_fd, LOOP_CLR_FD, 0) == 0);
+    close(loop_fd);
+    printf("LOOP_SET_STATUS64 ok\n");
+}
+
+static void test_read_only(int ctl)
+{
+    int number = ioctl(ctl, LOOP_CTL_GET_FREE);
+    assert(number >= 0);
+    int loop_fd = open_loop(number);
+
+    // 后端文件以只读方式打开时，设备也是只读的
+    int ro_fd = open(IMAGE_PATH, O_RDONLY);
+    assert(ro_fd >= 0);
+    assert(ioctl(loop_fd, LOOP_SET_FD, ro_fd) == 0);
+    struct loop_info64 info;
+    assert(ioctl(loop_fd, LOOP_GET_STATUS64, &info) == 0);
+    assert(info.lo_flags & LO_FLAGS_READ_ONLY);
+    char buf[SECTOR_SIZE];
+    assert(pread(loop_fd, buf, sizeof(buf), 0) == sizeof(buf));
+    assert(memcmp(buf, image, sizeof(buf)) == 0);
+
+    assert(ioctl(loop_fd, LOOP_CLR_FD, 0) == 0);
+    close(ro_fd);
+    close(loop_fd);
+    printf("read-only backing file ok\n");
+}
+
+static void test_invalid_backing(int ctl)
+{
+    int number = ioctl(ctl, LOOP_CTL_GET_FREE);
+    assert(number >= 0);
+    int loop_fd = open_loop(number);
+
+    // 后端文件不能是目录，文件描述符必须有效
+    int dir = open("/tmp", O_RDONLY | O_DIRECTORY);
+    assert(dir >= 0);
+    assert(ioctl(loop_fd, LOOP_SET_FD, dir) == -1 && errno == EINVAL);
+    close(dir);
+    assert(ioctl(loop_fd, LOOP_SET_FD, -1) == -1 && errno == EBADF);
+    // 设备仍然是空闲的
+    assert(ioctl(ctl, LOOP_CTL_GET_FREE) == number);
+
+    close(loop_fd);
+    printf("invalid backing files rejected\n");
+}
+
+static void test_control(int ctl)
+{
+    // 添加与删除指定编号的设备
+    if (ioctl(ctl, LOOP_CTL_ADD, SPARE_NUMBER) == -1)
+    {
+        assert(errno == EEXIST);
+        return;
+    }
+    assert(ioctl(ctl, LOOP_CTL_ADD, SPARE_NUMBER) == -1 && errno == EEXIST);
+    assert(ioctl(ctl, LOOP_CTL_REMOVE, SPARE_NUMBER) == 0);
+    assert(ioctl(ctl, LOOP_CTL_REMOVE, SPARE_NUMBER) == -1 && errno == ENODEV);
+    printf("loop-control add and remove ok\n");
+}
+
+int main()
+{
+    setbuf(stdout, NULL);
+    int ctl = open("/dev/loop-control", O_RDWR);
+    assert(ctl >= 0);
+    int image_fd = create_image();
+
+    test_attach_detach(ctl, image_fd);
+    test_set_status(ctl, image_fd);
+    test_read_only(ctl);
+    test_invalid_backing(ctl);
+    test_control(ctl);
+
+    close(image_fd);
+    close(ctl);
+    assert(unlink(IMAGE_PATH) == 0);
+    printf("All loop tests passed\n");
+    return 0;
+}
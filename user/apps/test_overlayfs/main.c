@@ -0,0 +1,221 @@
+// 测试overlayfs：合并目录、copy-up、whiteout、opaque目录，以及没有upperdir时的只读挂载
+#define _GNU_SOURCE
+#include <assert.h>
+#include <dirent.h>
+#include <errno.h>
+#include <fcntl.h>
+#include <stdio.h>
+#include <string.h>
+#include <sys/mount.h>
+#include <sys/stat.h>
+#include <sys/sysmacros.h>
+#include <unistd.h>
+
+#define ROOT "/tmp/test_overlayfs"
+#define LOWER ROOT "/lower"
+#define LOWER2 ROOT "/lower2"
+#define UPPER ROOT "/upper"
+#define WORK ROOT "/work"
+#define MERGED ROOT "/merged"
+
+static void write_file(const char *path, const char *content)
+{
+    int fd = open(path, O_WRONLY | O_CREAT | O_TRUNC, 0644);
+    assert(fd >= 0);
+    assert(write(fd, content, strlen(content)) == (ssize_t)strlen(content));
+    close(fd);
+}
+
+// 读取文件内容，文件不存在时返回-errno
+static int read_file(const char *path, char *buf, size_t size)
+{
+    int fd = open(path, O_RDONLY);
+    if (fd < 0)
+        return -errno;
+    ssize_t len = read(fd, buf, size - 1);
+    assert(len >= 0);
+    buf[len] = '\0';
+    close(fd);
+    return 0;
+}
+
+static void expect_content(const char *path, const char *content)
+{
+    char buf[64];
+    assert(read_file(path, buf, sizeof(buf)) == 0);
+    assert(strcmp(buf, content) == 0);
+}
+
+// 判断目录中是否有名为name的目录项
+static int dir_contains(const char *path, const char *name)
+{
+    DIR *dir = opendir(path);
+    assert(dir);
+    int found = 0;
+    struct dirent *entry;
+    while ((entry = readdir(dir)) != NULL)
+    {
+        if (strcmp(entry->d_name, name) == 0)
+            found = 1;
+    }
+    closedir(dir);
+    return found;
+}
+
+static int is_whiteout(const char *path)
+{
+    struct stat st;
+    return lstat(path, &st) == 0 && S_ISCHR(st.st_mode) && st.st_rdev == makedev(0, 0);
+}
+
+static void setup_layers(void)
+{
+    assert(mkdir(LOWER, 0755) == 0);
+    assert(mkdir(LOWER2, 0755) == 0);
+    assert(mkdir(UPPER, 0755) == 0);
+    assert(mkdir(WORK, 0755) == 0);
+    assert(mkdir(MERGED, 0755) == 0);
+
+    write_file(LOWER "/a", "lower a");
+    write_file(LOWER "/del", "lower del");
+    assert(mkdir(LOWER "/dir", 0755) == 0);
+    write_file(LOWER "/dir/x", "lower x");
+    write_file(LOWER "/dir/y", "lower y");
+    write_file(UPPER "/u", "upper u");
+    // 下层中更靠上的一层遮住更靠下的一层
+    write_file(LOWER2 "/a", "lower2 a");
+    write_file(LOWER2 "/b", "lower2 b");
+}
+
+static void test_merge(void)
+{
+    expect_content(MERGED "/a", "lower a");
+    expect_content(MERGED "/b", "lower2 b");
+    expect_content(MERGED "/u", "upper u");
+    expect_content(MERGED "/dir/x", "lower x");
+    assert(dir_contains(MERGED, "a") && dir_contains(MERGED, "b"));
+    assert(dir_contains(MERGED, "u") && dir_contains(MERGED, "dir"));
+
+    // 只读打开不会触发copy-up
+    char buf[64];
+    assert(read_file(MERGED "/a", buf, sizeof(buf)) == 0);
+    assert(access(UPPER "/a", F_OK) == -1 && errno == ENOENT);
+    printf("merged lookup ok\n");
+}
+
+static void test_copy_up(void)
+{
+    // 以可写方式打开时把文件复制到上层
+    int fd = open(MERGED "/a", O_WRONLY | O_APPEND);
+    assert(fd >= 0);
+    expect_content(UPPER "/a", "lower a");
+    assert(write(fd, "+", 1) == 1);
+    close(fd);
+    expect_content(MERGED "/a", "lower a+");
+    expect_content(UPPER "/a", "lower a+");
+    expect_content(LOWER "/a", "lower a");
+
+    // 修改目录中的文件时，先复制它的祖先目录
+    write_file(MERGED "/dir/x", "new x");
+    expect_content(UPPER "/dir/x", "new x");
+    expect_content(LOWER "/dir/x", "lower x");
+    // 复制目录不会复制其中的其他文件
+    assert(access(UPPER "/dir/y", F_OK) == -1 && errno == ENOENT);
+    expect_content(MERGED "/dir/y", "lower y");
+
+    // copy-up保留权限
+    int pfd = open(LOWER2 "/p", O_WRONLY | O_CREAT, 0600);
+    assert(pfd >= 0);
+    close(pfd);
+    write_file(MERGED "/p", "new p");
+    struct stat st;
+    assert(stat(UPPER "/p", &st) == 0 && (st.st_mode & 0777) == 0600);
+    printf("copy-up ok\n");
+}
+
+static void test_whiteout(void)
+{
+    // 删除下层的文件时在上层创建whiteout
+    assert(unlink(MERGED "/del") == 0);
+    assert(access(MERGED "/del", F_OK) == -1 && errno == ENOENT);
+    assert(!dir_contains(MERGED, "del"));
+    assert(is_whiteout(UPPER "/del"));
+    expect_content(LOWER "/del", "lower del");
+
+    // 在whiteout的位置创建新的文件
+    write_file(MERGED "/del", "new del");
+    expect_content(MERGED "/del", "new del");
+    assert(!is_whiteout(UPPER "/del"));
+
+    // 只在上层存在的文件被直接删除
+    assert(unlink(MERGED "/u") == 0);
+    assert(access(UPPER "/u", F_OK) == -1 && errno == ENOENT);
+    printf("whiteout ok\n");
+}
+
+static void test_opaque_dir(void)
+{
+    // 非空的合并目录不能删除
+    assert(rmdir(MERGED "/dir") == -1 && errno == ENOTEMPTY);
+    assert(unlink(MERGED "/dir/x") == 0);
+    assert(unlink(MERGED "/dir/y") == 0);
+    assert(rmdir(MERGED "/dir") == 0);
+    assert(is_whiteout(UPPER "/dir"));
+
+    // 重新创建的目录不再与下层的同名目录合并
+    assert(mkdir(MERGED "/dir", 0755) == 0);
+    assert(!dir_contains(MERGED "/dir", "x"));
+    assert(!dir_contains(MERGED "/dir", "y"));
+    assert(access(MERGED "/dir/y", F_OK) == -1 && errno == ENOENT);
+    write_file(MERGED "/dir/z", "z");
+    expect_content(UPPER "/dir/z", "z");
+    printf("opaque directory ok\n");
+}
+
+static void test_readonly(void)
+{
+    assert(mount("overlay", MERGED, "overlay", 0, "lowerdir=" LOWER ":" LOWER2) == 0);
+    expect_content(MERGED "/a", "lower a");
+    expect_content(MERGED "/b", "lower2 b");
+    assert(open(MERGED "/a", O_WRONLY) == -1 && errno == EROFS);
+    assert(unlink(MERGED "/a") == -1 && errno == EROFS);
+    assert(mkdir(MERGED "/new", 0755) == -1 && errno == EROFS);
+    assert(umount(MERGED) == 0);
+    printf("read-only overlay ok\n");
+}
+
+int main()
+{
+    mkdir(ROOT, 0755);
+    // upperdir所在的文件系统需要支持扩展属性
+    assert(mount("tmpfs", ROOT, "tmpfs", 0, NULL) == 0);
+    setup_layers();
+
+    // 缺少lowerdir，或者选项不合法
+    assert(mount("overlay", MERGED, "overlay", 0, "upperdir=" UPPER ",workdir=" WORK) == -1);
+    assert(errno == EINVAL);
+    assert(mount("overlay", MERGED, "overlay", 0, "lowerdir=" LOWER ",foo=bar") == -1);
+    assert(errno == EINVAL);
+    assert(mount("overlay", MERGED, "overlay", 0, "lowerdir=:") == -1);
+    assert(errno == EINVAL);
+    // 下层目录不存在或者不是目录
+    assert(mount("overlay", MERGED, "overlay", 0, "lowerdir=" ROOT "/none") == -1);
+    assert(errno == ENOENT);
+    assert(mount("overlay", MERGED, "overlay", 0, "lowerdir=" LOWER "/a") == -1);
+    assert(errno == EINVAL);
+
+    assert(mount("overlay", MERGED, "overlay", 0,
+                 "lowerdir=" LOWER ":" LOWER2 ",upperdir=" UPPER ",workdir=" WORK) == 0);
+    test_merge();
+    test_copy_up();
+    test_whiteout();
+    test_opaque_dir();
+    assert(umount(MERGED) == 0);
+
+    test_readonly();
+
+    assert(umount(ROOT) == 0);
+    assert(rmdir(ROOT) == 0);
+    printf("All overlayfs tests passed\n");
+    return 0;
+}
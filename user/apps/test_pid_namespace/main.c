@@ -0,0 +1,62 @@
+// 测试pid namespace：新namespace中的第一个进程号为1，并且getrlimit/setrlimit/prlimit作用于调用者自身
+#define _GNU_SOURCE
+#include <assert.h>
+#include <errno.h>
+#include <sched.h>
+#include <stdio.h>
+#include <sys/resource.h>
+#include <sys/syscall.h>
+#include <sys/wait.h>
+#include <unistd.h>
+
+static void child(void)
+{
+    // 新pid namespace中的第一个进程
+    assert(getpid() == 1);
+    assert(getppid() == 0);
+
+    struct rlimit old;
+    assert(getrlimit(RLIMIT_NOFILE, &old) == 0);
+    assert(old.rlim_cur <= old.rlim_max);
+
+    // 修改后的值能够读回来
+    struct rlimit new_limit = {.rlim_cur = old.rlim_cur - 1, .rlim_max = old.rlim_max};
+    assert(setrlimit(RLIMIT_NOFILE, &new_limit) == 0);
+    struct rlimit cur;
+    assert(getrlimit(RLIMIT_NOFILE, &cur) == 0);
+    assert(cur.rlim_cur == new_limit.rlim_cur && cur.rlim_max == new_limit.rlim_max);
+
+    // prlimit的pid按照调用者的pid namespace解释
+    assert(prlimit(getpid(), RLIMIT_NOFILE, NULL, &cur) == 0);
+    assert(cur.rlim_cur == new_limit.rlim_cur);
+    assert(prlimit(0, RLIMIT_NOFILE, &old, NULL) == 0);
+    assert(getrlimit(RLIMIT_NOFILE, &cur) == 0);
+    assert(cur.rlim_cur == old.rlim_cur);
+    // namespace外的进程不可见
+    assert(prlimit(12345, RLIMIT_NOFILE, NULL, &cur) == -1 && errno == ESRCH);
+
+    assert(getrlimit(RLIMIT_NLIMITS, &cur) == -1 && errno == EINVAL);
+    printf("rlimit in child pid namespace ok\n");
+    syscall(SYS_exit, 0);
+}
+
+int main()
+{
+    setbuf(stdout, NULL);
+    assert(unshare(CLONE_NEWPID) == 0);
+    // 调用者自己留在原来的namespace中
+    pid_t self = getpid();
+    assert(self != 1);
+
+    pid_t pid = fork();
+    assert(pid >= 0);
+    if (pid == 0)
+        child();
+
+    int status;
+    assert(waitpid(pid, &status, 0) == pid);
+    assert(WIFEXITED(status) && WEXITSTATUS(status) == 0);
+    assert(getpid() == self);
+    printf("All pid namespace tests passed\n");
+    return 0;
+}
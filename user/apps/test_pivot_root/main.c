@@ -0,0 +1,122 @@
+// 测试pivot_root：在新的mount namespace中切换根文件系统，并卸载旧的根
+#define _GNU_SOURCE
+#include <assert.h>
+#include <errno.h>
+#include <fcntl.h>
+#include <sched.h>
+#include <stdio.h>
+#include <string.h>
+#include <sys/mount.h>
+#include <sys/stat.h>
+#include <sys/syscall.h>
+#include <sys/wait.h>
+#include <unistd.h>
+
+#define NEW_ROOT "/tmp/test_pivot_root"
+#define PUT_OLD "old"
+#define MARKER "marker"
+// 另一个挂载点，不在new_root之下
+#define OTHER "/tmp/test_pivot_root_other"
+
+static int sys_pivot_root(const char *new_root, const char *put_old)
+{
+    return syscall(SYS_pivot_root, new_root, put_old);
+}
+
+static void write_file(const char *path, const char *content)
+{
+    int fd = open(path, O_WRONLY | O_CREAT | O_TRUNC, 0644);
+    assert(fd >= 0);
+    assert(write(fd, content, strlen(content)) == (ssize_t)strlen(content));
+    close(fd);
+}
+
+static void expect_content(const char *path, const char *content)
+{
+    char buf[64] = {0};
+    int fd = open(path, O_RDONLY);
+    assert(fd >= 0);
+    assert(read(fd, buf, sizeof(buf) - 1) == (ssize_t)strlen(content));
+    assert(strcmp(buf, content) == 0);
+    close(fd);
+}
+
+static void test_invalid_args(void)
+{
+    // new_root不是挂载点
+    assert(sys_pivot_root(NEW_ROOT "/" PUT_OLD, NEW_ROOT "/" PUT_OLD) == -1);
+    assert(errno == EINVAL);
+    // put_old不在new_root之下
+    assert(sys_pivot_root(NEW_ROOT, OTHER) == -1);
+    assert(errno == EINVAL);
+    // put_old不是目录
+    assert(sys_pivot_root(NEW_ROOT, NEW_ROOT "/" MARKER) == -1);
+    assert(errno == ENOTDIR);
+    // 路径不存在
+    assert(sys_pivot_root(NEW_ROOT, NEW_ROOT "/none") == -1);
+    assert(errno == ENOENT);
+    // new_root是当前的根
+    assert(sys_pivot_root("/", NEW_ROOT "/" PUT_OLD) == -1);
+    assert(errno == EBUSY);
+    printf("invalid arguments rejected\n");
+}
+
+static void child_pivot_root(void)
+{
+    assert(unshare(CLONE_NEWNS) == 0);
+    // Linux要求挂载不是共享的，这里忽略不支持传播类型的内核返回的错误
+    mount(NULL, "/", NULL, MS_REC | MS_PRIVATE, NULL);
+
+    assert(mount("tmpfs", NEW_ROOT, "tmpfs", 0, NULL) == 0);
+    assert(mkdir(NEW_ROOT "/" PUT_OLD, 0755) == 0);
+    write_file(NEW_ROOT "/" MARKER, "new root");
+    write_file("/tmp/test_pivot_root_marker", "old root");
+    assert(mount("tmpfs", OTHER, "tmpfs", 0, NULL) == 0);
+
+    test_invalid_args();
+    assert(umount(OTHER) == 0);
+
+    assert(sys_pivot_root(NEW_ROOT, NEW_ROOT "/" PUT_OLD) == 0);
+    assert(chdir("/") == 0);
+
+    // 新的根是原来的new_root，旧的根被挂载到put_old上
+    expect_content("/" MARKER, "new root");
+    expect_content("/" PUT_OLD "/tmp/test_pivot_root_marker", "old root");
+    struct stat st;
+    assert(stat("/tmp", &st) == -1 && errno == ENOENT);
+
+    // 卸载旧的根之后，put_old成为一个空目录
+    assert(umount2("/" PUT_OLD, MNT_DETACH) == 0);
+    assert(stat("/" PUT_OLD "/tmp", &st) == -1 && errno == ENOENT);
+    assert(rmdir("/" PUT_OLD) == 0);
+    printf("pivot_root ok\n");
+}
+
+int main()
+{
+    setbuf(stdout, NULL);
+    mkdir(NEW_ROOT, 0755);
+    mkdir(OTHER, 0755);
+
+    pid_t pid = fork();
+    assert(pid >= 0);
+    if (pid == 0)
+    {
+        child_pivot_root();
+        _exit(0);
+    }
+    int status;
+    assert(waitpid(pid, &status, 0) == pid);
+    assert(WIFEXITED(status) && WEXITSTATUS(status) == 0);
+
+    // 父进程所在的mount namespace不受影响
+    struct stat st;
+    assert(stat("/tmp", &st) == 0);
+    assert(stat(NEW_ROOT "/" MARKER, &st) == -1 && errno == ENOENT);
+    expect_content("/tmp/test_pivot_root_marker", "old root");
+    assert(unlink("/tmp/test_pivot_root_marker") == 0);
+    assert(rmdir(NEW_ROOT) == 0);
+    assert(rmdir(OTHER) == 0);
+    printf("All pivot_root tests passed\n");
+    return 0;
+}
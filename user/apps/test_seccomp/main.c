@@ -0,0 +1,246 @@
+// 测试seccomp：strict模式、BPF过滤器的安装与各种返回动作
+#define _GNU_SOURCE
+#include <assert.h>
+#include <errno.h>
+#include <linux/audit.h>
+#include <linux/filter.h>
+#include <linux/seccomp.h>
+#include <signal.h>
+#include <stddef.h>
+#include <stdio.h>
+#include <string.h>
+#include <sys/prctl.h>
+#include <sys/syscall.h>
+#include <sys/wait.h>
+#include <unistd.h>
+
+#if defined(__x86_64__)
+#define AUDIT_ARCH_CURRENT AUDIT_ARCH_X86_64
+#elif defined(__riscv) && __riscv_xlen == 64
+#define AUDIT_ARCH_CURRENT AUDIT_ARCH_RISCV64
+#endif
+
+#define SYSCALL_NR_OFFSET offsetof(struct seccomp_data, nr)
+#define ARCH_OFFSET offsetof(struct seccomp_data, arch)
+
+static int sys_seccomp(unsigned int op, unsigned int flags, void *args)
+{
+    return syscall(SYS_seccomp, op, flags, args);
+}
+
+// 构造一个过滤器：架构不匹配时杀死进程，系统调用号等于nr时返回action，否则放行
+static int install_filter(int nr, unsigned int action)
+{
+    struct sock_filter filter[] = {
+        BPF_STMT(BPF_LD | BPF_W | BPF_ABS, ARCH_OFFSET),
+        BPF_JUMP(BPF_JMP | BPF_JEQ | BPF_K, AUDIT_ARCH_CURRENT, 1, 0),
+        BPF_STMT(BPF_RET | BPF_K, SECCOMP_RET_KILL_PROCESS),
+        BPF_STMT(BPF_LD | BPF_W | BPF_ABS, SYSCALL_NR_OFFSET),
+        BPF_JUMP(BPF_JMP | BPF_JEQ | BPF_K, nr, 0, 1),
+        BPF_STMT(BPF_RET | BPF_K, action),
+        BPF_STMT(BPF_RET | BPF_K, SECCOMP_RET_ALLOW),
+    };
+    struct sock_fprog prog = {
+        .len = sizeof(filter) / sizeof(filter[0]),
+        .filter = filter,
+    };
+    return sys_seccomp(SECCOMP_SET_MODE_FILTER, 0, &prog);
+}
+
+// 在子进程中执行func，返回子进程的wait状态
+static int run_in_child(void (*func)(void))
+{
+    pid_t pid = fork();
+    assert(pid >= 0);
+    if (pid == 0)
+    {
+        func();
+        syscall(SYS_exit, 0);
+    }
+    int status;
+    assert(waitpid(pid, &status, 0) == pid);
+    return status;
+}
+
+static void test_action_avail(void)
+{
+    unsigned int action = SECCOMP_RET_ALLOW;
+    assert(sys_seccomp(SECCOMP_GET_ACTION_AVAIL, 0, &action) == 0);
+    action = SECCOMP_RET_KILL_PROCESS;
+    assert(sys_seccomp(SECCOMP_GET_ACTION_AVAIL, 0, &action) == 0);
+    action = 0x12340000;
+    assert(sys_seccomp(SECCOMP_GET_ACTION_AVAIL, 0, &action) == -1);
+    assert(errno == EOPNOTSUPP);
+    printf("SECCOMP_GET_ACTION_AVAIL ok\n");
+}
+
+static void test_invalid_filter(void)
+{
+    // 最后一条指令不是ret
+    struct sock_filter no_ret[] = {
+        BPF_STMT(BPF_LD | BPF_W | BPF_ABS, SYSCALL_NR_OFFSET),
+    };
+    struct sock_fprog prog = {.len = 1, .filter = no_ret};
+    assert(sys_seccomp(SECCOMP_SET_MODE_FILTER, 0, &prog) == -1);
+    assert(errno == EINVAL);
+
+    // 读取超出seccomp_data的范围
+    struct sock_filter out_of_range[] = {
+        BPF_STMT(BPF_LD | BPF_W | BPF_ABS, sizeof(struct seccomp_data)),
+        BPF_STMT(BPF_RET | BPF_K, SECCOMP_RET_ALLOW),
+    };
+    prog.len = 2;
+    prog.filter = out_of_range;
+    assert(sys_seccomp(SECCOMP_SET_MODE_FILTER, 0, &prog) == -1);
+    assert(errno == EINVAL);
+
+    // 只允许对齐的32位绝对偏移读取，以及seccomp支持的指令
+    struct sock_filter bad_insns[] = {
+        BPF_STMT(BPF_LD | BPF_B | BPF_ABS, SYSCALL_NR_OFFSET),
+        BPF_STMT(BPF_LD | BPF_H | BPF_ABS, SYSCALL_NR_OFFSET),
+        BPF_STMT(BPF_LD | BPF_W | BPF_ABS, SYSCALL_NR_OFFSET + 1),
+        BPF_STMT(BPF_LD | BPF_W | BPF_IND, SYSCALL_NR_OFFSET),
+        BPF_STMT(BPF_LDX | BPF_B | BPF_MSH, SYSCALL_NR_OFFSET),
+        BPF_STMT(BPF_ALU | BPF_MOD | BPF_K, 2),
+    };
+    for (size_t i = 0; i < sizeof(bad_insns) / sizeof(bad_insns[0]); i++)
+    {
+        struct sock_filter filter[] = {
+            bad_insns[i],
+            BPF_STMT(BPF_RET | BPF_K, SECCOMP_RET_ALLOW),
+        };
+        prog.len = 2;
+        prog.filter = filter;
+        assert(sys_seccomp(SECCOMP_SET_MODE_FILTER, 0, &prog) == -1);
+        assert(errno == EINVAL);
+    }
+
+    // 空程序
+    prog.len = 0;
+    assert(sys_seccomp(SECCOMP_SET_MODE_FILTER, 0, &prog) == -1);
+    assert(errno == EINVAL);
+
+    // 未知的flags
+    assert(sys_seccomp(SECCOMP_SET_MODE_FILTER, 1u << 31, &prog) == -1);
+    assert(errno == EINVAL);
+
+    assert(prctl(PR_GET_SECCOMP) == SECCOMP_MODE_DISABLED);
+    printf("invalid filters rejected\n");
+}
+
+static void child_load_len(void)
+{
+    // BPF_LEN读取到的是seccomp_data的大小
+    struct sock_filter filter[] = {
+        BPF_STMT(BPF_LD | BPF_W | BPF_LEN, 0),
+        BPF_JUMP(BPF_JMP | BPF_JEQ | BPF_K, sizeof(struct seccomp_data), 1, 0),
+        BPF_STMT(BPF_RET | BPF_K, SECCOMP_RET_KILL_PROCESS),
+        BPF_STMT(BPF_RET | BPF_K, SECCOMP_RET_ALLOW),
+    };
+    struct sock_fprog prog = {
+        .len = sizeof(filter) / sizeof(filter[0]),
+        .filter = filter,
+    };
+    assert(sys_seccomp(SECCOMP_SET_MODE_FILTER, 0, &prog) == 0);
+    assert(syscall(SYS_getpid) > 0);
+}
+
+static void child_errno(void)
+{
+    assert(install_filter(SYS_getppid, SECCOMP_RET_ERRNO | EPERM) == 0);
+    assert(prctl(PR_GET_SECCOMP) == SECCOMP_MODE_FILTER);
+    assert(syscall(SYS_getppid) == -1);
+    assert(errno == EPERM);
+    // 其他系统调用不受影响
+    assert(syscall(SYS_getpid) > 0);
+
+    // 后安装的过滤器不能放宽之前的限制；多个过滤器动作相同时，最后安装的过滤器的返回值生效
+    assert(install_filter(SYS_getppid, SECCOMP_RET_ALLOW) == 0);
+    assert(syscall(SYS_getppid) == -1);
+    assert(errno == EPERM);
+    assert(install_filter(SYS_getppid, SECCOMP_RET_ERRNO | EACCES) == 0);
+    assert(syscall(SYS_getppid) == -1);
+    assert(errno == EACCES);
+
+    // 过滤器模式不能切换回strict模式
+    assert(prctl(PR_SET_SECCOMP, SECCOMP_MODE_STRICT) == -1);
+    assert(errno == EINVAL);
+}
+
+static void child_inherit_check(void)
+{
+    assert(prctl(PR_GET_SECCOMP) == SECCOMP_MODE_FILTER);
+    assert(syscall(SYS_getppid) == -1);
+    assert(errno == ENOENT);
+}
+
+static void child_inherit(void)
+{
+    assert(install_filter(SYS_getppid, SECCOMP_RET_ERRNO | ENOENT) == 0);
+    // fork出的子进程继承过滤器
+    int status = run_in_child(child_inherit_check);
+    assert(WIFEXITED(status) && WEXITSTATUS(status) == 0);
+}
+
+static void child_kill(void)
+{
+    assert(install_filter(SYS_getppid, SECCOMP_RET_KILL_PROCESS) == 0);
+    syscall(SYS_getppid);
+    // 不应该执行到这里
+    syscall(SYS_exit, 1);
+}
+
+static void child_trap(void)
+{
+    assert(install_filter(SYS_getppid, SECCOMP_RET_TRAP) == 0);
+    syscall(SYS_getppid);
+    syscall(SYS_exit, 1);
+}
+
+static void child_strict(void)
+{
+    const char msg[] = "strict mode: write allowed\n";
+    assert(prctl(PR_SET_SECCOMP, SECCOMP_MODE_STRICT) == 0);
+    // read、write、exit、rt_sigreturn以外的系统调用会使进程被杀死
+    syscall(SYS_write, 1, msg, sizeof(msg) - 1);
+    syscall(SYS_getpid);
+    syscall(SYS_exit, 1);
+}
+
+int main()
+{
+    assert(prctl(PR_GET_SECCOMP) == SECCOMP_MODE_DISABLED);
+    assert(prctl(PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) == 0);
+
+    test_action_avail();
+    test_invalid_filter();
+
+    int status = run_in_child(child_errno);
+    assert(WIFEXITED(status) && WEXITSTATUS(status) == 0);
+    printf("SECCOMP_RET_ERRNO ok\n");
+
+    status = run_in_child(child_load_len);
+    assert(WIFEXITED(status) && WEXITSTATUS(status) == 0);
+    printf("BPF_LEN load ok\n");
+
+    status = run_in_child(child_inherit);
+    assert(WIFEXITED(status) && WEXITSTATUS(status) == 0);
+    printf("filter inherited by fork ok\n");
+
+    status = run_in_child(child_kill);
+    assert(WIFSIGNALED(status) && WTERMSIG(status) == SIGSYS);
+    printf("SECCOMP_RET_KILL_PROCESS ok\n");
+
+    status = run_in_child(child_trap);
+    assert(WIFSIGNALED(status) && WTERMSIG(status) == SIGSYS);
+    printf("SECCOMP_RET_TRAP ok\n");
+
+    status = run_in_child(child_strict);
+    assert(WIFSIGNALED(status) && WTERMSIG(status) == SIGKILL);
+    printf("strict mode ok\n");
+
+    // 父进程没有安装过滤器
+    assert(prctl(PR_GET_SECCOMP) == SECCOMP_MODE_DISABLED);
+    printf("All seccomp tests passed\n");
+    return 0;
+}
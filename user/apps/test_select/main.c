@@ -0,0 +1,289 @@
+// 测试select与pselect6：就绪状态、剩余超时时间的写回、错误的参数以及等待期间的信号屏蔽
+#define _GNU_SOURCE
+#include <assert.h>
+#include <errno.h>
+#include <signal.h>
+#include <stdio.h>
+#include <string.h>
+#include <sys/select.h>
+#include <sys/syscall.h>
+#include <sys/time.h>
+#include <sys/wait.h>
+#include <time.h>
+#include <unistd.h>
+
+// 内核中sigset_t的大小
+#define KERNEL_SIGSET_SIZE 8
+
+// pselect6的第六个参数
+struct pselect_sigmask
+{
+    const sigset_t *sigmask;
+    size_t sigsetsize;
+};
+
+static volatile sig_atomic_t got_sigusr1;
+
+static void sigusr1_handler(int sig)
+{
+    (void)sig;
+    got_sigusr1 = 1;
+}
+
+static int sys_select(int nfds, fd_set *readfds, fd_set *writefds, fd_set *exceptfds,
+                      struct timeval *timeout)
+{
+    return syscall(SYS_select, nfds, readfds, writefds, exceptfds, timeout);
+}
+
+static int sys_pselect6(int nfds, fd_set *readfds, fd_set *writefds, fd_set *exceptfds,
+                        struct timespec *timeout, const sigset_t *sigmask, size_t sigsetsize)
+{
+    struct pselect_sigmask sig = {.sigmask = sigmask, .sigsetsize = sigsetsize};
+    return syscall(SYS_pselect6, nfds, readfds, writefds, exceptfds, timeout, &sig);
+}
+
+static void test_ready(void)
+{
+    int fds[2];
+    assert(pipe(fds) == 0);
+    int nfds = fds[1] + 1;
+
+    // 管道为空时读端不可读，写端可写
+    fd_set rset, wset;
+    FD_ZERO(&rset);
+    FD_ZERO(&wset);
+    FD_SET(fds[0], &rset);
+    FD_SET(fds[1], &wset);
+    struct timeval tv = {.tv_sec = 0, .tv_usec = 0};
+    assert(sys_select(nfds, &rset, &wset, NULL, &tv) == 1);
+    assert(!FD_ISSET(fds[0], &rset));
+    assert(FD_ISSET(fds[1], &wset));
+
+    // 写入数据后两端都就绪
+    assert(write(fds[1], "x", 1) == 1);
+    FD_ZERO(&rset);
+    FD_ZERO(&wset);
+    FD_SET(fds[0], &rset);
+    FD_SET(fds[1], &wset);
+    assert(sys_select(nfds, &rset, &wset, NULL, NULL) == 2);
+    assert(FD_ISSET(fds[0], &rset));
+    assert(FD_ISSET(fds[1], &wset));
+
+    // 关闭写端后读端总是可读
+    char c;
+    assert(read(fds[0], &c, 1) == 1);
+    close(fds[1]);
+    FD_ZERO(&rset);
+    FD_SET(fds[0], &rset);
+    assert(sys_select(fds[0] + 1, &rset, NULL, NULL, NULL) == 1);
+    assert(FD_ISSET(fds[0], &rset));
+    close(fds[0]);
+    printf("select readiness ok\n");
+}
+
+static void test_timeout_writeback(void)
+{
+    int fds[2];
+    assert(pipe(fds) == 0);
+
+    // 超时后剩余时间被写回为0
+    fd_set rset;
+    FD_ZERO(&rset);
+    FD_SET(fds[0], &rset);
+    struct timeval tv = {.tv_sec = 0, .tv_usec = 100000};
+    assert(sys_select(fds[0] + 1, &rset, NULL, NULL, &tv) == 0);
+    assert(tv.tv_sec == 0 && tv.tv_usec == 0);
+    assert(!FD_ISSET(fds[0], &rset));
+
+    // 提前返回时写回剩余的时间
+    assert(write(fds[1], "x", 1) == 1);
+    FD_ZERO(&rset);
+    FD_SET(fds[0], &rset);
+    tv.tv_sec = 5;
+    tv.tv_usec = 0;
+    assert(sys_select(fds[0] + 1, &rset, NULL, NULL, &tv) == 1);
+    assert(tv.tv_sec == 4 || (tv.tv_sec == 5 && tv.tv_usec == 0));
+
+    // pselect6同样写回剩余的时间
+    FD_ZERO(&rset);
+    FD_SET(fds[0], &rset);
+    struct timespec ts = {.tv_sec = 5, .tv_nsec = 0};
+    assert(sys_pselect6(fds[0] + 1, &rset, NULL, NULL, &ts, NULL, 0) == 1);
+    assert(ts.tv_sec == 4 || (ts.tv_sec == 5 && ts.tv_nsec == 0));
+
+    char c;
+    assert(read(fds[0], &c, 1) == 1);
+    FD_ZERO(&rset);
+    FD_SET(fds[0], &rset);
+    ts.tv_sec = 0;
+    ts.tv_nsec = 50000000;
+    assert(sys_pselect6(fds[0] + 1, &rset, NULL, NULL, &ts, NULL, 0) == 0);
+    assert(ts.tv_sec == 0 && ts.tv_nsec == 0);
+
+    close(fds[0]);
+    close(fds[1]);
+    printf("timeout write-back ok\n");
+}
+
+static void test_invalid_args(void)
+{
+    int fds[2];
+    assert(pipe(fds) == 0);
+
+    // 集合中包含未打开的文件描述符
+    int closed = dup(fds[0]);
+    assert(closed >= 0);
+    close(closed);
+    fd_set rset;
+    FD_ZERO(&rset);
+    FD_SET(fds[0], &rset);
+    FD_SET(closed, &rset);
+    struct timeval tv = {.tv_sec = 0, .tv_usec = 0};
+    assert(sys_select(closed + 1, &rset, NULL, NULL, &tv) == -1);
+    assert(errno == EBADF);
+    FD_ZERO(&rset);
+    FD_SET(closed, &rset);
+    assert(sys_select(closed + 1, NULL, &rset, NULL, NULL) == -1);
+    assert(errno == EBADF);
+
+    // nfds为负数，或者超时时间不合法
+    assert(sys_select(-1, NULL, NULL, NULL, &tv) == -1);
+    assert(errno == EINVAL);
+    tv.tv_usec = -1;
+    assert(sys_select(0, NULL, NULL, NULL, &tv) == -1);
+    assert(errno == EINVAL);
+    // tv_usec超过一秒的部分计入tv_sec，写回的剩余时间是规范化之后的
+    FD_ZERO(&rset);
+    FD_SET(fds[1], &rset);
+    tv.tv_sec = 0;
+    tv.tv_usec = 1000000;
+    assert(sys_select(fds[1] + 1, NULL, &rset, NULL, &tv) == 1);
+    assert(tv.tv_sec <= 1 && tv.tv_usec >= 0 && tv.tv_usec < 1000000);
+    struct timespec ts = {.tv_sec = -1, .tv_nsec = 0};
+    assert(sys_pselect6(0, NULL, NULL, NULL, &ts, NULL, 0) == -1);
+    assert(errno == EINVAL);
+
+    // 信号集的大小不正确
+    sigset_t mask;
+    sigemptyset(&mask);
+    ts.tv_sec = 0;
+    assert(sys_pselect6(0, NULL, NULL, NULL, &ts, &mask, KERNEL_SIGSET_SIZE + 1) == -1);
+    assert(errno == EINVAL);
+
+    close(fds[0]);
+    close(fds[1]);
+    printf("invalid arguments rejected\n");
+}
+
+static void test_large_nfds(void)
+{
+    int fds[2];
+    assert(pipe(fds) == 0);
+    assert(write(fds[1], "x", 1) == 1);
+
+    // nfds可以超过文件描述符表的大小，多出来的部分不会被检查
+    fd_set rset;
+    FD_ZERO(&rset);
+    FD_SET(fds[0], &rset);
+    struct timeval tv = {.tv_sec = 0, .tv_usec = 0};
+    assert(sys_select(FD_SETSIZE, &rset, NULL, NULL, &tv) == 1);
+    assert(FD_ISSET(fds[0], &rset));
+    for (int fd = 0; fd < FD_SETSIZE; fd++)
+    {
+        if (fd != fds[0])
+            assert(!FD_ISSET(fd, &rset));
+    }
+
+    // nfds为0时相当于睡眠
+    tv.tv_sec = 0;
+    tv.tv_usec = 10000;
+    assert(sys_select(0, NULL, NULL, NULL, &tv) == 0);
+
+    close(fds[0]);
+    close(fds[1]);
+    printf("large nfds ok\n");
+}
+
+static int sigusr1_blocked(void)
+{
+    sigset_t cur;
+    assert(sigprocmask(SIG_SETMASK, NULL, &cur) == 0);
+    return sigismember(&cur, SIGUSR1);
+}
+
+static void test_pselect_sigmask(void)
+{
+    struct sigaction sa;
+    memset(&sa, 0, sizeof(sa));
+    sa.sa_handler = sigusr1_handler;
+    assert(sigaction(SIGUSR1, &sa, NULL) == 0);
+
+    sigset_t block;
+    sigemptyset(&block);
+    sigaddset(&block, SIGUSR1);
+    assert(sigprocmask(SIG_BLOCK, &block, NULL) == 0);
+
+    int fds[2];
+    assert(pipe(fds) == 0);
+    fd_set rset;
+
+    // 等待期间解除屏蔽，信号使pselect6返回EINTR，处理函数执行后恢复原来的屏蔽字
+    pid_t parent = getpid();
+    pid_t pid = fork();
+    assert(pid >= 0);
+    if (pid == 0)
+    {
+        usleep(100000);
+        kill(parent, SIGUSR1);
+        _exit(0);
+    }
+    sigset_t empty;
+    sigemptyset(&empty);
+    FD_ZERO(&rset);
+    FD_SET(fds[0], &rset);
+    got_sigusr1 = 0;
+    assert(sys_pselect6(fds[0] + 1, &rset, NULL, NULL, NULL, &empty, KERNEL_SIGSET_SIZE) == -1);
+    assert(errno == EINTR);
+    assert(got_sigusr1);
+    assert(sigusr1_blocked());
+    assert(waitpid(pid, NULL, 0) == pid);
+
+    // 等待期间屏蔽信号时，挂起的信号不会打断pselect6
+    assert(kill(getpid(), SIGUSR1) == 0);
+    got_sigusr1 = 0;
+    FD_ZERO(&rset);
+    FD_SET(fds[0], &rset);
+    struct timespec ts = {.tv_sec = 0, .tv_nsec = 50000000};
+    assert(sys_pselect6(fds[0] + 1, &rset, NULL, NULL, &ts, &block, KERNEL_SIGSET_SIZE) == 0);
+    assert(!got_sigusr1);
+    assert(sigusr1_blocked());
+    // 解除屏蔽后处理挂起的信号
+    assert(sigprocmask(SIG_UNBLOCK, &block, NULL) == 0);
+    assert(got_sigusr1);
+
+    // sigmask为NULL时不改变屏蔽字
+    assert(sigprocmask(SIG_BLOCK, &block, NULL) == 0);
+    FD_ZERO(&rset);
+    FD_SET(fds[0], &rset);
+    ts.tv_sec = 0;
+    ts.tv_nsec = 0;
+    assert(sys_pselect6(fds[0] + 1, &rset, NULL, NULL, &ts, NULL, 0) == 0);
+    assert(sigusr1_blocked());
+    assert(sigprocmask(SIG_UNBLOCK, &block, NULL) == 0);
+
+    close(fds[0]);
+    close(fds[1]);
+    printf("pselect6 sigmask ok\n");
+}
+
+int main()
+{
+    test_ready();
+    test_timeout_writeback();
+    test_invalid_args();
+    test_large_nfds();
+    test_pselect_sigmask();
+    printf("All select tests passed\n");
+    return 0;
+}
@@ -0,0 +1,116 @@
+// 测试setns：ns文件的文件描述符持有namespace，目标进程退出后仍然可以加入，以及参数错误时的返回值
+#define _GNU_SOURCE
+#include <assert.h>
+#include <errno.h>
+#include <fcntl.h>
+#include <sched.h>
+#include <stdio.h>
+#include <string.h>
+#include <sys/syscall.h>
+#include <sys/wait.h>
+#include <unistd.h>
+
+#define HOSTNAME "test-setns"
+
+// 创建一个位于新的UTS namespace中的子进程，返回打开的/proc/<pid>/ns/uts，返回前子进程已经退出
+static int open_exited_uts_ns(void)
+{
+    int ready[2], done[2];
+    assert(pipe(ready) == 0 && pipe(done) == 0);
+    pid_t pid = fork();
+    assert(pid >= 0);
+    if (pid == 0)
+    {
+        assert(unshare(CLONE_NEWUTS) == 0);
+        assert(sethostname(HOSTNAME, strlen(HOSTNAME)) == 0);
+        char c = 0;
+        assert(write(ready[1], &c, 1) == 1);
+        assert(read(done[0], &c, 1) == 1);
+        syscall(SYS_exit, 0);
+    }
+
+    char c;
+    assert(read(ready[0], &c, 1) == 1);
+    char path[64];
+    snprintf(path, sizeof(path), "/proc/%d/ns/uts", pid);
+    int fd = open(path, O_RDONLY);
+    assert(fd >= 0);
+    assert(write(done[1], &c, 1) == 1);
+
+    int status;
+    assert(waitpid(pid, &status, 0) == pid);
+    assert(WIFEXITED(status) && WEXITSTATUS(status) == 0);
+    close(ready[0]);
+    close(ready[1]);
+    close(done[0]);
+    close(done[1]);
+    return fd;
+}
+
+// 在子进程中加入`fd`引用的UTS namespace，检查主机名
+static void expect_join_uts(int fd)
+{
+    pid_t pid = fork();
+    assert(pid >= 0);
+    if (pid == 0)
+    {
+        assert(setns(fd, CLONE_NEWUTS) == 0);
+        char name[64];
+        assert(gethostname(name, sizeof(name)) == 0);
+        assert(strcmp(name, HOSTNAME) == 0);
+        syscall(SYS_exit, 0);
+    }
+    int status;
+    assert(waitpid(pid, &status, 0) == pid);
+    assert(WIFEXITED(status) && WEXITSTATUS(status) == 0);
+}
+
+static void test_fd_pins_namespace(void)
+{
+    int fd = open_exited_uts_ns();
+    expect_join_uts(fd);
+    // 复制得到的文件描述符引用同一个namespace
+    int dup_fd = dup(fd);
+    assert(dup_fd >= 0);
+    close(fd);
+    expect_join_uts(dup_fd);
+    close(dup_fd);
+
+    // 当前进程的主机名不受影响
+    char name[64];
+    assert(gethostname(name, sizeof(name)) == 0);
+    assert(strcmp(name, HOSTNAME) != 0);
+    printf("ns fd pins namespace ok\n");
+}
+
+static void test_invalid(void)
+{
+    char path[64];
+    snprintf(path, sizeof(path), "/proc/%d/ns/uts", getpid());
+    int fd = open(path, O_RDONLY);
+    assert(fd >= 0);
+    // nstype与文件对应的namespace类型不一致
+    assert(setns(fd, CLONE_NEWIPC) == -1 && errno == EINVAL);
+    // 加入自己所在的namespace是允许的
+    assert(setns(fd, 0) == 0);
+    assert(setns(fd, CLONE_NEWUTS) == 0);
+    close(fd);
+
+    assert(setns(fd, CLONE_NEWUTS) == -1 && errno == EBADF);
+    // 不是ns文件
+    int pipefd[2];
+    assert(pipe(pipefd) == 0);
+    assert(setns(pipefd[0], 0) == -1 && errno == EINVAL);
+    close(pipefd[0]);
+    close(pipefd[1]);
+    printf("invalid arguments rejected\n");
+}
+
+int main()
+{
+    setbuf(stdout, NULL);
+    test_fd_pins_namespace();
+    test_invalid();
+    printf("All setns tests passed\n");
+    return 0;
+}
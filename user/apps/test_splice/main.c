@@ -0,0 +1,265 @@
+// 测试sendfile、splice、tee与copy_file_range：部分传输、偏移量的更新以及错误的参数
+#define _GNU_SOURCE
+#include <assert.h>
+#include <errno.h>
+#include <fcntl.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <sys/sendfile.h>
+#include <sys/stat.h>
+#include <unistd.h>
+
+#define SRC_PATH "/tmp/test_splice_src"
+#define DST_PATH "/tmp/test_splice_dst"
+
+// 比管道的容量大，保证非阻塞的传输只能完成一部分
+#define BIG_SIZE (128 * 1024)
+
+static char src_data[BIG_SIZE];
+
+static int create_src(void)
+{
+    for (size_t i = 0; i < sizeof(src_data); i++)
+        src_data[i] = 'a' + i % 26;
+    int fd = open(SRC_PATH, O_RDWR | O_CREAT | O_TRUNC, 0644);
+    assert(fd >= 0);
+    assert(write(fd, src_data, sizeof(src_data)) == sizeof(src_data));
+    assert(lseek(fd, 0, SEEK_SET) == 0);
+    return fd;
+}
+
+static int create_dst(int flags)
+{
+    int fd = open(DST_PATH, O_RDWR | O_CREAT | O_TRUNC | flags, 0644);
+    assert(fd >= 0);
+    return fd;
+}
+
+// 读出管道中的所有数据，检查它们与src_data从offset开始的内容相同
+static size_t drain_pipe(int rfd, size_t offset)
+{
+    char buf[4096];
+    size_t total = 0;
+    ssize_t n;
+    while ((n = read(rfd, buf, sizeof(buf))) > 0)
+    {
+        assert(memcmp(buf, src_data + offset + total, n) == 0);
+        total += n;
+    }
+    assert(n == -1 && errno == EAGAIN);
+    return total;
+}
+
+static void test_sendfile_partial(void)
+{
+    int src = create_src();
+    int dst = create_dst(0);
+
+    // count超过文件末尾时只传输到文件末尾
+    off_t off = BIG_SIZE - 100;
+    assert(sendfile(dst, src, &off, 1000) == 100);
+    assert(off == BIG_SIZE);
+    // 指定了偏移量时不改变源文件的读写位置
+    assert(lseek(src, 0, SEEK_CUR) == 0);
+    // 位于文件末尾时返回0
+    assert(sendfile(dst, src, &off, 1000) == 0);
+
+    // 没有指定偏移量时更新源文件的读写位置
+    assert(lseek(src, 10, SEEK_SET) == 10);
+    assert(sendfile(dst, src, NULL, 20) == 20);
+    assert(lseek(src, 0, SEEK_CUR) == 30);
+
+    char buf[120];
+    assert(pread(dst, buf, sizeof(buf), 0) == 120);
+    assert(memcmp(buf, src_data + BIG_SIZE - 100, 100) == 0);
+    assert(memcmp(buf + 100, src_data + 10, 20) == 0);
+
+    // 非阻塞的管道满了之后只传输一部分，剩下的数据可以继续传输
+    int fds[2];
+    assert(pipe2(fds, O_NONBLOCK) == 0);
+    off = 0;
+    size_t received = 0;
+    while (received < BIG_SIZE)
+    {
+        ssize_t n = sendfile(fds[1], src, &off, BIG_SIZE - off);
+        assert(n > 0 && n < BIG_SIZE);
+        assert(off == (off_t)(received + n));
+        // 管道满了
+        assert(sendfile(fds[1], src, &off, BIG_SIZE - off) == -1 && errno == EAGAIN);
+        received += drain_pipe(fds[0], received);
+        assert(received == (size_t)off);
+    }
+
+    close(fds[0]);
+    close(fds[1]);
+    close(src);
+    close(dst);
+    printf("sendfile partial transfer ok\n");
+}
+
+static void test_splice_partial(void)
+{
+    int src = create_src();
+    int dst = create_dst(0);
+    int fds[2];
+    assert(pipe2(fds, O_NONBLOCK) == 0);
+
+    // 文件到管道：len超过文件末尾时只传输到文件末尾
+    loff_t off_in = BIG_SIZE - 10;
+    assert(splice(src, &off_in, fds[1], NULL, 100, 0) == 10);
+    assert(off_in == BIG_SIZE);
+    assert(lseek(src, 0, SEEK_CUR) == 0);
+
+    // 管道到文件：len超过管道中的数据时只传输管道中的数据
+    loff_t off_out = 5;
+    assert(splice(fds[0], NULL, dst, &off_out, 100, 0) == 10);
+    assert(off_out == 15);
+    assert(lseek(dst, 0, SEEK_CUR) == 0);
+    char buf[16];
+    assert(pread(dst, buf, 10, 5) == 10);
+    assert(memcmp(buf, src_data + BIG_SIZE - 10, 10) == 0);
+
+    // 管道为空时非阻塞的splice返回EAGAIN
+    assert(splice(fds[0], NULL, dst, NULL, 100, 0) == -1 && errno == EAGAIN);
+
+    // 非阻塞的管道满了之后只传输一部分
+    ssize_t n = splice(src, NULL, fds[1], NULL, BIG_SIZE, 0);
+    assert(n > 0 && n < BIG_SIZE);
+    assert(lseek(src, 0, SEEK_CUR) == n);
+
+    // 管道到管道：只移动一部分数据，剩下的留在源管道中
+    int fds2[2];
+    assert(pipe2(fds2, O_NONBLOCK) == 0);
+    assert(splice(fds[0], NULL, fds2[1], NULL, 7, 0) == 7);
+    assert(drain_pipe(fds2[0], 0) == 7);
+    assert(drain_pipe(fds[0], 7) == (size_t)n - 7);
+
+    // tee只复制数据，源管道中的数据保持不变
+    assert(write(fds[1], src_data, 20) == 20);
+    assert(tee(fds[0], fds2[1], 8, SPLICE_F_NONBLOCK) == 8);
+    assert(drain_pipe(fds2[0], 0) == 8);
+    assert(drain_pipe(fds[0], 0) == 20);
+    assert(tee(fds[0], fds2[1], 8, SPLICE_F_NONBLOCK) == -1 && errno == EAGAIN);
+
+    close(fds2[0]);
+    close(fds2[1]);
+    close(fds[0]);
+    close(fds[1]);
+    close(src);
+    close(dst);
+    printf("splice and tee partial transfer ok\n");
+}
+
+static void test_copy_file_range_partial(void)
+{
+    int src = create_src();
+    int dst = create_dst(0);
+
+    // len超过文件末尾时只复制到文件末尾
+    loff_t off_in = BIG_SIZE - 50;
+    loff_t off_out = 0;
+    assert(copy_file_range(src, &off_in, dst, &off_out, 1000, 0) == 50);
+    assert(off_in == BIG_SIZE && off_out == 50);
+    assert(lseek(src, 0, SEEK_CUR) == 0);
+    assert(lseek(dst, 0, SEEK_CUR) == 0);
+    assert(copy_file_range(src, &off_in, dst, &off_out, 1000, 0) == 0);
+
+    // 没有指定偏移量时使用并更新文件的读写位置
+    assert(lseek(src, 100, SEEK_SET) == 100);
+    assert(lseek(dst, 50, SEEK_SET) == 50);
+    assert(copy_file_range(src, NULL, dst, NULL, 30, 0) == 30);
+    assert(lseek(src, 0, SEEK_CUR) == 130);
+    assert(lseek(dst, 0, SEEK_CUR) == 80);
+
+    // 在目标文件末尾之后写入时，中间是空洞
+    off_in = 0;
+    off_out = 1000;
+    assert(copy_file_range(src, &off_in, dst, &off_out, 10, 0) == 10);
+    struct stat st;
+    assert(fstat(dst, &st) == 0 && st.st_size == 1010);
+
+    char buf[1010];
+    assert(pread(dst, buf, sizeof(buf), 0) == sizeof(buf));
+    assert(memcmp(buf, src_data + BIG_SIZE - 50, 50) == 0);
+    assert(memcmp(buf + 50, src_data + 100, 30) == 0);
+    for (int i = 80; i < 1000; i++)
+        assert(buf[i] == 0);
+    assert(memcmp(buf + 1000, src_data, 10) == 0);
+
+    // 同一个文件中不重叠的区域可以复制
+    off_in = 0;
+    off_out = 1000;
+    assert(copy_file_range(src, &off_in, src, &off_out, 1000, 0) == 1000);
+    assert(pread(src, buf, 1000, 1000) == 1000);
+    assert(memcmp(buf, src_data, 1000) == 0);
+
+    close(src);
+    close(dst);
+    printf("copy_file_range partial transfer ok\n");
+}
+
+static void test_invalid_args(void)
+{
+    int src = create_src();
+    int dst = create_dst(0);
+    int fds[2];
+    assert(pipe2(fds, O_NONBLOCK) == 0);
+    loff_t off = 0;
+    loff_t off2 = 10;
+
+    // sendfile的源文件必须是普通文件
+    assert(write(fds[1], "x", 1) == 1);
+    assert(sendfile(dst, fds[0], NULL, 1) == -1 && errno == EINVAL);
+    // 偏移量不能为负数
+    off_t neg = -1;
+    assert(sendfile(dst, src, &neg, 1) == -1 && errno == EINVAL);
+
+    // 管道一侧不能指定偏移量
+    assert(splice(fds[0], &off, dst, NULL, 1, 0) == -1 && errno == ESPIPE);
+    assert(splice(src, NULL, fds[1], &off, 1, 0) == -1 && errno == ESPIPE);
+    // 两侧都不是管道
+    assert(splice(src, NULL, dst, NULL, 1, 0) == -1 && errno == EINVAL);
+    // 未知的flags
+    assert(splice(fds[0], NULL, dst, NULL, 1, 0x100) == -1 && errno == EINVAL);
+    // tee的两侧都必须是管道，并且不能是同一个管道
+    assert(tee(src, fds[1], 1, 0) == -1 && errno == EINVAL);
+    assert(tee(fds[0], fds[1], 1, 0) == -1 && errno == EINVAL);
+
+    // copy_file_range的flags必须为0，源区域与目标区域不能重叠
+    assert(copy_file_range(src, NULL, dst, NULL, 1, 1) == -1 && errno == EINVAL);
+    off = 0;
+    assert(copy_file_range(src, &off, src, &off2, 100, 0) == -1 && errno == EINVAL);
+    // 不是普通文件
+    assert(copy_file_range(fds[0], NULL, dst, NULL, 1, 0) == -1 && errno == EINVAL);
+    int dir = open("/tmp", O_RDONLY | O_DIRECTORY);
+    assert(dir >= 0);
+    assert(copy_file_range(dir, NULL, dst, NULL, 1, 0) == -1 && errno == EISDIR);
+    close(dir);
+    // 目标文件以O_APPEND打开
+    int append = open(DST_PATH, O_WRONLY | O_APPEND);
+    assert(append >= 0);
+    assert(copy_file_range(src, NULL, append, NULL, 1, 0) == -1 && errno == EBADF);
+    // 源文件不可读
+    assert(copy_file_range(append, NULL, dst, NULL, 1, 0) == -1 && errno == EBADF);
+    close(append);
+
+    close(fds[0]);
+    close(fds[1]);
+    close(src);
+    close(dst);
+    printf("invalid arguments rejected\n");
+}
+
+int main()
+{
+    setbuf(stdout, NULL);
+    test_sendfile_partial();
+    test_splice_partial();
+    test_copy_file_range_partial();
+    test_invalid_args();
+    assert(unlink(SRC_PATH) == 0);
+    assert(unlink(DST_PATH) == 0);
+    printf("All splice tests passed\n");
+    return 0;
+}
@@ -0,0 +1,140 @@
+// 测试tmpfs：size=、nr_inodes=与mode=挂载选项，容量与inode数的统计，以及超出上限时的ENOSPC
+#define _GNU_SOURCE
+#include <assert.h>
+#include <errno.h>
+#include <fcntl.h>
+#include <stdio.h>
+#include <string.h>
+#include <sys/mount.h>
+#include <sys/stat.h>
+#include <sys/statfs.h>
+#include <unistd.h>
+
+#define MNT "/tmp/test_tmpfs"
+#define TMPFS_MAGIC 0x01021994
+
+static long page_size;
+static char page[65536];
+
+static struct statfs get_statfs(void)
+{
+    struct statfs st;
+    assert(statfs(MNT, &st) == 0);
+    return st;
+}
+
+static void test_mount_options(void)
+{
+    // 未知的选项与非法的值
+    assert(mount("tmpfs", MNT, "tmpfs", 0, "foo=1") == -1 && errno == EINVAL);
+    assert(mount("tmpfs", MNT, "tmpfs", 0, "size=abc") == -1 && errno == EINVAL);
+    assert(mount("tmpfs", MNT, "tmpfs", 0, "mode=9") == -1 && errno == EINVAL);
+
+    assert(mount("tmpfs", MNT, "tmpfs", 0, "size=1m,nr_inodes=100,mode=710") == 0);
+    struct statfs st = get_statfs();
+    assert(st.f_type == TMPFS_MAGIC);
+    assert(st.f_bsize == page_size);
+    assert(st.f_blocks == (unsigned long)((1 << 20) / page_size));
+    assert(st.f_bfree == st.f_blocks);
+    // 根目录占用一个inode
+    assert(st.f_files == 100);
+    assert(st.f_ffree == 99);
+
+    struct stat sb;
+    assert(stat(MNT, &sb) == 0);
+    assert(S_ISDIR(sb.st_mode) && (sb.st_mode & 07777) == 0710);
+    assert(umount(MNT) == 0);
+    printf("mount options ok\n");
+}
+
+static void test_size_limit(void)
+{
+    char opts[64];
+    snprintf(opts, sizeof(opts), "size=%ld", page_size * 4);
+    assert(mount("tmpfs", MNT, "tmpfs", 0, opts) == 0);
+    assert(get_statfs().f_blocks == 4);
+
+    int fd = open(MNT "/file", O_RDWR | O_CREAT, 0644);
+    assert(fd >= 0);
+    for (int i = 0; i < 4; i++)
+        assert(write(fd, page, page_size) == page_size);
+    assert(get_statfs().f_bfree == 0);
+    // 容量已满
+    assert(write(fd, page, 1) == -1 && errno == ENOSPC);
+    int fd2 = open(MNT "/other", O_RDWR | O_CREAT, 0644);
+    assert(fd2 >= 0);
+    assert(write(fd2, page, 1) == -1 && errno == ENOSPC);
+
+    // 截断文件后释放页面
+    assert(ftruncate(fd, page_size) == 0);
+    assert(get_statfs().f_bfree == 3);
+    assert(write(fd2, page, page_size) == page_size);
+    assert(get_statfs().f_bfree == 2);
+
+    // 扩大文件不分配页面，文件中的空洞读出来是0
+    assert(ftruncate(fd, page_size * 100) == 0);
+    assert(get_statfs().f_bfree == 2);
+    char buf[16];
+    memset(buf, 1, sizeof(buf));
+    assert(pread(fd, buf, sizeof(buf), page_size * 50) == sizeof(buf));
+    for (size_t i = 0; i < sizeof(buf); i++)
+        assert(buf[i] == 0);
+    struct stat sb;
+    assert(fstat(fd, &sb) == 0);
+    assert(sb.st_size == page_size * 100);
+    assert(sb.st_blocks == page_size / 512);
+
+    // 删除已经关闭的文件后释放页面；仍然打开的文件在关闭后才释放
+    close(fd2);
+    assert(unlink(MNT "/other") == 0);
+    assert(get_statfs().f_bfree == 3);
+    assert(unlink(MNT "/file") == 0);
+    assert(get_statfs().f_bfree == 3);
+    close(fd);
+    assert(get_statfs().f_bfree == 4);
+
+    assert(umount(MNT) == 0);
+    printf("size= limit ok\n");
+}
+
+static void test_inode_limit(void)
+{
+    assert(mount("tmpfs", MNT, "tmpfs", 0, "nr_inodes=4") == 0);
+    // 根目录占用了一个inode，还可以再创建三个
+    assert(mkdir(MNT "/dir", 0755) == 0);
+    int fd = open(MNT "/dir/a", O_RDWR | O_CREAT, 0644);
+    assert(fd >= 0);
+    close(fd);
+    assert(symlink("a", MNT "/dir/link") == 0);
+    assert(get_statfs().f_ffree == 0);
+    assert(open(MNT "/b", O_RDWR | O_CREAT, 0644) == -1 && errno == ENOSPC);
+    assert(mkdir(MNT "/c", 0755) == -1 && errno == ENOSPC);
+
+    // 删除文件后释放inode
+    assert(unlink(MNT "/dir/a") == 0);
+    assert(get_statfs().f_ffree == 1);
+    assert(mkdir(MNT "/c", 0755) == 0);
+
+    assert(unlink(MNT "/dir/link") == 0);
+    assert(rmdir(MNT "/dir") == 0);
+    assert(rmdir(MNT "/c") == 0);
+    assert(get_statfs().f_ffree == 3);
+    assert(umount(MNT) == 0);
+    printf("nr_inodes= limit ok\n");
+}
+
+int main()
+{
+    page_size = sysconf(_SC_PAGESIZE);
+    assert(page_size <= (long)sizeof(page));
+    memset(page, 'x', sizeof(page));
+    mkdir(MNT, 0755);
+
+    test_mount_options();
+    test_size_limit();
+    test_inode_limit();
+
+    assert(rmdir(MNT) == 0);
+    printf("All tmpfs tests passed\n");
+    return 0;
+}
@@ -0,0 +1,122 @@
+// 测试veth网卡对：在子进程的network namespace中创建veth的一端，配置地址后通过UDP通信
+#define _GNU_SOURCE
+#include <arpa/inet.h>
+#include <assert.h>
+#include <fcntl.h>
+#include <net/if.h>
+#include <netinet/in.h>
+#include <sched.h>
+#include <stdio.h>
+#include <string.h>
+#include <sys/ioctl.h>
+#include <sys/socket.h>
+#include <sys/time.h>
+#include <sys/wait.h>
+#include <unistd.h>
+
+// DragonOS私有的ioctl，用于创建veth网卡对
+#define SIOCVETHCREATE 0x89f0
+
+struct veth_create_req
+{
+    char name[IFNAMSIZ];
+    char peer_name[IFNAMSIZ];
+    int peer_netns_fd;
+    int pad;
+};
+
+static void set_addr(const char *ifname, const char *addr, const char *mask)
+{
+    int fd = socket(AF_INET, SOCK_DGRAM, 0);
+    assert(fd >= 0);
+
+    struct ifreq ifr;
+    memset(&ifr, 0, sizeof(ifr));
+    strncpy(ifr.ifr_name, ifname, IFNAMSIZ - 1);
+    struct sockaddr_in *sin = (struct sockaddr_in *)&ifr.ifr_addr;
+    sin->sin_family = AF_INET;
+    inet_pton(AF_INET, addr, &sin->sin_addr);
+    assert(ioctl(fd, SIOCSIFADDR, &ifr) == 0);
+
+    inet_pton(AF_INET, mask, &sin->sin_addr);
+    assert(ioctl(fd, SIOCSIFNETMASK, &ifr) == 0);
+
+    memset(&ifr.ifr_addr, 0, sizeof(ifr.ifr_addr));
+    assert(ioctl(fd, SIOCGIFADDR, &ifr) == 0);
+    char buf[INET_ADDRSTRLEN];
+    inet_ntop(AF_INET, &sin->sin_addr, buf, sizeof(buf));
+    printf("%s: %s\n", ifname, buf);
+    assert(strcmp(buf, addr) == 0);
+    close(fd);
+}
+
+int main()
+{
+    int to_child[2], to_parent[2];
+    assert(pipe(to_child) == 0 && pipe(to_parent) == 0);
+
+    pid_t pid = fork();
+    assert(pid >= 0);
+    if (pid == 0)
+    {
+        assert(unshare(CLONE_NEWNET) == 0);
+        write(to_parent[1], "r", 1);
+
+        // 等待父进程把veth的另一端移入当前namespace
+        char ifname[IFNAMSIZ] = {0};
+        assert(read(to_child[0], ifname, sizeof(ifname)) > 0);
+        set_addr(ifname, "10.0.0.2", "255.255.255.0");
+
+        int fd = socket(AF_INET, SOCK_DGRAM, 0);
+        struct sockaddr_in addr = {.sin_family = AF_INET, .sin_port = htons(5000)};
+        inet_pton(AF_INET, "10.0.0.2", &addr.sin_addr);
+        assert(bind(fd, (struct sockaddr *)&addr, sizeof(addr)) == 0);
+        write(to_parent[1], "b", 1);
+
+        char buf[64];
+        struct sockaddr_in src;
+        socklen_t len = sizeof(src);
+        ssize_t n = recvfrom(fd, buf, sizeof(buf), 0, (struct sockaddr *)&src, &len);
+        assert(n > 0);
+        sendto(fd, buf, n, 0, (struct sockaddr *)&src, len);
+        return 0;
+    }
+
+    char c;
+    assert(read(to_parent[0], &c, 1) == 1);
+
+    char path[64];
+    snprintf(path, sizeof(path), "/proc/%d/ns/net", pid);
+    int nsfd = open(path, O_RDONLY);
+    assert(nsfd >= 0);
+
+    int sock = socket(AF_INET, SOCK_DGRAM, 0);
+    struct veth_create_req req;
+    memset(&req, 0, sizeof(req));
+    req.peer_netns_fd = nsfd;
+    assert(ioctl(sock, SIOCVETHCREATE, &req) == 0);
+    printf("created %s <-> %s (in child netns)\n", req.name, req.peer_name);
+    close(sock);
+    close(nsfd);
+
+    set_addr(req.name, "10.0.0.1", "255.255.255.0");
+    write(to_child[1], req.peer_name, sizeof(req.peer_name));
+    assert(read(to_parent[0], &c, 1) == 1);
+
+    int fd = socket(AF_INET, SOCK_DGRAM, 0);
+    struct timeval tv = {.tv_sec = 5};
+    setsockopt(fd, SOL_SOCKET, SO_RCVTIMEO, &tv, sizeof(tv));
+    struct sockaddr_in dst = {.sin_family = AF_INET, .sin_port = htons(5000)};
+    inet_pton(AF_INET, "10.0.0.2", &dst.sin_addr);
+    const char *msg = "hello veth";
+    assert(sendto(fd, msg, strlen(msg), 0, (struct sockaddr *)&dst, sizeof(dst)) == (ssize_t)strlen(msg));
+
+    char buf[64] = {0};
+    ssize_t n = recv(fd, buf, sizeof(buf) - 1, 0);
+    assert(n == (ssize_t)strlen(msg) && strcmp(buf, msg) == 0);
+
+    int status;
+    waitpid(pid, &status, 0);
+    printf("test_veth: PASS\n");
+    return 0;
+}
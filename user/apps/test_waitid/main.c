@@ -0,0 +1,286 @@
+// 测试waitid的各种选项组合，以及pidfd_open、pidfd_send_signal与通过epoll等待pidfd就绪
+#define _GNU_SOURCE
+#include <assert.h>
+#include <errno.h>
+#include <fcntl.h>
+#include <signal.h>
+#include <stdio.h>
+#include <string.h>
+#include <sys/epoll.h>
+#include <sys/syscall.h>
+#include <sys/wait.h>
+#include <unistd.h>
+
+#ifndef P_PIDFD
+#define P_PIDFD 3
+#endif
+
+#ifndef PIDFD_NONBLOCK
+#define PIDFD_NONBLOCK O_NONBLOCK
+#endif
+
+static int sys_pidfd_open(pid_t pid, unsigned int flags)
+{
+    return syscall(SYS_pidfd_open, pid, flags);
+}
+
+static int sys_pidfd_send_signal(int pidfd, int sig)
+{
+    return syscall(SYS_pidfd_send_signal, pidfd, sig, NULL, 0);
+}
+
+// 创建一个子进程，等待父进程通过管道通知后以code退出
+static pid_t spawn_waiting(int *notify_fd, int code)
+{
+    int fds[2];
+    assert(pipe(fds) == 0);
+    pid_t pid = fork();
+    assert(pid >= 0);
+    if (pid == 0)
+    {
+        close(fds[1]);
+        char c;
+        read(fds[0], &c, 1);
+        _exit(code);
+    }
+    close(fds[0]);
+    *notify_fd = fds[1];
+    return pid;
+}
+
+static void release(int notify_fd)
+{
+    assert(write(notify_fd, "x", 1) == 1);
+    close(notify_fd);
+}
+
+static void test_exited(void)
+{
+    int notify;
+    pid_t pid = spawn_waiting(&notify, 42);
+
+    // 子进程尚未退出时，WNOHANG返回0，并且si_pid为0
+    siginfo_t info;
+    memset(&info, 0xff, sizeof(info));
+    assert(waitid(P_PID, pid, &info, WEXITED | WNOHANG) == 0);
+    assert(info.si_pid == 0);
+
+    release(notify);
+
+    // WNOWAIT只报告状态，子进程仍然可以被再次等待
+    memset(&info, 0, sizeof(info));
+    assert(waitid(P_PID, pid, &info, WEXITED | WNOWAIT) == 0);
+    assert(info.si_signo == SIGCHLD);
+    assert(info.si_code == CLD_EXITED);
+    assert(info.si_pid == pid);
+    assert(info.si_status == 42);
+
+    memset(&info, 0, sizeof(info));
+    assert(waitid(P_ALL, 0, &info, WEXITED) == 0);
+    assert(info.si_pid == pid && info.si_status == 42);
+
+    // 子进程已经被回收
+    assert(waitid(P_PID, pid, &info, WEXITED) == -1);
+    assert(errno == ECHILD);
+    printf("waitid WEXITED/WNOHANG/WNOWAIT ok\n");
+}
+
+static void test_killed_and_stopped(void)
+{
+    int notify;
+    pid_t pid = spawn_waiting(&notify, 0);
+    siginfo_t info;
+
+    // 只等待WEXITED时不报告停止的子进程
+    assert(kill(pid, SIGSTOP) == 0);
+    memset(&info, 0xff, sizeof(info));
+    assert(waitid(P_PID, pid, &info, WEXITED | WNOHANG) == 0);
+    assert(info.si_pid == 0);
+    memset(&info, 0, sizeof(info));
+    assert(waitid(P_PID, pid, &info, WSTOPPED) == 0);
+    assert(info.si_code == CLD_STOPPED);
+    assert(info.si_status == SIGSTOP);
+    assert(info.si_pid == pid);
+    // 停止事件已经被消费
+    memset(&info, 0xff, sizeof(info));
+    assert(waitid(P_PID, pid, &info, WSTOPPED | WEXITED | WNOHANG) == 0);
+    assert(info.si_pid == 0);
+
+    assert(kill(pid, SIGCONT) == 0);
+    memset(&info, 0, sizeof(info));
+    assert(waitid(P_PID, pid, &info, WCONTINUED) == 0);
+    assert(info.si_code == CLD_CONTINUED);
+    assert(info.si_status == SIGCONT);
+
+    assert(kill(pid, SIGKILL) == 0);
+    memset(&info, 0, sizeof(info));
+    assert(waitid(P_PID, pid, &info, WEXITED) == 0);
+    assert(info.si_code == CLD_KILLED);
+    assert(info.si_status == SIGKILL);
+    close(notify);
+    printf("waitid WSTOPPED/WCONTINUED/CLD_KILLED ok\n");
+}
+
+static void test_pgid(void)
+{
+    int notify;
+    pid_t pid = spawn_waiting(&notify, 7);
+    assert(setpgid(pid, pid) == 0);
+    release(notify);
+
+    siginfo_t info;
+    // 按进程组等待
+    assert(waitid(P_PGID, getpgrp(), &info, WEXITED | WNOHANG) == -1);
+    assert(errno == ECHILD);
+    memset(&info, 0, sizeof(info));
+    assert(waitid(P_PGID, pid, &info, WEXITED) == 0);
+    assert(info.si_pid == pid && info.si_status == 7);
+    printf("waitid P_PGID ok\n");
+}
+
+static void test_invalid_args(void)
+{
+    siginfo_t info;
+    // 必须至少指定WEXITED、WSTOPPED、WCONTINUED中的一个
+    assert(waitid(P_ALL, 0, &info, WNOHANG) == -1);
+    assert(errno == EINVAL);
+    // 未知的选项
+    assert(waitid(P_ALL, 0, &info, WEXITED | 0x10000) == -1);
+    assert(errno == EINVAL);
+    // 未知的类型
+    assert(syscall(SYS_waitid, 42, 0, &info, WEXITED, NULL) == -1);
+    assert(errno == EINVAL);
+    // P_PID的pid必须为正数
+    assert(waitid(P_PID, 0, &info, WEXITED) == -1);
+    assert(errno == EINVAL);
+    // 没有子进程
+    assert(waitid(P_ALL, 0, &info, WEXITED) == -1);
+    assert(errno == ECHILD);
+    // P_PIDFD的参数不是pidfd
+    assert(waitid(P_PIDFD, 0, &info, WEXITED) == -1);
+    assert(errno == EBADF);
+    printf("waitid invalid arguments rejected\n");
+}
+
+static void test_pidfd_epoll(void)
+{
+    int notify;
+    pid_t pid = spawn_waiting(&notify, 3);
+    int pidfd = sys_pidfd_open(pid, 0);
+    assert(pidfd >= 0);
+    // pidfd总是带有close-on-exec标志
+    assert(fcntl(pidfd, F_GETFD) & FD_CLOEXEC);
+
+    int epfd = epoll_create1(0);
+    assert(epfd >= 0);
+    struct epoll_event ev = {.events = EPOLLIN, .data.fd = pidfd};
+    assert(epoll_ctl(epfd, EPOLL_CTL_ADD, pidfd, &ev) == 0);
+
+    // 子进程运行期间pidfd不可读
+    struct epoll_event out;
+    assert(epoll_wait(epfd, &out, 1, 0) == 0);
+
+    // 子进程退出后pidfd可读，即使还没有被回收
+    release(notify);
+    memset(&out, 0, sizeof(out));
+    assert(epoll_wait(epfd, &out, 1, 5000) == 1);
+    assert(out.data.fd == pidfd);
+    assert(out.events & EPOLLIN);
+
+    // 通过pidfd回收子进程
+    siginfo_t info;
+    memset(&info, 0, sizeof(info));
+    assert(waitid(P_PIDFD, pidfd, &info, WEXITED) == 0);
+    assert(info.si_pid == pid && info.si_code == CLD_EXITED && info.si_status == 3);
+
+    // 回收之后仍然可读，并且报告EPOLLHUP
+    memset(&out, 0, sizeof(out));
+    assert(epoll_wait(epfd, &out, 1, 0) == 1);
+    assert(out.events & EPOLLIN);
+    assert(out.events & EPOLLHUP);
+
+    // 进程已经不存在
+    assert(sys_pidfd_send_signal(pidfd, 0) == -1);
+    assert(errno == ESRCH);
+    assert(waitid(P_PIDFD, pidfd, &info, WEXITED) == -1);
+    assert(errno == ECHILD);
+
+    close(epfd);
+    close(pidfd);
+    printf("pidfd epoll readiness ok\n");
+}
+
+static void test_pidfd_signal_nonblock(void)
+{
+    int notify;
+    pid_t pid = spawn_waiting(&notify, 0);
+    int pidfd = sys_pidfd_open(pid, PIDFD_NONBLOCK);
+    assert(pidfd >= 0);
+
+    // 非阻塞的pidfd在子进程状态没有变化时返回EAGAIN
+    siginfo_t info;
+    assert(waitid(P_PIDFD, pidfd, &info, WEXITED) == -1);
+    assert(errno == EAGAIN);
+
+    // 信号为0时只检查权限
+    assert(sys_pidfd_send_signal(pidfd, 0) == 0);
+    assert(sys_pidfd_send_signal(pidfd, SIGKILL) == 0);
+    memset(&info, 0, sizeof(info));
+    // 子进程退出需要一些时间，重试直到回收成功
+    while (waitid(P_PIDFD, pidfd, &info, WEXITED) == -1)
+    {
+        assert(errno == EAGAIN);
+        usleep(1000);
+    }
+    assert(info.si_pid == pid && info.si_code == CLD_KILLED && info.si_status == SIGKILL);
+
+    close(notify);
+    close(pidfd);
+    printf("pidfd_send_signal and PIDFD_NONBLOCK ok\n");
+}
+
+static void test_pidfd_invalid(void)
+{
+    assert(sys_pidfd_open(0, 0) == -1);
+    assert(errno == EINVAL);
+    assert(sys_pidfd_open(getpid(), 1) == -1);
+    assert(errno == EINVAL);
+
+    // 找一个不存在的pid
+    pid_t pid = fork();
+    assert(pid >= 0);
+    if (pid == 0)
+        _exit(0);
+    assert(waitpid(pid, NULL, 0) == pid);
+    assert(sys_pidfd_open(pid, 0) == -1);
+    assert(errno == ESRCH);
+
+    // 不是pidfd的文件描述符
+    int fds[2];
+    assert(pipe(fds) == 0);
+    assert(sys_pidfd_send_signal(fds[0], 0) == -1);
+    assert(errno == EBADF);
+    // 未知的flags
+    int pidfd = sys_pidfd_open(getpid(), 0);
+    assert(pidfd >= 0);
+    assert(syscall(SYS_pidfd_send_signal, pidfd, 0, NULL, 0x100) == -1);
+    assert(errno == EINVAL);
+    close(pidfd);
+    close(fds[0]);
+    close(fds[1]);
+    printf("pidfd invalid arguments rejected\n");
+}
+
+int main()
+{
+    setbuf(stdout, NULL);
+    test_exited();
+    test_killed_and_stopped();
+    test_pgid();
+    test_invalid_args();
+    test_pidfd_epoll();
+    test_pidfd_signal_nonblock();
+    test_pidfd_invalid();
+    printf("All waitid tests passed\n");
+    return 0;
+}
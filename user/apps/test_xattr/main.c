@@ -0,0 +1,204 @@
+// 测试扩展属性：user.、trusted.、security.命名空间的读写与权限，以及各种错误情况
+#define _GNU_SOURCE
+#include <assert.h>
+#include <errno.h>
+#include <fcntl.h>
+#include <stdio.h>
+#include <string.h>
+#include <sys/mount.h>
+#include <sys/stat.h>
+#include <sys/wait.h>
+#include <sys/xattr.h>
+#include <unistd.h>
+
+#define ROOT "/tmp/test_xattr"
+#define FILE_PATH ROOT "/file"
+#define LINK_PATH ROOT "/link"
+#define STICKY_DIR ROOT "/sticky"
+
+// 没有任何特权的用户
+#define NOBODY 65534
+
+// 检查listxattr返回的列表中是否包含name
+static int list_contains(const char *list, ssize_t len, const char *name)
+{
+    for (const char *p = list; p < list + len; p += strlen(p) + 1)
+    {
+        if (strcmp(p, name) == 0)
+            return 1;
+    }
+    return 0;
+}
+
+static void test_user_namespace(void)
+{
+    char buf[64];
+    assert(setxattr(FILE_PATH, "user.a", "hello", 5, 0) == 0);
+
+    // size为0时只返回属性值的长度，缓冲区太小时返回ERANGE
+    assert(getxattr(FILE_PATH, "user.a", NULL, 0) == 5);
+    assert(getxattr(FILE_PATH, "user.a", buf, 4) == -1 && errno == ERANGE);
+    memset(buf, 0, sizeof(buf));
+    assert(getxattr(FILE_PATH, "user.a", buf, sizeof(buf)) == 5);
+    assert(memcmp(buf, "hello", 5) == 0);
+
+    // XATTR_CREATE要求属性不存在，XATTR_REPLACE要求属性已经存在
+    assert(setxattr(FILE_PATH, "user.a", "x", 1, XATTR_CREATE) == -1 && errno == EEXIST);
+    assert(setxattr(FILE_PATH, "user.b", "x", 1, XATTR_REPLACE) == -1 && errno == ENODATA);
+    assert(setxattr(FILE_PATH, "user.a", "world!", 6, XATTR_REPLACE) == 0);
+    assert(getxattr(FILE_PATH, "user.a", buf, sizeof(buf)) == 6);
+    assert(memcmp(buf, "world!", 6) == 0);
+
+    // 值可以为空
+    assert(setxattr(FILE_PATH, "user.empty", "", 0, XATTR_CREATE) == 0);
+    assert(getxattr(FILE_PATH, "user.empty", buf, sizeof(buf)) == 0);
+
+    // 通过文件描述符访问
+    int fd = open(FILE_PATH, O_RDWR);
+    assert(fd >= 0);
+    assert(fsetxattr(fd, "user.fd", "1", 1, 0) == 0);
+    assert(fgetxattr(fd, "user.fd", buf, sizeof(buf)) == 1);
+
+    char list[256];
+    ssize_t len = flistxattr(fd, NULL, 0);
+    assert(len == (ssize_t)(sizeof("user.a") + sizeof("user.empty") + sizeof("user.fd")));
+    assert(flistxattr(fd, list, len - 1) == -1 && errno == ERANGE);
+    assert(flistxattr(fd, list, sizeof(list)) == len);
+    assert(list_contains(list, len, "user.a"));
+    assert(list_contains(list, len, "user.empty"));
+    assert(list_contains(list, len, "user.fd"));
+
+    assert(fremovexattr(fd, "user.fd") == 0);
+    assert(fremovexattr(fd, "user.fd") == -1 && errno == ENODATA);
+    assert(removexattr(FILE_PATH, "user.empty") == 0);
+    assert(fgetxattr(fd, "user.fd", buf, sizeof(buf)) == -1 && errno == ENODATA);
+    close(fd);
+    printf("user namespace ok\n");
+}
+
+static void test_invalid_names(void)
+{
+    char buf[16];
+    char long_name[300];
+    memset(long_name, 'a', sizeof(long_name) - 1);
+    long_name[sizeof(long_name) - 1] = '\0';
+    memcpy(long_name, "user.", 5);
+
+    // 不支持的命名空间
+    assert(setxattr(FILE_PATH, "foo.bar", "x", 1, 0) == -1 && errno == EOPNOTSUPP);
+    assert(getxattr(FILE_PATH, "foo.bar", buf, sizeof(buf)) == -1 && errno == EOPNOTSUPP);
+    // 前缀之后的名字为空
+    assert(setxattr(FILE_PATH, "user.", "x", 1, 0) == -1 && errno == EINVAL);
+    // 属性名为空或者太长
+    assert(setxattr(FILE_PATH, "", "x", 1, 0) == -1 && errno == ERANGE);
+    assert(setxattr(FILE_PATH, long_name, "x", 1, 0) == -1 && errno == ERANGE);
+    // 未知的flags
+    assert(setxattr(FILE_PATH, "user.a", "x", 1, 4) == -1 && errno == EINVAL);
+    // 属性值太大
+    static char big[65537];
+    assert(setxattr(FILE_PATH, "user.big", big, sizeof(big), 0) == -1 && errno == E2BIG);
+    // 文件不存在
+    assert(getxattr(ROOT "/none", "user.a", buf, sizeof(buf)) == -1 && errno == ENOENT);
+    assert(fgetxattr(-1, "user.a", buf, sizeof(buf)) == -1 && errno == EBADF);
+    printf("invalid names rejected\n");
+}
+
+static void test_symlink(void)
+{
+    char buf[16];
+    assert(symlink(FILE_PATH, LINK_PATH) == 0);
+
+    // 跟随符号链接时访问的是目标文件
+    assert(getxattr(LINK_PATH, "user.a", buf, sizeof(buf)) == 6);
+    // 符号链接本身不能有用户属性
+    assert(lsetxattr(LINK_PATH, "user.a", "x", 1, 0) == -1 && errno == EPERM);
+    assert(lgetxattr(LINK_PATH, "user.a", buf, sizeof(buf)) == -1 && errno == ENODATA);
+    // 但是可以有trusted属性
+    assert(lsetxattr(LINK_PATH, "trusted.t", "l", 1, 0) == 0);
+    assert(lgetxattr(LINK_PATH, "trusted.t", buf, sizeof(buf)) == 1);
+    assert(getxattr(FILE_PATH, "trusted.t", buf, sizeof(buf)) == -1 && errno == ENODATA);
+
+    assert(unlink(LINK_PATH) == 0);
+    printf("symlink ok\n");
+}
+
+static void child_unprivileged(void)
+{
+    char buf[16];
+    char list[256];
+    assert(setresgid(NOBODY, NOBODY, NOBODY) == 0);
+    assert(setresuid(NOBODY, NOBODY, NOBODY) == 0);
+
+    // trusted属性不可见，也不能被修改
+    assert(getxattr(FILE_PATH, "trusted.t", buf, sizeof(buf)) == -1 && errno == ENODATA);
+    assert(setxattr(FILE_PATH, "trusted.t", "x", 1, 0) == -1 && errno == EPERM);
+    assert(removexattr(FILE_PATH, "trusted.t") == -1 && errno == EPERM);
+    ssize_t len = listxattr(FILE_PATH, list, sizeof(list));
+    assert(len > 0);
+    assert(list_contains(list, len, "user.a"));
+    assert(list_contains(list, len, "security.s"));
+    assert(!list_contains(list, len, "trusted.t"));
+
+    // security属性可以读取，但是不能写入
+    assert(getxattr(FILE_PATH, "security.s", buf, sizeof(buf)) == 1);
+    assert(setxattr(FILE_PATH, "security.s", "x", 1, 0) == -1 && errno == EPERM);
+
+    // user属性的读写需要文件的读写权限
+    assert(getxattr(FILE_PATH, "user.a", buf, sizeof(buf)) == 6);
+    assert(setxattr(FILE_PATH, "user.a", "x", 1, 0) == -1 && errno == EACCES);
+
+    // 设置了粘滞位的目录，只有属主才能修改其用户属性
+    assert(setxattr(STICKY_DIR, "user.d", "x", 1, 0) == -1 && errno == EPERM);
+    _exit(0);
+}
+
+static void test_privileges(void)
+{
+    assert(setxattr(FILE_PATH, "trusted.t", "t", 1, 0) == 0);
+    assert(setxattr(FILE_PATH, "security.s", "s", 1, 0) == 0);
+    assert(chmod(FILE_PATH, 0644) == 0);
+    assert(mkdir(STICKY_DIR, 01777) == 0);
+    assert(chmod(STICKY_DIR, 01777) == 0);
+
+    // 拥有CAP_SYS_ADMIN时可以看到所有属性
+    char list[256];
+    ssize_t len = listxattr(FILE_PATH, list, sizeof(list));
+    assert(len > 0);
+    assert(list_contains(list, len, "trusted.t"));
+    assert(list_contains(list, len, "security.s"));
+
+    pid_t pid = fork();
+    assert(pid >= 0);
+    if (pid == 0)
+        child_unprivileged();
+    int status;
+    assert(waitpid(pid, &status, 0) == pid);
+    assert(WIFEXITED(status) && WEXITSTATUS(status) == 0);
+
+    // 属主可以修改粘滞目录的用户属性
+    assert(setxattr(STICKY_DIR, "user.d", "x", 1, 0) == 0);
+    assert(rmdir(STICKY_DIR) == 0);
+    printf("namespace permissions ok\n");
+}
+
+int main()
+{
+    setbuf(stdout, NULL);
+    mkdir(ROOT, 0755);
+    // 使用tmpfs，使测试不依赖于根文件系统是否支持扩展属性
+    assert(mount("tmpfs", ROOT, "tmpfs", 0, NULL) == 0);
+    int fd = open(FILE_PATH, O_WRONLY | O_CREAT, 0644);
+    assert(fd >= 0);
+    close(fd);
+
+    test_user_namespace();
+    test_invalid_names();
+    test_symlink();
+    test_privileges();
+
+    assert(unlink(FILE_PATH) == 0);
+    assert(umount(ROOT) == 0);
+    assert(rmdir(ROOT) == 0);
+    printf("All xattr tests passed\n");
+    return 0;
+}